@@ -0,0 +1,133 @@
+//! Front End Benchmarks
+//!
+//! Measures the tokeniser, parser, and binder over generated programs
+//! which stress different parts of the front end: deeply nested
+//! expressions, many small functions, and long runs of
+//! declarations. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fmt::Write;
+use ullage::sem::{Binder, Scope};
+use ullage::syntax::parse::Tokeniser;
+use ullage::syntax::text::SourceText;
+use ullage::syntax::SyntaxTree;
+
+/// Deeply Nested Expressions
+///
+/// Nested blocks and brackets exercise the recursion in the parser
+/// and the scope stack in the binder.
+fn deep_nesting(depth: usize) -> String {
+    let mut source = String::from("var total = 0\n");
+    for level in 0..depth {
+        writeln!(source, "while total < {}", level).unwrap();
+    }
+    source.push_str("total = total + ");
+    source.push_str(&"(1 + ".repeat(depth));
+    source.push('1');
+    source.push_str(&")".repeat(depth));
+    source.push('\n');
+    for _ in 0..depth {
+        source.push_str("end\n");
+    }
+    source.push_str("print total\n");
+    source
+}
+
+/// Many Functions
+///
+/// Each function calls the one before it, so the binder has plenty
+/// of function symbols to declare and look up.
+fn many_functions(count: usize) -> String {
+    let mut source = String::from("fn f0(n: Number): Number\n  n\nend\n");
+    for i in 1..count {
+        writeln!(
+            source,
+            "# Function number {}\nfn f{}(n: Number): Number\n  f{}(n + {}) * 2\nend",
+            i,
+            i,
+            i - 1,
+            i
+        )
+        .unwrap();
+    }
+    writeln!(source, "print f{}(1)", count - 1).unwrap();
+    source
+}
+
+/// Long Sequences
+///
+/// A flat run of declarations and statements, each referring back
+/// to earlier variables.
+fn long_sequence(count: usize) -> String {
+    let mut source = String::from("let v0 = 1\n");
+    for i in 1..count {
+        writeln!(
+            source,
+            "let v{} = v{} + {} * 3  # running total\nprint 'step ' + 'ünî'",
+            i,
+            i - 1,
+            i
+        )
+        .unwrap();
+    }
+    source
+}
+
+/// The Generated Programs
+fn programs() -> Vec<(&'static str, String)> {
+    vec![
+        ("deep_nesting", deep_nesting(100)),
+        ("many_functions", many_functions(2_000)),
+        ("long_sequence", long_sequence(10_000)),
+    ]
+}
+
+fn bench_tokeniser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenise");
+    for (name, text) in programs() {
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &text, |b, text| {
+            b.iter(|| {
+                let source = SourceText::new(text.as_str());
+                Tokeniser::new(&source).count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, text) in programs() {
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &text, |b, text| {
+            b.iter(|| {
+                let source = SourceText::new(text.as_str());
+                let tree = SyntaxTree::parse(&source);
+                assert!(!tree.has_diagnostics());
+                tree.arena().len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_binder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bind");
+    for (name, text) in programs() {
+        let source = SourceText::new(text.as_str());
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &source, |b, source| {
+            b.iter(|| {
+                let tree = SyntaxTree::parse(source);
+                let mut binder = Binder::new(Scope::new());
+                binder.bind_tree(tree);
+                assert!(binder.take_diagnostics().is_empty());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokeniser, bench_parser, bench_binder);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Build Script
+//!
+//! Compiles the `ullage_rt` runtime support library from `rt/`. The
+//! library is linked into the compiler, so code run with the JIT can
+//! call it, and its archive is embedded in the compiler so it can be
+//! linked into every executable. A second copy of the library which
+//! allocates from the Boehm collector is only embedded.
+//!
+//! The hash of the commit being built is also recorded, if the
+//! compiler is being built from a git checkout, so it can be included
+//! in the build information embedded in executables.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=rt/ullage_rt.c");
+
+    cc::Build::new()
+        .file("rt/ullage_rt.c")
+        .warnings(true)
+        .compile("ullage_rt");
+    cc::Build::new()
+        .file("rt/ullage_rt.c")
+        .warnings(true)
+        .define("ULLAGE_RT_GC_BOEHM", None)
+        .cargo_metadata(false)
+        .compile("ullage_rt_boehm");
+
+    let target = env::var("TARGET").expect("cargo sets TARGET for build scripts");
+    let out_dir = env::var("OUT_DIR").expect("cargo sets OUT_DIR for build scripts");
+    for (var, name) in &[
+        ("ULLAGE_RT_ARCHIVE", "ullage_rt"),
+        ("ULLAGE_RT_BOEHM_ARCHIVE", "ullage_rt_boehm"),
+    ] {
+        let archive = if target.contains("msvc") {
+            format!("{}.lib", name)
+        } else {
+            format!("lib{}.a", name)
+        };
+        println!(
+            "cargo:rustc-env={}={}",
+            var,
+            Path::new(&out_dir).join(archive).display()
+        );
+    }
+    println!("cargo:rustc-env=ULLAGE_RT_TARGET={}", target);
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=ULLAGE_GIT_HASH={}", hash.trim());
+    }
+}
@@ -0,0 +1,21 @@
+//! Binder Fuzz Target
+//!
+//! Parses and binds arbitrary text, then folds the constants in the
+//! bound tree. Trees are bound even if they have parse errors, so the
+//! binder sees all the recovered shapes the parser can produce.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ullage::sem::{self, Binder, Scope};
+use ullage::syntax::text::SourceText;
+use ullage::syntax::SyntaxTree;
+
+fuzz_target!(|text: &str| {
+    let source = SourceText::new(text);
+    let tree = SyntaxTree::parse(&source);
+    let mut binder = Binder::new(Scope::new());
+    let bound = binder.bind_tree(tree);
+    binder.take_diagnostics();
+    sem::fold_constants(bound);
+});
@@ -0,0 +1,17 @@
+//! Parser Fuzz Target
+//!
+//! Parses arbitrary text. Parsing should never panic, however broken
+//! the input is, and the tree should always cover the whole of the
+//! source text.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ullage::syntax::text::SourceText;
+use ullage::syntax::SyntaxTree;
+
+fuzz_target!(|text: &str| {
+    let source = SourceText::new(text);
+    let tree = SyntaxTree::parse(&source);
+    assert_eq!(text, tree.to_source_string());
+});
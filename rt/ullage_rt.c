@@ -0,0 +1,299 @@
+/*
+ * Ullage Runtime Support Library
+ *
+ * Helpers called by compiled Ullage programs. The compiler's build
+ * script builds this into the `ullage_rt` static library, which is
+ * linked into every executable. The library is also linked into the
+ * compiler itself so code run with the JIT can call it.
+ *
+ * Strings share the layout the compiler lowers them with: a 32 bit
+ * length, a 32 bit reference count, and then the bytes of the string.
+ * The bytes are always valid UTF-8, and are not NUL terminated. The
+ * length is in bytes, but the string functions the language exposes
+ * count in Unicode scalar values. String constants are never freed,
+ * and have a negative reference count.
+ */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* When built for the Boehm collector every allocation comes from the
+ * collector instead. Programs using it are linked against `libgc`,
+ * but its headers aren't needed to build the library. */
+#ifdef ULLAGE_RT_GC_BOEHM
+void *GC_malloc(size_t size);
+void *GC_realloc(void *old, size_t size);
+void GC_free(void *ptr);
+#define malloc GC_malloc
+#define realloc GC_realloc
+#define free GC_free
+#endif
+
+#ifdef _WIN32
+#include <io.h>
+#define write _write
+#else
+#include <unistd.h>
+#endif
+
+typedef struct ullage_string {
+	int32_t len;
+	int32_t refs;
+	char buffer[];
+} ullage_string;
+
+/* A String Builder
+ *
+ * Collects appended strings in a buffer which grows geometrically, so
+ * building a string a piece at a time takes time linear in its final
+ * length. Builders are reference counted in the same way as strings,
+ * but are never constant. */
+typedef struct ullage_builder {
+	int32_t refs;
+	int64_t len;
+	int64_t capacity;
+	char *buffer;
+} ullage_builder;
+
+/* Long enough for any 64 bit number, along with its sign and the
+ * trailing NUL `snprintf` writes. */
+#define NUMBER_MAX_LEN 21
+
+/* The Smallest Capacity an Array Grows To */
+#define ARRAY_MIN_CAPACITY 4
+
+#ifdef _MSC_VER
+#define NORETURN __declspec(noreturn)
+#else
+#define NORETURN __attribute__((noreturn))
+#endif
+
+/* Abort with a Message
+ *
+ * Writes `message` to standard error and aborts the process. Anything
+ * already printed is flushed first so it isn't lost. */
+NORETURN void ullage_rt_panic(const char *message, int64_t len)
+{
+	fflush(NULL);
+	write(2, message, (unsigned)len);
+	abort();
+}
+
+/* Abort Because an Allocation Failed */
+static NORETURN void out_of_memory(void)
+{
+	static const char message[] = "out of memory\n";
+	ullage_rt_panic(message, sizeof(message) - 1);
+}
+
+/* Allocate a String
+ *
+ * Returns a new string with room for `len` bytes, and a single
+ * reference to it. The length is set, but the contents are left for
+ * the caller to fill in. */
+ullage_string *ullage_rt_string_alloc(int32_t len)
+{
+	ullage_string *s = malloc(sizeof(ullage_string) + (size_t)len);
+	if (s == NULL) {
+		out_of_memory();
+	}
+	s->len = len;
+	s->refs = 1;
+	return s;
+}
+
+/* Retain a String
+ *
+ * Adds a reference to the string. Null strings and constants are
+ * left untouched. */
+void ullage_rt_string_retain(ullage_string *s)
+{
+	if (s != NULL && s->refs > 0) {
+		s->refs++;
+	}
+}
+
+/* Release a String
+ *
+ * Drops a reference to the string, freeing it once the last one is
+ * gone. Null strings and constants are left untouched. */
+void ullage_rt_string_release(ullage_string *s)
+{
+	if (s != NULL && s->refs > 0 && --s->refs == 0) {
+		free(s);
+	}
+}
+
+/* Grow an Array
+ *
+ * Makes sure the array at `elements` has room for at least `needed`
+ * elements of `elem_size` bytes. The capacity at least doubles each
+ * time it grows, and is updated in place. Returns the possibly moved
+ * elements. */
+void *ullage_rt_array_grow(void *elements, int64_t elem_size, int64_t *capacity, int64_t needed)
+{
+	if (needed <= *capacity) {
+		return elements;
+	}
+	int64_t new_capacity = *capacity * 2;
+	if (new_capacity < ARRAY_MIN_CAPACITY) {
+		new_capacity = ARRAY_MIN_CAPACITY;
+	}
+	if (new_capacity < needed) {
+		new_capacity = needed;
+	}
+	void *grown = realloc(elements, (size_t)(new_capacity * elem_size));
+	if (grown == NULL) {
+		out_of_memory();
+	}
+	*capacity = new_capacity;
+	return grown;
+}
+
+/* Is a Byte the Start of a Scalar Value?
+ *
+ * Every byte of UTF-8 other than a continuation byte starts a new
+ * Unicode scalar value. */
+static int is_scalar_start(char byte)
+{
+	return ((unsigned char)byte & 0xC0) != 0x80;
+}
+
+/* Count the Unicode Scalar Values in a String */
+int64_t ullage_rt_string_char_count(const ullage_string *s)
+{
+	int64_t count = 0;
+	for (int32_t i = 0; i < s->len; i++) {
+		count += is_scalar_start(s->buffer[i]);
+	}
+	return count;
+}
+
+/* Index a String
+ *
+ * Returns a new string holding the Unicode scalar value at `index`.
+ * Aborts if the index is out of bounds. */
+ullage_string *ullage_rt_string_char_at(const ullage_string *s, int64_t index)
+{
+	int32_t start = 0;
+	int64_t seen = -1;
+	for (; start < s->len; start++) {
+		if (is_scalar_start(s->buffer[start]) && ++seen == index) {
+			break;
+		}
+	}
+	if (index < 0 || start >= s->len) {
+		static const char message[] = "error: string index out of bounds\n";
+		ullage_rt_panic(message, sizeof(message) - 1);
+	}
+	int32_t end = start + 1;
+	while (end < s->len && !is_scalar_start(s->buffer[end])) {
+		end++;
+	}
+	ullage_string *c = ullage_rt_string_alloc(end - start);
+	memcpy(c->buffer, s->buffer + start, (size_t)(end - start));
+	return c;
+}
+
+/* Create a String Builder
+ *
+ * Returns a new, empty, builder with a single reference to it. */
+ullage_builder *ullage_rt_builder_new(void)
+{
+	ullage_builder *b = malloc(sizeof(ullage_builder));
+	if (b == NULL) {
+		out_of_memory();
+	}
+	b->refs = 1;
+	b->len = 0;
+	b->capacity = 0;
+	b->buffer = NULL;
+	return b;
+}
+
+/* Retain a String Builder */
+void ullage_rt_builder_retain(ullage_builder *b)
+{
+	if (b != NULL) {
+		b->refs++;
+	}
+}
+
+/* Release a String Builder
+ *
+ * Drops a reference to the builder, freeing it and its buffer once
+ * the last one is gone. */
+void ullage_rt_builder_release(ullage_builder *b)
+{
+	if (b != NULL && --b->refs == 0) {
+		free(b->buffer);
+		free(b);
+	}
+}
+
+/* Append a String to a Builder */
+void ullage_rt_builder_append(ullage_builder *b, const ullage_string *s)
+{
+	if (s->len == 0) {
+		return;
+	}
+	b->buffer = ullage_rt_array_grow(b->buffer, 1, &b->capacity, b->len + s->len);
+	memcpy(b->buffer + b->len, s->buffer, (size_t)s->len);
+	b->len += s->len;
+}
+
+/* Build a String
+ *
+ * Returns a new string holding everything appended to the builder so
+ * far. The builder is left as it was, so it can carry on being
+ * appended to. */
+ullage_string *ullage_rt_builder_to_string(const ullage_builder *b)
+{
+	if (b->len > INT32_MAX) {
+		static const char message[] = "error: string is too long\n";
+		ullage_rt_panic(message, sizeof(message) - 1);
+	}
+	ullage_string *s = ullage_rt_string_alloc((int32_t)b->len);
+	if (b->len > 0) {
+		memcpy(s->buffer, b->buffer, (size_t)b->len);
+	}
+	return s;
+}
+
+/* Print a String
+ *
+ * Writes the string followed by a newline to standard output. */
+void ullage_rt_print(const ullage_string *s)
+{
+	fwrite(s->buffer, 1, (size_t)s->len, stdout);
+	fputc('\n', stdout);
+}
+
+/* Convert a Number to a String */
+ullage_string *ullage_rt_number_to_string(int64_t n)
+{
+	char formatted[NUMBER_MAX_LEN];
+	int len = snprintf(formatted, sizeof(formatted), "%lld", (long long)n);
+	ullage_string *s = ullage_rt_string_alloc(len);
+	memcpy(s->buffer, formatted, (size_t)len);
+	return s;
+}
+
+/* Convert a String to a Number
+ *
+ * Reads an optional sign followed by decimal digits from the start of
+ * the string. Returns `0` if there aren't any. */
+int64_t ullage_rt_string_to_number(const ullage_string *s)
+{
+	char *cstr = malloc((size_t)s->len + 1);
+	if (cstr == NULL) {
+		out_of_memory();
+	}
+	memcpy(cstr, s->buffer, (size_t)s->len);
+	cstr[s->len] = '\0';
+	int64_t n = strtoll(cstr, NULL, 10);
+	free(cstr);
+	return n;
+}
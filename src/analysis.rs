@@ -0,0 +1,14 @@
+//! Source Analysis
+//!
+//! Queries over parsed and bound source text intended for tooling
+//! such as editors, highlighters, and refactoring tools. Each query
+//! works from a `SourceText` and runs whichever compiler phases it
+//! needs to produce an answer.
+
+pub mod completion;
+pub mod doc;
+pub mod fix;
+pub mod highlight;
+pub mod outline;
+pub mod rename;
+pub mod xref;
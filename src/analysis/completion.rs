@@ -0,0 +1,102 @@
+//! Completion
+//!
+//! Lists the identifiers which are visible at a given point in a
+//! source so that editors can offer them as completions. Visibility
+//! follows the binder's scoping rules: variables are visible after
+//! their declaration until the end of the enclosing block, and the
+//! body of a function can see its parameters and the functions
+//! declared around it but not outer variables.
+
+use crate::sem::{Binder, Scope, Symbol};
+use crate::syntax::text::{Pos, SourceText};
+use crate::syntax::SyntaxTree;
+
+/// Completion Candidate
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    /// The name of the visible symbol
+    pub name: String,
+    /// The symbol bound to the name
+    pub symbol: Symbol,
+}
+
+/// Find the Completions at a Position
+///
+/// Returns each symbol visible at `pos`, ordered by name.
+pub fn completions_at(source: &SourceText, pos: Pos) -> Vec<Completion> {
+    let tree = SyntaxTree::parse(source);
+    let mut binder = Binder::new(Scope::new()).with_probe(pos);
+    binder.bind_tree(tree);
+    let mut completions: Vec<_> = binder
+        .take_visible_symbols()
+        .into_iter()
+        .map(|(id, symbol)| Completion {
+            name: source.interned_value(id).into(),
+            symbol,
+        })
+        .collect();
+    completions.sort_by(|a, b| a.name.cmp(&b.name));
+    completions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sem::{BuiltinType, Typ};
+    use crate::syntax::VarStyle;
+
+    fn names_at(text: &str, offset: usize) -> Vec<String> {
+        let source = SourceText::new(text);
+        completions_at(&source, Pos::from(offset))
+            .into_iter()
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn completions_follow_scopes() {
+        let text = "let a = 1\nfn f(p: Number): Number\n  let q = p\n  q\nend\nwhile false\n  let w = a\nend\nprint a\n";
+        let builtin_fns = [
+            "append",
+            "build_string",
+            "byte_len",
+            "len",
+            "string_builder",
+            "to_number",
+            "to_string",
+        ];
+        let with_builtin_fns = |names: &[&str]| {
+            let mut all: Vec<String> = builtin_fns.iter().chain(names).map(|&n| n.into()).collect();
+            all.sort();
+            all
+        };
+        let with_builtins = |names: &[&str]| {
+            let types = ["Bool", "Number", "String", "StringBuilder"];
+            let names: Vec<&str> = types.iter().chain(names).copied().collect();
+            with_builtin_fns(&names)
+        };
+
+        // Before anything is declared only functions are visible
+        assert_eq!(with_builtins(&["f"]), names_at(text, 0));
+        // After the declaration of `a`
+        assert_eq!(with_builtins(&["a", "f"]), names_at(text, 10));
+        // Inside the function body, before and after `q`. Only
+        // functions are carried into a function's scope.
+        assert_eq!(with_builtin_fns(&["f", "p"]), names_at(text, 36));
+        assert_eq!(with_builtin_fns(&["f", "p", "q"]), names_at(text, 48));
+        // Inside the loop body, and after it
+        assert_eq!(with_builtins(&["a", "f", "w"]), names_at(text, 78));
+        assert_eq!(with_builtins(&["a", "f"]), names_at(text, 82));
+    }
+
+    #[test]
+    fn completions_include_symbols() {
+        let source = SourceText::new("var x: Bool = true\n");
+        let completions = completions_at(&source, source.end());
+        let x = completions.iter().find(|c| c.name == "x").unwrap();
+        assert_eq!(
+            Symbol::Variable(VarStyle::Mutable, Typ::Builtin(BuiltinType::Bool)),
+            x.symbol
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Documentation Generation
+//!
+//! Extracts the documentation for the functions declared at the top
+//! level of a source and renders it as Markdown or HTML. A function's
+//! documentation is the block of `##` doc comment lines directly
+//! above it:
+//!
+//! ```text
+//! ## Add two numbers together.
+//! fn add(a: Number, b: Number): Number
+//!     a + b
+//! end
+//! ```
+//!
+//! Signatures come from the bound tree, so they include the inferred
+//! types of the function's parameters.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::sem::{Binder, Expression, ExpressionKind, FnDecl, Scope};
+use crate::syntax::text::SourceText;
+use crate::syntax::visit::Visitor;
+use crate::syntax::{ExpressionArena, FunctionExpression, SyntaxTree};
+
+/// Documentation Output Format
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum DocFormat {
+    /// A Markdown document
+    #[default]
+    Markdown,
+    /// A standalone HTML page
+    Html,
+}
+
+/// Documented Item
+///
+/// The documentation for a single function.
+#[derive(Debug, PartialEq)]
+pub struct DocItem {
+    /// The name of the function
+    pub name: String,
+    /// The function's signature, e.g. `fn add(a: Number): Number`
+    pub signature: String,
+    /// The text of the function's doc comment, with the comment
+    /// markers removed. Empty if the function isn't documented.
+    pub docs: String,
+}
+
+/// Document a Source
+///
+/// Returns an item for each function declared at the top level of
+/// the source, in source order. Sources which don't bind cleanly are
+/// still documented, but may have incomplete signatures.
+pub fn document(source: &SourceText) -> Vec<DocItem> {
+    let tree = SyntaxTree::parse(source);
+    let mut collector = DocCollector {
+        source,
+        arena: tree.arena(),
+        docs: HashMap::new(),
+    };
+    collector.visit_expression(tree.root());
+    let mut docs = collector.docs;
+
+    let mut binder = Binder::new(Scope::new());
+    let bound = binder.bind_tree(tree);
+    let mut decls = Vec::new();
+    find_functions(&bound, &mut decls);
+    decls
+        .into_iter()
+        .map(|decl| DocItem {
+            name: decl.ident.clone(),
+            signature: signature(decl),
+            docs: docs.remove(&decl.ident).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Render Documentation
+///
+/// Formats the documented items as a single page with the given
+/// title.
+pub fn render(title: &str, items: &[DocItem], format: DocFormat) -> String {
+    match format {
+        DocFormat::Markdown => render_markdown(title, items),
+        DocFormat::Html => render_html(title, items),
+    }
+}
+
+/// Format the Signature of a Bound Function
+fn signature(decl: &FnDecl) -> String {
+    let params: Vec<_> = decl
+        .params
+        .iter()
+        .map(|p| format!("{}: {}", p.ident, p.ty.name()))
+        .collect();
+    format!(
+        "fn {}({}): {}",
+        decl.ident,
+        params.join(", "),
+        decl.ret_ty.name()
+    )
+}
+
+/// Find the Top Level Functions in a Bound Tree
+fn find_functions<'a>(expr: &'a Expression, decls: &mut Vec<&'a FnDecl>) {
+    match expr.kind {
+        ExpressionKind::Sequence(ref exprs) => {
+            for expr in exprs.iter() {
+                find_functions(expr, decls);
+            }
+        }
+        ExpressionKind::Function(ref fn_decl) => decls.push(fn_decl),
+        _ => (),
+    }
+}
+
+/// Render Documentation as Markdown
+fn render_markdown(title: &str, items: &[DocItem]) -> String {
+    let mut out = format!("# {}\n", title);
+    for item in items {
+        let _ = write!(out, "\n## `{}`\n", item.signature);
+        if !item.docs.is_empty() {
+            let _ = write!(out, "\n{}\n", item.docs);
+        }
+    }
+    out
+}
+
+/// Render Documentation as HTML
+///
+/// Each paragraph of a doc comment, separated by blank lines, becomes
+/// a paragraph in the page.
+fn render_html(title: &str, items: &[DocItem]) -> String {
+    let title = escape_html(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        title
+    );
+    for item in items {
+        let _ = writeln!(
+            out,
+            "<h2 id=\"{}\"><code>{}</code></h2>",
+            escape_html(&item.name),
+            escape_html(&item.signature)
+        );
+        for para in item.docs.split("\n\n").filter(|p| !p.trim().is_empty()) {
+            let _ = writeln!(out, "<p>{}</p>", escape_html(para.trim()));
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Escape Text for Inclusion in HTML
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Syntax visitor which collects the doc comments of functions
+///
+/// Functions aren't walked into, as only top level functions are
+/// documented.
+struct DocCollector<'a, 'ast> {
+    /// The source being documented
+    source: &'a SourceText,
+    /// The expressions of the tree being documented
+    arena: &'ast ExpressionArena,
+    /// The doc comments found so far, by function name
+    docs: HashMap<String, String>,
+}
+
+impl<'ast> Visitor<'ast> for DocCollector<'_, 'ast> {
+    fn arena(&self) -> &'ast ExpressionArena {
+        self.arena
+    }
+
+    fn visit_function(&mut self, func: &'ast FunctionExpression) {
+        if let Some(doc) = &func.doc {
+            let span = func.identifier_tok.span();
+            let name = self.source.slice(span.start(), span.end()).to_string();
+            self.docs.insert(name, doc.text(self.source));
+        }
+    }
+}
+
+impl FromStr for DocFormat {
+    type Err = String;
+
+    /// Parse a Documentation Format
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(DocFormat::Markdown),
+            "html" => Ok(DocFormat::Html),
+            _ => Err(format!("unknown documentation format '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn document_functions() {
+        let source = SourceText::new(
+            "## Not attached.\n\n## Add two numbers.\n##\n## Overflow wraps.\nfn add(a: Number, b: Number): Number\n  a + b\nend\n# Not docs.\nfn bare(): Bool true end\n",
+        );
+        assert_eq!(
+            vec![
+                DocItem {
+                    name: "add".into(),
+                    signature: "fn add(a: Number, b: Number): Number".into(),
+                    docs: "Add two numbers.\n\nOverflow wraps.".into(),
+                },
+                DocItem {
+                    name: "bare".into(),
+                    signature: "fn bare(): Bool".into(),
+                    docs: String::new(),
+                },
+            ],
+            document(&source)
+        );
+    }
+
+    #[test]
+    fn render_documentation() {
+        let items = vec![DocItem {
+            name: "lt".into(),
+            signature: "fn lt(a: Number, b: Number): Bool".into(),
+            docs: "Is a < b?\n\nCompares numbers.".into(),
+        }];
+        assert_eq!(
+            "# demo\n\n## `fn lt(a: Number, b: Number): Bool`\n\nIs a < b?\n\nCompares numbers.\n",
+            render("demo", &items, DocFormat::Markdown)
+        );
+        let html = render("demo", &items, DocFormat::Html);
+        assert!(html.contains("<title>demo</title>"));
+        assert!(html.contains("<p>Is a &lt; b?</p>\n<p>Compares numbers.</p>"));
+        assert_eq!(Ok(DocFormat::Html), "html".parse());
+        assert!("pdf".parse::<DocFormat>().is_err());
+    }
+}
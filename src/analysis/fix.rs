@@ -0,0 +1,126 @@
+//! Automatic Fixes
+//!
+//! Applies the fixes suggested by diagnostics which can be made
+//! without review. The binder only runs on sources which parse, so
+//! parse errors are fixed first. Fixing one problem can uncover
+//! more, so fixes are applied in rounds until none are left.
+
+use super::rename::{apply_edits, TextEdit};
+use crate::diag::Fix;
+use crate::sem::{Binder, Scope};
+use crate::syntax::text::SourceText;
+use crate::syntax::SyntaxTree;
+
+/// The Most Rounds of Fixes to Apply
+///
+/// Stops a fix which doesn't resolve its own diagnostic from being
+/// applied forever.
+const MAX_ROUNDS: usize = 16;
+
+/// Find the Machine Applicable Fixes for a Source
+///
+/// Returns the edits suggested by the source's diagnostics, in source
+/// order. Binder diagnostics are only used if the source parses
+/// without error. A fix which overlaps an earlier one is dropped.
+pub fn fixes(source: &SourceText) -> Vec<TextEdit> {
+    let tree = SyntaxTree::parse(source);
+    let diagnostics = if tree.has_diagnostics() {
+        tree.diagnostics().to_vec()
+    } else {
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        binder.take_diagnostics()
+    };
+
+    let mut fixes: Vec<_> = diagnostics
+        .into_iter()
+        .filter_map(|d| d.fix)
+        .filter(Fix::is_machine_applicable)
+        .collect();
+    fixes.sort_by_key(|f| f.span.start());
+
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for fix in fixes {
+        if edits
+            .last()
+            .is_some_and(|last| fix.span.start() < last.span.end())
+        {
+            continue;
+        }
+        edits.push(TextEdit {
+            span: fix.span,
+            new_text: fix.replacement,
+        });
+    }
+    edits
+}
+
+/// Apply the Machine Applicable Fixes to a Source
+///
+/// Returns the fixed text of the source along with the number of
+/// fixes which were applied.
+pub fn apply_fixes(source: &SourceText) -> (String, usize) {
+    let mut text = source.slice(source.start(), source.end()).to_string();
+    let mut applied = 0;
+    for _ in 0..MAX_ROUNDS {
+        let current = SourceText::with_name(text, source.name());
+        let edits = fixes(&current);
+        text = apply_edits(&current, &edits);
+        if edits.is_empty() {
+            break;
+        }
+        applied += edits.len();
+    }
+    (text, applied)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fix(source: &str) -> (String, usize) {
+        apply_fixes(&SourceText::new(source))
+    }
+
+    #[test]
+    fn fix_missing_closers() {
+        assert_eq!(
+            ("fn one(): Number\n  1\nend\n".to_string(), 1),
+            fix("fn one(): Number\n  1\n")
+        );
+        assert_eq!(
+            ("print (1 + 2)\nprint 3".to_string(), 1),
+            fix("print (1 + 2\nprint 3")
+        );
+        assert_eq!(
+            (
+                "while true\n  until false\n  print 1\nend\nend".to_string(),
+                2
+            ),
+            fix("while true\n  until false\n  print 1")
+        );
+    }
+
+    #[test]
+    fn fix_misspelt_names() {
+        assert_eq!(
+            ("let total: Number = 1\nprint total".to_string(), 2),
+            fix("let total: Numbr = 1\nprint totl")
+        );
+        // Parse errors are fixed before names are looked up.
+        assert_eq!(
+            ("fn f(count: Number): Number\n  count\nend".to_string(), 2),
+            fix("fn f(count: Number): Number\n  coutn")
+        );
+    }
+
+    #[test]
+    fn leave_unfixable_code() {
+        assert_eq!(("print nothing".to_string(), 0), fix("print nothing"));
+        assert_eq!(
+            ("let a: Number = 'a'".to_string(), 0),
+            fix("let a: Number = 'a'")
+        );
+        assert!(fixes(&SourceText::new("print 1")).is_empty());
+    }
+}
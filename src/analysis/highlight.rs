@@ -0,0 +1,292 @@
+//! Semantic Highlighting
+//!
+//! Classifies each token in a source text so that editors and other
+//! tools can colour it. Keywords, literals, and operators are
+//! classified from the syntax tree alone. Identifiers are classified
+//! using the results of binding the tree, so a reference to a
+//! function is highlighted differently from a reference to a
+//! variable.
+
+use crate::sem::{Binder, Scope, Symbol};
+use crate::syntax::text::{Ident, SourceText, Span, DUMMY_SPAN};
+use crate::syntax::visit::{self, Visitor};
+use crate::syntax::{
+    ExpressionArena, Literal, SyntaxTree, Token, TokenKind, TriviaToken, TriviaTokenKind, TypeRef,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, prelude::*};
+
+/// Token Class
+///
+/// The kind of highlighting to apply to a token. The names of the
+/// classes match the standard semantic token types from the Language
+/// Server Protocol.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum TokenClass {
+    /// A language keyword, including `true` and `false`
+    Keyword,
+    /// A reference to, or declaration of, a function
+    Function,
+    /// A reference to, or declaration of, a variable or parameter
+    Variable,
+    /// A type name
+    Type,
+    /// A numeric literal
+    Number,
+    /// A string literal
+    String,
+    /// An operator
+    Operator,
+    /// A comment
+    Comment,
+}
+
+impl TokenClass {
+    /// Every Token Class
+    ///
+    /// The position of each class in this list is its `index`.
+    pub const ALL: [TokenClass; 8] = [
+        TokenClass::Keyword,
+        TokenClass::Function,
+        TokenClass::Variable,
+        TokenClass::Type,
+        TokenClass::Number,
+        TokenClass::String,
+        TokenClass::Operator,
+        TokenClass::Comment,
+    ];
+
+    /// Get the Name of the Class
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Function => "function",
+            TokenClass::Variable => "variable",
+            TokenClass::Type => "type",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Comment => "comment",
+        }
+    }
+
+    /// Get the Index of the Class in `ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Classify the Tokens in a Source
+///
+/// Returns the span of each token, or comment, which should be
+/// highlighted along with its class. Spans are returned in source
+/// order and don't overlap. Punctuation and whitespace are not
+/// classified.
+pub fn classify(source: &SourceText) -> Vec<(Span, TokenClass)> {
+    let tree = SyntaxTree::parse(source);
+
+    let mut type_names = TypeNameCollector(tree.arena(), HashSet::new());
+    type_names.visit_expression(tree.root());
+
+    let mut classified = Vec::new();
+    for token in tree.tokens() {
+        classify_trivia(token.leading(), &mut classified);
+        if token.span() != DUMMY_SPAN {
+            if let Some(class) = syntactic_class(token, &type_names.1) {
+                classified.push((token.span(), class));
+            }
+        }
+        classify_trivia(token.trailing(), &mut classified);
+    }
+
+    // Refine the classes of identifiers using the symbols they bind
+    // to. Identifiers which couldn't be bound stay as variables.
+    let mut binder = Binder::new(Scope::new());
+    binder.bind_tree(tree);
+    let symbols: HashMap<Span, Symbol> = binder.take_symbol_spans().into_iter().collect();
+    for (span, class) in classified.iter_mut() {
+        if *class == TokenClass::Variable {
+            *class = match symbols.get(span) {
+                Some(Symbol::Function(..)) => TokenClass::Function,
+                Some(Symbol::Type(..)) => TokenClass::Type,
+                _ => TokenClass::Variable,
+            };
+        }
+    }
+
+    classified
+}
+
+/// Write Highlighted HTML
+///
+/// Writes the source as a `<pre>` block with each classified token
+/// wrapped in a `<span>`. The class of each span is the name of the
+/// token's class.
+pub fn write_html<W: Write>(source: &SourceText, writer: &mut W) -> io::Result<()> {
+    let mut pos = source.start();
+    write!(writer, "<pre class=\"ullage\">")?;
+    for (span, class) in classify(source) {
+        write_escaped(writer, source.slice(pos, span.start()))?;
+        write!(writer, "<span class=\"{}\">", class.name())?;
+        write_escaped(writer, source.slice(span.start(), span.end()))?;
+        write!(writer, "</span>")?;
+        pos = span.end();
+    }
+    write_escaped(writer, source.slice(pos, source.end()))?;
+    writeln!(writer, "</pre>")
+}
+
+/// Write Text Escaped for HTML
+fn write_escaped<W: Write>(writer: &mut W, text: &str) -> io::Result<()> {
+    for ch in text.chars() {
+        match ch {
+            '<' => write!(writer, "&lt;")?,
+            '>' => write!(writer, "&gt;")?,
+            '&' => write!(writer, "&amp;")?,
+            '"' => write!(writer, "&quot;")?,
+            _ => write!(writer, "{}", ch)?,
+        }
+    }
+    Ok(())
+}
+
+/// Classify a Token Without Binding Information
+///
+/// Identifiers are classified as variables, unless they appear in a
+/// type reference.
+fn syntactic_class(token: &Token, type_names: &HashSet<Span>) -> Option<TokenClass> {
+    Some(match token.kind {
+        TokenKind::Word(Ident::Unknown(_)) => {
+            if type_names.contains(&token.span()) {
+                TokenClass::Type
+            } else {
+                TokenClass::Variable
+            }
+        }
+        TokenKind::Word(_) => TokenClass::Keyword,
+        TokenKind::Literal(Literal::Number(_)) => TokenClass::Number,
+        TokenKind::Literal(Literal::RawString(_)) => TokenClass::String,
+        TokenKind::Equals
+        | TokenKind::DoubleEquals
+        | TokenKind::Bang
+        | TokenKind::BangEquals
+        | TokenKind::Plus
+        | TokenKind::Minus
+        | TokenKind::Star
+        | TokenKind::Slash
+        | TokenKind::LessThan
+        | TokenKind::LessThanEqual
+        | TokenKind::MoreThan
+        | TokenKind::MoreThanEqual => TokenClass::Operator,
+        TokenKind::OpenBracket
+        | TokenKind::CloseBracket
+        | TokenKind::OpenSqBracket
+        | TokenKind::CloseSqBracket
+        | TokenKind::Comma
+        | TokenKind::Colon
+        | TokenKind::AttributeOpen
+        | TokenKind::End => return None,
+    })
+}
+
+/// Classify the Comments in a List of Trivia
+fn classify_trivia(trivia: &[TriviaToken], classified: &mut Vec<(Span, TokenClass)>) {
+    for t in trivia {
+        if let TriviaTokenKind::Comment | TriviaTokenKind::DocComment = t.kind() {
+            classified.push((t.span(), TokenClass::Comment));
+        }
+    }
+}
+
+/// Syntax visitor which records the spans of named types
+struct TypeNameCollector<'ast>(&'ast ExpressionArena, HashSet<Span>);
+
+impl<'ast> Visitor<'ast> for TypeNameCollector<'ast> {
+    fn arena(&self) -> &'ast ExpressionArena {
+        self.0
+    }
+
+    fn visit_type_ref(&mut self, type_ref: &'ast TypeRef) {
+        if let TypeRef::Simple(tok) = type_ref {
+            self.1.insert(tok.span());
+        }
+        visit::walk_type_ref(self, type_ref);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn classes(text: &str) -> Vec<(String, TokenClass)> {
+        let source = SourceText::new(text);
+        classify(&source)
+            .into_iter()
+            .map(|(span, class)| (source.slice(span.start(), span.end()).into(), class))
+            .collect()
+    }
+
+    #[test]
+    fn classify_tokens() {
+        use self::TokenClass::*;
+        assert_eq!(
+            vec![
+                ("fn".into(), Keyword),
+                ("inc".into(), Function),
+                ("n".into(), Variable),
+                ("Number".into(), Type),
+                ("Number".into(), Type),
+                ("n".into(), Variable),
+                ("+".into(), Operator),
+                ("1".into(), Number),
+                ("end".into(), Keyword),
+                ("# bump it".into(), Comment),
+                ("print".into(), Keyword),
+                ("inc".into(), Function),
+                ("2".into(), Number),
+                ("if".into(), Keyword),
+                ("true".into(), Keyword),
+                ("else".into(), Keyword),
+                ("missing".into(), Variable),
+                ("## greeting".into(), Comment),
+                ("let".into(), Keyword),
+                ("s".into(), Variable),
+                ("=".into(), Operator),
+                ("'hi'".into(), String),
+            ],
+            classes(
+                "fn inc(n: Number): Number n + 1 end # bump it
+print inc(2) if true else missing
+## greeting
+let s = 'hi'"
+            )
+        );
+    }
+
+    #[test]
+    fn classify_type_used_as_value() {
+        assert_eq!(
+            vec![
+                ("print".into(), TokenClass::Keyword),
+                ("Bool".into(), TokenClass::Type)
+            ],
+            classes("print Bool")
+        );
+    }
+
+    #[test]
+    fn write_highlighted_html() {
+        let source = SourceText::new("let a = 1 < 2 # yes");
+        let mut buff = Vec::new();
+        write_html(&source, &mut buff).unwrap();
+        assert_eq!(
+            concat!(
+                "<pre class=\"ullage\"><span class=\"keyword\">let</span> ",
+                "<span class=\"variable\">a</span> <span class=\"operator\">=</span> ",
+                "<span class=\"number\">1</span> <span class=\"operator\">&lt;</span> ",
+                "<span class=\"number\">2</span> <span class=\"comment\"># yes</span></pre>\n",
+            ),
+            String::from_utf8(buff).unwrap()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Document Outline
+//!
+//! Lists the items declared in a source for editor outlines and code
+//! navigation. The outline only runs the declare pass of the binder,
+//! rather than a full bind, so it is cheap enough to recompute on
+//! every edit. As a result variables without a type annotation are
+//! listed without a type.
+
+use crate::sem::{Binder, Scope, Symbol, Typ};
+use crate::syntax::text::{SourceText, Span};
+use crate::syntax::visit::{self, Visitor};
+use crate::syntax::{
+    DeclarationExpression, ExpressionArena, FunctionExpression, SyntaxTree, VarStyle,
+};
+
+/// Outline Item Kind
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum OutlineKind {
+    /// A function declaration
+    Function,
+    /// A variable declaration
+    Variable,
+}
+
+/// Outline Item
+///
+/// A single declaration in the outline of a source. Items declared
+/// within a function are nested in its `children`.
+#[derive(Debug, PartialEq)]
+pub struct OutlineItem {
+    /// The name of the declared item
+    pub name: String,
+    /// The kind of item declared
+    pub kind: OutlineKind,
+    /// The span of the whole declaration
+    pub span: Span,
+    /// The span of the declared identifier
+    pub name_span: Span,
+    /// The signature of the item, as shown on hover
+    pub signature: String,
+    /// Items declared within this one
+    pub children: Vec<OutlineItem>,
+}
+
+/// Get the Outline of a Source
+///
+/// Returns the functions and variables declared in the source in
+/// source order.
+pub fn outline(source: &SourceText) -> Vec<OutlineItem> {
+    let tree = SyntaxTree::parse(source);
+    let mut binder = Binder::new(Scope::new());
+    binder.declare_tree(&tree);
+    let mut collector = OutlineCollector {
+        source,
+        arena: tree.arena(),
+        binder: &mut binder,
+        items: Vec::new(),
+    };
+    collector.visit_expression(tree.root());
+    collector.items
+}
+
+/// Format the Signature of a Symbol
+///
+/// Variables of unknown type are shown without a type.
+pub fn signature(name: &str, sym: &Symbol) -> String {
+    match sym {
+        Symbol::Variable(style, typ) => {
+            let kw = match style {
+                VarStyle::Immutable => "let",
+                VarStyle::Mutable => "var",
+            };
+            match typ {
+                Typ::Unknown => format!("{} {}", kw, name),
+                _ => format!("{} {}: {}", kw, name, typ.name()),
+            }
+        }
+        Symbol::Function(params, ret) => {
+            let params: Vec<_> = params.iter().map(|p| p.name()).collect();
+            format!("fn {}({}): {}", name, params.join(", "), ret.name())
+        }
+        Symbol::Type(typ) => format!("type {}", typ.name()),
+    }
+}
+
+/// Syntax visitor which collects the declarations at one level
+struct OutlineCollector<'a, 'ast> {
+    /// The source being outlined
+    source: &'a SourceText,
+    /// The expressions of the tree being outlined
+    arena: &'ast ExpressionArena,
+    /// Binder used to resolve declared types
+    binder: &'a mut Binder,
+    /// The items found so far at this level
+    items: Vec<OutlineItem>,
+}
+
+impl OutlineCollector<'_, '_> {
+    /// Get the Source Text Covered by a Span
+    fn text(&self, span: Span) -> String {
+        self.source.slice(span.start(), span.end()).into()
+    }
+}
+
+impl<'ast> Visitor<'ast> for OutlineCollector<'_, 'ast> {
+    fn arena(&self) -> &'ast ExpressionArena {
+        self.arena
+    }
+
+    fn visit_function(&mut self, func: &'ast FunctionExpression) {
+        let sym = self.binder.function_symbol(func, self.source);
+        let mut inner = OutlineCollector {
+            source: self.source,
+            arena: self.arena,
+            binder: self.binder,
+            items: Vec::new(),
+        };
+        visit::walk_function(&mut inner, func);
+        let children = inner.items;
+
+        let name = self.text(func.identifier_tok.span());
+        self.items.push(OutlineItem {
+            signature: signature(&name, &sym),
+            name,
+            kind: OutlineKind::Function,
+            span: Span::enclosing(func.fn_kw.span(), func.body.close.span()),
+            name_span: func.identifier_tok.span(),
+            children,
+        });
+    }
+
+    fn visit_declaration(&mut self, decl: &'ast DeclarationExpression) {
+        let typ = match &decl.id.typ {
+            Some(anno) => self.binder.bind_type(&anno.type_ref, self.source),
+            None => Typ::Unknown,
+        };
+        let name = self.text(decl.id.id_tok.span());
+        self.items.push(OutlineItem {
+            signature: signature(&name, &Symbol::Variable(decl.style, typ)),
+            name,
+            kind: OutlineKind::Variable,
+            span: decl.span(),
+            name_span: decl.id.id_tok.span(),
+            children: Vec::new(),
+        });
+        visit::walk_declaration(self, decl);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::text::Pos;
+
+    #[test]
+    fn outline_nests_declarations() {
+        let source = SourceText::new(
+            "var n: Number = 1\nfn fib(n: Number): Number\n  let prev = fib(n - 1)\n  prev\nend\n",
+        );
+        let items = outline(&source);
+        let span = |s: usize, e: usize| Span::new(Pos::from(s), Pos::from(e));
+        assert_eq!(
+            vec![
+                OutlineItem {
+                    name: "n".into(),
+                    kind: OutlineKind::Variable,
+                    span: span(0, 17),
+                    name_span: span(4, 5),
+                    signature: "var n: Number".into(),
+                    children: Vec::new(),
+                },
+                OutlineItem {
+                    name: "fib".into(),
+                    kind: OutlineKind::Function,
+                    span: span(18, 78),
+                    name_span: span(21, 24),
+                    signature: "fn fib(Number): Number".into(),
+                    children: vec![OutlineItem {
+                        name: "prev".into(),
+                        kind: OutlineKind::Variable,
+                        span: span(46, 67),
+                        name_span: span(50, 54),
+                        signature: "let prev".into(),
+                        children: Vec::new(),
+                    }],
+                },
+            ],
+            items
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Rename Refactoring
+//!
+//! Computes the edits needed to rename a symbol and every reference
+//! to it. Renames are checked by binding the edited source and
+//! making sure every identifier still resolves to the same
+//! definition. A rename which would cause the new name to collide
+//! with, shadow, or be shadowed by another symbol is refused.
+
+use crate::sem::{Binder, Scope};
+use crate::syntax::text::{Ident, Pos, SourceText, Span};
+use crate::syntax::SyntaxTree;
+use failure::Fail;
+use std::collections::{HashMap, HashSet};
+
+/// Text Edit
+///
+/// A replacement of the text covered by `span` with `new_text`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextEdit {
+    /// The span of the source to replace
+    pub span: Span,
+    /// The text to replace it with
+    pub new_text: String,
+}
+
+/// Rename Error
+///
+/// The reasons a rename can be refused.
+#[derive(Fail, Debug, PartialEq)]
+pub enum RenameError {
+    /// There is no symbol defined in the source at the position
+    #[fail(display = "no renameable symbol at offset {}", _0)]
+    NoSymbol(usize),
+
+    /// The new name can't be used as an identifier
+    #[fail(display = "'{}' is not a valid identifier", _0)]
+    InvalidName(String),
+
+    /// The new name would change what some identifier refers to
+    #[fail(display = "renaming to '{}' would collide with an existing symbol", _0)]
+    Collision(String),
+
+    /// Some identifier is bound to more than one definition, so which
+    /// references to rename can't be known
+    #[fail(
+        display = "the references to the symbol at offset {} are ambiguous",
+        _0
+    )]
+    Ambiguous(usize),
+}
+
+/// Rename the Symbol at a Position
+///
+/// Returns the edits which rename the symbol bound to the identifier
+/// at `pos`, its declaration and all of its references, to
+/// `new_name`. Edits are returned in source order.
+///
+/// # Errors
+///
+/// The rename is refused if there is no symbol at `pos`, if the
+/// symbol is a builtin, if `new_name` isn't a valid identifier, if
+/// any identifier in the source is bound to more than one definition,
+/// or if renaming would change the binding of any identifier in the
+/// source.
+pub fn rename(source: &SourceText, pos: Pos, new_name: &str) -> Result<Vec<TextEdit>, RenameError> {
+    if !is_identifier(source, new_name) {
+        return Err(RenameError::InvalidName(new_name.into()));
+    }
+
+    let mut binder = Binder::new(Scope::new());
+    binder.bind_tree(SyntaxTree::parse(source));
+    let edits: Vec<_> = binder
+        .references_at(pos)
+        .into_iter()
+        .map(|span| TextEdit {
+            span,
+            new_text: new_name.into(),
+        })
+        .collect();
+    if edits.is_empty() {
+        return Err(RenameError::NoSymbol(pos.offset()));
+    }
+    if is_ambiguous(binder.references()) {
+        return Err(RenameError::Ambiguous(pos.offset()));
+    }
+
+    // Bind the renamed source and check that every definition still
+    // has exactly the same references once moved to account for
+    // the edits.
+    let renamed = SourceText::with_name(apply_edits(source, &edits), source.name());
+    let mut renamed_binder = Binder::new(Scope::new());
+    renamed_binder.bind_tree(SyntaxTree::parse(&renamed));
+
+    let delta = new_name.len() as isize
+        - (edits[0].span.end().offset() - edits[0].span.start().offset()) as isize;
+    let moved = |span: Span| move_span(span, &edits, delta);
+    let expected = normalise(binder.references(), moved);
+    let actual = normalise(renamed_binder.references(), |span| span);
+    if expected != actual
+        || renamed_binder.take_diagnostics().len() != binder.take_diagnostics().len()
+    {
+        return Err(RenameError::Collision(new_name.into()));
+    }
+
+    Ok(edits)
+}
+
+/// Apply Edits to a Source
+///
+/// Returns the text of the source with each edit applied. Edits must
+/// be in source order and must not overlap.
+pub fn apply_edits(source: &SourceText, edits: &[TextEdit]) -> String {
+    let mut result = String::new();
+    let mut pos = source.start();
+    for edit in edits {
+        result.push_str(source.slice(pos, edit.span.start()));
+        result.push_str(&edit.new_text);
+        pos = edit.span.end();
+    }
+    result.push_str(source.slice(pos, source.end()));
+    result
+}
+
+/// Check if a Name is a Valid Identifier
+///
+/// The name must lex as a single word and must not be a keyword.
+fn is_identifier(source: &SourceText, name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && matches!(source.intern(name), Ident::Unknown(_))
+}
+
+/// Check if a Reference Index is Ambiguous
+///
+/// Every identifier should be bound to exactly one definition. If any
+/// appears in the references of more than one then renaming could
+/// change what it refers to.
+fn is_ambiguous(references: &HashMap<Span, Vec<Span>>) -> bool {
+    let mut seen = HashSet::new();
+    references
+        .values()
+        .flatten()
+        .any(|&span| !seen.insert(span))
+}
+
+/// Move a Span to Account for Edits
+///
+/// Each edit before the span shifts it by `delta`. Spans which are
+/// themselves edited are resized to fit the replacement text.
+fn move_span(span: Span, edits: &[TextEdit], delta: isize) -> Span {
+    let shift = |pos: Pos| {
+        let before = edits.iter().filter(|e| e.span.end() <= pos).count() as isize;
+        Pos::from((pos.offset() as isize + before * delta) as usize)
+    };
+    let start = shift(span.start());
+    match edits.iter().find(|e| e.span == span) {
+        Some(edit) => Span::new(start, Pos::from(start.offset() + edit.new_text.len())),
+        None => Span::new(start, shift(span.end())),
+    }
+}
+
+/// Normalise a Reference Index for Comparison
+///
+/// Maps each span in the index and sorts the references so indexes
+/// built in different orders compare equal.
+fn normalise<F>(references: &HashMap<Span, Vec<Span>>, map: F) -> HashMap<Span, Vec<Span>>
+where
+    F: Fn(Span) -> Span,
+{
+    references
+        .iter()
+        .map(|(&def, refs)| {
+            let mut refs: Vec<_> = refs.iter().map(|&r| map(r)).collect();
+            refs.sort_by_key(|r| r.start());
+            (map(def), refs)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn renamed(text: &str, offset: usize, new_name: &str) -> Result<String, RenameError> {
+        let source = SourceText::new(text);
+        rename(&source, Pos::from(offset), new_name).map(|edits| apply_edits(&source, &edits))
+    }
+
+    #[test]
+    fn rename_variable_and_references() {
+        assert_eq!(
+            Ok("var total = 1\ntotal = total + 2\nprint total\n".into()),
+            renamed("var n = 1\nn = n + 2\nprint n\n", 10, "total")
+        );
+    }
+
+    #[test]
+    fn rename_function_and_parameter() {
+        let text = "fn f(n: Number): Number\n  f(n - 1) if n > 0 else n\nend\nprint f(3)\n";
+        assert_eq!(
+            Ok("fn go(n: Number): Number\n  go(n - 1) if n > 0 else n\nend\nprint go(3)\n".into()),
+            renamed(text, 3, "go")
+        );
+        assert_eq!(
+            Ok("fn f(x: Number): Number\n  f(x - 1) if x > 0 else x\nend\nprint f(3)\n".into()),
+            renamed(text, 5, "x")
+        );
+    }
+
+    #[test]
+    fn rename_one_of_two_redeclarations() {
+        // Each `print` still prints the same variable, so the program
+        // still prints `0` then `1`.
+        let text = "var a = 0\nprint a\nvar a = 1\nprint a\n";
+        assert_eq!(
+            Ok("var b = 0\nprint b\nvar a = 1\nprint a\n".into()),
+            renamed(text, 4, "b")
+        );
+        assert_eq!(
+            Ok("var a = 0\nprint a\nvar b = 1\nprint b\n".into()),
+            renamed(text, 34, "b")
+        );
+    }
+
+    #[test]
+    fn ambiguous_references_are_detected() {
+        let span = |s: usize| Span::new(Pos::from(s), Pos::from(s + 1));
+        let mut references = HashMap::new();
+        references.insert(span(4), vec![span(4), span(16)]);
+        references.insert(span(22), vec![span(22), span(34)]);
+        assert!(!is_ambiguous(&references));
+        references.insert(span(4), vec![span(4), span(16), span(34)]);
+        assert!(is_ambiguous(&references));
+    }
+
+    #[test]
+    fn rename_refuses_collisions() {
+        // Same scope
+        assert_eq!(
+            Err(RenameError::Collision("a".into())),
+            renamed("let a = 1\nlet b = 2\nprint a + b\n", 14, "a")
+        );
+        // The renamed parameter would be shadowed by a local
+        assert_eq!(
+            Err(RenameError::Collision("m".into())),
+            renamed(
+                "fn f(n: Number): Number\n  let m = 2\n  n + m\nend\n",
+                5,
+                "m"
+            )
+        );
+        // Builtin types
+        assert_eq!(
+            Err(RenameError::Collision("Number".into())),
+            renamed("let a: Number = 1\nlet b: Number = a\n", 4, "Number")
+        );
+    }
+
+    #[test]
+    fn rename_refuses_invalid_requests() {
+        assert_eq!(
+            Err(RenameError::InvalidName("while".into())),
+            renamed("let a = 1\n", 4, "while")
+        );
+        assert_eq!(
+            Err(RenameError::InvalidName("1a".into())),
+            renamed("let a = 1\n", 4, "1a")
+        );
+        assert_eq!(
+            Err(RenameError::NoSymbol(8)),
+            renamed("let a = 1\n", 8, "b")
+        );
+    }
+}
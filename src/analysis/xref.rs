@@ -0,0 +1,87 @@
+//! Cross References
+//!
+//! Lists each symbol defined in a source along with every place it
+//! is referenced. Definitions and references are found by binding
+//! the source, so only identifiers which resolve are included.
+
+use crate::sem::{Binder, Scope};
+use crate::syntax::text::{SourceText, Span};
+use crate::syntax::SyntaxTree;
+use std::io::{self, prelude::*};
+
+/// Find the Cross References in a Source
+///
+/// Returns each definition in the source along with the references
+/// to it. Definitions, and the references to each, are in source
+/// order. The definition itself isn't included in its references.
+pub fn cross_references(source: &SourceText) -> Vec<(Span, Vec<Span>)> {
+    let tree = SyntaxTree::parse(source);
+    let mut binder = Binder::new(Scope::new());
+    binder.bind_tree(tree);
+
+    let mut xrefs: Vec<_> = binder
+        .references()
+        .iter()
+        .map(|(&def, refs)| {
+            let mut refs: Vec<_> = refs.iter().cloned().filter(|&r| r != def).collect();
+            refs.sort_by_key(|r| r.start());
+            (def, refs)
+        })
+        .collect();
+    xrefs.sort_by_key(|(def, _)| def.start());
+    xrefs
+}
+
+/// Write the Cross References for a Source
+///
+/// Writes one line for each definition giving its position and name
+/// followed by the positions of each reference to it. Lines are
+/// numbered from 1 and columns from 0, as in diagnostics.
+pub fn write_xref<W: Write>(source: &SourceText, writer: &mut W) -> io::Result<()> {
+    for (def, refs) in cross_references(source) {
+        let (line, col) = source.position(def);
+        write!(
+            writer,
+            "{}:{} {}",
+            line,
+            col,
+            source.slice(def.start(), def.end())
+        )?;
+        for (i, r) in refs.into_iter().enumerate() {
+            let (line, col) = source.position(r);
+            let sep = if i == 0 { " -> " } else { ", " };
+            write!(writer, "{}{}:{}", sep, line, col)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_cross_references() {
+        let source = SourceText::new(
+            "fn sq(n: Number): Number\n  n * n\nend\nlet x = sq(2)\nlet unused = sq(x)\n",
+        );
+        let mut buff = Vec::new();
+        write_xref(&source, &mut buff).unwrap();
+        assert_eq!(
+            "1:3 sq -> 4:8, 5:13\n1:6 n -> 2:2, 2:6\n4:4 x -> 5:16\n5:4 unused\n",
+            String::from_utf8(buff).unwrap()
+        );
+    }
+
+    #[test]
+    fn redeclarations_have_their_own_references() {
+        let source = SourceText::new("var a = 0\nprint a\nvar a = 1\nprint a\n");
+        let mut buff = Vec::new();
+        write_xref(&source, &mut buff).unwrap();
+        assert_eq!(
+            "1:4 a -> 2:6\n3:4 a -> 4:6\n",
+            String::from_utf8(buff).unwrap()
+        );
+    }
+}
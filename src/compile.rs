@@ -3,46 +3,189 @@
 
 use crate::diag::Diagnostic;
 use crate::low_loader::prelude::*;
+use crate::meta;
 use crate::sem;
-use crate::syntax;
+use crate::syntax::{self, text::DUMMY_SPAN};
+use std::fs;
 use std::path::Path;
 use std::process::Command;
 use tempfile::Builder;
 
+pub use self::embed::{JitFunction, JitProgram, JitSignature, JitValue};
 pub use self::error::{CompError, CompResult};
-pub use self::options::{CompilationOptions, OptimisationLevel};
+pub use self::hooks::{HookResult, Hooks};
+pub use self::options::{
+    CRuntime, CompilationOptions, EmitKind, GcStrategy, OptimisationLevel, RelocModel, Sanitizers,
+    StackProtector, WarningLevel,
+};
+pub use self::test_runner::{TestOutcome, TestResult, TEST_PREFIX};
 
+pub mod embed;
 pub mod error;
+pub mod hooks;
 pub mod options;
 
 mod lower;
 mod lower_context;
+mod runtime;
 mod string_builtins;
+mod test_runner;
 
-/// Add the Core Declarations to the Module
+/// Module name used when there is no output path to derive one from.
+const FALLBACK_MODULE_NAME: &str = "fallback_module_name";
+
+/// Convert a Module to Bytes
 ///
-/// This method is responsible for making sure that
-/// declarations/definitions of any builtin funtions are emitted.
-fn add_core_decls(ctx: &mut Context, module: &mut Module) -> CompResult<()> {
-    add_printf_decl(ctx, module);
-    module.add_global(ctx.const_str("%lld\n"), "printf_num_format");
-    module.add_global(ctx.const_str("%s\n"), "printf_cstr_format");
-    module.add_global(ctx.const_str("%.*s\n"), "printf_ustr_format");
-    module.add_global(ctx.const_str("true"), "print_true");
-    module.add_global(ctx.const_str("false"), "print_false");
+/// Serialises the module in the format requested by `kind`.
+fn module_to_bytes(
+    module: &Module,
+    machine: &TargetMachine,
+    kind: EmitKind,
+) -> CompResult<Vec<u8>> {
+    Ok(match kind {
+        EmitKind::Executable => {
+            return Err(CompError::Generic(
+                "executables can't be emitted to memory".into(),
+            ))
+        }
+        EmitKind::LlvmIr => module.to_ir_string().into_bytes(),
+        EmitKind::Bitcode => module.to_bitcode_buffer().into(),
+        EmitKind::Object => machine
+            .emit_to_memory_buffer(module, CodeGenFileType::Object)?
+            .into(),
+        EmitKind::Assembly => machine
+            .emit_to_memory_buffer(module, CodeGenFileType::Assembly)?
+            .into(),
+    })
+}
+
+/// Clean Up an Unoptimised Module
+///
+/// Promotes any stack slots to registers, and removes the dead code
+/// and empty blocks lowering leaves behind, such as the blocks after a
+/// `break`. This keeps code built without optimisation from being
+/// needlessly slow, and its IR readable, without changing its shape.
+fn run_cleanup(module: &mut Module, machine: &TargetMachine) -> CompResult<()> {
+    machine
+        .run_passes(module, "function(mem2reg,simplifycfg,dce)")
+        .map_err(|e| CompError::from(format!("could not clean up module: {}", e)))
+}
+
+/// Instrument a Module with AddressSanitizer
+///
+/// Only functions marked with the `sanitize_address` attribute are
+/// instrumented, so every function defined in the module is marked
+/// before the passes are run.
+///
+/// Without reference counting heap values are never freed, or are
+/// freed by a collector LeakSanitizer can't see, so leak detection is
+/// turned off by default unless `detect_leaks` is set. It can still be
+/// turned back on with `ASAN_OPTIONS`.
+fn add_address_sanitizer(
+    ctx: &mut Context,
+    module: &mut Module,
+    machine: &TargetMachine,
+    detect_leaks: bool,
+) -> CompResult<()> {
+    for mut fun in module.defined_functions() {
+        fun.add_attribute(Attribute::SanitizeAddress);
+    }
+    // From LLVM 14 the module pass instruments functions too. LLVM
+    // 15 renamed it, so fall back to the new name if the old one
+    // can't be parsed.
+    machine
+        .run_passes(module, "asan-module")
+        .or_else(|_| machine.run_passes(module, "asan"))
+        .map_err(|e| CompError::from(format!("could not add address sanitizer: {}", e)))?;
+    if detect_leaks {
+        return Ok(());
+    }
+
+    // The runtime calls this before it is initialised, so it must be
+    // added after the instrumentation passes have run.
+    let options = unsafe { module.add_global(ctx.const_str("detect_leaks=0"), "asan_options") };
+    let cstr_type = ctx.cstr_type();
+    let mut fun = unsafe { ctx.add_function(module, "__asan_default_options", cstr_type, &mut []) };
+    fun.set_calling_convention(CallConvention::CDecl);
+    let entry = ctx.add_block(&mut fun, "entry");
+    let mut builder = ctx.add_builder();
+    unsafe { builder.position_at_end(entry) };
+    let options = unsafe { builder.build_gep(options, &mut [ctx.const_int(0), ctx.const_int(0)]) };
+    unsafe { builder.build_ret(options) };
     Ok(())
 }
 
-/// Add a Printf Declaration to the Module
+/// Add Stack Protection to a Module
 ///
-/// Creates a new function in the given module which maps to the
-/// `printf` function. This will be used by the `print` operator
-/// to write output.
-fn add_printf_decl(ctx: &mut Context, module: &mut Module) {
-    let mut params = [ctx.cstr_type()];
-    let int_type = ctx.int_type(32);
-    let mut printf = ctx.add_varargs_function(module, "printf", int_type, &mut params);
-    printf.set_calling_convention(CallConvention::CDecl);
+/// Marks each function defined in the module with the attributes
+/// requested by the options. This is done before optimisation so
+/// code inlined from one function into another stays protected.
+fn add_stack_protection(module: &mut Module, options: &CompilationOptions) {
+    let protector = options.stack_protector.attribute();
+    for mut fun in module.defined_functions() {
+        if let Some(attr) = protector {
+            fun.add_attribute(attr);
+        }
+        if options.stack_probes {
+            fun.add_string_attribute("probe-stack", "inline-asm");
+        }
+    }
+}
+
+/// Convert a Hook Veto into Diagnostics
+fn hook_diagnostics(message: String) -> Vec<Diagnostic> {
+    vec![Diagnostic::new(
+        format!("compilation aborted: {}", message),
+        DUMMY_SPAN,
+    )]
+}
+
+/// Convert the LLVM Diagnostics from a Context
+///
+/// Takes the diagnostics LLVM has raised in the given context. If
+/// any are errors, or are warnings and warnings are denied, then
+/// compilation fails with them. Otherwise any warnings are returned.
+/// Notes, and remarks if `remarks` is set, are informational, so they
+/// are returned as notes and never fail compilation.
+fn llvm_diagnostics(
+    ctx: &Context,
+    level: WarningLevel,
+    remarks: bool,
+) -> CompResult<Vec<Diagnostic>> {
+    convert_llvm_diagnostics(ctx.take_diagnostics(), level, remarks)
+}
+
+/// Convert LLVM Diagnostics
+///
+/// Implementation of `llvm_diagnostics`.
+fn convert_llvm_diagnostics(
+    diagnostics: Vec<LlvmDiagnostic>,
+    level: WarningLevel,
+    remarks: bool,
+) -> CompResult<Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for diagnostic in diagnostics {
+        let message = |kind| format!("LLVM {}: {}", kind, diagnostic.message);
+        match diagnostic.severity {
+            Severity::Error => errors.push(Diagnostic::new(message("error"), DUMMY_SPAN)),
+            Severity::Warning if level == WarningLevel::Deny => {
+                errors.push(Diagnostic::new(message("warning"), DUMMY_SPAN))
+            }
+            Severity::Warning if level == WarningLevel::Allow => (),
+            Severity::Warning => warnings.push(Diagnostic::new(message("warning"), DUMMY_SPAN)),
+            Severity::Remark if !remarks => (),
+            Severity::Remark => {
+                warnings.push(Diagnostic::new(message("remark"), DUMMY_SPAN).as_note())
+            }
+            Severity::Note => warnings.push(Diagnostic::new(message("note"), DUMMY_SPAN).as_note()),
+        }
+    }
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(CompError::Codegen(errors))
+    }
 }
 
 /// Compilation State
@@ -53,96 +196,1114 @@ pub struct Compilation {
     expr: sem::Expression,
     /// The options for this compilation
     options: CompilationOptions,
-    /// diagnostics from this compilation
-    diagnostics: Vec<Diagnostic>,
+    /// Warnings raised while binding the tree
+    warnings: Vec<Diagnostic>,
+    /// The source being compiled. Used to give the location of
+    /// runtime errors.
+    source: syntax::text::SourceText,
 }
 
 impl Compilation {
     /// Create a new compilation
     ///
+    /// Binds the given syntax tree ready for emission. A compilation
+    /// can only be created from a tree which parses and binds
+    /// cleanly.
+    ///
     /// # Parameters
-    ///  * `source` - The source text for the program
-    ///  * `expr` - the expression to compile
+    ///  * `tree` - The parsed syntax tree of the program
     ///  * `opts` - The compilation options
-    #[allow(clippy::new_ret_no_self)]
-    pub fn new(tree: syntax::SyntaxTree, opts: CompilationOptions) -> CompResult<Self> {
-        let mut binder = sem::Binder::new(sem::Scope::new());
+    ///
+    /// # Errors
+    ///
+    /// If the tree contains any parse diagnostics, or if binding the
+    /// tree raises any diagnostics, then every diagnostic found is
+    /// returned rather than a `Compilation`. The same is true of
+    /// warnings raised by the bind if warnings are denied. If a parse
+    /// or bind hook vetoes the compilation its message is returned as
+    /// a diagnostic.
+    pub fn new(
+        tree: syntax::SyntaxTree,
+        opts: CompilationOptions,
+    ) -> Result<Self, Vec<Diagnostic>> {
+        if tree.has_diagnostics() {
+            return Err(tree.diagnostics().to_vec());
+        }
+        opts.hooks
+            .run_after_parse(&tree)
+            .map_err(hook_diagnostics)?;
+        let source = tree.source();
+        let source = syntax::text::SourceText::with_name(
+            source.slice(source.start(), source.end()),
+            source.name(),
+        );
+
+        let mut binder = sem::Binder::new(sem::Scope::new())
+            .with_edition(opts.edition)
+            .with_lint_levels(opts.lint_levels.clone());
+        if let Some(cfg) = &opts.cfg {
+            binder = binder.with_cfg(cfg.clone());
+        }
+        if let Some(entry) = &opts.entry {
+            binder = binder.with_entry(entry.clone());
+        }
         let sem_expr = binder.bind_tree(tree);
+        let diagnostics = binder.take_diagnostics();
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        let warnings = match opts.warning_level {
+            WarningLevel::Allow => Vec::new(),
+            WarningLevel::Warn => binder.take_warnings(),
+            WarningLevel::Deny => {
+                let warnings = binder.take_warnings();
+                if !warnings.is_empty() {
+                    return Err(warnings);
+                }
+                warnings
+            }
+        };
+        opts.hooks
+            .run_after_bind(&sem_expr)
+            .map_err(hook_diagnostics)?;
+
         Ok(Compilation {
             expr: sem_expr,
             options: opts,
-            diagnostics: binder.take_diagnostics(),
+            warnings,
+            source,
         })
     }
 
     /// Emit
     ///
-    /// Performs the compilation, emitting the results to the given file.
-    pub fn emit(self, target: &Target, output_path: &Path) -> CompResult<()> {
-        if !self.diagnostics.is_empty() {
-            return Err(CompError::Generic(
-                "can't emit a compilation contianing diagnostics".into(),
-            ));
+    /// Performs the compilation, emitting the results to the given
+    /// file. The kind of file written is controlled by the `emit_kind`
+    /// in the compilation options. Unless a `module_name` is set the
+    /// module is named after the output file. Any warnings raised while binding
+    /// or by LLVM are returned, unless the `warning_level` allows
+    /// them.
+    ///
+    /// If `save_temps` is set the module's LLVM IR, and the object
+    /// file handed to the linker, are kept. They're named after the
+    /// module with `.ll` and `.o` extensions. The runtime library is
+    /// kept too.
+    pub fn emit(mut self, target: &Target, output_path: &Path) -> CompResult<Vec<Diagnostic>> {
+        let mut warnings = std::mem::take(&mut self.warnings);
+        let kind = self.options.emit_kind;
+        let warning_level = self.options.warning_level;
+        let remarks = self.options.llvm_remarks;
+        let machine = self.create_target_machine(target)?;
+        let link_hooks = self.options.hooks.split_before_link();
+
+        let mut ctx = Context::new();
+        let name = match &self.options.module_name {
+            Some(name) => name.clone(),
+            None => output_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or(FALLBACK_MODULE_NAME)
+                .to_string(),
+        };
+        // Work out where to write the object file to. Unless it's
+        // being kept it goes in a temporary file. The link command is
+        // worked out now as building the module consumes the
+        // compilation.
+        let temps_dir = self.options.temps_dir_for(output_path);
+        if let Some(dir) = &temps_dir {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp_file = None;
+        let object_path = match &temps_dir {
+            Some(dir) => dir.join(format!("{}.o", name)),
+            None => temp_file
+                .get_or_insert(Builder::new().prefix("ullage").suffix(".o").tempfile()?)
+                .path()
+                .to_path_buf(),
+        };
+        // Executables are linked against the runtime library, which
+        // is written out alongside the object file. Freestanding
+        // programs provide their own.
+        let mut runtime_file = None;
+        let runtime_path = if kind == EmitKind::Executable && !self.options.freestanding {
+            if !runtime::is_available_for(target.triple()) {
+                return Err(CompError::from(format!(
+                    "the runtime library isn't available for '{}'",
+                    target.triple()
+                )));
+            }
+            let path = match &temps_dir {
+                Some(dir) => dir.join(runtime::ARCHIVE_NAME),
+                None => runtime_file
+                    .get_or_insert(Builder::new().prefix("ullage_rt").suffix(".a").tempfile()?)
+                    .path()
+                    .to_path_buf(),
+            };
+            runtime::write_archive(&path, self.options.gc)?;
+            Some(path)
+        } else {
+            None
+        };
+        let command = self.link_command(&object_path, runtime_path.as_deref(), target, output_path);
+        let module = self.build_module(&mut ctx, &machine, &name)?;
+
+        if let Some(dir) = &temps_dir {
+            if kind != EmitKind::LlvmIr {
+                fs::write(dir.join(format!("{}.ll", name)), module.to_ir_string())?;
+            }
         }
 
+        if kind != EmitKind::Executable {
+            let bytes = module_to_bytes(&module, &machine, kind);
+            warnings.append(&mut llvm_diagnostics(&ctx, warning_level, remarks)?);
+            fs::write(output_path, bytes?)?;
+            return Ok(warnings);
+        }
+
+        let emitted = machine.emit_to_file(&module, &object_path, CodeGenFileType::Object);
+        warnings.append(&mut llvm_diagnostics(&ctx, warning_level, remarks)?);
+        emitted?;
+
+        // Shell out to the linker to link the final executable
+        link_hooks
+            .run_before_link(&command)
+            .map_err(CompError::Aborted)?;
+        let linker = &command[0];
+        let output = Command::new(linker)
+            .args(&command[1..])
+            .output()
+            .map_err(|e| CompError::link_not_run(linker, e))?;
+        let status = output.status;
+
+        if status.success() {
+            Ok(warnings)
+        } else {
+            Err(CompError::link_fail(linker, status.code(), &output.stderr))
+        }
+    }
+
+    /// Get the Link Command
+    ///
+    /// Returns the command line used to link `object`, and the
+    /// runtime library if there is one, into an executable at
+    /// `output_path`, starting with the linker itself.
+    fn link_command(
+        &self,
+        object: &Path,
+        runtime: Option<&Path>,
+        target: &Target,
+        output_path: &Path,
+    ) -> Vec<String> {
+        let mut command = vec![
+            self.options.linker().to_string(),
+            object.display().to_string(),
+        ];
+        command.extend(runtime.map(|path| path.display().to_string()));
+        command.extend(vec![
+            format!("--target={}", target.triple()),
+            "-o".to_string(),
+            output_path.display().to_string(),
+        ]);
+        command.extend(self.options.all_linker_args());
+        command
+    }
+
+    /// Emit to Memory
+    ///
+    /// Performs the compilation and returns the resulting artifact as
+    /// a buffer of bytes rather than writing it to disk. For
+    /// `EmitKind::LlvmIr` and `EmitKind::Assembly` the buffer contains
+    /// UTF-8 text.
+    ///
+    /// # Errors
+    ///
+    /// Executables can't be emitted to memory as they require an
+    /// external link step. Errors raised by LLVM fail the emission,
+    /// but as there is nowhere to return them warnings are dropped
+    /// unless they are denied.
+    pub fn emit_to_memory(self, target: &Target, kind: EmitKind) -> CompResult<Vec<u8>> {
+        let machine = self.create_target_machine(target)?;
+        let warning_level = self.options.warning_level;
+        let remarks = self.options.llvm_remarks;
         let mut ctx = Context::new();
-        let name = output_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("fallback_module_name");
-        let mut module = ctx.add_module(name);
-        module.set_target(target);
+        let name = self
+            .options
+            .module_name
+            .clone()
+            .unwrap_or_else(|| FALLBACK_MODULE_NAME.to_string());
+        let module = self.build_module(&mut ctx, &machine, &name)?;
+        let bytes = module_to_bytes(&module, &machine, kind);
+        llvm_diagnostics(&ctx, warning_level, remarks)?;
+        bytes
+    }
+
+    /// Create the Target Machine
+    ///
+    /// Configures a target machine for the given target using the
+    /// CPU, features, optimisation level, and relocation model from
+    /// the options. Sanitized code is always position independent, as
+    /// the instrumentation refers to hidden symbols which can't be
+    /// relocated absolutely in a position independent executable.
+    fn create_target_machine(&self, target: &Target) -> CompResult<TargetMachine> {
+        let opts = &self.options;
+        let reloc_model = if opts.sanitizers.any() {
+            RelocModel::Pic
+        } else {
+            opts.reloc_model
+        };
+        target
+            .create_target_machine(
+                opts.target_cpu.as_ref().map_or("", |c| &c[..]),
+                opts.target_features.as_ref().map_or("", |f| &f[..]),
+                opts.opt_level.into(),
+                reloc_model.into(),
+            )
+            .map_err(CompError::from)
+    }
 
-        add_core_decls(&mut ctx, &mut module)?;
+    /// Build the Module
+    ///
+    /// Folds the constants in the bound expression, lowers it into a
+    /// new LLVM module in the given context, and runs any requested
+    /// optimisations over it.
+    /// The module's triple and data layout are set to match the
+    /// target machine.
+    fn build_module(
+        self,
+        ctx: &mut Context,
+        machine: &TargetMachine,
+        name: &str,
+    ) -> CompResult<Module> {
+        let mut module = ctx.add_module(name);
+        module.set_source_file_name(self.source.name());
+        module.set_target(machine);
+        module.add_ident(&meta::descriptive_version());
+        module.add_flag("Ullage Edition", self.options.edition.year());
+        if self.options.build_info {
+            let info = meta::build_info(&machine.triple(), &self.options.codegen_flags());
+            let bytes = info
+                .bytes()
+                .chain(Some(0))
+                .map(|b| ctx.const_char(b))
+                .collect::<Vec<_>>();
+            // Nothing refers to the build info, so it has to be marked
+            // used to survive optimisation and `--gc-sections`.
+            unsafe {
+                let global = module.add_global_array(ctx.int_type(8), &bytes, "ullage_build_info");
+                module.mark_used(global);
+            }
+        }
 
         let fun = {
-            let mut lower_ctx = lower_context::LowerContext::new(&mut ctx, &mut module);
-            lower_ctx.add_intrinsics();
+            let mut lower_ctx = lower_context::LowerContext::new(ctx, &mut module);
             lower_ctx.add_core_types();
-            lower::lower_as_main(&mut lower_ctx, self.expr)?
+            lower_ctx.overflow_checks =
+                self.options.overflow_checks || self.options.sanitizers.undefined;
+            lower_ctx.reference_counting = self.options.gc.is_reference_counted();
+            lower_ctx.runtime_checks = !self.options.no_runtime_checks;
+            lower_ctx.freestanding = self.options.freestanding;
+            lower_ctx.source = Some(&self.source);
+            if self.options.debug_info {
+                let optimised = self.options.opt_level.unpack().is_some();
+                lower_ctx.debug_info = Some(lower_context::DebugInfo::new(
+                    lower_ctx.module,
+                    self.source.name(),
+                    optimised,
+                ));
+            }
+            let expr = sem::fold_constants(self.expr);
+            let fun = lower::lower_as_main(&mut lower_ctx, expr, self.options.entry.as_deref())?;
+            lower_ctx.finalise_debug_info();
+            fun
         };
 
-        fun.verify_or_panic();
-        module.verify_or_panic();
-
-        // Create a tempdir to write the LLVM IR to
-        let temp_file = Builder::new().prefix("ullage").suffix(".ll").tempfile()?;
+        fun.verify()
+            .map_err(|e| CompError::invalid_function(&fun, e))?;
+        module.verify().map_err(CompError::InvalidModule)?;
+        add_stack_protection(&mut module, &self.options);
 
         // check if we have optimiation enabled and run the
-        // corresponding optimisations if we do.
+        // corresponding optimisations if we do. Otherwise just tidy
+        // up what lowering left behind.
         if let Some((level, size)) = self.options.opt_level.unpack() {
             module.run_optimiser(level, size);
+        } else if !self.options.no_cleanup {
+            run_cleanup(&mut module, machine)?;
+        }
+        if self.options.sanitizers.address {
+            let detect_leaks = self.options.gc.is_reference_counted();
+            add_address_sanitizer(ctx, &mut module, machine, detect_leaks)?;
         }
 
+        self.options
+            .hooks
+            .run_after_lower(&module)
+            .map_err(CompError::Aborted)?;
+
         // Check what we have, and dump it to the screen
         if self.options.dump_ir {
-            module.dump();
+            print!("{}", module.to_ir_string());
         }
-        module.write_to_file(temp_file.path())?;
 
-        // Shell out to Clang to link the final assembly
-        let output = Command::new("clang")
-            .arg(temp_file.path())
-            .arg(format!("--target={}", target.triple()))
-            .arg("-o")
-            .arg(output_path)
-            .output()?;
-        let status = output.status;
+        Ok(module)
+    }
+}
 
-        if status.success() {
-            Ok(())
-        } else {
-            Err(CompError::link_fail(status.code(), output.stderr))
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn compilation_from_clean_tree() {
+        let source = SourceText::new("fn id(n: Number): Number n end print id(1)");
+        let tree = syntax::SyntaxTree::parse(&source);
+        assert!(Compilation::new(tree, CompilationOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn emit_ir_to_memory() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("define"));
+        assert!(ir.contains("@main"));
+    }
+
+    #[test]
+    fn runtime_functions_declared_on_use() {
+        let emit = |text| {
+            let source = SourceText::new(text);
+            let tree = syntax::SyntaxTree::parse(&source);
+            let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let silent = emit("let x = 1 + 2");
+        assert!(!silent.contains("declare"), "{}", silent);
+
+        let printed = emit("print 1\nprint to_string(2)\nprint 'three'");
+        let declarations = |name: &str| {
+            printed
+                .lines()
+                .filter(|line| line.starts_with("declare") && line.contains(name))
+                .count()
+        };
+        assert_eq!(1, declarations("@ullage_rt_print("));
+        assert_eq!(1, declarations("@ullage_rt_number_to_string("));
+        assert_eq!(0, declarations("@ullage_rt_string_to_number("));
+    }
+
+    #[test]
+    fn strings_are_reference_counted() {
+        let emit = |text| {
+            let source = SourceText::new(text);
+            let tree = syntax::SyntaxTree::parse(&source);
+            let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+        let calls = |ir: &str, name: &str| {
+            ir.lines()
+                .filter(|line| line.contains("call") && line.contains(name))
+                .count()
+        };
+
+        // The declaration and `print s` each retain `s`. Its last use
+        // moves it, so there's nothing left to release at the end of
+        // `main`. Concatenation releases both its operands, and the
+        // sequence releases each unused statement's value.
+        let ir = emit("let s = 'ab'\nprint s\nprint s + 'c'");
+        assert_eq!(2, calls(&ir, "@ullage_rt_string_retain("), "{}", ir);
+        assert_eq!(5, calls(&ir, "@ullage_rt_string_release("), "{}", ir);
+
+        // Numbers aren't counted
+        let ir = emit("var n = 1\nn = n + 1");
+        assert!(!ir.contains("ullage_rt_string_re"), "{}", ir);
+    }
+
+    #[test]
+    fn locals_are_kept_in_ssa_form() {
+        let source = SourceText::new(
+            "var i = 0\nvar total = 0\nwhile i < 3\n  var i2 = i * 2\n  total = total + i2 if i > 1 else total\n  i = i + 1\nend\nprint total",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+
+        // Even without optimisation variables never touch the stack.
+        // Values are merged with phis named after the variable.
+        assert!(!ir.contains("alloca"), "{}", ir);
+        assert!(!ir.contains("load"), "{}", ir);
+        assert!(ir.contains("%i = phi i64"), "{}", ir);
+        assert!(ir.contains("%total = phi i64"), "{}", ir);
+        assert!(!ir.contains("%i2 = phi"), "{}", ir);
+    }
+
+    #[test]
+    fn unoptimised_code_is_cleaned_up() {
+        let emit = |options: CompilationOptions| {
+            let source = SourceText::new(
+                "var i = 0\nwhile true\n  i = i + 1\n  break if i > 3 else 0\nend\nprint i",
+            );
+            let tree = syntax::SyntaxTree::parse(&source);
+            let comp = Compilation::new(tree, options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let ir = emit(CompilationOptions::default());
+        assert!(!ir.contains("afterjump"), "{}", ir);
+        let ir = emit(CompilationOptions::default().with_no_cleanup(true));
+        assert!(ir.contains("afterjump"), "{}", ir);
+    }
+
+    #[test]
+    fn shadowed_locals_are_restored() {
+        let source = SourceText::new(
+            "var s = 'outer'\nvar i = 0\nwhile i < 1\n  var s = 'inner'\n  print s\n  i = i + 1\nend\nprint s",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        assert!(comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .is_ok());
+    }
+
+    #[test]
+    fn long_concatenations_use_a_builder() {
+        let emit = |text| {
+            let source = SourceText::new(text);
+            let tree = syntax::SyntaxTree::parse(&source);
+            let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let pair = emit("fn f(a: String): String a + a end");
+        assert!(!pair.contains("ullage_rt_builder_"), "{}", pair);
+
+        let chain = emit("fn f(a: String): String a + '-' + a + a end");
+        let calls = |name: &str| {
+            chain
+                .lines()
+                .filter(|line| line.contains("call") && line.contains(name))
+                .count()
+        };
+        assert_eq!(1, calls("@ullage_rt_builder_new("), "{}", chain);
+        assert_eq!(4, calls("@ullage_rt_builder_append("), "{}", chain);
+        assert_eq!(1, calls("@ullage_rt_builder_release("), "{}", chain);
+        assert_eq!(0, calls("@ullage_rt_string_alloc("), "{}", chain);
+    }
+
+    #[test]
+    fn collected_strings_are_not_reference_counted() {
+        for &gc in &[GcStrategy::Boehm, GcStrategy::None] {
+            let source = SourceText::new("let s = 'a'\nprint s\nprint s + 'b'");
+            let tree = syntax::SyntaxTree::parse(&source);
+            let options = CompilationOptions::default().with_gc(gc);
+            let comp = Compilation::new(tree, options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            let ir = String::from_utf8(ir).unwrap();
+            assert!(ir.contains("@ullage_rt_string_alloc("), "{}", ir);
+            assert!(!ir.contains("ullage_rt_string_re"), "{}", ir);
+        }
+    }
+
+    #[test]
+    fn entry_point_is_called_from_main() {
+        let source = SourceText::new("fn start(): Number 3 end");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let options = CompilationOptions::default().with_entry("start");
+        let comp = Compilation::new(tree, options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("call fastcc i64 @start()"), "{}", ir);
+    }
+
+    #[test]
+    fn modules_identify_the_compiler() {
+        let source = SourceText::with_name("print 1", "hello.ulg");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("source_filename = \"hello.ulg\""), "{}", ir);
+        assert!(ir.contains(&meta::descriptive_version()), "{}", ir);
+        assert!(ir.contains("!\"Ullage Edition\", i32 2026"), "{}", ir);
+    }
+
+    #[test]
+    fn debug_info_describes_functions() {
+        let source = SourceText::with_name(
+            "fn twice(n: Number): Number\n  n * 2\nend\nprint twice(21)",
+            "src/twice.ulg",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let options = CompilationOptions::default().with_debug_info(true);
+        let comp = Compilation::new(tree, options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(
+            ir.contains("!DIFile(filename: \"twice.ulg\", directory: \"src\")"),
+            "{}",
+            ir
+        );
+        assert!(ir.contains("!DISubprogram(name: \"main\""), "{}", ir);
+        assert!(ir.contains("!DISubprogram(name: \"twice\""), "{}", ir);
+        assert!(ir.contains("!DILocation(line: 2, column: 3"), "{}", ir);
+    }
+
+    #[test]
+    fn build_info_is_embedded() {
+        let emit = |options: CompilationOptions| {
+            let source = SourceText::new("print 1");
+            let tree = syntax::SyntaxTree::parse(&source);
+            let comp = Compilation::new(tree, options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let ir = emit(CompilationOptions::default());
+        assert!(!ir.contains("ullage_build_info"), "{}", ir);
+        let options = CompilationOptions::default()
+            .with_opt_level(OptimisationLevel::High)
+            .with_save_temps(true)
+            .with_build_info(true);
+        let ir = emit(options);
+        let info = meta::build_info(
+            Target::default().triple(),
+            &["-O3".into(), "--edition=2026".into()],
+        );
+        assert!(ir.contains("@ullage_build_info = "), "{}", ir);
+        assert!(ir.contains(&info), "{}", ir);
+        assert!(info.ends_with("options=-O3 --edition=2026"), "{}", info);
+        assert!(
+            ir.contains("@llvm.used = appending global [1 x i8*] [i8* getelementptr"),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn division_checks_for_zero() {
+        let source = SourceText::with_name(
+            "fn half(n: Number): Number\n  n / 2\nend\nvar d = 0\nprint half(1) / d",
+            "div.ulg",
+        );
+        // The divisor is constant, so cleanup would remove the
+        // division which can never be reached.
+        let tree = syntax::SyntaxTree::parse(&source);
+        let options = CompilationOptions::default().with_no_cleanup(true);
+        let comp = Compilation::new(tree, options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert_eq!(2, ir.matches("sdiv").count(), "{}", ir);
+        assert!(
+            ir.contains("div.ulg:2:2:error: attempt to divide by zero"),
+            "{}",
+            ir
+        );
+        assert!(
+            ir.contains("div.ulg:5:6:error: attempt to divide by zero"),
+            "{}",
+            ir
+        );
+        assert!(
+            ir.contains("div.ulg:5:6:error: attempt to divide with overflow"),
+            "{}",
+            ir
+        );
+
+        let tree = syntax::SyntaxTree::parse(&source);
+        let options = CompilationOptions::default()
+            .with_no_cleanup(true)
+            .with_no_runtime_checks(true);
+        let comp = Compilation::new(tree, options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert_eq!(2, ir.matches("sdiv").count(), "{}", ir);
+        assert!(!ir.contains("divide by zero"), "{}", ir);
+        assert!(!ir.contains("divide with overflow"), "{}", ir);
+    }
+
+    #[test]
+    fn stack_protection_is_added_to_functions() {
+        let source = SourceText::new("fn f(): Number 1 end\nprint f()");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let options = CompilationOptions::default()
+            .with_stack_protector(StackProtector::Strong)
+            .with_stack_probes(true);
+        let comp = Compilation::new(tree, options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert_eq!(2, ir.matches("define ").count(), "{}", ir);
+        assert!(ir.contains("sspstrong"), "{}", ir);
+        assert!(ir.contains("\"probe-stack\"=\"inline-asm\""), "{}", ir);
+
+        let source = SourceText::new("print 1");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(!ir.contains("ssp"), "{}", ir);
+        assert!(!ir.contains("probe-stack"), "{}", ir);
+    }
+
+    #[test]
+    fn function_attributes_are_lowered() {
+        let source = SourceText::new(
+            "#[inline]\nfn hinted(): Number 1 end\n#[export]\nfn exported(): Number 2 end\nfn plain(): Number 3 end\nprint hinted() + exported() + plain()",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        let define = |name: &str| {
+            ir.lines()
+                .find(|l| l.starts_with("define") && l.contains(&format!("@{}(", name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(define("plain").contains("fastcc"), "{}", ir);
+        assert!(!define("exported").contains("fastcc"), "{}", ir);
+        assert!(define("hinted").contains("fastcc"), "{}", ir);
+        assert!(ir.contains("inlinehint"), "{}", ir);
+    }
+
+    #[test]
+    fn overflow_checks_trap_on_overflow() {
+        let emit = |overflow_checks| {
+            let source = SourceText::new("var i = 1\nprint i * 2 - i");
+            let tree = syntax::SyntaxTree::parse(&source);
+            let options = CompilationOptions::default().with_overflow_checks(overflow_checks);
+            let comp = Compilation::new(tree, options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let wrapping = emit(false);
+        assert!(!wrapping.contains("with.overflow"), "{}", wrapping);
+        assert!(!wrapping.contains("@ullage_rt_panic"), "{}", wrapping);
+
+        let checked = emit(true);
+        assert!(
+            checked.contains("@llvm.smul.with.overflow.i64"),
+            "{}",
+            checked
+        );
+        assert!(
+            checked.contains("@llvm.ssub.with.overflow.i64"),
+            "{}",
+            checked
+        );
+        assert!(
+            checked.contains("call void @ullage_rt_panic("),
+            "{}",
+            checked
+        );
+        assert!(checked.contains("unreachable"), "{}", checked);
+    }
+
+    #[test]
+    fn sanitizers_instrument_module() {
+        let emit = |sanitizers| {
+            let source = SourceText::new("var i = 1\nprint i * 2");
+            let tree = syntax::SyntaxTree::parse(&source);
+            let options = CompilationOptions::default().with_sanitizers(sanitizers);
+            let comp = Compilation::new(tree, options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let plain = emit(Sanitizers::default());
+        assert!(!plain.contains("sanitize_address"), "{}", plain);
+        assert!(!plain.contains("with.overflow"), "{}", plain);
+
+        let address = emit("address".parse().unwrap());
+        assert!(address.contains("sanitize_address"), "{}", address);
+        assert!(address.contains("@__asan_"), "{}", address);
+        // Leak detection is only disabled when values aren't counted
+        assert!(!address.contains("@__asan_default_options"), "{}", address);
+        let options = CompilationOptions::default()
+            .with_sanitizers("address".parse().unwrap())
+            .with_gc(GcStrategy::None);
+        let source = SourceText::new("print 1");
+        let comp = Compilation::new(syntax::SyntaxTree::parse(&source), options).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("@__asan_default_options"), "{}", ir);
+
+        let undefined = emit("undefined".parse().unwrap());
+        assert!(undefined.contains("with.overflow"), "{}", undefined);
+    }
+
+    #[test]
+    fn module_and_source_names() {
+        let source = SourceText::with_name("print 1", "<stdin>");
+        let emit = |options| {
+            let comp = Compilation::new(syntax::SyntaxTree::parse(&source), options).unwrap();
+            let ir = comp
+                .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+                .unwrap();
+            String::from_utf8(ir).unwrap()
+        };
+
+        let unnamed = emit(CompilationOptions::default());
+        assert!(unnamed.contains("; ModuleID = 'fallback_module_name'"));
+        assert!(unnamed.contains("source_filename = \"<stdin>\""));
+
+        let named = emit(CompilationOptions::default().with_module_name("demo"));
+        assert!(named.contains("; ModuleID = 'demo'"), "{}", named);
+        assert!(named.contains("source_filename = \"<stdin>\""));
+    }
+
+    #[test]
+    fn emit_bitcode_to_memory() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let bitcode = comp
+            .emit_to_memory(&Target::default(), EmitKind::Bitcode)
+            .unwrap();
+        assert_eq!(b"BC\xC0\xDE", &bitcode[..4]);
+    }
+
+    #[test]
+    fn emit_object_to_memory() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        let object = comp
+            .emit_to_memory(&Target::default(), EmitKind::Object)
+            .unwrap();
+        assert!(!object.is_empty());
+    }
+
+    #[test]
+    fn emit_assembly_for_cpu() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default()
+            .with_target_cpu("generic")
+            .with_reloc_model(RelocModel::Pic);
+        let comp = Compilation::new(tree, opts).unwrap();
+        let asm = comp
+            .emit_to_memory(&Target::default(), EmitKind::Assembly)
+            .unwrap();
+        assert!(String::from_utf8(asm).unwrap().contains("main"));
+    }
+
+    #[test]
+    fn emit_executable_to_memory_fails() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        assert!(comp
+            .emit_to_memory(&Target::default(), EmitKind::Executable)
+            .is_err());
+    }
+
+    #[test]
+    fn hooks_observe_each_phase() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (parse_seen, bind_seen, lower_seen) = (seen.clone(), seen.clone(), seen.clone());
+        let opts = CompilationOptions::default()
+            .with_after_parse(move |_| {
+                parse_seen.borrow_mut().push("parse".to_string());
+                Ok(())
+            })
+            .with_after_bind(move |expr| {
+                bind_seen.borrow_mut().push(format!("bind {:?}", expr.typ));
+                Ok(())
+            })
+            .with_after_lower(move |module| {
+                assert!(module.to_ir_string().contains("@main"));
+                lower_seen.borrow_mut().push("lower".to_string());
+                Ok(())
+            });
+
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, opts).unwrap();
+        comp.emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+
+        assert_eq!(
+            vec!["parse", "bind Builtin(Number)", "lower"],
+            *seen.borrow()
+        );
+    }
+
+    #[test]
+    fn hooks_can_veto_compilation() {
+        let source = SourceText::new("print 1 + 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default().with_after_bind(|_| Err("no thanks".into()));
+        let diagnostics = Compilation::new(tree, opts).err().unwrap();
+        assert_eq!(1, diagnostics.len());
+        assert!(diagnostics[0].message.contains("no thanks"));
+
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default().with_after_lower(|_| Err("nope".into()));
+        let comp = Compilation::new(tree, opts).unwrap();
+        match comp.emit_to_memory(&Target::default(), EmitKind::LlvmIr) {
+            Err(CompError::Aborted(message)) => assert_eq!("nope", message),
+            _ => panic!("expected the lowering hook to abort compilation"),
+        }
+    }
+
+    #[test]
+    fn link_hooks_see_the_link_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("linked");
+        let source = SourceText::new("print 1");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default()
+            .with_sysroot("/sysroot")
+            .with_linker_args(vec!["-lm"])
+            .with_before_link(|command| Err(command.join(" ")));
+        let comp = Compilation::new(tree, opts).unwrap();
+        match comp.emit(&Target::default(), &output) {
+            Err(CompError::Aborted(command)) => {
+                assert!(command.starts_with("clang "));
+                assert!(command.contains(&format!("-o {}", output.display())));
+                assert!(command.ends_with("--sysroot=/sysroot -lm"));
+            }
+            _ => panic!("expected the link hook to abort compilation"),
         }
+        assert!(!output.exists());
     }
 
-    /// Does the compilation have any diagnostics to emit?
-    pub fn has_diagnostics(&self) -> bool {
-        !self.diagnostics.is_empty()
+    #[test]
+    fn freestanding_programs_link_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = SourceText::new("let n = 1");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default()
+            .with_freestanding(true)
+            .with_temps_dir(dir.path())
+            .with_before_link(|command| Err(command.join(" ")));
+        let comp = Compilation::new(tree, opts).unwrap();
+        match comp.emit(&Target::default(), &dir.path().join("linked")) {
+            Err(CompError::Aborted(command)) => {
+                assert!(command.ends_with("-nostdlib"), "{}", command);
+                assert!(!command.contains(runtime::ARCHIVE_NAME), "{}", command);
+            }
+            _ => panic!("expected the link hook to abort compilation"),
+        }
+        assert!(!dir.path().join(runtime::ARCHIVE_NAME).exists());
+    }
+
+    #[test]
+    fn freestanding_programs_cant_use_the_runtime() {
+        let lower = |src: &str| {
+            let source = SourceText::new(src);
+            let tree = syntax::SyntaxTree::parse(&source);
+            let opts = CompilationOptions::default().with_freestanding(true);
+            let comp = Compilation::new(tree, opts).unwrap();
+            match comp.emit_to_memory(&Target::default(), EmitKind::LlvmIr) {
+                Err(CompError::Codegen(errors)) => message_strs(&errors)
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>(),
+                _ => panic!("expected lowering to fail"),
+            }
+        };
+
+        let needs = |what| {
+            vec![format!(
+                "{} the runtime library, which freestanding programs aren't linked with",
+                what
+            )]
+        };
+        assert_eq!(needs("`print` needs"), lower("print 1"));
+        assert_eq!(needs("strings need"), lower("let s = 'hello'"));
+        assert_eq!(
+            needs("strings need"),
+            lower("fn len(s: String): Number\n  0\nend")
+        );
+    }
+
+    #[test]
+    fn freestanding_checks_trap() {
+        let source = SourceText::new("fn div(n: Number, d: Number): Number\n  n / d\nend");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default().with_freestanding(true);
+        let comp = Compilation::new(tree, opts).unwrap();
+        let ir = comp
+            .emit_to_memory(&Target::default(), EmitKind::LlvmIr)
+            .unwrap();
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("call void @llvm.trap()"), "{}", ir);
+        assert!(!ir.contains("ullage_rt"), "{}", ir);
+    }
+
+    #[test]
+    fn save_temps_keeps_intermediates() {
+        let dir = tempfile::tempdir().unwrap();
+        let temps = dir.path().join("temps");
+        let source = SourceText::new("print 1");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default()
+            .with_temps_dir(&temps)
+            .with_before_link(|_| Err("stop".into()));
+        let comp = Compilation::new(tree, opts).unwrap();
+        assert!(comp
+            .emit(&Target::default(), &dir.path().join("prog"))
+            .is_err());
+        let ir = fs::read_to_string(temps.join("prog.ll")).unwrap();
+        assert!(ir.contains("ModuleID = 'prog'"));
+        assert!(fs::metadata(temps.join("prog.o")).unwrap().len() > 0);
+        assert!(temps.join(runtime::ARCHIVE_NAME).exists());
+    }
+
+    #[test]
+    fn missing_linker_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = SourceText::new("print 1");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let opts = CompilationOptions::default().with_linker("/no/such/linker");
+        let comp = Compilation::new(tree, opts).unwrap();
+        match comp.emit(&Target::default(), &dir.path().join("linked")) {
+            Err(CompError::Linker(e)) => {
+                assert!(e.to_string().contains("/no/such/linker"));
+                assert_eq!(None, e.output());
+            }
+            _ => panic!("expected the link to fail"),
+        }
+    }
+
+    #[test]
+    fn compilation_returns_parse_diagnostics() {
+        let source = SourceText::new("print (1 + ");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let diagnostics = Compilation::new(tree, CompilationOptions::default())
+            .err()
+            .unwrap();
+        assert!(!diagnostics.is_empty());
+    }
+
+    #[test]
+    fn compilation_returns_all_bind_diagnostics() {
+        let source = SourceText::new("print foo\nprint bar");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let diagnostics = Compilation::new(tree, CompilationOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics.iter().all(|d| d.span != DUMMY_SPAN));
+    }
+
+    #[test]
+    fn llvm_remarks_and_notes_are_notes() {
+        let diagnostics = || {
+            vec![
+                LlvmDiagnostic {
+                    severity: Severity::Remark,
+                    message: "inlined".into(),
+                },
+                LlvmDiagnostic {
+                    severity: Severity::Note,
+                    message: "see here".into(),
+                },
+            ]
+        };
+        for &level in &[WarningLevel::Allow, WarningLevel::Warn, WarningLevel::Deny] {
+            let notes = convert_llvm_diagnostics(diagnostics(), level, true).unwrap();
+            assert_eq!(
+                vec!["LLVM remark: inlined", "LLVM note: see here"],
+                message_strs(&notes)
+            );
+            assert!(notes.iter().all(|n| n.is_note));
+
+            let notes = convert_llvm_diagnostics(diagnostics(), level, false).unwrap();
+            assert_eq!(vec!["LLVM note: see here"], message_strs(&notes));
+        }
+
+        let warning = LlvmDiagnostic {
+            severity: Severity::Warning,
+            message: "odd".into(),
+        };
+        let warnings =
+            convert_llvm_diagnostics(vec![warning.clone()], WarningLevel::Warn, false).unwrap();
+        assert!(!warnings[0].is_note);
+        assert!(convert_llvm_diagnostics(vec![warning], WarningLevel::Deny, false).is_err());
+    }
+
+    #[test]
+    fn bind_warnings_are_reported_or_denied() {
+        let source = SourceText::new("while true end\nprint 1");
+        let compile = |level| {
+            let opts = CompilationOptions::default()
+                .with_emit_kind(EmitKind::LlvmIr)
+                .with_warning_level(level);
+            Compilation::new(syntax::SyntaxTree::parse(&source), opts)
+        };
+
+        let denied = compile(WarningLevel::Deny).err().unwrap();
+        assert_eq!(vec!["Unreachable expression"], message_strs(&denied));
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("warned.ll");
+        let warnings = compile(WarningLevel::Warn)
+            .unwrap()
+            .emit(&Target::default(), &output)
+            .unwrap();
+        assert_eq!(vec!["Unreachable expression"], message_strs(&warnings));
+
+        let allowed = compile(WarningLevel::Allow)
+            .unwrap()
+            .emit(&Target::default(), &output)
+            .unwrap();
+        assert!(allowed.is_empty());
     }
 
-    /// Borrow a slice of the diagnostics in this compilation
-    pub fn diagnostics(&self) -> &[Diagnostic] {
-        &self.diagnostics
+    fn message_strs(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|d| &d.message[..]).collect()
     }
 }
@@ -0,0 +1,341 @@
+//! Embedding API
+//!
+//! Compiles a program into the JIT so that its functions can be
+//! called from Rust. This allows ullage to be embedded in a host
+//! program as a scripting or extension language:
+//!
+//! ```no_run
+//! # use ullage::compile::{Compilation, CompilationOptions, CompResult};
+//! # use ullage::syntax::{text::SourceText, SyntaxTree};
+//! # fn main() -> CompResult<()> {
+//! let source = SourceText::new("fn double(n: Number): Number\n  n * 2\nend");
+//! let tree = SyntaxTree::parse(&source);
+//! let comp = Compilation::new(tree, CompilationOptions::default())
+//!     .expect("the program should bind");
+//!
+//! let program = comp.jit()?;
+//! let double = program.get_function::<fn(i64) -> i64>("double")?;
+//! assert_eq!(42, double.call(21));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only functions which take and return `Number`s and `Bool`s can be
+//! called. These map to Rust's `i64` and `bool`.
+
+use super::runtime;
+use super::{CompError, CompResult, Compilation};
+use crate::low_loader::prelude::*;
+use crate::sem::{BuiltinType, Expression, ExpressionKind, FnDecl, Typ};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// A Value which can Cross into the JIT
+///
+/// Implemented for the Rust types which have an ullage equivalent.
+/// Values are passed to and from the JIT widened to an `i64`.
+pub trait JitValue: Copy {
+    /// The ullage type this value is passed as
+    const TYPE: BuiltinType;
+
+    /// Widen the value to pass it to the JIT
+    fn into_raw(self) -> i64;
+
+    /// Narrow a value returned from the JIT
+    fn from_raw(raw: i64) -> Self;
+}
+
+impl JitValue for i64 {
+    const TYPE: BuiltinType = BuiltinType::Number;
+
+    fn into_raw(self) -> i64 {
+        self
+    }
+
+    fn from_raw(raw: i64) -> Self {
+        raw
+    }
+}
+
+impl JitValue for bool {
+    const TYPE: BuiltinType = BuiltinType::Bool;
+
+    fn into_raw(self) -> i64 {
+        self as i64
+    }
+
+    fn from_raw(raw: i64) -> Self {
+        raw != 0
+    }
+}
+
+/// A Function Signature which can be Looked Up
+///
+/// Implemented for Rust `fn` pointer types, such as `fn(i64) -> i64`,
+/// whose parameters and return are all `JitValue`s.
+pub trait JitSignature {
+    /// The type of the C shim which wraps the function
+    type Shim: Copy;
+
+    /// The ullage types of the parameters
+    fn params() -> Vec<Typ>;
+
+    /// The ullage type of the return
+    fn ret() -> Typ;
+}
+
+/// A Function Compiled by the JIT
+///
+/// Borrows the `JitProgram` it was looked up in, so it can't outlive
+/// the compiled code. Call it with `call`.
+pub struct JitFunction<'j, F: JitSignature> {
+    shim: F::Shim,
+    _program: PhantomData<&'j JitProgram>,
+}
+
+// Expands to `i64` for each parameter of a shim's signature.
+macro_rules! raw_type {
+    ($param:ident) => {
+        i64
+    };
+}
+
+macro_rules! impl_signature {
+    ($($param:ident),*) => {
+        impl<R: JitValue, $($param: JitValue),*> JitSignature for fn($($param),*) -> R {
+            type Shim = extern "C" fn($(raw_type!($param)),*) -> i64;
+
+            fn params() -> Vec<Typ> {
+                vec![$(Typ::Builtin($param::TYPE)),*]
+            }
+
+            fn ret() -> Typ {
+                Typ::Builtin(R::TYPE)
+            }
+        }
+
+        impl<'j, R: JitValue, $($param: JitValue),*> JitFunction<'j, fn($($param),*) -> R> {
+            /// Call the Function
+            #[allow(non_snake_case)]
+            pub fn call(&self, $($param: $param),*) -> R {
+                R::from_raw((self.shim)($($param.into_raw()),*))
+            }
+        }
+    };
+}
+
+impl_signature!();
+impl_signature!(A);
+impl_signature!(A, B);
+impl_signature!(A, B, C);
+impl_signature!(A, B, C, D);
+
+/// A Program Compiled into the JIT
+///
+/// Owns the JIT the program's functions were compiled into. Functions
+/// are looked up by name with `get_function`.
+pub struct JitProgram {
+    jit: Jit,
+    /// The signature of each callable function, keyed by name
+    functions: HashMap<String, (Vec<Typ>, Typ)>,
+}
+
+impl JitProgram {
+    /// Look Up a Function
+    ///
+    /// Returns the function with the given name, checking that its
+    /// declared signature matches `F`. For example a function declared
+    /// as `fn double(n: Number): Number` is looked up as
+    /// `fn(i64) -> i64`.
+    ///
+    /// # Errors
+    ///
+    /// If there is no function with the given name, or its signature
+    /// doesn't match `F`, then an error describing the problem is
+    /// returned.
+    pub fn get_function<F: JitSignature>(&self, name: &str) -> CompResult<JitFunction<'_, F>> {
+        let (params, ret) = self
+            .functions
+            .get(name)
+            .ok_or_else(|| CompError::from(format!("no callable function '{}'", name)))?;
+        if *params != F::params() || *ret != F::ret() {
+            return Err(CompError::from(format!(
+                "function '{}' has signature {}, not {}",
+                name,
+                signature_name(params, *ret),
+                signature_name(&F::params(), F::ret())
+            )));
+        }
+        // The shim was generated with a C calling convention, taking
+        // and returning each value widened to an `i64`.
+        let shim = unsafe { self.jit.lookup::<F::Shim>(&shim_name(name)) }
+            .ok_or_else(|| CompError::from(format!("missing shim for '{}'", name)))?;
+        Ok(JitFunction {
+            shim,
+            _program: PhantomData,
+        })
+    }
+}
+
+impl Compilation {
+    /// Compile into the JIT
+    ///
+    /// Compiles the program into an in-process JIT so that its
+    /// functions can be called from Rust. As with `run_tests` the
+    /// program's top level statements are not run, and the code isn't
+    /// instrumented with the AddressSanitizer.
+    ///
+    /// # Errors
+    ///
+    /// If the program can't be lowered, or the JIT can't be created
+    /// for the host, then the compilation error is returned.
+    pub fn jit(mut self) -> CompResult<JitProgram> {
+        self.options.sanitizers.address = false;
+        let mut functions = HashMap::new();
+        find_functions(&self.expr, &mut functions);
+
+        let target = Target::default();
+        let machine = self.create_target_machine(&target)?;
+        let mut jit = Jit::new(Context::new()).map_err(CompError::Generic)?;
+        runtime::add_to_jit(&mut jit);
+        let mut module = self.build_module(jit.context(), &machine, "jit")?;
+        for (name, (params, ret)) in functions.iter() {
+            add_shim(jit.context(), &mut module, name, params, *ret);
+        }
+        jit.add_module(module).map_err(CompError::Generic)?;
+
+        Ok(JitProgram { jit, functions })
+    }
+}
+
+/// Find the Callable Functions in an Expression
+///
+/// Walks the same declarations as lowering does, collecting each
+/// function which only takes and returns values with a `JitValue`
+/// equivalent.
+fn find_functions(expr: &Expression, functions: &mut HashMap<String, (Vec<Typ>, Typ)>) {
+    match expr.kind {
+        ExpressionKind::Sequence(ref exprs) => {
+            for expr in exprs.iter() {
+                find_functions(expr, functions);
+            }
+        }
+        ExpressionKind::Function(ref fn_decl) if is_callable(fn_decl) => {
+            let params = fn_decl.params.iter().map(|p| p.ty).collect();
+            functions.insert(fn_decl.ident.clone(), (params, fn_decl.ret_ty));
+        }
+        _ => (),
+    }
+}
+
+/// Can the Function be Called from Rust?
+fn is_callable(fn_decl: &FnDecl) -> bool {
+    let is_value = |typ: &Typ| {
+        matches!(
+            typ,
+            Typ::Builtin(BuiltinType::Number) | Typ::Builtin(BuiltinType::Bool)
+        )
+    };
+    is_value(&fn_decl.ret_ty) && fn_decl.params.iter().all(|p| is_value(&p.ty))
+}
+
+/// Format a Signature for an Error Message
+fn signature_name(params: &[Typ], ret: Typ) -> String {
+    let params = params
+        .iter()
+        .map(|p| p.name().into_owned())
+        .collect::<Vec<_>>();
+    format!("'({}): {}'", params.join(", "), ret.name())
+}
+
+/// Get the Name of the Shim for a Function
+fn shim_name(name: &str) -> String {
+    format!("__ullage_embed_shim_{}", name)
+}
+
+/// Add a Shim to the Module
+///
+/// Ullage functions use their own calling convention, so each one is
+/// wrapped in a C function. The shim takes every parameter as an
+/// `i64`, narrowing `Bool`s, and widens the result to an `i64`.
+fn add_shim(ctx: &mut Context, module: &mut Module, name: &str, params: &[Typ], ret: Typ) {
+    let function = module
+        .find_function(name)
+        .unwrap_or_else(|| panic!("missing function '{}'", name));
+    let int_type = ctx.int_type(64);
+    let mut raw_params = vec![int_type; params.len()];
+    let mut shim = unsafe { ctx.add_function(module, &shim_name(name), int_type, &mut raw_params) };
+    shim.set_calling_convention(CallConvention::CDecl);
+    let entry = ctx.add_block(&mut shim, "entry");
+
+    let mut builder = ctx.add_builder();
+    unsafe { builder.position_at_end(entry) };
+    let mut args = params
+        .iter()
+        .enumerate()
+        .map(|(idx, typ)| {
+            let raw = shim.get_param(idx as u32);
+            match typ {
+                Typ::Builtin(BuiltinType::Bool) => unsafe {
+                    builder.build_icmp(Predicate::Neq, raw, ctx.const_int(0))
+                },
+                _ => raw,
+            }
+        })
+        .collect::<Vec<_>>();
+    let result = unsafe { builder.build_call(&function, &mut args) };
+    let result = match ret {
+        Typ::Builtin(BuiltinType::Bool) => unsafe { builder.build_zext(result, int_type) },
+        _ => result,
+    };
+    unsafe { builder.build_ret(result) };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile::CompilationOptions;
+    use crate::syntax::{text::SourceText, SyntaxTree};
+
+    fn jit(text: &str) -> JitProgram {
+        let source = SourceText::new(text);
+        let tree = SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        comp.jit().unwrap()
+    }
+
+    #[test]
+    fn call_jit_functions() {
+        let program = jit("fn double(n: Number): Number n * 2 end
+             fn pick(c: Bool, a: Number, b: Number): Number a if c else b end
+             fn is_even(n: Number): Bool (n / 2) * 2 == n end
+             fn answer(): Number 42 end");
+
+        let double = program.get_function::<fn(i64) -> i64>("double").unwrap();
+        assert_eq!(42, double.call(21));
+        assert_eq!(-8, double.call(-4));
+        let pick = program
+            .get_function::<fn(bool, i64, i64) -> i64>("pick")
+            .unwrap();
+        assert_eq!(1, pick.call(true, 1, 2));
+        assert_eq!(2, pick.call(false, 1, 2));
+        let is_even = program.get_function::<fn(i64) -> bool>("is_even").unwrap();
+        assert!(is_even.call(10));
+        assert!(!is_even.call(7));
+        let answer = program.get_function::<fn() -> i64>("answer").unwrap();
+        assert_eq!(42, answer.call());
+    }
+
+    #[test]
+    fn signatures_are_checked() {
+        let program = jit("fn double(n: Number): Number n * 2 end
+             fn greet(s: String): String 'hello ' + s end");
+
+        assert!(program.get_function::<fn(i64) -> i64>("missing").is_err());
+        assert!(program.get_function::<fn(bool) -> i64>("double").is_err());
+        assert!(program.get_function::<fn(i64) -> bool>("double").is_err());
+        assert!(program.get_function::<fn() -> i64>("double").is_err());
+        // Functions which take or return strings can't be called
+        assert!(program.get_function::<fn(i64) -> i64>("greet").is_err());
+    }
+}
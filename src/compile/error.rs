@@ -1,6 +1,8 @@
 //! Compilation error module. Contains the Result and Error types for
 //! the compile module.
 
+use crate::diag::Diagnostic;
+use crate::low_loader::prelude::Function;
 use failure::Fail;
 use std::io;
 
@@ -12,6 +14,29 @@ pub enum CompError {
     #[fail(display = "compilation error: {}", _0)]
     Generic(String),
 
+    /// Compilation Aborted by a Hook
+    #[fail(display = "compilation aborted: {}", _0)]
+    Aborted(String),
+
+    /// Code Generation Failure
+    ///
+    /// LLVM reported errors, or warnings which are being treated as
+    /// errors, while generating code, or the program uses something
+    /// the output can't provide.
+    #[fail(display = "code generation failed")]
+    Codegen(Vec<Diagnostic>),
+
+    /// A Function Failed Verification
+    ///
+    /// Lowering produced invalid IR for the named function. This is a
+    /// bug in the compiler rather than in the program being compiled.
+    #[fail(display = "function '{}' failed verification: {}", _0, _1)]
+    InvalidFunction(String, String),
+
+    /// The Module Failed Verification
+    #[fail(display = "module failed verification: {}", _0)]
+    InvalidModule(String),
+
     /// Linker Failure
     #[fail(display = "linker failed: {}", _0)]
     Linker(#[cause] LinkerError),
@@ -29,13 +54,33 @@ pub type CompResult<T> = Result<T, CompError>;
 /// Used to group together the different failure modes for the linker.
 #[derive(Fail, Debug)]
 pub enum LinkerError {
+    /// The linker couldn't be started
+    #[fail(display = "could not run '{}': {}", _0, _1)]
+    CouldNotRun(String, #[cause] io::Error),
+
     /// The linker failed with a known exit status
-    #[fail(display = "linker returned exit status {}: {}", _0, _1)]
-    WithExitStatus(i32, String),
+    #[fail(display = "'{}' returned exit status {}", _0, _1)]
+    WithExitStatus(String, i32, String),
 
     /// The linker failed with an unknown exit status
-    #[fail(display = "unknown linker error: {}", _0)]
-    UnknownFailure(String),
+    #[fail(display = "'{}' was terminated without an exit status", _0)]
+    UnknownFailure(String, String),
+}
+
+impl LinkerError {
+    /// Get the Linker's Output
+    ///
+    /// Returns what the linker wrote to standard error, if it ran.
+    /// This usually explains the failure, e.g. which symbols were
+    /// missing.
+    pub fn output(&self) -> Option<&str> {
+        match self {
+            LinkerError::CouldNotRun(..) => None,
+            LinkerError::WithExitStatus(_, _, stderr) | LinkerError::UnknownFailure(_, stderr) => {
+                Some(stderr)
+            }
+        }
+    }
 }
 
 impl From<String> for CompError {
@@ -55,11 +100,27 @@ impl CompError {
     /// Compilation Linker Error
     ///
     /// When the linker has failed and caused compilation to fail.
-    pub fn link_fail(exit_status: Option<i32>, stderr: Vec<u8>) -> Self {
-        let stderr = String::from_utf8(stderr).unwrap();
+    pub fn link_fail(linker: &str, exit_status: Option<i32>, stderr: &[u8]) -> Self {
+        let linker = linker.to_string();
+        let stderr = String::from_utf8_lossy(stderr).into_owned();
         CompError::Linker(match exit_status {
-            Some(status) => LinkerError::WithExitStatus(status, stderr),
-            None => LinkerError::UnknownFailure(stderr),
+            Some(status) => LinkerError::WithExitStatus(linker, status, stderr),
+            None => LinkerError::UnknownFailure(linker, stderr),
         })
     }
+
+    /// Linker Start Failure
+    ///
+    /// When the linker couldn't be run at all, e.g. because it isn't
+    /// installed.
+    pub fn link_not_run(linker: &str, err: io::Error) -> Self {
+        CompError::Linker(LinkerError::CouldNotRun(linker.to_string(), err))
+    }
+
+    /// Function Verification Error
+    ///
+    /// When the function produced by lowering isn't valid IR.
+    pub fn invalid_function(function: &Function, message: String) -> Self {
+        CompError::InvalidFunction(function.name(), message)
+    }
 }
@@ -0,0 +1,123 @@
+//! Compilation Hooks
+//!
+//! Callbacks which can be registered to observe each phase of a
+//! compilation. Each hook is given the output of the phase which has
+//! just completed and can veto the rest of the compilation by
+//! returning an error.
+
+use crate::low_loader::prelude::Module;
+use crate::sem;
+use crate::syntax::SyntaxTree;
+
+/// Hook Result
+///
+/// Returned from each hook. An `Err` aborts the compilation with the
+/// given message.
+pub type HookResult = Result<(), String>;
+
+/// Hook called with the parsed syntax tree
+type ParseHook = Box<dyn Fn(&SyntaxTree<'_>) -> HookResult>;
+
+/// Hook called with the bound semantic tree
+type BindHook = Box<dyn Fn(&sem::Expression) -> HookResult>;
+
+/// Hook called with the lowered module
+type LowerHook = Box<dyn Fn(&Module) -> HookResult>;
+
+/// Hook called with the linker command line
+type LinkHook = Box<dyn Fn(&[String]) -> HookResult>;
+
+/// Compilation Hooks
+///
+/// The set of callbacks registered for a compilation. Hooks for a
+/// given phase are run in the order they were registered, and only
+/// once the phase has completed without any diagnostics.
+#[derive(Default)]
+pub struct Hooks {
+    /// Callbacks run once the source has been parsed
+    after_parse: Vec<ParseHook>,
+    /// Callbacks run once the tree has been bound
+    after_bind: Vec<BindHook>,
+    /// Callbacks run once the module has been lowered and optimised
+    after_lower: Vec<LowerHook>,
+    /// Callbacks run just before the linker is invoked
+    before_link: Vec<LinkHook>,
+}
+
+impl Hooks {
+    /// Register a Parse Hook
+    pub fn add_after_parse<F>(&mut self, hook: F)
+    where
+        F: Fn(&SyntaxTree<'_>) -> HookResult + 'static,
+    {
+        self.after_parse.push(Box::new(hook));
+    }
+
+    /// Register a Bind Hook
+    pub fn add_after_bind<F>(&mut self, hook: F)
+    where
+        F: Fn(&sem::Expression) -> HookResult + 'static,
+    {
+        self.after_bind.push(Box::new(hook));
+    }
+
+    /// Register a Lowering Hook
+    pub fn add_after_lower<F>(&mut self, hook: F)
+    where
+        F: Fn(&Module) -> HookResult + 'static,
+    {
+        self.after_lower.push(Box::new(hook));
+    }
+
+    /// Register a Link Hook
+    ///
+    /// Link hooks are given the full linker command line, starting
+    /// with the linker itself. They're only run when emitting an
+    /// executable.
+    pub fn add_before_link<F>(&mut self, hook: F)
+    where
+        F: Fn(&[String]) -> HookResult + 'static,
+    {
+        self.before_link.push(Box::new(hook));
+    }
+
+    /// Split Off the Link Hooks
+    ///
+    /// Moves the link hooks into a new set of hooks. Linking happens
+    /// after the rest of the compilation has been consumed to build
+    /// the module, so these are kept aside until then.
+    pub(crate) fn split_before_link(&mut self) -> Hooks {
+        Hooks {
+            before_link: std::mem::take(&mut self.before_link),
+            ..Hooks::default()
+        }
+    }
+
+    /// Run the Parse Hooks
+    ///
+    /// Stops at the first hook to veto the compilation.
+    pub fn run_after_parse(&self, tree: &SyntaxTree<'_>) -> HookResult {
+        self.after_parse.iter().try_for_each(|hook| hook(tree))
+    }
+
+    /// Run the Bind Hooks
+    ///
+    /// Stops at the first hook to veto the compilation.
+    pub fn run_after_bind(&self, expr: &sem::Expression) -> HookResult {
+        self.after_bind.iter().try_for_each(|hook| hook(expr))
+    }
+
+    /// Run the Lowering Hooks
+    ///
+    /// Stops at the first hook to veto the compilation.
+    pub fn run_after_lower(&self, module: &Module) -> HookResult {
+        self.after_lower.iter().try_for_each(|hook| hook(module))
+    }
+
+    /// Run the Link Hooks
+    ///
+    /// Stops at the first hook to veto the compilation.
+    pub fn run_before_link(&self, command: &[String]) -> HookResult {
+        self.before_link.iter().try_for_each(|hook| hook(command))
+    }
+}
@@ -3,18 +3,20 @@
 //! This module is responsible for taking Expressions and lowering
 //! them to LLVM.
 
+use crate::diag::Diagnostic;
 use crate::low_loader::prelude::*;
-use crate::sem::{BuiltinType, Expression, ExpressionKind, Typ};
+use crate::sem::visit::Visitor;
+use crate::sem::{BuiltinType, Expression, ExpressionKind, FnDecl, Typ};
+use crate::syntax::text::{Span, DUMMY_SPAN};
 use crate::syntax::Constant;
 use crate::syntax::{InfixOp, PrefixOp};
 
 use super::error::*;
-use super::lower_context::LowerContext;
+use super::lower_context::{DropScope, Locals, LowerContext};
+use super::runtime::RuntimeFn;
 use super::string_builtins::*;
 
-use std::collections::HashMap;
-
-type Local = (bool, LLVMValueRef);
+use std::collections::HashSet;
 
 impl From<InfixOp> for Predicate {
     fn from(op: InfixOp) -> Self {
@@ -35,20 +37,41 @@ impl From<InfixOp> for Predicate {
 /// Takes a given tree of expressions and adds a new `main` function
 /// to the LLVM Context. When called `main` will compute the value of
 /// the expression and return `0`.
-pub fn lower_as_main(ctx: &mut LowerContext<'_>, expr: Expression) -> CompResult<Function> {
+///
+/// If an `entry` function is given then `main` calls it instead, and
+/// returns its result. The binder ensures the tree then only declares
+/// functions, so lowering it runs no code.
+pub fn lower_as_main(
+    ctx: &mut LowerContext<'_>,
+    expr: Expression,
+    entry: Option<&str>,
+) -> CompResult<Function> {
     let int_type = ctx.llvm_ctx.int_type(64);
-    let mut fun = ctx
-        .llvm_ctx
-        .add_function(ctx.module, "main", int_type, &mut []);
+    let mut fun = unsafe {
+        ctx.llvm_ctx
+            .add_function(ctx.module, "main", int_type, &mut [])
+    };
     fun.set_calling_convention(CallConvention::CDecl);
     let bb = ctx.llvm_ctx.add_block(&mut fun, "entry");
 
     let mut builder = ctx.llvm_ctx.add_builder();
-    builder.position_at_end(bb);
+    unsafe { builder.position_at_end(bb) };
+    ctx.enter_function(&mut fun, "main", DUMMY_SPAN, &mut builder);
 
     lower_expression(ctx, &mut fun, &mut builder, expr)?;
 
-    builder.build_ret(ctx.llvm_ctx.const_int(0));
+    let status = match entry {
+        Some(entry) => {
+            let entry = ctx
+                .module
+                .find_function(entry)
+                .ok_or_else(|| CompError::from(format!("missing entry point '{}'", entry)))?;
+            unsafe { builder.build_call(&entry, &mut []) }
+        }
+        None => ctx.llvm_ctx.const_int(0),
+    };
+    unsafe { builder.build_ret(status) };
+    ctx.leave_function();
 
     Ok(fun)
 }
@@ -75,11 +98,18 @@ pub fn lower_expression(
     builder: &mut Builder,
     expr: Expression,
 ) -> CompResult<()> {
-    let mut vars = HashMap::new();
+    let mut vars = Locals::new();
 
     add_decls(ctx, &expr);
 
-    lower_internal(ctx, fun, builder, &mut vars, expr)?;
+    ctx.push_drop_scope();
+    let typ = expr.typ;
+    let val = lower_internal(ctx, fun, builder, &mut vars, expr)?;
+    if typ.is_reference_counted() {
+        build_release(ctx, builder, val, typ);
+    }
+    let locals = ctx.pop_drop_scope();
+    build_drops(ctx, builder, &vars, locals);
     Ok(())
 }
 
@@ -96,21 +126,32 @@ fn add_decls(ctx: &mut LowerContext<'_>, expr: &Expression) {
             }
         }
         ExpressionKind::Function(ref fn_decl) => {
-            let ret = ctx
-                .llvm_type(fn_decl.ret_ty)
-                .expect("no type in context for function return");
+            let ret = if fn_decl.ret_ty == Typ::Unit {
+                ctx.llvm_ctx.void_type()
+            } else {
+                ctx.llvm_type(fn_decl.ret_ty)
+                    .expect("no type in context for function return")
+            };
             let mut params = fn_decl
                 .params
                 .iter()
                 .map(|p| {
-                    ctx.llvm_type(p.ty.unwrap_or(Typ::Builtin(BuiltinType::Number)))
+                    ctx.llvm_type(p.ty)
                         .expect("no type in context for function param")
                 })
                 .collect::<Vec<_>>();
-            let mut fun =
+            let mut fun = unsafe {
                 ctx.llvm_ctx
-                    .add_function(ctx.module, &fn_decl.ident, ret, &mut params[..]);
-            fun.set_calling_convention(CallConvention::Fastcall);
+                    .add_function(ctx.module, &fn_decl.ident, ret, &mut params[..])
+            };
+            // Exported functions keep the C calling convention so
+            // they can be called from outside the program.
+            if !fn_decl.attrs.export {
+                fun.set_calling_convention(CallConvention::Fastcall);
+            }
+            if fn_decl.attrs.inline {
+                fun.add_attribute(Attribute::InlineHint);
+            }
         }
         _ => (),
     }
@@ -118,79 +159,132 @@ fn add_decls(ctx: &mut LowerContext<'_>, expr: &Expression) {
 
 /// Internal Lowering of `Expression`s
 ///
-/// Converts an `Expression` to LLVM IR
+/// Converts an `Expression` to LLVM IR. Reference counted values are
+/// returned owned, that is with a reference the caller is responsible
+/// for releasing or handing on.
 pub fn lower_internal(
     ctx: &mut LowerContext<'_>,
     fun: &mut Function,
     builder: &mut Builder,
-    vars: &mut HashMap<String, Local>,
+    vars: &mut Locals,
     expr: Expression,
 ) -> CompResult<LLVMValueRef> {
+    ctx.push_location(builder, expr.span);
+    let val = lower_located(ctx, fun, builder, vars, expr);
+    ctx.pop_location(builder);
+    val
+}
+
+/// Lower an `Expression` at its Location
+///
+/// The builder has already been pointed at the expression's location
+/// by `lower_internal`.
+fn lower_located(
+    ctx: &mut LowerContext<'_>,
+    fun: &mut Function,
+    builder: &mut Builder,
+    vars: &mut Locals,
+    expr: Expression,
+) -> CompResult<LLVMValueRef> {
+    check_freestanding(ctx, &expr)?;
     match expr.kind {
         ExpressionKind::Error => Err(CompError::from(
             "ICE: Can't lower `Expression::Error`".to_string(),
         )),
-        ExpressionKind::Identifier(id) => match vars.get(&id) {
-            Some(&(is_mut, val)) => Ok(if is_mut { builder.build_load(val) } else { val }),
+        ExpressionKind::Identifier(id) => match vars.lookup(&id) {
+            Some(slot) => {
+                let val = vars.get(slot).value;
+                if expr.typ.is_reference_counted() {
+                    build_retain(ctx, builder, val, expr.typ);
+                }
+                Ok(val)
+            }
+            None => Err(CompError::from(format!("Reference to undefined '{}'", id))),
+        },
+        ExpressionKind::Move(id) => match vars.lookup(&id) {
+            // Without reference counting a move is just a read.
+            Some(slot) if !ctx.reference_counting => Ok(vars.get(slot).value),
+            // Mutable variables are cleared, as they may be assigned
+            // again, so releasing them at the end of the scope does
+            // nothing.
+            Some(slot) if vars.get(slot).is_mut => {
+                let val = vars.get(slot).value;
+                let typ = ctx
+                    .llvm_type(expr.typ)
+                    .expect("no type in context for moved variable");
+                vars.set(slot, unsafe { ctx.llvm_ctx.const_null(typ) });
+                Ok(val)
+            }
+            Some(slot) => {
+                ctx.remove_drop(slot);
+                Ok(vars.get(slot).value)
+            }
             None => Err(CompError::from(format!("Reference to undefined '{}'", id))),
         },
         ExpressionKind::Literal(constant) => match constant {
             Constant::Number(n) => Ok(ctx.llvm_ctx.const_int(n)),
             Constant::Bool(b) => Ok(ctx.llvm_ctx.const_bool(b)),
-            Constant::String(s) => {
-                let initialiser = ctx.llvm_ctx.const_struct(vec![
-                    ctx.llvm_ctx.const_int_width(s.bytes().len() as i64, 32),
-                    ctx.llvm_ctx.const_str(&s),
-                ]);
-                let global = ctx.module.add_global(initialiser, "s_const");
-
-                let string_ty = ctx
-                    .llvm_type(expr.typ.unwrap())
-                    .expect("no type in context for string literal");
-                Ok(builder.build_bitcast(global, string_ty, "string_const"))
-            }
+            Constant::String(s) => Ok(build_string_const(ctx, builder, &s)),
         },
         ExpressionKind::Prefix(op, inner) => {
             let val = lower_internal(ctx, fun, builder, vars, *inner)?;
             Ok(match op {
                 PrefixOp::Identity => val,
-                PrefixOp::Negate => builder.build_neg(val),
-                PrefixOp::Not => builder.build_not(val),
+                PrefixOp::Negate => unsafe { builder.build_neg(val) },
+                PrefixOp::Not => unsafe { builder.build_not(val) },
             })
         }
+        ExpressionKind::Infix(lhs, InfixOp::Add, rhs)
+            if expr.typ == Typ::Builtin(BuiltinType::String) =>
+        {
+            let mut parts = Vec::new();
+            collect_concat(*lhs, &mut parts);
+            collect_concat(*rhs, &mut parts);
+            let parts = parts
+                .into_iter()
+                .map(|part| lower_internal(ctx, fun, builder, vars, part))
+                .collect::<CompResult<Vec<_>>>()?;
+            Ok(build_concat(ctx, builder, &parts))
+        }
         ExpressionKind::Infix(lhs, op, rhs) => {
             let lhs_val = lower_internal(ctx, fun, builder, vars, *lhs)?;
             let rhs_val = lower_internal(ctx, fun, builder, vars, *rhs)?;
             let val = match op {
-                InfixOp::Add => match expr.typ.unwrap() {
-                    Typ::Builtin(BuiltinType::Number) => builder.build_add(lhs_val, rhs_val),
-                    Typ::Builtin(BuiltinType::String) => {
-                        build_string_concat(ctx, builder, lhs_val, rhs_val)
+                InfixOp::Add => match expr.typ {
+                    Typ::Builtin(BuiltinType::Number) => {
+                        build_arith(ctx, fun, builder, op, lhs_val, rhs_val)
                     }
                     _ => Err(CompError::from(
                         "invalid operand types for `Add`".to_string(),
                     ))?,
                 },
-                InfixOp::Sub => builder.build_sub(lhs_val, rhs_val),
-                InfixOp::Mul => builder.build_mul(lhs_val, rhs_val),
-                InfixOp::Div => builder.build_sdiv(lhs_val, rhs_val),
+                InfixOp::Sub | InfixOp::Mul => build_arith(ctx, fun, builder, op, lhs_val, rhs_val),
+                InfixOp::Div => build_div(ctx, fun, builder, lhs_val, rhs_val, expr.span),
 
                 InfixOp::Eq
                 | InfixOp::NotEq
                 | InfixOp::Lt
                 | InfixOp::Gt
                 | InfixOp::LtEq
-                | InfixOp::GtEq => builder.build_icmp(Predicate::from(op), lhs_val, rhs_val),
+                | InfixOp::GtEq => unsafe {
+                    builder.build_icmp(Predicate::from(op), lhs_val, rhs_val)
+                },
 
                 InfixOp::Assign => unreachable!(),
             };
             Ok(val)
         }
         ExpressionKind::Assignment(id, expression) => {
+            let typ = expression.typ;
             let val = lower_internal(ctx, fun, builder, vars, *expression)?;
-            match vars.get(&id) {
-                Some(&(true, var)) => {
-                    builder.build_store(val, var);
+            match vars.lookup(&id) {
+                Some(slot) if vars.get(slot).is_mut => {
+                    let old = vars.get(slot).value;
+                    vars.set(slot, val);
+                    if typ.is_reference_counted() {
+                        build_release(ctx, builder, old, typ);
+                        build_retain(ctx, builder, val, typ);
+                    }
                     Ok(val)
                 }
                 _ => Err(CompError::from(format!("Can't assign to '{}'", id))),
@@ -198,49 +292,92 @@ pub fn lower_internal(
         }
         ExpressionKind::Call(callee, args) => {
             if let ExpressionKind::Identifier(name) = callee.kind {
+                let args = args
+                    .into_iter()
+                    .map(|arg| {
+                        let typ = arg.typ;
+                        Ok((lower_internal(ctx, fun, builder, vars, arg)?, typ))
+                    })
+                    .collect::<CompResult<Vec<_>>>()?;
                 match ctx.module.find_function(&name) {
                     Some(function) => {
-                        let mut args = args
-                            .into_iter()
-                            .map(|arg| lower_internal(ctx, fun, builder, vars, arg))
-                            .collect::<CompResult<Vec<_>>>()?;
-                        let call_res = builder.build_call(&function, &mut args);
-                        Ok(call_res)
+                        let mut args: Vec<_> = args.into_iter().map(|(arg, _)| arg).collect();
+                        // Functions returning `()` are `void`, so the
+                        // call has no value of its own.
+                        if expr.typ == Typ::Unit {
+                            unsafe { builder.build_void_call(&function, &mut args) };
+                            Ok(ctx.llvm_ctx.const_int(0))
+                        } else {
+                            Ok(unsafe { builder.build_call(&function, &mut args) })
+                        }
                     }
-                    None => Err(CompError::from(format!("Can't find function '{}'", name))),
+                    None => build_builtin_call(ctx, fun, builder, &name, &args),
                 }
             } else {
                 unimplemented!()
             }
         }
-        ExpressionKind::Index(_expr, _index) => unimplemented!(),
+        ExpressionKind::Index(indexee, index) => {
+            let typ = indexee.typ;
+            let s = lower_internal(ctx, fun, builder, vars, *indexee)?;
+            let index = lower_internal(ctx, fun, builder, vars, *index)?;
+            let char_at = RuntimeFn::StringCharAt.declare(ctx);
+            let c = unsafe { builder.build_call(&char_at, &mut [s, index]) };
+            build_release(ctx, builder, s, typ);
+            Ok(c)
+        }
         ExpressionKind::IfThenElse(iff, then, els) => {
             let cond = lower_internal(ctx, fun, builder, vars, *iff)?;
 
-            let typ = expr
-                .typ
-                .and_then(|t| ctx.llvm_type(t))
+            let typ = ctx
+                .llvm_type(expr.typ)
                 .ok_or_else(|| CompError::from("No type for if expression".to_string()))?;
-            let ret = builder.build_alloca(typ, "if");
-
             let thenblock = ctx.llvm_ctx.add_block(fun, "thenblock");
             let elsblock = ctx.llvm_ctx.add_block(fun, "elseblock");
             let joinblock = ctx.llvm_ctx.add_block(fun, "joinblock");
 
-            builder.build_cond_br(cond, thenblock, elsblock);
+            unsafe { builder.build_cond_br(cond, thenblock, elsblock) };
 
-            builder.position_at_end(thenblock);
-            let then = lower_internal(ctx, fun, builder, vars, *then)?;
-            builder.build_store(then, ret);
-            builder.build_br(joinblock);
+            // Each branch may leave the builder in a different block
+            // to the one it started in, so take the incoming block
+            // for the phi after lowering the branch. Both branches
+            // start with the locals as they were before the `if`.
+            let before = vars.values();
+            // A branch which never completes, such as a `break`, may
+            // have a different type. Its value never reaches the phi.
+            unsafe { builder.position_at_end(thenblock) };
+            let then_typ = then.typ;
+            let mut then = lower_scoped(ctx, fun, builder, vars, *then)?;
+            if then_typ != expr.typ {
+                then = unsafe { ctx.llvm_ctx.const_undef(typ) };
+            }
+            let then_end = builder.insert_block();
+            let then_values = vars.values();
+            unsafe { builder.build_br(joinblock) };
+            vars.reset(&before);
 
-            builder.position_at_end(elsblock);
-            let els = lower_internal(ctx, fun, builder, vars, *els)?;
-            builder.build_store(els, ret);
-            builder.build_br(joinblock);
+            unsafe { builder.position_at_end(elsblock) };
+            let els_typ = els.typ;
+            let mut els = lower_scoped(ctx, fun, builder, vars, *els)?;
+            if els_typ != expr.typ {
+                els = unsafe { ctx.llvm_ctx.const_undef(typ) };
+            }
+            let els_end = builder.insert_block();
+            let els_values = vars.values();
+            unsafe { builder.build_br(joinblock) };
 
-            builder.position_at_end(joinblock);
-            Ok(builder.build_load(ret))
+            unsafe { builder.position_at_end(joinblock) };
+            let mut phi = unsafe { builder.build_phi(typ) };
+            unsafe {
+                phi.add_incoming(then, then_end);
+                phi.add_incoming(els, els_end);
+            }
+            vars.merge(
+                ctx,
+                builder,
+                &[(then_end, then_values), (els_end, els_values)],
+            );
+            Ok(phi.as_raw())
         }
         ExpressionKind::Function(fn_decl) => {
             let mut fun = ctx
@@ -249,25 +386,42 @@ pub fn lower_internal(
                 .unwrap_or_else(|| panic!("missing function declaration '{}'", fn_decl.ident));
             let bb = ctx.llvm_ctx.add_block(&mut fun, "body");
             let mut builder = ctx.llvm_ctx.add_builder();
-            builder.position_at_end(bb);
+            unsafe { builder.position_at_end(bb) };
+            ctx.enter_function(&mut fun, &fn_decl.ident, expr.span, &mut builder);
 
-            let mut vars = fn_decl
-                .params
-                .into_iter()
-                .enumerate()
-                .map(|(i, p)| {
-                    let typ = ctx
-                        .llvm_type(p.ty.unwrap_or(Typ::Builtin(BuiltinType::Number)))
-                        .expect("no type in context for function parameter");
-                    let param = builder.build_alloca(typ, &p.ident);
-                    builder.build_store(fun.get_param(i as u32), param);
-                    (p.ident, (true, param))
-                })
-                .collect::<HashMap<String, Local>>();
+            // Parameters are passed owned, so the function releases
+            // them along with its own locals.
+            ctx.push_drop_scope();
+            let mut vars = Locals::new();
+            for (i, p) in fn_decl.params.into_iter().enumerate() {
+                let counted = p.ty.is_reference_counted();
+                let slot = vars.declare(p.ident, true, fun.get_param(i as u32), p.ty);
+                if counted {
+                    ctx.add_drop(slot);
+                }
+            }
 
+            let body_typ = fn_decl.body.typ;
             let body = lower_internal(ctx, &mut fun, &mut builder, &mut vars, *fn_decl.body)?;
-            builder.build_ret(body);
-            fun.verify_or_panic();
+            // Functions returning `()` discard the value of their body.
+            let returns_unit = fn_decl.ret_ty == Typ::Unit;
+            if returns_unit && body_typ.is_reference_counted() {
+                build_release(ctx, &mut builder, body, body_typ);
+            }
+            let locals = ctx.pop_drop_scope();
+            build_drops(ctx, &mut builder, &vars, locals);
+            if returns_unit {
+                builder.build_ret_void();
+            } else {
+                unsafe { builder.build_ret(body) };
+            }
+            ctx.leave_function();
+            // Debug info can't be verified until it is finalised, so
+            // with it the function is only checked with the module.
+            if ctx.debug_info.is_none() {
+                fun.verify()
+                    .map_err(|e| CompError::invalid_function(&fun, e))?;
+            }
             Ok(unsafe { fun.as_raw() })
         }
         ExpressionKind::Loop(cond, body) => {
@@ -275,69 +429,342 @@ pub fn lower_internal(
             let bodyblock = ctx.llvm_ctx.add_block(fun, "whilebody");
             let joinblock = ctx.llvm_ctx.add_block(fun, "joinblock");
 
-            builder.build_br(condblock);
-            builder.position_at_end(condblock);
+            // Each variable assigned in the loop is given a phi at the
+            // head of the loop, merging its value from before the
+            // loop with its value at the end of each iteration.
+            let assigned = assigned_locals(vars, &[&cond, &body]);
+            let entry = (builder.insert_block(), vars.values());
+            unsafe {
+                builder.build_br(condblock);
+                builder.position_at_end(condblock);
+            }
+            let mut header = Vec::new();
+            for slot in assigned {
+                let local = vars.get(slot);
+                let typ = ctx
+                    .llvm_type(local.typ)
+                    .expect("no type in context for local");
+                let phi = unsafe { builder.build_named_phi(typ, &local.name) };
+                vars.set(slot, phi.as_raw());
+                header.push((slot, phi));
+            }
 
-            let cond = lower_internal(ctx, fun, builder, vars, *cond)?;
-            builder.build_cond_br(cond, bodyblock, joinblock);
+            let cond = lower_scoped(ctx, fun, builder, vars, *cond)?;
+            let exit = (builder.insert_block(), vars.values());
+            unsafe { builder.build_cond_br(cond, bodyblock, joinblock) };
 
-            builder.position_at_end(bodyblock);
-            lower_internal(ctx, fun, builder, vars, *body)?;
-            builder.build_br(condblock);
+            unsafe { builder.position_at_end(bodyblock) };
+            let body_typ = body.typ;
+            ctx.push_loop(condblock, joinblock);
+            let body = lower_scoped(ctx, fun, builder, vars, *body)?;
+            let (mut continues, mut breaks) = ctx.pop_loop();
+            if body_typ.is_reference_counted() {
+                build_release(ctx, builder, body, body_typ);
+            }
+            continues.push((builder.insert_block(), vars.values()));
+            unsafe { builder.build_br(condblock) };
+
+            continues.push(entry);
+            for (slot, phi) in header.iter_mut() {
+                for (block, values) in continues.iter() {
+                    unsafe { phi.add_incoming(values[*slot], *block) };
+                }
+            }
 
-            builder.position_at_end(joinblock);
+            unsafe { builder.position_at_end(joinblock) };
+            breaks.insert(0, exit);
+            vars.merge(ctx, builder, &breaks);
 
             Ok(cond)
         }
+        ExpressionKind::LoopControl(control, depth) => {
+            for locals in ctx.loop_drops(depth) {
+                build_drops(ctx, builder, vars, locals);
+            }
+            let edge = (builder.insert_block(), vars.values());
+            let target = ctx.add_loop_edge(control, depth, edge);
+            unsafe { builder.build_br(target) };
+            // Anything after the branch can never run, but is still
+            // lowered into a block of its own.
+            let dead = ctx.llvm_ctx.add_block(fun, "afterjump");
+            unsafe { builder.position_at_end(dead) };
+            Ok(ctx.llvm_ctx.const_int(0))
+        }
         ExpressionKind::Sequence(seq) => {
             let mut last = None;
-            for e in seq.into_iter() {
-                last = Some(lower_internal(ctx, fun, builder, vars, e)?);
+            let mut seq = seq.into_iter().peekable();
+            while let Some(e) = seq.next() {
+                let typ = e.typ;
+                let val = lower_internal(ctx, fun, builder, vars, e)?;
+                // Only the final value is kept.
+                if seq.peek().is_some() && typ.is_reference_counted() {
+                    build_release(ctx, builder, val, typ);
+                }
+                last = Some(val);
             }
             // FIXME: What should an empty expression yeild?
             Ok(last.unwrap_or_else(|| ctx.llvm_ctx.const_int(0)))
         }
         ExpressionKind::Print(inner) => {
-            let val = lower_internal(ctx, fun, builder, vars, *inner)?;
-
-            // Get the format string and formatted value to print. We
-            // do this so that some values, such as `bool`s can be
-            // converted before printing.
-            //
-            // There are a few TODOs with this though:
-            // TODO: Once Strings become available we should switch to
-            //       `to_string` here
-            // TODO: Stop falling back to the LLVM type here.
-            let (to_format, format) = expr
-                .typ
-                .and_then(|t| fmt_from_type(t, ctx, fun, builder, val))
-                .unwrap_or_else(|| fmt_from_llvm(ctx, fun, builder, val));
-            fmt(ctx, builder, to_format, format);
+            // The binder converts the printed value to a `String`.
+            // The print expression itself evaluates to the value from
+            // before the conversion though.
+            let (val, printed) = match inner.kind {
+                ExpressionKind::Convert(value) => {
+                    let from = value.typ;
+                    let val = lower_internal(ctx, fun, builder, vars, *value)?;
+                    let printed = build_conversion(ctx, fun, builder, val, from, inner.typ)?;
+                    (val, Some(printed).filter(|_| !from.is_reference_counted()))
+                }
+                _ => {
+                    let val = lower_internal(ctx, fun, builder, vars, *inner)?;
+                    (val, None)
+                }
+            };
+            // Only a temporary string made for printing is released,
+            // the printed value itself is the result.
+            build_print(ctx, builder, printed.unwrap_or(val));
+            if let Some(temporary) = printed {
+                build_release(ctx, builder, temporary, Typ::Builtin(BuiltinType::String));
+            }
             Ok(val)
         }
+        ExpressionKind::Convert(inner) => {
+            let from = inner.typ;
+            let val = lower_internal(ctx, fun, builder, vars, *inner)?;
+            build_conversion(ctx, fun, builder, val, from, expr.typ)
+        }
         ExpressionKind::Declaration(decl, is_mut, initialiser) => {
-            let initialiser = lower_internal(ctx, fun, builder, vars, *initialiser)?;
-            let value = if is_mut {
-                let typ = decl.ty.map_or_else(
-                    || ctx.llvm_ctx.get_type(initialiser),
-                    |ty| {
-                        ctx.llvm_type(ty)
-                            .expect("no type in context for declaration")
-                    },
-                );
-
-                let stackloc = builder.build_alloca(typ, &decl.ident);
-                builder.build_store(initialiser, stackloc);
-                stackloc
-            } else {
-                initialiser
+            let initialiser = match initialiser {
+                Some(init) => Some(lower_internal(ctx, fun, builder, vars, *init)?),
+                None => None,
             };
-            vars.insert(decl.ident, (is_mut, value));
-            Ok(initialiser)
+            let counted = decl.ty.is_reference_counted();
+            let value = match initialiser {
+                Some(initialiser) => initialiser,
+                // The binder only allows mutable variables to be
+                // declared without an initialiser.
+                None if !is_mut => {
+                    return Err(CompError::from(format!(
+                        "ICE: '{}' declared without a value",
+                        decl.ident
+                    )))
+                }
+                None => {
+                    let typ = ctx
+                        .llvm_type(decl.ty)
+                        .expect("no type in context for declaration");
+                    // Uninitialised values start out null so they
+                    // can be released safely. Others are always
+                    // assigned before they are read.
+                    if counted {
+                        unsafe { ctx.llvm_ctx.const_null(typ) }
+                    } else {
+                        unsafe { ctx.llvm_ctx.const_undef(typ) }
+                    }
+                }
+            };
+            let slot = vars.declare(decl.ident, is_mut, value, decl.ty);
+            if counted {
+                ctx.add_drop(slot);
+                // The variable keeps the initialiser's reference, so
+                // the declaration's own value needs another.
+                if let Some(initialiser) = initialiser {
+                    build_retain(ctx, builder, initialiser, decl.ty);
+                }
+            }
+            Ok(initialiser.unwrap_or_else(|| ctx.llvm_ctx.const_int(0)))
         }
     }
 }
 
+/// Check an `Expression` Can be Lowered Freestanding
+///
+/// Freestanding programs aren't linked with the runtime library, so
+/// can't `print`, or use strings as the runtime allocates them.
+fn check_freestanding(ctx: &LowerContext<'_>, expr: &Expression) -> CompResult<()> {
+    if !ctx.freestanding {
+        return Ok(());
+    }
+    let uses_strings = match &expr.kind {
+        ExpressionKind::Function(fn_decl) => {
+            fn_decl.ret_ty.is_reference_counted()
+                || fn_decl.params.iter().any(|p| p.ty.is_reference_counted())
+        }
+        _ => expr.typ.is_reference_counted(),
+    };
+    let used = match expr.kind {
+        ExpressionKind::Print(_) => "`print` needs",
+        _ if uses_strings => "strings need",
+        _ => return Ok(()),
+    };
+    Err(CompError::Codegen(vec![Diagnostic::new(
+        format!(
+            "{} the runtime library, which freestanding programs aren't linked with",
+            used
+        ),
+        expr.span,
+    )]))
+}
+
+/// Lower an Expression in a New Drop Scope
+///
+/// Any reference counted locals declared by the expression are
+/// released once it has been lowered.
+fn lower_scoped(
+    ctx: &mut LowerContext<'_>,
+    fun: &mut Function,
+    builder: &mut Builder,
+    vars: &mut Locals,
+    expr: Expression,
+) -> CompResult<LLVMValueRef> {
+    ctx.push_drop_scope();
+    let scope = vars.enter();
+    let val = lower_internal(ctx, fun, builder, vars, expr)?;
+    let locals = ctx.pop_drop_scope();
+    build_drops(ctx, builder, vars, locals);
+    vars.leave(scope);
+    Ok(val)
+}
+
+/// Retain a Reference Counted Value
+///
+/// Does nothing unless the context has reference counting enabled,
+/// as do `build_release` and `build_drops`.
+fn build_retain(ctx: &mut LowerContext<'_>, builder: &mut Builder, val: LLVMValueRef, typ: Typ) {
+    if !ctx.reference_counting {
+        return;
+    }
+    let retain = match typ {
+        Typ::Builtin(BuiltinType::StringBuilder) => RuntimeFn::BuilderRetain,
+        _ => RuntimeFn::StringRetain,
+    }
+    .declare(ctx);
+    unsafe { builder.build_void_call(&retain, &mut [val]) };
+}
+
+/// Release a Reference Counted Value
+fn build_release(ctx: &mut LowerContext<'_>, builder: &mut Builder, val: LLVMValueRef, typ: Typ) {
+    if !ctx.reference_counting {
+        return;
+    }
+    let release = match typ {
+        Typ::Builtin(BuiltinType::StringBuilder) => RuntimeFn::BuilderRelease,
+        _ => RuntimeFn::StringRelease,
+    }
+    .declare(ctx);
+    unsafe { builder.build_void_call(&release, &mut [val]) };
+}
+
+/// Release the Locals from a Drop Scope
+///
+/// Locals are released in the reverse of the order they were
+/// declared in.
+fn build_drops(
+    ctx: &mut LowerContext<'_>,
+    builder: &mut Builder,
+    vars: &Locals,
+    locals: DropScope,
+) {
+    if !ctx.reference_counting {
+        return;
+    }
+    for slot in locals.into_iter().rev() {
+        let local = vars.get(slot);
+        build_release(ctx, builder, local.value, local.typ);
+    }
+}
+
+/// Find the Locals Assigned in Some Expressions
+///
+/// Returns the slots of the mutable locals which are assigned to, or
+/// moved out of, anywhere in the expressions.
+fn assigned_locals(vars: &Locals, exprs: &[&Expression]) -> Vec<usize> {
+    let mut assigned = AssignedNames(HashSet::new());
+    for expr in exprs.iter() {
+        assigned.visit_expression(expr);
+    }
+    let mut slots: Vec<_> = assigned
+        .0
+        .into_iter()
+        .filter_map(|name| vars.lookup(name))
+        .filter(|&slot| vars.get(slot).is_mut)
+        .collect();
+    slots.sort_unstable();
+    slots
+}
+
+/// Visitor to Find the Names Assigned in an Expression
+struct AssignedNames<'a>(HashSet<&'a str>);
+
+impl<'a> Visitor<'a> for AssignedNames<'a> {
+    fn visit_assignment(&mut self, id: &'a str, value: &'a Expression) {
+        self.0.insert(id);
+        self.visit_expression(value);
+    }
+
+    fn visit_move(&mut self, id: &'a str) {
+        self.0.insert(id);
+    }
+
+    fn visit_function(&mut self, _func: &'a FnDecl) {
+        // Functions can't see the locals they are declared among.
+    }
+}
+
+/// Collect the Operands of a Concatenation
+///
+/// Flattens a chain of `String` additions, such as `a + b + c`, into
+/// its operands in evaluation order.
+fn collect_concat(expr: Expression, parts: &mut Vec<Expression>) {
+    match expr {
+        Expression {
+            kind: ExpressionKind::Infix(lhs, InfixOp::Add, rhs),
+            typ: Typ::Builtin(BuiltinType::String),
+            ..
+        } => {
+            collect_concat(*lhs, parts);
+            collect_concat(*rhs, parts);
+        }
+        expr => parts.push(expr),
+    }
+}
+
+/// Concatenate a Chain of `String` Values
+///
+/// A pair of strings is copied straight into a new string. Longer
+/// chains are appended to a `StringBuilder` instead, so no
+/// intermediate strings are made and each operand is only copied
+/// once. The operands are released.
+fn build_concat(
+    ctx: &mut LowerContext<'_>,
+    builder: &mut Builder,
+    parts: &[LLVMValueRef],
+) -> LLVMValueRef {
+    let res = match *parts {
+        [pref, suf] => build_string_concat(ctx, builder, pref, suf),
+        _ => {
+            let new = RuntimeFn::BuilderNew.declare(ctx);
+            let append = RuntimeFn::BuilderAppend.declare(ctx);
+            let to_string = RuntimeFn::BuilderToString.declare(ctx);
+            let release = RuntimeFn::BuilderRelease.declare(ctx);
+            let sb = unsafe { builder.build_call(&new, &mut []) };
+            for &part in parts {
+                unsafe { builder.build_void_call(&append, &mut [sb, part]) };
+            }
+            let res = unsafe { builder.build_call(&to_string, &mut [sb]) };
+            // The builder never escapes, so it is freed even when
+            // reference counting is off.
+            unsafe { builder.build_void_call(&release, &mut [sb]) };
+            res
+        }
+    };
+    for &part in parts {
+        build_release(ctx, builder, part, Typ::Builtin(BuiltinType::String));
+    }
+    res
+}
+
 /// Concatenate `String` Values
 ///
 /// Takes a pair of strings and concatenates them.
@@ -350,22 +777,9 @@ fn build_string_concat(
     let pre_len = string_get_len(builder, pref);
     let suf_len = string_get_len(builder, suf);
 
-    // FIXME: This makes the assuption that the `<{i32, [0 x i8]}>`
-    // structure is 4 bytes in size. What happens if it has different
-    // size or alignment requirements?
-    let buf_size = builder.build_add(pre_len, suf_len);
-    let size = builder.build_add(buf_size, ctx.llvm_ctx.const_int_width(4, 32));
-
-    let i8ty = ctx.llvm_ctx.int_type(8);
-    let res = builder.build_malloc(i8ty, Some(size), "concat");
-    let res = builder.build_bitcast(
-        res,
-        ctx.llvm_type(Typ::Builtin(BuiltinType::String))
-            .expect("no type in context for string concat"),
-        "catenated",
-    );
-
-    string_set_len(builder, res, buf_size);
+    let buf_size = unsafe { builder.build_add(pre_len, suf_len) };
+    let string_alloc = RuntimeFn::StringAlloc.declare(ctx);
+    let res = unsafe { builder.build_call(&string_alloc, &mut [buf_size]) };
 
     string_copy_guts(ctx, builder, res, pref, pre_len, None);
     string_copy_guts(ctx, builder, res, suf, suf_len, Some(pre_len));
@@ -373,118 +787,328 @@ fn build_string_concat(
     res
 }
 
-/// Format with Printf
+/// Build Arithmetic on `Number`s
 ///
-/// Constructs a call to the `printf` function using the given format
-/// name string to write `to_format` to the standard output. This
-/// method is intended to be used as part of the `print`
-/// operator/expression. For a list of the supported format string
-/// names check out `add_core_decls`.
-fn fmt(
+/// Lowers an add, subtract, or multiply. Arithmetic wraps on
+/// overflow unless overflow checks are enabled, in which case the
+/// program is aborted with a message instead.
+fn build_arith(
     ctx: &mut LowerContext<'_>,
+    fun: &mut Function,
     builder: &mut Builder,
-    mut to_format: Vec<LLVMValueRef>,
-    format_name: &str,
-) {
-    let format = ctx
-        .module
-        .find_global(format_name)
-        .expect("could not find printf format in globals");
-    let format_ptr = builder.build_gep(
-        format,
-        &mut [ctx.llvm_ctx.const_int(0), ctx.llvm_ctx.const_int(0)],
+    op: InfixOp,
+    lhs: LLVMValueRef,
+    rhs: LLVMValueRef,
+) -> LLVMValueRef {
+    let (intrinsic, verb) = match op {
+        InfixOp::Add if ctx.overflow_checks => (Intrinsic::SAddWithOverflow, "add"),
+        InfixOp::Sub if ctx.overflow_checks => (Intrinsic::SSubWithOverflow, "subtract"),
+        InfixOp::Mul if ctx.overflow_checks => (Intrinsic::SMulWithOverflow, "multiply"),
+        InfixOp::Add => return unsafe { builder.build_add(lhs, rhs) },
+        InfixOp::Sub => return unsafe { builder.build_sub(lhs, rhs) },
+        InfixOp::Mul => return unsafe { builder.build_mul(lhs, rhs) },
+        _ => panic!("Infix op {:?} is not arithmetic", op),
+    };
+
+    let (result, overflowed) = unsafe { builder.build_overflow_op(intrinsic, lhs, rhs) };
+    let overflow_block = ctx.llvm_ctx.add_block(fun, "overflow");
+    let cont_block = ctx.llvm_ctx.add_block(fun, "no_overflow");
+    unsafe { builder.build_cond_br(overflowed, overflow_block, cont_block) };
+
+    unsafe { builder.position_at_end(overflow_block) };
+    build_abort(
+        ctx,
+        builder,
+        &format!("error: attempt to {} with overflow\n", verb),
     );
-    let mut args = vec![format_ptr];
-    args.append(&mut to_format);
-    let printf = ctx
-        .module
-        .find_function("printf")
-        .expect("could not find printf");
-    builder.build_call(&printf, &mut args);
+
+    unsafe { builder.position_at_end(cont_block) };
+    result
 }
 
-/// Get Format String from Expression Type
-fn fmt_from_type(
-    typ: Typ,
+/// Build a Division of `Number`s
+///
+/// Division by zero is undefined, so unless runtime checks are
+/// turned off the divisor is checked first. A zero divisor aborts the
+/// program with the location of the division. So does dividing the
+/// smallest `Number` by `-1`, as the result overflows.
+fn build_div(
     ctx: &mut LowerContext<'_>,
     fun: &mut Function,
     builder: &mut Builder,
-    val: LLVMValueRef,
-) -> Option<(Vec<LLVMValueRef>, &'static str)> {
-    match typ {
-        Typ::Builtin(BuiltinType::Bool) => {
-            let formatted = fmt_convert_bool(ctx, fun, builder, val);
-            Some((vec![formatted], "printf_cstr_format"))
-        }
-        Typ::Builtin(BuiltinType::Number) => Some((vec![val], "printf_num_format")),
-        Typ::Builtin(BuiltinType::String) => {
-            let len = string_get_len(builder, val);
-            let ptr = string_get_buffer(builder, val);
-            Some((vec![len, ptr], "printf_ustr_format"))
-        }
-        _ => None,
+    lhs: LLVMValueRef,
+    rhs: LLVMValueRef,
+    span: Span,
+) -> LLVMValueRef {
+    if !ctx.runtime_checks {
+        return unsafe { builder.build_sdiv(lhs, rhs) };
+    }
+
+    let is_zero = unsafe { builder.build_icmp(Predicate::Eq, rhs, ctx.llvm_ctx.const_int(0)) };
+    let zero_block = ctx.llvm_ctx.add_block(fun, "div_by_zero");
+    let cont_block = ctx.llvm_ctx.add_block(fun, "div");
+    unsafe { builder.build_cond_br(is_zero, zero_block, cont_block) };
+
+    unsafe { builder.position_at_end(zero_block) };
+    build_abort(ctx, builder, &div_error(ctx, span, "divide by zero"));
+
+    // Only `MIN / -1` overflows, so the dividend is only checked when
+    // the divisor is `-1`.
+    unsafe { builder.position_at_end(cont_block) };
+    let is_neg_one = unsafe { builder.build_icmp(Predicate::Eq, rhs, ctx.llvm_ctx.const_int(-1)) };
+    let min_block = ctx.llvm_ctx.add_block(fun, "div_by_neg_one");
+    let overflow_block = ctx.llvm_ctx.add_block(fun, "div_overflow");
+    let div_block = ctx.llvm_ctx.add_block(fun, "div");
+    unsafe {
+        builder.build_cond_br(is_neg_one, min_block, div_block);
+        builder.position_at_end(min_block);
     }
+    let is_min =
+        unsafe { builder.build_icmp(Predicate::Eq, lhs, ctx.llvm_ctx.const_int(i64::MIN)) };
+    unsafe {
+        builder.build_cond_br(is_min, overflow_block, div_block);
+        builder.position_at_end(overflow_block);
+    }
+    build_abort(ctx, builder, &div_error(ctx, span, "divide with overflow"));
+
+    unsafe {
+        builder.position_at_end(div_block);
+        builder.build_sdiv(lhs, rhs)
+    }
+}
+
+/// Format a Division Error
+///
+/// Describes the failed division, with its location if it is known.
+fn div_error(ctx: &LowerContext<'_>, span: Span, problem: &str) -> String {
+    match ctx.location(span) {
+        Some(location) => format!("{}:error: attempt to {}\n", location, problem),
+        None => format!("error: attempt to {}\n", problem),
+    }
+}
+
+/// Abort the Program with a Message
+///
+/// Calls the runtime to write `message` to standard error and abort.
+/// Freestanding programs may not have the runtime, so trap without a
+/// message instead. The current block is terminated, so the builder
+/// must be moved to a new block before anything else is built.
+fn build_abort(ctx: &mut LowerContext<'_>, builder: &mut Builder, message: &str) {
+    if ctx.freestanding {
+        builder.build_trap();
+        builder.build_unreachable();
+        return;
+    }
+    let panic = RuntimeFn::Panic.declare(ctx);
+    let global = unsafe {
+        ctx.module
+            .add_global(ctx.llvm_ctx.const_str(message), "abort_msg")
+    };
+    let message_ptr = unsafe {
+        builder.build_gep(
+            global,
+            &mut [ctx.llvm_ctx.const_int(0), ctx.llvm_ctx.const_int(0)],
+        )
+    };
+    let len = ctx.llvm_ctx.const_int(message.len() as i64);
+    unsafe { builder.build_void_call(&panic, &mut [message_ptr, len]) };
+    builder.build_unreachable();
+}
+
+/// Print a `String`
+///
+/// Writes the string followed by a newline to standard output. Any
+/// value can be printed by first converting it with
+/// `build_to_string`.
+fn build_print(ctx: &mut LowerContext<'_>, builder: &mut Builder, s: LLVMValueRef) {
+    let print = RuntimeFn::Print.declare(ctx);
+    unsafe { builder.build_void_call(&print, &mut [s]) };
 }
 
-/// Format Bool to String
+/// Build a Constant `String`
 ///
-/// Prepares a bool value to be formatted. This compiles down to a
-/// ternary with two fixed strings `true` and `false`.
-fn fmt_convert_bool(
+/// Adds a global holding the string's length and contents to the
+/// module and returns a pointer to it as a `String` value. Constants
+/// have a negative reference count, so retaining and releasing them
+/// does nothing.
+fn build_string_const(ctx: &mut LowerContext<'_>, builder: &mut Builder, s: &str) -> LLVMValueRef {
+    let initialiser = unsafe {
+        ctx.llvm_ctx.const_struct(vec![
+            ctx.llvm_ctx.const_int_width(s.len() as i64, 32),
+            ctx.llvm_ctx.const_int_width(-1, 32),
+            ctx.llvm_ctx.const_str(s),
+        ])
+    };
+    let global = unsafe { ctx.module.add_global(initialiser, "s_const") };
+
+    let string_ty = ctx
+        .llvm_type(Typ::Builtin(BuiltinType::String))
+        .expect("no type in context for string literal");
+    unsafe { builder.build_bitcast(global, string_ty, "string_const") }
+}
+
+/// Build a Type Conversion
+///
+/// Converts `val` from type `from` to type `to`. The binder only
+/// inserts the conversions in its conversion table, so any other
+/// conversion is an internal error.
+fn build_conversion(
     ctx: &mut LowerContext<'_>,
     fun: &mut Function,
     builder: &mut Builder,
     val: LLVMValueRef,
+    from: Typ,
+    to: Typ,
+) -> CompResult<LLVMValueRef> {
+    use crate::sem::BuiltinType::*;
+    Ok(match (from, to) {
+        _ if from == to => val,
+        (Typ::Builtin(Number), Typ::Builtin(Bool)) => unsafe {
+            builder.build_icmp(Predicate::Neq, val, ctx.llvm_ctx.const_int(0))
+        },
+        (_, Typ::Builtin(String)) => build_to_string(ctx, fun, builder, val, from)?,
+        _ => {
+            return Err(CompError::from(format!(
+                "ICE: no conversion from '{}' to '{}'",
+                from.name(),
+                to.name()
+            )))
+        }
+    })
+}
+
+/// Convert a `Number` to a `String`
+fn build_number_to_string(
+    ctx: &mut LowerContext<'_>,
+    builder: &mut Builder,
+    val: LLVMValueRef,
 ) -> LLVMValueRef {
-    let cstr_type = ctx.llvm_ctx.cstr_type();
-    let temp = builder.build_alloca(cstr_type, "bool_formatted");
-    let true_bb = ctx.llvm_ctx.add_block(fun, "true");
-    let false_bb = ctx.llvm_ctx.add_block(fun, "false");
-    let join_bb = ctx.llvm_ctx.add_block(fun, "join");
+    let number_to_string = RuntimeFn::NumberToString.declare(ctx);
+    unsafe { builder.build_call(&number_to_string, &mut [val]) }
+}
 
-    builder.build_cond_br(val, true_bb, false_bb);
-
-    builder.position_at_end(true_bb);
-    let true_s = ctx
-        .module
-        .find_global("print_true")
-        .expect("could't find `print_true`");
-    let true_s = builder.build_bitcast(true_s, cstr_type, "true");
-    builder.build_store(true_s, temp);
-    builder.build_br(join_bb);
-
-    builder.position_at_end(false_bb);
-    let false_s = ctx
-        .module
-        .find_global("print_false")
-        .expect("couldn't find `print_false`");
-    let false_s = builder.build_bitcast(false_s, cstr_type, "false");
-    builder.build_store(false_s, temp);
-    builder.build_br(join_bb);
-
-    builder.position_at_end(join_bb);
-
-    builder.build_load(temp)
+/// Convert a Value to a `String`
+///
+/// This is the one place values are turned into strings. Printing,
+/// `to_string`, and implicit conversions all go through it, so a type
+/// becomes printable as soon as it has a case here.
+fn build_to_string(
+    ctx: &mut LowerContext<'_>,
+    fun: &mut Function,
+    builder: &mut Builder,
+    val: LLVMValueRef,
+    typ: Typ,
+) -> CompResult<LLVMValueRef> {
+    use crate::sem::BuiltinType::*;
+    Ok(match typ {
+        Typ::Builtin(String) => val,
+        Typ::Builtin(Number) => build_number_to_string(ctx, builder, val),
+        Typ::Builtin(Bool) => build_bool_to_string(ctx, fun, builder, val),
+        _ => {
+            return Err(CompError::from(format!(
+                "ICE: can't convert '{}' to a string",
+                typ.name()
+            )))
+        }
+    })
 }
 
-/// Format from LLVM Type
+/// Build a Call to a Builtin Function
 ///
-/// Gets a format string specifier from the LLVM type. This is only
-/// used as a fallback.
+/// Builtin functions aren't defined in the module. Instead each call
+/// to one is lowered inline. As with other calls the arguments are
+/// passed owned.
+fn build_builtin_call(
+    ctx: &mut LowerContext<'_>,
+    fun: &mut Function,
+    builder: &mut Builder,
+    name: &str,
+    args: &[(LLVMValueRef, Typ)],
+) -> CompResult<LLVMValueRef> {
+    match (name, args) {
+        ("to_number", &[(s, typ)]) => {
+            let n = build_string_to_number(ctx, builder, s);
+            build_release(ctx, builder, s, typ);
+            Ok(n)
+        }
+        ("len", &[(s, typ)]) => {
+            let char_count = RuntimeFn::StringCharCount.declare(ctx);
+            let len = unsafe { builder.build_call(&char_count, &mut [s]) };
+            build_release(ctx, builder, s, typ);
+            Ok(len)
+        }
+        ("byte_len", &[(s, typ)]) => {
+            let len = string_get_len(builder, s);
+            let len = unsafe { builder.build_zext(len, ctx.llvm_ctx.int_type(64)) };
+            build_release(ctx, builder, s, typ);
+            Ok(len)
+        }
+        ("string_builder", &[]) => {
+            let new = RuntimeFn::BuilderNew.declare(ctx);
+            Ok(unsafe { builder.build_call(&new, &mut []) })
+        }
+        // Builders have reference semantics, so appending modifies
+        // the builder in place. The reference passed in is handed
+        // back as the result.
+        ("append", &[(sb, _), (s, typ)]) => {
+            let append = RuntimeFn::BuilderAppend.declare(ctx);
+            unsafe { builder.build_void_call(&append, &mut [sb, s]) };
+            build_release(ctx, builder, s, typ);
+            Ok(sb)
+        }
+        ("build_string", &[(sb, typ)]) => {
+            let to_string = RuntimeFn::BuilderToString.declare(ctx);
+            let s = unsafe { builder.build_call(&to_string, &mut [sb]) };
+            build_release(ctx, builder, sb, typ);
+            Ok(s)
+        }
+        ("to_string", &[(val, typ)]) => build_to_string(ctx, fun, builder, val, typ),
+        _ => Err(CompError::from(format!("Can't find function '{}'", name))),
+    }
+}
+
+/// Convert a `String` to a `Number`
+fn build_string_to_number(
+    ctx: &mut LowerContext<'_>,
+    builder: &mut Builder,
+    val: LLVMValueRef,
+) -> LLVMValueRef {
+    let string_to_number = RuntimeFn::StringToNumber.declare(ctx);
+    unsafe { builder.build_call(&string_to_number, &mut [val]) }
+}
+
+/// Convert a `Bool` to a `String`
 ///
-/// FIXME: Stop falling back to this function for printing.
-fn fmt_from_llvm(
+/// Compiles down to a ternary choosing between the two constant
+/// strings `true` and `false`.
+fn build_bool_to_string(
     ctx: &mut LowerContext<'_>,
     fun: &mut Function,
     builder: &mut Builder,
     val: LLVMValueRef,
-) -> (Vec<LLVMValueRef>, &'static str) {
-    match Type::from(ctx.llvm_ctx.get_type(val)) {
-        Type::Int(1) => {
-            let formatted = fmt_convert_bool(ctx, fun, builder, val);
-            (vec![formatted], "printf_cstr_format")
-        }
-        Type::Int(_) => (vec![val], "printf_num_format"),
-        _ => unimplemented!(),
+) -> LLVMValueRef {
+    let true_bb = ctx.llvm_ctx.add_block(fun, "true");
+    let false_bb = ctx.llvm_ctx.add_block(fun, "false");
+    let join_bb = ctx.llvm_ctx.add_block(fun, "join");
+
+    unsafe { builder.build_cond_br(val, true_bb, false_bb) };
+
+    unsafe { builder.position_at_end(true_bb) };
+    let true_s = build_string_const(ctx, builder, "true");
+    unsafe { builder.build_br(join_bb) };
+
+    unsafe { builder.position_at_end(false_bb) };
+    let false_s = build_string_const(ctx, builder, "false");
+    unsafe { builder.build_br(join_bb) };
+
+    unsafe { builder.position_at_end(join_bb) };
+    let string_ty = ctx
+        .llvm_type(Typ::Builtin(BuiltinType::String))
+        .expect("no type in context for bool conversion");
+    let mut phi = unsafe { builder.build_phi(string_ty) };
+    unsafe {
+        phi.add_incoming(true_s, true_bb);
+        phi.add_incoming(false_s, false_bb);
     }
+    phi.as_raw()
 }
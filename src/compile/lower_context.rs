@@ -4,8 +4,218 @@
 //! expresion is lowered to LLVM.
 
 use crate::low_loader::prelude::*;
+use crate::meta;
 use crate::sem::{BuiltinType, Typ};
+use crate::syntax::text::{SourceText, Span, DUMMY_SPAN};
+use crate::syntax::LoopControl;
 use std::collections::HashMap;
+use std::path::Path;
+
+/// The Reference Counted Locals Declared in a Scope
+///
+/// Holds the slot of each local in the function's `Locals`.
+pub type DropScope = Vec<usize>;
+
+/// A Branch Between Blocks
+///
+/// Pairs the block a branch was made from with the value of each
+/// local in the function when it was made, by slot.
+pub type Edge = (LLVMBasicBlockRef, Vec<LLVMValueRef>);
+
+/// A Local Variable
+///
+/// Locals are kept in SSA form rather than in stack slots. Each holds
+/// its current value, which an assignment replaces.
+#[derive(Debug, Clone)]
+pub struct Local {
+    /// The variable's name
+    pub name: String,
+    /// Can the variable be assigned to?
+    pub is_mut: bool,
+    /// The variable's current value
+    pub value: LLVMValueRef,
+    /// The variable's type
+    pub typ: Typ,
+}
+
+/// The Locals of a Function
+///
+/// Each local is given a slot when it is declared. Names are looked up
+/// to find the slot of the innermost local with that name. Leaving a
+/// scope frees the slots declared in it, and restores the names it
+/// hid.
+#[derive(Debug, Default)]
+pub struct Locals {
+    /// The slot of each visible name
+    names: HashMap<String, usize>,
+    /// Every local in an enclosing scope, in order of declaration
+    slots: Vec<Local>,
+}
+
+/// The Locals Visible Before a Scope was Entered
+pub struct LocalsScope {
+    names: HashMap<String, usize>,
+    slots: usize,
+}
+
+impl Locals {
+    /// Create an Empty Set of Locals
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Declare a Local
+    ///
+    /// Returns the new local's slot. It hides any other local with the
+    /// same name until the current scope is left.
+    pub fn declare(&mut self, name: String, is_mut: bool, value: LLVMValueRef, typ: Typ) -> usize {
+        let slot = self.slots.len();
+        self.names.insert(name.clone(), slot);
+        self.slots.push(Local {
+            name,
+            is_mut,
+            value,
+            typ,
+        });
+        slot
+    }
+
+    /// Find the Slot of a Name
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// Borrow the Local in a Slot
+    pub fn get(&self, slot: usize) -> &Local {
+        &self.slots[slot]
+    }
+
+    /// Replace the Value of a Local
+    pub fn set(&mut self, slot: usize, value: LLVMValueRef) {
+        self.slots[slot].value = value;
+    }
+
+    /// Get the Current Value of Every Local
+    pub fn values(&self) -> Vec<LLVMValueRef> {
+        self.slots.iter().map(|local| local.value).collect()
+    }
+
+    /// Reset the Value of Every Local
+    ///
+    /// Takes values from `values`, which must have come from an
+    /// earlier call to `values` in the same scope.
+    pub fn reset(&mut self, values: &[LLVMValueRef]) {
+        for (local, &value) in self.slots.iter_mut().zip(values.iter()) {
+            local.value = value;
+        }
+    }
+
+    /// Enter a Scope
+    ///
+    /// The returned scope must be passed to `leave` once the locals
+    /// declared in the scope are no longer visible.
+    pub fn enter(&self) -> LocalsScope {
+        LocalsScope {
+            names: self.names.clone(),
+            slots: self.slots.len(),
+        }
+    }
+
+    /// Leave a Scope
+    pub fn leave(&mut self, scope: LocalsScope) {
+        self.names = scope.names;
+        self.slots.truncate(scope.slots);
+    }
+
+    /// Merge the Values from Several Branches
+    ///
+    /// Called once the builder is positioned in the block the branches
+    /// join at. Each local declared before the branches which has a
+    /// different value on some of them is given a phi node.
+    pub fn merge(&mut self, ctx: &LowerContext<'_>, builder: &mut Builder, edges: &[Edge]) {
+        for slot in 0..self.slots.len() {
+            let first = match edges.first() {
+                Some((_, values)) => values[slot],
+                None => return,
+            };
+            if edges.iter().all(|(_, values)| values[slot] == first) {
+                self.slots[slot].value = first;
+                continue;
+            }
+            let local = &self.slots[slot];
+            let typ = ctx
+                .llvm_type(local.typ)
+                .expect("no type in context for local");
+            let mut phi = unsafe { builder.build_named_phi(typ, &local.name) };
+            for (block, values) in edges.iter() {
+                unsafe { phi.add_incoming(values[slot], *block) };
+            }
+            self.slots[slot].value = phi.as_raw();
+        }
+    }
+}
+
+/// A Loop Being Lowered
+struct LoopBlocks {
+    /// The block which checks the loop's condition
+    cond: LLVMBasicBlockRef,
+    /// The block after the loop
+    join: LLVMBasicBlockRef,
+    /// The number of drop scopes outside the loop's body
+    drop_depth: usize,
+    /// The branches made by `continue`s
+    continues: Vec<Edge>,
+    /// The branches made by `break`s
+    breaks: Vec<Edge>,
+}
+
+/// Debug Info for a Module
+///
+/// Holds the debug info builder along with the file every function is
+/// described in.
+pub struct DebugInfo {
+    /// The builder the module's debug info is created with
+    builder: DebugInfoBuilder,
+    /// The source file being lowered
+    file: LLVMMetadataRef,
+    /// The functions being lowered, innermost last. Each has a stack
+    /// of the locations of the expressions being lowered within it,
+    /// innermost last.
+    functions: Vec<(LLVMMetadataRef, Vec<LLVMMetadataRef>)>,
+}
+
+impl DebugInfo {
+    /// Create the Debug Info for a Module
+    ///
+    /// Describes the source file, and adds a compile unit for it to
+    /// the module.
+    pub fn new(module: &mut Module, source_name: &str, optimised: bool) -> Self {
+        let path = Path::new(source_name);
+        let name = path
+            .file_name()
+            .map_or_else(|| source_name.into(), |name| name.to_string_lossy());
+        let directory = path
+            .parent()
+            .map_or(Default::default(), Path::to_string_lossy);
+        let mut builder = DebugInfoBuilder::new(module);
+        let file = builder.create_file(&name, &directory);
+        unsafe { builder.create_compile_unit(file, &meta::descriptive_version(), optimised) };
+        DebugInfo {
+            builder,
+            file,
+            functions: Vec::new(),
+        }
+    }
+
+    /// Create a Location in the Current Function
+    fn location(&mut self, line: usize, column: usize) -> Option<LLVMMetadataRef> {
+        let scope = self.functions.last()?.0;
+        Some(unsafe {
+            self.builder
+                .create_location(line as u32, column as u32, scope)
+        })
+    }
+}
 
 /// Lower Context
 ///
@@ -16,9 +226,30 @@ pub struct LowerContext<'a> {
     pub llvm_ctx: &'a mut Context,
     /// The LLVM Module this context is building IR into.
     pub module: &'a mut Module,
+    /// Trap on arithmetic overflow rather than wrapping
+    pub overflow_checks: bool,
+    /// Retain and release reference counted values. When this is off
+    /// heap values are left for a collector, or never freed.
+    pub reference_counting: bool,
+    /// Check for errors, such as division by zero, which would
+    /// otherwise be undefined behaviour, and abort the program
+    pub runtime_checks: bool,
+    /// Don't call the runtime library to report errors. Runtime
+    /// checks trap instead.
+    pub freestanding: bool,
+    /// The source being lowered. Used to describe where a runtime
+    /// error happened.
+    pub source: Option<&'a SourceText>,
+    /// Debug info for the module, if it is being generated
+    pub debug_info: Option<DebugInfo>,
 
     /// Map of Ty values to LLVM Types
     ty_map: HashMap<Typ, LLVMTypeRef>,
+    /// The reference counted locals to release at the end of each
+    /// enclosing scope, innermost last.
+    drop_scopes: Vec<DropScope>,
+    /// The loops whose bodies are being lowered, innermost last
+    loops: Vec<LoopBlocks>,
 }
 
 impl<'a> LowerContext<'a> {
@@ -30,36 +261,35 @@ impl<'a> LowerContext<'a> {
         LowerContext {
             llvm_ctx: ctx,
             module,
+            overflow_checks: false,
+            reference_counting: true,
+            runtime_checks: true,
+            freestanding: false,
+            source: None,
+            debug_info: None,
             ty_map: Default::default(),
+            drop_scopes: Vec::new(),
+            loops: Vec::new(),
         }
     }
 
-    /// Add LLVM Intrinsic Declarations
-    ///
-    /// Updates the module to add declarations for the LLVM intrinsics
-    /// we care about. Need to find a better way to create these.
-    pub fn add_intrinsics(&mut self) {
-        let i8ptr = self.llvm_ctx.pointer_type(self.llvm_ctx.int_type(8));
-        let i32ty = self.llvm_ctx.int_type(32);
-
-        self.llvm_ctx.add_function(
-            self.module,
-            "llvm.memcpy.p0i8.p0i8.i32",
-            self.llvm_ctx.void_type(),
-            &mut [i8ptr, i8ptr, i32ty, self.llvm_ctx.bool_type()],
-        );
-    }
-
     /// Add Core LLVM Types
     ///
     /// Adds entries to the type map for the bulitin types mappign
     /// them to their underlying LLVM representation.
     pub fn add_core_types(&mut self) {
-        let lang_string = self.llvm_ctx.pointer_type(self.llvm_ctx.struct_type(vec![
-            self.llvm_ctx.int_type(32),
-            self.llvm_ctx.array_type(self.llvm_ctx.int_type(8), 0),
-        ]));
+        let lang_string = unsafe {
+            self.llvm_ctx.pointer_type(self.llvm_ctx.struct_type(&[
+                self.llvm_ctx.int_type(32),
+                self.llvm_ctx.int_type(32),
+                self.llvm_ctx.array_type(self.llvm_ctx.int_type(8), 0),
+            ]))
+        };
         self.add_type(Typ::Builtin(BuiltinType::String), lang_string);
+        // Builders are only ever handled by the runtime, so their
+        // layout is left opaque.
+        let lang_builder = self.llvm_ctx.cstr_type();
+        self.add_type(Typ::Builtin(BuiltinType::StringBuilder), lang_builder);
         let llvm_bool = self.llvm_ctx.bool_type();
         self.add_type(Typ::Builtin(BuiltinType::Bool), llvm_bool);
         let llvm_number = self.llvm_ctx.int_type(64);
@@ -78,4 +308,216 @@ impl<'a> LowerContext<'a> {
     pub fn llvm_type(&self, ty: Typ) -> Option<LLVMTypeRef> {
         self.ty_map.get(&ty).cloned()
     }
+
+    /// Describe the Location of a Span
+    ///
+    /// Returns the span's position as `name:line:col`, in the same
+    /// form diagnostics use, for use in runtime error messages. If the
+    /// source or span isn't known then `None` is returned.
+    pub fn location(&self, span: Span) -> Option<String> {
+        let source = self.source.filter(|_| span != DUMMY_SPAN)?;
+        let (line, col) = source.position(span);
+        Some(format!("{}:{}:{}", source.name(), line, col))
+    }
+
+    /// Get the Line and Column of a Span
+    ///
+    /// Both are numbered from 1, as debug info expects. If the source
+    /// or span isn't known then `None` is returned.
+    fn line_and_column(&self, span: Span) -> Option<(usize, usize)> {
+        let source = self.source.filter(|_| span != DUMMY_SPAN)?;
+        let (line, col) = source.position(span);
+        Some((line, col + 1))
+    }
+
+    /// Enter a Function's Debug Scope
+    ///
+    /// When debug info is being generated the function is described
+    /// to the debugger, and the builder is pointed at the line the
+    /// function was declared on. Must be balanced by a call to
+    /// `leave_function`.
+    pub fn enter_function(
+        &mut self,
+        fun: &mut Function,
+        name: &str,
+        span: Span,
+        builder: &mut Builder,
+    ) {
+        let (line, column) = self.line_and_column(span).unwrap_or((1, 0));
+        if let Some(debug) = self.debug_info.as_mut() {
+            let file = debug.file;
+            let subprogram = unsafe {
+                let ty = debug.builder.create_subroutine_type(file, &[]);
+                debug
+                    .builder
+                    .create_function(file, file, fun, name, line as u32, ty)
+            };
+            debug.functions.push((subprogram, Vec::new()));
+            let location = debug.location(line, column).unwrap();
+            debug.functions.last_mut().unwrap().1.push(location);
+            unsafe { builder.set_debug_location(location) };
+        }
+    }
+
+    /// Leave the Current Function's Debug Scope
+    pub fn leave_function(&mut self) {
+        if let Some(debug) = self.debug_info.as_mut() {
+            debug.functions.pop();
+        }
+    }
+
+    /// Enter the Location of an Expression
+    ///
+    /// Instructions built until the matching `pop_location` are
+    /// attributed to the start of the span. If the span isn't known
+    /// then the enclosing location is kept.
+    pub fn push_location(&mut self, builder: &mut Builder, span: Span) {
+        let position = self.line_and_column(span);
+        if let Some(debug) = self.debug_info.as_mut() {
+            let location = match position {
+                Some((line, column)) => debug.location(line, column),
+                None => debug.functions.last().and_then(|f| f.1.last().cloned()),
+            };
+            if let (Some(location), Some(function)) = (location, debug.functions.last_mut()) {
+                function.1.push(location);
+                unsafe { builder.set_debug_location(location) };
+            }
+        }
+    }
+
+    /// Leave the Location of an Expression
+    ///
+    /// Points the builder back at the enclosing expression's location.
+    pub fn pop_location(&mut self, builder: &mut Builder) {
+        if let Some(function) = self
+            .debug_info
+            .as_mut()
+            .and_then(|d| d.functions.last_mut())
+        {
+            if function.1.len() > 1 {
+                function.1.pop();
+            }
+            let location = *function.1.last().unwrap();
+            unsafe { builder.set_debug_location(location) };
+        }
+    }
+
+    /// Finish the Module's Debug Info
+    ///
+    /// Must be called once lowering is complete, before the module is
+    /// verified.
+    pub fn finalise_debug_info(&mut self) {
+        if let Some(debug) = self.debug_info.as_mut() {
+            debug.builder.finalise();
+        }
+    }
+
+    /// Enter a Drop Scope
+    ///
+    /// Locals added with `add_drop` are collected in the new scope
+    /// until it is left with `pop_drop_scope`.
+    pub fn push_drop_scope(&mut self) {
+        self.drop_scopes.push(Vec::new());
+    }
+
+    /// Leave the Current Drop Scope
+    ///
+    /// Returns the locals which need releasing, in the order they
+    /// were declared.
+    pub fn pop_drop_scope(&mut self) -> DropScope {
+        self.drop_scopes
+            .pop()
+            .expect("drop scope popped without being pushed")
+    }
+
+    /// Add a Local to the Current Drop Scope
+    pub fn add_drop(&mut self, slot: usize) {
+        if let Some(scope) = self.drop_scopes.last_mut() {
+            scope.push(slot);
+        }
+    }
+
+    /// Enter the Body of a Loop
+    ///
+    /// `continue` branches to the `cond` block and `break` to the
+    /// `join` block until the loop is left with `pop_loop`.
+    pub fn push_loop(&mut self, cond: LLVMBasicBlockRef, join: LLVMBasicBlockRef) {
+        self.loops.push(LoopBlocks {
+            cond,
+            join,
+            drop_depth: self.drop_scopes.len(),
+            continues: Vec::new(),
+            breaks: Vec::new(),
+        });
+    }
+
+    /// Leave the Body of a Loop
+    ///
+    /// Returns the branches made by the `continue`s and the `break`s
+    /// of the loop.
+    pub fn pop_loop(&mut self) -> (Vec<Edge>, Vec<Edge>) {
+        let blocks = self.loops.pop().expect("loop popped without being pushed");
+        (blocks.continues, blocks.breaks)
+    }
+
+    /// Find the Locals Released by a `break` or `continue`
+    ///
+    /// Returns the slots of the locals which must be released before
+    /// branching, innermost scope first. `depth` is the number of
+    /// loops to skip outwards. The locals stay in their scopes, as the
+    /// code following the branch is still lowered.
+    pub fn loop_drops(&self, depth: usize) -> Vec<DropScope> {
+        let drop_depth = self.enclosing_loop(depth).drop_depth;
+        self.drop_scopes[drop_depth..]
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    }
+
+    /// Branch to a Loop's Blocks
+    ///
+    /// Records the edge so that the locals can be merged where it
+    /// arrives, and returns the block to branch to.
+    pub fn add_loop_edge(
+        &mut self,
+        control: LoopControl,
+        depth: usize,
+        edge: Edge,
+    ) -> LLVMBasicBlockRef {
+        let index = self.loops.len() - 1 - depth;
+        let blocks = &mut self.loops[index];
+        match control {
+            LoopControl::Break => {
+                blocks.breaks.push(edge);
+                blocks.join
+            }
+            LoopControl::Continue => {
+                blocks.continues.push(edge);
+                blocks.cond
+            }
+        }
+    }
+
+    /// Find an Enclosing Loop
+    fn enclosing_loop(&self, depth: usize) -> &LoopBlocks {
+        self.loops
+            .iter()
+            .rev()
+            .nth(depth)
+            .expect("loop control outside of a loop")
+    }
+
+    /// Remove a Local from its Drop Scope
+    ///
+    /// Used once a local's reference has been moved elsewhere, and
+    /// so must no longer be released.
+    pub fn remove_drop(&mut self, slot: usize) {
+        for scope in self.drop_scopes.iter_mut().rev() {
+            if let Some(index) = scope.iter().position(|&local| local == slot) {
+                scope.remove(index);
+                return;
+            }
+        }
+    }
 }
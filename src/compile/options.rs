@@ -3,7 +3,15 @@
 //! This module defines the options structure used to tweak
 //! compilation output.
 
+use super::hooks::{HookResult, Hooks};
 use crate::low_loader::pass_manager as pm;
+use crate::low_loader::prelude::{Attribute, Module};
+use crate::low_loader::targets::{CodeGenOptLevel, RelocMode};
+use crate::sem;
+use crate::sem::lint::LintLevels;
+use crate::syntax::{Edition, SyntaxTree};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Compilation Options
 ///
@@ -14,6 +22,83 @@ pub struct CompilationOptions {
     pub dump_ir: bool,
     /// Optimisation level to use when emitting code
     pub opt_level: OptimisationLevel,
+    /// Generate debug information for the compiled module
+    ///
+    /// Functions are described to the debugger, and each instruction
+    /// is attributed to the line and column it was lowered from.
+    pub debug_info: bool,
+    /// The CPU to generate code for. If none is set the generic CPU
+    /// for the target is used.
+    pub target_cpu: Option<String>,
+    /// Target features to enable or disable, e.g. `+avx2,-sse4.1`
+    pub target_features: Option<String>,
+    /// Relocation model to use when generating code
+    pub reloc_model: RelocModel,
+    /// The linker to invoke when producing an executable. If none is
+    /// set then `clang` is used.
+    pub linker: Option<String>,
+    /// Extra arguments passed through to the linker
+    pub linker_args: Vec<String>,
+    /// The root directory the linker searches for the target's
+    /// headers and libraries in, when cross compiling
+    pub sysroot: Option<PathBuf>,
+    /// How the C runtime is linked into executables
+    pub c_runtime: CRuntime,
+    /// The kind of artifact to produce when emitting
+    pub emit_kind: EmitKind,
+    /// How warnings raised during compilation should be treated
+    pub warning_level: WarningLevel,
+    /// Report the remarks LLVM's passes make about the code as notes.
+    /// LLVM's notes are always reported.
+    pub llvm_remarks: bool,
+    /// The level of each lint run over the bound tree
+    pub lint_levels: LintLevels,
+    /// Abort the program if arithmetic overflows rather than
+    /// silently wrapping
+    pub overflow_checks: bool,
+    /// Sanitizers to instrument the generated code with
+    pub sanitizers: Sanitizers,
+    /// How the memory for heap values, such as strings, is managed
+    pub gc: GcStrategy,
+    /// Leave out the runtime checks for undefined behaviour, such as
+    /// division by zero
+    pub no_runtime_checks: bool,
+    /// Don't run the cleanup passes over unoptimised code, leaving the
+    /// IR exactly as it was lowered
+    pub no_cleanup: bool,
+    /// Which functions have a stack canary inserted
+    pub stack_protector: StackProtector,
+    /// Probe each page of large stack frames as they are allocated,
+    /// so overflowing the stack always hits the guard page
+    pub stack_probes: bool,
+    /// Link without the C library, start files, or runtime library.
+    /// Programs can't print or use strings, and must provide `_start`.
+    pub freestanding: bool,
+    /// Embed a description of the build in the output. This records
+    /// the compiler's version, the target, and the options which
+    /// affect the generated code.
+    pub build_info: bool,
+    /// The function to call as the program's entry point. If none is
+    /// set the top level of the program is run as `main`.
+    pub entry: Option<String>,
+    /// The name to give the compiled module. If none is set the
+    /// module is named after the output path when emitting to a file.
+    pub module_name: Option<String>,
+    /// Keep the intermediate files written while emitting rather
+    /// than deleting them
+    pub save_temps: bool,
+    /// The directory to keep intermediate files in. If none is set
+    /// they are kept next to the output.
+    pub temps_dir: Option<PathBuf>,
+    /// The configuration `cfg` attributes are checked against. If
+    /// none is set the configuration for the host is used.
+    pub cfg: Option<sem::CfgSet>,
+    /// The edition of the language the program is written in. The
+    /// tree being compiled should have been parsed for the same
+    /// edition.
+    pub edition: Edition,
+    /// Callbacks to run after each phase of the compilation
+    pub hooks: Hooks,
 }
 
 /// Optimisation levels
@@ -21,8 +106,10 @@ pub struct CompilationOptions {
 /// Defines the different levels of optimisation that the compiler
 /// supports. These levels are usually controlled from the command
 /// line.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
 pub enum OptimisationLevel {
     /// No optimisation
+    #[default]
     Off,
     /// Low optimisation, same as -O1
     Low,
@@ -34,6 +121,204 @@ pub enum OptimisationLevel {
     Size,
 }
 
+/// Emit Kind
+///
+/// The kind of artifact to produce when emitting a compilation.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum EmitKind {
+    /// A linked executable. This can't be emitted to memory.
+    #[default]
+    Executable,
+    /// Textual LLVM IR
+    LlvmIr,
+    /// LLVM bitcode
+    Bitcode,
+    /// Native object code for the target
+    Object,
+    /// Native assembly for the target
+    Assembly,
+}
+
+impl EmitKind {
+    /// Infer the Emit Kind from an Output Path
+    ///
+    /// Returns the kind of artifact conventionally written to a file
+    /// with the path's extension: `.ll`, `.bc`, `.o`, or `.s`. Any
+    /// other path has no inferred kind.
+    pub fn from_extension(path: &Path) -> Option<EmitKind> {
+        match path.extension()?.to_str()? {
+            "ll" => Some(EmitKind::LlvmIr),
+            "bc" => Some(EmitKind::Bitcode),
+            "o" => Some(EmitKind::Object),
+            "s" => Some(EmitKind::Assembly),
+            _ => None,
+        }
+    }
+}
+
+/// Relocation Model
+///
+/// Controls how code and data addresses are resolved in the
+/// generated code.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum RelocModel {
+    /// The default relocation model for the target
+    #[default]
+    Default,
+    /// Non-relocatable code
+    Static,
+    /// Position independent code
+    Pic,
+    /// Relocatable external references, non-relocatable code
+    DynamicNoPic,
+}
+
+/// C Runtime Linkage
+///
+/// Controls how the C runtime and library are linked into an
+/// executable. Which C library is used is chosen by the environment
+/// of the target triple, e.g. `x86_64-unknown-linux-musl`.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum CRuntime {
+    /// The linker's default, usually a dynamically linked C library
+    #[default]
+    Default,
+    /// Link the C runtime and library statically
+    Static,
+    /// Link statically, as a position independent executable
+    StaticPie,
+}
+
+impl CRuntime {
+    /// Get the Linker Arguments
+    ///
+    /// Returns the arguments which select this runtime linkage.
+    pub fn linker_args(self) -> Vec<String> {
+        match self {
+            CRuntime::Default => Vec::new(),
+            CRuntime::Static => vec!["-static".into()],
+            CRuntime::StaticPie => vec!["-static-pie".into()],
+        }
+    }
+}
+
+/// Sanitizers
+///
+/// The runtime checks which can be added to the generated code to
+/// help find bugs in it.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Sanitizers {
+    /// Check memory accesses with AddressSanitizer. Executables are
+    /// linked against its runtime library.
+    pub address: bool,
+    /// Check for undefined behaviour. The only undefined behaviour
+    /// lowering can produce is arithmetic overflow, so this enables
+    /// the overflow checks.
+    pub undefined: bool,
+}
+
+impl Sanitizers {
+    /// Is Any Sanitizer Enabled?
+    pub fn any(&self) -> bool {
+        self.address || self.undefined
+    }
+
+    /// Get the Linker Arguments
+    ///
+    /// Returns the arguments which link the runtimes of the enabled
+    /// sanitizers into an executable.
+    pub fn linker_args(&self) -> Vec<String> {
+        if self.address {
+            vec!["-fsanitize=address".into()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Garbage Collection Strategy
+///
+/// Controls how the memory for heap values is reclaimed once the
+/// program no longer refers to it.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum GcStrategy {
+    /// Reference count heap values, freeing each one when its last
+    /// reference is released
+    #[default]
+    Rc,
+    /// Allocate from the Boehm conservative collector. Executables
+    /// are linked against `libgc`.
+    Boehm,
+    /// Never free heap values
+    None,
+}
+
+impl GcStrategy {
+    /// Does Lowering Insert Reference Counting?
+    pub fn is_reference_counted(self) -> bool {
+        self == GcStrategy::Rc
+    }
+
+    /// Get the Linker Arguments
+    ///
+    /// Returns the arguments which link the collector's library into
+    /// an executable.
+    pub fn linker_args(self) -> Vec<String> {
+        match self {
+            GcStrategy::Boehm => vec!["-lgc".into()],
+            GcStrategy::Rc | GcStrategy::None => Vec::new(),
+        }
+    }
+}
+
+/// Stack Protector
+///
+/// Controls which functions have their stack guarded against buffer
+/// overflows with a canary. Mirrors the `-fstack-protector` family of
+/// options in C compilers.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum StackProtector {
+    /// Don't protect the stack
+    #[default]
+    None,
+    /// Protect functions with character arrays on the stack
+    Basic,
+    /// Protect functions with any arrays on the stack, or which take
+    /// the address of a local
+    Strong,
+    /// Protect every function
+    All,
+}
+
+impl StackProtector {
+    /// Get the Function Attribute
+    ///
+    /// Returns the attribute which requests this level of protection,
+    /// if any.
+    pub fn attribute(self) -> Option<Attribute> {
+        match self {
+            StackProtector::None => None,
+            StackProtector::Basic => Some(Attribute::StackProtect),
+            StackProtector::Strong => Some(Attribute::StackProtectStrong),
+            StackProtector::All => Some(Attribute::StackProtectReq),
+        }
+    }
+}
+
+/// Warning Level
+///
+/// Controls how warnings are reported during compilation.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum WarningLevel {
+    /// Don't report warnings
+    Allow,
+    /// Report warnings, but don't fail compilation
+    #[default]
+    Warn,
+    /// Treat warnings as errors
+    Deny,
+}
+
 impl CompilationOptions {
     /// Se the `dump_ir` flag
     ///
@@ -49,11 +334,416 @@ impl CompilationOptions {
     pub fn with_opt_level(self, opt_level: OptimisationLevel) -> Self {
         CompilationOptions { opt_level, ..self }
     }
-}
 
-impl Default for OptimisationLevel {
-    fn default() -> Self {
-        OptimisationLevel::Off
+    /// Set the `debug_info` flag
+    ///
+    /// Enables or disables generation of debug information.
+    pub fn with_debug_info(self, debug_info: bool) -> Self {
+        CompilationOptions { debug_info, ..self }
+    }
+
+    /// Set the Target CPU
+    pub fn with_target_cpu<S: Into<String>>(self, cpu: S) -> Self {
+        CompilationOptions {
+            target_cpu: Some(cpu.into()),
+            ..self
+        }
+    }
+
+    /// Set the Target Features
+    ///
+    /// Features are given as a comma separated list of `+feature` or
+    /// `-feature` items in the format LLVM expects.
+    pub fn with_target_features<S: Into<String>>(self, features: S) -> Self {
+        CompilationOptions {
+            target_features: Some(features.into()),
+            ..self
+        }
+    }
+
+    /// Set the Relocation Model
+    pub fn with_reloc_model(self, reloc_model: RelocModel) -> Self {
+        CompilationOptions {
+            reloc_model,
+            ..self
+        }
+    }
+
+    /// Set the Linker
+    ///
+    /// Chooses the program invoked to link executables.
+    pub fn with_linker<S: Into<String>>(self, linker: S) -> Self {
+        CompilationOptions {
+            linker: Some(linker.into()),
+            ..self
+        }
+    }
+
+    /// Add Linker Arguments
+    ///
+    /// Appends the given arguments to those passed to the linker.
+    pub fn with_linker_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.linker_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the Sysroot
+    ///
+    /// The sysroot is passed to the linker so it finds the C runtime
+    /// and libraries for the target rather than the host.
+    pub fn with_sysroot<P: Into<PathBuf>>(self, sysroot: P) -> Self {
+        CompilationOptions {
+            sysroot: Some(sysroot.into()),
+            ..self
+        }
+    }
+
+    /// Set the C Runtime Linkage
+    pub fn with_c_runtime(self, c_runtime: CRuntime) -> Self {
+        CompilationOptions { c_runtime, ..self }
+    }
+
+    /// Set the `save_temps` Flag
+    ///
+    /// When set, the LLVM IR and object file handed to the linker are
+    /// kept when emitting.
+    pub fn with_save_temps(self, save_temps: bool) -> Self {
+        CompilationOptions { save_temps, ..self }
+    }
+
+    /// Set the Directory to Keep Intermediate Files In
+    ///
+    /// Setting a directory also turns on `save_temps`.
+    pub fn with_temps_dir<P: Into<PathBuf>>(self, temps_dir: P) -> Self {
+        CompilationOptions {
+            save_temps: true,
+            temps_dir: Some(temps_dir.into()),
+            ..self
+        }
+    }
+
+    /// Get the Directory for Intermediate Files
+    ///
+    /// Returns where intermediate files for the given output should be
+    /// kept, or `None` if they shouldn't be kept at all.
+    pub fn temps_dir_for(&self, output_path: &Path) -> Option<PathBuf> {
+        if !self.save_temps {
+            return None;
+        }
+        Some(match &self.temps_dir {
+            Some(dir) => dir.clone(),
+            None => output_path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        })
+    }
+
+    /// Set the Emit Kind
+    ///
+    /// Controls the kind of artifact written when the compilation is
+    /// emitted.
+    pub fn with_emit_kind(self, emit_kind: EmitKind) -> Self {
+        CompilationOptions { emit_kind, ..self }
+    }
+
+    /// Set the Warning Level
+    pub fn with_warning_level(self, warning_level: WarningLevel) -> Self {
+        CompilationOptions {
+            warning_level,
+            ..self
+        }
+    }
+
+    /// Set the `overflow_checks` flag
+    ///
+    /// Enables or disables trapping on arithmetic overflow.
+    pub fn with_overflow_checks(self, overflow_checks: bool) -> Self {
+        CompilationOptions {
+            overflow_checks,
+            ..self
+        }
+    }
+
+    /// Set the Conditional Compilation Configuration
+    ///
+    /// Items whose `cfg` attributes don't match the configuration
+    /// aren't compiled.
+    pub fn with_cfg(self, cfg: sem::CfgSet) -> Self {
+        CompilationOptions {
+            cfg: Some(cfg),
+            ..self
+        }
+    }
+
+    /// Set the Lint Levels
+    ///
+    /// Lints set to `Deny` fail the compilation. Lints which are
+    /// reported as warnings are then treated as the `warning_level`
+    /// says.
+    pub fn with_lint_levels(self, lint_levels: LintLevels) -> Self {
+        CompilationOptions {
+            lint_levels,
+            ..self
+        }
+    }
+
+    /// Set the Language Edition
+    pub fn with_edition(self, edition: Edition) -> Self {
+        CompilationOptions { edition, ..self }
+    }
+
+    /// Set the Garbage Collection Strategy
+    pub fn with_gc(self, gc: GcStrategy) -> Self {
+        CompilationOptions { gc, ..self }
+    }
+
+    /// Set the `freestanding` Flag
+    ///
+    /// Freestanding executables aren't linked against the C library
+    /// or the runtime library, and don't get the C start files. This
+    /// allows targets without an operating system to be compiled for.
+    /// Lowering fails if the program prints or uses strings, as they
+    /// need the runtime library.
+    pub fn with_freestanding(self, freestanding: bool) -> Self {
+        CompilationOptions {
+            freestanding,
+            ..self
+        }
+    }
+
+    /// Set the Entry Point
+    ///
+    /// The named function is called as the program's `main`, and its
+    /// result is used as the exit status. The top level of the
+    /// program may then only contain function declarations.
+    pub fn with_entry<S: Into<String>>(self, entry: S) -> Self {
+        CompilationOptions {
+            entry: Some(entry.into()),
+            ..self
+        }
+    }
+
+    /// Set the `no_runtime_checks` Flag
+    ///
+    /// Without runtime checks operations such as division by zero are
+    /// undefined behaviour rather than aborting the program.
+    pub fn with_no_runtime_checks(self, no_runtime_checks: bool) -> Self {
+        CompilationOptions {
+            no_runtime_checks,
+            ..self
+        }
+    }
+
+    /// Set the `no_cleanup` Flag
+    ///
+    /// Without cleanup, code compiled with optimisation off is emitted
+    /// as it was lowered, including any dead blocks.
+    pub fn with_no_cleanup(self, no_cleanup: bool) -> Self {
+        CompilationOptions { no_cleanup, ..self }
+    }
+
+    /// Set the Build Information
+    ///
+    /// Embeds a description of the build, formatted by
+    /// `meta::build_info`, in the output as the `ullage_build_info`
+    /// global. The options recorded are the `codegen_flags`.
+    pub fn with_build_info(self, build_info: bool) -> Self {
+        CompilationOptions { build_info, ..self }
+    }
+
+    /// Set the Stack Protector
+    pub fn with_stack_protector(self, stack_protector: StackProtector) -> Self {
+        CompilationOptions {
+            stack_protector,
+            ..self
+        }
+    }
+
+    /// Set the `stack_probes` Flag
+    pub fn with_stack_probes(self, stack_probes: bool) -> Self {
+        CompilationOptions {
+            stack_probes,
+            ..self
+        }
+    }
+
+    /// Set the `llvm_remarks` Flag
+    ///
+    /// LLVM remarks on most functions it generates code for, so they
+    /// are dropped unless asked for.
+    pub fn with_llvm_remarks(self, llvm_remarks: bool) -> Self {
+        CompilationOptions {
+            llvm_remarks,
+            ..self
+        }
+    }
+
+    /// Set the Sanitizers
+    pub fn with_sanitizers(self, sanitizers: Sanitizers) -> Self {
+        CompilationOptions { sanitizers, ..self }
+    }
+
+    /// Set the Module Name
+    pub fn with_module_name<S: Into<String>>(self, name: S) -> Self {
+        CompilationOptions {
+            module_name: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Add an After Parse Hook
+    ///
+    /// The hook is called with the syntax tree before it is bound.
+    pub fn with_after_parse<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&SyntaxTree<'_>) -> HookResult + 'static,
+    {
+        self.hooks.add_after_parse(hook);
+        self
+    }
+
+    /// Add an After Bind Hook
+    ///
+    /// The hook is called with the semantic tree before it is
+    /// lowered.
+    pub fn with_after_bind<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&sem::Expression) -> HookResult + 'static,
+    {
+        self.hooks.add_after_bind(hook);
+        self
+    }
+
+    /// Add an After Lowering Hook
+    ///
+    /// The hook is called with the lowered, and optimised, module
+    /// before it is emitted.
+    pub fn with_after_lower<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Module) -> HookResult + 'static,
+    {
+        self.hooks.add_after_lower(hook);
+        self
+    }
+
+    /// Add a Link Hook
+    ///
+    /// The hook is called with the full linker command line just
+    /// before the linker is run.
+    pub fn with_before_link<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&[String]) -> HookResult + 'static,
+    {
+        self.hooks.add_before_link(hook);
+        self
+    }
+
+    /// Get the Linker
+    ///
+    /// Returns the linker to use, falling back to `clang` if none
+    /// has been set.
+    pub fn linker(&self) -> &str {
+        self.linker.as_ref().map_or("clang", |l| &l[..])
+    }
+
+    /// Get All the Linker Arguments
+    ///
+    /// Returns the arguments for the sysroot, C runtime, garbage
+    /// collector, and sanitizers, followed by the extra
+    /// `linker_args`. The object file, target, and output path aren't
+    /// included.
+    pub fn all_linker_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(sysroot) = &self.sysroot {
+            args.push(format!("--sysroot={}", sysroot.display()));
+        }
+        args.extend(self.c_runtime.linker_args());
+        if self.freestanding {
+            args.push("-nostdlib".into());
+        }
+        args.extend(self.gc.linker_args());
+        args.extend(self.sanitizers.linker_args());
+        args.extend(self.linker_args.iter().cloned());
+        args
+    }
+
+    /// Get the Code Generation Flags
+    ///
+    /// Returns the command line flags for each option which changes
+    /// the code generated and isn't set to its default. Options which
+    /// only affect linking, diagnostics, or where output is written
+    /// aren't included.
+    pub fn codegen_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        let opt_level = match self.opt_level {
+            OptimisationLevel::Off => None,
+            OptimisationLevel::Low => Some("-O1"),
+            OptimisationLevel::Med => Some("-O2"),
+            OptimisationLevel::High => Some("-O3"),
+            OptimisationLevel::Size => Some("-Os"),
+        };
+        flags.extend(opt_level.map(String::from));
+        if self.debug_info {
+            flags.push("-g".into());
+        }
+        if let Some(cpu) = &self.target_cpu {
+            flags.push(format!("--target-cpu={}", cpu));
+        }
+        if let Some(features) = &self.target_features {
+            flags.push(format!("--target-features={}", features));
+        }
+        let reloc_model = match self.reloc_model {
+            RelocModel::Default => None,
+            RelocModel::Static => Some("static"),
+            RelocModel::Pic => Some("pic"),
+            RelocModel::DynamicNoPic => Some("dynamic-no-pic"),
+        };
+        flags.extend(reloc_model.map(|m| format!("--reloc-model={}", m)));
+        flags.push(format!("--edition={}", self.edition));
+        if self.overflow_checks {
+            flags.push("--overflow-checks".into());
+        }
+        let sanitizers = [
+            (self.sanitizers.address, "address"),
+            (self.sanitizers.undefined, "undefined"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+        if !sanitizers.is_empty() {
+            flags.push(format!("--sanitize={}", sanitizers.join(",")));
+        }
+        match self.gc {
+            GcStrategy::Rc => (),
+            GcStrategy::Boehm => flags.push("--gc=boehm".into()),
+            GcStrategy::None => flags.push("--gc=none".into()),
+        }
+        if self.no_runtime_checks {
+            flags.push("--no-runtime-checks".into());
+        }
+        let stack_protector = match self.stack_protector {
+            StackProtector::None => None,
+            StackProtector::Basic => Some("basic"),
+            StackProtector::Strong => Some("strong"),
+            StackProtector::All => Some("all"),
+        };
+        flags.extend(stack_protector.map(|p| format!("--stack-protector={}", p)));
+        if self.stack_probes {
+            flags.push("--stack-probes".into());
+        }
+        if self.freestanding {
+            flags.push("--freestanding".into());
+        }
+        if let Some(entry) = &self.entry {
+            flags.push(format!("--entry={}", entry));
+        }
+        flags
     }
 }
 
@@ -63,7 +753,7 @@ impl OptimisationLevel {
     /// Retrieves a (level, size) tuple which defines how to configure
     /// the LLVM optimiser for this optimisation level.
     pub fn unpack(&self) -> Option<(pm::OptLevel, pm::OptSize)> {
-        use crate::OptimisationLevel::*;
+        use self::OptimisationLevel::*;
         match self {
             Off => None,
             Low => Some((pm::OptLevel::Low, pm::OptSize::Off)),
@@ -73,3 +763,326 @@ impl OptimisationLevel {
         }
     }
 }
+
+impl FromStr for EmitKind {
+    type Err = String;
+
+    /// Parse an Emit Kind
+    ///
+    /// Accepts the short names used on the command line: `exe`,
+    /// `ir`, `bc`, `obj`, and `asm`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exe" => Ok(EmitKind::Executable),
+            "ir" | "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "bc" => Ok(EmitKind::Bitcode),
+            "obj" => Ok(EmitKind::Object),
+            "asm" => Ok(EmitKind::Assembly),
+            _ => Err(format!("unknown emit kind '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for RelocModel {
+    type Err = String;
+
+    /// Parse a Relocation Model
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(RelocModel::Default),
+            "static" => Ok(RelocModel::Static),
+            "pic" => Ok(RelocModel::Pic),
+            "dynamic-no-pic" => Ok(RelocModel::DynamicNoPic),
+            _ => Err(format!("unknown relocation model '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for WarningLevel {
+    type Err = String;
+
+    /// Parse a Warning Level
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(WarningLevel::Allow),
+            "warn" => Ok(WarningLevel::Warn),
+            "deny" => Ok(WarningLevel::Deny),
+            _ => Err(format!("unknown warning level '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for CRuntime {
+    type Err = String;
+
+    /// Parse a C Runtime Linkage
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(CRuntime::Default),
+            "static" => Ok(CRuntime::Static),
+            "static-pie" => Ok(CRuntime::StaticPie),
+            _ => Err(format!("unknown C runtime '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for GcStrategy {
+    type Err = String;
+
+    /// Parse a Garbage Collection Strategy
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rc" => Ok(GcStrategy::Rc),
+            "boehm" => Ok(GcStrategy::Boehm),
+            "none" => Ok(GcStrategy::None),
+            _ => Err(format!("unknown garbage collector '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for StackProtector {
+    type Err = String;
+
+    /// Parse a Stack Protector Level
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(StackProtector::None),
+            "basic" => Ok(StackProtector::Basic),
+            "strong" => Ok(StackProtector::Strong),
+            "all" => Ok(StackProtector::All),
+            _ => Err(format!("unknown stack protector '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for Sanitizers {
+    type Err = String;
+
+    /// Parse a List of Sanitizers
+    ///
+    /// Accepts a comma separated list of sanitizer names, e.g.
+    /// `address,undefined`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sanitizers = Sanitizers::default();
+        for name in s.split(',') {
+            match name.trim() {
+                "address" => sanitizers.address = true,
+                "undefined" => sanitizers.undefined = true,
+                name => return Err(format!("unknown sanitizer '{}'", name)),
+            }
+        }
+        Ok(sanitizers)
+    }
+}
+
+impl From<OptimisationLevel> for CodeGenOptLevel {
+    fn from(level: OptimisationLevel) -> Self {
+        match level {
+            OptimisationLevel::Off => CodeGenOptLevel::None,
+            OptimisationLevel::Low => CodeGenOptLevel::Less,
+            OptimisationLevel::Med | OptimisationLevel::Size => CodeGenOptLevel::Default,
+            OptimisationLevel::High => CodeGenOptLevel::Aggressive,
+        }
+    }
+}
+
+impl From<RelocModel> for RelocMode {
+    fn from(model: RelocModel) -> Self {
+        match model {
+            RelocModel::Default => RelocMode::Default,
+            RelocModel::Static => RelocMode::Static,
+            RelocModel::Pic => RelocMode::Pic,
+            RelocModel::DynamicNoPic => RelocMode::DynamicNoPic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builder_sets_options() {
+        let opts = CompilationOptions::default()
+            .with_dump_ir(true)
+            .with_debug_info(true)
+            .with_target_cpu("generic")
+            .with_target_features("+sse2")
+            .with_reloc_model(RelocModel::Pic)
+            .with_linker("ld.lld")
+            .with_linker_args(vec!["-lm"])
+            .with_linker_args(vec!["-s"])
+            .with_emit_kind(EmitKind::Object)
+            .with_warning_level(WarningLevel::Deny)
+            .with_overflow_checks(true)
+            .with_module_name("demo");
+
+        assert!(opts.dump_ir);
+        assert!(opts.debug_info);
+        assert_eq!(Some("generic".into()), opts.target_cpu);
+        assert_eq!(Some("+sse2".into()), opts.target_features);
+        assert_eq!(RelocModel::Pic, opts.reloc_model);
+        assert_eq!("ld.lld", opts.linker());
+        assert_eq!(vec!["-lm", "-s"], opts.linker_args);
+        assert_eq!(EmitKind::Object, opts.emit_kind);
+        assert_eq!(WarningLevel::Deny, opts.warning_level);
+        assert!(opts.overflow_checks);
+        assert_eq!(Some("demo".into()), opts.module_name);
+    }
+
+    #[test]
+    fn default_options() {
+        let opts = CompilationOptions::default();
+        assert_eq!("clang", opts.linker());
+        assert_eq!(EmitKind::Executable, opts.emit_kind);
+        assert_eq!(RelocModel::Default, opts.reloc_model);
+        assert_eq!(WarningLevel::Warn, opts.warning_level);
+        assert!(!opts.overflow_checks);
+        assert_eq!(None, opts.module_name);
+    }
+
+    #[test]
+    fn parse_emit_kinds() {
+        assert_eq!(Ok(EmitKind::Executable), "exe".parse());
+        assert_eq!(Ok(EmitKind::LlvmIr), "ir".parse());
+        assert_eq!(Ok(EmitKind::Bitcode), "bc".parse());
+        assert_eq!(Ok(EmitKind::Object), "obj".parse());
+        assert_eq!(Ok(EmitKind::Assembly), "asm".parse());
+        assert!("elf".parse::<EmitKind>().is_err());
+    }
+
+    #[test]
+    fn codegen_flags_skip_defaults() {
+        let opts = CompilationOptions::default();
+        assert_eq!(vec!["--edition=2026"], opts.codegen_flags());
+
+        let opts = CompilationOptions::default()
+            .with_opt_level(OptimisationLevel::Size)
+            .with_debug_info(true)
+            .with_reloc_model(RelocModel::Pic)
+            .with_sanitizers(Sanitizers {
+                address: true,
+                undefined: true,
+            })
+            .with_gc(GcStrategy::Boehm)
+            .with_stack_protector(StackProtector::Strong)
+            .with_linker("ld.lld")
+            .with_save_temps(true)
+            .with_warning_level(WarningLevel::Deny);
+        assert_eq!(
+            vec![
+                "-Os",
+                "-g",
+                "--reloc-model=pic",
+                "--edition=2026",
+                "--sanitize=address,undefined",
+                "--gc=boehm",
+                "--stack-protector=strong",
+            ],
+            opts.codegen_flags()
+        );
+    }
+
+    #[test]
+    fn linker_args_for_cross_builds() {
+        let opts = CompilationOptions::default()
+            .with_sysroot("/opt/sysroots/aarch64")
+            .with_c_runtime("static".parse().unwrap())
+            .with_sanitizers("address".parse().unwrap())
+            .with_linker_args(vec!["-lm"]);
+        assert_eq!(
+            vec![
+                "--sysroot=/opt/sysroots/aarch64",
+                "-static",
+                "-fsanitize=address",
+                "-lm"
+            ],
+            opts.all_linker_args()
+        );
+        assert!(CompilationOptions::default().all_linker_args().is_empty());
+        assert_eq!(Ok(CRuntime::StaticPie), "static-pie".parse());
+        assert!("glibc".parse::<CRuntime>().is_err());
+    }
+
+    #[test]
+    fn parse_gc_strategies() {
+        assert_eq!(GcStrategy::Rc, CompilationOptions::default().gc);
+        assert_eq!(Ok(GcStrategy::Boehm), "boehm".parse());
+        assert_eq!(Ok(GcStrategy::None), "none".parse());
+        assert!("arc".parse::<GcStrategy>().is_err());
+
+        let boehm = CompilationOptions::default().with_gc(GcStrategy::Boehm);
+        assert!(!boehm.gc.is_reference_counted());
+        assert_eq!(vec!["-lgc"], boehm.all_linker_args());
+        assert!(GcStrategy::Rc.is_reference_counted());
+        assert!(GcStrategy::None.linker_args().is_empty());
+    }
+
+    #[test]
+    fn parse_stack_protectors() {
+        assert_eq!(
+            StackProtector::None,
+            CompilationOptions::default().stack_protector
+        );
+        assert_eq!(Ok(StackProtector::Basic), "basic".parse());
+        assert_eq!(Ok(StackProtector::Strong), "strong".parse());
+        assert_eq!(Ok(StackProtector::All), "all".parse());
+        assert!("some".parse::<StackProtector>().is_err());
+        assert_eq!(None, StackProtector::None.attribute());
+        assert_eq!(
+            Some(Attribute::StackProtectReq),
+            StackProtector::All.attribute()
+        );
+    }
+
+    #[test]
+    fn emit_kind_from_extension() {
+        let kind = |p: &str| EmitKind::from_extension(Path::new(p));
+        assert_eq!(Some(EmitKind::LlvmIr), kind("out/foo.ll"));
+        assert_eq!(Some(EmitKind::Bitcode), kind("foo.bc"));
+        assert_eq!(Some(EmitKind::Object), kind("foo.o"));
+        assert_eq!(Some(EmitKind::Assembly), kind("foo.s"));
+        assert_eq!(None, kind("foo"));
+        assert_eq!(None, kind("foo.exe"));
+        assert_eq!(None, kind(".ll"));
+    }
+
+    #[test]
+    fn temps_dir_for_output() {
+        let opts = CompilationOptions::default();
+        assert_eq!(None, opts.temps_dir_for(Path::new("out/prog")));
+
+        let opts = opts.with_save_temps(true);
+        assert_eq!(
+            Some(PathBuf::from("out")),
+            opts.temps_dir_for(Path::new("out/prog"))
+        );
+        assert_eq!(
+            Some(PathBuf::from(".")),
+            opts.temps_dir_for(Path::new("prog"))
+        );
+
+        let opts = CompilationOptions::default().with_temps_dir("/tmp/build");
+        assert!(opts.save_temps);
+        assert_eq!(
+            Some(PathBuf::from("/tmp/build")),
+            opts.temps_dir_for(Path::new("out/prog"))
+        );
+    }
+
+    #[test]
+    fn parse_sanitizers() {
+        let both = Sanitizers {
+            address: true,
+            undefined: true,
+        };
+        assert_eq!(Ok(both), "address,undefined".parse());
+        assert_eq!(Ok(both), "undefined, address".parse());
+        let address: Sanitizers = "address".parse().unwrap();
+        assert!(address.address && !address.undefined);
+        assert_eq!(vec!["-fsanitize=address"], address.linker_args());
+        assert!(Sanitizers::default().linker_args().is_empty());
+        assert!("thread".parse::<Sanitizers>().is_err());
+        assert!("".parse::<Sanitizers>().is_err());
+    }
+}
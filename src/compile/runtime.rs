@@ -0,0 +1,275 @@
+//! Runtime Support Library
+//!
+//! Compiled programs call into the `ullage_rt` library for anything
+//! more involved than a handful of instructions, such as allocating
+//! strings or printing. The library is written in C, in `rt/`, and
+//! built by the crate's build script for the compiler's own target.
+//!
+//! The library's archive is embedded in the compiler and written out
+//! alongside the object file when linking an executable. The library
+//! is also linked into the compiler itself, so the JIT can resolve
+//! calls to it.
+
+use super::lower_context::LowerContext;
+use super::options::GcStrategy;
+use crate::low_loader::prelude::*;
+use crate::sem::{BuiltinType, CfgSet, Typ};
+use std::ffi::c_void;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The Runtime Library's Archive
+const ARCHIVE: &[u8] = include_bytes!(env!("ULLAGE_RT_ARCHIVE"));
+
+/// The Runtime Library's Archive, Allocating From the Boehm Collector
+///
+/// The same library built with `malloc` and friends swapped for the
+/// collector's allocator. It isn't linked into the compiler, so the
+/// JIT always uses the plain library.
+const BOEHM_ARCHIVE: &[u8] = include_bytes!(env!("ULLAGE_RT_BOEHM_ARCHIVE"));
+
+/// The Target the Runtime Library was Built For
+const TARGET: &str = env!("ULLAGE_RT_TARGET");
+
+/// The File Name of the Runtime Library's Archive
+pub(crate) const ARCHIVE_NAME: &str = "libullage_rt.a";
+
+extern "C" {
+    fn ullage_rt_string_alloc(len: i32) -> *mut c_void;
+    fn ullage_rt_string_retain(s: *mut c_void);
+    fn ullage_rt_string_release(s: *mut c_void);
+    fn ullage_rt_string_char_count(s: *const c_void) -> i64;
+    fn ullage_rt_string_char_at(s: *const c_void, index: i64) -> *mut c_void;
+    fn ullage_rt_builder_new() -> *mut c_void;
+    fn ullage_rt_builder_retain(b: *mut c_void);
+    fn ullage_rt_builder_release(b: *mut c_void);
+    fn ullage_rt_builder_append(b: *mut c_void, s: *const c_void);
+    fn ullage_rt_builder_to_string(b: *const c_void) -> *mut c_void;
+    fn ullage_rt_array_grow(
+        elements: *mut c_void,
+        elem_size: i64,
+        capacity: *mut i64,
+        needed: i64,
+    ) -> *mut c_void;
+    fn ullage_rt_panic(message: *const u8, len: i64) -> !;
+    fn ullage_rt_print(s: *const c_void);
+    fn ullage_rt_number_to_string(n: i64) -> *mut c_void;
+    fn ullage_rt_string_to_number(s: *const c_void) -> i64;
+}
+
+/// Runtime Function
+///
+/// The functions provided by the runtime library.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum RuntimeFn {
+    /// Allocate a `String` with room for a given number of bytes
+    StringAlloc,
+    /// Add a reference to a `String`
+    StringRetain,
+    /// Drop a reference to a `String`, freeing it if it was the last
+    StringRelease,
+    /// Count the Unicode scalar values in a `String`
+    StringCharCount,
+    /// Get the Unicode scalar value at an index in a `String`, as a
+    /// new `String`
+    StringCharAt,
+    /// Create an empty `StringBuilder`
+    BuilderNew,
+    /// Add a reference to a `StringBuilder`
+    BuilderRetain,
+    /// Drop a reference to a `StringBuilder`, freeing it if it was
+    /// the last
+    BuilderRelease,
+    /// Append a `String` to a `StringBuilder`
+    BuilderAppend,
+    /// Copy the contents of a `StringBuilder` into a new `String`
+    BuilderToString,
+    /// Grow an array's storage to hold at least a given number of
+    /// elements
+    ArrayGrow,
+    /// Write a message to standard error and abort
+    Panic,
+    /// Write a `String` and a newline to standard output
+    Print,
+    /// Format a `Number` as a new `String`
+    NumberToString,
+    /// Parse a `Number` from the start of a `String`
+    StringToNumber,
+}
+
+impl RuntimeFn {
+    /// Every Runtime Function
+    pub const ALL: [RuntimeFn; 15] = [
+        RuntimeFn::StringAlloc,
+        RuntimeFn::StringRetain,
+        RuntimeFn::StringRelease,
+        RuntimeFn::StringCharCount,
+        RuntimeFn::StringCharAt,
+        RuntimeFn::BuilderNew,
+        RuntimeFn::BuilderRetain,
+        RuntimeFn::BuilderRelease,
+        RuntimeFn::BuilderAppend,
+        RuntimeFn::BuilderToString,
+        RuntimeFn::ArrayGrow,
+        RuntimeFn::Panic,
+        RuntimeFn::Print,
+        RuntimeFn::NumberToString,
+        RuntimeFn::StringToNumber,
+    ];
+
+    /// Get the Function's Symbol Name
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeFn::StringAlloc => "ullage_rt_string_alloc",
+            RuntimeFn::StringRetain => "ullage_rt_string_retain",
+            RuntimeFn::StringRelease => "ullage_rt_string_release",
+            RuntimeFn::StringCharCount => "ullage_rt_string_char_count",
+            RuntimeFn::StringCharAt => "ullage_rt_string_char_at",
+            RuntimeFn::BuilderNew => "ullage_rt_builder_new",
+            RuntimeFn::BuilderRetain => "ullage_rt_builder_retain",
+            RuntimeFn::BuilderRelease => "ullage_rt_builder_release",
+            RuntimeFn::BuilderAppend => "ullage_rt_builder_append",
+            RuntimeFn::BuilderToString => "ullage_rt_builder_to_string",
+            RuntimeFn::ArrayGrow => "ullage_rt_array_grow",
+            RuntimeFn::Panic => "ullage_rt_panic",
+            RuntimeFn::Print => "ullage_rt_print",
+            RuntimeFn::NumberToString => "ullage_rt_number_to_string",
+            RuntimeFn::StringToNumber => "ullage_rt_string_to_number",
+        }
+    }
+
+    /// Get the Function's Address in the Compiler
+    fn address(self) -> *mut c_void {
+        match self {
+            RuntimeFn::StringAlloc => ullage_rt_string_alloc as *mut c_void,
+            RuntimeFn::StringRetain => ullage_rt_string_retain as *mut c_void,
+            RuntimeFn::StringRelease => ullage_rt_string_release as *mut c_void,
+            RuntimeFn::StringCharCount => ullage_rt_string_char_count as *mut c_void,
+            RuntimeFn::StringCharAt => ullage_rt_string_char_at as *mut c_void,
+            RuntimeFn::BuilderNew => ullage_rt_builder_new as *mut c_void,
+            RuntimeFn::BuilderRetain => ullage_rt_builder_retain as *mut c_void,
+            RuntimeFn::BuilderRelease => ullage_rt_builder_release as *mut c_void,
+            RuntimeFn::BuilderAppend => ullage_rt_builder_append as *mut c_void,
+            RuntimeFn::BuilderToString => ullage_rt_builder_to_string as *mut c_void,
+            RuntimeFn::ArrayGrow => ullage_rt_array_grow as *mut c_void,
+            RuntimeFn::Panic => ullage_rt_panic as *mut c_void,
+            RuntimeFn::Print => ullage_rt_print as *mut c_void,
+            RuntimeFn::NumberToString => ullage_rt_number_to_string as *mut c_void,
+            RuntimeFn::StringToNumber => ullage_rt_string_to_number as *mut c_void,
+        }
+    }
+
+    /// Declare the Function
+    ///
+    /// Returns the function from the context's module, adding a
+    /// declaration for it if there isn't one already.
+    pub fn declare(self, ctx: &mut LowerContext<'_>) -> Function {
+        if let Some(fun) = ctx.module.find_function(self.name()) {
+            return fun;
+        }
+        let string_type = ctx
+            .llvm_type(Typ::Builtin(BuiltinType::String))
+            .expect("no type in context for runtime strings");
+        let builder_type = ctx
+            .llvm_type(Typ::Builtin(BuiltinType::StringBuilder))
+            .expect("no type in context for runtime string builders");
+        let number_type = ctx.llvm_ctx.int_type(64);
+        let cstr_type = ctx.llvm_ctx.cstr_type();
+        let void_type = ctx.llvm_ctx.void_type();
+        let (ret, mut params) = match self {
+            RuntimeFn::StringAlloc => (string_type, vec![ctx.llvm_ctx.int_type(32)]),
+            RuntimeFn::StringRetain | RuntimeFn::StringRelease => (void_type, vec![string_type]),
+            RuntimeFn::StringCharCount => (number_type, vec![string_type]),
+            RuntimeFn::StringCharAt => (string_type, vec![string_type, number_type]),
+            RuntimeFn::BuilderNew => (builder_type, vec![]),
+            RuntimeFn::BuilderRetain | RuntimeFn::BuilderRelease => (void_type, vec![builder_type]),
+            RuntimeFn::BuilderAppend => (void_type, vec![builder_type, string_type]),
+            RuntimeFn::BuilderToString => (string_type, vec![builder_type]),
+            RuntimeFn::ArrayGrow => (
+                cstr_type,
+                vec![
+                    cstr_type,
+                    number_type,
+                    unsafe { ctx.llvm_ctx.pointer_type(number_type) },
+                    number_type,
+                ],
+            ),
+            RuntimeFn::Panic => (void_type, vec![cstr_type, number_type]),
+            RuntimeFn::Print => (void_type, vec![string_type]),
+            RuntimeFn::NumberToString => (string_type, vec![number_type]),
+            RuntimeFn::StringToNumber => (number_type, vec![string_type]),
+        };
+        let mut fun = unsafe {
+            ctx.llvm_ctx
+                .add_function(ctx.module, self.name(), ret, &mut params)
+        };
+        fun.set_calling_convention(CallConvention::CDecl);
+        fun
+    }
+}
+
+/// Is the Runtime Library Available for a Target?
+///
+/// The library is only built for the compiler's own target. Triples
+/// are compared by architecture and OS, as LLVM and Rust don't always
+/// agree on the rest of the triple.
+pub(crate) fn is_available_for(triple: &str) -> bool {
+    let built = CfgSet::for_target(TARGET);
+    let wanted = CfgSet::for_target(triple);
+    ["target_arch", "target_os"]
+        .iter()
+        .all(|key| built.value(key) == wanted.value(key))
+}
+
+/// Write the Runtime Library's Archive
+///
+/// Writes the archive built for the garbage collection strategy to
+/// `path` so it can be passed to the linker.
+pub(crate) fn write_archive(path: &Path, gc: GcStrategy) -> io::Result<()> {
+    match gc {
+        GcStrategy::Boehm => fs::write(path, BOEHM_ARCHIVE),
+        GcStrategy::Rc | GcStrategy::None => fs::write(path, ARCHIVE),
+    }
+}
+
+/// Add the Runtime Library to a JIT
+///
+/// Code compiled by the JIT runs in process, so calls to the runtime
+/// are resolved to the copy linked into the compiler.
+pub(crate) fn add_to_jit(jit: &mut Jit) {
+    for function in RuntimeFn::ALL.iter() {
+        // The addresses are those of the declared functions, which
+        // live as long as the process.
+        unsafe { jit.add_symbol(function.name(), function.address()) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runtime_available_for_own_target() {
+        assert!(is_available_for(TARGET));
+        assert!(!ARCHIVE.is_empty());
+        assert!(!BOEHM_ARCHIVE.is_empty());
+        assert_ne!(ARCHIVE, BOEHM_ARCHIVE);
+    }
+
+    #[test]
+    fn runtime_functions_declared_once() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("runtime");
+        let mut lower_ctx = LowerContext::new(&mut ctx, &mut module);
+        lower_ctx.add_core_types();
+        for function in RuntimeFn::ALL.iter() {
+            let declared = function.declare(&mut lower_ctx);
+            let again = function.declare(&mut lower_ctx);
+            assert_eq!(declared.name(), again.name());
+            assert_eq!(function.name(), declared.name());
+        }
+        let ir = module.to_ir_string();
+        assert_eq!(RuntimeFn::ALL.len(), ir.matches("declare").count());
+    }
+}
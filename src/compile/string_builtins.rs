@@ -17,24 +17,19 @@ pub(crate) fn string_copy_guts(
     len: LLVMValueRef,
     offset: Option<LLVMValueRef>,
 ) {
-    let memcpy = ctx
-        .module
-        .find_function("llvm.memcpy.p0i8.p0i8.i32")
-        .expect("can't find memcpy intrinsic");
-
     let src_buffer = string_get_buffer(builder, src);
-    let src_buffer = builder.build_gep(
-        src_buffer,
-        &mut [ctx.llvm_ctx.const_int(0), ctx.llvm_ctx.const_int(0)],
-    );
+    let src_buffer = unsafe {
+        builder.build_gep(
+            src_buffer,
+            &mut [ctx.llvm_ctx.const_int(0), ctx.llvm_ctx.const_int(0)],
+        )
+    };
     let dest_buffer = string_get_buffer(builder, dest);
     let offset = offset.unwrap_or_else(|| ctx.llvm_ctx.const_int(0));
-    let dest_buffer = builder.build_gep(dest_buffer, &mut [ctx.llvm_ctx.const_int(0), offset]);
+    let dest_buffer =
+        unsafe { builder.build_gep(dest_buffer, &mut [ctx.llvm_ctx.const_int(0), offset]) };
 
-    builder.build_void_call(
-        &memcpy,
-        &mut [dest_buffer, src_buffer, len, ctx.llvm_ctx.const_bool(false)],
-    );
+    unsafe { builder.build_memcpy(dest_buffer, src_buffer, len) };
 }
 
 /// Get String's Buffer Pointer
@@ -43,7 +38,7 @@ pub(crate) fn string_copy_guts(
 /// body. This is a `[0 x i8]*`. It can be converted into a poitner to
 /// a given character offset with a GEP instruction]
 pub(crate) fn string_get_buffer(builder: &mut Builder, s: LLVMValueRef) -> LLVMValueRef {
-    builder.build_struct_gep(s, 1)
+    unsafe { builder.build_struct_gep(s, 2) }
 }
 
 /// Get String Length
@@ -52,14 +47,6 @@ pub(crate) fn string_get_buffer(builder: &mut Builder, s: LLVMValueRef) -> LLVMV
 /// representation. This is a constant-time operation and returns the
 /// length in bytes.
 pub(crate) fn string_get_len(builder: &mut Builder, val: LLVMValueRef) -> LLVMValueRef {
-    let len_field = builder.build_struct_gep(val, 0);
-    builder.build_load(len_field)
-}
-
-/// Set Stirng Length
-///
-/// Set the internal string length field to a new value.
-pub(crate) fn string_set_len(builder: &mut Builder, val: LLVMValueRef, size: LLVMValueRef) {
-    let len_field = builder.build_struct_gep(val, 0);
-    builder.build_store(size, len_field);
+    let len_field = unsafe { builder.build_struct_gep(val, 0) };
+    unsafe { builder.build_load(len_field) }
 }
@@ -0,0 +1,208 @@
+//! In-Language Test Runner
+//!
+//! Finds the test functions declared in a program and runs them in
+//! process with the JIT. A test function is any function whose name
+//! starts with `test_`. Tests take no parameters and return a `Bool`
+//! which is `true` if the test passed.
+
+use super::runtime;
+use super::{CompError, CompResult, Compilation};
+use crate::low_loader::prelude::*;
+use crate::sem::{BuiltinType, Expression, ExpressionKind, Typ};
+
+/// The Prefix which Marks a Function as a Test
+pub const TEST_PREFIX: &str = "test_";
+
+/// Test Outcome
+///
+/// The result of running a single test function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestOutcome {
+    /// The test returned `true`
+    Passed,
+    /// The test returned `false`
+    Failed,
+    /// The function's signature isn't that of a test, so it wasn't
+    /// run. Tests must take no parameters and return `Bool`.
+    Invalid,
+}
+
+/// Test Result
+///
+/// Pairs a test function's name with its outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// The name of the test function
+    pub name: String,
+    /// What happened when it was run
+    pub outcome: TestOutcome,
+}
+
+impl Compilation {
+    /// Run the Tests
+    ///
+    /// Compiles the program into a JIT and calls each of its test
+    /// functions in the order they are declared. The program's top
+    /// level statements are not run. A test which aborts, for example
+    /// because of an overflow check, aborts the whole process.
+    ///
+    /// The AddressSanitizer runtime can't be loaded into the JIT, so
+    /// tests aren't instrumented with it.
+    ///
+    /// # Errors
+    ///
+    /// If the program can't be lowered, or the JIT can't be created
+    /// for the host, then the compilation error is returned.
+    pub fn run_tests(mut self) -> CompResult<Vec<TestResult>> {
+        self.options.sanitizers.address = false;
+        let mut tests = Vec::new();
+        find_tests(&self.expr, &mut tests);
+
+        // Code generated for the JIT is run in process, so it must be
+        // generated for the host.
+        let target = Target::default();
+        let machine = self.create_target_machine(&target)?;
+        let mut jit = Jit::new(Context::new()).map_err(CompError::Generic)?;
+        runtime::add_to_jit(&mut jit);
+        let mut module = self.build_module(jit.context(), &machine, "tests")?;
+        for (idx, test) in tests.iter().enumerate() {
+            if test.outcome != TestOutcome::Invalid {
+                add_test_shim(jit.context(), &mut module, &test.name, idx);
+            }
+        }
+        jit.add_module(module).map_err(CompError::Generic)?;
+
+        for (idx, test) in tests.iter_mut().enumerate() {
+            if test.outcome == TestOutcome::Invalid {
+                continue;
+            }
+            // The shim is generated with a C calling convention, and
+            // returns `1` if the test passed.
+            let shim: extern "C" fn() -> i64 = unsafe { jit.lookup(&shim_name(idx)) }
+                .ok_or_else(|| CompError::from(format!("missing test shim for '{}'", test.name)))?;
+            if shim() == 0 {
+                test.outcome = TestOutcome::Failed;
+            }
+        }
+        Ok(tests)
+    }
+}
+
+/// Find the Test Functions in an Expression
+///
+/// Walks the same declarations as lowering does, collecting each
+/// function with the test prefix. Functions with a valid signature
+/// are assumed to pass until they are run.
+fn find_tests(expr: &Expression, tests: &mut Vec<TestResult>) {
+    match expr.kind {
+        ExpressionKind::Sequence(ref exprs) => {
+            for expr in exprs.iter() {
+                find_tests(expr, tests);
+            }
+        }
+        ExpressionKind::Function(ref fn_decl) if fn_decl.ident.starts_with(TEST_PREFIX) => {
+            let valid =
+                fn_decl.params.is_empty() && fn_decl.ret_ty == Typ::Builtin(BuiltinType::Bool);
+            tests.push(TestResult {
+                name: fn_decl.ident.clone(),
+                outcome: if valid {
+                    TestOutcome::Passed
+                } else {
+                    TestOutcome::Invalid
+                },
+            });
+        }
+        _ => (),
+    }
+}
+
+/// Get the Name of the Shim for a Test
+fn shim_name(idx: usize) -> String {
+    format!("__ullage_test_shim_{}", idx)
+}
+
+/// Add a Test Shim to the Module
+///
+/// Ullage functions use their own calling convention, so each test
+/// is wrapped in a C function which calls it and widens its result to
+/// a `Number`.
+fn add_test_shim(ctx: &mut Context, module: &mut Module, name: &str, idx: usize) {
+    let test = module
+        .find_function(name)
+        .unwrap_or_else(|| panic!("missing test function '{}'", name));
+    let int_type = ctx.int_type(64);
+    let mut shim = unsafe { ctx.add_function(module, &shim_name(idx), int_type, &mut []) };
+    shim.set_calling_convention(CallConvention::CDecl);
+    let entry = ctx.add_block(&mut shim, "entry");
+    let passed = ctx.add_block(&mut shim, "passed");
+    let failed = ctx.add_block(&mut shim, "failed");
+
+    let mut builder = ctx.add_builder();
+    unsafe { builder.position_at_end(entry) };
+    let result = unsafe { builder.build_call(&test, &mut []) };
+    unsafe {
+        builder.build_cond_br(result, passed, failed);
+        builder.position_at_end(passed);
+        builder.build_ret(ctx.const_int(1));
+    }
+
+    let mut builder = ctx.add_builder();
+    unsafe {
+        builder.position_at_end(failed);
+        builder.build_ret(ctx.const_int(0));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile::CompilationOptions;
+    use crate::syntax::{text::SourceText, SyntaxTree};
+
+    fn run(src: &str) -> Vec<(String, TestOutcome)> {
+        let source = SourceText::new(src);
+        let tree = SyntaxTree::parse(&source);
+        let comp = Compilation::new(tree, CompilationOptions::default()).unwrap();
+        comp.run_tests()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.name, t.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn run_passing_and_failing_tests() {
+        let results = run("fn add(a: Number, b: Number): Number a + b end
+             fn test_add(): Bool add(1, 2) == 3 end
+             fn test_wrong(): Bool add(1, 2) == 4 end
+             fn helper(): Bool false end
+             print 'not run'");
+        assert_eq!(
+            vec![
+                ("test_add".to_string(), TestOutcome::Passed),
+                ("test_wrong".to_string(), TestOutcome::Failed),
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn tests_with_bad_signatures_are_invalid() {
+        let results = run("fn test_params(n: Number): Bool n > 0 end
+             fn test_number(): Number 1 end
+             fn test_ok(): Bool true end");
+        assert_eq!(
+            vec![
+                ("test_params".to_string(), TestOutcome::Invalid),
+                ("test_number".to_string(), TestOutcome::Invalid),
+                ("test_ok".to_string(), TestOutcome::Passed),
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn program_without_tests() {
+        assert!(run("print 1").is_empty());
+    }
+}
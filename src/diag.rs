@@ -7,16 +7,108 @@
 //! The types in this module will usually be consumed by pushing
 //! diagnostics into a `DiagnosticBag`.
 
-use crate::syntax::text::Span;
+mod code;
+
+pub use self::code::Code;
+
+use crate::syntax::text::{SourceText, Span, DUMMY_SPAN};
+use std::fmt::Write;
 
 /// The main `Daignostic` type. Each diagnostic consists of a primary
 /// message, location, and level.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Diagnostic {
     /// The diagnostic message generated by the compiler
     pub message: String,
 
     /// The location that this diagnostic occured at
     pub span: Span,
+
+    /// The kind of diagnostic, if it has been assigned a code
+    pub code: Option<Code>,
+
+    /// Secondary locations related to the diagnostic
+    pub labels: Vec<Label>,
+
+    /// Extra information which doesn't belong to any location
+    pub notes: Vec<String>,
+
+    /// A suggested edit which would resolve the diagnostic
+    pub fix: Option<Fix>,
+
+    /// Is the diagnostic only informational?
+    ///
+    /// Notes are always rendered at the `note` level, whichever level
+    /// the diagnostics around them are reported at.
+    pub is_note: bool,
+}
+
+/// A Secondary Label
+///
+/// Points at a location which helps explain a diagnostic, such as
+/// the declaration which gave a value its expected type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// The location the label points at
+    pub span: Span,
+
+    /// The message shown alongside the location
+    pub message: String,
+}
+
+/// A Suggested Fix
+///
+/// Replacement text for a span of the source which should resolve
+/// the diagnostic. Insertions replace an empty span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// The location of the text to replace
+    pub span: Span,
+
+    /// The text to replace it with
+    pub replacement: String,
+
+    /// A description of the fix to show to the user
+    pub message: String,
+
+    /// How confident we are that the fix is what was meant
+    pub applicability: Applicability,
+}
+
+/// Fix Applicability
+///
+/// Only fixes which are `MachineApplicable` are applied by
+/// `ullage --fix`. The rest are shown but must be applied by hand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Applicability {
+    /// The fix is certainly what was meant
+    MachineApplicable,
+    /// The fix is a likely guess, but could change the meaning of the
+    /// code in ways the user didn't intend
+    MaybeIncorrect,
+}
+
+impl Fix {
+    /// Create a Fix
+    ///
+    /// The fix replaces the text at `span` with `replacement`.
+    pub fn new<R, M>(span: Span, replacement: R, message: M, applicability: Applicability) -> Self
+    where
+        R: Into<String>,
+        M: Into<String>,
+    {
+        Fix {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+            applicability,
+        }
+    }
+
+    /// Check if the Fix can be Applied Automatically
+    pub fn is_machine_applicable(&self) -> bool {
+        self.applicability == Applicability::MachineApplicable
+    }
 }
 
 impl Diagnostic {
@@ -26,8 +118,8 @@ impl Diagnostic {
     /// # Parameters
     ///  * `message` - the diagnostic message to emit
     ///  * `span` - The locaiton the diagnostic is tied to. If there
-    ///             is no logical location for this diagnostic then
-    ///             `DUMMY_SPAN` should be used.
+    ///    is no logical location for this diagnostic then
+    ///    `DUMMY_SPAN` should be used.
     pub fn new<S>(message: S, span: Span) -> Self
     where
         S: Into<String>,
@@ -35,6 +127,353 @@ impl Diagnostic {
         Diagnostic {
             message: message.into(),
             span,
+            code: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            fix: None,
+            is_note: false,
+        }
+    }
+
+    /// Set the Diagnostic's Code
+    pub fn with_code(self, code: Code) -> Self {
+        Diagnostic {
+            code: Some(code),
+            ..self
+        }
+    }
+
+    /// Add a Secondary Label
+    ///
+    /// Labels without a location are ignored.
+    pub fn with_label<S: Into<String>>(mut self, span: Span, message: S) -> Self {
+        if span != DUMMY_SPAN {
+            self.labels.push(Label {
+                span,
+                message: message.into(),
+            });
+        }
+        self
+    }
+
+    /// Add a Note
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Set the Diagnostic's Suggested Fix
+    pub fn with_fix(self, fix: Fix) -> Self {
+        Diagnostic {
+            fix: Some(fix),
+            ..self
+        }
+    }
+
+    /// Mark the Diagnostic as a Note
+    pub fn as_note(self) -> Self {
+        Diagnostic {
+            is_note: true,
+            ..self
+        }
+    }
+
+    /// Render the Diagnostic
+    ///
+    /// Formats the diagnostic for display at the given level, such as
+    /// `error` or `warning`. The first line holds the file name,
+    /// position, and message. If the diagnostic has a location the
+    /// source lines it covers follow, with the span underlined by
+    /// carets. Each label is shown in the same way, underlined with
+    /// dashes, followed by the notes and the suggested fix. The code of the diagnostic,
+    /// if it has one, follows the message. Notes are always rendered
+    /// at the `note` level.
+    pub fn render(&self, source: &SourceText, level: &str) -> String {
+        let level = if self.is_note { "note" } else { level };
+        let message = match self.code {
+            Some(code) => format!("{} [{}]", self.message, code),
+            None => self.message.clone(),
+        };
+        let mut last_line = None;
+        let mut rendered = if self.span == DUMMY_SPAN {
+            format!("{}:{}: {}\n", source.name(), level, message)
+        } else {
+            let (line, col) = source.position(self.span);
+            let mut rendered = format!(
+                "{}:{}:{}:{}: {}\n",
+                source.name(),
+                line,
+                col,
+                level,
+                message
+            );
+            render_snippet(&mut rendered, &mut last_line, source, self.span, '^', None);
+            rendered
+        };
+        for label in &self.labels {
+            render_snippet(
+                &mut rendered,
+                &mut last_line,
+                source,
+                label.span,
+                '-',
+                Some(&label.message),
+            );
+        }
+        for note in &self.notes {
+            let _ = writeln!(rendered, "     = note: {}", note);
+        }
+        if let Some(fix) = &self.fix {
+            let _ = writeln!(rendered, "     = help: {}", fix.message);
+        }
+        rendered
+    }
+}
+
+/// Sort Diagnostics into Source Order
+///
+/// Orders diagnostics by where they start, so that they read from
+/// the top of the file to the bottom. Diagnostics which start at the
+/// same position keep the order they were raised in, which keeps
+/// related errors together. Exact duplicates, such as those raised
+/// when part of the tree is bound more than once, are removed.
+pub fn sort_diagnostics(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut sorted: Vec<Diagnostic> = Vec::with_capacity(diagnostics.len());
+    for diagnostic in diagnostics {
+        if !sorted.contains(&diagnostic) {
+            sorted.push(diagnostic);
+        }
+    }
+    sorted.sort_by_key(|d| d.span.start());
+    sorted
+}
+
+/// Summarise Diagnostics
+///
+/// Describes diagnostics which aren't going to be shown in full,
+/// such as those past an error limit, by counting how many there
+/// are with each code. Codes are listed in order, with diagnostics
+/// which have no code last.
+pub fn summarise(diagnostics: &[Diagnostic], level: &str) -> String {
+    let mut counts: Vec<(Option<Code>, usize)> = Vec::new();
+    for diagnostic in diagnostics {
+        match counts.iter_mut().find(|(code, _)| *code == diagnostic.code) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((diagnostic.code, 1)),
+        }
+    }
+    counts.sort_by_key(|(code, _)| code.map_or(usize::MAX, |c| c as usize));
+
+    let plural = if diagnostics.len() == 1 { "" } else { "s" };
+    let mut summary = format!("{} more {}{} not shown", diagnostics.len(), level, plural);
+    let breakdown: Vec<_> = counts
+        .into_iter()
+        .map(|(code, count)| match code {
+            Some(code) => format!("{} {}", count, code),
+            None => format!("{} other", count),
+        })
+        .collect();
+    if !breakdown.is_empty() {
+        let _ = write!(summary, " ({})", breakdown.join(", "));
+    }
+    summary
+}
+
+/// Render the Source Lines Covered by a Span
+///
+/// Writes each line the span covers to `rendered`, underlining the
+/// span with `marker`. The label, if there is one, follows the
+/// underline on the last line. If the span starts on `last_line`,
+/// the line which was written last, then it isn't written again and
+/// the underline is added below the existing one.
+fn render_snippet(
+    rendered: &mut String,
+    last_line: &mut Option<usize>,
+    source: &SourceText,
+    span: Span,
+    marker: char,
+    label: Option<&str>,
+) {
+    let (line, col) = source.position(span);
+    let (end_line, end_col) = source.position(Span::new_at(span.end()));
+    // A span ending at the start of a line doesn't cover it, but
+    // does cover the whole of the line before.
+    let (end_line, end_col) = if end_line > line && end_col == 0 {
+        (end_line - 1, None)
+    } else {
+        (end_line, Some(end_col))
+    };
+    if *last_line != Some(line) {
+        rendered.push_str("     |\n");
+    }
+    for line_no in line..=end_line {
+        let text = match source.line_text(line_no) {
+            Some(text) => text,
+            None => continue,
+        };
+        if *last_line != Some(line_no) {
+            let _ = writeln!(rendered, "{:4} | {}", line_no, text);
         }
+        *last_line = Some(line_no);
+
+        let start = if line_no == line {
+            col
+        } else {
+            text.chars().take_while(|c| c.is_whitespace()).count()
+        };
+        let end = match end_col {
+            Some(end_col) if line_no == end_line => end_col,
+            _ => text.chars().count(),
+        };
+        // Tabs are kept in the padding so the underline lines up
+        // with the text above it.
+        let padding: String = text
+            .chars()
+            .take(start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline = marker.to_string().repeat(end.saturating_sub(start).max(1));
+        match label {
+            Some(label) if line_no == end_line => {
+                let _ = writeln!(rendered, "     | {}{} {}", padding, underline, label);
+            }
+            _ => {
+                let _ = writeln!(rendered, "     | {}{}", padding, underline);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::text::Pos;
+
+    fn render(text: &str, start: usize, end: usize) -> String {
+        let source = SourceText::with_name(text, "test.ulg");
+        Diagnostic::new("oops", Span::new(Pos::from(start), Pos::from(end)))
+            .render(&source, "error")
+    }
+
+    #[test]
+    fn render_without_location() {
+        let source = SourceText::with_name("print 1", "test.ulg");
+        assert_eq!(
+            "test.ulg:warning: oops\n",
+            Diagnostic::new("oops", DUMMY_SPAN).render(&source, "warning")
+        );
+        assert_eq!(
+            "test.ulg:error: oops [U0017]\n",
+            Diagnostic::new("oops", DUMMY_SPAN)
+                .with_code(Code::Unsupported)
+                .render(&source, "error")
+        );
+        assert_eq!(
+            "test.ulg:note: oops\n",
+            Diagnostic::new("oops", DUMMY_SPAN)
+                .as_note()
+                .render(&source, "warning")
+        );
+    }
+
+    #[test]
+    fn render_underlines_span() {
+        assert_eq!(
+            "test.ulg:2:6:error: oops\n     |\n   2 | print 'a' - 1\n     |       ^^^^^^^\n",
+            render("var a = 1\nprint 'a' - 1\n", 16, 23)
+        );
+        assert_eq!(
+            "test.ulg:1:3:error: oops\n     |\n   1 | \tb\tc\n     | \t \t^\n",
+            render("\tb\tc\n", 3, 3)
+        );
+    }
+
+    #[test]
+    fn render_multiline_span() {
+        assert_eq!(
+            "test.ulg:1:4:error: oops\n     |\n   1 | let x = (\n     |     ^^^^^\n   2 |   1)\n     |   ^^\n",
+            render("let x = (\n  1)\nprint x\n", 4, 14)
+        );
+        assert_eq!(
+            "test.ulg:1:0:error: oops\n     |\n   1 | print 1\n     | ^^^^^^^\n",
+            render("print 1\nprint 2\n", 0, 8)
+        );
+    }
+
+    #[test]
+    fn sort_into_source_order() {
+        let at = |start, end| Span::new(Pos::from(start), Pos::from(end));
+        let diagnostics = vec![
+            Diagnostic::new("c", at(10, 12)),
+            Diagnostic::new("a", at(2, 4)),
+            Diagnostic::new("b", at(2, 3)),
+            Diagnostic::new("c", at(10, 12)),
+            Diagnostic::new("a", at(2, 4)).with_code(Code::MismatchedTypes),
+            Diagnostic::new("global", DUMMY_SPAN),
+        ];
+        let sorted = sort_diagnostics(diagnostics);
+        assert_eq!(
+            vec![
+                ("global", None),
+                ("a", None),
+                ("b", None),
+                ("a", Some(Code::MismatchedTypes)),
+                ("c", None),
+            ],
+            sorted
+                .iter()
+                .map(|d| (d.message.as_str(), d.code))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn summarise_counts_codes() {
+        let diagnostics = vec![
+            Diagnostic::new("a", DUMMY_SPAN).with_code(Code::MismatchedTypes),
+            Diagnostic::new("b", DUMMY_SPAN),
+            Diagnostic::new("c", DUMMY_SPAN).with_code(Code::UndefinedName),
+            Diagnostic::new("d", DUMMY_SPAN).with_code(Code::MismatchedTypes),
+        ];
+        assert_eq!(
+            "4 more errors not shown (1 U0007, 2 U0013, 1 other)",
+            summarise(&diagnostics, "error")
+        );
+        assert_eq!(
+            "1 more warning not shown (1 U0007)",
+            summarise(&diagnostics[2..3], "warning")
+        );
+    }
+
+    #[test]
+    fn render_labels_on_the_same_line() {
+        let source = SourceText::with_name("let a: Bool = 1\n", "test.ulg");
+        let diagnostic = Diagnostic::new("mismatch", Span::new(Pos::from(4), Pos::from(5)))
+            .with_label(Span::new(Pos::from(7), Pos::from(11)), "expected")
+            .with_label(Span::new(Pos::from(14), Pos::from(15)), "found");
+        assert_eq!(
+            "test.ulg:1:4:error: mismatch\n     |\n   1 | let a: Bool = 1\n     |     ^\n     |        ---- expected\n     |               - found\n",
+            diagnostic.render(&source, "error")
+        );
+    }
+
+    #[test]
+    fn render_labels_and_notes() {
+        let source = SourceText::with_name("var a = 'a'\na = 1\n", "test.ulg");
+        let diagnostic = Diagnostic::new("mismatch", Span::new(Pos::from(14), Pos::from(15)))
+            .with_label(Span::new(Pos::from(4), Pos::from(5)), "declared here")
+            .with_label(DUMMY_SPAN, "ignored")
+            .with_note("types must match")
+            .with_fix(Fix::new(
+                Span::new(Pos::from(0), Pos::from(3)),
+                "let",
+                "declare with `let`",
+                Applicability::MaybeIncorrect,
+            ));
+        assert_eq!(1, diagnostic.labels.len());
+        assert_eq!(
+            "test.ulg:2:2:error: mismatch\n     |\n   2 | a = 1\n     |   ^\n     |\n   1 | var a = 'a'\n     |     - declared here\n     = note: types must match\n     = help: declare with `let`\n",
+            diagnostic.render(&source, "error")
+        );
     }
 }
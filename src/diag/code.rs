@@ -0,0 +1,554 @@
+//! Diagnostic Codes
+//!
+//! Each kind of diagnostic the parser and binder raise has a stable
+//! code. Codes are shown alongside the diagnostic's message, and
+//! `ullage --explain` prints the extended description of a code.
+//! Codes must never be reused or renumbered once they have been
+//! assigned.
+
+use std::fmt;
+
+/// Diagnostic Code
+///
+/// Identifies the kind of a diagnostic. Codes are displayed as a `U`
+/// followed by the four digit number of the code, e.g. `U0007`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Code {
+    /// A character which can't start any token
+    UnrecognisedCharacter = 1,
+    /// A token other than the one the grammar requires
+    UnexpectedToken = 2,
+    /// A token where an identifier is required
+    ExpectedIdentifier = 3,
+    /// A token where a type is required
+    ExpectedType = 4,
+    /// A token where an expression is required
+    ExpectedExpression = 5,
+    /// A declaration whose type can't be inferred
+    CannotInferType = 6,
+    /// A reference to a name which isn't in scope
+    UndefinedName = 7,
+    /// A reference to a type which isn't in scope
+    UndefinedType = 8,
+    /// A read of a variable which may not have been assigned
+    UninitialisedVariable = 9,
+    /// A type used where a value is expected
+    TypeUsedAsValue = 10,
+    /// An assignment to something which isn't a variable
+    InvalidAssignmentTarget = 11,
+    /// An assignment to an immutable variable
+    AssignToImmutable = 12,
+    /// A value whose type isn't the type required
+    MismatchedTypes = 13,
+    /// An operator applied to operands it doesn't accept
+    InvalidOperands = 14,
+    /// A call with the wrong number of arguments
+    WrongArgumentCount = 15,
+    /// A call of something which isn't a function
+    NotCallable = 16,
+    /// Use of a feature which isn't supported yet
+    Unsupported = 17,
+    /// A function parameter without a type
+    MissingParameterType = 18,
+    /// Two function parameters with the same name
+    DuplicateParameter = 19,
+    /// A function path which doesn't return the declared type
+    MissingReturn = 20,
+    /// Code which can never run
+    UnreachableCode = 21,
+    /// A `print` of a value which can't be printed
+    UnprintableValue = 22,
+    /// An immutable declaration without an initialiser
+    MissingInitialiser = 23,
+    /// A condition which isn't a `Bool`
+    InvalidCondition = 24,
+    /// A number literal which doesn't fit in a `Number`
+    NumberTooLarge = 25,
+    /// An attribute which is unknown or misplaced
+    InvalidAttribute = 26,
+    /// A macro is defined more than once, defined somewhere other
+    /// than the top level, or expands forever
+    InvalidMacro = 27,
+    /// A variable or parameter which is never used
+    UnusedVariable = 28,
+    /// A declaration which hides another variable of the same name
+    ShadowedVariable = 29,
+    /// A condition whose value is always the same
+    ConstantCondition = 30,
+    /// An entry point which is missing or has the wrong signature, or
+    /// top level code alongside an entry point
+    InvalidEntryPoint = 31,
+    /// A `break` or `continue` outside of a loop, or naming a label
+    /// which isn't on an enclosing loop
+    InvalidLoopControl = 32,
+}
+
+impl Code {
+    /// Every Diagnostic Code, in Order
+    pub const ALL: &'static [Code] = &[
+        Code::UnrecognisedCharacter,
+        Code::UnexpectedToken,
+        Code::ExpectedIdentifier,
+        Code::ExpectedType,
+        Code::ExpectedExpression,
+        Code::CannotInferType,
+        Code::UndefinedName,
+        Code::UndefinedType,
+        Code::UninitialisedVariable,
+        Code::TypeUsedAsValue,
+        Code::InvalidAssignmentTarget,
+        Code::AssignToImmutable,
+        Code::MismatchedTypes,
+        Code::InvalidOperands,
+        Code::WrongArgumentCount,
+        Code::NotCallable,
+        Code::Unsupported,
+        Code::MissingParameterType,
+        Code::DuplicateParameter,
+        Code::MissingReturn,
+        Code::UnreachableCode,
+        Code::UnprintableValue,
+        Code::MissingInitialiser,
+        Code::InvalidCondition,
+        Code::NumberTooLarge,
+        Code::InvalidAttribute,
+        Code::InvalidMacro,
+        Code::UnusedVariable,
+        Code::ShadowedVariable,
+        Code::ConstantCondition,
+        Code::InvalidEntryPoint,
+        Code::InvalidLoopControl,
+    ];
+
+    /// Look up a Code
+    ///
+    /// Parses a code as it is displayed, e.g. `U0007`. The leading
+    /// `U` is optional and may be lower case.
+    pub fn from_id(id: &str) -> Option<Code> {
+        let digits = id.trim_start_matches(['U', 'u']);
+        let number: usize = digits.parse().ok()?;
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|&code| code as usize == number)
+    }
+
+    /// Get the Code's Lint Name
+    ///
+    /// Warnings raised by lints have a name which can be used to
+    /// configure them, e.g. with an `#allow` pragma or `-W`. Errors
+    /// can't be allowed so have no name.
+    pub fn lint_name(self) -> Option<&'static str> {
+        match self {
+            Code::UnreachableCode => Some("unreachable_code"),
+            Code::UnusedVariable => Some("unused_variable"),
+            Code::ShadowedVariable => Some("shadowing"),
+            Code::ConstantCondition => Some("constant_condition"),
+            _ => None,
+        }
+    }
+
+    /// Look up a Code by its Lint Name
+    pub fn from_lint_name(name: &str) -> Option<Code> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|code| code.lint_name() == Some(name))
+    }
+
+    /// Get the Code's Explanation
+    ///
+    /// Returns an extended description of the diagnostic, with an
+    /// example of code which raises it.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Code::UnrecognisedCharacter => {
+                "The source contains a character which can't begin any token.
+
+Example:
+
+    let a = 1 $ 2
+
+Remove the character, or move it into a string literal."
+            }
+            Code::UnexpectedToken => {
+                "The parser found a token other than the one the grammar needs at this point.
+
+Example:
+
+    fn add(a: Number, b: Number): Number
+        a + b
+
+Here the function's body is missing its closing `end`."
+            }
+            Code::ExpectedIdentifier => {
+                "A name was required but some other token was found.
+
+Example:
+
+    let 1 = 2
+
+Declarations, parameters, and functions must be named with an identifier."
+            }
+            Code::ExpectedType => {
+                "A type was required but some other token was found.
+
+Example:
+
+    fn answer(): 42
+        42
+    end
+
+Type annotations must name a type, such as `Number`, `String`, or `Bool`."
+            }
+            Code::ExpectedExpression => {
+                "An expression was required but some other token, or the end of the
+file, was found.
+
+Example:
+
+    let a = * 2
+
+Every operator needs an expression for each of its operands."
+            }
+            Code::CannotInferType => {
+                "The type of a declaration couldn't be worked out from how it is used.
+
+Example:
+
+    var a
+    print 1
+
+Either add a type annotation to the declaration, or assign it a value."
+            }
+            Code::UndefinedName => {
+                "A name was used which isn't declared in any visible scope.
+
+Example:
+
+    let counter = 1
+    print countr
+
+Check the spelling of the name, and that it is declared before it is used."
+            }
+            Code::UndefinedType => {
+                "A type annotation names a type which doesn't exist.
+
+Example:
+
+    let a: Int = 1
+
+The builtin types are `Number`, `String`, and `Bool`."
+            }
+            Code::UninitialisedVariable => {
+                "A variable declared without an initialiser is read before it has been
+assigned on every path to the read.
+
+Example:
+
+    var a: Number
+    let b = (a = 1) if 1 > 2 else 0
+    print a
+
+Assign the variable on every path, or give it an initialiser."
+            }
+            Code::TypeUsedAsValue => {
+                "The name of a type was used where a value is needed.
+
+Example:
+
+    print Number
+
+Types can only appear in type annotations."
+            }
+            Code::InvalidAssignmentTarget => {
+                "Only variables can be assigned to.
+
+Example:
+
+    fn one(): Number
+        1
+    end
+    one = 2
+
+The left hand side of an assignment must name a variable."
+            }
+            Code::AssignToImmutable => {
+                "A variable declared with `let` was assigned to after its declaration.
+
+Example:
+
+    let a = 1
+    a = 2
+
+Declare the variable with `var` if it needs to change."
+            }
+            Code::MismatchedTypes => {
+                "A value has a different type to the one it is required to have.
+
+Example:
+
+    var a: Number = 'one'
+
+Values assigned to a variable, passed as an argument, or produced by
+the branches of an `if` must all have the expected type. Conversions
+are only inserted automatically for conditions and `print`."
+            }
+            Code::InvalidOperands => {
+                "No overload of the operator accepts the types of its operands.
+
+Example:
+
+    print 'a' - 'b'
+
+The diagnostic lists the operand types each overload does accept."
+            }
+            Code::WrongArgumentCount => {
+                "A function was called with more or fewer arguments than it has
+parameters.
+
+Example:
+
+    fn add(a: Number, b: Number): Number
+        a + b
+    end
+    print add(1)
+
+Pass exactly one argument for each parameter."
+            }
+            Code::NotCallable => {
+                "Something other than a function was called.
+
+Example:
+
+    let a = 1
+    print a(2)
+
+Only functions can be called."
+            }
+            Code::Unsupported => {
+                "The code uses a feature of the language which isn't implemented yet,
+such as indexing or array and tuple types.
+
+Example:
+
+    let a: [Number] = 1
+
+Rewrite the code without the feature for now."
+            }
+            Code::MissingParameterType => {
+                "A function parameter has no type annotation.
+
+Example:
+
+    fn double(n): Number
+        n * 2
+    end
+
+Parameter types are never inferred, so each one must be annotated."
+            }
+            Code::DuplicateParameter => {
+                "Two parameters of a function have the same name.
+
+Example:
+
+    fn add(a: Number, a: Number): Number
+        a + a
+    end
+
+Give each parameter a distinct name."
+            }
+            Code::MissingReturn => {
+                "A path through a function's body produces a value of a type other than
+the declared return type.
+
+Example:
+
+    fn count(n: Number): Number
+        while n > 0
+            n = n - 1
+        end
+    end
+
+The last expression of the body must produce the declared type. Add a
+final expression, or declare the function to return `()`."
+            }
+            Code::UnreachableCode => {
+                "An expression follows one which never completes, so can never run.
+
+Example:
+
+    while true
+    end
+    print 1
+
+Remove the unreachable code, or make the loop terminate. If the code
+is unreachable on purpose the warning can be allowed by writing
+`#allow(unreachable_code)` on the line before it."
+            }
+            Code::UnprintableValue => {
+                "A value was printed whose type has no conversion to `String`.
+
+Example:
+
+    fn f(): Number
+        1
+    end
+    print f
+
+Numbers, strings, and booleans can be printed."
+            }
+            Code::MissingInitialiser => {
+                "A variable declared with `let` has no initialiser. Immutable variables
+can't be assigned later, so must be given their value when declared.
+
+Example:
+
+    let a: Number
+
+Add an initialiser, or declare the variable with `var`."
+            }
+            Code::InvalidCondition => {
+                "The condition of an `if`, `unless`, `while`, or `until` has a type
+which can't be converted to `Bool`.
+
+Example:
+
+    print 1 if 'yes' else 2
+
+Numbers can be used as conditions, and are true when they aren't zero."
+            }
+            Code::NumberTooLarge => {
+                "A number literal is too large to be stored in a `Number`, which is a
+64 bit signed integer.
+
+Example:
+
+    print 99999999999999999999
+
+The largest number which can be written is 9223372036854775807."
+            }
+            Code::InvalidAttribute => {
+                "An attribute is unknown, has the wrong arguments, or is applied to
+something it can't be. Attributes can only be applied to functions
+and variable declarations.
+
+Example:
+
+    #[inline]
+    let a = 1
+
+`inline` and `export` only apply to functions. `allow`, `warn`, and
+`deny` apply to both, and take the names or codes of the lints to
+configure."
+            }
+            Code::InvalidMacro => {
+                "A macro is defined more than once, is defined somewhere other than
+the top level of the source, or expands forever.
+
+Example:
+
+    macro forever(n)
+        forever(n + 1)
+    end
+    print forever(0)
+
+Macros are expanded before the program is bound, so a macro which
+calls itself never stops expanding. Use a function instead."
+            }
+            Code::UnusedVariable => {
+                "A variable or function parameter is declared but never used.
+
+Example:
+
+    fn double(n: Number, unused: Number): Number
+        n * 2
+    end
+
+Remove the variable, or start its name with an underscore to show
+that it is unused on purpose."
+            }
+            Code::ShadowedVariable => {
+                "A declaration has the same name as a variable in an enclosing scope,
+hiding it for the rest of the scope.
+
+Example:
+
+    fn count(n: Number): Number
+        var n = 0
+        n
+    end
+
+This lint is allowed by default. Enable it with `-W shadowing`."
+            }
+            Code::ConstantCondition => {
+                "The condition of an `if`, or of a loop which never runs, is a
+constant, so one of the branches is never taken.
+
+Example:
+
+    print 1 if true else 2
+
+Loops whose condition is always true are allowed, as that is how an
+infinite loop is written."
+            }
+            Code::InvalidEntryPoint => {
+                "The function named with `--entry` doesn't exist, doesn't have the
+right signature, or there is code at the top level of the program.
+
+Example:
+
+    fn start(n: Number): Number
+        n
+    end
+
+An entry point takes no parameters and returns a `Number`, which is
+used as the program's exit status. Only the entry point is run, so
+the top level of the program may only contain function declarations."
+            }
+            Code::InvalidLoopControl => {
+                "A `break` or `continue` isn't inside a loop, or names a label which
+isn't on any of the loops it is inside.
+
+Example:
+
+    outer: while true
+        break inner
+    end
+
+Without a label `break` and `continue` apply to the innermost loop.
+Loops in an enclosing function can't be controlled from inside a
+nested function."
+            }
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U{:04}", *self as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn code_ids_round_trip() {
+        for (index, &code) in Code::ALL.iter().enumerate() {
+            assert_eq!(index + 1, code as usize);
+            assert_eq!(Some(code), Code::from_id(&code.to_string()));
+            assert!(code.explanation().contains("Example:"));
+        }
+        assert_eq!("U0007", Code::UndefinedName.to_string());
+        assert_eq!(Some(Code::UndefinedName), Code::from_id("u7"));
+        assert_eq!(None, Code::from_id("U0000"));
+        assert_eq!(None, Code::from_id("E0007"));
+        assert_eq!(
+            Some(Code::UnreachableCode),
+            Code::from_lint_name("unreachable_code")
+        );
+        assert_eq!(None, Code::from_lint_name("undefined_name"));
+    }
+}
@@ -0,0 +1,32 @@
+//! The Ullage Compiler
+//!
+//! This crate contains the parser, semantic analysis and code
+//! generation for the Ullage language. The `ullage` binary is a thin
+//! command line driver on top of this library. Other tools can use
+//! the modules here to parse, bind and compile source programmatically
+//! rather than spawning the compiler.
+//!
+//! # Example
+//!
+//! ```
+//! use ullage::syntax::{text::SourceText, SyntaxTree};
+//!
+//! let source = SourceText::new("print 1 + 2");
+//! let tree = SyntaxTree::parse(&source);
+//! assert!(!tree.has_diagnostics());
+//! ```
+
+#![warn(missing_docs)]
+// The `Fail` derive expands to impls nested inside constants.
+#![allow(non_local_definitions)]
+
+pub mod analysis;
+pub mod compile;
+pub mod diag;
+pub mod low_loader;
+pub mod lsp;
+pub mod meta;
+pub mod project;
+pub mod sem;
+pub mod syntax;
+pub mod watch;
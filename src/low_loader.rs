@@ -1,6 +1,6 @@
 //! Low Loader
 //!
-//! Low-level bindings to LLVM. This module provides a set of safe
+//! Low-level bindings to LLVM. This module provides a set of
 //! wrappers around LLVM objects and types to allow them to be used
 //! more ergonomically from Rust code.
 //!
@@ -15,18 +15,31 @@
 //! object. The builder has a set of factory methods for appending IR
 //! to a given basic block.
 //!
+//! # Safety
+//!
+//! Values, types, basic blocks and debug metadata aren't wrapped and
+//! are passed around as raw LLVM references. Any function which takes
+//! one is `unsafe`. Each reference passed in must be valid and must
+//! belong to the same `Context` as the object it is passed to. Any
+//! further requirement, such as the types an instruction accepts, is
+//! documented in the function's own `# Safety` section. Functions
+//! which only take wrapper types check their requirements themselves.
+//!
 //! [`Context`]: ./context/struct.Context.html
 //! [`Module`]: ./module/struct.Module.html
 //! [`Type`]: ./types/enum.Type.html
 //! [`Builder`]: ./builder/struct.Builder.html
 
 #![deny(missing_docs)]
-
 use llvm_sys;
 
 pub mod builder;
 pub mod context;
+pub mod debug_info;
 pub mod function;
+pub mod intrinsics;
+pub mod jit;
+pub mod memory_buffer;
 pub mod module;
 pub mod pass_manager;
 pub mod targets;
@@ -39,15 +52,23 @@ pub mod value;
 /// statements.
 pub mod prelude {
     pub use super::builder::Builder;
-    pub use super::builder::Predicate;
-    pub use super::context::Context;
-    pub use super::function::{CallConvention, Function};
+    pub use super::builder::{Phi, Predicate};
+    pub use super::context::{Context, LlvmDiagnostic, Severity};
+    pub use super::debug_info::DebugInfoBuilder;
+    pub use super::function::{Attribute, CallConvention, Function};
+    pub use super::intrinsics::Intrinsic;
+    pub use super::jit::Jit;
+    pub use super::memory_buffer::MemoryBuffer;
     pub use super::module::Module;
-    pub use super::targets::{Target, TargetLookupError};
+    pub use super::targets::{
+        CodeGenFileType, CodeGenOptLevel, RelocMode, Target, TargetLookupError, TargetMachine,
+    };
     pub use super::types::Type;
     pub use super::value::Value;
 
     // FIXME: only expose Value in public interface.
+    pub use super::llvm_sys::prelude::LLVMBasicBlockRef;
+    pub use super::llvm_sys::prelude::LLVMMetadataRef;
     pub use super::llvm_sys::prelude::LLVMTypeRef;
     pub use super::llvm_sys::prelude::LLVMValueRef;
 }
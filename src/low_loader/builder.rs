@@ -13,6 +13,7 @@ use std::ffi::{CStr, CString};
 use std::os::raw::c_uint;
 
 use super::function::Function;
+use super::intrinsics::{self, Intrinsic};
 
 /// IR Builder
 ///
@@ -22,6 +23,16 @@ pub struct Builder {
     raw: LLVMBuilderRef,
 }
 
+/// Phi Node
+///
+/// A phi node selects between values depending on which basic block
+/// control arrived from. Each possible predecessor must be given an
+/// incoming value with `add_incoming`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Phi {
+    raw: LLVMValueRef,
+}
+
 /// Comparison Predicate Type
 ///
 /// Choice of comparison operators. These will be mapped through to
@@ -49,42 +60,107 @@ impl Builder {
     /// Takes ownership of the given builder pointer. The builder can
     /// then be manipulated through the returned object and will be
     /// disposed of when this object leaves scope.
-    pub fn from_raw(raw: LLVMBuilderRef) -> Self {
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid builder which nothing else owns or
+    /// disposes of.
+    pub unsafe fn from_raw(raw: LLVMBuilderRef) -> Self {
         Builder { raw }
     }
 
     /// Build at the End of a Block
     ///
     /// Takes the builder, points it at the end of the basic block.
-    pub fn position_at_end(&mut self, block: LLVMBasicBlockRef) {
+    ///
+    /// # Safety
+    ///
+    /// `block` must be a basic block which has been added to a
+    /// function.
+    pub unsafe fn position_at_end(&mut self, block: LLVMBasicBlockRef) {
         unsafe {
             core::LLVMPositionBuilderAtEnd(self.raw, block);
         }
     }
 
+    /// Get the Insertion Block
+    ///
+    /// Returns the basic block the builder is currently adding
+    /// instructions to. Lowering nested control flow can move the
+    /// builder to a new block, so this is the block to use as the
+    /// incoming block for a `Phi`.
+    pub fn insert_block(&self) -> LLVMBasicBlockRef {
+        unsafe { core::LLVMGetInsertBlock(self.raw) }
+    }
+
     /// Add a Ret Instrution
     ///
     /// Returns control from the current function
     /// immediately. Consumes this build context as t the current
     /// basic block can't have any more instructions added after a
     /// terminator instruciton.
-    pub fn build_ret(self, value: LLVMValueRef) {
+    ///
+    /// # Safety
+    ///
+    /// `value` must have the return type of the function being built.
+    pub unsafe fn build_ret(self, value: LLVMValueRef) {
         unsafe {
             core::LLVMBuildRet(self.raw, value);
         }
     }
 
+    /// Set the Debug Location
+    ///
+    /// Instructions built after this call are attributed to the
+    /// given location, as created by a `DebugInfoBuilder`. The
+    /// builder must be positioned in a block first.
+    ///
+    /// # Safety
+    ///
+    /// `location` must have been created by a `DebugInfoBuilder` for
+    /// the module being built.
+    pub unsafe fn set_debug_location(&mut self, location: LLVMMetadataRef) {
+        unsafe {
+            let block = core::LLVMBasicBlockAsValue(self.insert_block());
+            let ctx = core::LLVMGetTypeContext(core::LLVMTypeOf(block));
+            core::LLVMSetCurrentDebugLocation(self.raw, core::LLVMMetadataAsValue(ctx, location));
+        }
+    }
+
+    /// Build a Return with no Value
+    ///
+    /// Used to return from `void` functions.
+    pub fn build_ret_void(self) {
+        unsafe {
+            core::LLVMBuildRetVoid(self.raw);
+        }
+    }
+
     /// Build a Call to a `void` Function
     ///
     /// The built value produces nothing so no value is returned.
-    pub fn build_void_call(&mut self, function: &Function, args: &mut [LLVMValueRef]) {
+    ///
+    /// # Safety
+    ///
+    /// `args` must match the number and types of `function`'s
+    /// parameters.
+    pub unsafe fn build_void_call(&mut self, function: &Function, args: &mut [LLVMValueRef]) {
         self.build_named_call(function, args, None);
     }
 
     /// Build a Call Instruction
     ///
     /// Emits a call to the given function.
-    pub fn build_call(&mut self, function: &Function, args: &mut [LLVMValueRef]) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `args` must match the number and types of `function`'s
+    /// parameters.
+    pub unsafe fn build_call(
+        &mut self,
+        function: &Function,
+        args: &mut [LLVMValueRef],
+    ) -> LLVMValueRef {
         self.build_named_call(function, args, Some("call"))
     }
 
@@ -93,7 +169,12 @@ impl Builder {
     /// Emits a call instruction. The name provided to the call
     /// controls the resulting temporary value. If no name is provided
     /// then the result isn't bound to any temporary.
-    pub fn build_named_call(
+    ///
+    /// # Safety
+    ///
+    /// `args` must match the number and types of `function`'s
+    /// parameters.
+    pub unsafe fn build_named_call(
         &mut self,
         function: &Function,
         args: &mut [LLVMValueRef],
@@ -117,7 +198,16 @@ impl Builder {
     /// Build a GEP
     ///
     /// GEP, or GetElementPointer, retrieves a pointer to an element in an item.
-    pub fn build_gep(&mut self, value: LLVMValueRef, indices: &mut [LLVMValueRef]) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `value` must be a pointer and `indices` integers which index
+    /// into the type it points to.
+    pub unsafe fn build_gep(
+        &mut self,
+        value: LLVMValueRef,
+        indices: &mut [LLVMValueRef],
+    ) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"gep\0");
             core::LLVMBuildGEP(
@@ -133,7 +223,16 @@ impl Builder {
     /// Build a Structure GEP
     ///
     /// Loads an element from a given structure pointer.
-    pub fn build_struct_gep(&mut self, struct_val: LLVMValueRef, index: u32) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `struct_val` must be a pointer to a structure with a field at
+    /// `index`.
+    pub unsafe fn build_struct_gep(
+        &mut self,
+        struct_val: LLVMValueRef,
+        index: u32,
+    ) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"sgep\0");
             core::LLVMBuildStructGEP(self.raw, struct_val, index as c_uint, name.as_ptr())
@@ -143,15 +242,277 @@ impl Builder {
     /// Extract a Value from an Aggregate
     ///
     /// Reads a value from a structure at the given struct offset.
-    pub fn build_extract_value(&mut self, struct_val: LLVMValueRef, index: u32) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `struct_val` must be an aggregate with an element at `index`.
+    pub unsafe fn build_extract_value(
+        &mut self,
+        struct_val: LLVMValueRef,
+        index: u32,
+    ) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"extracted\0");
             core::LLVMBuildExtractValue(self.raw, struct_val, index as c_uint, name.as_ptr())
         }
     }
 
+    /// Insert an Element into a Vector
+    ///
+    /// Returns a copy of the vector with the element at `index`
+    /// replaced by `value`.
+    ///
+    /// # Safety
+    ///
+    /// `vector` must be a vector of `value`'s type, and `index` an
+    /// integer.
+    pub unsafe fn build_insert_element(
+        &mut self,
+        vector: LLVMValueRef,
+        value: LLVMValueRef,
+        index: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            let name = CStr::from_bytes_with_nul_unchecked(b"inserted\0");
+            core::LLVMBuildInsertElement(self.raw, vector, value, index, name.as_ptr())
+        }
+    }
+
+    /// Extract an Element from a Vector
+    ///
+    /// # Safety
+    ///
+    /// `vector` must be a vector and `index` an integer.
+    pub unsafe fn build_extract_element(
+        &mut self,
+        vector: LLVMValueRef,
+        index: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            let name = CStr::from_bytes_with_nul_unchecked(b"element\0");
+            core::LLVMBuildExtractElement(self.raw, vector, index, name.as_ptr())
+        }
+    }
+
+    /// Shuffle the Elements of Two Vectors
+    ///
+    /// Builds a new vector by picking elements from `lhs` and `rhs`.
+    /// The `mask` is a constant vector of indices where indices past
+    /// the end of `lhs` select from `rhs`.
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be vectors of the same type, and `mask` a
+    /// constant vector of `i32`s.
+    pub unsafe fn build_shuffle_vector(
+        &mut self,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+        mask: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            let name = CStr::from_bytes_with_nul_unchecked(b"shuffled\0");
+            core::LLVMBuildShuffleVector(self.raw, lhs, rhs, mask, name.as_ptr())
+        }
+    }
+
+    /// Insert a Value into an Aggregate
+    ///
+    /// Returns a copy of the structure with the value at the given
+    /// struct offset replaced.
+    ///
+    /// # Safety
+    ///
+    /// `struct_val` must be an aggregate whose element at `index` has
+    /// `value`'s type.
+    pub unsafe fn build_insert_value(
+        &mut self,
+        struct_val: LLVMValueRef,
+        value: LLVMValueRef,
+        index: u32,
+    ) -> LLVMValueRef {
+        unsafe {
+            let name = CStr::from_bytes_with_nul_unchecked(b"inserted\0");
+            core::LLVMBuildInsertValue(self.raw, struct_val, value, index as c_uint, name.as_ptr())
+        }
+    }
+
+    /// Build a Call to `llvm.memcpy`
+    ///
+    /// Copies `len` bytes from `src` to `dest`. The buffers must not
+    /// overlap.
+    ///
+    /// # Safety
+    ///
+    /// `dest` and `src` must be pointers and `len` an `i64`.
+    pub unsafe fn build_memcpy(
+        &mut self,
+        dest: LLVMValueRef,
+        src: LLVMValueRef,
+        len: LLVMValueRef,
+    ) {
+        let memcpy = self.intrinsic(Intrinsic::Memcpy, &[dest, src, len]);
+        let is_volatile = self.const_false(dest);
+        self.build_void_call(&memcpy, &mut [dest, src, len, is_volatile]);
+    }
+
+    /// Build a Call to `llvm.memset`
+    ///
+    /// Fills `len` bytes at `dest` with the byte `value`.
+    ///
+    /// # Safety
+    ///
+    /// `dest` must be a pointer, `value` an `i8` and `len` an `i64`.
+    pub unsafe fn build_memset(
+        &mut self,
+        dest: LLVMValueRef,
+        value: LLVMValueRef,
+        len: LLVMValueRef,
+    ) {
+        let memset = self.intrinsic(Intrinsic::Memset, &[dest, len]);
+        let is_volatile = self.const_false(dest);
+        self.build_void_call(&memset, &mut [dest, value, len, is_volatile]);
+    }
+
+    /// Build a Call to `llvm.powi`
+    ///
+    /// Raises the floating point `base` to the 32 bit integer power
+    /// `exp`.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be a floating point value and `exp` an `i32`.
+    pub unsafe fn build_powi(&mut self, base: LLVMValueRef, exp: LLVMValueRef) -> LLVMValueRef {
+        let powi = self.intrinsic(Intrinsic::Powi, &[base]);
+        self.build_named_call(&powi, &mut [base, exp], Some("powi"))
+    }
+
+    /// Build a Call to `llvm.ctpop`
+    ///
+    /// Counts the number of set bits in an integer.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be an integer or a vector of integers.
+    pub unsafe fn build_ctpop(&mut self, value: LLVMValueRef) -> LLVMValueRef {
+        let ctpop = self.intrinsic(Intrinsic::Ctpop, &[value]);
+        self.build_named_call(&ctpop, &mut [value], Some("ctpop"))
+    }
+
+    /// Build a Call to `llvm.trap`
+    ///
+    /// Aborts the program without calling any library functions.
+    ///
+    /// # Panics
+    ///
+    /// If the builder isn't positioned in a block.
+    pub fn build_trap(&mut self) {
+        let trap = self.intrinsic(Intrinsic::Trap, &[]);
+        unsafe { self.build_void_call(&trap, &mut []) };
+    }
+
+    /// Build Overflow Checked Arithmetic
+    ///
+    /// Performs the arithmetic with one of the `*.with.overflow`
+    /// intrinsics. Returns the result of the operation and an `i1`
+    /// which is set if the operation overflowed.
+    ///
+    /// # Safety
+    ///
+    /// `intrinsic` must be one of the `with.overflow` intrinsics, and
+    /// `lhs` and `rhs` integers of the same type.
+    pub unsafe fn build_overflow_op(
+        &mut self,
+        intrinsic: Intrinsic,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> (LLVMValueRef, LLVMValueRef) {
+        let op = self.intrinsic(intrinsic, &[lhs]);
+        let pair = self.build_named_call(&op, &mut [lhs, rhs], Some("checked"));
+        (
+            self.build_extract_value(pair, 0),
+            self.build_extract_value(pair, 1),
+        )
+    }
+
+    /// Declare an Intrinsic in the Current Module
+    ///
+    /// The intrinsic is overloaded on the types of `values`.
+    fn intrinsic(&self, intrinsic: Intrinsic, values: &[LLVMValueRef]) -> Function {
+        unsafe {
+            let block = self.insert_block();
+            assert!(!block.is_null(), "builder isn't positioned in a block");
+            let fun = core::LLVMGetBasicBlockParent(block);
+            let module = core::LLVMGetGlobalParent(fun);
+            let overloads: Vec<_> = values.iter().map(|&v| core::LLVMTypeOf(v)).collect();
+            intrinsics::declare_in(module, intrinsic, &overloads)
+        }
+    }
+
+    /// Get a Constant `false` in the Same Context as a Value
+    fn const_false(&self, value: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let ctx = core::LLVMGetTypeContext(core::LLVMTypeOf(value));
+            core::LLVMConstInt(core::LLVMInt1TypeInContext(ctx), 0, 0)
+        }
+    }
+
+    /// Build a Call to Inline Assembly
+    ///
+    /// Emits the assembly string in place, passing it `args`. The
+    /// `constraints` string describes how the outputs and inputs map
+    /// to registers and memory, using LLVM's constraint syntax. If
+    /// `side_effects` is set then the assembly is never removed or
+    /// reordered by the optimiser even if its result isn't used.
+    ///
+    /// # Safety
+    ///
+    /// `args` must match the inputs `constraints` describes, and
+    /// `ret_type` its output.
+    pub unsafe fn build_inline_asm(
+        &mut self,
+        asm: &str,
+        constraints: &str,
+        ret_type: LLVMTypeRef,
+        args: &mut [LLVMValueRef],
+        side_effects: bool,
+    ) -> LLVMValueRef {
+        unsafe {
+            let mut param_types: Vec<_> = args.iter().map(|&a| core::LLVMTypeOf(a)).collect();
+            let fn_type = core::LLVMFunctionType(
+                ret_type,
+                param_types.as_mut_ptr(),
+                param_types.len() as c_uint,
+                0,
+            );
+            let asm = get_inline_asm(
+                fn_type,
+                asm.as_ptr() as *mut _,
+                asm.len(),
+                constraints.as_ptr() as *mut _,
+                constraints.len(),
+                side_effects as LLVMBool,
+                0,
+                LLVMInlineAsmDialect::LLVMInlineAsmDialectATT,
+                0,
+            );
+            let name = CStr::from_bytes_with_nul_unchecked(b"\0");
+            core::LLVMBuildCall(
+                self.raw,
+                asm,
+                args.as_mut_ptr(),
+                args.len() as c_uint,
+                name.as_ptr(),
+            )
+        }
+    }
+
     /// Build an Integer Negation
-    pub fn build_neg(&mut self, value: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `value` must be an integer.
+    pub unsafe fn build_neg(&mut self, value: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"negated\0");
             core::LLVMBuildNeg(self.raw, value, name.as_ptr())
@@ -159,7 +520,11 @@ impl Builder {
     }
 
     /// Build an Integer Add
-    pub fn build_add(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be integers of the same type.
+    pub unsafe fn build_add(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"addtmp\0");
             core::LLVMBuildAdd(self.raw, lhs, rhs, name.as_ptr())
@@ -167,7 +532,11 @@ impl Builder {
     }
 
     /// Build an Integer Subtraction
-    pub fn build_sub(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be integers of the same type.
+    pub unsafe fn build_sub(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"subtmp\0");
             core::LLVMBuildSub(self.raw, lhs, rhs, name.as_ptr())
@@ -175,7 +544,11 @@ impl Builder {
     }
 
     /// Build an Integer Multiplication
-    pub fn build_mul(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be integers of the same type.
+    pub unsafe fn build_mul(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"multmp\0");
             core::LLVMBuildMul(self.raw, lhs, rhs, name.as_ptr())
@@ -183,7 +556,11 @@ impl Builder {
     }
 
     /// Build a Signed Integer Division
-    pub fn build_sdiv(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be integers of the same type.
+    pub unsafe fn build_sdiv(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"divtmp\0");
             core::LLVMBuildSDiv(self.raw, lhs, rhs, name.as_ptr())
@@ -191,7 +568,12 @@ impl Builder {
     }
 
     /// Build an Integer Comparision
-    pub fn build_icmp(
+    ///
+    /// # Safety
+    ///
+    /// `lhs` and `rhs` must be integers, or pointers, of the same
+    /// type.
+    pub unsafe fn build_icmp(
         &mut self,
         op: Predicate,
         lhs: LLVMValueRef,
@@ -215,7 +597,11 @@ impl Builder {
     ///
     /// Creates a new value allocated for the remainder of the current
     /// stack frame.
-    pub fn build_alloca(&mut self, typ: LLVMTypeRef, name: &str) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `typ` must be a sized type.
+    pub unsafe fn build_alloca(&mut self, typ: LLVMTypeRef, name: &str) -> LLVMValueRef {
         let name = CString::new(name).unwrap();
         unsafe { core::LLVMBuildAlloca(self.raw, typ, name.as_ptr()) }
     }
@@ -224,7 +610,11 @@ impl Builder {
     ///
     /// Creates a new value allocated on the heap. Retusna pointer to
     /// the new value.
-    pub fn build_malloc(
+    ///
+    /// # Safety
+    ///
+    /// `typ` must be a sized type.
+    pub unsafe fn build_malloc(
         &mut self,
         typ: LLVMTypeRef,
         count: Option<LLVMValueRef>,
@@ -243,7 +633,12 @@ impl Builder {
     ///
     /// If the condition is true then execution continues in the first
     /// block, otherwise execution will move to the second block.
-    pub fn build_cond_br(
+    ///
+    /// # Safety
+    ///
+    /// `cond` must be an `i1`, and both blocks must be in the
+    /// function being built.
+    pub unsafe fn build_cond_br(
         &mut self,
         cond: LLVMValueRef,
         iftrue: LLVMBasicBlockRef,
@@ -255,14 +650,32 @@ impl Builder {
     }
 
     /// Create an Unconditional Branch
-    pub fn build_br(&mut self, block: LLVMBasicBlockRef) {
+    ///
+    /// # Safety
+    ///
+    /// `block` must be in the function being built.
+    pub unsafe fn build_br(&mut self, block: LLVMBasicBlockRef) {
         unsafe {
             core::LLVMBuildBr(self.raw, block);
         }
     }
 
+    /// Mark the Current Position as Unreachable
+    ///
+    /// Terminates the current block. Used after calls which never
+    /// return, such as `abort`.
+    pub fn build_unreachable(&mut self) {
+        unsafe {
+            core::LLVMBuildUnreachable(self.raw);
+        }
+    }
+
     /// Load from Variable
-    pub fn build_load(&mut self, var: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `var` must be a pointer to a sized type.
+    pub unsafe fn build_load(&mut self, var: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"loaded\0");
             core::LLVMBuildLoad(self.raw, var, name.as_ptr())
@@ -270,24 +683,64 @@ impl Builder {
     }
 
     /// Store to Variable
-    pub fn build_store(&mut self, val: LLVMValueRef, var: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `var` must be a pointer to `val`'s type.
+    pub unsafe fn build_store(&mut self, val: LLVMValueRef, var: LLVMValueRef) -> LLVMValueRef {
         unsafe { core::LLVMBuildStore(self.raw, val, var) }
     }
 
     /// Built a Not
-    pub fn build_not(&mut self, val: LLVMValueRef) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `val` must be an integer.
+    pub unsafe fn build_not(&mut self, val: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             let name = CStr::from_bytes_with_nul_unchecked(b"not\0");
             core::LLVMBuildNot(self.raw, val, name.as_ptr())
         }
     }
 
+    /// Build a Phi Node
+    ///
+    /// Creates an empty phi node of the given type at the current
+    /// position. Phi nodes must be grouped at the start of a basic
+    /// block.
+    ///
+    /// # Safety
+    ///
+    /// `typ` must be the type of every value later added to the phi.
+    pub unsafe fn build_phi(&mut self, typ: LLVMTypeRef) -> Phi {
+        self.build_named_phi(typ, "phi")
+    }
+
+    /// Build a Named Phi Node
+    ///
+    /// As `build_phi`, but the node is given the name `name`. Used to
+    /// give the values of variables readable names in the IR.
+    ///
+    /// # Safety
+    ///
+    /// `typ` must be the type of every value later added to the phi.
+    pub unsafe fn build_named_phi(&mut self, typ: LLVMTypeRef, name: &str) -> Phi {
+        let name = CString::new(name).unwrap();
+        let raw = unsafe { core::LLVMBuildPhi(self.raw, typ, name.as_ptr()) };
+        Phi { raw }
+    }
+
     /// Bitcast
     ///
     /// Re-interpret the input value to be of the given type. This
     /// just transforms how the underlying bits are interpreted rather
     /// than performing any smarter coercion.
-    pub fn build_bitcast(
+    ///
+    /// # Safety
+    ///
+    /// `val` must have the same size as `typ`, and be a pointer only
+    /// if `typ` is.
+    pub unsafe fn build_bitcast(
         &mut self,
         val: LLVMValueRef,
         typ: LLVMTypeRef,
@@ -298,6 +751,46 @@ impl Builder {
             core::LLVMBuildBitCast(self.raw, val, typ, name.as_ptr())
         }
     }
+
+    /// Zero Extend
+    ///
+    /// Widens an integer value to the given integer type, filling
+    /// the new high bits with zeros.
+    ///
+    /// # Safety
+    ///
+    /// `val` must be an integer narrower than the integer type `typ`.
+    pub unsafe fn build_zext(&mut self, val: LLVMValueRef, typ: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            let name = CStr::from_bytes_with_nul_unchecked(b"zext\0");
+            core::LLVMBuildZExt(self.raw, val, typ, name.as_ptr())
+        }
+    }
+}
+
+impl Phi {
+    /// Add an Incoming Value
+    ///
+    /// The phi node will take the value `value` when control arrives
+    /// from `block`.
+    ///
+    /// # Safety
+    ///
+    /// `value` must have the phi's type, and `block` must be a
+    /// predecessor of the phi's block.
+    pub unsafe fn add_incoming(&mut self, value: LLVMValueRef, block: LLVMBasicBlockRef) {
+        let mut values = [value];
+        let mut blocks = [block];
+        unsafe { core::LLVMAddIncoming(self.raw, values.as_mut_ptr(), blocks.as_mut_ptr(), 1) }
+    }
+
+    /// Get the Raw Value
+    ///
+    /// Returns the phi node as a value which can be used as the
+    /// operand to other instructions.
+    pub fn as_raw(&self) -> LLVMValueRef {
+        self.raw
+    }
 }
 
 impl Drop for Builder {
@@ -308,3 +801,179 @@ impl Drop for Builder {
         }
     }
 }
+
+extern "C" {
+    /// Create an Inline Assembly Value
+    ///
+    /// LLVM 13 added the `CanThrow` parameter to the end of this
+    /// function. Arguments are cleaned up by the caller in the C
+    /// calling convention, so always passing it is safe with older
+    /// versions of LLVM too, which just ignore it.
+    #[link_name = "LLVMGetInlineAsm"]
+    fn get_inline_asm(
+        ty: LLVMTypeRef,
+        asm_string: *mut libc::c_char,
+        asm_string_size: usize,
+        constraints: *mut libc::c_char,
+        constraints_size: usize,
+        has_side_effects: LLVMBool,
+        is_align_stack: LLVMBool,
+        dialect: LLVMInlineAsmDialect,
+        can_throw: LLVMBool,
+    ) -> LLVMValueRef;
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::prelude::*;
+
+    #[test]
+    fn build_phi_with_incoming_values() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("phi");
+        let bool_type = ctx.bool_type();
+        let int_type = ctx.int_type(64);
+        let mut fun =
+            unsafe { ctx.add_function(&mut module, "select", int_type, &mut [bool_type]) };
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let then = ctx.add_block(&mut fun, "then");
+        let join = ctx.add_block(&mut fun, "join");
+        let mut builder = ctx.add_builder();
+        unsafe {
+            builder.position_at_end(entry);
+            builder.build_cond_br(fun.get_param(0), then, join);
+            builder.position_at_end(then);
+            builder.build_br(join);
+        }
+
+        unsafe { builder.position_at_end(join) };
+        let mut phi = unsafe { builder.build_phi(int_type) };
+        unsafe {
+            phi.add_incoming(ctx.const_int(1), entry);
+            phi.add_incoming(ctx.const_int(2), then);
+        }
+        assert_eq!(join, builder.insert_block());
+        unsafe { builder.build_ret(phi.as_raw()) };
+
+        fun.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(ir.contains("phi i64 [ 1, %entry ], [ 2, %then ]"), "{}", ir);
+    }
+
+    #[test]
+    fn build_struct_access() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("structs");
+        let int_type = ctx.int_type(64);
+        let pair = unsafe { ctx.named_struct_type("Pair", &[int_type, ctx.bool_type()]) };
+        let mut fun = unsafe { ctx.add_function(&mut module, "first", int_type, &mut [int_type]) };
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        unsafe {
+            let slot = builder.build_alloca(pair, "pair");
+            let field = builder.build_struct_gep(slot, 0);
+            builder.build_store(fun.get_param(0), field);
+            let loaded = builder.build_load(slot);
+            let updated = builder.build_insert_value(loaded, ctx.const_bool(true), 1);
+            let first = builder.build_extract_value(updated, 0);
+            builder.build_ret(first);
+        }
+
+        fun.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(ir.contains("%Pair = type { i64, i1 }"), "{}", ir);
+        assert!(ir.contains("insertvalue %Pair"), "{}", ir);
+    }
+
+    #[test]
+    fn build_intrinsic_calls() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("intrinsics");
+        let int_type = ctx.int_type(64);
+        let mut fun =
+            unsafe { ctx.add_function(&mut module, "checked", int_type, &mut [int_type]) };
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        let buffer = unsafe { builder.build_alloca(ctx.array_type(ctx.int_type(8), 8), "buffer") };
+        let bytes = unsafe { builder.build_bitcast(buffer, ctx.cstr_type(), "bytes") };
+        unsafe {
+            builder.build_memset(bytes, ctx.const_char(0), ctx.const_int(8));
+            builder.build_memcpy(bytes, bytes, ctx.const_int(0));
+        }
+        let bits = unsafe { builder.build_ctpop(fun.get_param(0)) };
+        let (sum, _) =
+            unsafe { builder.build_overflow_op(Intrinsic::SAddWithOverflow, bits, bits) };
+        unsafe { builder.build_ret(sum) };
+
+        fun.verify_or_panic();
+        module.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(ir.contains("@llvm.memset.p0i8.i64"), "{}", ir);
+        assert!(ir.contains("@llvm.sadd.with.overflow.i64"), "{}", ir);
+    }
+
+    #[test]
+    fn build_inline_asm_call() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("asm");
+        let int_type = ctx.int_type(64);
+        let mut fun = unsafe { ctx.add_function(&mut module, "copy", int_type, &mut [int_type]) };
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        let copied = unsafe {
+            builder.build_inline_asm(
+                "mov $1, $0",
+                "=r,r",
+                int_type,
+                &mut [fun.get_param(0)],
+                true,
+            )
+        };
+        unsafe { builder.build_ret(copied) };
+
+        fun.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("call i64 asm sideeffect \"mov $1, $0\", \"=r,r\"(i64 %0)"),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn build_vector_operations() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("vectors");
+        let int_type = ctx.int_type(64);
+        let vec_type = unsafe { ctx.vector_type(int_type, 2) };
+        let mut fun = unsafe { ctx.add_function(&mut module, "sum", int_type, &mut [vec_type]) };
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        let int32 = |i| ctx.const_int_width(i, 32);
+        unsafe {
+            let swapped = builder.build_shuffle_vector(
+                fun.get_param(0),
+                fun.get_param(0),
+                ctx.const_vector(&[int32(1), int32(0)]),
+            );
+            let updated = builder.build_insert_element(swapped, ctx.const_int(7), int32(1));
+            let added = builder.build_add(updated, fun.get_param(0));
+            let first = builder.build_extract_element(added, int32(0));
+            builder.build_ret(first);
+        }
+
+        fun.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(ir.contains("shufflevector <2 x i64>"), "{}", ir);
+        assert!(ir.contains("add <2 x i64>"), "{}", ir);
+    }
+}
@@ -3,10 +3,11 @@
 //! Contains a Rust wrapper for dealing with LLVM Context objects.
 
 use super::llvm_sys::prelude::*;
-use super::llvm_sys::{core, target};
+use super::llvm_sys::{core, target, LLVMDiagnosticSeverity};
 use super::prelude::*;
-use std::ffi::CString;
-use std::os::raw::c_uint;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_uint, c_void};
 
 /// Ensure Initialised
 ///
@@ -19,9 +20,9 @@ use std::os::raw::c_uint;
 /// If any of the LLVM subsystems can't be successfully initialised
 /// then this function will panic.
 pub(crate) fn ensure_initialised() {
-    use std::sync::{Once, ONCE_INIT};
+    use std::sync::Once;
 
-    static INIT: Once = ONCE_INIT;
+    static INIT: Once = Once::new();
 
     INIT.call_once(|| {
         unsafe {
@@ -30,7 +31,9 @@ pub(crate) fn ensure_initialised() {
             // cross compiling.
             target::LLVM_InitializeAllTargets();
             target::LLVM_InitializeAllTargetInfos();
-            // target::LLVM_InitializeAllAsmPrinters();
+            // ASM printers are needed to emit object code for
+            // non-native targets too.
+            target::LLVM_InitializeAllAsmPrinters();
             // target::LLVM_InitializeAllAsmParsers();
             target::LLVM_InitializeAllTargetMCs();
             if target::LLVM_InitializeNativeAsmPrinter() != 0 {
@@ -51,7 +54,39 @@ pub(crate) fn ensure_initialised() {
 /// threasds. We'll enforce this by taking `&mut self` when
 /// meddling with the context.
 #[derive(Debug, PartialEq)]
-pub struct Context(LLVMContextRef);
+pub struct Context {
+    raw: LLVMContextRef,
+    /// Diagnostics raised by LLVM. This is boxed so that the address
+    /// given to the diagnostic handler stays the same if the context
+    /// is moved.
+    diagnostics: Box<RefCell<Vec<LlvmDiagnostic>>>,
+}
+
+/// Severity of an LLVM Diagnostic
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Severity {
+    /// An error. The output of the current operation is not usable
+    Error,
+    /// A warning
+    Warning,
+    /// An optimisation remark
+    Remark,
+    /// A note attached to a previous diagnostic
+    Note,
+}
+
+/// LLVM Diagnostic
+///
+/// A warning or error reported by LLVM itself, rather than by the
+/// compiler. For example a problem with inline assembly or a remark
+/// from one of the optimisation passes.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LlvmDiagnostic {
+    /// How serious the diagnostic is
+    pub severity: Severity,
+    /// The description of the problem, as formatted by LLVM
+    pub message: String,
+}
 
 impl Context {
     /// Create Context
@@ -70,7 +105,22 @@ impl Context {
     /// function will panic.
     pub fn new() -> Self {
         ensure_initialised();
-        Context(unsafe { core::LLVMContextCreate() })
+        let raw = unsafe { core::LLVMContextCreate() };
+        let diagnostics = Box::new(RefCell::new(Vec::new()));
+        unsafe {
+            let handler_ctx = &*diagnostics as *const RefCell<_> as *mut c_void;
+            core::LLVMContextSetDiagnosticHandler(raw, Some(handle_diagnostic), handler_ctx);
+        }
+        Context { raw, diagnostics }
+    }
+
+    /// Take the Diagnostics Raised by LLVM
+    ///
+    /// Returns the diagnostics LLVM has reported in this context
+    /// since the last call. Without a handler installed LLVM would
+    /// write these straight to standard error.
+    pub fn take_diagnostics(&self) -> Vec<LlvmDiagnostic> {
+        self.diagnostics.replace(Vec::new())
     }
 
     /// Add Module
@@ -78,9 +128,12 @@ impl Context {
     /// Creates a new LLVM module in this context.
     pub fn add_module(&mut self, name: &str) -> Module {
         let mod_name = CString::new(name).unwrap();
-        Module::from_raw(unsafe {
-            core::LLVMModuleCreateWithNameInContext(mod_name.as_ptr(), self.as_raw())
-        })
+        unsafe {
+            Module::from_raw(core::LLVMModuleCreateWithNameInContext(
+                mod_name.as_ptr(),
+                self.as_raw(),
+            ))
+        }
     }
 
     /// Add a Function to the Module
@@ -88,7 +141,12 @@ impl Context {
     /// Creates a new function in the module. The function has no body
     /// attached. If nothing extra is done with the returned
     /// `Fucntion` then it will serve as an external declaration/import.
-    pub fn add_function(
+    ///
+    /// # Safety
+    ///
+    /// `ret_type` and `params` must be first class types, and
+    /// `module` must have been created by this context.
+    pub unsafe fn add_function(
         &mut self,
         module: &mut Module,
         name: &str,
@@ -103,7 +161,12 @@ impl Context {
     /// Creates a new function in the module in the same way as
     /// `add_function`. In addition the function is declared with a
     /// variable argument list.
-    pub fn add_varargs_function(
+    ///
+    /// # Safety
+    ///
+    /// `ret_type` and `params` must be first class types, and
+    /// `module` must have been created by this context.
+    pub unsafe fn add_varargs_function(
         &mut self,
         module: &mut Module,
         name: &str,
@@ -162,7 +225,7 @@ impl Context {
     ///
     /// Creates and initalises a new IR Builder in this `Context`.
     pub fn add_builder(&mut self) -> Builder {
-        Builder::from_raw(unsafe { core::LLVMCreateBuilderInContext(self.as_raw()) })
+        unsafe { Builder::from_raw(core::LLVMCreateBuilderInContext(self.as_raw())) }
     }
 
     /// Create A Constant Value
@@ -222,13 +285,57 @@ impl Context {
     /// Create a Structure Contstant
     ///
     /// Initialses a new structrure based on the given values.
-    pub fn const_struct(&self, mut values: Vec<LLVMValueRef>) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `values` must all be constants.
+    pub unsafe fn const_struct(&self, mut values: Vec<LLVMValueRef>) -> LLVMValueRef {
         let len = values.len();
         unsafe {
             core::LLVMConstStructInContext(self.as_raw(), values.as_mut_ptr(), len as c_uint, 0)
         }
     }
 
+    /// Create an Array Constant
+    ///
+    /// Initialises a new array of `elem_ty` from the given values. Each
+    /// value must be a constant of the element type.
+    ///
+    /// # Safety
+    ///
+    /// `values` must all be constants of type `elem_ty`.
+    pub unsafe fn const_array(
+        &self,
+        elem_ty: LLVMTypeRef,
+        values: &[LLVMValueRef],
+    ) -> LLVMValueRef {
+        let mut values = values.to_vec();
+        unsafe { core::LLVMConstArray(elem_ty, values.as_mut_ptr(), values.len() as c_uint) }
+    }
+
+    /// Create a Null Pointer Constant
+    ///
+    /// The given type must be a pointer type.
+    ///
+    /// # Safety
+    ///
+    /// `ptr_ty` must be a pointer type.
+    pub unsafe fn const_null(&self, ptr_ty: LLVMTypeRef) -> LLVMValueRef {
+        unsafe { core::LLVMConstPointerNull(ptr_ty) }
+    }
+
+    /// Create an Undefined Value
+    ///
+    /// Used where a value of the given type is needed by code which
+    /// can never run.
+    ///
+    /// # Safety
+    ///
+    /// `ty` must not be `void` or a function type.
+    pub unsafe fn const_undef(&self, ty: LLVMTypeRef) -> LLVMValueRef {
+        unsafe { core::LLVMGetUndef(ty) }
+    }
+
     /// Raw Borrow
     ///
     /// # Safety
@@ -238,8 +345,7 @@ impl Context {
     /// the `Context`, and to make sure you don't break any of LLVMs
     /// thread safety requirements.
     pub unsafe fn as_raw(&self) -> LLVMContextRef {
-        let &Context(raw_ctx) = self;
-        raw_ctx
+        self.raw
     }
 
     /// A Sized Integer Type in this Context
@@ -275,10 +381,56 @@ impl Context {
     ///
     /// Given a set of fields create a structure type with fields
     /// layed out in that order.
-    pub fn struct_type(&self, mut fields: Vec<LLVMTypeRef>) -> LLVMTypeRef {
-        let len = fields.len();
+    ///
+    /// # Safety
+    ///
+    /// `fields` must all be sized types.
+    pub unsafe fn struct_type(&self, fields: &[LLVMTypeRef]) -> LLVMTypeRef {
+        let mut fields = fields.to_vec();
         unsafe {
-            core::LLVMStructTypeInContext(self.as_raw(), fields.as_mut_ptr(), len as c_uint, 0)
+            core::LLVMStructTypeInContext(
+                self.as_raw(),
+                fields.as_mut_ptr(),
+                fields.len() as c_uint,
+                0,
+            )
+        }
+    }
+
+    /// Create a Named Structure Type
+    ///
+    /// Named structures are distinct from any other structure with
+    /// the same layout and show up by name in the emitted IR.
+    ///
+    /// # Safety
+    ///
+    /// `fields` must all be sized types.
+    pub unsafe fn named_struct_type(&self, name: &str, fields: &[LLVMTypeRef]) -> LLVMTypeRef {
+        let ty = self.opaque_struct_type(name);
+        self.set_struct_body(ty, fields);
+        ty
+    }
+
+    /// Create an Opaque Structure Type
+    ///
+    /// Creates a named structure with no body. The body can be
+    /// filled in later with `set_struct_body`, which allows the
+    /// structure to contain pointers to itself.
+    pub fn opaque_struct_type(&self, name: &str) -> LLVMTypeRef {
+        let name = CString::new(name).unwrap();
+        unsafe { core::LLVMStructCreateNamed(self.as_raw(), name.as_ptr()) }
+    }
+
+    /// Set the Fields of a Named Structure
+    ///
+    /// # Safety
+    ///
+    /// `ty` must be an opaque structure type, and `fields` all sized
+    /// types.
+    pub unsafe fn set_struct_body(&self, ty: LLVMTypeRef, fields: &[LLVMTypeRef]) {
+        let mut fields = fields.to_vec();
+        unsafe {
+            core::LLVMStructSetBody(ty, fields.as_mut_ptr(), fields.len() as c_uint, 0);
         }
     }
 
@@ -286,31 +438,89 @@ impl Context {
     ///
     /// Returns a type which represents a contiguous array of the
     /// inner type.
-    pub fn array_type(&self, inner: LLVMTypeRef, size: usize) -> LLVMTypeRef {
+    ///
+    /// # Safety
+    ///
+    /// `inner` must be a sized type.
+    pub unsafe fn array_type(&self, inner: LLVMTypeRef, size: usize) -> LLVMTypeRef {
         unsafe { core::LLVMArrayType(inner, size as c_uint) }
     }
 
+    /// Create a Vector Type
+    ///
+    /// Returns a SIMD vector type holding `count` elements of the
+    /// inner type. Operations on vectors apply to each element.
+    ///
+    /// # Safety
+    ///
+    /// `inner` must be an integer, floating point or pointer type.
+    pub unsafe fn vector_type(&self, inner: LLVMTypeRef, count: usize) -> LLVMTypeRef {
+        unsafe { core::LLVMVectorType(inner, count as c_uint) }
+    }
+
+    /// Create a Vector Constant
+    ///
+    /// Initialises a new vector from the given constant values.
+    ///
+    /// # Safety
+    ///
+    /// `values` must all be constants of the same type.
+    pub unsafe fn const_vector(&self, values: &[LLVMValueRef]) -> LLVMValueRef {
+        let mut values = values.to_vec();
+        unsafe { core::LLVMConstVector(values.as_mut_ptr(), values.len() as c_uint) }
+    }
+
     /// Create a Pointer Type
     ///
     /// Wraps a given type to creat a poitner to it.
-    pub fn pointer_type(&self, inner: LLVMTypeRef) -> LLVMTypeRef {
+    ///
+    /// # Safety
+    ///
+    /// `inner` must not be `void`.
+    pub unsafe fn pointer_type(&self, inner: LLVMTypeRef) -> LLVMTypeRef {
         unsafe { core::LLVMPointerType(inner, 0) }
     }
 
     /// Get the Void Type
     pub fn void_type(&self) -> LLVMTypeRef {
-        unsafe { core::LLVMVoidTypeInContext(self.0) }
+        unsafe { core::LLVMVoidTypeInContext(self.raw) }
     }
 
     /// Get the LLVM Type from a Value
     ///
     /// Inspects a given LLVM Value and returns the type as known by
     /// LLVM. This is basically jsut an `LLVMTypeOf` call.
-    pub fn get_type(&self, value: LLVMValueRef) -> LLVMTypeRef {
+    ///
+    /// # Safety
+    ///
+    /// `value` can be any value created in this context.
+    pub unsafe fn get_type(&self, value: LLVMValueRef) -> LLVMTypeRef {
         unsafe { core::LLVMTypeOf(value) }
     }
 }
 
+/// LLVM Diagnostic Handler
+///
+/// Records each diagnostic in the buffer owned by the `Context`.
+extern "C" fn handle_diagnostic(info: LLVMDiagnosticInfoRef, handler_ctx: *mut c_void) {
+    let diagnostics = unsafe { &*(handler_ctx as *const RefCell<Vec<LlvmDiagnostic>>) };
+    let severity = match unsafe { core::LLVMGetDiagInfoSeverity(info) } {
+        LLVMDiagnosticSeverity::LLVMDSError => Severity::Error,
+        LLVMDiagnosticSeverity::LLVMDSWarning => Severity::Warning,
+        LLVMDiagnosticSeverity::LLVMDSRemark => Severity::Remark,
+        LLVMDiagnosticSeverity::LLVMDSNote => Severity::Note,
+    };
+    let message = unsafe {
+        let description = core::LLVMGetDiagInfoDescription(info);
+        let message = CStr::from_ptr(description).to_string_lossy().into_owned();
+        core::LLVMDisposeMessage(description);
+        message
+    };
+    diagnostics
+        .borrow_mut()
+        .push(LlvmDiagnostic { severity, message });
+}
+
 impl Drop for Context {
     fn drop(&mut self) {
         unsafe {
@@ -324,3 +534,57 @@ impl Default for Context {
         Context::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::llvm_sys::{core, LLVMAttributeFunctionIndex};
+    use super::*;
+
+    #[test]
+    fn context_captures_llvm_diagnostics() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("diags");
+        let int_type = ctx.int_type(64);
+        let mut fun = unsafe { ctx.add_function(&mut module, "big", int_type, &mut []) };
+        // Ask LLVM to warn about any stack frame over 8 bytes
+        unsafe {
+            let (key, value) = ("warn-stack-size", "8");
+            let attr = core::LLVMCreateStringAttribute(
+                ctx.as_raw(),
+                key.as_ptr() as *const _,
+                key.len() as c_uint,
+                value.as_ptr() as *const _,
+                value.len() as c_uint,
+            );
+            core::LLVMAddAttributeAtIndex(fun.as_raw(), LLVMAttributeFunctionIndex, attr);
+        }
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        unsafe {
+            let slot = builder.build_alloca(ctx.array_type(int_type, 64), "buffer");
+            let first = builder.build_struct_gep(slot, 0);
+            builder.build_store(ctx.const_int(1), first);
+            let loaded = builder.build_load(first);
+            builder.build_ret(loaded);
+        }
+
+        assert!(ctx.take_diagnostics().is_empty());
+        Target::default()
+            .create_target_machine("", "", CodeGenOptLevel::None, RelocMode::Default)
+            .unwrap()
+            .emit_to_memory_buffer(&module, CodeGenFileType::Object)
+            .unwrap();
+
+        let diagnostics = ctx.take_diagnostics();
+        assert!(
+            diagnostics
+                .iter()
+                .any(|d| d.severity == Severity::Warning && d.message.contains("stack")),
+            "{:?}",
+            diagnostics
+        );
+        assert!(ctx.take_diagnostics().is_empty());
+    }
+}
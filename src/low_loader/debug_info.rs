@@ -0,0 +1,387 @@
+//! LLVM Debug Info Builder Wrapper
+//!
+//! Contains a Rust wrapper around LLVM's `DIBuilder`. Debug info
+//! metadata describes the source files, functions, and variables of
+//! a module so that debuggers can map the compiled code back to the
+//! source.
+
+use super::function::Function;
+use super::llvm_sys::debuginfo::*;
+use super::llvm_sys::prelude::*;
+use super::llvm_sys::{core, LLVMModuleFlagBehavior};
+use super::module::Module;
+use std::os::raw::{c_char, c_uint};
+use std::ptr;
+
+/// Basic Type Encoding
+///
+/// The DWARF encodings used to describe how the bits of a basic type
+/// should be interpreted.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding {
+    /// A true or false value
+    Boolean,
+    /// A signed integer
+    Signed,
+    /// An unsigned integer
+    Unsigned,
+    /// A machine address
+    Address,
+}
+
+impl From<Encoding> for LLVMDWARFTypeEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Address => 0x01,
+            Encoding::Boolean => 0x02,
+            Encoding::Signed => 0x05,
+            Encoding::Unsigned => 0x08,
+        }
+    }
+}
+
+/// Debug Info Builder
+///
+/// Creates debug info metadata for a single module. The metadata
+/// isn't complete until the builder has been finalised.
+pub struct DebugInfoBuilder {
+    raw: LLVMDIBuilderRef,
+    ctx: LLVMContextRef,
+}
+
+impl DebugInfoBuilder {
+    /// Create a Debug Info Builder for a Module
+    ///
+    /// Also adds the `Debug Info Version` flag to the module. Without
+    /// it LLVM strips the debug info from the module when it is
+    /// verified.
+    pub fn new(module: &mut Module) -> Self {
+        unsafe {
+            let raw_mod = module.as_raw();
+            let ctx = core::LLVMGetModuleContext(raw_mod);
+            let key = "Debug Info Version";
+            let version = core::LLVMConstInt(
+                core::LLVMInt32TypeInContext(ctx),
+                u64::from(LLVMDebugMetadataVersion()),
+                0,
+            );
+            core::LLVMAddModuleFlag(
+                raw_mod,
+                LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+                key.as_ptr() as *const c_char,
+                key.len(),
+                core::LLVMValueAsMetadata(version),
+            );
+            DebugInfoBuilder {
+                raw: LLVMCreateDIBuilder(raw_mod),
+                ctx,
+            }
+        }
+    }
+
+    /// Create a File
+    ///
+    /// Files are used as the scope for compile units and functions.
+    pub fn create_file(&mut self, name: &str, directory: &str) -> LLVMMetadataRef {
+        unsafe {
+            LLVMDIBuilderCreateFile(
+                self.raw,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                directory.as_ptr() as *const c_char,
+                directory.len(),
+            )
+        }
+    }
+
+    /// Create a Compile Unit
+    ///
+    /// Each module should have a single compile unit. It records the
+    /// main file for the module and the tool which produced it.
+    ///
+    /// # Safety
+    ///
+    /// `file` must have been created by this builder.
+    pub unsafe fn create_compile_unit(
+        &mut self,
+        file: LLVMMetadataRef,
+        producer: &str,
+        optimised: bool,
+    ) -> LLVMMetadataRef {
+        unsafe {
+            create_compile_unit(
+                self.raw,
+                LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+                file,
+                producer.as_ptr() as *const c_char,
+                producer.len(),
+                optimised as LLVMBool,
+                ptr::null(),
+                0,
+                0,
+                ptr::null(),
+                0,
+                LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+                0,
+                0,
+                0,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+            )
+        }
+    }
+
+    /// Create a Basic Type
+    ///
+    /// Describes a scalar type of the given size.
+    pub fn create_basic_type(
+        &mut self,
+        name: &str,
+        size_in_bits: u64,
+        encoding: Encoding,
+    ) -> LLVMMetadataRef {
+        unsafe {
+            LLVMDIBuilderCreateBasicType(
+                self.raw,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                size_in_bits,
+                encoding.into(),
+                LLVMDIFlags::LLVMDIFlagZero,
+            )
+        }
+    }
+
+    /// Create a Subroutine Type
+    ///
+    /// The first entry in `types` is the return type of the
+    /// subroutine, the remaining entries are the parameter types.
+    ///
+    /// # Safety
+    ///
+    /// `file` and every entry in `types` must have been created by
+    /// this builder.
+    pub unsafe fn create_subroutine_type(
+        &mut self,
+        file: LLVMMetadataRef,
+        types: &[LLVMMetadataRef],
+    ) -> LLVMMetadataRef {
+        let mut types = types.to_vec();
+        unsafe {
+            LLVMDIBuilderCreateSubroutineType(
+                self.raw,
+                file,
+                types.as_mut_ptr(),
+                types.len() as c_uint,
+                LLVMDIFlags::LLVMDIFlagZero,
+            )
+        }
+    }
+
+    /// Create a Subprogram for a Function
+    ///
+    /// Describes the function's definition and attaches the returned
+    /// subprogram to it. The subprogram is the scope for locations
+    /// and variables within the function.
+    ///
+    /// # Safety
+    ///
+    /// `scope`, `file` and `ty` must have been created by this
+    /// builder, and `function` must be in the builder's module.
+    pub unsafe fn create_function(
+        &mut self,
+        scope: LLVMMetadataRef,
+        file: LLVMMetadataRef,
+        function: &mut Function,
+        name: &str,
+        line: u32,
+        ty: LLVMMetadataRef,
+    ) -> LLVMMetadataRef {
+        unsafe {
+            let subprogram = LLVMDIBuilderCreateFunction(
+                self.raw,
+                scope,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                name.as_ptr() as *const c_char,
+                name.len(),
+                file,
+                line,
+                ty,
+                0,
+                1,
+                line,
+                LLVMDIFlags::LLVMDIFlagZero,
+                0,
+            );
+            LLVMSetSubprogram(function.as_raw(), subprogram);
+            subprogram
+        }
+    }
+
+    /// Create a Source Location
+    ///
+    /// Locations can be set on a `Builder` so the instructions it
+    /// builds are attributed to a line and column of the source.
+    ///
+    /// # Safety
+    ///
+    /// `scope` must be a subprogram or lexical block created by this
+    /// builder.
+    pub unsafe fn create_location(
+        &mut self,
+        line: u32,
+        column: u32,
+        scope: LLVMMetadataRef,
+    ) -> LLVMMetadataRef {
+        unsafe { LLVMDIBuilderCreateDebugLocation(self.ctx, line, column, scope, ptr::null_mut()) }
+    }
+
+    /// Create a Local Variable
+    ///
+    /// Describes a variable declared within the given scope. The
+    /// variable is bound to its storage with `insert_declare`.
+    ///
+    /// # Safety
+    ///
+    /// `scope`, `file` and `ty` must have been created by this
+    /// builder.
+    pub unsafe fn create_local_variable(
+        &mut self,
+        scope: LLVMMetadataRef,
+        file: LLVMMetadataRef,
+        name: &str,
+        line: u32,
+        ty: LLVMMetadataRef,
+    ) -> LLVMMetadataRef {
+        unsafe {
+            LLVMDIBuilderCreateAutoVariable(
+                self.raw,
+                scope,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                file,
+                line,
+                ty,
+                1,
+                LLVMDIFlags::LLVMDIFlagZero,
+                0,
+            )
+        }
+    }
+
+    /// Declare a Variable's Storage
+    ///
+    /// Inserts a declaration at the end of `block` which binds the
+    /// variable to the given `alloca`.
+    ///
+    /// # Safety
+    ///
+    /// `storage` must be an `alloca` in `block`, and `variable` and
+    /// `location` must have been created by this builder.
+    pub unsafe fn insert_declare(
+        &mut self,
+        storage: LLVMValueRef,
+        variable: LLVMMetadataRef,
+        location: LLVMMetadataRef,
+        block: LLVMBasicBlockRef,
+    ) {
+        unsafe {
+            let expr = LLVMDIBuilderCreateExpression(self.raw, ptr::null_mut(), 0);
+            LLVMDIBuilderInsertDeclareAtEnd(self.raw, storage, variable, expr, location, block);
+        }
+    }
+
+    /// Finalise the Debug Info
+    ///
+    /// Resolves any temporary metadata. This must be called before
+    /// the module is verified or emitted.
+    pub fn finalise(&mut self) {
+        unsafe { LLVMDIBuilderFinalize(self.raw) }
+    }
+}
+
+impl Drop for DebugInfoBuilder {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeDIBuilder(self.raw) }
+    }
+}
+
+extern "C" {
+    /// Create a Compile Unit
+    ///
+    /// LLVM 11 added the `SysRoot` and `SDK` parameters to the end of
+    /// this function. Arguments are cleaned up by the caller in the C
+    /// calling convention, so always passing them is safe with older
+    /// versions of LLVM too, which just ignore them.
+    #[link_name = "LLVMDIBuilderCreateCompileUnit"]
+    fn create_compile_unit(
+        builder: LLVMDIBuilderRef,
+        lang: LLVMDWARFSourceLanguage,
+        file: LLVMMetadataRef,
+        producer: *const c_char,
+        producer_len: usize,
+        is_optimized: LLVMBool,
+        flags: *const c_char,
+        flags_len: usize,
+        runtime_ver: c_uint,
+        split_name: *const c_char,
+        split_name_len: usize,
+        kind: LLVMDWARFEmissionKind,
+        dwo_id: c_uint,
+        split_debug_inlining: LLVMBool,
+        debug_info_for_profiling: LLVMBool,
+        sys_root: *const c_char,
+        sys_root_len: usize,
+        sdk: *const c_char,
+        sdk_len: usize,
+    ) -> LLVMMetadataRef;
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::prelude::*;
+    use super::*;
+
+    #[test]
+    fn build_function_debug_info() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("debug");
+        let int_type = ctx.int_type(64);
+        let mut fun = unsafe { ctx.add_function(&mut module, "main", int_type, &mut []) };
+
+        let mut di = DebugInfoBuilder::new(&mut module);
+        let file = di.create_file("main.ulg", "/src");
+        let number = di.create_basic_type("Number", 64, Encoding::Signed);
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        unsafe {
+            let unit = di.create_compile_unit(file, "ullage", false);
+            let fn_ty = di.create_subroutine_type(file, &[number]);
+            let scope = di.create_function(unit, file, &mut fun, "main", 1, fn_ty);
+            let location = di.create_location(2, 4, scope);
+            builder.set_debug_location(location);
+            let slot = builder.build_alloca(int_type, "x");
+            let var = di.create_local_variable(scope, file, "x", 2, number);
+            di.insert_declare(slot, var, location, entry);
+            builder.build_store(ctx.const_int(1), slot);
+            let loaded = builder.build_load(slot);
+            builder.build_ret(loaded);
+        }
+        di.finalise();
+
+        module.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("!DIFile(filename: \"main.ulg\", directory: \"/src\")"),
+            "{}",
+            ir
+        );
+        assert!(ir.contains("!DISubprogram(name: \"main\""), "{}", ir);
+        assert!(ir.contains("!DILocalVariable(name: \"x\""), "{}", ir);
+        assert!(ir.contains("!DILocation(line: 2, column: 4"), "{}", ir);
+    }
+}
@@ -5,7 +5,9 @@
 use super::llvm_sys::analysis;
 use super::llvm_sys::core;
 use super::llvm_sys::prelude::*;
-use super::llvm_sys::LLVMCallConv;
+use super::llvm_sys::{LLVMAttributeFunctionIndex, LLVMAttributeIndex, LLVMCallConv};
+use super::targets::take_message;
+use std::ptr;
 
 /// Function
 ///
@@ -18,13 +20,20 @@ pub struct Function {
 
 /// Calling Contentions
 ///
-/// This is a subset of the LLVM calling contentions.
+/// This is a subset of the LLVM calling contentions. Functions which
+/// are only called from ullage code use `Fastcall`. Anything which
+/// is called from, or calls into, C code must use `CDecl`.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CallConvention {
-    /// THe `fastcall` calling contention
+    /// LLVM's `fastcc` calling convention. This lets LLVM pick the
+    /// fastest way to pass arguments, and enables tail calls.
     Fastcall,
     /// The C Calling Contention
     CDecl,
+    /// LLVM's `coldcc` calling convention, for functions which are
+    /// rarely called. Calls to these functions are made as cheap as
+    /// possible for the caller.
+    Cold,
 }
 
 impl From<CallConvention> for libc::c_uint {
@@ -32,6 +41,7 @@ impl From<CallConvention> for libc::c_uint {
         let llvm_conv = match call_convention {
             CallConvention::Fastcall => LLVMCallConv::LLVMFastCallConv,
             CallConvention::CDecl => LLVMCallConv::LLVMCCallConv,
+            CallConvention::Cold => LLVMCallConv::LLVMColdCallConv,
         };
         llvm_conv as libc::c_uint
     }
@@ -41,17 +51,73 @@ impl From<libc::c_uint> for CallConvention {
     fn from(llvm_conv: libc::c_uint) -> Self {
         if llvm_conv == LLVMCallConv::LLVMFastCallConv as libc::c_uint {
             CallConvention::Fastcall
+        } else if llvm_conv == LLVMCallConv::LLVMColdCallConv as libc::c_uint {
+            CallConvention::Cold
         } else {
             CallConvention::CDecl
         }
     }
 }
 
+/// Function Attributes
+///
+/// A subset of the LLVM attributes which can be applied to a
+/// function or to one of its parameters.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Attribute {
+    /// The function should never be inlined
+    NoInline,
+    /// The function should be inlined at every call site
+    AlwaysInline,
+    /// Calls to the function should be inlined if possible
+    InlineHint,
+    /// The function never unwinds
+    NoUnwind,
+    /// The function never returns normally
+    NoReturn,
+    /// The parameter points to where the function's result is
+    /// stored
+    StructRet,
+    /// The function's memory accesses should be checked by
+    /// AddressSanitizer
+    SanitizeAddress,
+    /// Protect the stack if the function has character arrays on it
+    StackProtect,
+    /// Protect the stack if the function has any arrays, or takes the
+    /// address of a local
+    StackProtectStrong,
+    /// Always protect the stack
+    StackProtectReq,
+}
+
+impl Attribute {
+    /// Get the LLVM Name of the Attribute
+    pub fn name(self) -> &'static str {
+        match self {
+            Attribute::NoInline => "noinline",
+            Attribute::AlwaysInline => "alwaysinline",
+            Attribute::InlineHint => "inlinehint",
+            Attribute::NoUnwind => "nounwind",
+            Attribute::NoReturn => "noreturn",
+            Attribute::StructRet => "sret",
+            Attribute::SanitizeAddress => "sanitize_address",
+            Attribute::StackProtect => "ssp",
+            Attribute::StackProtectStrong => "sspstrong",
+            Attribute::StackProtectReq => "sspreq",
+        }
+    }
+}
+
 impl Function {
     /// Wrap an Existing Funciton
     ///
     /// Takes ownership of the given function and provides more
     /// stronlgy typed access to it.
+    ///
+    /// # Safety
+    ///
+    /// The given value must be a valid LLVM function. No check is
+    /// made that the value really is a function.
     pub unsafe fn from_raw(raw: LLVMValueRef) -> Self {
         Function {
             raw,
@@ -65,16 +131,48 @@ impl Function {
     /// valid we will panic to signal the error. This is intended for
     /// debugging the compiler's output.
     pub fn verify_or_panic(&self) {
+        if let Err(message) = self.verify() {
+            unsafe {
+                core::LLVMDumpValue(self.raw);
+            }
+            panic!("Function failed verification!\n{}", message)
+        }
+    }
+
+    /// Verify the Function, Returning any Problems
+    ///
+    /// Makes LLVM check the function is valid. If it isn't then the
+    /// verifier's description of the problems is returned. LLVM can
+    /// only describe the problems for a whole module, so the message
+    /// may mention other functions in the same module too.
+    pub fn verify(&self) -> Result<(), String> {
         use self::analysis::*;
         let verified = unsafe {
-            LLVMVerifyFunction(self.raw, LLVMVerifierFailureAction::LLVMPrintMessageAction)
+            LLVMVerifyFunction(self.raw, LLVMVerifierFailureAction::LLVMReturnStatusAction)
         };
+        if verified == 0 {
+            return Ok(());
+        }
 
-        if verified == 1 {
-            unsafe {
-                core::LLVMDumpValue(self.raw);
-            }
-            panic!("Function failed verification!")
+        let module = unsafe { core::LLVMGetGlobalParent(self.raw) };
+        let mut message = ptr::null_mut();
+        unsafe {
+            LLVMVerifyModule(
+                module,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            );
+            Err(take_message(message))
+        }
+    }
+
+    /// Get the Function's Name
+    pub fn name(&self) -> String {
+        unsafe {
+            let mut len = 0;
+            let name = core::LLVMGetValueName2(self.raw, &mut len);
+            let bytes = std::slice::from_raw_parts(name as *const u8, len);
+            String::from_utf8_lossy(bytes).into_owned()
         }
     }
 
@@ -82,8 +180,96 @@ impl Function {
     ///
     /// Returns a value that can be used to access the `n`th function
     /// argument.
+    ///
+    /// # Panics
+    ///
+    /// If the function has `n` or fewer parameters.
     pub fn get_param(&self, n: u32) -> LLVMValueRef {
-        unsafe { core::LLVMGetParam(self.as_raw(), n) }
+        unsafe {
+            let count = core::LLVMCountParams(self.as_raw());
+            assert!(
+                n < count,
+                "parameter {} out of range for {} parameters",
+                n,
+                count
+            );
+            core::LLVMGetParam(self.as_raw(), n)
+        }
+    }
+
+    /// Add an Attribute to the Function
+    pub fn add_attribute(&mut self, attr: Attribute) {
+        self.add_attribute_at(LLVMAttributeFunctionIndex, attr);
+    }
+
+    /// Add an Attribute to a Parameter
+    ///
+    /// Parameters are numbered from 0, as with `get_param`.
+    pub fn add_param_attribute(&mut self, n: u32, attr: Attribute) {
+        self.add_attribute_at(n + 1, attr);
+    }
+
+    /// Add a String Attribute to the Function
+    ///
+    /// String attributes are `key=value` pairs which configure code
+    /// generation for the function, e.g. `probe-stack`.
+    pub fn add_string_attribute(&mut self, key: &str, value: &str) {
+        unsafe {
+            let ctx = core::LLVMGetTypeContext(core::LLVMTypeOf(self.raw));
+            let llvm_attr = core::LLVMCreateStringAttribute(
+                ctx,
+                key.as_ptr() as *const _,
+                key.len() as libc::c_uint,
+                value.as_ptr() as *const _,
+                value.len() as libc::c_uint,
+            );
+            core::LLVMAddAttributeAtIndex(self.raw, LLVMAttributeFunctionIndex, llvm_attr);
+        }
+    }
+
+    /// Get the Value of a String Attribute
+    ///
+    /// Returns `None` if the function doesn't have the attribute.
+    pub fn string_attribute(&self, key: &str) -> Option<String> {
+        unsafe {
+            let attr = core::LLVMGetStringAttributeAtIndex(
+                self.raw,
+                LLVMAttributeFunctionIndex,
+                key.as_ptr() as *const _,
+                key.len() as libc::c_uint,
+            );
+            if attr.is_null() {
+                return None;
+            }
+            let mut len = 0;
+            let value = core::LLVMGetStringAttributeValue(attr, &mut len);
+            let bytes = std::slice::from_raw_parts(value as *const u8, len as usize);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+
+    /// Check if the Function has a Given Attribute
+    pub fn has_attribute(&self, attr: Attribute) -> bool {
+        self.has_attribute_at(LLVMAttributeFunctionIndex, attr)
+    }
+
+    /// Check if a Parameter has a Given Attribute
+    pub fn param_has_attribute(&self, n: u32, attr: Attribute) -> bool {
+        self.has_attribute_at(n + 1, attr)
+    }
+
+    fn add_attribute_at(&mut self, index: LLVMAttributeIndex, attr: Attribute) {
+        unsafe {
+            let ctx = core::LLVMGetTypeContext(core::LLVMTypeOf(self.raw));
+            let llvm_attr = core::LLVMCreateEnumAttribute(ctx, attribute_kind(attr), 0);
+            core::LLVMAddAttributeAtIndex(self.raw, index, llvm_attr);
+        }
+    }
+
+    fn has_attribute_at(&self, index: LLVMAttributeIndex, attr: Attribute) -> bool {
+        let found =
+            unsafe { core::LLVMGetEnumAttributeAtIndex(self.raw, index, attribute_kind(attr)) };
+        !found.is_null()
     }
 
     /// Raw Borrow
@@ -118,3 +304,94 @@ impl Function {
         }
     }
 }
+
+/// Look up the LLVM Kind ID for an Attribute
+fn attribute_kind(attr: Attribute) -> libc::c_uint {
+    let name = attr.name();
+    unsafe { core::LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len()) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::prelude::*;
+    use super::*;
+
+    #[test]
+    fn add_function_and_param_attributes() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("attrs");
+        let pair = unsafe { ctx.struct_type(&[ctx.int_type(64), ctx.int_type(64)]) };
+        let pair_ptr = unsafe { ctx.pointer_type(pair) };
+        let mut fun =
+            unsafe { ctx.add_function(&mut module, "make_pair", ctx.void_type(), &mut [pair_ptr]) };
+        fun.add_attribute(Attribute::NoInline);
+        fun.add_attribute(Attribute::NoUnwind);
+        fun.add_param_attribute(0, Attribute::StructRet);
+
+        assert!(fun.has_attribute(Attribute::NoInline));
+        assert!(fun.has_attribute(Attribute::NoUnwind));
+        assert!(!fun.has_attribute(Attribute::AlwaysInline));
+        assert!(fun.param_has_attribute(0, Attribute::StructRet));
+        assert!(!fun.param_has_attribute(0, Attribute::NoReturn));
+
+        fun.add_string_attribute("probe-stack", "inline-asm");
+        assert_eq!(
+            Some("inline-asm".to_string()),
+            fun.string_attribute("probe-stack")
+        );
+        assert_eq!(None, fun.string_attribute("stack-probe-size"));
+
+        let entry = ctx.add_block(&mut fun, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        builder.build_ret_void();
+        fun.verify_or_panic();
+    }
+
+    #[test]
+    fn verify_reports_invalid_function() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("verify");
+        let int_type = ctx.int_type(64);
+        let mut fun = unsafe { ctx.add_function(&mut module, "broken", int_type, &mut []) };
+        // A block without a terminator isn't valid
+        ctx.add_block(&mut fun, "entry");
+
+        assert_eq!("broken", fun.name());
+        let message = fun.verify().unwrap_err();
+        assert!(message.contains("terminator"), "{}", message);
+        assert!(module.verify().is_err());
+    }
+
+    #[test]
+    fn set_calling_conventions() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("conventions");
+        let int_type = ctx.int_type(64);
+        let mut callee = unsafe { ctx.add_function(&mut module, "callee", int_type, &mut []) };
+        assert_eq!(CallConvention::CDecl, callee.call_conv());
+        callee.set_calling_convention(CallConvention::Fastcall);
+        let entry = ctx.add_block(&mut callee, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe {
+            builder.position_at_end(entry);
+            builder.build_ret(ctx.const_int(1));
+        }
+
+        let mut caller = unsafe { ctx.add_function(&mut module, "caller", int_type, &mut []) };
+        caller.set_calling_convention(CallConvention::Cold);
+        let entry = ctx.add_block(&mut caller, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        let result = unsafe { builder.build_call(&callee, &mut []) };
+        unsafe { builder.build_ret(result) };
+
+        let found = module.find_function("caller").unwrap();
+        assert_eq!(CallConvention::Cold, found.call_conv());
+        module.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(ir.contains("define fastcc i64 @callee()"), "{}", ir);
+        assert!(ir.contains("call fastcc i64 @callee()"), "{}", ir);
+        assert!(ir.contains("define coldcc i64 @caller()"), "{}", ir);
+    }
+}
@@ -0,0 +1,189 @@
+//! LLVM Intrinsics
+//!
+//! Looks up declarations of LLVM's intrinsic functions. Most
+//! intrinsics are overloaded on the types of their arguments and the
+//! overload types are mangled into the declaration's name. Rather
+//! than building these names by hand LLVM is asked to declare each
+//! intrinsic for a given set of overload types.
+//!
+//! Typed helpers for calling the intrinsics are available on the
+//! `Builder`.
+
+use super::function::Function;
+use super::llvm_sys::core;
+use super::llvm_sys::prelude::*;
+use super::module::Module;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// Intrinsic
+///
+/// The intrinsic functions we know how to declare.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Intrinsic {
+    /// Copy a block of memory. Overloaded on the destination, source,
+    /// and length types.
+    Memcpy,
+    /// Fill a block of memory with a byte. Overloaded on the
+    /// destination and length types.
+    Memset,
+    /// Raise a floating point value to an integer power. Overloaded
+    /// on the floating point type.
+    Powi,
+    /// Count the set bits in an integer. Overloaded on the integer
+    /// type.
+    Ctpop,
+    /// Signed addition returning an overflow flag
+    SAddWithOverflow,
+    /// Unsigned addition returning an overflow flag
+    UAddWithOverflow,
+    /// Signed subtraction returning an overflow flag
+    SSubWithOverflow,
+    /// Unsigned subtraction returning an overflow flag
+    USubWithOverflow,
+    /// Signed multiplication returning an overflow flag
+    SMulWithOverflow,
+    /// Unsigned multiplication returning an overflow flag
+    UMulWithOverflow,
+    /// Abort the program with a trap instruction. Not overloaded.
+    Trap,
+}
+
+impl Intrinsic {
+    /// Get the Base Name of the Intrinsic
+    ///
+    /// This is the name without any of the mangled overload types.
+    pub fn name(self) -> &'static str {
+        match self {
+            Intrinsic::Memcpy => "llvm.memcpy",
+            Intrinsic::Memset => "llvm.memset",
+            Intrinsic::Powi => "llvm.powi",
+            Intrinsic::Ctpop => "llvm.ctpop",
+            Intrinsic::SAddWithOverflow => "llvm.sadd.with.overflow",
+            Intrinsic::UAddWithOverflow => "llvm.uadd.with.overflow",
+            Intrinsic::SSubWithOverflow => "llvm.ssub.with.overflow",
+            Intrinsic::USubWithOverflow => "llvm.usub.with.overflow",
+            Intrinsic::SMulWithOverflow => "llvm.smul.with.overflow",
+            Intrinsic::UMulWithOverflow => "llvm.umul.with.overflow",
+            Intrinsic::Trap => "llvm.trap",
+        }
+    }
+}
+
+/// Declare an Intrinsic in a Module
+///
+/// Returns the declaration of the intrinsic for the given overload
+/// types, adding it to the module if it isn't already there.
+///
+/// # Safety
+///
+/// `overloads` must be the types `intrinsic` is overloaded on, and
+/// belong to `module`'s context.
+pub unsafe fn declare(
+    module: &mut Module,
+    intrinsic: Intrinsic,
+    overloads: &[LLVMTypeRef],
+) -> Function {
+    unsafe { declare_in(module.as_raw(), intrinsic, overloads) }
+}
+
+/// Declare an Intrinsic in a Raw Module
+///
+/// # Safety
+///
+/// The module must be valid, and the overload types must belong to
+/// the module's context.
+pub(super) unsafe fn declare_in(
+    module: LLVMModuleRef,
+    intrinsic: Intrinsic,
+    overloads: &[LLVMTypeRef],
+) -> Function {
+    let mut overloads = overloads.to_vec();
+    // LLVM 13 started overloading `powi` on the exponent type too.
+    if intrinsic == Intrinsic::Powi && powi_overloads_exponent() {
+        overloads.push(core::LLVMInt32TypeInContext(core::LLVMGetModuleContext(
+            module,
+        )));
+    }
+    let decl = core::LLVMGetIntrinsicDeclaration(
+        module,
+        intrinsic_id(module, intrinsic),
+        overloads.as_mut_ptr(),
+        overloads.len(),
+    );
+    Function::from_raw(decl)
+}
+
+/// Look up the ID of an Intrinsic
+///
+/// Intrinsic IDs change between LLVM versions, and the C API can't
+/// look them up by name in all the versions we support. LLVM does
+/// recognise intrinsics by name when a function is created though,
+/// so a placeholder function is used to find the ID.
+unsafe fn intrinsic_id(module: LLVMModuleRef, intrinsic: Intrinsic) -> libc::c_uint {
+    let ctx = core::LLVMGetModuleContext(module);
+    let fn_type = core::LLVMFunctionType(core::LLVMVoidTypeInContext(ctx), [].as_mut_ptr(), 0, 0);
+    let name = CString::new(intrinsic.name()).unwrap();
+    let placeholder = core::LLVMAddFunction(module, name.as_ptr(), fn_type);
+    let id = core::LLVMGetIntrinsicID(placeholder);
+    core::LLVMDeleteFunction(placeholder);
+    id
+}
+
+/// Check if `powi` is Overloaded on the Exponent
+///
+/// Calls `powi` by its old name in a scratch module and checks if the
+/// verifier accepts it. Intrinsic names are only checked at call
+/// sites, so a declaration alone isn't enough. The result is cached.
+fn powi_overloads_exponent() -> bool {
+    use super::context::Context;
+    static CHECK: Once = Once::new();
+    static OVERLOADED: AtomicBool = AtomicBool::new(false);
+
+    CHECK.call_once(|| {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("powi_check");
+        let double = unsafe { core::LLVMDoubleTypeInContext(ctx.as_raw()) };
+        let int32 = ctx.int_type(32);
+        let powi =
+            unsafe { ctx.add_function(&mut module, "llvm.powi.f64", double, &mut [double, int32]) };
+        let mut check = unsafe { ctx.add_function(&mut module, "check", double, &mut []) };
+        let entry = ctx.add_block(&mut check, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe { builder.position_at_end(entry) };
+        let base = unsafe { core::LLVMConstReal(double, 1.0) };
+        let result = unsafe { builder.build_call(&powi, &mut [base, ctx.const_int_width(2, 32)]) };
+        unsafe { builder.build_ret(result) };
+        OVERLOADED.store(module.verify().is_err(), Ordering::SeqCst);
+    });
+    OVERLOADED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::prelude::*;
+    use super::*;
+
+    #[test]
+    fn declare_overloaded_intrinsics() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("intrinsics");
+        let i8ptr = unsafe { ctx.pointer_type(ctx.int_type(8)) };
+        let int64 = ctx.int_type(64);
+
+        let memcpy = unsafe { declare(&mut module, Intrinsic::Memcpy, &[i8ptr, i8ptr, int64]) };
+        assert_eq!("llvm.memcpy.p0i8.p0i8.i64", memcpy.name());
+        let ctpop = unsafe { declare(&mut module, Intrinsic::Ctpop, &[int64]) };
+        assert_eq!("llvm.ctpop.i64", ctpop.name());
+        let again = unsafe { declare(&mut module, Intrinsic::Ctpop, &[int64]) };
+        assert_eq!(ctpop, again);
+        let trap = unsafe { declare(&mut module, Intrinsic::Trap, &[]) };
+        assert_eq!("llvm.trap", trap.name());
+        let double = unsafe { core::LLVMDoubleTypeInContext(ctx.as_raw()) };
+        let powi = unsafe { declare(&mut module, Intrinsic::Powi, &[double]) };
+        assert!(powi.name().starts_with("llvm.powi.f64"));
+
+        module.verify_or_panic();
+    }
+}
@@ -0,0 +1,165 @@
+//! JIT Execution Engine Wrapper
+//!
+//! Contains a wrapper around an LLVM execution engine which compiles
+//! modules to machine code in memory so they can be run in-process.
+//! The engine is built on MCJIT, as its C API is the same across all
+//! the LLVM versions we support.
+
+use super::context::Context;
+use super::llvm_sys::core::LLVMGetModuleContext;
+use super::llvm_sys::execution_engine::*;
+use super::llvm_sys::support::LLVMAddSymbol;
+use super::module::Module;
+use super::targets::take_message;
+use std::ffi::{c_void, CString};
+use std::mem;
+use std::ptr;
+
+/// JIT
+///
+/// Owns a `Context` and the execution engine which compiles the
+/// modules added to it. Modules should be created in the JIT's own
+/// context, which can be accessed with `context`.
+pub struct Jit {
+    engine: LLVMExecutionEngineRef,
+    ctx: Context,
+}
+
+impl Jit {
+    /// Create a JIT
+    ///
+    /// Takes ownership of the given context. The context must live
+    /// as long as any code compiled by the engine, so it is only
+    /// disposed of when the JIT is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If the execution engine can't be created for the host then
+    /// LLVM's description of the problem is returned.
+    pub fn new(mut ctx: Context) -> Result<Self, String> {
+        // The engine needs a module to be created. Start it with an
+        // empty one which later modules are added alongside.
+        let module = ctx.add_module("jit");
+        unsafe {
+            LLVMLinkInMCJIT();
+            let mut options: LLVMMCJITCompilerOptions = mem::zeroed();
+            LLVMInitializeMCJITCompilerOptions(&mut options, mem::size_of_val(&options));
+            let mut engine = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            let failed = LLVMCreateMCJITCompilerForModule(
+                &mut engine,
+                module.into(),
+                &mut options,
+                mem::size_of_val(&options),
+                &mut error,
+            );
+            if failed != 0 {
+                return Err(take_message(error));
+            }
+            Ok(Jit { engine, ctx })
+        }
+    }
+
+    /// Get the JIT's Context
+    ///
+    /// Modules passed to `add_module` must be created in this
+    /// context.
+    pub fn context(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+
+    /// Add a Module to the JIT
+    ///
+    /// The engine takes ownership of the module. Its functions are
+    /// compiled the first time a symbol is looked up.
+    ///
+    /// # Errors
+    ///
+    /// If the module wasn't created in the JIT's context it is
+    /// rejected and an error returned.
+    pub fn add_module(&mut self, module: Module) -> Result<(), String> {
+        unsafe {
+            if LLVMGetModuleContext(module.as_raw()) != self.ctx.as_raw() {
+                return Err("module was not created in the JIT's context".into());
+            }
+            LLVMAddModule(self.engine, module.into());
+        }
+        Ok(())
+    }
+
+    /// Add a Symbol for the JIT to Resolve
+    ///
+    /// Calls from compiled code to the function `name` are resolved to
+    /// `address`. Symbols are shared by every JIT in the process.
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to a function which matches how compiled
+    /// code calls `name`, and must stay valid for as long as any JIT
+    /// code which calls it.
+    pub unsafe fn add_symbol(&mut self, name: &str, address: *mut c_void) {
+        let name = CString::new(name).unwrap();
+        LLVMAddSymbol(name.as_ptr(), address);
+    }
+
+    /// Look Up a Compiled Function
+    ///
+    /// Returns a pointer to the function with the given name, cast to
+    /// `F`. If there is no function with that name then `None` is
+    /// returned.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be an `extern "C"` function pointer type which
+    /// matches the signature of the generated function. The pointer
+    /// must not be called after the JIT is dropped.
+    pub unsafe fn lookup<F: Copy>(&self, name: &str) -> Option<F> {
+        assert_eq!(
+            mem::size_of::<F>(),
+            mem::size_of::<usize>(),
+            "JIT lookups must be cast to a function pointer"
+        );
+        let name = CString::new(name).unwrap();
+        match LLVMGetFunctionAddress(self.engine, name.as_ptr()) {
+            0 => None,
+            address => Some(mem::transmute_copy(&(address as usize))),
+        }
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // The engine owns the modules added to it, and must be
+        // disposed of before the context they were created in.
+        unsafe { LLVMDisposeExecutionEngine(self.engine) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn jit_compiled_function() {
+        let mut jit = Jit::new(Context::new()).unwrap();
+        let module = {
+            let ctx = jit.context();
+            let mut module = ctx.add_module("adder");
+            let int_type = ctx.int_type(64);
+            let mut fun = unsafe {
+                ctx.add_function(&mut module, "add", int_type, &mut [int_type, int_type])
+            };
+            let entry = ctx.add_block(&mut fun, "entry");
+            let mut builder = ctx.add_builder();
+            unsafe { builder.position_at_end(entry) };
+            let sum = unsafe { builder.build_add(fun.get_param(0), fun.get_param(1)) };
+            unsafe { builder.build_ret(sum) };
+            module
+        };
+        jit.add_module(module).unwrap();
+
+        let add: extern "C" fn(i64, i64) -> i64 = unsafe { jit.lookup("add") }.unwrap();
+        assert_eq!(42, add(40, 2));
+        assert!(unsafe { jit.lookup::<extern "C" fn()>("missing") }.is_none());
+    }
+}
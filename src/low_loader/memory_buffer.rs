@@ -0,0 +1,68 @@
+//! LLVM Memory Buffer Wrapper
+//!
+//! Contains a Rust wrapper for LLVM memory buffers. These are
+//! returned by LLVM when emitting bitcode or object code into memory
+//! rather than writing it out to a file.
+
+use super::llvm_sys::core;
+use super::llvm_sys::prelude::*;
+
+use std::slice;
+
+/// Memory Buffer
+///
+/// An owned block of bytes allocated by LLVM. The buffer is disposed
+/// of when this object leaves scope.
+#[derive(Debug, PartialEq)]
+pub struct MemoryBuffer {
+    raw: LLVMMemoryBufferRef,
+}
+
+impl MemoryBuffer {
+    /// Create a Memory Buffer from a Raw Pointer
+    ///
+    /// Takes ownership of the given buffer pointer.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid, non-null, memory buffer
+    /// reference which isn't owned by anything else.
+    pub unsafe fn from_raw(raw: LLVMMemoryBufferRef) -> Self {
+        MemoryBuffer { raw }
+    }
+
+    /// Get the Size of the Buffer in Bytes
+    pub fn len(&self) -> usize {
+        unsafe { core::LLVMGetBufferSize(self.raw) }
+    }
+
+    /// Is the Buffer Empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Borrow the Contents of the Buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.len();
+        if len == 0 {
+            return &[];
+        }
+        unsafe {
+            let start = core::LLVMGetBufferStart(self.raw) as *const u8;
+            slice::from_raw_parts(start, len)
+        }
+    }
+}
+
+impl Drop for MemoryBuffer {
+    fn drop(&mut self) {
+        unsafe { core::LLVMDisposeMemoryBuffer(self.raw) }
+    }
+}
+
+impl From<MemoryBuffer> for Vec<u8> {
+    /// Copy the Buffer's Contents into a Vector
+    fn from(buffer: MemoryBuffer) -> Vec<u8> {
+        buffer.as_bytes().to_vec()
+    }
+}
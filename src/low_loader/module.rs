@@ -4,11 +4,15 @@
 
 use super::function::Function;
 use super::llvm_sys::prelude::*;
-use super::llvm_sys::{analysis, core};
+use super::llvm_sys::{
+    analysis, bit_writer, core, linker, target_machine, LLVMLinkage, LLVMModuleFlagBehavior,
+};
+use super::memory_buffer::MemoryBuffer;
 use super::pass_manager::{OptLevel, OptSize, PassManagerBuilder};
-use super::targets::Target;
+use super::targets::{take_message, TargetMachine};
 
 use std::ffi::{CStr, CString};
+use std::os::raw::c_uint;
 use std::path::Path;
 use std::ptr;
 
@@ -31,26 +35,96 @@ impl Module {
     ///
     /// *Note*: You shouldn't need to use this directly, instead modules
     /// can be created with `Context::add_module`.
-    pub fn from_raw(mod_ref: LLVMModuleRef) -> Self {
+    ///
+    /// # Safety
+    ///
+    /// `mod_ref` must be a valid module which nothing else owns or
+    /// disposes of.
+    pub unsafe fn from_raw(mod_ref: LLVMModuleRef) -> Self {
         Module { raw: mod_ref }
     }
 
     /// Set the Modules's Target
     ///
-    /// Defines which targe the module is being comiled for. This can
-    /// enable target-specific optimisations in the compilation of
-    /// this module.
-    pub fn set_target(&mut self, target: &Target) {
-        let triple = CString::new(target.norm_triple()).unwrap();
+    /// Defines which targe the module is being comiled for by setting
+    /// the module's triple and data layout to match the given
+    /// machine. This can enable target-specific optimisations in the
+    /// compilation of this module, so should be done before it is
+    /// optimised.
+    pub fn set_target(&mut self, machine: &TargetMachine) {
+        let triple = CString::new(machine.triple()).unwrap();
+        let layout = CString::new(machine.data_layout()).unwrap();
+        unsafe {
+            let norm_triple =
+                take_message(target_machine::LLVMNormalizeTargetTriple(triple.as_ptr()));
+            let norm_triple = CString::new(norm_triple).unwrap();
+            core::LLVMSetTarget(self.as_raw(), norm_triple.as_ptr());
+            core::LLVMSetDataLayout(self.as_raw(), layout.as_ptr());
+        }
+    }
+
+    /// Set the Module's Source File Name
+    ///
+    /// Records the name of the file the module was compiled from. It
+    /// is shown as the `source_filename` in the module's IR.
+    pub fn set_source_file_name(&mut self, name: &str) {
+        unsafe { core::LLVMSetSourceFileName(self.as_raw(), name.as_ptr() as *const _, name.len()) }
+    }
+
+    /// Get the Module's Source File Name
+    pub fn source_file_name(&self) -> String {
         unsafe {
-            core::LLVMSetTarget(self.as_raw(), triple.as_ptr());
+            let mut len = 0;
+            let name = core::LLVMGetSourceFileName(self.as_raw(), &mut len);
+            let name = std::slice::from_raw_parts(name as *const u8, len);
+            String::from_utf8_lossy(name).into_owned()
+        }
+    }
+
+    /// Add a Producer Ident to the Module
+    ///
+    /// Appends `producer` to the module's `llvm.ident` list. This
+    /// names the compiler which produced the module, and is written
+    /// to the `.comment` section of object files.
+    pub fn add_ident(&mut self, producer: &str) {
+        let name = CString::new("llvm.ident").unwrap();
+        unsafe {
+            let ctx = core::LLVMGetModuleContext(self.raw);
+            let mut ident = [core::LLVMMDStringInContext(
+                ctx,
+                producer.as_ptr() as *const _,
+                producer.len() as c_uint,
+            )];
+            let node = core::LLVMMDNodeInContext(ctx, ident.as_mut_ptr(), 1);
+            core::LLVMAddNamedMetadataOperand(self.raw, name.as_ptr(), node);
+        }
+    }
+
+    /// Add a Module Flag
+    ///
+    /// Records an integer `value` under `key` in the module's
+    /// `llvm.module.flags`. If modules with different values for the
+    /// flag are linked together LLVM warns, and keeps the first.
+    pub fn add_flag(&mut self, key: &str, value: u32) {
+        unsafe {
+            let ctx = core::LLVMGetModuleContext(self.raw);
+            let value = core::LLVMConstInt(core::LLVMInt32TypeInContext(ctx), u64::from(value), 0);
+            core::LLVMAddModuleFlag(
+                self.raw,
+                LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+                key.as_ptr() as *const _,
+                key.len(),
+                core::LLVMValueAsMetadata(value),
+            );
         }
     }
 
     /// Dump the Module
     ///
-    /// Writes a representation of the module to standard output. This
-    /// is intended to be used as an aid to debugging.
+    /// Writes a representation of the module to standard error. This
+    /// is intended to be used as an aid to debugging. LLVM writes the
+    /// output directly, so it can't be captured. Use `to_ir_string`
+    /// to get the IR as a `String` instead.
     pub fn dump(&self) {
         unsafe { core::LLVMDumpModule(self.raw) }
     }
@@ -59,15 +133,29 @@ impl Module {
     ///
     /// Checks that the whole module is valid before continuing
     pub fn verify_or_panic(&self) {
-        let verified = unsafe {
-            analysis::LLVMVerifyModule(
+        if let Err(message) = self.verify() {
+            panic!("Module failed validation\n{}", message);
+        }
+    }
+
+    /// Verify the Module, Returning any Problems
+    ///
+    /// Checks that the whole module is valid. If it isn't then the
+    /// verifier's description of the problems is returned.
+    pub fn verify(&self) -> Result<(), String> {
+        let mut message = ptr::null_mut();
+        unsafe {
+            let verified = analysis::LLVMVerifyModule(
                 self.as_raw(),
-                analysis::LLVMVerifierFailureAction::LLVMPrintMessageAction,
-                ptr::null_mut(),
-            )
-        };
-        if verified != 0 {
-            panic!("Module failed validation");
+                analysis::LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            );
+            let message = take_message(message);
+            if verified == 0 {
+                Ok(())
+            } else {
+                Err(message)
+            }
         }
     }
 
@@ -105,6 +193,84 @@ impl Module {
         }
     }
 
+    /// Get the Module's IR as a String
+    ///
+    /// Returns the textual LLVM IR representation of the module.
+    pub fn to_ir_string(&self) -> String {
+        unsafe {
+            let message = core::LLVMPrintModuleToString(self.raw);
+            let ir = CStr::from_ptr(message).to_string_lossy().into_owned();
+            core::LLVMDisposeMessage(message);
+            ir
+        }
+    }
+
+    /// Write the Module to the Given File as LLVM Bitcode
+    pub fn write_bitcode_to_file(&self, path: &Path) -> Result<(), String> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| format!("path '{}' is not valid unicode", path.display()))?;
+        let path = CString::new(path).map_err(|e| e.to_string())?;
+        let r = unsafe { bit_writer::LLVMWriteBitcodeToFile(self.raw, path.as_ptr()) };
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "could not write bitcode to '{}'",
+                path.to_string_lossy()
+            ))
+        }
+    }
+
+    /// Write the Module as Bitcode to a Memory Buffer
+    pub fn to_bitcode_buffer(&self) -> MemoryBuffer {
+        unsafe { MemoryBuffer::from_raw(bit_writer::LLVMWriteBitcodeToMemoryBuffer(self.raw)) }
+    }
+
+    /// Link Another Module into this One
+    ///
+    /// Merges the definitions from `other` into this module. The
+    /// other module is consumed by the link. Both modules must belong
+    /// to the same `Context`.
+    ///
+    /// # Errors
+    ///
+    /// If the modules can't be linked, for example because both
+    /// define the same symbol, then an error is returned. The details
+    /// of the problem are reported through the context's diagnostics.
+    /// Modules from different contexts are never linked.
+    pub fn link_in(&mut self, other: Module) -> Result<(), String> {
+        let failed = unsafe {
+            if core::LLVMGetModuleContext(self.raw) != core::LLVMGetModuleContext(other.raw) {
+                return Err("can't link modules from different contexts".into());
+            }
+            linker::LLVMLinkModules2(self.raw, other.into())
+        };
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err("failed to link modules".into())
+        }
+    }
+
+    /// Append Module-Level Inline Assembly
+    ///
+    /// Adds the given assembly to the module's top-level assembly
+    /// block. It is emitted as-is into the generated object.
+    pub fn append_inline_asm(&mut self, asm: &str) {
+        unsafe { core::LLVMAppendModuleInlineAsm(self.raw, asm.as_ptr() as *const _, asm.len()) }
+    }
+
+    /// Get the Module-Level Inline Assembly
+    pub fn inline_asm(&self) -> String {
+        unsafe {
+            let mut len = 0;
+            let asm = core::LLVMGetModuleInlineAsm(self.raw, &mut len);
+            let bytes = std::slice::from_raw_parts(asm as *const u8, len);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    }
+
     /// Find a Function by Name
     pub fn find_function(&self, name: &str) -> Option<Function> {
         let function_name = CString::new(name).unwrap();
@@ -119,7 +285,11 @@ impl Module {
     }
 
     /// Add a Global Variable
-    pub fn add_global(&mut self, initialiser: LLVMValueRef, name: &str) -> LLVMValueRef {
+    ///
+    /// # Safety
+    ///
+    /// `initialiser` must be a constant.
+    pub unsafe fn add_global(&mut self, initialiser: LLVMValueRef, name: &str) -> LLVMValueRef {
         let global_name = CString::new(name).unwrap();
         unsafe {
             let global = core::LLVMAddGlobal(
@@ -132,6 +302,147 @@ impl Module {
         }
     }
 
+    /// Add a Thread-Local Global Variable
+    ///
+    /// Creates a global where each thread gets its own copy, starting
+    /// from the given initialiser.
+    ///
+    /// # Safety
+    ///
+    /// `initialiser` must be a constant.
+    pub unsafe fn add_thread_local_global(
+        &mut self,
+        initialiser: LLVMValueRef,
+        name: &str,
+    ) -> LLVMValueRef {
+        let global = self.add_global(initialiser, name);
+        unsafe { core::LLVMSetThreadLocal(global, 1) };
+        global
+    }
+
+    /// Add a Constant Array Global
+    ///
+    /// Creates a global initialised with an array of the given
+    /// values. The global is marked as constant so it can be placed in
+    /// read-only memory. Used to emit array literals and string tables
+    /// without having to store each element individually.
+    ///
+    /// # Safety
+    ///
+    /// `values` must all be constants of type `elem_ty`.
+    pub unsafe fn add_global_array(
+        &mut self,
+        elem_ty: LLVMTypeRef,
+        values: &[LLVMValueRef],
+        name: &str,
+    ) -> LLVMValueRef {
+        let mut values = values.to_vec();
+        unsafe {
+            let initialiser =
+                core::LLVMConstArray(elem_ty, values.as_mut_ptr(), values.len() as c_uint);
+            let global = self.add_global(initialiser, name);
+            core::LLVMSetGlobalConstant(global, 1);
+            global
+        }
+    }
+
+    /// Register a Global Constructor
+    ///
+    /// Adds the function to the module's `llvm.global_ctors` list so
+    /// it is run before `main`. Constructors with a lower `priority`
+    /// run first. The function must take no parameters and return
+    /// `void`.
+    pub fn add_global_constructor(&mut self, function: &Function, priority: u32) {
+        let name = CString::new("llvm.global_ctors").unwrap();
+        unsafe {
+            let raw_fn = function.as_raw();
+            let ctx = core::LLVMGetModuleContext(self.raw);
+            let int32 = core::LLVMInt32TypeInContext(ctx);
+            let i8ptr = core::LLVMPointerType(core::LLVMInt8TypeInContext(ctx), 0);
+            let mut fields = [int32, core::LLVMTypeOf(raw_fn), i8ptr];
+            let entry_ty = core::LLVMStructTypeInContext(ctx, fields.as_mut_ptr(), 3, 0);
+            let mut values = [
+                core::LLVMConstInt(int32, u64::from(priority), 0),
+                raw_fn,
+                core::LLVMConstNull(i8ptr),
+            ];
+            let entry = core::LLVMConstNamedStruct(entry_ty, values.as_mut_ptr(), 3);
+
+            // The list's type depends on its length, so adding an
+            // entry means replacing the existing global.
+            let mut entries = Vec::new();
+            let existing = core::LLVMGetNamedGlobal(self.raw, name.as_ptr());
+            if !existing.is_null() {
+                let init = core::LLVMGetInitializer(existing);
+                for i in 0..core::LLVMGetNumOperands(init) {
+                    entries.push(core::LLVMGetOperand(init, i as c_uint));
+                }
+                core::LLVMDeleteGlobal(existing);
+            }
+            entries.push(entry);
+
+            let initialiser =
+                core::LLVMConstArray(entry_ty, entries.as_mut_ptr(), entries.len() as c_uint);
+            let global = self.add_global(initialiser, "llvm.global_ctors");
+            core::LLVMSetLinkage(global, LLVMLinkage::LLVMAppendingLinkage);
+        }
+    }
+
+    /// Mark a Global as Used
+    ///
+    /// Adds the global to the module's `llvm.used` list. Neither the
+    /// optimiser nor the linker's garbage collection of unreferenced
+    /// sections will remove it, even if nothing refers to it.
+    ///
+    /// # Safety
+    ///
+    /// `global` must be a global value defined in this module.
+    pub unsafe fn mark_used(&mut self, global: LLVMValueRef) {
+        let name = CString::new("llvm.used").unwrap();
+        let section = CString::new("llvm.metadata").unwrap();
+        unsafe {
+            let ctx = core::LLVMGetModuleContext(self.raw);
+            let i8ptr = core::LLVMPointerType(core::LLVMInt8TypeInContext(ctx), 0);
+
+            // As with the constructors the list's type depends on its
+            // length, so it is replaced rather than added to.
+            let mut entries = Vec::new();
+            let existing = core::LLVMGetNamedGlobal(self.raw, name.as_ptr());
+            if !existing.is_null() {
+                let init = core::LLVMGetInitializer(existing);
+                for i in 0..core::LLVMGetNumOperands(init) {
+                    entries.push(core::LLVMGetOperand(init, i as c_uint));
+                }
+                core::LLVMDeleteGlobal(existing);
+            }
+            entries.push(core::LLVMConstBitCast(global, i8ptr));
+
+            let initialiser =
+                core::LLVMConstArray(i8ptr, entries.as_mut_ptr(), entries.len() as c_uint);
+            let used = self.add_global(initialiser, "llvm.used");
+            core::LLVMSetLinkage(used, LLVMLinkage::LLVMAppendingLinkage);
+            core::LLVMSetSection(used, section.as_ptr());
+        }
+    }
+
+    /// Get the Functions Defined in the Module
+    ///
+    /// Returns each function which has a body. Functions which are
+    /// only declared are skipped.
+    pub fn defined_functions(&self) -> Vec<Function> {
+        let mut functions = Vec::new();
+        unsafe {
+            let mut current = core::LLVMGetFirstFunction(self.as_raw());
+            while !current.is_null() {
+                if core::LLVMIsDeclaration(current) == 0 {
+                    functions.push(Function::from_raw(current));
+                }
+                current = core::LLVMGetNextFunction(current);
+            }
+        }
+        functions
+    }
+
     /// Find a Global Variable in the Module by Name
     ///
     /// Looks up a given global variale in the module and returns
@@ -180,3 +491,224 @@ impl From<Module> for LLVMModuleRef {
         mod_ref
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::llvm_sys::core;
+    use super::super::prelude::*;
+    use super::super::targets::{CodeGenOptLevel, RelocMode, Target};
+
+    #[test]
+    fn add_constant_array_global() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("arrays");
+        let int_type = ctx.int_type(64);
+        let values = [ctx.const_int(1), ctx.const_int(2), ctx.const_int(3)];
+        unsafe { module.add_global_array(int_type, &values, "table") };
+
+        assert!(module.find_global("table").is_some());
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("@table = constant [3 x i64] [i64 1, i64 2, i64 3]"),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn const_array_matches_array_type() {
+        let ctx = Context::new();
+        let int_type = ctx.int_type(8);
+        unsafe {
+            let array = ctx.const_array(int_type, &[ctx.const_char(b'a'), ctx.const_char(b'b')]);
+            assert_eq!(ctx.array_type(int_type, 2), ctx.get_type(array));
+        }
+    }
+
+    #[test]
+    fn add_module_metadata() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("meta");
+        module.add_ident("ullage 1.2.3");
+        module.add_flag("Some Flag", 7);
+        let ir = module.to_ir_string();
+        assert!(ir.contains("!llvm.ident = !{!0}"), "{}", ir);
+        assert!(ir.contains("!{!\"ullage 1.2.3\"}"), "{}", ir);
+        assert!(ir.contains("!{i32 2, !\"Some Flag\", i32 7}"), "{}", ir);
+        module.verify_or_panic();
+    }
+
+    #[test]
+    fn set_source_file_name() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("named");
+        assert_eq!("named", module.source_file_name());
+        module.set_source_file_name("<stdin>");
+        assert_eq!("<stdin>", module.source_file_name());
+        assert!(module
+            .to_ir_string()
+            .contains("source_filename = \"<stdin>\""));
+    }
+
+    #[test]
+    fn link_modules() {
+        let mut ctx = Context::new();
+        let int_type = ctx.int_type(64);
+        let mut main = ctx.add_module("main");
+        unsafe { ctx.add_function(&mut main, "answer", int_type, &mut []) };
+
+        let mut runtime = ctx.add_module("runtime");
+        let mut answer = unsafe { ctx.add_function(&mut runtime, "answer", int_type, &mut []) };
+        let entry = ctx.add_block(&mut answer, "entry");
+        let mut builder = ctx.add_builder();
+        unsafe {
+            builder.position_at_end(entry);
+            builder.build_ret(ctx.const_int(42));
+        }
+
+        main.link_in(runtime).unwrap();
+        main.verify_or_panic();
+        assert!(main.to_ir_string().contains("ret i64 42"));
+        assert!(ctx.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn link_duplicate_definitions_fails() {
+        let mut ctx = Context::new();
+        let mut modules = Vec::new();
+        for name in &["first", "second"] {
+            let mut module = ctx.add_module(name);
+            unsafe { module.add_global(ctx.const_int(1), "value") };
+            modules.push(module);
+        }
+        let second = modules.pop().unwrap();
+        let mut first = modules.pop().unwrap();
+
+        assert!(first.link_in(second).is_err());
+        let diagnostics = ctx.take_diagnostics();
+        assert_eq!(Severity::Error, diagnostics[0].severity);
+        assert!(diagnostics[0].message.contains("value"));
+    }
+
+    #[test]
+    fn link_modules_from_different_contexts_fails() {
+        let mut first_ctx = Context::new();
+        let mut second_ctx = Context::new();
+        let mut first = first_ctx.add_module("first");
+        let second = second_ctx.add_module("second");
+
+        assert!(first.link_in(second).is_err());
+    }
+
+    #[test]
+    fn module_inline_asm() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("asm");
+        module.append_inline_asm(".globl answer");
+        module.append_inline_asm("answer = 42");
+        assert_eq!(".globl answer\nanswer = 42\n", module.inline_asm());
+        assert!(module.to_ir_string().contains("module asm \"answer = 42\""));
+    }
+
+    #[test]
+    fn register_global_constructors() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("ctors");
+        let void_type = ctx.void_type();
+        for name in &["init_heap", "seed_rng"] {
+            let mut fun = unsafe { ctx.add_function(&mut module, name, void_type, &mut []) };
+            let entry = ctx.add_block(&mut fun, "entry");
+            let mut builder = ctx.add_builder();
+            unsafe { builder.position_at_end(entry) };
+            builder.build_ret_void();
+        }
+        let init_heap = module.find_function("init_heap").unwrap();
+        let seed_rng = module.find_function("seed_rng").unwrap();
+        module.add_global_constructor(&init_heap, 0);
+        module.add_global_constructor(&seed_rng, 65535);
+
+        module.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("@llvm.global_ctors = appending global [2 x"),
+            "{}",
+            ir
+        );
+        assert!(ir.contains("i32 0, void ()* @init_heap"), "{}", ir);
+        assert!(ir.contains("i32 65535, void ()* @seed_rng"), "{}", ir);
+    }
+
+    #[test]
+    fn mark_globals_used() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("used");
+        unsafe {
+            let version = module.add_global(ctx.const_int(1), "version");
+            let build = module.add_global(ctx.const_int(2), "build");
+            module.mark_used(version);
+            module.mark_used(build);
+        }
+
+        module.verify_or_panic();
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("@llvm.used = appending global [2 x i8*] [i8* bitcast (i64* @version to i8*), i8* bitcast (i64* @build to i8*)], section \"llvm.metadata\""),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn add_thread_local_global() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("tls");
+        let error_slot = unsafe { module.add_thread_local_global(ctx.const_int(0), "error_slot") };
+        let counter = unsafe { module.add_global(ctx.const_int(0), "counter") };
+
+        assert!(unsafe { core::LLVMIsThreadLocal(error_slot) } != 0);
+        assert!(unsafe { core::LLVMIsThreadLocal(counter) } == 0);
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("@error_slot = thread_local global i64 0"),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn set_module_target() {
+        let target = Target::from_triple("aarch64-linux-gnu").unwrap();
+        let machine = target
+            .create_target_machine("", "", CodeGenOptLevel::None, RelocMode::Default)
+            .unwrap();
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("targeted");
+        module.set_target(&machine);
+
+        let ir = module.to_ir_string();
+        assert!(
+            ir.contains("target triple = \"aarch64-unknown-linux-gnu\""),
+            "{}",
+            ir
+        );
+        let layout = format!("target datalayout = \"{}\"", machine.data_layout());
+        assert!(ir.contains(&layout), "{}", ir);
+    }
+
+    #[test]
+    fn write_bitcode_to_file() {
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("bitcode");
+        unsafe { module.add_global(ctx.const_int(1337), "value") };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bitcode.bc");
+        module.write_bitcode_to_file(&path).unwrap();
+        let in_memory: Vec<u8> = module.to_bitcode_buffer().into();
+        assert_eq!(in_memory, std::fs::read(&path).unwrap());
+        assert_eq!(b"BC", &in_memory[..2]);
+
+        let missing = dir.path().join("missing").join("bitcode.bc");
+        assert!(module.write_bitcode_to_file(&missing).is_err());
+    }
+}
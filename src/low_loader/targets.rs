@@ -1,10 +1,15 @@
 //! LLVM Targets
 
 use super::llvm_sys::core::LLVMDisposeMessage;
+use super::llvm_sys::error::{LLVMDisposeErrorMessage, LLVMErrorRef, LLVMGetErrorMessage};
+use super::llvm_sys::target::*;
 use super::llvm_sys::target_machine::*;
+use super::memory_buffer::MemoryBuffer;
+use super::module::Module;
 use failure::Fail;
 use libc;
 use std::ffi::{CStr, CString};
+use std::path::Path;
 use std::{fmt, ptr};
 
 /// Compilation Target
@@ -16,6 +21,59 @@ pub struct Target {
     triple: String,
 }
 
+/// Target Machine
+///
+/// A target machine is configured from a `Target` and is responsible
+/// for generating native code for modules.
+pub struct TargetMachine {
+    raw: LLVMTargetMachineRef,
+    opt_level: CodeGenOptLevel,
+    reloc: RelocMode,
+}
+
+/// Code Generation Optimisation Level
+///
+/// How much effort a `TargetMachine` puts into optimising the code it
+/// generates. This is separate from the optimisation passes run over
+/// the IR of a module.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CodeGenOptLevel {
+    /// No optimisation, same as -O0
+    None,
+    /// Less optimisation, same as -O1
+    Less,
+    /// The default optimisation, same as -O2
+    Default,
+    /// Aggressive optimisation, same as -O3
+    Aggressive,
+}
+
+/// Code Generation File Type
+///
+/// Choice of output when emitting code with a `TargetMachine`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CodeGenFileType {
+    /// Textual native assembly
+    Assembly,
+    /// Native object code
+    Object,
+}
+
+/// Relocation Mode
+///
+/// The relocation model a `TargetMachine` generates code for.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RelocMode {
+    /// The target's default relocation model
+    Default,
+    /// Non-relocatable code
+    Static,
+    /// Position independent code
+    Pic,
+    /// Relocatable external references, non-relocatable code
+    DynamicNoPic,
+}
+
 /// Target Lookup Error
 ///
 /// Returned if a target couldn't be resolved from the given triple.
@@ -50,7 +108,7 @@ impl Target {
     pub fn from_triple(triple: &str) -> Result<Self, TargetLookupError> {
         super::context::ensure_initialised();
 
-        let ffi_trip = CString::new(triple).unwrap();
+        let ffi_trip = CString::new(triple).map_err(|e| TargetLookupError(e.to_string()))?;
 
         let mut target: LLVMTargetRef = ptr::null_mut();
         let mut error: *mut libc::c_char = ptr::null_mut();
@@ -113,6 +171,249 @@ impl Target {
         let has_backend = unsafe { LLVMTargetHasAsmBackend(self.llvm_target) };
         has_backend != 0
     }
+
+    /// Create a Target Machine
+    ///
+    /// Builds a machine for this target. An empty `cpu` or `features`
+    /// string selects the generic CPU and feature set. Returns an
+    /// error if the CPU or features contain a NUL character.
+    pub fn create_target_machine(
+        &self,
+        cpu: &str,
+        features: &str,
+        opt_level: CodeGenOptLevel,
+        reloc: RelocMode,
+    ) -> Result<TargetMachine, String> {
+        let triple = CString::new(&self.triple[..]).unwrap();
+        let cpu = CString::new(cpu).map_err(|_| format!("invalid target CPU '{}'", cpu))?;
+        let features = CString::new(features)
+            .map_err(|_| format!("invalid target features '{}'", features))?;
+        let raw = unsafe {
+            LLVMCreateTargetMachine(
+                self.llvm_target,
+                triple.as_ptr(),
+                cpu.as_ptr(),
+                features.as_ptr(),
+                opt_level.into(),
+                reloc.into(),
+                LLVMCodeModel::LLVMCodeModelDefault,
+            )
+        };
+        Ok(TargetMachine {
+            raw,
+            opt_level,
+            reloc,
+        })
+    }
+}
+
+impl TargetMachine {
+    /// Get the Target Triple
+    ///
+    /// Retrieves the triple this machine generates code for.
+    pub fn triple(&self) -> String {
+        unsafe { take_message(LLVMGetTargetMachineTriple(self.raw)) }
+    }
+
+    /// Get the Data Layout
+    ///
+    /// Retrieves the string representation of the data layout used
+    /// by this machine. This describes the size and alignment of
+    /// types on the target.
+    pub fn data_layout(&self) -> String {
+        unsafe {
+            let data = LLVMCreateTargetDataLayout(self.raw);
+            let layout = take_message(LLVMCopyStringRepOfTargetData(data));
+            LLVMDisposeTargetData(data);
+            layout
+        }
+    }
+
+    /// Get the CPU
+    ///
+    /// Retrieves the CPU this machine generates code for. This is
+    /// empty if the generic CPU was selected.
+    pub fn cpu(&self) -> String {
+        unsafe { take_message(LLVMGetTargetMachineCPU(self.raw)) }
+    }
+
+    /// Get the Feature String
+    ///
+    /// Retrieves the target features enabled or disabled for this
+    /// machine.
+    pub fn features(&self) -> String {
+        unsafe { take_message(LLVMGetTargetMachineFeatureString(self.raw)) }
+    }
+
+    /// Get the Code Generation Optimisation Level
+    pub fn opt_level(&self) -> CodeGenOptLevel {
+        self.opt_level
+    }
+
+    /// Get the Relocation Mode
+    pub fn reloc_mode(&self) -> RelocMode {
+        self.reloc
+    }
+
+    /// Emit a Module to a File
+    ///
+    /// Generates code for the given module as either assembly or
+    /// object code and writes it to `path`. If code generation fails
+    /// the LLVM error message is returned.
+    pub fn emit_to_file(
+        &self,
+        module: &Module,
+        path: &Path,
+        file_type: CodeGenFileType,
+    ) -> Result<(), String> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| format!("path '{}' is not valid unicode", path.display()))?;
+        let path = CString::new(path).map_err(|e| e.to_string())?;
+        let mut error: *mut libc::c_char = ptr::null_mut();
+        let r = unsafe {
+            LLVMTargetMachineEmitToFile(
+                self.raw,
+                module.as_raw(),
+                // LLVM doesn't modify the file name, it just isn't
+                // declared `const` in the C API.
+                path.as_ptr() as *mut _,
+                file_type.into(),
+                &mut error,
+            )
+        };
+        if r != 0 {
+            return Err(unsafe { take_message(error) });
+        }
+        Ok(())
+    }
+
+    /// Emit a Module to a Memory Buffer
+    ///
+    /// Generates code for the given module as either assembly or
+    /// object code. If code generation fails the LLVM error message
+    /// is returned.
+    pub fn emit_to_memory_buffer(
+        &self,
+        module: &Module,
+        file_type: CodeGenFileType,
+    ) -> Result<MemoryBuffer, String> {
+        let mut error: *mut libc::c_char = ptr::null_mut();
+        let mut buffer = ptr::null_mut();
+        let r = unsafe {
+            LLVMTargetMachineEmitToMemoryBuffer(
+                self.raw,
+                module.as_raw(),
+                file_type.into(),
+                &mut error,
+                &mut buffer,
+            )
+        };
+        if r != 0 {
+            return Err(unsafe { take_message(error) });
+        }
+        Ok(unsafe { MemoryBuffer::from_raw(buffer) })
+    }
+
+    /// Run a Pass Pipeline over a Module
+    ///
+    /// Runs the passes described by `pipeline`, in the textual format
+    /// accepted by `opt -passes`, with LLVM's new pass manager. If
+    /// the pipeline can't be parsed the LLVM error message is
+    /// returned.
+    pub fn run_passes(&self, module: &mut Module, pipeline: &str) -> Result<(), String> {
+        let pipeline = CString::new(pipeline).map_err(|e| e.to_string())?;
+        unsafe {
+            let options = LLVMCreatePassBuilderOptions();
+            let error = LLVMRunPasses(module.as_raw(), pipeline.as_ptr(), self.raw, options);
+            LLVMDisposePassBuilderOptions(options);
+            if error.is_null() {
+                Ok(())
+            } else {
+                Err(take_error_message(LLVMGetErrorMessage(error)))
+            }
+        }
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeTargetMachine(self.raw) }
+    }
+}
+
+impl From<RelocMode> for LLVMRelocMode {
+    fn from(mode: RelocMode) -> Self {
+        match mode {
+            RelocMode::Default => LLVMRelocMode::LLVMRelocDefault,
+            RelocMode::Static => LLVMRelocMode::LLVMRelocStatic,
+            RelocMode::Pic => LLVMRelocMode::LLVMRelocPIC,
+            RelocMode::DynamicNoPic => LLVMRelocMode::LLVMRelocDynamicNoPic,
+        }
+    }
+}
+
+impl From<CodeGenOptLevel> for LLVMCodeGenOptLevel {
+    fn from(level: CodeGenOptLevel) -> Self {
+        match level {
+            CodeGenOptLevel::None => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            CodeGenOptLevel::Less => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            CodeGenOptLevel::Default => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            CodeGenOptLevel::Aggressive => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        }
+    }
+}
+
+impl From<CodeGenFileType> for LLVMCodeGenFileType {
+    fn from(file_type: CodeGenFileType) -> Self {
+        match file_type {
+            CodeGenFileType::Assembly => LLVMCodeGenFileType::LLVMAssemblyFile,
+            CodeGenFileType::Object => LLVMCodeGenFileType::LLVMObjectFile,
+        }
+    }
+}
+
+/// Take an LLVM Message
+///
+/// Copies the contents of a string allocated by LLVM and then
+/// disposes of it.
+///
+/// # Safety
+///
+/// The message must be a valid C string allocated by LLVM which is
+/// not used again after this call.
+pub(super) unsafe fn take_message(message: *mut libc::c_char) -> String {
+    let owned = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    owned
+}
+
+/// Take an Error Message from LLVM
+///
+/// Messages from `LLVMGetErrorMessage` are freed with their own
+/// function rather than `LLVMDisposeMessage`.
+unsafe fn take_error_message(message: *mut libc::c_char) -> String {
+    let owned = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeErrorMessage(message);
+    owned
+}
+
+/// Opaque Pass Builder Options
+enum LLVMOpaquePassBuilderOptions {}
+
+extern "C" {
+    /// Run Passes with the New Pass Manager
+    ///
+    /// These were added to the C API in LLVM 13 and aren't bound by
+    /// `llvm-sys` 80.
+    fn LLVMRunPasses(
+        module: super::llvm_sys::prelude::LLVMModuleRef,
+        passes: *const libc::c_char,
+        machine: LLVMTargetMachineRef,
+        options: *mut LLVMOpaquePassBuilderOptions,
+    ) -> LLVMErrorRef;
+    fn LLVMCreatePassBuilderOptions() -> *mut LLVMOpaquePassBuilderOptions;
+    fn LLVMDisposePassBuilderOptions(options: *mut LLVMOpaquePassBuilderOptions);
 }
 
 /// Get the Default Target Triple
@@ -121,7 +422,22 @@ impl Target {
 /// if no triple is specified by the user.
 pub fn get_default_triple() -> String {
     let native_target = unsafe { CStr::from_ptr(LLVMGetDefaultTargetTriple()) };
-    native_target.to_string_lossy().to_owned().to_string()
+    native_target.to_string_lossy().into_owned()
+}
+
+/// Normalise a Target Triple
+///
+/// Returns the canonical form of the triple, with each of its
+/// components in the standard position. The triple doesn't need to be
+/// a target this build of LLVM supports.
+pub fn normalise_triple(triple: &str) -> String {
+    let ffi_trip = CString::new(triple).unwrap();
+    unsafe {
+        let norm_trip = LLVMNormalizeTargetTriple(ffi_trip.as_ptr());
+        let normalised = CStr::from_ptr(norm_trip).to_string_lossy().into_owned();
+        LLVMDisposeMessage(norm_trip);
+        normalised
+    }
 }
 
 /// Dump Available LLVM Targets
@@ -149,6 +465,7 @@ pub fn dump_targets() {
 #[cfg(test)]
 mod test {
 
+    use super::super::context::Context;
     use super::*;
 
     #[test]
@@ -169,17 +486,67 @@ mod test {
         let target = Target::from_triple("aarch64-linux-gnu").unwrap();
         assert_eq!("aarch64", target.name());
         assert_eq!("AArch64 (little endian)", target.description());
-        assert_eq!(true, target.has_asm_backend());
+        assert!(target.has_asm_backend());
         assert_eq!("aarch64-linux-gnu", target.triple());
         assert_eq!("aarch64-unknown-linux-gnu", target.norm_triple());
     }
 
+    #[test]
+    fn create_target_machine() {
+        let target = Target::from_triple("x86_64-unknown-linux-gnu").unwrap();
+        let machine = target
+            .create_target_machine(
+                "haswell",
+                "+avx2",
+                CodeGenOptLevel::Aggressive,
+                RelocMode::Pic,
+            )
+            .unwrap();
+        assert_eq!("x86_64-unknown-linux-gnu", machine.triple());
+        assert_eq!("haswell", machine.cpu());
+        assert_eq!("+avx2", machine.features());
+        assert_eq!(CodeGenOptLevel::Aggressive, machine.opt_level());
+        assert_eq!(RelocMode::Pic, machine.reloc_mode());
+        assert!(machine.data_layout().starts_with("e-m:e"));
+
+        // User supplied strings containing NULs are rejected
+        assert!(target
+            .create_target_machine("has\0nul", "", CodeGenOptLevel::None, RelocMode::Default)
+            .is_err());
+        assert!(target
+            .create_target_machine("", "+a\0", CodeGenOptLevel::None, RelocMode::Default)
+            .is_err());
+        assert!(Target::from_triple("x86_64\0linux").is_err());
+    }
+
+    #[test]
+    fn emit_module_to_file() {
+        let target = Target::from_triple("x86_64-unknown-linux-gnu").unwrap();
+        let machine = target
+            .create_target_machine("", "", CodeGenOptLevel::None, RelocMode::Default)
+            .unwrap();
+        let mut ctx = Context::new();
+        let mut module = ctx.add_module("emitted");
+        module.set_target(&machine);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emitted.s");
+        machine
+            .emit_to_file(&module, &path, CodeGenFileType::Assembly)
+            .unwrap();
+        let in_memory: Vec<u8> = machine
+            .emit_to_memory_buffer(&module, CodeGenFileType::Assembly)
+            .unwrap()
+            .into();
+        assert_eq!(in_memory, std::fs::read(&path).unwrap());
+    }
+
     #[test]
     fn create_long_triple() {
         let target = Target::from_triple("arm-eabi-linux-gnu-elf").unwrap();
         assert_eq!("arm", target.name());
         assert_eq!("ARM", target.description());
-        assert_eq!(true, target.has_asm_backend());
+        assert!(target.has_asm_backend());
         assert_eq!("arm-eabi-linux-gnu-elf", target.triple());
         assert_eq!("arm-eabi-linux-gnu-elf", target.norm_triple());
     }
@@ -70,10 +70,15 @@ pub enum Type {
     Token,
 }
 
-impl From<LLVMTypeRef> for Type {
-    fn from(llvm_type: LLVMTypeRef) -> Self {
+impl Type {
+    /// Get the Kind of a Raw LLVM Type
+    ///
+    /// # Safety
+    ///
+    /// `llvm_type` must be a valid type reference.
+    pub unsafe fn from_raw(llvm_type: LLVMTypeRef) -> Self {
         use self::LLVMTypeKind::*;
-        match unsafe { core::LLVMGetTypeKind(llvm_type) } {
+        match core::LLVMGetTypeKind(llvm_type) {
             LLVMVoidTypeKind => Type::Void,
             LLVMHalfTypeKind => Type::Float(FloatWidth::Half),
             LLVMFloatTypeKind => Type::Float(FloatWidth::Half),
@@ -82,9 +87,7 @@ impl From<LLVMTypeRef> for Type {
             LLVMFP128TypeKind => Type::Float(FloatWidth::FP128),
             LLVMPPC_FP128TypeKind => Type::Float(FloatWidth::PPC_FP128),
             LLVMLabelTypeKind => Type::Label,
-            LLVMIntegerTypeKind => {
-                Type::Int(unsafe { core::LLVMGetIntTypeWidth(llvm_type) } as usize)
-            }
+            LLVMIntegerTypeKind => Type::Int(core::LLVMGetIntTypeWidth(llvm_type) as usize),
             LLVMFunctionTypeKind => Type::Function,
             LLVMStructTypeKind => Type::Struct,
             LLVMArrayTypeKind => Type::Array,
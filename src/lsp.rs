@@ -0,0 +1,437 @@
+//! Language Server
+//!
+//! An implementation of the Language Server Protocol on top of the
+//! parser and binder. The server communicates over a pair of streams,
+//! usually standard input and output, and supports publishing
+//! diagnostics, hover information, and document symbols for open
+//! documents.
+//!
+//! Documents are kept in full by the server and re-analysed on each
+//! request. Sources are small enough that this is fast and it avoids
+//! any need to keep stale trees up to date.
+
+mod document;
+pub mod transport;
+
+pub use self::document::Document;
+
+use crate::analysis::highlight::TokenClass;
+use crate::meta;
+use crate::syntax::text::Pos;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+
+/// JSON-RPC error code for unknown methods
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for requests received after shutdown
+const INVALID_REQUEST: i64 = -32600;
+
+/// LSP error code for valid requests which couldn't be carried out
+const REQUEST_FAILED: i64 = -32803;
+
+/// Serve the Language Server Protocol
+///
+/// Reads messages from `input` and writes the server's responses and
+/// notifications to `output` until the client sends `exit` or closes
+/// the input.
+///
+/// # Errors
+///
+/// Returns an error if either stream fails, if a malformed message
+/// is received, or if the client exits without first requesting a
+/// shutdown.
+pub fn serve<R, W>(mut input: R, mut output: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut server = Server::new();
+    while let Some(message) = transport::read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            transport::write_message(&mut output, &reply)?;
+        }
+        if server.exited() {
+            break;
+        }
+    }
+    if server.shutdown {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            "client exited without requesting shutdown",
+        ))
+    }
+}
+
+/// Language Server
+///
+/// Holds the state of a single session with a client. Messages are
+/// passed to `handle` which returns any messages to send back.
+#[derive(Default)]
+pub struct Server {
+    /// Documents the client has open, by URI
+    documents: HashMap<String, Document>,
+    /// Has the client requested a shutdown?
+    shutdown: bool,
+    /// Has the client sent the `exit` notification?
+    exited: bool,
+}
+
+impl Server {
+    /// Create a New Server
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Has the Client Exited?
+    ///
+    /// Once the client sends `exit` no more messages should be
+    /// passed to the server.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Handle a Message
+    ///
+    /// Dispatches a request or notification from the client. Returns
+    /// the messages which should be sent in reply. Requests always
+    /// produce a single response.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        match (message["method"].as_str(), message.get("id")) {
+            (Some(method), Some(id)) => {
+                let reply = match self.handle_request(method, params) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": code, "message": message},
+                    }),
+                };
+                vec![reply]
+            }
+            (Some(method), None) => self.handle_notification(method, params),
+            // We never send requests, so any responses are ignored.
+            _ => Vec::new(),
+        }
+    }
+
+    /// Handle a Request
+    ///
+    /// Returns the result of the request, or an error code and
+    /// message.
+    fn handle_request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shutting down".into()));
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "documentSymbolProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": {
+                            "tokenTypes": TokenClass::ALL.iter().map(|c| c.name()).collect::<Vec<_>>(),
+                            "tokenModifiers": [],
+                        },
+                        "full": true,
+                    },
+                },
+                "serverInfo": {"name": "ullage", "version": meta::version()},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => Ok(self
+                .document_position(params)
+                .and_then(|(doc, pos)| doc.hover(pos))
+                .unwrap_or(Value::Null)),
+            "textDocument/definition" => Ok(self
+                .document_position(params)
+                .and_then(|(doc, pos)| doc.definition(pos))
+                .unwrap_or(Value::Null)),
+            "textDocument/references" => {
+                let include_declaration = params["context"]["includeDeclaration"] == true;
+                Ok(self
+                    .document_position(params)
+                    .map_or(Value::Null, |(doc, pos)| {
+                        doc.references(pos, include_declaration).into()
+                    }))
+            }
+            "textDocument/rename" => {
+                let new_name = params["newName"].as_str().unwrap_or("");
+                match self.document_position(params) {
+                    Some((doc, pos)) => doc
+                        .rename(pos, new_name)
+                        .map_err(|e| (REQUEST_FAILED, e.to_string())),
+                    None => Ok(Value::Null),
+                }
+            }
+            "textDocument/documentSymbol" => Ok(self
+                .document(params)
+                .map_or(Value::Null, |doc| doc.symbols().into())),
+            "textDocument/semanticTokens/full" => Ok(self
+                .document(params)
+                .map_or(Value::Null, |doc| json!({"data": doc.semantic_tokens()}))),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        }
+    }
+
+    /// Handle a Notification
+    ///
+    /// Changes to documents are replied to with a fresh set of
+    /// diagnostics for the document.
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match method {
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.open(uri, text)
+            }
+            "textDocument/didChange" => {
+                // We only support full document sync so the last
+                // change contains the whole new text.
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|c| c.last()?["text"].as_str()) {
+                    Some(text) => self.open(uri, text),
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Open, or Replace, a Document
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        let document = Document::new(uri, text);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.into(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// Find the Document a Request Refers to
+    fn document(&self, params: &Value) -> Option<&Document> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri)
+    }
+
+    /// Find the Document and Position a Request Refers to
+    fn document_position(&self, params: &Value) -> Option<(&Document, Pos)> {
+        let doc = self.document(params)?;
+        let position = &params["position"];
+        let pos = doc.offset_at(
+            position["line"].as_u64()? as usize,
+            position["character"].as_u64()? as usize,
+        )?;
+        Some((doc, pos))
+    }
+}
+
+/// Create a `publishDiagnostics` Notification
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const URI: &str = "file:///test.ulg";
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "method": method, "params": params})
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": URI, "languageId": "ullage", "version": 1, "text": text}}),
+        ))
+    }
+
+    #[test]
+    fn initialize_reports_capabilities() {
+        let mut server = Server::new();
+        let reply = server.handle(&request(1, "initialize", json!({})));
+        assert_eq!(1, reply.len());
+        assert_eq!(1, reply[0]["id"]);
+        let capabilities = &reply[0]["result"]["capabilities"];
+        assert_eq!(true, capabilities["hoverProvider"]);
+        assert_eq!(true, capabilities["documentSymbolProvider"]);
+        assert_eq!(
+            "keyword",
+            capabilities["semanticTokensProvider"]["legend"]["tokenTypes"][0]
+        );
+    }
+
+    #[test]
+    fn document_changes_publish_diagnostics() {
+        let mut server = Server::new();
+        let reply = open(&mut server, "print 1");
+        assert_eq!("textDocument/publishDiagnostics", reply[0]["method"]);
+        assert_eq!(json!([]), reply[0]["params"]["diagnostics"]);
+
+        let reply = server.handle(&notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": {"uri": URI, "version": 2},
+                "contentChanges": [{"text": "print x"}],
+            }),
+        ));
+        let diagnostics = &reply[0]["params"]["diagnostics"];
+        assert_eq!(URI, reply[0]["params"]["uri"]);
+        assert_eq!("Can't find 'x' in this scope", diagnostics[0]["message"]);
+
+        let reply = server.handle(&notification(
+            "textDocument/didClose",
+            json!({"textDocument": {"uri": URI}}),
+        ));
+        assert_eq!(json!([]), reply[0]["params"]["diagnostics"]);
+    }
+
+    #[test]
+    fn hover_and_symbols_for_open_document() {
+        let mut server = Server::new();
+        open(&mut server, "let x = 1 + 2\nprint x");
+
+        let reply = server.handle(&request(
+            2,
+            "textDocument/hover",
+            json!({"textDocument": {"uri": URI}, "position": {"line": 1, "character": 6}}),
+        ));
+        assert_eq!(
+            "```ullage\nlet x: Number\n```",
+            reply[0]["result"]["contents"]["value"]
+        );
+
+        let reply = server.handle(&request(
+            3,
+            "textDocument/documentSymbol",
+            json!({"textDocument": {"uri": URI}}),
+        ));
+        assert_eq!("x", reply[0]["result"][0]["name"]);
+
+        let reply = server.handle(&request(
+            6,
+            "textDocument/definition",
+            json!({"textDocument": {"uri": URI}, "position": {"line": 1, "character": 6}}),
+        ));
+        assert_eq!(URI, reply[0]["result"]["uri"]);
+        assert_eq!(
+            json!({"start": {"line": 0, "character": 4}, "end": {"line": 0, "character": 5}}),
+            reply[0]["result"]["range"]
+        );
+
+        let reply = server.handle(&request(
+            7,
+            "textDocument/references",
+            json!({
+                "textDocument": {"uri": URI},
+                "position": {"line": 0, "character": 4},
+                "context": {"includeDeclaration": false},
+            }),
+        ));
+        let references = reply[0]["result"].as_array().unwrap();
+        assert_eq!(1, references.len());
+        assert_eq!(1, references[0]["range"]["start"]["line"]);
+
+        let reply = server.handle(&request(
+            8,
+            "textDocument/rename",
+            json!({
+                "textDocument": {"uri": URI},
+                "position": {"line": 0, "character": 4},
+                "newName": "y",
+            }),
+        ));
+        assert_eq!(
+            2,
+            reply[0]["result"]["changes"][URI].as_array().unwrap().len()
+        );
+        let reply = server.handle(&request(
+            9,
+            "textDocument/rename",
+            json!({
+                "textDocument": {"uri": URI},
+                "position": {"line": 0, "character": 4},
+                "newName": "let",
+            }),
+        ));
+        assert_eq!(REQUEST_FAILED, reply[0]["error"]["code"]);
+
+        let reply = server.handle(&request(
+            4,
+            "textDocument/semanticTokens/full",
+            json!({"textDocument": {"uri": URI}}),
+        ));
+        let data = reply[0]["result"]["data"].as_array().unwrap();
+        assert_eq!(json!([0, 0, 3, 0, 0]), Value::from(&data[..5]));
+
+        let reply = server.handle(&request(
+            5,
+            "textDocument/hover",
+            json!({"textDocument": {"uri": "file:///missing.ulg"}, "position": {"line": 0, "character": 0}}),
+        ));
+        assert_eq!(Value::Null, reply[0]["result"]);
+    }
+
+    #[test]
+    fn unknown_requests_are_errors() {
+        let mut server = Server::new();
+        let reply = server.handle(&request(7, "textDocument/typeDefinition", json!({})));
+        assert_eq!(METHOD_NOT_FOUND, reply[0]["error"]["code"]);
+    }
+
+    #[test]
+    fn serve_session() {
+        let mut input = Vec::new();
+        for message in &[
+            request(1, "initialize", json!({})),
+            notification("initialized", json!({})),
+            request(2, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ] {
+            transport::write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        serve(&input[..], &mut output).unwrap();
+
+        let mut output = &output[..];
+        let init = transport::read_message(&mut output).unwrap().unwrap();
+        assert_eq!(1, init["id"]);
+        let shutdown = transport::read_message(&mut output).unwrap().unwrap();
+        assert_eq!(json!({"jsonrpc": "2.0", "id": 2, "result": null}), shutdown);
+        assert_eq!(None, transport::read_message(&mut output).unwrap());
+    }
+
+    #[test]
+    fn serve_requires_shutdown() {
+        let mut input = Vec::new();
+        transport::write_message(&mut input, &notification("exit", Value::Null)).unwrap();
+        assert!(serve(&input[..], Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! LSP Documents
+//!
+//! Holds the text of a document open in the editor and answers the
+//! queries the server supports by parsing and binding it. Positions
+//! are converted between byte offsets in the source and the
+//! zero-based line and UTF-16 character offsets used by the protocol.
+
+use crate::analysis::highlight;
+use crate::analysis::outline::{self, signature, OutlineItem, OutlineKind};
+use crate::analysis::rename::{self, RenameError};
+use crate::diag::Diagnostic;
+use crate::sem::{Binder, Scope};
+use crate::syntax::text::{Pos, SourceText, Span};
+use crate::syntax::SyntaxTree;
+use serde_json::{json, Value};
+
+/// LSP `SymbolKind` for functions
+const SYMBOL_KIND_FUNCTION: u32 = 12;
+
+/// LSP `SymbolKind` for variables
+const SYMBOL_KIND_VARIABLE: u32 = 13;
+
+/// LSP `DiagnosticSeverity` for errors
+const SEVERITY_ERROR: u32 = 1;
+
+/// LSP `DiagnosticSeverity` for warnings
+const SEVERITY_WARNING: u32 = 2;
+
+/// Open Document
+///
+/// The current text of a document the client has opened.
+pub struct Document {
+    /// The source of the document
+    source: SourceText,
+}
+
+impl Document {
+    /// Create a Document
+    ///
+    /// The document's URI is used as the name of its source.
+    pub fn new<T: Into<String>>(uri: &str, text: T) -> Self {
+        Document {
+            source: SourceText::with_name(text, uri),
+        }
+    }
+
+    /// Get the Document's Diagnostics
+    ///
+    /// If the document fails to parse then the parse diagnostics are
+    /// returned. Otherwise the tree is bound and any diagnostics and
+    /// warnings from the bind are returned.
+    pub fn diagnostics(&self) -> Vec<Value> {
+        let tree = SyntaxTree::parse(&self.source);
+        let (diagnostics, warnings) = if tree.has_diagnostics() {
+            (tree.diagnostics().to_vec(), Vec::new())
+        } else {
+            let mut binder = Binder::new(Scope::new());
+            binder.bind_tree(tree);
+            (binder.take_diagnostics(), binder.take_warnings())
+        };
+        let with_severity = |severity| {
+            move |d: Diagnostic| {
+                let related: Vec<_> = d
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        json!({
+                            "location": {
+                                "uri": self.source.name(),
+                                "range": self.range(label.span),
+                            },
+                            "message": label.message,
+                        })
+                    })
+                    .collect();
+                let mut message = d.message;
+                for note in &d.notes {
+                    message.push_str("\nnote: ");
+                    message.push_str(note);
+                }
+                if let Some(fix) = &d.fix {
+                    message.push_str("\nhelp: ");
+                    message.push_str(&fix.message);
+                }
+                json!({
+                    "range": self.range(d.span),
+                    "severity": severity,
+                    "code": d.code.map(|c| c.to_string()),
+                    "source": "ullage",
+                    "message": message,
+                    "relatedInformation": related,
+                })
+            }
+        };
+        diagnostics
+            .into_iter()
+            .map(with_severity(SEVERITY_ERROR))
+            .chain(warnings.into_iter().map(with_severity(SEVERITY_WARNING)))
+            .collect()
+    }
+
+    /// Get Hover Information
+    ///
+    /// Returns the signature of the symbol bound to the identifier
+    /// at the given position, if there is one.
+    pub fn hover(&self, pos: Pos) -> Option<Value> {
+        let tree = SyntaxTree::parse(&self.source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let (span, sym) = binder
+            .take_symbol_spans()
+            .into_iter()
+            .find(|(span, _)| span.contains(pos))?;
+        let name = self.source.slice(span.start(), span.end());
+        Some(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```ullage\n{}\n```", signature(name, &sym)),
+            },
+            "range": self.range(span),
+        }))
+    }
+
+    /// Find the Definition of a Symbol
+    ///
+    /// Returns the `Location` of the declaration the identifier at
+    /// the given position is bound to.
+    pub fn definition(&self, pos: Pos) -> Option<Value> {
+        let tree = SyntaxTree::parse(&self.source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let span = binder.definition_at(pos)?;
+        Some(json!({
+            "uri": self.source.name(),
+            "range": self.range(span),
+        }))
+    }
+
+    /// Find the References to a Symbol
+    ///
+    /// Returns the `Location` of every identifier bound to the same
+    /// definition as the identifier at the given position. The
+    /// declaration is only included if `include_declaration` is set.
+    pub fn references(&self, pos: Pos, include_declaration: bool) -> Vec<Value> {
+        let tree = SyntaxTree::parse(&self.source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let definition = binder.definition_at(pos);
+        binder
+            .references_at(pos)
+            .into_iter()
+            .filter(|&span| include_declaration || Some(span) != definition)
+            .map(|span| json!({"uri": self.source.name(), "range": self.range(span)}))
+            .collect()
+    }
+
+    /// Rename a Symbol
+    ///
+    /// Returns a `WorkspaceEdit` which renames the symbol bound to
+    /// the identifier at the given position to `new_name`.
+    ///
+    /// # Errors
+    ///
+    /// If the rename is refused then the reason is returned.
+    pub fn rename(&self, pos: Pos, new_name: &str) -> Result<Value, RenameError> {
+        let edits: Vec<_> = rename::rename(&self.source, pos, new_name)?
+            .into_iter()
+            .map(|edit| json!({"range": self.range(edit.span), "newText": edit.new_text}))
+            .collect();
+        Ok(json!({ "changes": { self.source.name(): edits } }))
+    }
+
+    /// Get the Document's Symbols
+    ///
+    /// Returns the functions and variables declared in the document
+    /// as a tree of `DocumentSymbol`s. Symbols declared within a
+    /// function are nested inside it.
+    pub fn symbols(&self) -> Vec<Value> {
+        self.document_symbols(outline::outline(&self.source))
+    }
+
+    /// Get the Document's Semantic Tokens
+    ///
+    /// Returns the classified tokens in the relative encoding used by
+    /// the `textDocument/semanticTokens` request. Each token is five
+    /// integers: the line delta, start character delta, length,
+    /// token type, and modifiers. Tokens which span multiple lines
+    /// are split into one token per line.
+    pub fn semantic_tokens(&self) -> Vec<u32> {
+        let mut data = Vec::new();
+        let (mut prev_line, mut prev_start) = (0, 0);
+        for (span, class) in highlight::classify(&self.source) {
+            let (start_line, start_char) = self.line_character(span.start());
+            let (end_line, end_char) = self.line_character(span.end());
+            for line in start_line..=end_line {
+                let start = if line == start_line { start_char } else { 0 };
+                let end = if line == end_line {
+                    end_char
+                } else {
+                    self.line_length(line)
+                };
+                if end <= start {
+                    continue;
+                }
+                let delta_start = if line == prev_line {
+                    start - prev_start
+                } else {
+                    start
+                };
+                data.extend_from_slice(&[
+                    (line - prev_line) as u32,
+                    delta_start as u32,
+                    (end - start) as u32,
+                    class.index() as u32,
+                    0,
+                ]);
+                prev_line = line;
+                prev_start = start;
+            }
+        }
+        data
+    }
+
+    /// Convert Outline Items to Document Symbols
+    fn document_symbols(&self, items: Vec<OutlineItem>) -> Vec<Value> {
+        items
+            .into_iter()
+            .map(|item| {
+                let kind = match item.kind {
+                    OutlineKind::Function => SYMBOL_KIND_FUNCTION,
+                    OutlineKind::Variable => SYMBOL_KIND_VARIABLE,
+                };
+                json!({
+                    "name": item.name,
+                    "detail": item.signature,
+                    "kind": kind,
+                    "range": self.range(item.span),
+                    "selectionRange": self.range(item.name_span),
+                    "children": self.document_symbols(item.children),
+                })
+            })
+            .collect()
+    }
+
+    /// Convert a Position to a Byte Offset
+    ///
+    /// Takes a zero-based line and UTF-16 character offset, as used
+    /// by the protocol, and returns the matching position in the
+    /// source. Characters past the end of the line are clamped to
+    /// the end of the line.
+    pub fn offset_at(&self, line: usize, character: usize) -> Option<Pos> {
+        let start = self.source.line_start(line + 1)?;
+        let text = self.source.line_text(line + 1)?;
+        let mut units = 0;
+        let mut bytes = 0;
+        for ch in text.chars() {
+            if units >= character {
+                break;
+            }
+            units += ch.len_utf16();
+            bytes += ch.len_utf8();
+        }
+        Some(Pos::from(start.offset() + bytes))
+    }
+
+    /// Convert a Byte Offset to a Zero-Based Line and Character
+    fn line_character(&self, pos: Pos) -> (usize, usize) {
+        let (line, col) = self.source.position(Span::new_at(pos));
+        let character = self
+            .source
+            .line_text(line)
+            .unwrap_or("")
+            .chars()
+            .take(col)
+            .map(char::len_utf16)
+            .sum();
+        (line - 1, character)
+    }
+
+    /// Get the Length of a Zero-Based Line in UTF-16 Code Units
+    fn line_length(&self, line: usize) -> usize {
+        let text = self.source.line_text(line + 1).unwrap_or("");
+        text.chars().map(char::len_utf16).sum()
+    }
+
+    /// Convert a Byte Offset to a Protocol Position
+    fn position(&self, pos: Pos) -> Value {
+        let (line, character) = self.line_character(pos);
+        json!({ "line": line, "character": character })
+    }
+
+    /// Convert a Span to a Protocol Range
+    fn range(&self, span: Span) -> Value {
+        json!({
+            "start": self.position(span.start()),
+            "end": self.position(span.end()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_positions() {
+        let doc = Document::new("file:///test.ulg", "let a = 1\nprint 'ü𝄞' + a\n");
+        assert_eq!(Some(Pos::from(0)), doc.offset_at(0, 0));
+        assert_eq!(Some(Pos::from(14)), doc.offset_at(1, 4));
+        // `𝄞` is two UTF-16 code units, and four bytes
+        assert_eq!(Some(Pos::from(23)), doc.offset_at(1, 10));
+        assert_eq!(Some(Pos::from(28)), doc.offset_at(1, 99));
+        assert_eq!(None, doc.offset_at(5, 0));
+
+        assert_eq!(
+            json!({"line": 1, "character": 10}),
+            doc.position(Pos::from(23))
+        );
+        assert_eq!(
+            json!({"line": 2, "character": 0}),
+            doc.position(Pos::from(29))
+        );
+    }
+
+    #[test]
+    fn diagnostics_for_parse_and_bind_errors() {
+        let doc = Document::new("file:///test.ulg", "print 1 +\n");
+        let diagnostics = doc.diagnostics();
+        assert_eq!(
+            "Expected expression but found end of file",
+            diagnostics[0]["message"]
+        );
+
+        let doc = Document::new("file:///test.ulg", "\nprint foo");
+        let diagnostics = doc.diagnostics();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("Can't find 'foo' in this scope", diagnostics[0]["message"]);
+        assert_eq!(
+            json!({
+                "start": {"line": 1, "character": 6},
+                "end": {"line": 1, "character": 9},
+            }),
+            diagnostics[0]["range"]
+        );
+        assert_eq!(SEVERITY_ERROR, diagnostics[0]["severity"]);
+        assert_eq!("U0007", diagnostics[0]["code"]);
+
+        let doc = Document::new("file:///test.ulg", "var a = 1\na = 'one'");
+        let diagnostics = doc.diagnostics();
+        assert_eq!(
+            json!([
+                {
+                    "location": {
+                        "uri": "file:///test.ulg",
+                        "range": {
+                            "start": {"line": 1, "character": 4},
+                            "end": {"line": 1, "character": 9},
+                        },
+                    },
+                    "message": "this is 'String'",
+                },
+                {
+                    "location": {
+                        "uri": "file:///test.ulg",
+                        "range": {
+                            "start": {"line": 0, "character": 4},
+                            "end": {"line": 0, "character": 5},
+                        },
+                    },
+                    "message": "'a' is declared as 'Number' here",
+                },
+            ]),
+            diagnostics[0]["relatedInformation"]
+        );
+
+        let doc = Document::new("file:///test.ulg", "while true end\nprint 1");
+        let diagnostics = doc.diagnostics();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("Unreachable expression", diagnostics[0]["message"]);
+        assert_eq!(SEVERITY_WARNING, diagnostics[0]["severity"]);
+    }
+
+    #[test]
+    fn hover_shows_signatures() {
+        let doc = Document::new(
+            "file:///test.ulg",
+            "fn add(a: Number, b: Number): Number a + b end\nlet total = add(1, 2)",
+        );
+        let hover = |line, character| {
+            doc.hover(doc.offset_at(line, character).unwrap())
+                .map(|h| h["contents"]["value"].as_str().unwrap().to_owned())
+        };
+        assert_eq!(
+            Some("```ullage\nfn add(Number, Number): Number\n```".into()),
+            hover(1, 13)
+        );
+        assert_eq!(Some("```ullage\nvar a: Number\n```".into()), hover(0, 37));
+        assert_eq!(
+            Some("```ullage\nlet total: Number\n```".into()),
+            hover(1, 6)
+        );
+        assert_eq!(None, hover(1, 19));
+    }
+
+    #[test]
+    fn semantic_tokens_are_relative() {
+        let doc = Document::new("file:///test.ulg", "let x = 'a\nb'\n  print x");
+        let keyword = highlight::TokenClass::Keyword.index() as u32;
+        let variable = highlight::TokenClass::Variable.index() as u32;
+        let operator = highlight::TokenClass::Operator.index() as u32;
+        let string = highlight::TokenClass::String.index() as u32;
+        assert_eq!(
+            [
+                [0, 0, 3, keyword, 0],
+                [0, 4, 1, variable, 0],
+                [0, 2, 1, operator, 0],
+                [0, 2, 2, string, 0],
+                [1, 0, 2, string, 0],
+                [1, 2, 5, keyword, 0],
+                [0, 6, 1, variable, 0],
+            ]
+            .concat(),
+            doc.semantic_tokens()
+        );
+    }
+
+    #[test]
+    fn symbols_are_nested() {
+        let doc = Document::new(
+            "file:///test.ulg",
+            "var x: Number = 1\nfn foo(): Bool\n  let y = true\n  y\nend",
+        );
+        let symbols = doc.symbols();
+        assert_eq!(2, symbols.len());
+        assert_eq!("x", symbols[0]["name"]);
+        assert_eq!("var x: Number", symbols[0]["detail"]);
+        assert_eq!("foo", symbols[1]["name"]);
+        assert_eq!("fn foo(): Bool", symbols[1]["detail"]);
+        assert_eq!(
+            json!({"line": 1, "character": 3}),
+            symbols[1]["selectionRange"]["start"]
+        );
+        assert_eq!("y", symbols[1]["children"][0]["name"]);
+    }
+}
@@ -0,0 +1,87 @@
+//! LSP Transport
+//!
+//! Reads and writes JSON-RPC messages using the base protocol framing
+//! from the Language Server Protocol. Each message is a set of
+//! headers, a blank line, and then a JSON body of the length given in
+//! the `Content-Length` header.
+
+use serde_json::Value;
+use std::io::{self, prelude::*};
+
+/// Read a Message
+///
+/// Reads the next framed message from `input`. Returns `None` once
+/// the input has been closed.
+///
+/// # Errors
+///
+/// If the message is missing its `Content-Length` header, or the body
+/// isn't valid JSON, then an error of kind `InvalidData` is returned.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            let value = value.trim().parse().map_err(invalid_data)?;
+            length = Some(value);
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(invalid_data)
+}
+
+/// Write a Message
+///
+/// Serialises `message` and writes it to `output` with the required
+/// headers.
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Create an Invalid Data Error
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn write_then_read_message() {
+        let message = json!({"jsonrpc": "2.0", "method": "exit"});
+        let mut buff = Vec::new();
+        write_message(&mut buff, &message).unwrap();
+        write_message(&mut buff, &json!(["ü"])).unwrap();
+
+        let mut input = &buff[..];
+        assert_eq!(Some(message), read_message(&mut input).unwrap());
+        assert_eq!(Some(json!(["ü"])), read_message(&mut input).unwrap());
+        assert_eq!(None, read_message(&mut input).unwrap());
+    }
+
+    #[test]
+    fn read_message_without_length() {
+        let mut input = &b"Content-Type: foo\r\n\r\n{}"[..];
+        let err = read_message(&mut input).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
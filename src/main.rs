@@ -1,26 +1,27 @@
-//! Expression tree parsing using Top-Down Operator Precedence
-//! parsing.
+//! Ullage Compiler Driver
+//!
+//! Command line entry point for the compiler. Argument parsing lives
+//! here, everything else is provided by the `ullage` library crate.
 
 #![warn(missing_docs)]
 
-use failure;
-
-pub mod compile;
-pub mod diag;
-pub mod low_loader;
-pub mod meta;
-pub mod sem;
-pub mod syntax;
-
-use crate::compile::*;
-use crate::low_loader::targets;
-use crate::syntax::text::DUMMY_SPAN;
-use crate::syntax::*;
 use docopt::Docopt;
 use serde::{Deserialize, Deserializer};
 use std::fmt;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::*;
+use ullage::analysis::{doc, fix, rename, xref};
+use ullage::compile::*;
+use ullage::diag;
+use ullage::low_loader::targets;
+use ullage::lsp;
+use ullage::meta;
+use ullage::project::{self, Project, ProjectSources};
+use ullage::sem::lint::LintLevels;
+use ullage::sem::CfgSet;
+use ullage::syntax::{self, text};
+use ullage::watch::{self, Snapshot};
 
 /// Usage Information
 ///
@@ -32,20 +33,83 @@ Ullage Compiler
 
 Usage:
   ullage [--version --help]
-  ullage [options] [-o <outfile>] [<file>]
+  ullage --explain=<code>
+  ullage lsp
+  ullage rename --at=<offset> --to=<name> [<file>]
+  ullage fix [<file>]
+  ullage doc [--format=<fmt>] [-o <outfile>] [<file>]
+  ullage test [options] [--cfg=<spec>...] [--warn=<lint>...] [<file>]
+  ullage build [options] [--cfg=<spec>...] [--warn=<lint>...] [--link-arg=<arg>...]
+  ullage [options] [--cfg=<spec>...] [--warn=<lint>...] [--link-arg=<arg>...] [-o <outfile>] [<file>]
 
 Options:
   -h, --help             Show this message.
   --version              Show version.
   -O, --optimise=<lvl>   Set the compilation optimisation level.
                          0 = off, 1 = low, 2 = medium, 3 = high, s = size.
+  -g, --debug-info       Generate debug information.
   -o, --output=<out>     Write the output to <out>.
+  --format=<fmt>         Set the documentation format. One of markdown
+                         or html [default: markdown].
+  --module-name=<name>   Set the name of the compiled module. Defaults to
+                         the name of the output file.
   --target=<triple>      Set the compilation target triple.
-  --dumpir               Dump the LLVM IR for the module.
+  --target-cpu=<cpu>     Set the CPU to generate code for.
+  --target-features=<f>  Enable or disable target features, e.g. +avx2.
+  --edition=<year>       Set the language edition. One of 2018 or 2026.
+                         Defaults to the project's edition, or 2026.
+  --cfg=<spec>           Enable a conditional compilation option. Either
+                         a flag, `name`, or a value, `key=value`.
+  -W, --warn=<lint>      Warn about <lint>. `no-<lint>` allows it,
+                         `error=<lint>` denies it, `all` warns about
+                         every lint, and `error` makes warnings errors.
+  --reloc-model=<model>  Set the relocation model. One of default,
+                         static, pic, or dynamic-no-pic.
+  --emit=<kind>          Set the kind of output to write. One of exe,
+                         ir, bc, obj, or asm. If not set the kind is
+                         inferred from the output's extension, e.g.
+                         `-o foo.ll` writes IR, otherwise exe.
+  --linker=<linker>      Use <linker> to link executables.
+  --link-arg=<arg>       Pass <arg> through to the linker.
+  --verbose-link         Print the linker command line before running it.
+  --save-temps           Keep the intermediate IR and object files next
+                         to the output.
+  --temps-dir=<dir>      Keep the intermediate files in <dir>.
+  --sysroot=<dir>        Link against the target's libraries in <dir>.
+  --crt=<kind>           Set how the C runtime is linked. One of
+                         default, static, or static-pie.
+  --overflow-checks      Abort on arithmetic overflow instead of wrapping.
+  --sanitize=<list>      Instrument the program with sanitizers. A comma
+                         separated list of address and undefined.
+  --gc=<kind>            Set how heap values are freed. One of rc, boehm,
+                         or none. Defaults to rc.
+  --no-runtime-checks    Don't check for division by zero.
+  --no-cleanup           Don't clean up the IR when optimisation is off.
+  --llvm-remarks         Report LLVM's remarks about the code as notes.
+  --stack-protector=<k>  Guard functions' stacks with a canary. One of
+                         none, basic, strong, or all. Defaults to none.
+  --stack-probes         Probe large stack frames so overflowing the stack
+                         always faults.
+  --build-info           Embed the compiler version, target, and code
+                         generation options in the output as
+                         `ullage_build_info`.
+  --freestanding         Don't link the C library, start files, or runtime
+                         library. `_start` must be provided, and the
+                         program can't print or use strings.
+  --entry=<fn>           Run the function <fn> as the entry point rather
+                         than the top level of the program.
+  --dumpir               Dump the LLVM IR for the module to stdout.
   --dumpast              Dump the syntax tree to stdout and exit.
   --prettytree           Dump a prettified summary of the syntax tree.
+  --xref                 Dump each symbol's definition and references.
   --dumptargets          Dump the available targets and exit.
   --dumptargetinfo       Dump information about the given triple.
+  --at=<offset>          Byte offset of the symbol to rename.
+  --to=<name>            New name for the renamed symbol.
+  --explain=<code>       Describe the diagnostic with the given code or lint name.
+  --error-limit=<n>      Show at most <n> diagnostics, summarising the
+                         rest. 0 shows them all [default: 20].
+  --watch                Recompile whenever the input changes.
 ";
 
 /// Program Arguments
@@ -54,14 +118,54 @@ Options:
 /// program. This is filled in for us by Docopt.
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_lsp: bool,
+    cmd_rename: bool,
+    cmd_fix: bool,
+    cmd_doc: bool,
+    cmd_test: bool,
+    cmd_build: bool,
+    flag_at: Option<usize>,
+    flag_to: Option<String>,
+    flag_explain: Option<String>,
+    flag_error_limit: usize,
+    flag_watch: bool,
     flag_output: Option<String>,
+    flag_format: String,
+    flag_module_name: Option<String>,
     flag_optimise: Option<OptFlag>,
+    flag_debug_info: bool,
     flag_target: Option<String>,
+    flag_target_cpu: Option<String>,
+    flag_target_features: Option<String>,
+    flag_edition: Option<String>,
+    flag_cfg: Vec<String>,
+    flag_warn: Vec<String>,
+    flag_reloc_model: Option<String>,
+    flag_emit: Option<String>,
+    flag_linker: Option<String>,
+    flag_link_arg: Vec<String>,
+    flag_verbose_link: bool,
+    flag_save_temps: bool,
+    flag_temps_dir: Option<String>,
+    flag_sysroot: Option<String>,
+    flag_crt: Option<String>,
+    flag_overflow_checks: bool,
+    flag_sanitize: Option<String>,
+    flag_gc: Option<String>,
+    flag_no_runtime_checks: bool,
+    flag_no_cleanup: bool,
+    flag_llvm_remarks: bool,
+    flag_stack_protector: Option<String>,
+    flag_stack_probes: bool,
+    flag_build_info: bool,
+    flag_freestanding: bool,
+    flag_entry: Option<String>,
     arg_file: Option<String>,
 
     // TODO: maybe move these dump options into a single flag?
     flag_dumpast: bool,
     flag_prettytree: bool,
+    flag_xref: bool,
     flag_dumpir: bool,
     flag_dumptargets: bool,
     flag_dumptargetinfo: bool,
@@ -70,7 +174,7 @@ struct Args {
 /// Optimisation Level
 ///
 /// Used to hold the requested optimisation level
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 enum OptFlag {
     /// numeric optimisation level
     Numeric(u64),
@@ -148,6 +252,16 @@ fn main() {
         })
         .unwrap_or_else(|e| e.exit());
 
+    if args.cmd_lsp {
+        serve_language_server();
+    }
+    if let Some(id) = &args.flag_explain {
+        explain(id);
+    }
+    if args.flag_watch {
+        watch_inputs(args.cmd_build, args.arg_file.clone());
+    }
+
     if args.flag_dumptargets {
         targets::dump_targets();
         if args.arg_file.is_none() {
@@ -155,7 +269,10 @@ fn main() {
         }
     }
 
-    let triple = args.flag_target.unwrap_or_else(targets::get_default_triple);
+    let triple = args
+        .flag_target
+        .clone()
+        .unwrap_or_else(targets::get_default_triple);
     let target = targets::Target::from_triple(&triple).unwrap_or_else(|e| {
         eprintln!("error: could not create target: {}", e);
         exit(1);
@@ -168,89 +285,586 @@ fn main() {
         }
     }
 
-    let output_path = &args.flag_output.unwrap_or_else(|| "a.out".to_string());
-    let output_path = Path::new(&output_path);
+    let inputs = Inputs::read(&args, &target);
+    let source = &inputs.source;
 
-    // Load the file into memory, so we can parse it into a syntax tree
-    let source = if let Some(path) = args.arg_file {
-        text::SourceText::from_path(Path::new(&path))
-    } else {
-        text::SourceText::from_stdin()
-    };
-    let source = source.unwrap_or_else(|e| {
-        eprintln!("error: could not read input: {}", e);
-        exit(1)
-    });
+    if args.cmd_doc {
+        write_docs(source, &args.flag_format, inputs.output_path);
+    }
+    if args.cmd_rename {
+        let at = text::Pos::from(args.flag_at.unwrap_or_default());
+        rename_symbol(source, at, args.flag_to.as_deref().unwrap_or_default());
+    }
+    if args.cmd_fix {
+        apply_fixes(source, args.arg_file.as_deref());
+    }
+
+    let output_path = inputs
+        .output_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("a.out"));
+    let limit = args.flag_error_limit;
 
     // Parse the module
-    let tree = syntax::SyntaxTree::parse(&source);
+    let edition = match &args.flag_edition {
+        Some(year) => parse_or_exit(year),
+        None => inputs.edition,
+    };
+    let tree = syntax::SyntaxTree::parse_with_edition(source, edition);
     if tree.has_diagnostics() {
         eprintln!("error: could not parse source: one or more errors:");
-        dump_diagnostics(&source, tree.diagnostics());
+        dump_diagnostics(inputs.render(), tree.diagnostics(), limit);
         exit(1)
     };
 
     // Are we just dumping the AST or compiling the whole thing?
     if args.flag_dumpast {
-        println!("parsed AST: {:#?}", tree.root());
+        println!("parsed AST: {:#?}", tree);
         exit(0);
     }
     if args.flag_prettytree {
         tree.write_to(&mut std::io::stdout()).unwrap();
         exit(0);
     }
+    if args.flag_xref {
+        xref::write_xref(source, &mut std::io::stdout()).unwrap();
+        exit(0);
+    }
+
+    let options = compilation_options(&args, &target, edition, &output_path);
+    let comp = Compilation::new(tree, options).unwrap_or_else(|diagnostics| {
+        dump_diagnostics(inputs.render(), &diagnostics, limit);
+        eprintln!(
+            "error: compilation failed with {} errors",
+            diagnostics.len()
+        );
+        exit(1);
+    });
+
+    if args.cmd_test {
+        run_tests(inputs.render(), comp, limit);
+    }
+
+    // Create a compilation, and emit to the output path
+    if let Some(dir) = output_path.parent() {
+        fs::create_dir_all(dir).unwrap_or_else(|e| {
+            eprintln!("error: could not create output directory: {}", e);
+            exit(1);
+        });
+    }
+    let emit_result = comp.emit(&target, &output_path);
+    report_emit_result(inputs.render(), emit_result, limit);
+}
+
+/// The Sources Being Compiled
+///
+/// A project's sources, or a file and the files it includes, are
+/// combined into a single source to be compiled.
+struct Inputs {
+    /// The source to compile
+    source: text::SourceText,
+    /// The sources the source was combined from, if there are more
+    /// than one
+    sources: Option<ProjectSources>,
+    /// The output path, if one was given or the project chose one
+    output_path: Option<PathBuf>,
+    /// The edition the sources are written in
+    edition: syntax::Edition,
+}
+
+impl Inputs {
+    /// Read the Inputs
+    ///
+    /// Loads the project for `build`, otherwise the file or standard
+    /// input, along with any files they include. Exits the process if
+    /// they can't be read.
+    fn read(args: &Args, target: &targets::Target) -> Self {
+        let mut output_path = args.flag_output.as_ref().map(PathBuf::from);
+
+        if args.cmd_build {
+            let (project, sources) = load_project().unwrap_or_else(|e| {
+                eprintln!("error: could not load project: {}", e);
+                exit(1)
+            });
+            if output_path.is_none() {
+                output_path = Some(project.artifact_path(target.triple()));
+            }
+            return Inputs {
+                source: sources.combined(project.name()),
+                sources: Some(sources),
+                output_path,
+                edition: project.edition(),
+            };
+        }
+
+        let source = match args.arg_file.as_deref() {
+            Some("-") | None => text::SourceText::from_stdin(),
+            // SAFETY: A large input is mapped rather than read. The
+            // compiler reads its input once and exits, so the mapping
+            // only lives for a single compilation. As with other tools
+            // which map their inputs, editing the file while it is
+            // being compiled is a race left to the user. The language
+            // server, which holds sources while they are edited, never
+            // maps them.
+            Some(path) => unsafe { text::SourceText::map_path(Path::new(path)) },
+        };
+        let source = source.unwrap_or_else(|e| {
+            eprintln!("error: could not read input: {}", e);
+            exit(1)
+        });
+        let name = source.name().to_string();
+        let sources =
+            ProjectSources::with_includes(vec![source], Path::new("")).unwrap_or_else(|e| {
+                eprintln!("error: could not read input: {}", e);
+                exit(1)
+            });
+        let (source, sources) = if sources.included_paths().is_empty() {
+            (sources.into_main_source(), None)
+        } else {
+            (sources.combined(&name), Some(sources))
+        };
+        Inputs {
+            source,
+            sources,
+            output_path,
+            edition: syntax::Edition::LATEST,
+        }
+    }
 
-    let options = CompilationOptions::default()
+    /// Get the Renderer for Diagnostics in the Inputs
+    fn render(&self) -> &dyn Render {
+        match &self.sources {
+            Some(sources) => sources,
+            None => &self.source,
+        }
+    }
+}
+
+/// Run the Language Server
+///
+/// Serves the language server protocol over standard input and
+/// output, then exits the process.
+fn serve_language_server() -> ! {
+    let stdin = std::io::stdin();
+    if let Err(e) = lsp::serve(stdin.lock(), std::io::stdout()) {
+        eprintln!("error: language server failed: {}", e);
+        exit(1);
+    }
+    exit(0);
+}
+
+/// Explain a Diagnostic
+///
+/// Prints the explanation of the diagnostic with the given code or
+/// lint name, then exits the process.
+fn explain(id: &str) -> ! {
+    match diag::Code::from_id(id).or_else(|| diag::Code::from_lint_name(id)) {
+        Some(code) => {
+            println!("{}\n\n{}", code, code.explanation());
+            exit(0);
+        }
+        None => {
+            eprintln!("error: unknown diagnostic code '{}'", id);
+            exit(1);
+        }
+    }
+}
+
+/// Write the Documentation for a Source
+///
+/// Writes the documentation to `output_path`, or to standard output if
+/// there isn't one, then exits the process.
+fn write_docs(source: &text::SourceText, format: &str, output_path: Option<PathBuf>) -> ! {
+    let title = match source.name() {
+        "<stdin>" => "Documentation",
+        name => Path::new(name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(name),
+    };
+    let items = doc::document(source);
+    let page = doc::render(title, &items, parse_or_exit(format));
+    match output_path {
+        Some(path) => fs::write(path, page).unwrap_or_else(|e| {
+            eprintln!("error: could not write documentation: {}", e);
+            exit(1);
+        }),
+        None => print!("{}", page),
+    }
+    exit(0);
+}
+
+/// Rename a Symbol
+///
+/// Prints the source with the symbol at `at` renamed to `to`, then
+/// exits the process.
+fn rename_symbol(source: &text::SourceText, at: text::Pos, to: &str) -> ! {
+    match rename::rename(source, at, to) {
+        Ok(edits) => print!("{}", rename::apply_edits(source, &edits)),
+        Err(e) => {
+            eprintln!("error: could not rename: {}", e);
+            exit(1);
+        }
+    }
+    exit(0);
+}
+
+/// Apply the Suggested Fixes to a Source
+///
+/// The fixed source is written back to `file`, or to standard output
+/// if the source was read from standard input. Exits the process once
+/// the fixes are applied.
+fn apply_fixes(source: &text::SourceText, file: Option<&str>) -> ! {
+    let (fixed, count) = fix::apply_fixes(source);
+    match file {
+        Some("-") | None => print!("{}", fixed),
+        Some(path) => std::fs::write(path, fixed).unwrap_or_else(|e| {
+            eprintln!("error: could not write fixes: {}", e);
+            exit(1);
+        }),
+    }
+    let plural = if count == 1 { "" } else { "es" };
+    eprintln!("applied {} fix{}", count, plural);
+    exit(0);
+}
+
+/// Build the Compilation Options
+///
+/// Maps the command line arguments onto the library's options. Exits
+/// the process if any of them aren't valid.
+fn compilation_options(
+    args: &Args,
+    target: &targets::Target,
+    edition: syntax::Edition,
+    output_path: &Path,
+) -> CompilationOptions {
+    let mut options = CompilationOptions::default()
         .with_dump_ir(args.flag_dumpir)
+        .with_debug_info(args.flag_debug_info)
         .with_opt_level(
             args.flag_optimise
-                .map_or(OptimisationLevel::Off, |o| o.into()),
+                .as_ref()
+                .map_or(OptimisationLevel::Off, |&o| o.into()),
+        )
+        .with_emit_kind(match &args.flag_emit {
+            Some(kind) => parse_or_exit(kind),
+            None => EmitKind::from_extension(output_path).unwrap_or_default(),
+        })
+        .with_linker_args(args.flag_link_arg.clone())
+        .with_overflow_checks(args.flag_overflow_checks)
+        .with_no_runtime_checks(args.flag_no_runtime_checks)
+        .with_no_cleanup(args.flag_no_cleanup)
+        .with_llvm_remarks(args.flag_llvm_remarks)
+        .with_stack_probes(args.flag_stack_probes)
+        .with_freestanding(args.flag_freestanding)
+        .with_build_info(args.flag_build_info)
+        .with_edition(edition)
+        .with_cfg(
+            args.flag_cfg
+                .iter()
+                .fold(CfgSet::for_target(target.triple()), |cfg, option| {
+                    cfg.with_option(option)
+                }),
         );
-    let comp = match Compilation::new(tree, options) {
-        Ok(c) => c,
+    let mut lint_levels = LintLevels::new();
+    for option in args.flag_warn.iter() {
+        if option == "error" {
+            options = options.with_warning_level(WarningLevel::Deny);
+        } else {
+            lint_levels = lint_levels.with_option(option).unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                exit(1)
+            });
+        }
+    }
+    options = options.with_lint_levels(lint_levels);
+    if let Some(cpu) = &args.flag_target_cpu {
+        options = options.with_target_cpu(cpu.as_str());
+    }
+    if let Some(features) = &args.flag_target_features {
+        options = options.with_target_features(features.as_str());
+    }
+    if let Some(model) = &args.flag_reloc_model {
+        options = options.with_reloc_model(parse_or_exit(model));
+    }
+    if let Some(sanitizers) = &args.flag_sanitize {
+        options = options.with_sanitizers(parse_or_exit(sanitizers));
+    }
+    if let Some(gc) = &args.flag_gc {
+        options = options.with_gc(parse_or_exit(gc));
+    }
+    if let Some(protector) = &args.flag_stack_protector {
+        options = options.with_stack_protector(parse_or_exit(protector));
+    }
+    if let Some(entry) = &args.flag_entry {
+        options = options.with_entry(entry.as_str());
+    }
+    if let Some(name) = &args.flag_module_name {
+        options = options.with_module_name(name.as_str());
+    }
+    if let Some(sysroot) = &args.flag_sysroot {
+        options = options.with_sysroot(sysroot.as_str());
+    }
+    if let Some(crt) = &args.flag_crt {
+        options = options.with_c_runtime(parse_or_exit(crt));
+    }
+    options = options.with_save_temps(args.flag_save_temps);
+    if let Some(dir) = &args.flag_temps_dir {
+        options = options.with_temps_dir(dir.as_str());
+    }
+    if args.flag_verbose_link {
+        options = options.with_before_link(|command| {
+            eprintln!("link: {}", shell_words(command));
+            Ok(())
+        });
+    }
+    if let Some(linker) = &args.flag_linker {
+        options = options.with_linker(linker.as_str());
+    }
+    options
+}
+
+/// Report the Result of Emitting a Compilation
+///
+/// Prints any warnings. On failure the errors are printed, along with
+/// the linker's output if linking failed, and the process exits with a
+/// failure status.
+fn report_emit_result(
+    source: &dyn Render,
+    result: CompResult<Vec<diag::Diagnostic>>,
+    limit: usize,
+) {
+    match result {
+        Ok(warnings) => dump_diagnostics_at(source, &warnings, "warning", limit),
+        Err(CompError::Codegen(diagnostics)) => {
+            dump_diagnostics(source, &diagnostics, limit);
+            handle_comp_err(&CompError::Codegen(diagnostics));
+        }
+        Err(CompError::Linker(e)) => {
+            eprintln!("error: linking failed: {}", e);
+            if let Some(output) = e.output().filter(|o| !o.trim().is_empty()) {
+                eprintln!("note: linker output:");
+                for line in output.trim_end().lines() {
+                    eprintln!("  {}", line);
+                }
+            }
+            exit(1);
+        }
         Err(e) => handle_comp_err(&e),
-    };
+    }
+}
 
-    if comp.has_diagnostics() {
-        dump_diagnostics(&source, comp.diagnostics());
-        let diag_count = comp.diagnostics().len();
-        eprintln!("error: compilation failed with {} errors", diag_count);
+/// Join a Command Line for Display
+///
+/// Arguments which contain spaces or shell metacharacters are quoted
+/// so the line can be pasted back into a shell.
+fn shell_words(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_=+/.,:@%".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Watch the Inputs and Recompile
+///
+/// Runs the compiler again, without `--watch`, each time one of its
+/// inputs changes. Each run reports its own diagnostics. When
+/// building a project every source in it is watched, along with the
+/// manifest.
+fn watch_inputs(is_build: bool, file: Option<String>) -> ! {
+    if !is_build && file.as_deref().is_none_or(|f| f == "-") {
+        eprintln!("error: standard input can't be watched, pass a file");
         exit(1);
     }
+    let paths = || match &file {
+        Some(file) if !is_build => file_paths(file),
+        _ => project_paths(),
+    };
+    let exe = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!("error: could not find the compiler executable: {}", e);
+        exit(1)
+    });
+    let child_args: Vec<_> = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != "--watch")
+        .collect();
 
-    // Create a compilation, and emit to the output path
-    let emit_result = comp.emit(&target, &output_path);
+    let mut snapshot = Snapshot::take(paths());
+    loop {
+        match Command::new(&exe).args(&child_args).status() {
+            Ok(status) if status.success() => eprintln!("watch: compilation succeeded"),
+            Ok(_) => eprintln!("watch: compilation failed"),
+            Err(e) => eprintln!("error: could not run the compiler: {}", e),
+        }
+        let plural = if snapshot.len() == 1 { "" } else { "s" };
+        eprintln!(
+            "watch: waiting for changes to {} file{}",
+            snapshot.len(),
+            plural
+        );
+        snapshot = watch::wait_for_change(&snapshot, paths);
+    }
+}
 
-    // Print any failures encountered and return a failure status
-    if let Err(e) = emit_result {
-        handle_comp_err(&e);
+/// Get the Paths to Watch for a Single File
+///
+/// Returns the file along with any files it includes.
+fn file_paths(file: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(file)];
+    if let Ok(source) = text::SourceText::from_path(Path::new(file)) {
+        if let Ok(sources) = ProjectSources::with_includes(vec![source], Path::new("")) {
+            paths.extend_from_slice(sources.included_paths());
+        }
     }
+    paths
 }
 
-/// Write Dignostics to STDERR
+/// Get the Paths to Watch in a Project
 ///
-fn dump_diagnostics(source: &text::SourceText, diagnostics: &[diag::Diagnostic]) {
-    for error in diagnostics.iter() {
-        if error.span == DUMMY_SPAN {
-            eprintln!("{}:error: {}", source.name(), error.message);
-        } else {
-            let pos = source.line_pos(error.span.start());
-            eprintln!(
-                "{}:{}:{}:error: {}",
-                source.name(),
-                pos.0,
-                pos.1,
-                error.message
-            );
-            let (s, e) = source.line_extents(error.span);
-            eprintln!("     |");
-            let mut line_no = pos.0;
-            for line in source.slice(s, e).lines() {
-                eprintln!("{:4} | {}", line_no, line);
-                line_no += 1;
-            }
-            eprintln!("");
+/// Returns the manifest, the main source, and the other sources of
+/// the project in the current directory. The main source is always
+/// included so that creating it is noticed.
+fn project_paths() -> Vec<PathBuf> {
+    let project = match std::env::current_dir().map(|dir| Project::find(&dir)) {
+        Ok(Ok(project)) => project,
+        _ => return Vec::new(),
+    };
+    let mut paths = project.source_paths().unwrap_or_default();
+    let main = project.root().join("src").join("main.ulg");
+    if !paths.contains(&main) {
+        paths.push(main);
+    }
+    if let Ok(sources) = project.load_sources() {
+        paths.extend_from_slice(sources.included_paths());
+    }
+    paths.push(project.root().join(project::MANIFEST_NAME));
+    paths
+}
+
+/// Load the Project in the Current Directory
+///
+/// Finds the project containing the working directory and loads its
+/// sources.
+fn load_project() -> Result<(Project, ProjectSources), failure::Error> {
+    let project = Project::find(&std::env::current_dir()?)?;
+    let sources = project.load_sources()?;
+    Ok((project, sources))
+}
+
+/// Diagnostic Renderer
+///
+/// The source diagnostics are rendered against. A project's
+/// diagnostics are raised against the combination of its sources, so
+/// are rendered against the file they came from.
+trait Render {
+    /// Render a Diagnostic at the Given Level
+    fn render(&self, diagnostic: &diag::Diagnostic, level: &str) -> String;
+}
+
+impl Render for text::SourceText {
+    fn render(&self, diagnostic: &diag::Diagnostic, level: &str) -> String {
+        diagnostic.render(self, level)
+    }
+}
+
+impl Render for ProjectSources {
+    fn render(&self, diagnostic: &diag::Diagnostic, level: &str) -> String {
+        ProjectSources::render(self, diagnostic, level)
+    }
+}
+
+/// Run the Tests in a Compilation
+///
+/// Runs each of the program's test functions and reports whether it
+/// passed. Exits the process with a failure status if any test didn't
+/// pass.
+fn run_tests(source: &dyn Render, comp: Compilation, limit: usize) -> ! {
+    let results = comp.run_tests().unwrap_or_else(|e| {
+        if let CompError::Codegen(diagnostics) = &e {
+            dump_diagnostics(source, diagnostics, limit);
         }
+        handle_comp_err(&e)
+    });
+
+    let plural = if results.len() == 1 { "" } else { "s" };
+    println!("running {} test{}", results.len(), plural);
+    for result in &results {
+        let outcome = match result.outcome {
+            TestOutcome::Passed => "ok",
+            TestOutcome::Failed => "FAILED",
+            TestOutcome::Invalid => "INVALID (tests take no parameters and return Bool)",
+        };
+        println!("test {} ... {}", result.name, outcome);
+    }
+
+    let passed = results
+        .iter()
+        .filter(|r| r.outcome == TestOutcome::Passed)
+        .count();
+    let failed = results.len() - passed;
+    let status = if failed == 0 { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        status, passed, failed
+    );
+    exit(if failed == 0 { 0 } else { 1 });
+}
+
+/// Parse a Command Line Value
+///
+/// Parses the given flag value, printing the error and exiting the
+/// process if it isn't valid.
+fn parse_or_exit<T>(value: &str) -> T
+where
+    T: std::str::FromStr<Err = String>,
+{
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        exit(1)
+    })
+}
+
+/// Write Dignostics to STDERR
+///
+fn dump_diagnostics(source: &dyn Render, diagnostics: &[diag::Diagnostic], limit: usize) {
+    dump_diagnostics_at(source, diagnostics, "error", limit);
+}
+
+/// Write Diagnostics to STDERR at a Given Level
+///
+/// At most `limit` diagnostics are written in full, the rest are
+/// summarised. A limit of `0` writes them all.
+fn dump_diagnostics_at(
+    source: &dyn Render,
+    diagnostics: &[diag::Diagnostic],
+    level: &str,
+    limit: usize,
+) {
+    let shown = if limit == 0 {
+        diagnostics.len()
+    } else {
+        limit.min(diagnostics.len())
+    };
+    for diagnostic in diagnostics[..shown].iter() {
+        eprintln!("{}", source.render(diagnostic, level));
+    }
+    if shown < diagnostics.len() {
+        eprintln!(
+            "{}: {}",
+            level,
+            diag::summarise(&diagnostics[shown..], level)
+        );
     }
 }
 
@@ -1,7 +1,8 @@
 //! # Meta Module
 //!
 //! This module contains metadata about the compiler driver. It's
-//! basically just used to expose the version number.
+//! basically just used to expose the version number, and to describe
+//! the build of a program for embedding in it.
 
 /// Version Number
 ///
@@ -10,6 +11,13 @@
 /// empty.
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
+/// Git Hash
+///
+/// The short hash of the commit the compiler was built from. This is
+/// set by the build script, and is empty if the compiler wasn't built
+/// from a git checkout.
+const GIT_HASH: Option<&'static str> = option_env!("ULLAGE_GIT_HASH");
+
 /// Retrieve the Version Numer
 ///
 /// If no version number is available then 'unknown' is returned.
@@ -23,3 +31,27 @@ pub fn version() -> &'static str {
 pub fn descriptive_version() -> String {
     format!("ullage {}", version())
 }
+
+/// Retrieve the Git Hash
+///
+/// Returns the short hash of the commit the compiler was built from,
+/// if it is known.
+pub fn git_hash() -> Option<&'static str> {
+    GIT_HASH
+}
+
+/// Format Build Information
+///
+/// Describes the build of a program, for embedding in its output. The
+/// description holds the compiler's version and commit, the target
+/// triple, and the options the compiler was invoked with, e.g.
+/// `ullage 0.1.0 (1a2b3c4); target=x86_64-pc-linux-gnu; options=-O2`.
+pub fn build_info(target: &str, options: &[String]) -> String {
+    format!(
+        "{} ({}); target={}; options={}",
+        descriptive_version(),
+        git_hash().unwrap_or("unknown"),
+        target,
+        options.join(" ")
+    )
+}
@@ -0,0 +1,763 @@
+//! Ullage Projects
+//!
+//! A project is a directory containing an `Ullage.toml` manifest and a
+//! `src/` folder of sources. The program starts in `src/main.ulg`, and
+//! every other source in `src/` is compiled along with it. Artifacts
+//! are written under `target/<triple>/`, with the executable named
+//! after the project.
+//!
+//! The language doesn't have modules yet, so a project's sources are
+//! combined into a single source before being compiled. Diagnostics
+//! raised against the combined source are mapped back to the file
+//! they came from when they are rendered. Sources may also splice
+//! in other files with an `include 'path.ulg'` directive.
+//!
+//! As the combination is parsed and bound as one tree, a project's
+//! files aren't parsed in parallel. Identifiers are interned by the
+//! source they are parsed from, so trees parsed from separate files
+//! can't yet be merged into one program.
+
+use crate::diag::Diagnostic;
+use crate::syntax::text::{Pos, SourceText, Span, DUMMY_SPAN};
+use crate::syntax::Edition;
+use failure::Fail;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The Name of the Project Manifest
+pub const MANIFEST_NAME: &str = "Ullage.toml";
+
+/// The Source File the Program Starts In
+///
+/// Projects use the same `.ulg` extension as single sources, rather
+/// than a shorter `.ul`.
+const MAIN_SOURCE: &str = "main.ulg";
+
+/// Project Error
+///
+/// The ways loading a project can fail.
+#[derive(Fail, Debug)]
+pub enum ProjectError {
+    /// No manifest was found
+    #[fail(display = "could not find {} in '{}' or any parent", _0, _1)]
+    NotFound(&'static str, String),
+
+    /// The manifest couldn't be understood
+    #[fail(display = "invalid manifest, line {}: {}", _0, _1)]
+    Manifest(usize, String),
+
+    /// The manifest is missing a required key
+    #[fail(display = "invalid manifest: missing `{}`", _0)]
+    MissingKey(&'static str),
+
+    /// There were no sources to compile
+    #[fail(display = "no sources to compile")]
+    NoSources,
+
+    /// The project doesn't have a `src/main.ulg`
+    #[fail(display = "project has no source at '{}'", _0)]
+    NoMain(String),
+
+    /// An included file couldn't be read
+    #[fail(display = "could not include '{}' from {}: {}", _0, _1, _2)]
+    Include(String, String, #[cause] io::Error),
+
+    /// A file includes itself, directly or through other files
+    #[fail(display = "'{}' includes itself", _0)]
+    IncludeCycle(String),
+
+    /// Wrapped IO Error
+    #[fail(display = "IO error: {}", _0)]
+    IO(#[cause] io::Error),
+}
+
+impl From<io::Error> for ProjectError {
+    fn from(e: io::Error) -> Self {
+        ProjectError::IO(e)
+    }
+}
+
+/// Project Manifest
+///
+/// The settings read from a project's `Ullage.toml`.
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    /// The name of the project, used to name its executable
+    pub name: String,
+    /// The edition of the language the project is written in. If
+    /// the manifest doesn't give one the latest edition is used.
+    pub edition: Edition,
+}
+
+impl Manifest {
+    /// Parse a Manifest
+    ///
+    /// Only the subset of TOML the manifest uses is understood:
+    /// tables, comments, and keys with basic or literal string
+    /// values, i.e. double or single quoted. The `[package]` table
+    /// must have a `name`, and may have an `edition`. Unknown keys are
+    /// ignored so that manifests can be extended later.
+    pub fn parse(text: &str) -> Result<Self, ProjectError> {
+        let mut table = String::new();
+        let mut name = None;
+        let mut edition = Edition::LATEST;
+        for (idx, line) in text.lines().enumerate() {
+            let err = |message: &str| ProjectError::Manifest(idx + 1, message.into());
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let (header, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| err("unclosed table"))?;
+                if !is_comment(rest) {
+                    return Err(err("expected a comment after the table"));
+                }
+                table = header.trim().to_string();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| err("expected `=`"))?;
+            let value = parse_string(value).filter(|v| !v.is_empty());
+            match (&table[..], key.trim()) {
+                ("package", "name") => {
+                    let value = value.ok_or_else(|| err("name should be a non-empty string"))?;
+                    name = Some(value.to_string());
+                }
+                ("package", "edition") => {
+                    let value = value.ok_or_else(|| err("edition should be a string"))?;
+                    edition = value.parse().map_err(|e: String| err(&e))?;
+                }
+                _ => (),
+            }
+        }
+        let name = name.ok_or(ProjectError::MissingKey("package.name"))?;
+        Ok(Manifest { name, edition })
+    }
+}
+
+/// Parse a Manifest String Value
+///
+/// Accepts a string in either double or single quotes, optionally
+/// followed by a comment. Escapes aren't supported, so the string
+/// can't contain its own quote.
+fn parse_string(value: &str) -> Option<&str> {
+    let value = value.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let (string, rest) = value[1..].split_once(quote)?;
+    Some(string).filter(|_| is_comment(rest))
+}
+
+/// Is the Rest of a Line Empty or a Comment?
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Project
+///
+/// A project found on disk, along with its parsed manifest.
+#[derive(Debug)]
+pub struct Project {
+    /// The directory containing the manifest
+    root: PathBuf,
+    /// The project's manifest
+    manifest: Manifest,
+}
+
+impl Project {
+    /// Find the Project Containing a Directory
+    ///
+    /// Searches `dir` and then each of its parents for a manifest,
+    /// and loads the first one found.
+    pub fn find(dir: &Path) -> Result<Self, ProjectError> {
+        let root = dir
+            .ancestors()
+            .find(|d| d.join(MANIFEST_NAME).is_file())
+            .ok_or_else(|| ProjectError::NotFound(MANIFEST_NAME, dir.display().to_string()))?;
+        let manifest = Manifest::parse(&fs::read_to_string(root.join(MANIFEST_NAME))?)?;
+        Ok(Project {
+            root: root.to_owned(),
+            manifest,
+        })
+    }
+
+    /// Get the Project's Name
+    pub fn name(&self) -> &str {
+        &self.manifest.name
+    }
+
+    /// Get the Edition the Project is Written In
+    pub fn edition(&self) -> Edition {
+        self.manifest.edition
+    }
+
+    /// Get the Project's Root Directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the Path of the Executable
+    ///
+    /// Artifacts for each target are kept apart, under
+    /// `target/<triple>/`.
+    pub fn artifact_path(&self, triple: &str) -> PathBuf {
+        self.root.join("target").join(triple).join(self.name())
+    }
+
+    /// Find the Project's Sources
+    ///
+    /// Returns the path of each `.ulg` file in `src/`, in a stable
+    /// order. `src/main.ulg` always comes last so that the functions
+    /// in the other sources are declared before the program runs.
+    pub fn source_paths(&self) -> Result<Vec<PathBuf>, ProjectError> {
+        let src = self.root.join("src");
+        let main = src.join(MAIN_SOURCE);
+        if !main.is_file() {
+            return Err(ProjectError::NoMain(main.display().to_string()));
+        }
+        let mut paths = Vec::new();
+        find_sources(&src, &mut paths)?;
+        paths.retain(|p| *p != main);
+        paths.sort();
+        paths.push(main);
+        Ok(paths)
+    }
+
+    /// Load the Project's Sources
+    pub fn load_sources(&self) -> Result<ProjectSources, ProjectError> {
+        let files = self
+            .source_paths()?
+            .iter()
+            .map(|path| {
+                let name = path.strip_prefix(&self.root).unwrap_or(path);
+                let text = fs::read_to_string(path)?;
+                Ok(SourceText::with_name(text, name.display().to_string()))
+            })
+            .collect::<Result<Vec<_>, ProjectError>>()?;
+        ProjectSources::with_includes(files, &self.root)
+    }
+}
+
+/// Find the Sources in a Directory
+///
+/// Recursively walks the directory collecting the `.ulg` files in it.
+fn find_sources(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_sources(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "ulg") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Project Sources
+///
+/// The sources of a project, or of a single file, combined into one
+/// source. Any file a source includes with an `include` directive is
+/// spliced into the combination in place of the directive. The
+/// combination is made up of segments, each of which is a run of text
+/// from a single file, so positions in it can be mapped back to the
+/// file they came from.
+pub struct ProjectSources {
+    /// Each source, followed by the files included by them
+    files: Vec<SourceText>,
+    /// The index of the source the program starts in
+    main: usize,
+    /// The paths of the included files
+    included: Vec<PathBuf>,
+    /// The runs of text which make up the combined source, in order
+    segments: Vec<Segment>,
+    /// The combined text
+    text: String,
+}
+
+/// Segment of the Combined Source
+///
+/// A run of the combined source which comes from a single file.
+struct Segment {
+    /// Where the segment starts in the combined source
+    start: usize,
+    /// The index of the file the segment comes from
+    file: usize,
+    /// Where the segment starts in its file
+    file_start: usize,
+}
+
+impl ProjectSources {
+    /// Create a Set of Project Sources
+    ///
+    /// The last source is the one the program starts in. Include
+    /// directives are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no sources.
+    pub fn new(sources: Vec<SourceText>) -> Result<Self, ProjectError> {
+        if sources.is_empty() {
+            return Err(ProjectError::NoSources);
+        }
+        let mut combined = ProjectSources::empty();
+        for source in sources {
+            combined.separate();
+            let len = source.slice(source.start(), source.end()).len();
+            combined.main = combined.push_file(source);
+            combined.push_segment(combined.main, 0, len);
+        }
+        Ok(combined)
+    }
+
+    /// Create a Set of Sources and Resolve their Includes
+    ///
+    /// Like `new`, but `include` directives in the sources, and in the
+    /// files they include, are replaced by the text of the included
+    /// file. Paths are relative to the file with the directive. The
+    /// sources are named relative to `root`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no sources, if an included file can't be
+    /// read, or if a file ends up including itself.
+    pub fn with_includes(sources: Vec<SourceText>, root: &Path) -> Result<Self, ProjectError> {
+        if sources.is_empty() {
+            return Err(ProjectError::NoSources);
+        }
+        let mut combined = ProjectSources::empty();
+        for source in sources {
+            combined.separate();
+            let path = root.join(source.name());
+            combined.main = combined.files.len();
+            combined.include(source, &path, root, &mut Vec::new())?;
+        }
+        Ok(combined)
+    }
+
+    /// Create an Empty Set of Sources
+    fn empty() -> Self {
+        ProjectSources {
+            files: Vec::new(),
+            main: 0,
+            included: Vec::new(),
+            segments: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    /// Combine the Sources
+    ///
+    /// Joins each source together into a single source which can be
+    /// parsed and compiled.
+    pub fn combined(&self, name: &str) -> SourceText {
+        SourceText::with_name(self.text.clone(), name)
+    }
+
+    /// Take the Main Source
+    ///
+    /// Returns the source the program starts in, as it was before any
+    /// includes were spliced in.
+    pub fn into_main_source(mut self) -> SourceText {
+        self.files.swap_remove(self.main)
+    }
+
+    /// Get the Paths of the Included Files
+    ///
+    /// Each file is listed once, in the order they were first
+    /// included.
+    pub fn included_paths(&self) -> &[PathBuf] {
+        &self.included
+    }
+
+    /// Render a Diagnostic
+    ///
+    /// Renders a diagnostic raised against the combined source at the
+    /// file it came from. Labels pointing into other files become
+    /// notes giving their location. Diagnostics without a location
+    /// are rendered against the main source.
+    pub fn render(&self, diagnostic: &Diagnostic, level: &str) -> String {
+        let segment = match diagnostic.span {
+            DUMMY_SPAN => self.main_segment(),
+            span => self.segment_at(span.start()),
+        };
+        let source = &self.files[segment.file];
+        let mut local = diagnostic.clone();
+        local.span = self.localise(diagnostic.span, segment);
+        local.labels.clear();
+        for label in &diagnostic.labels {
+            let label_segment = self.segment_at(label.span.start());
+            let span = self.localise(label.span, label_segment);
+            if label_segment.file == segment.file {
+                local = local.with_label(span, label.message.clone());
+            } else {
+                let label_source = &self.files[label_segment.file];
+                let (line, col) = label_source.position(span);
+                local = local.with_note(format!(
+                    "{} at {}:{}:{}",
+                    label.message,
+                    label_source.name(),
+                    line,
+                    col
+                ));
+            }
+        }
+        if let Some(fix) = &mut local.fix {
+            fix.span = self.localise(fix.span, segment);
+        }
+        local.render(source, level)
+    }
+
+    /// Add a File and Splice in its Includes
+    ///
+    /// The `stack` holds the files currently being included, so that
+    /// cycles can be found.
+    fn include(
+        &mut self,
+        source: SourceText,
+        path: &Path,
+        root: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), ProjectError> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        if stack.contains(&canonical) {
+            return Err(ProjectError::IncludeCycle(source.name().to_string()));
+        }
+        let text = source.slice(source.start(), source.end());
+        let len = text.len();
+        let directives = include_directives(text);
+        let dir = path.parent().unwrap_or(root);
+        let file = self.push_file(source);
+        stack.push(canonical);
+
+        let mut pos = 0;
+        for (line, start, end, target) in directives {
+            self.push_segment(file, pos, start);
+            let target = dir.join(target);
+            let name = target.strip_prefix(root).unwrap_or(&target);
+            let included = SourceText::from_path(&target).map_err(|e| {
+                let from = format!("{}:{}", self.files[file].name(), line);
+                ProjectError::Include(name.display().to_string(), from, e)
+            })?;
+            let included = SourceText::with_name(
+                included.slice(included.start(), included.end()),
+                name.display().to_string(),
+            );
+            if !self.included.contains(&target) {
+                self.included.push(target.clone());
+            }
+            self.include(included, &target, root, stack)?;
+            pos = end;
+        }
+        self.push_segment(file, pos, len);
+
+        stack.pop();
+        Ok(())
+    }
+
+    /// Add a File to the Sources
+    ///
+    /// Returns the index of the file.
+    fn push_file(&mut self, source: SourceText) -> usize {
+        self.files.push(source);
+        self.files.len() - 1
+    }
+
+    /// Add the Text Between Two Offsets in a File
+    fn push_segment(&mut self, file: usize, start: usize, end: usize) {
+        let source = &self.files[file];
+        let text = &source.slice(source.start(), source.end())[start..end];
+        self.segments.push(Segment {
+            start: self.text.len(),
+            file,
+            file_start: start,
+        });
+        self.text.push_str(text);
+    }
+
+    /// Separate the Next Source from the Last
+    ///
+    /// Sources are separated by a newline, so that the last line of
+    /// one can't run into the next.
+    fn separate(&mut self) {
+        if !self.segments.is_empty() {
+            self.text.push('\n');
+        }
+    }
+
+    /// Find the Segment Containing a Position in the Combined Source
+    fn segment_at(&self, pos: Pos) -> &Segment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= pos.offset())
+            .unwrap_or(&self.segments[0])
+    }
+
+    /// Find the Last Segment of the Main Source
+    fn main_segment(&self) -> &Segment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.file == self.main)
+            .unwrap_or(&self.segments[0])
+    }
+
+    /// Convert a Combined Span to a Span in a Single File
+    fn localise(&self, span: Span, segment: &Segment) -> Span {
+        if span == DUMMY_SPAN {
+            return span;
+        }
+        let local =
+            |pos: Pos| Pos::from((pos.offset() + segment.file_start).saturating_sub(segment.start));
+        Span::new(local(span.start()), local(span.end()))
+    }
+}
+
+/// Find the Include Directives in a Source
+///
+/// A directive is a line containing just `include`, followed by a
+/// quoted path, and optionally a comment. Returns the line number of
+/// each directive, the offsets of the start and end of its line, and
+/// the path it includes.
+fn include_directives(text: &str) -> Vec<(usize, usize, usize, String)> {
+    let mut directives = Vec::new();
+    let mut start = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if let Some(path) = include_directive(content) {
+            directives.push((index + 1, start, start + content.len(), path.to_string()));
+        }
+        start += line.len();
+    }
+    directives
+}
+
+/// Parse a Single Include Directive
+fn include_directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("include")?;
+    let rest = rest.trim_start();
+    if rest.len() == line.trim_start().len() - "include".len() {
+        // The keyword must be followed by whitespace.
+        return None;
+    }
+    let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let rest = &rest[1..];
+    let close = rest.find(quote)?;
+    let trailing = rest[close + 1..].trim();
+    if trailing.is_empty() || trailing.starts_with('#') {
+        Some(&rest[..close])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sem::{Binder, Scope};
+    use crate::syntax::SyntaxTree;
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = Manifest::parse(
+            "# A project\n[package]\nname = \"hello\"\nversion = \"0.1.0\"\n\n[other]\nname = \"no\"",
+        )
+        .unwrap();
+        assert_eq!("hello", manifest.name);
+        assert_eq!(Edition::LATEST, manifest.edition);
+
+        let manifest = Manifest::parse("[package]\nname = \"old\"\nedition = \"2018\"").unwrap();
+        assert_eq!(Edition::Edition2018, manifest.edition);
+
+        let manifest = Manifest::parse(
+            "[package] # the project\nname = 'demo' # x\nedition = \"2018\"# old\nlicence = 'MIT'",
+        )
+        .unwrap();
+        assert_eq!("demo", manifest.name);
+        assert_eq!(Edition::Edition2018, manifest.edition);
+        assert_eq!(
+            "it's",
+            Manifest::parse("[package]\nname = \"it's\"").unwrap().name
+        );
+    }
+
+    #[test]
+    fn parse_invalid_manifests() {
+        let line = |text| match Manifest::parse(text) {
+            Err(ProjectError::Manifest(line, _)) => line,
+            other => panic!("expected a manifest error, found {:?}", other),
+        };
+        assert_eq!(2, line("[package]\nname = hello"));
+        assert_eq!(2, line("[package]\nname = 'hello\""));
+        assert_eq!(2, line("[package]\nname = \"hello\" extra"));
+        assert_eq!(1, line("[package] extra\nname = \"a\""));
+        assert_eq!(2, line("[package]\nname = \"\""));
+        assert_eq!(1, line("[package\nname = \"a\""));
+        assert_eq!(3, line("[package]\nname = \"a\"\nnonsense"));
+        assert_eq!(2, line("[package]\nedition = \"1999\"\nname = \"a\""));
+    }
+
+    #[test]
+    fn manifest_without_name() {
+        let err = Manifest::parse("[package]\nversion = \"1\"").unwrap_err();
+        assert!(matches!(err, ProjectError::MissingKey("package.name")));
+        assert_eq!("invalid manifest: missing `package.name`", err.to_string());
+    }
+
+    #[test]
+    fn sources_cant_be_empty() {
+        assert!(matches!(
+            ProjectSources::new(Vec::new()),
+            Err(ProjectError::NoSources)
+        ));
+        assert!(matches!(
+            ProjectSources::with_includes(Vec::new(), Path::new("")),
+            Err(ProjectError::NoSources)
+        ));
+    }
+
+    #[test]
+    fn find_project_and_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(MANIFEST_NAME), "[package]\nname = \"demo\"\n").unwrap();
+        fs::create_dir_all(root.join("src").join("util")).unwrap();
+        fs::write(root.join("src").join("main.ulg"), "print double(2)").unwrap();
+        fs::write(root.join("src").join("util").join("maths.ulg"), "").unwrap();
+        fs::write(root.join("src").join("a.ulg"), "").unwrap();
+        fs::write(root.join("src").join("notes.txt"), "").unwrap();
+
+        let project = Project::find(&root.join("src").join("util")).unwrap();
+        assert_eq!("demo", project.name());
+        assert_eq!(root, project.root());
+        assert_eq!(
+            root.join("target")
+                .join("x86_64-unknown-linux-gnu")
+                .join("demo"),
+            project.artifact_path("x86_64-unknown-linux-gnu")
+        );
+        let src = root.join("src");
+        assert_eq!(
+            vec![
+                src.join("a.ulg"),
+                src.join("util").join("maths.ulg"),
+                src.join("main.ulg"),
+            ],
+            project.source_paths().unwrap()
+        );
+
+        fs::remove_file(src.join("main.ulg")).unwrap();
+        assert!(matches!(
+            project.source_paths(),
+            Err(ProjectError::NoMain(_))
+        ));
+    }
+
+    #[test]
+    fn find_project_without_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Project::find(dir.path()),
+            Err(ProjectError::NotFound(..))
+        ));
+    }
+
+    #[test]
+    fn render_diagnostics_in_their_file() {
+        let sources = ProjectSources::new(vec![
+            SourceText::with_name("fn double(n: Number): Number\n  n * 2\nend", "src/lib.ulg"),
+            SourceText::with_name("print double(1)\nprint tripple(1)", "src/main.ulg"),
+        ])
+        .unwrap();
+        let combined = sources.combined("demo");
+        let tree = SyntaxTree::parse(&combined);
+        assert!(!tree.has_diagnostics());
+
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let diagnostics = binder.take_diagnostics();
+        assert_eq!(1, diagnostics.len());
+        let rendered = sources.render(&diagnostics[0], "error");
+        assert!(
+            rendered.starts_with("src/main.ulg:2:6:error:"),
+            "{}",
+            rendered
+        );
+        assert!(rendered.contains("print tripple(1)"), "{}", rendered);
+    }
+
+    #[test]
+    fn splice_included_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(
+            root.join("lib").join("maths.ulg"),
+            "include 'double.ulg' # helpers\nfn triple(n: Number): Number\n  n * 3\nend",
+        )
+        .unwrap();
+        fs::write(
+            root.join("lib").join("double.ulg"),
+            "fn double(n: Number): Number\n  n * tw0\nend",
+        )
+        .unwrap();
+        let main = SourceText::with_name(
+            "include \"lib/maths.ulg\"\nprint triple(double(1))",
+            "main.ulg",
+        );
+
+        let sources = ProjectSources::with_includes(vec![main], root).unwrap();
+        assert_eq!(
+            vec![
+                root.join("lib").join("maths.ulg"),
+                root.join("lib").join("double.ulg")
+            ],
+            sources.included_paths()
+        );
+        let combined = sources.combined("demo");
+        assert!(combined
+            .slice(combined.start(), combined.end())
+            .starts_with("fn double(n: Number): Number\n  n * tw0\nend\nfn triple"));
+        let tree = SyntaxTree::parse(&combined);
+        assert!(!tree.has_diagnostics());
+
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let diagnostics = binder.take_diagnostics();
+        assert_eq!(1, diagnostics.len());
+        let rendered = sources.render(&diagnostics[0], "error");
+        assert!(
+            rendered.starts_with("lib/double.ulg:2:6:error:"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn invalid_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.ulg"), "include 'b.ulg'").unwrap();
+        fs::write(root.join("b.ulg"), "include 'a.ulg'").unwrap();
+
+        let cycle = SourceText::with_name("include 'a.ulg'", "main.ulg");
+        assert!(matches!(
+            ProjectSources::with_includes(vec![cycle], root),
+            Err(ProjectError::IncludeCycle(ref name)) if name == "a.ulg"
+        ));
+
+        let missing = SourceText::with_name("print 1\ninclude 'missing.ulg'", "main.ulg");
+        match ProjectSources::with_includes(vec![missing], root) {
+            Err(ProjectError::Include(name, from, _)) => {
+                assert_eq!("missing.ulg", name);
+                assert_eq!("main.ulg:2", from);
+            }
+            _ => panic!("expected an include error"),
+        }
+
+        let text = "print 'include'\ninclude 'x' + 1\nlet include = 1";
+        assert!(include_directives(text).is_empty());
+    }
+}
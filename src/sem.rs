@@ -4,13 +4,29 @@
 //! representation of a program, as produced by the parser, into a
 //! semantically rich model ready to be lowered for execution.
 
+mod attributes;
 mod binder;
+mod cfg;
+mod const_fold;
+mod conversions;
+mod infer;
+mod last_use;
+pub mod lint;
 mod operators;
+mod pragma;
+mod reachability;
 mod sem_ctx;
+#[cfg(test)]
+mod snapshot_tests;
+mod suggest;
 mod tree;
 mod types;
+pub mod visit;
 
-pub use self::binder::{Binder, Scope, Symbol};
+pub use self::binder::{Binder, Namespace, Scope, Symbol};
+pub use self::cfg::CfgSet;
+pub use self::const_fold::fold_constants;
+pub use self::operators::{OperatorTable, SemOp};
 pub use self::sem_ctx::SemCtx;
-pub use self::tree::{Expression, ExpressionKind};
+pub use self::tree::{Expression, ExpressionKind, FnAttrs, FnDecl, VarDecl};
 pub use self::types::{BuiltinType, Typ};
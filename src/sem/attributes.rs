@@ -0,0 +1,228 @@
+//! Attribute Interpretation
+//!
+//! Gives meaning to the attributes parsed on functions and variable
+//! declarations. The attributes currently understood are:
+//!
+//!  * `#[inline]` - Hint that calls to a function should be inlined.
+//!  * `#[export]` - Give a function the C calling convention so it
+//!    can be called from outside the program.
+//!  * `#[allow(...)]`, `#[warn(...)]`, and `#[deny(...)]` - Set the
+//!    level of the listed lints within the function or declaration.
+//!    See the `lint` module.
+//!  * `#[cfg(...)]` - Only compile the item if the configuration
+//!    matches. See the `cfg` module.
+
+use super::lint::{self, LintLevel};
+use super::tree::FnAttrs;
+use crate::diag::{Code, Diagnostic};
+use crate::syntax::text::SourceText;
+use crate::syntax::{Attribute, AttributeArg};
+
+/// Find the Lint Levels Set by Attributes
+///
+/// Returns the codes named by the `allow`, `warn`, and `deny`
+/// attributes in the list, along with the level they are set to.
+/// Each lint may be given by its name or by its code.
+pub fn lint_levels(attrs: &[Attribute], source: &SourceText) -> Vec<(Code, LintLevel)> {
+    attrs
+        .iter()
+        .filter_map(|attr| Some((attr, attr.name(source).parse::<LintLevel>().ok()?)))
+        .flat_map(|(attr, level)| attr.args().map(move |arg| (arg, level)))
+        .filter_map(|(arg, level)| Some((lint::lookup(arg.name(source))?, level)))
+        .collect()
+}
+
+/// Check a List of Attributes
+///
+/// Reports attributes which aren't known, which are given the wrong
+/// arguments, or which can't be applied to the item. Functions are
+/// items which may have `inline` and `export` applied. Returns the
+/// function attributes which were set.
+pub fn check(
+    attrs: &[Attribute],
+    is_function: bool,
+    source: &SourceText,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> FnAttrs {
+    let mut fn_attrs = FnAttrs::default();
+    let mut error = |message: String, attr: &Attribute| {
+        diagnostics.push(Diagnostic::new(message, attr.span()).with_code(Code::InvalidAttribute));
+    };
+    for attr in attrs {
+        let name = attr.name(source);
+        match name {
+            "inline" | "export" => {
+                if !is_function {
+                    error(
+                        format!("The `{}` attribute can only be applied to functions", name),
+                        attr,
+                    );
+                } else if attr.args.is_some() {
+                    error(
+                        format!("The `{}` attribute doesn't take arguments", name),
+                        attr,
+                    );
+                } else if name == "inline" {
+                    fn_attrs.inline = true;
+                } else {
+                    fn_attrs.export = true;
+                }
+            }
+            "allow" | "warn" | "deny" => {
+                if attr.args().next().is_none() {
+                    error(
+                        format!("The `{}` attribute needs a list of warnings", name),
+                        attr,
+                    );
+                }
+                for arg in attr.args() {
+                    let warning = arg.name(source);
+                    if arg.value.is_some() || arg.args.is_some() || lint::lookup(warning).is_none()
+                    {
+                        error(format!("Unknown warning `{}`", warning), attr);
+                    }
+                }
+            }
+            "cfg" => {
+                if attr.args().next().is_none() {
+                    error("The `cfg` attribute needs a list of options".into(), attr);
+                }
+                let mut problems = Vec::new();
+                for arg in attr.args() {
+                    check_cfg_predicate(arg, source, &mut problems);
+                }
+                for problem in problems {
+                    error(problem, attr);
+                }
+            }
+            _ => error(format!("Unknown attribute `{}`", name), attr),
+        }
+    }
+    fn_attrs
+}
+
+/// Check a `cfg` Predicate
+///
+/// Only `not`, `any`, and `all` can have a list of predicates, and
+/// `not` must have exactly one.
+fn check_cfg_predicate(arg: &AttributeArg, source: &SourceText, problems: &mut Vec<String>) {
+    if arg.args.is_none() {
+        return;
+    }
+    match arg.name(source) {
+        "not" if arg.args().count() != 1 => {
+            problems.push("`not` needs exactly one `cfg` predicate".into())
+        }
+        "not" | "any" | "all" => (),
+        name => problems.push(format!("Unknown `cfg` predicate `{}(...)`", name)),
+    }
+    for nested in arg.args() {
+        check_cfg_predicate(nested, source, problems);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::SyntaxTree;
+
+    fn check_source(src: &str) -> (FnAttrs, Vec<Code>, Vec<String>) {
+        let source = SourceText::new(src);
+        let tree = SyntaxTree::parse(&source);
+        assert!(!tree.has_diagnostics(), "{:?}", tree.diagnostics());
+        let root = &tree[tree.root_id()];
+        let item = match root {
+            crate::syntax::Expression::Sequence(items) => &tree[items[0]],
+            _ => root,
+        };
+        let attrs = item.attributes();
+        let is_function = matches!(item, crate::syntax::Expression::Function(_));
+        let mut diagnostics = Vec::new();
+        let fn_attrs = check(attrs, is_function, &source, &mut diagnostics);
+        (
+            fn_attrs,
+            lint_levels(attrs, &source)
+                .into_iter()
+                .map(|(code, _)| code)
+                .collect(),
+            diagnostics.into_iter().map(|d| d.message).collect(),
+        )
+    }
+
+    #[test]
+    fn function_attributes() {
+        let (attrs, codes, errors) = check_source(
+            "#[inline] #[export]\n#[allow(unreachable_code, U0021)]\nfn f(): Number 1 end",
+        );
+        assert!(attrs.inline);
+        assert!(attrs.export);
+        assert_eq!(vec![Code::UnreachableCode, Code::UnreachableCode], codes);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn lint_level_attributes() {
+        let source = SourceText::new("#[warn(shadowing)] #[deny(U0028)] #[allow(U0007)] let a = 1");
+        let tree = SyntaxTree::parse(&source);
+        let item = match tree.root() {
+            crate::syntax::Expression::Sequence(items) => &tree[items[0]],
+            root => root,
+        };
+        let mut diagnostics = Vec::new();
+        check(item.attributes(), false, &source, &mut diagnostics);
+        assert_eq!(
+            vec![
+                (Code::ShadowedVariable, LintLevel::Warn),
+                (Code::UnusedVariable, LintLevel::Deny),
+            ],
+            lint_levels(item.attributes(), &source)
+        );
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("Unknown warning `U0007`", diagnostics[0].message);
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        let (attrs, _, errors) = check_source("#[inline] #[frobnicate] let a = 1");
+        assert_eq!(FnAttrs::default(), attrs);
+        assert_eq!(
+            vec![
+                "The `inline` attribute can only be applied to functions",
+                "Unknown attribute `frobnicate`",
+            ],
+            errors
+        );
+
+        let (_, codes, errors) =
+            check_source("#[export(c)] #[allow] #[allow(nonsense)]\nfn f(): Number 1 end");
+        assert!(codes.is_empty());
+        assert_eq!(
+            vec![
+                "The `export` attribute doesn't take arguments",
+                "The `allow` attribute needs a list of warnings",
+                "Unknown warning `nonsense`",
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn cfg_predicates() {
+        let (_, _, errors) = check_source("#[cfg(any(unix, all(not(windows))))] let a = 1");
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, _, errors) =
+            check_source("#[cfg(not(unix, windows), os(linux), any(not()))] let a = 1");
+        assert_eq!(
+            vec![
+                "`not` needs exactly one `cfg` predicate",
+                "Unknown `cfg` predicate `os(...)`",
+                "`not` needs exactly one `cfg` predicate",
+            ],
+            errors
+        );
+
+        let (_, _, errors) = check_source("#[allow(unreachable_code(x))] let a = 1");
+        assert_eq!(vec!["Unknown warning `unreachable_code`"], errors);
+    }
+}
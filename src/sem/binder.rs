@@ -18,18 +18,40 @@
 //! used. Bound trees which contain either of these error values
 //! should also produce diagnostics in the bind. Failure to do so is a
 //! bug in the binder.
+//!
+//! Types are inferred as the tree is bound. Declarations without a
+//! type annotation are given a type variable which is unified with
+//! the type of each value the variable must hold. Once the whole
+//! tree is bound every type variable is resolved, so the bound tree
+//! only contains concrete types.
+//!
+//! Mutable variables can be declared without an initialiser. The
+//! binder tracks which of these variables may still be unassigned as
+//! it walks the tree in evaluation order. Reading a variable which
+//! isn't assigned on every path to the read is an error.
 
 use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::default::Default;
-
-use super::operators;
+use std::mem;
+use std::rc::Rc;
+
+use super::attributes;
+use super::cfg::CfgSet;
+use super::conversions;
+use super::infer::TypeTable;
+use super::last_use;
+use super::lint::{Lint, LintLevel, LintLevels, LintRegistry};
+use super::operators::OperatorTable;
+use super::pragma;
+use super::reachability;
+use super::suggest;
 use super::tree::{FnDecl, VarDecl};
 use super::{BuiltinType, Expression, ExpressionKind, Typ};
-use crate::diag::Diagnostic;
+use crate::diag::{self, Applicability, Code, Diagnostic, Fix};
 use crate::syntax::{
     self,
-    text::{Ident, SourceText, Span},
-    Constant, InfixOp, PrefixOp, SyntaxNode, TokenKind, TypeRef, VarStyle,
+    text::{Ident, Pos, SourceText, Span, DUMMY_SPAN},
+    Constant, Edition, InfixOp, PrefixOp, SyntaxNode, TokenKind, TypeRef, VarStyle,
 };
 
 /// An item that can appear in a `Scope`
@@ -42,34 +64,75 @@ pub enum Symbol {
     /// Function argument or local variable
     Variable(VarStyle, Typ),
     /// A Function declaration
-    Function(Vec<Typ>, Typ),
+    ///
+    /// The parameter types are shared so that copies of the symbol,
+    /// such as those recorded for each reference to it, are cheap.
+    Function(Rc<[Typ]>, Typ),
     /// A type
     Type(Typ),
 }
 
+impl Symbol {
+    /// The namespace the symbol is declared in
+    fn namespace(&self) -> Namespace {
+        match self {
+            Symbol::Function(..) => Namespace::Function,
+            _ => Namespace::Value,
+        }
+    }
+}
+
+/// Symbol Namespace
+///
+/// Functions are declared in a namespace of their own, so a variable
+/// can share its name with a function in the same scope. Lookups
+/// search one namespace first and fall back to the other.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Namespace {
+    /// Variables and types
+    Value,
+    /// Function declarations
+    Function,
+}
+
+impl Namespace {
+    /// The namespaces to search when looking for a symbol in this one
+    fn search_order(self) -> [Namespace; 2] {
+        match self {
+            Namespace::Value => [Namespace::Value, Namespace::Function],
+            Namespace::Function => [Namespace::Function, Namespace::Value],
+        }
+    }
+}
+
 /// Declaration Scope
 ///
 /// Holds the declared items at a given level in the scope stack
 /// during a bind. Once a scope has an item with a given name declared
-/// a new item can't be inserted to overwrite it. The return value of
-/// `try_declare` exposes the success or failure of declaring an item.
+/// in a namespace a new item can't be inserted to overwrite it. The
+/// return value of `try_declare` exposes the success or failure of
+/// declaring an item.
 ///
 /// # Examples
 ///
 /// ```
-/// # let interner = Interner::new();
+/// # use ullage::sem::{Scope, Symbol, Typ};
+/// # use ullage::syntax::text::Interner;
+/// # let mut interner = Interner::new();
 /// let mut scope = Scope::new();
 ///
-/// assert!(scope.try_declare(interner.intern("foo"), Symbol::Type(Typ::Unit));
+/// assert!(scope.try_declare(interner.intern("foo"), Symbol::Type(Typ::Unit)));
 ///
 /// // we can look the symbols up later
 /// assert_eq!(None, scope.lookup(interner.intern("bar")));
-/// assert_eq!(Some(Symbol::Type(Typ::Unit)), scope.lookup(interner.intern("foo")));
+/// assert_eq!(Some(&Symbol::Type(Typ::Unit)), scope.lookup(interner.intern("foo")));
 /// ```
 #[derive(Default)]
 pub struct Scope {
     /// Symbols declared in this scope
-    symbols: HashMap<Ident, Symbol>,
+    symbols: HashMap<(Namespace, Ident), Symbol>,
+    /// The span each symbol was declared at, if it came from source
+    definitions: HashMap<(Namespace, Ident), Span>,
 }
 
 impl Scope {
@@ -81,10 +144,27 @@ impl Scope {
     /// Lookup a Symbol from the scope
     ///
     /// Searches the current scope, and any parent scopes, for the
-    /// given identifier. If any symbol is bound to the idnetifier it
-    /// is returned otherwise `None` is returned.
-    pub fn lookup(&self, ident: Ident) -> Option<Symbol> {
-        self.symbols.get(&ident).cloned()
+    /// given identifier. If any symbol is bound to the idnetifier a
+    /// reference to it is returned otherwise `None` is returned.
+    /// Variables and types are preferred over functions.
+    pub fn lookup(&self, ident: Ident) -> Option<&Symbol> {
+        self.lookup_in(ident, Namespace::Value)
+    }
+
+    /// Lookup a Symbol Preferring a Namespace
+    ///
+    /// As `lookup`, but a symbol in `namespace` is preferred over
+    /// one with the same name in the other namespace.
+    pub fn lookup_in(&self, ident: Ident, namespace: Namespace) -> Option<&Symbol> {
+        self.find(ident, namespace).map(|(_, sym)| sym)
+    }
+
+    /// Find the Symbol a Lookup Resolves to, and its Namespace
+    fn find(&self, ident: Ident, namespace: Namespace) -> Option<(Namespace, &Symbol)> {
+        namespace
+            .search_order()
+            .iter()
+            .find_map(|ns| self.symbols.get(&(*ns, ident)).map(|sym| (*ns, sym)))
     }
 
     /// Try to declare a Symbol in this scope
@@ -95,14 +175,16 @@ impl Scope {
     /// # Examples
     ///
     /// ```
+    /// # use ullage::sem::{Scope, Symbol, Typ};
+    /// # use ullage::syntax::text::Interner;
     /// # let id = Interner::new().intern("foo");
     /// # let sym = Symbol::Type(Typ::Unit);
     /// # let mut scope = Scope::new();
-    /// assert!(scope.try_declare(id), sym);
-    /// assert!(!scope.try_declare(id));
+    /// assert!(scope.try_declare(id, sym.clone()));
+    /// assert!(!scope.try_declare(id, sym));
     /// ```
     pub fn try_declare(&mut self, ident: Ident, sym: Symbol) -> bool {
-        match self.symbols.entry(ident) {
+        match self.symbols.entry((sym.namespace(), ident)) {
             Entry::Occupied(_) => false,
             Entry::Vacant(v) => {
                 v.insert(sym);
@@ -110,6 +192,49 @@ impl Scope {
             }
         }
     }
+
+    /// Try to declare a Symbol Defined in the Source
+    ///
+    /// As `try_declare` but also records the span of the symbol's
+    /// definition so it can be found with `definition`.
+    pub fn try_declare_at(&mut self, ident: Ident, sym: Symbol, span: Span) -> bool {
+        let key = (sym.namespace(), ident);
+        let declared = self.try_declare(ident, sym);
+        if declared {
+            self.definitions.insert(key, span);
+        }
+        declared
+    }
+
+    /// Declare a Symbol Defined in the Source
+    ///
+    /// As `try_declare_at` but replaces any symbol already declared
+    /// with the same name in the same namespace of this scope, along
+    /// with its recorded definition. Later lookups find the new
+    /// symbol. Used for variables, which can be redeclared within a
+    /// scope. A function with the same name is left in place.
+    pub fn declare_at(&mut self, ident: Ident, sym: Symbol, span: Span) {
+        let key = (sym.namespace(), ident);
+        self.symbols.insert(key, sym);
+        self.definitions.insert(key, span);
+    }
+
+    /// Lookup the Definition of a Symbol
+    ///
+    /// Returns the span the symbol `lookup` finds for `ident` was
+    /// declared at. Symbols which were declared without a span, such
+    /// as the builtin types, have no definition.
+    pub fn definition(&self, ident: Ident) -> Option<Span> {
+        self.definition_in(ident, Namespace::Value)
+    }
+
+    /// Lookup the Definition of a Symbol Preferring a Namespace
+    ///
+    /// As `definition`, but for the symbol `lookup_in` finds.
+    pub fn definition_in(&self, ident: Ident, namespace: Namespace) -> Option<Span> {
+        self.find(ident, namespace)
+            .and_then(|(ns, _)| self.definitions.get(&(ns, ident)).cloned())
+    }
 }
 
 /// Stack of scopes
@@ -120,9 +245,9 @@ impl Scope {
 ///
 /// Lookups in the scope stack start at the innermost scope and work
 /// outward. Once an item is found it is returned. This allows items
-/// in inner scopes to shadow those in outer ones. Seen as all items
-/// are in the same namespace at the moment this allows variables to
-/// shadow functions with the same name and vice-versa.
+/// in inner scopes to shadow those in outer ones. The innermost scope
+/// with a symbol in either namespace wins, so variables shadow
+/// functions with the same name from outer scopes and vice-versa.
 ///
 /// The stack is maipulated with the `push()` and `pop()` methods.
 pub struct ScopeStack(Vec<Scope>);
@@ -137,10 +262,37 @@ impl ScopeStack {
     ///
     /// Starts at the innermost 'current' scope and walks outward
     /// searching for a `Symbol` bound to the given `id`. If no symbol
-    /// is found then `None` is returned, otherwise a copy of the
+    /// is found then `None` is returned, otherwise a reference to the
     /// symbol is returned.
-    pub fn lookup(&self, id: Ident) -> Option<Symbol> {
-        self.0.iter().rev().find_map(|s| s.lookup(id))
+    pub fn lookup(&self, id: Ident) -> Option<&Symbol> {
+        self.lookup_in(id, Namespace::Value)
+    }
+
+    /// Lookup a symbol in the scope stack preferring a namespace
+    ///
+    /// As `lookup`, but within the scope the symbol is found in one
+    /// from `namespace` is preferred.
+    pub fn lookup_in(&self, id: Ident, namespace: Namespace) -> Option<&Symbol> {
+        self.0.iter().rev().find_map(|s| s.lookup_in(id, namespace))
+    }
+
+    /// Lookup the definition of a symbol in the scope stack
+    ///
+    /// Finds the scope `lookup` would resolve `id` in and returns
+    /// the span the symbol was declared at there.
+    pub fn lookup_definition(&self, id: Ident) -> Option<Span> {
+        self.lookup_definition_in(id, Namespace::Value)
+    }
+
+    /// Lookup the definition of a symbol preferring a namespace
+    ///
+    /// As `lookup_definition`, but for the symbol `lookup_in` finds.
+    pub fn lookup_definition_in(&self, id: Ident, namespace: Namespace) -> Option<Span> {
+        self.0
+            .iter()
+            .rev()
+            .find(|s| s.lookup_in(id, namespace).is_some())
+            .and_then(|s| s.definition_in(id, namespace))
     }
 
     /// Get the scope at the top of the stack
@@ -169,6 +321,25 @@ impl ScopeStack {
         self.0.pop()
     }
 
+    /// Get the visible symbols
+    ///
+    /// Returns each symbol which a lookup from the current scope
+    /// could find. Symbols shadowed by an inner scope are not
+    /// included.
+    pub fn visible(&self) -> Vec<(Ident, Symbol)> {
+        let mut seen = HashSet::new();
+        let mut visible = Vec::new();
+        for scope in self.0.iter().rev() {
+            for ((_, id), sym) in scope.symbols.iter() {
+                if !seen.contains(id) {
+                    visible.push((*id, sym.clone()));
+                }
+            }
+            seen.extend(scope.symbols.keys().map(|(_, id)| *id));
+        }
+        visible
+    }
+
     /// Flatten the function declarations current scope into a single
     /// target scope.
     ///
@@ -180,9 +351,12 @@ impl ScopeStack {
     /// Scope visibility and shadowing is preserved.
     pub fn flatten_decls_into(&self, target: &mut Scope) {
         for scope in self.0.iter().rev() {
-            for (id, sym) in scope.symbols.iter() {
+            for ((_, id), sym) in scope.symbols.iter() {
                 if let Symbol::Function(..) = *sym {
-                    target.try_declare(*id, sym.clone());
+                    match scope.definition_in(*id, Namespace::Function) {
+                        Some(span) => target.try_declare_at(*id, sym.clone(), span),
+                        None => target.try_declare(*id, sym.clone()),
+                    };
                 }
             }
         }
@@ -198,6 +372,46 @@ pub struct Binder {
     scopes: ScopeStack,
     /// The diagnostics for the current bind
     diagnostics: Vec<Diagnostic>,
+    /// The warnings for the current bind
+    warnings: Vec<Diagnostic>,
+    /// The symbol each bound identifier resolved to
+    symbol_spans: Vec<(Span, Symbol)>,
+    /// The definition each bound identifier resolved to
+    definitions: Vec<(Span, Span)>,
+    /// The type of each bound identifier
+    types: Vec<(Span, Typ)>,
+    /// Every identifier bound to each definition, by definition
+    references: HashMap<Span, Vec<Span>>,
+    /// The position to capture the visible symbols at, if any
+    probe: Option<Pos>,
+    /// The symbols visible at the probe position
+    visible: Option<Vec<(Ident, Symbol)>>,
+    /// The bindings of the type variables created so far
+    inference: TypeTable,
+    /// The type variable of each declaration with an inferred type
+    inferred: Vec<(Span, String, Typ)>,
+    /// The definitions of the variables which may not have been
+    /// assigned yet
+    unassigned: HashSet<Span>,
+    /// The operators available to the bind
+    operators: OperatorTable,
+    /// The lint levels set by pragmas and attributes, and the
+    /// expressions they apply to
+    lint_scopes: Vec<(Span, Code, LintLevel)>,
+    /// The lints run once the tree is bound
+    lints: LintRegistry,
+    /// The level of each lint outside of any pragmas or attributes
+    lint_levels: LintLevels,
+    /// The configuration `cfg` attributes are checked against
+    cfg: CfgSet,
+    /// The edition of the language being bound
+    edition: Edition,
+    /// The function which is the program's entry point, if the top
+    /// level isn't
+    entry: Option<String>,
+    /// The labels of the loops whose bodies are being bound, from the
+    /// outermost inwards
+    loops: Vec<Option<Ident>>,
 }
 
 impl Binder {
@@ -206,19 +420,209 @@ impl Binder {
         Binder {
             scopes: ScopeStack::new(scope),
             diagnostics: Vec::new(),
+            warnings: Vec::new(),
+            symbol_spans: Vec::new(),
+            definitions: Vec::new(),
+            types: Vec::new(),
+            references: HashMap::new(),
+            probe: None,
+            visible: None,
+            inference: TypeTable::new(),
+            inferred: Vec::new(),
+            unassigned: HashSet::new(),
+            operators: OperatorTable::builtin(),
+            lint_scopes: Vec::new(),
+            lints: LintRegistry::builtin(),
+            lint_levels: LintLevels::default(),
+            cfg: CfgSet::host(),
+            edition: Edition::default(),
+            entry: None,
+            loops: Vec::new(),
+        }
+    }
+
+    /// Set the Operator Table
+    ///
+    /// Replaces the builtin operators with the given table. Infix
+    /// expressions are only valid if the table has an operator which
+    /// accepts their operand types.
+    pub fn with_operators(self, operators: OperatorTable) -> Self {
+        Binder { operators, ..self }
+    }
+
+    /// Set the Conditional Compilation Configuration
+    ///
+    /// Items with `cfg` attributes which don't hold for the
+    /// configuration are dropped before they are declared. By default
+    /// the configuration for the host is used.
+    pub fn with_cfg(self, cfg: CfgSet) -> Self {
+        Binder { cfg, ..self }
+    }
+
+    /// Set the Lint Levels
+    ///
+    /// Pragmas and attributes in the source can still change the
+    /// level of a lint for part of the tree.
+    pub fn with_lint_levels(self, lint_levels: LintLevels) -> Self {
+        Binder {
+            lint_levels,
+            ..self
+        }
+    }
+
+    /// Add a Lint
+    ///
+    /// The lint is run over the tree along with the builtin lints.
+    pub fn with_lint(mut self, lint: Box<dyn Lint>) -> Self {
+        self.lints.register(lint);
+        self
+    }
+
+    /// Set the Edition of the Language
+    ///
+    /// Macros are only expanded in editions which have them.
+    pub fn with_edition(self, edition: Edition) -> Self {
+        Binder { edition, ..self }
+    }
+
+    /// Set the Entry Point
+    ///
+    /// Once the tree is bound the named function is checked to exist
+    /// and to be callable as an entry point. Any code at the top level
+    /// of the tree, other than function declarations, is an error as
+    /// it would never be run.
+    pub fn with_entry(self, entry: String) -> Self {
+        Binder {
+            entry: Some(entry),
+            ..self
+        }
+    }
+
+    /// Set the Probe Position
+    ///
+    /// While binding, the binder will capture the symbols visible at
+    /// the probe position. Once the bind is complete they can be
+    /// retrieved with `take_visible_symbols`.
+    pub fn with_probe(self, pos: Pos) -> Self {
+        Binder {
+            probe: Some(pos),
+            ..self
         }
     }
 
     /// Bind an Expression
     ///
     /// Converts a syntax expression into a semantic one by binding it
-    /// in the binder's current scope.
+    /// in the binder's current scope. Any macros in the tree are
+    /// expanded first. The types in the returned tree are fully
+    /// inferred, and the last use of each reference counted variable
+    /// is marked as a move.
     pub fn bind_tree(&mut self, tree: syntax::SyntaxTree<'_>) -> Expression {
-        let source = tree.source();
-        add_builtin_types(self.scopes.current_mut(), source);
-        let (expr, _end) = tree.into_parts();
-        self.declare_expression(&expr);
-        self.bind_expression(&expr, source)
+        let tree = if self.edition.has_macros() {
+            let (tree, diagnostics) = syntax::macros::expand(tree);
+            self.diagnostics.extend(diagnostics);
+            tree
+        } else {
+            tree
+        };
+        self.declare_tree(&tree);
+        self.capture_visible(tree.source().start());
+        let bound = self.bind_expression(tree.root_id(), &tree);
+        let bound = self.finalise_types(bound);
+        if let Some(entry) = self.entry.clone() {
+            self.check_entry(&entry, &bound);
+        }
+        self.run_lints(&bound);
+        last_use::mark_moves(bound)
+    }
+
+    /// Check the Entry Point
+    ///
+    /// The entry point must be a function declared at the top level
+    /// which takes no parameters and returns a `Number`, the exit
+    /// status. Nothing else at the top level would be run, so anything
+    /// other than a function declaration is reported.
+    fn check_entry(&mut self, entry: &str, bound: &Expression) {
+        let mut items = vec![bound];
+        let mut found = false;
+        while let Some(item) = items.pop() {
+            match item.kind {
+                ExpressionKind::Sequence(ref exprs) => items.extend(exprs.iter().rev()),
+                ExpressionKind::Function(ref fn_decl) if fn_decl.ident == entry => {
+                    found = true;
+                    if !fn_decl.params.is_empty()
+                        || fn_decl.ret_ty != Typ::Builtin(BuiltinType::Number)
+                    {
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                format!(
+                                    "Entry point '{}' must take no parameters and return 'Number'",
+                                    entry
+                                ),
+                                item.span,
+                            )
+                            .with_code(Code::InvalidEntryPoint),
+                        );
+                    }
+                }
+                ExpressionKind::Function(_) => (),
+                _ => self.diagnostics.push(
+                    Diagnostic::new(
+                        format!(
+                            "Top level code is never run when '{}' is the entry point",
+                            entry
+                        ),
+                        item.span,
+                    )
+                    .with_code(Code::InvalidEntryPoint),
+                ),
+            }
+        }
+        if !found {
+            self.diagnostics.push(
+                Diagnostic::new(
+                    format!("Entry point '{}' is not defined", entry),
+                    DUMMY_SPAN,
+                )
+                .with_code(Code::InvalidEntryPoint),
+            );
+        }
+    }
+
+    /// Run the Lints Over a Bound Tree
+    fn run_lints(&mut self, bound: &Expression) {
+        let found = self.lints.check(bound, &self.references, &self.lint_levels);
+        for (level, diagnostic) in found {
+            self.report_lint(diagnostic, level);
+        }
+    }
+
+    /// Finalise the Inferred Types
+    ///
+    /// Raises a diagnostic for each declaration whose type couldn't
+    /// be inferred and then resolves the types in the bound tree.
+    fn finalise_types(&mut self, expr: Expression) -> Expression {
+        for (span, name, typ) in self.inferred.drain(..) {
+            if !self.inference.is_resolved(typ) {
+                self.diagnostics.push(
+                    Diagnostic::new(format!("Can't infer the type of '{}'", name), span)
+                        .with_code(Code::CannotInferType),
+                );
+            }
+        }
+        self.inference.finalise_tree(expr)
+    }
+
+    /// Declare the Items in a Tree
+    ///
+    /// Adds the builtin types to the binder's current scope and then
+    /// declares the top level items of the tree. This is the first
+    /// phase of `bind_tree`. On its own it is enough to resolve type
+    /// annotations and the signatures of functions.
+    pub fn declare_tree(&mut self, tree: &syntax::SyntaxTree<'_>) {
+        add_builtin_types(self.scopes.current_mut());
+        self.declare_expression(tree.root_id(), tree);
+        add_builtin_functions(self.scopes.current_mut(), tree.source());
     }
 
     /// Declare any items in the current expression that should be
@@ -228,20 +632,36 @@ impl Binder {
     /// the block is bound. It adds delclarations for any items that
     /// should be mutually recursive into the scope so that they are
     /// available to the following bind.
-    pub fn declare_expression(&mut self, expression: &syntax::Expression) {
+    pub fn declare_expression(
+        &mut self,
+        expression: syntax::ExprId,
+        tree: &syntax::SyntaxTree<'_>,
+    ) {
         use syntax::Expression::*;
-        match *expression {
-            Function(ref func) => self.declare_function(func),
+        if !self.is_enabled(expression, tree) {
+            return;
+        }
+        match tree[expression] {
+            Function(ref func) => self.declare_function(func, tree.source()),
             Sequence(ref seq) => {
-                for expr in seq.iter() {
-                    self.declare_expression(expr);
+                for &expr in seq.iter() {
+                    self.declare_expression(expr, tree);
                 }
             }
-            Grouping(ref group) => self.declare_expression(&group.inner),
+            Grouping(ref group) => self.declare_expression(group.inner, tree),
             _ => {}
         }
     }
 
+    /// Is the Expression Enabled?
+    ///
+    /// Checks the `cfg` attributes of the expression against the
+    /// binder's configuration.
+    fn is_enabled(&self, expression: syntax::ExprId, tree: &syntax::SyntaxTree<'_>) -> bool {
+        self.cfg
+            .is_enabled(tree[expression].attributes(), tree.source())
+    }
+
     /// Builds out the type for the function and creates an entry in
     /// the current symbol table for it.
     ///
@@ -250,7 +670,23 @@ impl Binder {
     /// method. Later when each expression in the tree is visited
     /// again for binding the body of the function is bound in a new
     /// child scope.
-    pub fn declare_function(&mut self, func: &syntax::FunctionExpression) {
+    pub fn declare_function(&mut self, func: &syntax::FunctionExpression, source: &SourceText) {
+        let sym = self.function_symbol(func, source);
+        self.scopes
+            .current_mut()
+            .try_declare_at(func.identifier, sym, func.identifier_tok.span());
+    }
+
+    /// Build the Symbol for a Function
+    ///
+    /// Binds the types of the function's parameters and return type
+    /// in the current scope. Parameters without a type annotation
+    /// have the type `Typ::Error`.
+    pub fn function_symbol(
+        &mut self,
+        func: &syntax::FunctionExpression,
+        source: &SourceText,
+    ) -> Symbol {
         let param_tys = func
             .params
             .iter()
@@ -259,14 +695,12 @@ impl Binder {
                     .as_inner()
                     .typ
                     .as_ref()
-                    .map(|t| self.bind_type(&t.type_ref))
+                    .map(|t| self.bind_type(&t.type_ref, source))
                     .unwrap_or(Typ::Error)
             })
             .collect();
-        let ret_ty = self.bind_type(&func.return_type.type_ref);
-        self.scopes
-            .current_mut()
-            .try_declare(func.identifier, Symbol::Function(param_tys, ret_ty));
+        let ret_ty = self.bind_type(&func.return_type.type_ref, source);
+        Symbol::Function(param_tys, ret_ty)
     }
 
     /// Bind a Single Expression
@@ -275,24 +709,40 @@ impl Binder {
     /// appropriate `bind_*` method.
     pub fn bind_expression(
         &mut self,
-        expression: &syntax::Expression,
-        source: &SourceText,
+        expression: syntax::ExprId,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
         use syntax::Expression::*;
-        match *expression {
-            Identifier(ref ident) => self.bind_identifier(ident, source),
+        let span = expression.span();
+        if span != DUMMY_SPAN {
+            self.capture_visible(span.start());
+        }
+        if !self.is_enabled(expression, tree) {
+            return Expression::new(ExpressionKind::Sequence(Vec::new()), Typ::Unit);
+        }
+        let bound = match tree[expression] {
+            Identifier(ref ident) => self.bind_identifier(ident, tree.source()),
             Literal(ref lit) => self.bind_literal(lit),
-            Prefix(ref pref) => self.bind_prefix(pref, source),
-            Infix(ref innie) => self.bind_infix(innie, source),
-            Call(ref call) => self.bind_call(call, source),
-            Index(ref index) => self.bind_index(index, source),
-            IfThenElse(ref if_else_expr) => self.bind_if_else(if_else_expr, source),
-            Function(ref func) => self.bind_function(func, source),
-            Loop(ref loop_expr) => self.bind_loop(loop_expr, source),
-            Sequence(ref exprs) => self.bind_sequence(&exprs[..], source),
-            Print(ref print) => self.bind_print(print, source),
-            Declaration(ref decl) => self.bind_declaration(decl, source),
-            Grouping(ref group) => self.bind_expression(&group.inner, source),
+            Prefix(ref pref) => self.bind_prefix(pref, tree),
+            Infix(ref innie) => self.bind_infix(innie, tree),
+            Call(ref call) => self.bind_call(call, tree),
+            Index(ref index) => self.bind_index(index, tree),
+            IfThenElse(ref if_else_expr) => self.bind_if_else(if_else_expr, tree),
+            Function(ref func) => self.bind_function(func, tree),
+            // Macros are expanded before binding, so their
+            // definitions have nothing left to bind.
+            Macro(_) => Expression::new(ExpressionKind::Sequence(Vec::new()), Typ::Unit),
+            Loop(ref loop_expr) => self.bind_loop(loop_expr, tree),
+            LoopControl(ref control) => self.bind_loop_control(control, tree.source()),
+            Sequence(ref exprs) => self.bind_sequence(&exprs[..], tree),
+            Print(ref print) => self.bind_print(print, tree),
+            Declaration(ref decl) => self.bind_declaration(decl, tree),
+            Grouping(ref group) => self.bind_expression(group.inner, tree),
+        };
+        if bound.span == DUMMY_SPAN {
+            bound.with_span(span)
+        } else {
+            bound
         }
     }
 
@@ -307,27 +757,81 @@ impl Binder {
         ident: &syntax::IdentifierExpression,
         source: &SourceText,
     ) -> Expression {
-        if let Some(sym) = self.scopes.lookup(ident.ident) {
+        self.bind_identifier_in(ident, source, Namespace::Value)
+    }
+
+    /// Bind a Reference to an Identifier Preferring a Namespace
+    ///
+    /// As `bind_identifier`, but a symbol in `namespace` is preferred
+    /// if there is one with the same name in the other namespace.
+    fn bind_identifier_in(
+        &mut self,
+        ident: &syntax::IdentifierExpression,
+        source: &SourceText,
+        namespace: Namespace,
+    ) -> Expression {
+        if let Some(sym) = self.scopes.lookup_in(ident.ident, namespace).cloned() {
+            let definition = self.scopes.lookup_definition_in(ident.ident, namespace);
+            self.record_symbol(ident.token.span(), ident.ident, &sym, definition);
             let id_str = source.interned_value(ident.ident);
             let typ = match sym {
-                Symbol::Variable(_, t) => Some(t),
-                Symbol::Function(..) => Some(Typ::Function(ident.ident)),
+                Symbol::Variable(_, t) => {
+                    if definition.is_some_and(|d| self.unassigned.contains(&d)) {
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                format!("Use of possibly uninitialised variable '{}'", id_str),
+                                ident.token.span(),
+                            )
+                            .with_code(Code::UninitialisedVariable),
+                        );
+                    }
+                    t
+                }
+                Symbol::Function(..) => Typ::Function(ident.ident),
                 // FIXME: First-class types?
-                Symbol::Type(..) => None,
+                Symbol::Type(..) => {
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!("'{}' is a type, not a value", id_str),
+                            ident.token.span(),
+                        )
+                        .with_code(Code::TypeUsedAsValue),
+                    );
+                    return Expression::error();
+                }
             };
-            Expression::new(ExpressionKind::Identifier(id_str), typ)
+            Expression::new(ExpressionKind::Identifier(id_str.into()), typ)
         } else {
-            self.diagnostics.push(Diagnostic::new(
-                format!(
-                    "Can't find '{}' in this scope",
-                    source.interned_value(ident.ident)
-                ),
+            let id_str = source.interned_value(ident.ident);
+            let suggestion = self.suggest(id_str, source, |sym| !matches!(sym, Symbol::Type(_)));
+            let diagnostic = Diagnostic::new(
+                format!("Can't find '{}' in this scope", id_str),
                 ident.token.span(),
-            ));
+            )
+            .with_code(Code::UndefinedName);
+            self.diagnostics
+                .push(with_suggestion(diagnostic, suggestion));
             Expression::error()
         }
     }
 
+    /// Suggest a Visible Name
+    ///
+    /// Searches the visible symbols accepted by `filter` for one
+    /// whose name is close to `name`.
+    fn suggest<F>(&self, name: &str, source: &SourceText, filter: F) -> Option<String>
+    where
+        F: Fn(&Symbol) -> bool,
+    {
+        let candidates = self
+            .scopes
+            .visible()
+            .into_iter()
+            .filter(|(_, sym)| filter(sym))
+            .map(|(id, _)| source.interned_value(id).to_string());
+        suggest::closest(name, candidates)
+    }
+
     /// Bind a literal value
     pub fn bind_literal(&mut self, lit: &syntax::LiteralExpression) -> Expression {
         let constant_value = lit.value.clone();
@@ -336,16 +840,16 @@ impl Binder {
             Constant::Number(_) => BuiltinType::Number,
             Constant::String(_) => BuiltinType::String,
         });
-        Expression::new(ExpressionKind::Literal(constant_value), Some(typ))
+        Expression::new(ExpressionKind::Literal(constant_value), typ)
     }
 
     /// Prefix operation
     pub fn bind_prefix(
         &mut self,
         pref: &syntax::PrefixExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let bound_inner = self.bind_expression(&pref.inner, source);
+        let bound_inner = self.bind_expression(pref.inner, tree);
         // TODO: Do we wnat some kind of type table for these
         //       operations like we have for infix operators?
         let typ = bound_inner.typ;
@@ -366,37 +870,70 @@ impl Binder {
     pub fn bind_infix(
         &mut self,
         infix: &syntax::InfixOperatorExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
         if infix.op == InfixOp::Assign {
-            if let syntax::Expression::Identifier(ref id) = *infix.left {
-                self.bind_assign(id, infix, source)
+            if let syntax::Expression::Identifier(ref id) = tree[infix.left] {
+                self.bind_assign(id, infix, tree)
             } else {
-                self.diagnostics.push(Diagnostic::new(
-                    "left hand side of an assignment must be an identifier",
-                    infix.left.span(),
-                ));
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        "left hand side of an assignment must be an identifier",
+                        infix.left.span(),
+                    )
+                    .with_code(Code::InvalidAssignmentTarget),
+                );
                 Expression::error()
             }
         } else {
-            let lhs = self.bind_expression(&infix.left, source);
-            let rhs = self.bind_expression(&infix.right, source);
-
-            let lhs_typ = lhs.typ.unwrap_or(Typ::Unknown);
-            let rhs_typ = rhs.typ.unwrap_or(Typ::Unknown);
+            let lhs = self.bind_expression(infix.left, tree);
+            let rhs = self.bind_expression(infix.right, tree);
+
+            // If the operands are yet to be inferred and there is only
+            // one overload of the operator then they must be its
+            // operand types.
+            let candidates = self.operators.candidates(infix.op);
+            if let [candidate] = candidates[..] {
+                let _ = self.inference.unify(candidate.lhs_typ, lhs.typ);
+                let _ = self.inference.unify(candidate.rhs_typ, rhs.typ);
+            }
+            // Operands are otherwise assumed to be the same type, so
+            // if either is yet to be inferred it can be inferred from
+            // the other.
+            if !self.inference.is_resolved(lhs.typ) || !self.inference.is_resolved(rhs.typ) {
+                let _ = self.inference.unify(lhs.typ, rhs.typ);
+            }
+            let lhs_typ = self.inference.resolve(lhs.typ);
+            let rhs_typ = self.inference.resolve(rhs.typ);
 
             // Look the operator up in the operator table to check if
-            // it is permissable and what the reutnr type is.
-            match operators::find_builtin_op(infix.op, lhs_typ, rhs_typ) {
+            // it is permissable and what the return type is.
+            match self.operators.find(infix.op, lhs_typ, rhs_typ) {
                 Some(operator) => Expression::new(
                     ExpressionKind::Infix(Box::new(lhs), infix.op, Box::new(rhs)),
-                    Some(operator.result_typ),
+                    operator.result_typ,
                 ),
+                // Errors in the operands have already been reported.
+                None if lhs_typ == Typ::Error || rhs_typ == Typ::Error => Expression::error(),
                 None => {
-                    self.diagnostics.push(Diagnostic::new(
-                        format!("Use of operator `{:?}` with invalid arguments", infix.op),
-                        Span::enclosing(infix.left.span(), infix.right.span()),
-                    ));
+                    let mut message = format!(
+                        "No operator `{}` accepts '{}' and '{}'",
+                        infix.op.symbol(),
+                        lhs_typ.name(),
+                        rhs_typ.name()
+                    );
+                    if !candidates.is_empty() {
+                        let signatures: Vec<_> =
+                            candidates.iter().map(|c| format!("'{}'", c)).collect();
+                        message.push_str(&format!(". Candidates are: {}", signatures.join(", ")));
+                    }
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            message,
+                            Span::enclosing(infix.left.span(), infix.right.span()),
+                        )
+                        .with_code(Code::InvalidOperands),
+                    );
                     Expression::error()
                 }
             }
@@ -417,50 +954,91 @@ impl Binder {
         &mut self,
         id: &syntax::IdentifierExpression,
         infix: &syntax::InfixOperatorExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        match self.scopes.lookup(id.ident) {
+        let source = tree.source();
+        let sym = self.scopes.lookup(id.ident).cloned();
+        let definition = self.scopes.lookup_definition(id.ident);
+        if let Some(sym) = &sym {
+            self.record_symbol(id.token.span(), id.ident, sym, definition);
+        }
+        match sym {
             Some(Symbol::Variable(style, typ)) => {
                 if style != VarStyle::Mutable {
-                    self.diagnostics.push(Diagnostic::new(
-                        format!(
-                            "Can't assign to '{}', it isn't mutable",
-                            source.interned_value(id.ident)
-                        ),
-                        infix.op_token.span(),
-                    ));
+                    let name = source.interned_value(id.ident);
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!("Can't assign to '{}', it isn't mutable", name),
+                            infix.op_token.span(),
+                        )
+                        .with_code(Code::AssignToImmutable)
+                        .with_label(
+                            definition.unwrap_or(DUMMY_SPAN),
+                            format!("'{}' is declared here", name),
+                        )
+                        .with_note(format!("declare '{}' with `var` to allow assignment", name)),
+                    );
                 }
-                let rhs = self.bind_expression(&infix.right, source);
-                let resolved_ty = rhs.typ.unwrap_or(typ);
-                if resolved_ty != typ {
-                    self.diagnostics.push(Diagnostic::new(
-                        format!(
-                            "Type mismatch in assignment to '{}' ",
-                            source.interned_value(id.ident)
-                        ),
-                        infix.op_token.span(),
-                    ));
+                let rhs = self.bind_expression(infix.right, tree);
+                if let Some(definition) = definition {
+                    self.unassigned.remove(&definition);
                 }
+                let resolved_ty = match self.inference.unify(typ, rhs.typ) {
+                    Ok(unified) => unified,
+                    Err(mismatch) if mismatch.has_error() => Typ::Error,
+                    Err(mismatch) => {
+                        let name = source.interned_value(id.ident);
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                format!("Type mismatch in assignment to '{}'", name),
+                                infix.op_token.span(),
+                            )
+                            .with_code(Code::MismatchedTypes)
+                            .with_label(
+                                infix.right.span(),
+                                format!("this is '{}'", mismatch.found.name()),
+                            )
+                            .with_label(
+                                definition.unwrap_or(DUMMY_SPAN),
+                                format!(
+                                    "'{}' is declared as '{}' here",
+                                    name,
+                                    mismatch.expected.name()
+                                ),
+                            ),
+                        );
+                        rhs.typ
+                    }
+                };
                 Expression::new(
-                    ExpressionKind::Assignment(source.interned_value(id.ident), Box::new(rhs)),
-                    Some(resolved_ty),
+                    ExpressionKind::Assignment(
+                        source.interned_value(id.ident).into(),
+                        Box::new(rhs),
+                    ),
+                    resolved_ty,
                 )
             }
             Some(_) => {
-                self.diagnostics.push(Diagnostic::new(
-                    format!(
-                        "Can't write to '{}' as it isn't a variable.",
-                        source.interned_value(id.ident)
-                    ),
-                    id.token.span(),
-                ));
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        format!(
+                            "Can't write to '{}' as it isn't a variable.",
+                            source.interned_value(id.ident)
+                        ),
+                        id.token.span(),
+                    )
+                    .with_code(Code::InvalidAssignmentTarget),
+                );
                 Expression::error()
             }
             None => {
-                self.diagnostics.push(Diagnostic::new(
-                    format!("Can't assign to '{}'", source.interned_value(id.ident)),
-                    id.token.span(),
-                ));
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        format!("Can't assign to '{}'", source.interned_value(id.ident)),
+                        id.token.span(),
+                    )
+                    .with_code(Code::UndefinedName),
+                );
                 Expression::error()
             }
         }
@@ -476,125 +1054,205 @@ impl Binder {
     /// If the called item is not a function, or the type or arity of
     /// the function arguments do not match the declared paramters
     /// then a diagnostic is raised.
-    pub fn bind_call(&mut self, call: &syntax::CallExpression, source: &SourceText) -> Expression {
-        let callee = self.bind_expression(&call.callee, source);
-        match callee.typ {
-            Some(Typ::Function(id)) => match self.scopes.lookup(id) {
+    pub fn bind_call(
+        &mut self,
+        call: &syntax::CallExpression,
+        tree: &syntax::SyntaxTree<'_>,
+    ) -> Expression {
+        let callee = match tree[call.callee] {
+            // Called names are looked up as functions first, so a
+            // function can still be called when a variable shares its
+            // name.
+            syntax::Expression::Identifier(ref ident) => {
+                let span = call.callee.span();
+                self.capture_visible(span.start());
+                self.bind_identifier_in(ident, tree.source(), Namespace::Function)
+                    .with_span(span)
+            }
+            _ => self.bind_expression(call.callee, tree),
+        };
+        match self.inference.resolve(callee.typ) {
+            Typ::Function(id) => match self.scopes.lookup_in(id, Namespace::Function).cloned() {
                 Some(Symbol::Function(param_tys, ret_ty)) => {
                     let param_count = param_tys.len();
                     let arg_count = call.arguments.len();
 
                     if arg_count < param_count {
-                        self.diagnostics.push(Diagnostic::new(
-                            "Too few arguments to call",
-                            Span::enclosing(call.open_paren.span(), call.close_paren.span()),
-                        ));
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                "Too few arguments to call",
+                                Span::enclosing(call.open_paren.span(), call.close_paren.span()),
+                            )
+                            .with_code(Code::WrongArgumentCount),
+                        );
                     }
 
                     if arg_count > param_count {
-                        let start = call.arguments[param_count].span().start();
-                        self.diagnostics.push(Diagnostic::new(
-                            "Too many arguments to call",
-                            Span::new(start, call.close_paren.span().start()),
-                        ))
+                        let first_extra = call.arguments[param_count].as_inner().span();
+                        let close = call.close_paren.span();
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                "Too many arguments to call",
+                                Span::new(first_extra.start(), close.start())
+                                    .with_file(first_extra.file()),
+                            )
+                            .with_code(Code::WrongArgumentCount),
+                        )
                     }
 
                     let args: Vec<_> = call
                         .arguments
                         .iter()
-                        .zip(param_tys)
+                        .map(|arg| *arg.as_inner())
+                        .zip(param_tys.iter().copied())
                         .map(|(arg, param)| {
-                            let bound_arg = self.bind_expression(arg, source);
-                            if bound_arg.typ != Some(param) {
-                                self.diagnostics.push(Diagnostic::new(
-                                    format!(
-                                        "Invalid argument. Expected '{}' but found '{}'",
-                                        param.name(),
-                                        bound_arg.typ.unwrap_or(Typ::Unknown).name()
-                                    ),
-                                    arg.span(),
-                                ))
+                            let bound_arg = self.bind_expression(arg, tree);
+                            let unified = self.inference.unify(param, bound_arg.typ);
+                            if let Some(mismatch) = unified.err().filter(|m| !m.has_error()) {
+                                self.diagnostics.push(
+                                    Diagnostic::new(
+                                        format!(
+                                            "Invalid argument. Expected '{}' but found '{}'",
+                                            mismatch.expected.name(),
+                                            mismatch.found.name()
+                                        ),
+                                        arg.span(),
+                                    )
+                                    .with_code(Code::MismatchedTypes),
+                                )
                             }
                             bound_arg
                         })
                         .collect();
 
-                    Expression::new(ExpressionKind::Call(Box::new(callee), args), Some(ret_ty))
+                    Expression::new(ExpressionKind::Call(Box::new(callee), args), ret_ty)
                 }
+                // Function expressions which aren't hoisted are never
+                // declared, so calling one directly finds nothing.
                 _ => {
-                    unreachable!();
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!(
+                                "Can't find '{}' in this scope",
+                                tree.source().interned_value(id)
+                            ),
+                            call.callee.span(),
+                        )
+                        .with_code(Code::UndefinedName),
+                    );
+                    Expression::error()
                 }
             },
+            // The callee has already been reported.
+            Typ::Error => Expression::error(),
             _ => {
-                self.diagnostics.push(Diagnostic::new(
-                    "Called item is not a function",
-                    call.callee.span(),
-                ));
+                self.diagnostics.push(
+                    Diagnostic::new("Called item is not a function", call.callee.span())
+                        .with_code(Code::NotCallable),
+                );
                 Expression::error()
             }
         }
     }
 
     /// Bind an index/slice expression
+    ///
+    /// Only `String`s can be indexed so far. Indexing a string by a
+    /// `Number` gives the Unicode scalar value at that position, as a
+    /// `String`.
     pub fn bind_index(
         &mut self,
         index: &syntax::IndexExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let _indexee = self.bind_expression(&index.indexee, source);
-        let _inddex = self.bind_expression(&index.index, source);
-
-        // TODO: Index expressions.
-        self.diagnostics.push(Diagnostic::new(
-            "Index expressions are not yet supported",
-            Span::enclosing(index.open_bracket.span(), index.close_bracket.span()),
-        ));
-        Expression::error()
+        let indexee = self.bind_expression(index.indexee, tree);
+        let bound_index = self.bind_expression(index.index, tree);
+        let string = Typ::Builtin(BuiltinType::String);
+
+        match self.inference.resolve(indexee.typ) {
+            Typ::Builtin(BuiltinType::String) => {
+                let number = Typ::Builtin(BuiltinType::Number);
+                let unified = self.inference.unify(number, bound_index.typ);
+                if let Some(mismatch) = unified.err().filter(|m| !m.has_error()) {
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!(
+                                "Invalid index. Expected '{}' but found '{}'",
+                                mismatch.expected.name(),
+                                mismatch.found.name()
+                            ),
+                            index.index.span(),
+                        )
+                        .with_code(Code::MismatchedTypes),
+                    );
+                }
+                Expression::new(
+                    ExpressionKind::Index(Box::new(indexee), Box::new(bound_index)),
+                    string,
+                )
+            }
+            Typ::Error => Expression::error(),
+            // TODO: Index expressions for other types.
+            _ => {
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        "Index expressions are only supported on 'String's",
+                        Span::enclosing(index.open_bracket.span(), index.close_bracket.span()),
+                    )
+                    .with_code(Code::Unsupported),
+                );
+                Expression::error()
+            }
+        }
     }
 
     /// Bind a if then else expression
     pub fn bind_if_else(
         &mut self,
         if_else: &syntax::IfElseExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let cond = self.bind_expression(&if_else.cond, source);
-        let if_true = self.bind_expression(&if_else.if_true, source);
-        let if_false = self.bind_expression(&if_else.if_false, source);
-
-        // Check that the condition type is bool
-        //
-        // TODO: Bind a conversion to bool here to allow `if` to
-        //       coerce values to `Bool`
-        let cond_ty = cond.typ.unwrap_or(Typ::Unknown);
-        if cond_ty != Typ::Builtin(BuiltinType::Bool) {
-            self.diagnostics.push(Diagnostic::new(
-                format!(
-                    "Condition expression should be 'Bool' but is '{}'",
-                    cond_ty.name()
-                ),
-                if_else.cond.span(),
-            ));
-        }
-
-        let typ = if_true.typ;
-        let true_typ = if_true.typ.unwrap_or(Typ::Unknown);
-        let false_typ = if_false.typ.unwrap_or(Typ::Unknown);
-
-        // TODO: This doesn't deal with the case of both types being
-        //       missing. Hopefully we can get rid of optional types
-        //       on the bound tree and rely on `Typ::Unknown` so we
-        //       don't have to handle such cases.
-        if true_typ != false_typ {
-            self.diagnostics.push(Diagnostic::new(
-                format!(
-                    "If and else have mismatched types. '{}' and '{}'",
-                    true_typ.name(),
-                    false_typ.name()
-                ),
-                Span::enclosing(if_else.if_true.span(), if_else.if_false.span()),
-            ));
-        }
+        let cond = self.bind_expression(if_else.cond, tree);
+        // Only one branch is evaluated. Variables are only assigned
+        // after the `if` if both branches assign them.
+        let before = self.unassigned.clone();
+        let if_true = self.bind_expression(if_else.if_true, tree);
+        let after_true = mem::replace(&mut self.unassigned, before);
+        let if_false = self.bind_expression(if_else.if_false, tree);
+        self.unassigned.extend(after_true);
+
+        let cond = self.convert_condition(cond, if_else.cond.span());
+
+        // A branch which never completes, such as a `break`, doesn't
+        // need to match the type of the other branch.
+        let typ = match self.inference.unify(if_true.typ, if_false.typ) {
+            Ok(unified) => unified,
+            Err(_) if reachability::diverges(&if_true) => if_false.typ,
+            Err(_) if reachability::diverges(&if_false) => if_true.typ,
+            Err(mismatch) if mismatch.has_error() => Typ::Error,
+            Err(mismatch) => {
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        format!(
+                            "If and else have mismatched types. '{}' and '{}'",
+                            mismatch.expected.name(),
+                            mismatch.found.name()
+                        ),
+                        Span::enclosing(if_else.if_true.span(), if_else.if_false.span()),
+                    )
+                    .with_code(Code::MismatchedTypes)
+                    .with_label(
+                        if_else.if_true.span(),
+                        format!("this is '{}'", mismatch.expected.name()),
+                    )
+                    .with_label(
+                        if_else.if_false.span(),
+                        format!("this is '{}'", mismatch.found.name()),
+                    ),
+                );
+                if_true.typ
+            }
+        };
 
         Expression::new(
             ExpressionKind::IfThenElse(Box::new(cond), Box::new(if_true), Box::new(if_false)),
@@ -611,12 +1269,16 @@ impl Binder {
     /// # Errors
     ///
     /// Any errors from the bindig of the function body are added to
-    /// this `Binder`'s diagnostics.
+    /// this `Binder`'s diagnostics. If the body doesn't produce the
+    /// declared return type then a diagnostic is raised. Functions
+    /// declared to return `()` discard whatever their body produces.
     pub fn bind_function(
         &mut self,
         func: &syntax::FunctionExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
+        let source = tree.source();
+        let attrs = attributes::check(&func.attributes, true, source, &mut self.diagnostics);
         let mut parent_scope = Scope::new();
         self.scopes.flatten_decls_into(&mut parent_scope);
 
@@ -627,95 +1289,321 @@ impl Binder {
             .map(|p| {
                 let p = p.as_inner();
                 let typ = match p.typ.as_ref() {
-                    Some(anno) => self.bind_type(&anno.type_ref),
+                    Some(anno) => self.bind_type(&anno.type_ref, source),
                     None => {
-                        self.diagnostics.push(Diagnostic::new(
-                            format!("Parameter '{}' missing type", source.interned_value(p.id)),
-                            p.id_tok.span(),
-                        ));
+                        self.diagnostics.push(
+                            Diagnostic::new(
+                                format!("Parameter '{}' missing type", source.interned_value(p.id)),
+                                p.id_tok.span(),
+                            )
+                            .with_code(Code::MissingParameterType),
+                        );
                         Typ::Error
                     }
                 };
                 if !seen_idents.insert(p.id) {
-                    self.diagnostics.push(Diagnostic::new(
-                        format!(
-                            "Duplicate function parameter '{}'",
-                            source.interned_value(p.id)
-                        ),
-                        p.id_tok.span(),
-                    ));
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!(
+                                "Duplicate function parameter '{}'",
+                                source.interned_value(p.id)
+                            ),
+                            p.id_tok.span(),
+                        )
+                        .with_code(Code::DuplicateParameter),
+                    );
                 }
-                parent_scope.try_declare(p.id, Symbol::Variable(VarStyle::Mutable, typ));
+                let sym = Symbol::Variable(VarStyle::Mutable, typ);
+                let span = p.id_tok.span();
+                self.record_symbol(span, p.id, &sym, Some(span));
+                parent_scope.try_declare_at(p.id, sym, span);
                 VarDecl {
-                    ident: source.interned_value(p.id),
-                    ty: Some(typ),
+                    ident: source.interned_value(p.id).into(),
+                    ty: typ,
+                    span,
                 }
             })
             .collect();
 
         let mut binder = Binder::new(parent_scope);
-        let bound_body = binder.bind_block(&func.body, source);
-        let ret_ty = self.bind_type(&func.return_type.type_ref);
+        binder.probe = self.probe;
+        binder.inference = mem::take(&mut self.inference);
+        binder.operators = mem::take(&mut self.operators);
+        let bound_body = binder.bind_block(&func.body, tree);
+        let ret_ty = self.bind_type(&func.return_type.type_ref, source);
+        self.inference = mem::take(&mut binder.inference);
+        self.operators = mem::take(&mut binder.operators);
+        self.inferred.append(&mut binder.inferred);
+        // There is no `return`, so a function which returns `()` can
+        // only end with whatever its last expression is.
+        if ret_ty != Typ::Unit {
+            self.check_returns(
+                func.body.contents,
+                tree,
+                &bound_body,
+                ret_ty,
+                func.body.close.span(),
+            );
+        }
 
         // Report any diagnostics from the child binder in this bind.
         self.diagnostics.append(&mut binder.take_diagnostics());
+        self.warnings.append(&mut binder.warnings);
+        self.lint_scopes.append(&mut binder.lint_scopes);
+        self.symbol_spans.append(&mut binder.symbol_spans);
+        self.definitions.append(&mut binder.definitions);
+        self.types.append(&mut binder.types);
+        if binder.visible.is_some() {
+            self.visible = binder.visible.take();
+        }
+        self.capture_visible(func.body.close.span().end());
+        for (definition, mut refs) in binder.references.drain() {
+            self.references
+                .entry(definition)
+                .or_default()
+                .append(&mut refs);
+        }
+        if let Some(sym) = self
+            .scopes
+            .lookup_in(func.identifier, Namespace::Function)
+            .cloned()
+        {
+            let definition = self
+                .scopes
+                .lookup_definition_in(func.identifier, Namespace::Function);
+            self.record_symbol(
+                func.identifier_tok.span(),
+                func.identifier,
+                &sym,
+                definition,
+            );
+        }
 
         Expression::new(
             ExpressionKind::Function(FnDecl {
-                ident: source.interned_value(func.identifier),
+                ident: source.interned_value(func.identifier).into(),
                 ret_ty,
                 params,
+                attrs,
                 body: Box::new(bound_body),
             }),
-            Some(Typ::Error),
+            Typ::Function(func.identifier),
         )
     }
 
+    /// Check Every Path Through a Function Returns a Value
+    ///
+    /// Walks the tail positions of a function body. Each branch the
+    /// function can return from must produce a value of the declared
+    /// return type. Branches which never complete don't return, so
+    /// aren't checked. The branches of an `if` are only checked
+    /// separately when their types differ. A diagnostic is raised at
+    /// each branch which falls through with the wrong type, or at
+    /// `fallback` if the branch has no span of its own.
+    fn check_returns(
+        &mut self,
+        body: syntax::ExprId,
+        tree: &syntax::SyntaxTree<'_>,
+        bound: &Expression,
+        ret_ty: Typ,
+        fallback: Span,
+    ) {
+        if reachability::diverges(bound) {
+            return;
+        }
+        match (&tree[body], &bound.kind) {
+            (syntax::Expression::Grouping(group), _) => {
+                return self.check_returns(group.inner, tree, bound, ret_ty, fallback);
+            }
+            (syntax::Expression::Sequence(exprs), ExpressionKind::Sequence(bound_exprs)) => {
+                if let (Some(&last), Some(bound_last)) = (exprs.last(), bound_exprs.last()) {
+                    return self.check_returns(last, tree, bound_last, ret_ty, fallback);
+                }
+            }
+            (
+                syntax::Expression::IfThenElse(if_else),
+                ExpressionKind::IfThenElse(_, if_true, if_false),
+            ) if self.inference.resolve(if_true.typ) != self.inference.resolve(if_false.typ) => {
+                self.check_returns(if_else.if_true, tree, if_true, ret_ty, fallback);
+                self.check_returns(if_else.if_false, tree, if_false, ret_ty, fallback);
+                return;
+            }
+            _ => (),
+        }
+
+        // Errors in the branch have already been reported.
+        if self.inference.resolve(ret_ty) == Typ::Error
+            || self.inference.resolve(bound.typ) == Typ::Error
+        {
+            return;
+        }
+        if let Err(mismatch) = self.inference.unify(ret_ty, bound.typ) {
+            let span = match body.span() {
+                DUMMY_SPAN => fallback,
+                span => span,
+            };
+            self.diagnostics.push(
+                Diagnostic::new(
+                    format!(
+                        "Function should return '{}' but this branch produces '{}'",
+                        mismatch.expected.name(),
+                        mismatch.found.name()
+                    ),
+                    span,
+                )
+                .with_code(Code::MissingReturn),
+            );
+        }
+    }
+
     /// Bind a loop expression
     pub fn bind_loop(
         &mut self,
         loop_expr: &syntax::LoopExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let mut condition = self.bind_expression(&loop_expr.condition, source);
+        let condition = self.bind_expression(loop_expr.condition, tree);
+        let mut condition = self.convert_condition(condition, loop_expr.condition.span());
         if loop_expr.kw_token.kind == TokenKind::Word(Ident::Until) {
-            let typ = condition.typ;
+            let (typ, span) = (condition.typ, condition.span);
             condition = Expression::new(
                 ExpressionKind::Prefix(PrefixOp::Not, Box::new(condition)),
                 typ,
-            );
+            )
+            .with_span(span);
         }
-        let body = self.bind_block(&loop_expr.body, source);
+        // The body might not run at all, so assignments in it don't
+        // count once the loop has finished.
+        let before = self.unassigned.clone();
+        self.loops.push(loop_expr.label.as_ref().map(|l| l.name));
+        let body = self.bind_block(&loop_expr.body, tree);
+        self.loops.pop();
+        self.unassigned = before;
         Expression::new(
             ExpressionKind::Loop(Box::new(condition), Box::new(body)),
-            Some(Typ::Unit),
+            Typ::Unit,
         )
     }
 
-    /// Bind a sequence of expressions
-    pub fn bind_sequence(
+    /// Bind a `break` or `continue`
+    ///
+    /// Finds the loop the expression applies to on the stack of
+    /// enclosing loops. Labels which are used more than once refer to
+    /// the innermost loop with that label.
+    ///
+    /// # Errors
+    ///
+    /// If there is no enclosing loop, or none with the given label,
+    /// then a diagnostic is raised.
+    pub fn bind_loop_control(
         &mut self,
-        exprs: &[syntax::Expression],
+        control: &syntax::LoopControlExpression,
         source: &SourceText,
     ) -> Expression {
-        let transformed: Vec<_> = exprs
-            .iter()
-            .map(|e| self.bind_expression(e, source))
-            .collect();
-        let typ = transformed.last().and_then(|e| e.typ).unwrap_or(Typ::Unit);
-        Expression::new(ExpressionKind::Sequence(transformed), Some(typ))
+        let kw = source.interned_value(match control.kind {
+            syntax::LoopControl::Break => Ident::Break,
+            syntax::LoopControl::Continue => Ident::Continue,
+        });
+        let depth = match control.label {
+            Some((_, label)) => self.loops.iter().rev().position(|&l| l == Some(label)),
+            None if self.loops.is_empty() => None,
+            None => Some(0),
+        };
+        match depth {
+            Some(depth) => {
+                Expression::new(ExpressionKind::LoopControl(control.kind, depth), Typ::Unit)
+            }
+            None => {
+                let message = match control.label {
+                    Some((_, label)) => format!(
+                        "No enclosing loop is labelled '{}'",
+                        source.interned_value(label)
+                    ),
+                    None => format!("`{}` outside of a loop", kw),
+                };
+                let span = Span::enclosing(
+                    control.kw_token.span(),
+                    control
+                        .label
+                        .as_ref()
+                        .map_or(control.kw_token.span(), |l| l.0.span()),
+                );
+                self.diagnostics
+                    .push(Diagnostic::new(message, span).with_code(Code::InvalidLoopControl));
+                Expression::error()
+            }
+        }
     }
 
-    /// Bind a `print` expression
-    pub fn bind_print(
+    /// Bind a sequence of expressions
+    ///
+    /// If an expression in the sequence never completes then a
+    /// warning is raised for the code after it, which can never run.
+    /// Function declarations are still reachable through calls so
+    /// they aren't included.
+    pub fn bind_sequence(
         &mut self,
-        print: &syntax::PrintExpression,
-        source: &SourceText,
+        exprs: &[syntax::ExprId],
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let bound_printee = self.bind_expression(&print.inner, source);
-        // TODO: Does the print expression convert things to `String`s?
+        let mut transformed = Vec::with_capacity(exprs.len());
+        for &expr in exprs.iter() {
+            if !self.is_enabled(expr, tree) {
+                continue;
+            }
+            let span = expr.span();
+            let levels = pragma::allowed_codes(tree.source(), span)
+                .into_iter()
+                .map(|code| (code, LintLevel::Allow))
+                .chain(attributes::lint_levels(
+                    tree[expr].attributes(),
+                    tree.source(),
+                ));
+            for (code, level) in levels {
+                self.lint_scopes.push((span, code, level));
+            }
+            transformed.push(self.bind_expression(expr, tree));
+        }
+        let typ = transformed.last().map_or(Typ::Unit, |e| e.typ);
+        Expression::new(ExpressionKind::Sequence(transformed), typ)
+    }
+
+    /// Bind a `print` expression
+    pub fn bind_print(
+        &mut self,
+        print: &syntax::PrintExpression,
+        tree: &syntax::SyntaxTree<'_>,
+    ) -> Expression {
+        let bound_printee = self.bind_expression(print.inner, tree);
+        // The print expression evaluates to the value being printed,
+        // rather than the `String` it is converted to.
         let typ = bound_printee.typ;
-        Expression::new(ExpressionKind::Print(Box::new(bound_printee)), typ)
+        let string_typ = Typ::Builtin(BuiltinType::String);
+        let printee = match self.inference.resolve(typ) {
+            // The type isn't known yet. Convert it anyway, lowering
+            // will see the final type.
+            Typ::Var(_) => {
+                let span = bound_printee.span;
+                Expression::new(ExpressionKind::Convert(Box::new(bound_printee)), string_typ)
+                    .with_span(span)
+            }
+            Typ::Error => bound_printee,
+            resolved => {
+                let printee =
+                    Expression::new(bound_printee.kind, resolved).with_span(bound_printee.span);
+                conversions::convert(printee, string_typ).unwrap_or_else(|printee| {
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!("Can't print a value of type '{}'", resolved.name()),
+                            print.inner.span(),
+                        )
+                        .with_code(Code::UnprintableValue),
+                    );
+                    printee
+                })
+            }
+        };
+        Expression::new(ExpressionKind::Print(Box::new(printee)), typ)
     }
 
     /// Bind Variable Declaration Statement
@@ -727,57 +1615,116 @@ impl Binder {
     /// Variable declarations have type inference if the type clause
     /// is missing. If not the type of the initialiser should be
     /// convertable to the declaration's type annotation.
+    ///
+    /// Mutable variables can be declared without an initialiser. They
+    /// must then be assigned before they are read.
     pub fn bind_declaration(
         &mut self,
         decl: &syntax::DeclarationExpression,
-        source: &SourceText,
+        tree: &syntax::SyntaxTree<'_>,
     ) -> Expression {
-        let decl_type = if let Some(anno) = &decl.id.typ {
-            self.bind_type(&anno.type_ref)
-        } else {
-            Typ::Unknown
+        let source = tree.source();
+        attributes::check(&decl.attributes, false, source, &mut self.diagnostics);
+        let decl_type = match &decl.id.typ {
+            Some(anno) => self.bind_type(&anno.type_ref, source),
+            None => {
+                // If we don't have a type annotation in the
+                // declaration then the type is inferred.
+                let var = self.inference.fresh();
+                self.inferred.push((
+                    decl.id.id_tok.span(),
+                    source.interned_value(decl.id.id).into(),
+                    var,
+                ));
+                var
+            }
         };
-        let bound_initialiser = self.bind_expression(&decl.initialiser, source);
-
         let id = decl.id.id;
+        let span = decl.id.id_tok.span();
+        let bound_initialiser = match &decl.initialiser {
+            Some(init) => Some(self.bind_expression(init.value, tree)),
+            None => {
+                if decl.style != VarStyle::Mutable {
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            format!(
+                                "Immutable variable '{}' must have an initialiser",
+                                source.interned_value(id)
+                            ),
+                            span,
+                        )
+                        .with_code(Code::MissingInitialiser),
+                    );
+                }
+                self.unassigned.insert(span);
+                None
+            }
+        };
 
-        // If we don't have a type annotation in the declaration then
-        // infer the type from the initialiser
-        let ty = if decl_type != Typ::Unknown {
-            match bound_initialiser.typ {
-                Some(t) if t != decl_type => {
-                    // The declaration type doesn't match the
-                    // expression being used to initialise it.
-                    self.diagnostics.push(Diagnostic::new(
-                        format!(
-                            "Initialiser doesn't match declaration type for '{}'",
-                            source.interned_value(id)
-                        ),
-                        decl.id.id_tok.span(),
-                    ));
-                    Some(Typ::Error)
+        let init_ty = bound_initialiser.as_ref().map_or(decl_type, |i| i.typ);
+        let ty = match self.inference.unify(decl_type, init_ty) {
+            Ok(unified) => unified,
+            Err(mismatch) if mismatch.has_error() => Typ::Error,
+            Err(mismatch) => {
+                // The declaration type doesn't match the expression
+                // being used to initialise it.
+                let mut diagnostic = Diagnostic::new(
+                    format!(
+                        "Initialiser doesn't match declaration type for '{}'",
+                        source.interned_value(id)
+                    ),
+                    decl.id.id_tok.span(),
+                )
+                .with_code(Code::MismatchedTypes);
+                if let (Some(anno), Some(init)) = (&decl.id.typ, &decl.initialiser) {
+                    let found = mismatch.found.name();
+                    diagnostic = diagnostic
+                        .with_label(
+                            anno.type_ref.span(),
+                            format!("expected '{}' because of this", mismatch.expected.name()),
+                        )
+                        .with_label(init.value.span(), format!("this is '{}'", found));
+                    // Only types which can be written in an annotation
+                    // can replace it.
+                    if let Typ::Builtin(_) | Typ::Unit = mismatch.found {
+                        diagnostic = diagnostic.with_fix(Fix::new(
+                            anno.type_ref.span(),
+                            found.clone(),
+                            format!(
+                                "change the type of '{}' to '{}'",
+                                source.interned_value(id),
+                                found
+                            ),
+                            Applicability::MaybeIncorrect,
+                        ));
+                    }
                 }
-                _ => Some(decl_type),
+                self.diagnostics.push(diagnostic);
+                // Keep the declared type so uses of the variable are
+                // still checked against it.
+                mismatch.expected
             }
-        } else {
-            bound_initialiser.typ
         };
 
-        self.scopes
-            .current_mut()
-            .try_declare(id, Symbol::Variable(decl.style, ty.unwrap_or(Typ::Unknown)));
+        let sym = Symbol::Variable(decl.style, ty);
+        self.record_symbol(span, id, &sym, Some(span));
+        self.scopes.current_mut().declare_at(id, sym, span);
+        self.capture_visible(decl.span().end());
 
         let is_mut = decl.style == VarStyle::Mutable;
+        // A declaration without an initialiser has no value.
+        let typ = bound_initialiser.as_ref().map_or(Typ::Unit, |_| ty);
         Expression::new(
             ExpressionKind::Declaration(
                 VarDecl {
-                    ident: source.interned_value(id),
+                    ident: source.interned_value(id).into(),
                     ty,
+                    span,
                 },
                 is_mut,
-                Box::new(bound_initialiser),
+                bound_initialiser.map(Box::new),
             ),
-            ty,
+            typ,
         )
     }
 
@@ -785,75 +1732,354 @@ impl Binder {
     ///
     /// Creates a new scope and binds the contents of the block in
     /// that scope before popping that scope from the stack.
-    pub fn bind_block(&mut self, block: &syntax::BlockBody, source: &SourceText) -> Expression {
+    pub fn bind_block(
+        &mut self,
+        block: &syntax::BlockBody,
+        tree: &syntax::SyntaxTree<'_>,
+    ) -> Expression {
         self.scopes.push(Scope::new());
-        let bound = self.bind_expression(&block.contents, source);
+        let bound = self.bind_expression(block.contents, tree);
         self.scopes.pop();
+        self.capture_visible(block.close.span().end());
         bound
     }
 
+    /// Convert a Condition to `Bool`
+    ///
+    /// Conditions must be `Bool`, or a type which can be converted to
+    /// `Bool`. The condition is returned with any conversion
+    /// applied. If the condition has any other type a diagnostic is
+    /// raised at `span`.
+    fn convert_condition(&mut self, cond: Expression, span: Span) -> Expression {
+        let bool_typ = Typ::Builtin(BuiltinType::Bool);
+        let typ = self.inference.resolve(cond.typ);
+        if let Typ::Var(_) = typ {
+            // Nothing else is known about the type, so the condition
+            // might as well be a `Bool`.
+            let _ = self.inference.unify(bool_typ, typ);
+            return cond;
+        }
+        if typ == Typ::Error {
+            return cond;
+        }
+        let cond = Expression::new(cond.kind, typ).with_span(cond.span);
+        conversions::convert(cond, bool_typ).unwrap_or_else(|cond| {
+            self.diagnostics.push(
+                Diagnostic::new(
+                    format!(
+                        "Condition expression should be 'Bool' but is '{}'",
+                        typ.name()
+                    ),
+                    span,
+                )
+                .with_code(Code::InvalidCondition),
+            );
+            cond
+        })
+    }
+
     /// Bind the type in the current scope
     ///
     /// Looks the type up if there is an annotation. If the annotation
     /// is missing then `None` is retunred.
-    pub fn bind_type(&mut self, ty_ref: &TypeRef) -> Typ {
+    pub fn bind_type(&mut self, ty_ref: &TypeRef, source: &SourceText) -> Typ {
         match *ty_ref {
             TypeRef::Unit(..) => Typ::Unit,
             TypeRef::Simple(ref name) => {
                 let id = match name.kind {
                     TokenKind::Word(id) => id,
-                    _ => panic!("Expected word token"),
+                    // The parser only creates simple types from words,
+                    // but a tree built by hand might not.
+                    ref kind => {
+                        self.diagnostics.push(
+                            Diagnostic::new(format!("expected type, found: {}", kind), name.span())
+                                .with_code(Code::ExpectedType),
+                        );
+                        return Typ::Error;
+                    }
                 };
                 match self.scopes.lookup(id) {
-                    Some(Symbol::Type(ty)) => ty,
+                    Some(&Symbol::Type(ty)) => ty,
                     _ => {
+                        let name_str = source.interned_value(id);
+                        let suggestion =
+                            self.suggest(name_str, source, |sym| matches!(sym, Symbol::Type(_)));
+                        let diagnostic = Diagnostic::new(
+                            format!("Reference to undefined type '{}'", name_str),
+                            name.span(),
+                        )
+                        .with_code(Code::UndefinedType);
                         self.diagnostics
-                            .push(Diagnostic::new("Reference to undefined type", name.span()));
+                            .push(with_suggestion(diagnostic, suggestion));
                         Typ::Error
                     }
                 }
             }
             // TODO: array and tuple types
-            TypeRef::Array(..) => unimplemented!("array types are not yet supported"),
-            TypeRef::Tuple(..) => unimplemented!("tuple types are not yet supported"),
-            TypeRef::Missing => panic!("Can't lower missing type"),
+            TypeRef::Array(..) | TypeRef::Tuple(..) => {
+                let kind = if let TypeRef::Array(..) = ty_ref {
+                    "array"
+                } else {
+                    "tuple"
+                };
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        format!("{} types are not yet supported", kind),
+                        ty_ref.span(),
+                    )
+                    .with_code(Code::Unsupported),
+                );
+                Typ::Error
+            }
+            // The parser will already have reported the missing type.
+            TypeRef::Missing => Typ::Error,
         }
     }
 
     /// Clears out the diagnostics list and returns any diagnostics
-    /// that have been accumulated.
+    /// that have been accumulated, in source order.
     pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
-        self.diagnostics.drain(..).collect()
+        diag::sort_diagnostics(self.diagnostics.drain(..).collect())
+    }
+
+    /// Report a Diagnostic Raised by a Lint
+    ///
+    /// The innermost pragma or attribute setting the level of the
+    /// lint around the diagnostic decides how it is reported. If
+    /// there isn't one then `level` is used.
+    fn report_lint(&mut self, diagnostic: Diagnostic, level: LintLevel) {
+        let start = diagnostic.span.start();
+        let level = self
+            .lint_scopes
+            .iter()
+            .filter(|&&(span, code, _)| diagnostic.code == Some(code) && span.contains(start))
+            .min_by_key(|(span, _, _)| span.end().offset() - span.start().offset())
+            .map_or(level, |&(_, _, level)| level);
+        match level {
+            LintLevel::Allow => (),
+            LintLevel::Warn => self.warnings.push(diagnostic),
+            LintLevel::Deny => self.diagnostics.push(diagnostic),
+        }
+    }
+
+    /// Clears out the warnings list and returns any warnings that
+    /// have been accumulated, in source order.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        diag::sort_diagnostics(self.warnings.drain(..).collect())
+    }
+
+    /// Take the Symbol Spans
+    ///
+    /// Returns the span of each identifier bound so far, both
+    /// declarations and references, along with the symbol it
+    /// resolved to. Identifiers which couldn't be resolved are not
+    /// included.
+    pub fn take_symbol_spans(&mut self) -> Vec<(Span, Symbol)> {
+        let spans: Vec<_> = self.symbol_spans.drain(..).collect();
+        spans
+            .into_iter()
+            .map(|(span, sym)| (span, self.finalise_symbol(sym)))
+            .collect()
+    }
+
+    /// Take the Visible Symbols
+    ///
+    /// Returns the symbols which were visible at the probe position
+    /// set with `with_probe`, along with the identifier each is bound
+    /// to. If no probe was set then the symbols visible in the
+    /// current scope are returned.
+    pub fn take_visible_symbols(&mut self) -> Vec<(Ident, Symbol)> {
+        let visible = self.visible.take().unwrap_or_else(|| self.scopes.visible());
+        visible
+            .into_iter()
+            .map(|(id, sym)| (id, self.finalise_symbol(sym)))
+            .collect()
+    }
+
+    /// Find the Definition of the Identifier at a Position
+    ///
+    /// Returns the span of the declaration the identifier at `pos`
+    /// resolved to. Declarations resolve to themselves. If there is
+    /// no bound identifier at `pos`, or it refers to a builtin, then
+    /// `None` is returned.
+    pub fn definition_at(&self, pos: Pos) -> Option<Span> {
+        self.definitions
+            .iter()
+            .find(|(span, _)| span.contains(pos))
+            .map(|&(_, definition)| definition)
+    }
+
+    /// Find the Type of the Identifier at a Position
+    ///
+    /// Returns the type of the symbol the identifier at `pos`
+    /// resolved to. For references to types this is the type
+    /// itself.
+    pub fn type_at(&self, pos: Pos) -> Option<Typ> {
+        self.types
+            .iter()
+            .find(|(span, _)| span.contains(pos))
+            .map(|&(_, typ)| self.inference.finalise(typ))
+    }
+
+    /// Get the Reference Index
+    ///
+    /// Returns a map from the span of each definition to the spans
+    /// of every identifier bound to it. Declarations are included as
+    /// references to themselves. References are in the order they
+    /// were bound, which isn't always source order.
+    pub fn references(&self) -> &HashMap<Span, Vec<Span>> {
+        &self.references
+    }
+
+    /// Find the References to the Identifier at a Position
+    ///
+    /// Resolves the identifier at `pos` to its definition and
+    /// returns every identifier bound to that definition, including
+    /// the declaration itself, in source order.
+    pub fn references_at(&self, pos: Pos) -> Vec<Span> {
+        let mut refs = self
+            .definition_at(pos)
+            .and_then(|def| self.references.get(&def))
+            .cloned()
+            .unwrap_or_default();
+        refs.sort_by_key(|span| span.start());
+        refs
+    }
+
+    /// Capture the Visible Symbols
+    ///
+    /// Called at each point during the bind where the set of visible
+    /// symbols may change. Points are reached in source order, so the
+    /// last point at or before the probe captures the symbols visible
+    /// at the probe.
+    fn capture_visible(&mut self, pos: Pos) {
+        if matches!(self.probe, Some(probe) if pos <= probe) {
+            self.visible = Some(self.scopes.visible());
+        }
+    }
+
+    /// Finalise the Type of a Symbol
+    ///
+    /// Variables may have been declared with a type which was later
+    /// inferred. Resolves the symbol's type to the inferred one.
+    fn finalise_symbol(&self, sym: Symbol) -> Symbol {
+        match sym {
+            Symbol::Variable(style, typ) => Symbol::Variable(style, self.inference.finalise(typ)),
+            sym => sym,
+        }
+    }
+
+    /// Record a Bound Identifier
+    ///
+    /// Stores the symbol, type, and definition an identifier at
+    /// `span` resolved to for later queries.
+    fn record_symbol(&mut self, span: Span, ident: Ident, sym: &Symbol, def: Option<Span>) {
+        let typ = match *sym {
+            Symbol::Variable(_, t) | Symbol::Type(t) => t,
+            Symbol::Function(..) => Typ::Function(ident),
+        };
+        self.symbol_spans.push((span, sym.clone()));
+        self.types.push((span, typ));
+        if let Some(def) = def.filter(|&d| d != DUMMY_SPAN) {
+            self.definitions.push((span, def));
+            self.references.entry(def).or_default().push(span);
+        }
     }
 }
 
 /// Add the Default Type Declarations
 ///
 /// Inserts the builtin types `String`, `Bool`, and `Number` types
-/// into the given scope. Identifiers for each are looked up from
-/// `source`'s interner.
-fn add_builtin_types(scope: &mut Scope, source: &SourceText) {
+/// into the given scope. Their names are interned up front by every
+/// source, so no lookup is needed.
+fn add_builtin_types(scope: &mut Scope) {
     scope.try_declare(
-        source.intern("String"),
+        Ident::STRING,
         Symbol::Type(Typ::Builtin(BuiltinType::String)),
     );
+    scope.try_declare(Ident::BOOL, Symbol::Type(Typ::Builtin(BuiltinType::Bool)));
+    scope.try_declare(
+        Ident::NUMBER,
+        Symbol::Type(Typ::Builtin(BuiltinType::Number)),
+    );
+}
+
+/// Add the Builtin Function Declarations
+///
+/// Inserts `to_number` and `to_string`, which convert between
+/// `String` and `Number`, and `len` and `byte_len`, which give the
+/// length of a `String` in Unicode scalar values and in bytes, into
+/// the given scope. The `StringBuilder` type is declared too, along
+/// with `string_builder`, `append`, and `build_string` to use it. These are declared after the program's
+/// own items, so a program which defines its own function with the
+/// same name calls that instead.
+fn add_builtin_functions(scope: &mut Scope, source: &SourceText) {
+    let number = Typ::Builtin(BuiltinType::Number);
+    let string = Typ::Builtin(BuiltinType::String);
+    let builder = Typ::Builtin(BuiltinType::StringBuilder);
     scope.try_declare(
-        source.intern("Bool"),
-        Symbol::Type(Typ::Builtin(BuiltinType::Bool)),
+        source.intern("to_number"),
+        Symbol::Function(Rc::from(vec![string]), number),
     );
     scope.try_declare(
-        source.intern("Number"),
-        Symbol::Type(Typ::Builtin(BuiltinType::Number)),
+        source.intern("to_string"),
+        Symbol::Function(Rc::from(vec![number]), string),
+    );
+    scope.try_declare(
+        source.intern("len"),
+        Symbol::Function(Rc::from(vec![string]), number),
+    );
+    scope.try_declare(
+        source.intern("byte_len"),
+        Symbol::Function(Rc::from(vec![string]), number),
+    );
+    scope.try_declare(source.intern("StringBuilder"), Symbol::Type(builder));
+    scope.try_declare(
+        source.intern("string_builder"),
+        Symbol::Function(Rc::from(vec![]), builder),
+    );
+    scope.try_declare(
+        source.intern("append"),
+        Symbol::Function(Rc::from(vec![builder, string]), builder),
+    );
+    scope.try_declare(
+        source.intern("build_string"),
+        Symbol::Function(Rc::from(vec![builder]), string),
     );
 }
 
+/// Add a Suggested Name to a Diagnostic
+///
+/// The suggestion is mentioned in the message, and attached as a fix
+/// which renames the diagnostic's span.
+fn with_suggestion(diagnostic: Diagnostic, suggestion: Option<String>) -> Diagnostic {
+    match suggestion {
+        Some(suggestion) => {
+            let fix = Fix::new(
+                diagnostic.span,
+                suggestion.clone(),
+                format!("rename to '{}'", suggestion),
+                Applicability::MachineApplicable,
+            );
+            Diagnostic {
+                message: format!("{}. Did you mean '{}'?", diagnostic.message, suggestion),
+                ..diagnostic
+            }
+            .with_fix(fix)
+        }
+        None => diagnostic,
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::super::BuiltinType;
+    use super::super::visit::Visitor;
+    use super::super::{BuiltinType, SemOp};
     use super::*;
+    use crate::syntax::builder::TreeBuilder;
     use crate::syntax::text::Interner;
     use crate::syntax::{
-        IdentifierExpression, InfixOperatorExpression, Literal, LiteralExpression,
+        ExpressionArena, IdentifierExpression, Literal, LiteralExpression, LoopControl,
         PrefixExpression, Token, TokenKind,
     };
 
@@ -873,7 +2099,7 @@ mod test {
         let id = interner.intern("test§");
 
         assert!(scope.try_declare(id, Symbol::Variable(VarStyle::Mutable, Typ::Unit)));
-        let found = scope.lookup(id);
+        let found = scope.lookup(id).cloned();
         assert!(!scope.try_declare(id, Symbol::Variable(VarStyle::Mutable, Typ::Unit)));
 
         assert_eq!(Some(Symbol::Variable(VarStyle::Mutable, Typ::Unit)), found);
@@ -913,21 +2139,21 @@ mod test {
         let failed = scopes.lookup(interner.intern("nothere"));
 
         assert_eq!(
-            Some(Symbol::Variable(
+            Some(&Symbol::Variable(
                 VarStyle::Mutable,
                 Typ::Builtin(BuiltinType::Number)
             )),
             foo_lookup
         );
         assert_eq!(
-            Some(Symbol::Variable(
+            Some(&Symbol::Variable(
                 VarStyle::Mutable,
                 Typ::Builtin(BuiltinType::String)
             )),
             bar_lookup
         );
         assert_eq!(
-            Some(Symbol::Variable(
+            Some(&Symbol::Variable(
                 VarStyle::Mutable,
                 Typ::Builtin(BuiltinType::Bool)
             )),
@@ -936,6 +2162,23 @@ mod test {
         assert_eq!(None, failed);
     }
 
+    #[test]
+    fn scope_stack_lookup_shares_function_params() {
+        let source = SourceText::new("");
+        let params: Rc<[Typ]> = vec![Typ::Builtin(BuiltinType::Number)].into();
+        let mut scopes = ScopeStack::new(Scope::new());
+        scopes.current_mut().try_declare(
+            source.intern("f"),
+            Symbol::Function(params.clone(), Typ::Unit),
+        );
+        scopes.push(Scope::new());
+
+        match scopes.lookup(source.intern("f")).cloned() {
+            Some(Symbol::Function(found, _)) => assert!(Rc::ptr_eq(&params, &found)),
+            other => panic!("expected function symbol, found {:?}", other),
+        }
+    }
+
     #[test]
     fn scope_stack_current() {
         let source = SourceText::new("");
@@ -962,7 +2205,7 @@ mod test {
         ));
 
         assert_eq!(
-            Some(Symbol::Variable(
+            Some(&Symbol::Variable(
                 VarStyle::Mutable,
                 Typ::Builtin(BuiltinType::Number)
             )),
@@ -972,7 +2215,7 @@ mod test {
         scopes.pop();
 
         assert_eq!(
-            Some(Symbol::Variable(
+            Some(&Symbol::Variable(
                 VarStyle::Mutable,
                 Typ::Builtin(BuiltinType::Bool)
             )),
@@ -985,23 +2228,23 @@ mod test {
         let mut scope = Scope::new();
         let source = SourceText::new("");
 
-        add_builtin_types(&mut scope, &source);
+        add_builtin_types(&mut scope);
 
         let string_lookup = scope.lookup(source.intern("String"));
         assert_eq!(
-            Some(Symbol::Type(Typ::Builtin(BuiltinType::String))),
+            Some(&Symbol::Type(Typ::Builtin(BuiltinType::String))),
             string_lookup
         );
 
         let bool_lookup = scope.lookup(source.intern("Bool"));
         assert_eq!(
-            Some(Symbol::Type(Typ::Builtin(BuiltinType::Bool))),
+            Some(&Symbol::Type(Typ::Builtin(BuiltinType::Bool))),
             bool_lookup
         );
 
         let num_lookup = scope.lookup(source.intern("Number"));
         assert_eq!(
-            Some(Symbol::Type(Typ::Builtin(BuiltinType::Number))),
+            Some(&Symbol::Type(Typ::Builtin(BuiltinType::Number))),
             num_lookup
         );
     }
@@ -1019,13 +2262,13 @@ mod test {
         let bound = binder.bind_identifier(
             &IdentifierExpression {
                 ident: source.intern("melles"),
-                token: Box::new(Token::new(TokenKind::Word(source.intern("melles")))),
+                token: Token::new(TokenKind::Word(source.intern("melles"))),
             },
             &source,
         );
 
         assert_eq!(ExpressionKind::Identifier("melles".into()), bound.kind);
-        assert_eq!(Some(Typ::Builtin(BuiltinType::Bool)), bound.typ);
+        assert_eq!(Typ::Builtin(BuiltinType::Bool), bound.typ);
     }
 
     #[test]
@@ -1033,29 +2276,38 @@ mod test {
         let mut binder = Binder::new(Scope::new());
 
         let bound = binder.bind_literal(&LiteralExpression {
-            token: Box::new(Token::new(TokenKind::Literal(Literal::Number(1337)))),
+            token: Token::new(TokenKind::Literal(Literal::Number(1337))),
             value: Constant::Number(1337),
         });
 
         assert_eq!(ExpressionKind::Literal(Constant::Number(1337)), bound.kind);
-        assert_eq!(Some(Typ::Builtin(BuiltinType::Number)), bound.typ);
+        assert_eq!(Typ::Builtin(BuiltinType::Number), bound.typ);
     }
 
     #[test]
     fn bind_prefix_expression() {
         let source = SourceText::new("");
         let mut binder = Binder::new(Scope::new());
+        let mut arena = ExpressionArena::new();
+        let inner = arena.alloc(syntax::Expression::constant_num(
+            Token::new(TokenKind::Literal(Literal::Number(23))),
+            23,
+        ));
+        let tree = syntax::SyntaxTree::new(
+            &source,
+            arena,
+            inner,
+            Vec::new(),
+            Token::new(TokenKind::End),
+        );
 
         let bound = binder.bind_prefix(
             &PrefixExpression {
-                op_token: Box::new(Token::new(TokenKind::Minus)),
+                op_token: Token::new(TokenKind::Minus),
                 op: PrefixOp::Negate,
-                inner: Box::new(syntax::Expression::constant_num(
-                    Token::new(TokenKind::Literal(Literal::Number(23))),
-                    23,
-                )),
+                inner,
             },
-            &source,
+            &tree,
         );
 
         assert_eq!(
@@ -1063,26 +2315,776 @@ mod test {
                 PrefixOp::Negate,
                 Box::new(Expression::new(
                     ExpressionKind::Literal(Constant::Number(23)),
-                    Some(Typ::Builtin(BuiltinType::Number))
+                    Typ::Builtin(BuiltinType::Number)
                 ))
             ),
             bound.kind
         );
-        assert_eq!(Some(Typ::Builtin(BuiltinType::Number)), bound.typ);
-    }
-
-    // TODO: need a better way of creating the expression trees to run
-    //       the binder over for these tests. More complex tests may
-    //       also benefit from snapshot testing.
-
-    // Infix(ref innie)
-    // Call(ref call)
-    // Index(ref index)
-    // IfThenElse(ref if_else_expr)
-    // Function(ref func)
-    // Loop(ref loop_expr)
-    // Sequence(ref exprs)
-    // Print(ref print)
-    // Declaration(ref decl)
-    // Grouping(ref group)
+        assert_eq!(Typ::Builtin(BuiltinType::Number), bound.typ);
+    }
+
+    #[test]
+    fn scope_records_definitions() {
+        let source = SourceText::new("");
+        let span = Span::new(Pos::from(4), Pos::from(7));
+        let mut scopes = ScopeStack::new(Scope::new());
+        let sym = Symbol::Variable(VarStyle::Mutable, Typ::Unit);
+
+        assert!(scopes
+            .current_mut()
+            .try_declare_at(source.intern("foo"), sym.clone(), span));
+        assert!(scopes
+            .current_mut()
+            .try_declare(source.intern("bar"), sym.clone()));
+        scopes.push(Scope::new());
+
+        assert_eq!(Some(span), scopes.lookup_definition(source.intern("foo")));
+        assert_eq!(None, scopes.lookup_definition(source.intern("bar")));
+
+        // Shadowing symbols hide the outer definition
+        assert!(scopes
+            .current_mut()
+            .try_declare(source.intern("foo"), sym.clone()));
+        assert_eq!(None, scopes.lookup_definition(source.intern("foo")));
+
+        // Functions are in their own namespace
+        let function = Symbol::Function(Vec::new().into(), Typ::Unit);
+        assert!(scopes
+            .current_mut()
+            .try_declare(source.intern("foo"), function.clone()));
+        assert_eq!(Some(&sym), scopes.lookup(source.intern("foo")));
+        assert_eq!(
+            Some(&function),
+            scopes.lookup_in(source.intern("foo"), Namespace::Function)
+        );
+
+        // Redeclaring replaces the symbol and its definition
+        let redeclared = Span::new(Pos::from(10), Pos::from(13));
+        let number = Symbol::Variable(VarStyle::Mutable, Typ::Builtin(BuiltinType::Number));
+        scopes
+            .current_mut()
+            .declare_at(source.intern("foo"), number.clone(), redeclared);
+        assert_eq!(Some(&number), scopes.lookup(source.intern("foo")));
+        assert_eq!(
+            Some(redeclared),
+            scopes.lookup_definition(source.intern("foo"))
+        );
+    }
+
+    #[test]
+    fn bind_redeclarations_in_the_same_scope() {
+        let source = SourceText::new("var a = 0\nprint a\nvar a = 1\nprint a\n");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let span = |s: usize| Span::new(Pos::from(s), Pos::from(s + 1));
+        // The first `print a` uses the declaration on line 1, and the
+        // second the one on line 3.
+        assert_eq!(Some(span(4)), binder.definition_at(Pos::from(16)));
+        assert_eq!(Some(span(22)), binder.definition_at(Pos::from(34)));
+        assert_eq!(vec![span(4), span(16)], binder.references_at(Pos::from(4)));
+        assert_eq!(
+            vec![span(22), span(34)],
+            binder.references_at(Pos::from(34))
+        );
+    }
+
+    #[test]
+    fn functions_and_variables_have_separate_namespaces() {
+        let source =
+            SourceText::new("fn foo(): Number\n  1\nend\nlet foo = 100\nprint foo\nprint foo()\n");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let span = |s: usize| Span::new(Pos::from(s), Pos::from(s + 3));
+        // Reading `foo` finds the variable, calling it the function.
+        assert_eq!(Some(span(29)), binder.definition_at(Pos::from(45)));
+        assert_eq!(Some(span(3)), binder.definition_at(Pos::from(55)));
+    }
+
+    #[test]
+    fn bind_records_definitions_and_types() {
+        let source =
+            SourceText::new("fn id(n: Number): Number\n  n\nend\nlet x = id(1)\nprint x\n");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let span = |s: usize, e: usize| Span::new(Pos::from(s), Pos::from(e));
+        let number = Typ::Builtin(BuiltinType::Number);
+
+        // parameter use
+        assert_eq!(Some(span(6, 7)), binder.definition_at(Pos::from(27)));
+        assert_eq!(Some(number), binder.type_at(Pos::from(27)));
+        // function call
+        assert_eq!(Some(span(3, 5)), binder.definition_at(Pos::from(42)));
+        assert_eq!(
+            Some(Typ::Function(source.intern("id"))),
+            binder.type_at(Pos::from(42))
+        );
+        // variable use, and the declaration itself
+        assert_eq!(Some(span(37, 38)), binder.definition_at(Pos::from(53)));
+        assert_eq!(Some(span(37, 38)), binder.definition_at(Pos::from(37)));
+        assert_eq!(Some(number), binder.type_at(Pos::from(53)));
+        // builtin types have no definition, and literals have no type
+        assert_eq!(None, binder.definition_at(Pos::from(10)));
+        assert_eq!(None, binder.type_at(Pos::from(44)));
+    }
+
+    #[test]
+    fn bind_records_references() {
+        let source = SourceText::new(
+            "fn f(n: Number): Number\n  n * n\nend\nvar i = f(1)\ni = f(i)\nprint i\n",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let span = |s: usize| Span::new(Pos::from(s), Pos::from(s + 1));
+        let uses_of_i = vec![span(40), span(49), span(55), span(64)];
+        assert_eq!(uses_of_i, binder.references_at(Pos::from(55)));
+        assert_eq!(uses_of_i, binder.references_at(Pos::from(40)));
+        assert_eq!(
+            vec![span(3), span(44), span(53)],
+            binder.references_at(Pos::from(53))
+        );
+        assert_eq!(
+            vec![span(5), span(26), span(30)],
+            binder.references_at(Pos::from(30))
+        );
+        assert_eq!(3, binder.references().len());
+        assert!(binder.references_at(Pos::from(10)).is_empty());
+    }
+
+    #[test]
+    fn bind_infers_declaration_types() {
+        let source = SourceText::new("let x = 1\nvar y = x + 2\nprint y\n");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let number = Typ::Builtin(BuiltinType::Number);
+        let exprs = match bound.kind {
+            ExpressionKind::Sequence(exprs) => exprs,
+            other => panic!("expected sequence, found {:?}", other),
+        };
+        for expr in exprs.iter() {
+            assert_eq!(number, expr.typ);
+            if let ExpressionKind::Declaration(ref decl, _, _) = expr.kind {
+                assert_eq!(number, decl.ty);
+            }
+        }
+        assert_eq!(Some(number), binder.type_at(Pos::from(4)));
+    }
+
+    #[test]
+    fn bind_inserts_conversions() {
+        let source = SourceText::new("print 1 if 2 else 3");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+
+        let number = Typ::Builtin(BuiltinType::Number);
+        let literal = |n| {
+            Box::new(Expression::new(
+                ExpressionKind::Literal(Constant::Number(n)),
+                number,
+            ))
+        };
+        let cond = Expression::new(
+            ExpressionKind::Convert(literal(2)),
+            Typ::Builtin(BuiltinType::Bool),
+        );
+        let if_else = Expression::new(
+            ExpressionKind::IfThenElse(Box::new(cond), literal(1), literal(3)),
+            number,
+        );
+        let printee = Expression::new(
+            ExpressionKind::Convert(Box::new(if_else)),
+            Typ::Builtin(BuiltinType::String),
+        );
+        let print = Expression::new(ExpressionKind::Print(Box::new(printee)), number);
+        assert_eq!(
+            Expression::new(ExpressionKind::Sequence(vec![print]), number),
+            bound
+        );
+    }
+
+    #[test]
+    fn bind_reports_bad_conditions_and_type_values() {
+        let source = SourceText::new("while 'yes'\n  print Number\nend\n");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let messages: Vec<_> = binder
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            vec![
+                "Condition expression should be 'Bool' but is 'String'",
+                "'Number' is a type, not a value",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn bind_honours_allow_pragmas() {
+        let source = SourceText::new(
+            "#allow(unreachable_code)\nfn f(): Number\n  while true end\n  1\nend\nwhile true end\n#allow(unreachable_code)\nprint 2\nprint 3\n",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        assert!(binder.take_warnings().is_empty());
+
+        let source = SourceText::new("#allow(unreachable_code)\nprint 1\nwhile true end\nprint 2");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        assert_eq!(1, binder.take_warnings().len());
+    }
+
+    #[test]
+    fn bind_checks_attributes() {
+        let source = SourceText::new(
+            "#[allow(unreachable_code)]\nfn f(): Number\n  while true end\n  1\nend\n#[inline]\nlet _x = f()",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        assert!(binder.take_warnings().is_empty());
+        let diagnostics = binder.take_diagnostics();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Some(Code::InvalidAttribute), diagnostics[0].code);
+    }
+
+    #[test]
+    fn bind_builtin_functions() {
+        let source = SourceText::new("print to_number(to_string(1) + '0')");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        assert_eq!(Typ::Builtin(BuiltinType::Number), bound.typ);
+
+        let source = SourceText::new(
+            "var b: StringBuilder = string_builder()\nb = append(b, 'x')\nprint build_string(b)",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        assert_eq!(Typ::Builtin(BuiltinType::String), bound.typ);
+
+        let source = SourceText::new("let s = 'héllo'\nprint s[len(s) - byte_len(s) + 1]");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        assert_eq!(Typ::Builtin(BuiltinType::String), bound.typ);
+
+        let source = SourceText::new("print 'abc'['b']\nprint 1[0]");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(Some(Code::MismatchedTypes), diagnostics[0].code);
+        assert_eq!(Some(Code::Unsupported), diagnostics[1].code);
+
+        let source =
+            SourceText::new("fn to_string(b: Bool): String 'b' end\nprint to_string(true)");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn bind_entry_points() {
+        let entry_diagnostics = |text: &str| {
+            let source = SourceText::new(text);
+            let tree = syntax::SyntaxTree::parse(&source);
+            let mut binder = Binder::new(Scope::new()).with_entry("start".into());
+            binder.bind_tree(tree);
+            binder
+                .take_diagnostics()
+                .into_iter()
+                .map(|d| (d.code, d.message))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(
+            entry_diagnostics("fn helper(): Number 1 end\nfn start(): Number helper() end")
+                .is_empty()
+        );
+        assert_eq!(
+            vec![(
+                Some(Code::InvalidEntryPoint),
+                "Entry point 'start' is not defined".to_string()
+            )],
+            entry_diagnostics("fn main(): Number 0 end")
+        );
+        assert_eq!(
+            vec![(
+                Some(Code::InvalidEntryPoint),
+                "Entry point 'start' must take no parameters and return 'Number'".to_string()
+            )],
+            entry_diagnostics("fn start(n: Number): Number n end")
+        );
+        assert_eq!(
+            vec![(
+                Some(Code::InvalidEntryPoint),
+                "Top level code is never run when 'start' is the entry point".to_string()
+            )],
+            entry_diagnostics("fn start(): Number 0 end\nprint 1")
+        );
+    }
+
+    #[test]
+    fn bind_loop_control_against_enclosing_loops() {
+        let source = SourceText::new(
+            "outer: while true\n  while true\n    break outer\n    continue\n  end\nend",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+        struct Controls(Vec<(LoopControl, usize)>);
+        impl<'a> Visitor<'a> for Controls {
+            fn visit_loop_control(&mut self, control: LoopControl, depth: usize) {
+                self.0.push((control, depth));
+            }
+        }
+        let mut controls = Controls(Vec::new());
+        controls.visit_expression(&bound);
+        assert_eq!(
+            vec![(LoopControl::Break, 1), (LoopControl::Continue, 0)],
+            controls.0
+        );
+
+        let source = SourceText::new(
+            "break\na: while true\n  fn f(): Number\n    continue\n    1\n  end\n  break b\nend",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+        let diagnostics = binder
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| (d.code, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    Some(Code::InvalidLoopControl),
+                    "`break` outside of a loop".to_string()
+                ),
+                (
+                    Some(Code::InvalidLoopControl),
+                    "`continue` outside of a loop".to_string()
+                ),
+                (
+                    Some(Code::InvalidLoopControl),
+                    "No enclosing loop is labelled 'b'".to_string()
+                ),
+            ],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn bind_drops_disabled_items() {
+        let source = SourceText::new(
+            "#[cfg(target_os = 'linux')]\nfn name(): String 'linux' end\n#[cfg(target_os = 'windows')]\nfn name(): String missing end\n#[cfg(feature = 'x')]\nlet x = missing\nprint name()",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let cfg = CfgSet::for_target("x86_64-unknown-linux-gnu");
+        let mut binder = Binder::new(Scope::new()).with_cfg(cfg);
+        let bound = binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        match bound.kind {
+            ExpressionKind::Sequence(ref exprs) => assert_eq!(2, exprs.len()),
+            ref kind => panic!("expected a sequence, found {:?}", kind),
+        }
+
+        let source = SourceText::new("#[cfg(windows)]\nfn f(): Number 1 end\nprint f()");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let cfg = CfgSet::for_target("x86_64-unknown-linux-gnu");
+        let mut binder = Binder::new(Scope::new()).with_cfg(cfg);
+        binder.bind_tree(tree);
+        let diagnostics = binder.take_diagnostics();
+        assert_eq!(Some(Code::UndefinedName), diagnostics[0].code);
+    }
+
+    #[test]
+    fn bind_warns_about_unreachable_code() {
+        let source = SourceText::new(
+            "print 1\nwhile true\n  print 2\nend\nprint 3\nfn f(): Number 4 end\nprint 5\n",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        assert!(binder.take_diagnostics().is_empty());
+        let warnings = binder.take_warnings();
+        assert_eq!(1, warnings.len());
+        assert_eq!("Unreachable expression", warnings[0].message);
+        assert_eq!(Span::new(Pos::from(33), Pos::from(69)), warnings[0].span);
+    }
+
+    #[test]
+    fn bind_checks_all_paths_return() {
+        let source = SourceText::new(
+            "fn f(b: Bool): Number\n  'a' if b else 'b'\nend\n\
+             fn g(): Number\n  print 1\n  while false end\nend\n\
+             fn h(): String\nend\n\
+             fn i(): Number\n  while true end\nend\n\
+             fn j(b: Bool): Number\n  1 if b else (until b end)\nend\n\
+             fn k(): ()\n  2 * 3\nend\n",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let messages = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.span))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    "Function should return 'Number' but this branch produces 'String'",
+                    Span::new(Pos::from(24), Pos::from(41))
+                ),
+                (
+                    "Function should return 'Number' but this branch produces '()'",
+                    Span::new(Pos::from(73), Pos::from(88))
+                ),
+                (
+                    "Function should return 'String' but this branch produces '()'",
+                    Span::new(Pos::from(108), Pos::from(111))
+                ),
+                (
+                    "If and else have mismatched types. 'Number' and '()'",
+                    Span::new(Pos::from(172), Pos::from(197))
+                ),
+                (
+                    "Function should return 'Number' but this branch produces '()'",
+                    Span::new(Pos::from(185), Pos::from(196))
+                ),
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn bind_checks_definite_assignment() {
+        let text = "var a: Number\nvar b\nb = 1 if a > 0 else 2\n\
+             var c: Number\nlet d = (c = 1) if b > 0 else 2\nprint c\n\
+             var e: Bool\nwhile b < 10\n  e = true\n  b = b + 1\nend\nprint e\n\
+             let f: String\n";
+        let slice = |span: Span| &text[span.start().offset()..span.end().offset()];
+        let source = SourceText::new(text);
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let messages = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), slice(d.span)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("Use of possibly uninitialised variable 'a'", "a"),
+                ("Use of possibly uninitialised variable 'c'", "c"),
+                ("Use of possibly uninitialised variable 'e'", "e"),
+                ("Immutable variable 'f' must have an initialiser", "f"),
+            ],
+            messages
+        );
+        let mut inferred = binder
+            .take_symbol_spans()
+            .into_iter()
+            .filter_map(
+                |(span, sym)| {
+                    if slice(span) == "b" {
+                        Some(sym)
+                    } else {
+                        None
+                    }
+                },
+            );
+        assert_eq!(
+            Some(Symbol::Variable(
+                VarStyle::Mutable,
+                Typ::Builtin(BuiltinType::Number)
+            )),
+            inferred.next()
+        );
+    }
+
+    #[test]
+    fn bind_checks_operator_operands() {
+        let text = "print 'a' - 'b'\nprint true < false\nprint 1 + 'c'\nprint true == false";
+        let slice = |span: Span| &text[span.start().offset()..span.end().offset()];
+        let source = SourceText::new(text);
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let messages = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), slice(d.span)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    "No operator `-` accepts 'String' and 'String'. \
+                     Candidates are: 'Number - Number -> Number'",
+                    "'a' - 'b'"
+                ),
+                (
+                    "No operator `<` accepts 'Bool' and 'Bool'. \
+                     Candidates are: 'Number < Number -> Bool'",
+                    "true < false"
+                ),
+                (
+                    "No operator `+` accepts 'Number' and 'String'. \
+                     Candidates are: 'Number + Number -> Number', 'String + String -> String'",
+                    "1 + 'c'"
+                ),
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn bind_suggests_similar_names() {
+        let source = SourceText::new(
+            "var total = 1\nfn count(n: Number): Numbr\n  n\nend\nprint totl + zzz",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(messages.contains(&"Reference to undefined type 'Numbr'. Did you mean 'Number'?"));
+        assert!(messages.contains(&"Can't find 'totl' in this scope. Did you mean 'total'?"));
+        assert!(messages.contains(&"Can't find 'zzz' in this scope"));
+
+        let fixes = diagnostics
+            .iter()
+            .filter_map(|d| d.fix.as_ref())
+            .map(|f| {
+                (
+                    source.slice(f.span.start(), f.span.end()),
+                    f.replacement.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("Numbr", "Number"), ("totl", "total")], fixes);
+    }
+
+    #[test]
+    fn bind_suppresses_follow_on_errors() {
+        let source = SourceText::new(
+            "let a = nope\nlet b: Number = a\nprint a(1)\nprint 1 if a else 2\nvar c = ''\nc = a\nprint (a if true else 'x') + 1\nlet d: Bool = 1\nlet e: Number = d",
+        );
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Can't find 'nope' in this scope",
+                "Initialiser doesn't match declaration type for 'd'",
+                "Initialiser doesn't match declaration type for 'e'",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn bind_labels_mismatches() {
+        let text = "let a = 1\na = 2\nlet b: Bool = 'yes'\nvar c = true\nc = 3";
+        let slice = |span: Span| &text[span.start().offset()..span.end().offset()];
+        let source = SourceText::new(text);
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        binder.bind_tree(tree);
+
+        let diagnostics = binder.take_diagnostics();
+        let labels = diagnostics
+            .iter()
+            .map(|d| {
+                let labels = d
+                    .labels
+                    .iter()
+                    .map(|l| (slice(l.span), l.message.as_str()))
+                    .collect::<Vec<_>>();
+                (d.code, labels, d.notes.clone())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    Some(Code::AssignToImmutable),
+                    vec![("a", "'a' is declared here")],
+                    vec!["declare 'a' with `var` to allow assignment".to_string()]
+                ),
+                (
+                    Some(Code::MismatchedTypes),
+                    vec![
+                        ("Bool", "expected 'Bool' because of this"),
+                        ("'yes'", "this is 'String'")
+                    ],
+                    vec![]
+                ),
+                (
+                    Some(Code::MismatchedTypes),
+                    vec![
+                        ("3", "this is 'Number'"),
+                        ("c", "'c' is declared as 'Bool' here")
+                    ],
+                    vec![]
+                ),
+            ],
+            labels
+        );
+    }
+
+    #[test]
+    fn bind_with_registered_operators() {
+        let string = Typ::Builtin(BuiltinType::String);
+        let number = Typ::Builtin(BuiltinType::Number);
+        let mut operators = OperatorTable::builtin();
+        operators.register(SemOp::new(InfixOp::Mul, string, number, string));
+
+        let source = SourceText::new("fn f(s: String): String\n  s * 3\nend");
+        let tree = syntax::SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new()).with_operators(operators);
+        binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+    }
+
+    fn bind_built(b: TreeBuilder<'_>, root: syntax::ExprId) -> (Typ, Vec<Option<Code>>) {
+        let tree = b.finish(root);
+        let mut binder = Binder::new(Scope::new());
+        let bound = binder.bind_tree(tree);
+        let codes = binder.take_diagnostics().iter().map(|d| d.code).collect();
+        (bound.typ, codes)
+    }
+
+    #[test]
+    fn bind_built_function_calls() {
+        let source = SourceText::new("");
+        let b = TreeBuilder::new(&source);
+        let double = b.func(
+            "double",
+            &[("n", "Number")],
+            "Number",
+            vec![b.infix(b.ident("n"), InfixOp::Mul, b.lit_num(2))],
+        );
+        let call = b.call(b.ident("double"), vec![b.lit_num(21)]);
+        let bad_call = b.call(b.ident("double"), vec![b.lit_str("21")]);
+        let root = b.sequence(vec![double, b.print(call), bad_call]);
+
+        assert_eq!(
+            (
+                Typ::Builtin(BuiltinType::Number),
+                vec![Some(Code::MismatchedTypes)]
+            ),
+            bind_built(b, root)
+        );
+    }
+
+    #[test]
+    fn bind_built_declarations_and_assignments() {
+        let source = SourceText::new("");
+        let b = TreeBuilder::new(&source);
+        let total = b.declare(VarStyle::Mutable, "total", Some("Number"), None);
+        let assign = b.infix(b.ident("total"), InfixOp::Assign, b.lit_num(1));
+        let fixed = b.declare(VarStyle::Immutable, "fixed", None, Some(b.lit_bool(true)));
+        let reassign = b.infix(b.ident("fixed"), InfixOp::Assign, b.lit_bool(false));
+        let root = b.sequence(vec![total, assign, fixed, reassign]);
+
+        assert_eq!(
+            (
+                Typ::Builtin(BuiltinType::Bool),
+                vec![Some(Code::AssignToImmutable)]
+            ),
+            bind_built(b, root)
+        );
+    }
+
+    #[test]
+    fn bind_built_conditions_and_loops() {
+        let source = SourceText::new("");
+        let b = TreeBuilder::new(&source);
+        let count = b.declare(VarStyle::Mutable, "count", None, Some(b.lit_num(0)));
+        let body = b.infix(
+            b.ident("count"),
+            InfixOp::Assign,
+            b.infix(b.ident("count"), InfixOp::Add, b.lit_num(1)),
+        );
+        let cond = b.infix(b.ident("count"), InfixOp::Lt, b.lit_num(10));
+        let counter = b.while_loop(cond, vec![body]);
+        let choice = b.if_else(b.lit_str("yes"), b.lit_num(1), b.group(b.lit_num(2)));
+        let root = b.sequence(vec![count, counter, b.print(choice)]);
+
+        assert_eq!(
+            (
+                Typ::Builtin(BuiltinType::Number),
+                vec![Some(Code::InvalidCondition)]
+            ),
+            bind_built(b, root)
+        );
+    }
+
+    #[test]
+    fn bind_malformed_types() {
+        let source = SourceText::new("");
+        let mut binder = Binder::new(Scope::new());
+
+        let not_a_word = TypeRef::simple(Token::new(TokenKind::Plus));
+        assert_eq!(Typ::Error, binder.bind_type(&not_a_word, &source));
+        assert_eq!(Typ::Error, binder.bind_type(&TypeRef::missing(), &source));
+
+        let codes: Vec<_> = binder.take_diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(vec![Some(Code::ExpectedType)], codes);
+    }
 }
@@ -0,0 +1,267 @@
+//! Conditional Compilation
+//!
+//! Items annotated with `#[cfg(...)]` are only compiled if every
+//! predicate in the attribute holds. A predicate is either a bare
+//! flag, such as `#[cfg(unix)]`, a key and value, such as
+//! `#[cfg(target_os = 'linux')]`, or a combination of predicates with
+//! `not(...)`, `any(...)`, or `all(...)`. The binder drops disabled
+//! items before they are declared, so they can refer to things which
+//! don't exist on other platforms.
+//!
+//! Like other attributes `cfg` can only be applied to functions and
+//! declarations. Other statements, such as `print`, can be made
+//! conditional by moving them into a function.
+
+use crate::low_loader::targets;
+use crate::syntax::text::SourceText;
+use crate::syntax::{Attribute, AttributeArg, Literal, TokenKind};
+use std::collections::BTreeSet;
+
+/// Operating systems which are part of the `unix` family
+const UNIX_OSES: &[&str] = &[
+    "linux",
+    "darwin",
+    "macos",
+    "ios",
+    "freebsd",
+    "netbsd",
+    "openbsd",
+    "dragonfly",
+    "solaris",
+    "haiku",
+];
+
+/// Conditional Compilation Configuration
+///
+/// The set of flags and key-value pairs which `cfg` attributes are
+/// checked against. Keys may have more than one value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CfgSet {
+    /// The enabled options, a name and optional value
+    options: BTreeSet<(String, Option<String>)>,
+}
+
+impl CfgSet {
+    /// Create an Empty Configuration
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create the Configuration for a Target Triple
+    ///
+    /// Sets `target` to the whole triple along with `target_arch`,
+    /// `target_vendor`, `target_os` and `target_env` from its
+    /// components. Unix-like targets also set the `unix` flag and
+    /// Windows targets the `windows` flag.
+    pub fn for_target(triple: &str) -> Self {
+        let normalised = targets::normalise_triple(triple);
+        let mut parts = normalised.split('-');
+        let mut cfg = CfgSet::new().with_value("target", triple);
+        let keys = ["target_arch", "target_vendor", "target_os", "target_env"];
+        for (key, part) in keys.iter().zip(&mut parts) {
+            let value = if *key == "target_os" {
+                // Drop any version, e.g. `macosx10.15`
+                part.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+            } else {
+                part
+            };
+            if !value.is_empty() {
+                cfg = cfg.with_value(*key, value);
+            }
+        }
+        let os = cfg.value("target_os").unwrap_or_default().to_string();
+        if UNIX_OSES.contains(&&os[..]) {
+            cfg = cfg.with_flag("unix");
+        } else if os == "windows" {
+            cfg = cfg.with_flag("windows");
+        }
+        cfg
+    }
+
+    /// Create the Configuration for the Host
+    pub fn host() -> Self {
+        CfgSet::for_target(&targets::get_default_triple())
+    }
+
+    /// Enable a Flag
+    pub fn with_flag<S: Into<String>>(mut self, flag: S) -> Self {
+        self.options.insert((flag.into(), None));
+        self
+    }
+
+    /// Add a Value for a Key
+    pub fn with_value<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.options.insert((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Add an Option from the Command Line
+    ///
+    /// Options are either a flag name, `name`, or a key and value,
+    /// `key=value`. Quotes around the value are ignored.
+    pub fn with_option(self, option: &str) -> Self {
+        match option.find('=') {
+            Some(idx) => {
+                let value = option[idx + 1..]
+                    .trim()
+                    .trim_matches(|c| c == '\'' || c == '"');
+                self.with_value(option[..idx].trim(), value)
+            }
+            None => self.with_flag(option.trim()),
+        }
+    }
+
+    /// Get the First Value for a Key
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(name, value)| name == key && value.is_some())
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Is the Option Set?
+    ///
+    /// Checks a single predicate. A flag is set if it has been
+    /// enabled, and a key-value pair if the key has that value.
+    pub fn is_set(&self, name: &str, value: Option<&str>) -> bool {
+        self.options
+            .iter()
+            .any(|(n, v)| n == name && v.as_deref() == value)
+    }
+
+    /// Are the Attributes Enabled?
+    ///
+    /// Returns false if any `cfg` attribute in the list has a
+    /// predicate which doesn't hold. Items without `cfg` attributes
+    /// are always enabled.
+    pub fn is_enabled(&self, attrs: &[Attribute], source: &SourceText) -> bool {
+        attrs
+            .iter()
+            .filter(|attr| attr.name(source) == "cfg")
+            .flat_map(|attr| attr.args())
+            .all(|arg| self.holds(arg, source))
+    }
+
+    /// Does a Predicate Hold?
+    ///
+    /// `not(p)` holds if `p` doesn't, `any(...)` if any of its
+    /// predicates hold, and `all(...)` if every one does. Other
+    /// predicates are options, which hold if they are set.
+    pub fn holds(&self, arg: &AttributeArg, source: &SourceText) -> bool {
+        if arg.args.is_none() {
+            return self.is_set(arg.name(source), arg_value(arg, source).as_deref());
+        }
+        let mut predicates = arg.args();
+        match arg.name(source) {
+            "not" => !predicates.all(|p| self.holds(p, source)),
+            "any" => predicates.any(|p| self.holds(p, source)),
+            "all" => predicates.all(|p| self.holds(p, source)),
+            _ => false,
+        }
+    }
+}
+
+/// Get the Text of an Attribute Argument's Value
+///
+/// String literals are unquoted, and words are used as they appear
+/// in the source.
+fn arg_value(arg: &AttributeArg, source: &SourceText) -> Option<String> {
+    let value = arg.value()?;
+    Some(match value.kind {
+        TokenKind::Literal(Literal::RawString(ref s)) => s.clone(),
+        TokenKind::Literal(ref lit) => lit.to_string(),
+        _ => {
+            let span = value.span();
+            source.slice(span.start(), span.end()).to_string()
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::{Expression, SyntaxTree};
+
+    #[test]
+    fn target_configuration() {
+        let linux = CfgSet::for_target("x86_64-unknown-linux-gnu");
+        assert_eq!(Some("x86_64-unknown-linux-gnu"), linux.value("target"));
+        assert_eq!(Some("x86_64"), linux.value("target_arch"));
+        assert_eq!(Some("linux"), linux.value("target_os"));
+        assert_eq!(Some("gnu"), linux.value("target_env"));
+        assert!(linux.is_set("unix", None));
+        assert!(!linux.is_set("windows", None));
+
+        let windows = CfgSet::for_target("x86_64-pc-windows-msvc");
+        assert_eq!(Some("windows"), windows.value("target_os"));
+        assert!(windows.is_set("windows", None));
+
+        let mac = CfgSet::for_target("aarch64-apple-macosx10.15");
+        assert_eq!(Some("macosx"), mac.value("target_os"));
+        assert_eq!(Some("apple"), mac.value("target_vendor"));
+    }
+
+    #[test]
+    fn command_line_options() {
+        let cfg = CfgSet::new()
+            .with_option("debug")
+            .with_option("feature='fast'")
+            .with_option("feature = slow");
+        assert!(cfg.is_set("debug", None));
+        assert!(cfg.is_set("feature", Some("fast")));
+        assert!(cfg.is_set("feature", Some("slow")));
+        assert!(!cfg.is_set("feature", None));
+    }
+
+    #[test]
+    fn attributes_enabled() {
+        let cfg = CfgSet::for_target("x86_64-unknown-linux-gnu").with_flag("debug");
+        let enabled = |src: &str| {
+            let source = SourceText::new(src);
+            let tree = SyntaxTree::parse(&source);
+            assert!(!tree.has_diagnostics(), "{:?}", tree.diagnostics());
+            let item = match tree.root() {
+                Expression::Sequence(items) => &tree[items[0]],
+                root => root,
+            };
+            cfg.is_enabled(item.attributes(), &source)
+        };
+        assert!(enabled("let a = 1"));
+        assert!(enabled("#[cfg(unix)] let a = 1"));
+        assert!(enabled("#[cfg(target_os = 'linux', debug)] let a = 1"));
+        assert!(enabled(
+            "#[cfg(target_arch = x86_64)]\n#[cfg(debug)] let a = 1"
+        ));
+        assert!(!enabled("#[cfg(windows)] let a = 1"));
+        assert!(!enabled(
+            "#[cfg(unix)] #[cfg(target_os = 'macos')] let a = 1"
+        ));
+        assert!(!enabled("#[cfg(target_os)] let a = 1"));
+    }
+
+    #[test]
+    fn combined_predicates() {
+        let cfg = CfgSet::for_target("x86_64-unknown-linux-gnu");
+        let enabled = |src: &str| {
+            let source = SourceText::new(src);
+            let tree = SyntaxTree::parse(&source);
+            assert!(!tree.has_diagnostics(), "{:?}", tree.diagnostics());
+            let item = match tree.root() {
+                Expression::Sequence(items) => &tree[items[0]],
+                root => root,
+            };
+            cfg.is_enabled(item.attributes(), &source)
+        };
+        assert!(enabled("#[cfg(not(windows))] let a = 1"));
+        assert!(!enabled("#[cfg(not(unix))] let a = 1"));
+        assert!(enabled(
+            "#[cfg(any(windows, target_os = 'linux'))] let a = 1"
+        ));
+        assert!(!enabled("#[cfg(any(windows, debug))] let a = 1"));
+        assert!(!enabled("#[cfg(any())] let a = 1"));
+        assert!(enabled("#[cfg(all(unix, not(any(debug))))] let a = 1"));
+        assert!(!enabled("#[cfg(all(unix, debug))] let a = 1"));
+        assert!(enabled("#[cfg(all())] let a = 1"));
+        assert!(!enabled("#[cfg(unix(linux))] let a = 1"));
+    }
+}
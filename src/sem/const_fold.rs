@@ -0,0 +1,155 @@
+//! Constant Folding
+//!
+//! Evaluates the parts of a bound tree which only depend on literal
+//! values so that they don't need to be computed at runtime. Folding
+//! happens before lowering so even unoptimised output is free of
+//! trivially computable expressions.
+//!
+//! Folding must not change the meaning of the program. Operations
+//! which could fail at runtime, such as division by zero or
+//! arithmetic which overflows, are left unfolded so that the lowered
+//! code can handle them.
+
+use super::tree::{Expression, ExpressionKind};
+use super::visit::{self, Folder};
+use crate::syntax::{Constant, InfixOp, PrefixOp};
+
+/// Fold the Constants in a Tree
+///
+/// Returns the tree with each constant expression replaced by its
+/// value.
+pub fn fold_constants(expr: Expression) -> Expression {
+    ConstFolder.fold_expression(expr)
+}
+
+/// Folder which Evaluates Constant Expressions
+struct ConstFolder;
+
+impl Folder for ConstFolder {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        let expr = visit::fold_children(self, expr);
+        let typ = expr.typ;
+        let span = expr.span;
+        let value = match &expr.kind {
+            ExpressionKind::Prefix(op, inner) => literal(inner).and_then(|v| fold_prefix(*op, v)),
+            ExpressionKind::Infix(lhs, op, rhs) => match (literal(lhs), literal(rhs)) {
+                (Some(l), Some(r)) => fold_infix(l, *op, r),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(value) = value {
+            return Expression::new(ExpressionKind::Literal(value), typ).with_span(span);
+        }
+        match expr.kind {
+            // Only one branch of an `if` with a constant condition
+            // can ever be evaluated.
+            ExpressionKind::IfThenElse(cond, if_true, if_false) => match literal(&cond) {
+                Some(Constant::Bool(true)) => *if_true,
+                Some(Constant::Bool(false)) => *if_false,
+                _ => Expression::new(ExpressionKind::IfThenElse(cond, if_true, if_false), typ)
+                    .with_span(span),
+            },
+            kind => Expression::new(kind, typ).with_span(span),
+        }
+    }
+}
+
+/// Get the Value of a Literal Expression
+fn literal(expr: &Expression) -> Option<&Constant> {
+    match &expr.kind {
+        ExpressionKind::Literal(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Fold a Prefix Operator Applied to a Constant
+fn fold_prefix(op: PrefixOp, value: &Constant) -> Option<Constant> {
+    Some(match (op, value) {
+        (PrefixOp::Identity, Constant::Number(n)) => Constant::Number(*n),
+        (PrefixOp::Negate, Constant::Number(n)) => Constant::Number(n.wrapping_neg()),
+        (PrefixOp::Not, Constant::Number(n)) => Constant::Number(!n),
+        (PrefixOp::Not, Constant::Bool(b)) => Constant::Bool(!b),
+        _ => return None,
+    })
+}
+
+/// Fold an Infix Operator Applied to Two Constants
+///
+/// Returns `None` if the operation can't be evaluated at compile
+/// time.
+fn fold_infix(lhs: &Constant, op: InfixOp, rhs: &Constant) -> Option<Constant> {
+    use crate::syntax::Constant::*;
+    Some(match (lhs, rhs) {
+        (Number(l), Number(r)) => match op {
+            InfixOp::Add => Number(l.checked_add(*r)?),
+            InfixOp::Sub => Number(l.checked_sub(*r)?),
+            InfixOp::Mul => Number(l.checked_mul(*r)?),
+            InfixOp::Div => Number(l.checked_div(*r)?),
+            InfixOp::Eq => Bool(l == r),
+            InfixOp::NotEq => Bool(l != r),
+            InfixOp::Lt => Bool(l < r),
+            InfixOp::LtEq => Bool(l <= r),
+            InfixOp::Gt => Bool(l > r),
+            InfixOp::GtEq => Bool(l >= r),
+            InfixOp::Assign => return None,
+        },
+        (Bool(l), Bool(r)) => match op {
+            InfixOp::Eq => Bool(l == r),
+            InfixOp::NotEq => Bool(l != r),
+            _ => return None,
+        },
+        (String(l), String(r)) if op == InfixOp::Add => String(format!("{}{}", l, r)),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Binder, Scope};
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use crate::syntax::SyntaxTree;
+
+    fn bind(source: &str) -> Expression {
+        let source = SourceText::new(source);
+        let tree = SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let expr = binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+        expr
+    }
+
+    fn assert_folds_to(source: &str, expected: &str) {
+        assert_eq!(fold_constants(bind(expected)), fold_constants(bind(source)));
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        assert_eq!(bind("print 7"), fold_constants(bind("print 1 + 2 * 3")));
+        assert_folds_to("print -(10 - 4) / 2", "print -3");
+    }
+
+    #[test]
+    fn fold_comparisons_and_prefixes() {
+        assert_folds_to("print 1 < 2", "print true");
+        assert_folds_to("print !(4 >= 5)", "print true");
+        assert_folds_to("print true != false", "print true");
+        assert_folds_to("print +3", "print 3");
+    }
+
+    #[test]
+    fn fold_strings_and_conditionals() {
+        assert_folds_to("print 'hello ' + 'world'", "print 'hello world'");
+        assert_folds_to("print 1 if 2 > 3 else 4", "print 4");
+    }
+
+    #[test]
+    fn leave_runtime_values_unfolded() {
+        let source = "var i = 1\nprint i + 2 * 3\nprint 1 / 0\nprint 9223372036854775807 + 1";
+        assert_eq!(
+            bind("var i = 1\nprint i + 6\nprint 1 / 0\nprint 9223372036854775807 + 1"),
+            fold_constants(bind(source))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Type Conversions
+//!
+//! Holds the table of implicit conversions between types. The binder
+//! uses this to convert values where the language allows it, such as
+//! using a `Number` as a condition, or printing a `Bool`. Each
+//! conversion is made explicit in the bound tree with an
+//! `ExpressionKind::Convert` node so that later passes don't need to
+//! know which conversions are allowed.
+
+use super::tree::{Expression, ExpressionKind};
+use super::types::{BuiltinType, Typ};
+
+/// Implicit Conversions
+///
+/// Each entry is a pair of the type being converted from and the type
+/// it is converted to.
+const CONVERSIONS: &[(BuiltinType, BuiltinType)] = &[
+    (BuiltinType::Number, BuiltinType::Bool),
+    (BuiltinType::Number, BuiltinType::String),
+    (BuiltinType::Bool, BuiltinType::String),
+];
+
+/// Check if a Type Can be Converted
+///
+/// Returns `true` if values of type `from` can be implicitly
+/// converted to type `to`. Every type can be converted to itself.
+pub fn can_convert(from: Typ, to: Typ) -> bool {
+    if from == to {
+        return true;
+    }
+    match (from, to) {
+        (Typ::Builtin(from), Typ::Builtin(to)) => CONVERSIONS.contains(&(from, to)),
+        _ => false,
+    }
+}
+
+/// Convert an Expression
+///
+/// Wraps the expression in a conversion to the type `to`. If the
+/// expression already has the given type it is returned unchanged.
+///
+/// # Errors
+///
+/// If there is no conversion from the expression's type then the
+/// expression is returned unchanged as the error.
+// The error is the expression handed back to the caller, so boxing it
+// would only add an allocation.
+#[allow(clippy::result_large_err)]
+pub fn convert(expr: Expression, to: Typ) -> Result<Expression, Expression> {
+    if expr.typ == to {
+        Ok(expr)
+    } else if can_convert(expr.typ, to) {
+        let span = expr.span;
+        Ok(Expression::new(ExpressionKind::Convert(Box::new(expr)), to).with_span(span))
+    } else {
+        Err(expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversion_table() {
+        let number = Typ::Builtin(BuiltinType::Number);
+        let bool_typ = Typ::Builtin(BuiltinType::Bool);
+        let string = Typ::Builtin(BuiltinType::String);
+
+        assert!(can_convert(number, number));
+        assert!(can_convert(number, bool_typ));
+        assert!(can_convert(bool_typ, string));
+        assert!(!can_convert(string, number));
+        assert!(!can_convert(bool_typ, number));
+        assert!(!can_convert(Typ::Unit, string));
+        assert!(!can_convert(Typ::Error, bool_typ));
+    }
+
+    #[test]
+    fn convert_wraps_expressions() {
+        let number = Typ::Builtin(BuiltinType::Number);
+        let string = Typ::Builtin(BuiltinType::String);
+        let value = || Expression::new(ExpressionKind::Identifier("n".into()), number);
+
+        assert_eq!(Ok(value()), convert(value(), number));
+        assert_eq!(
+            Ok(Expression::new(
+                ExpressionKind::Convert(Box::new(value())),
+                string
+            )),
+            convert(value(), string)
+        );
+        assert_eq!(Err(value()), convert(value(), Typ::Unit));
+    }
+}
@@ -0,0 +1,210 @@
+//! Type Inference
+//!
+//! Types in the bound tree are inferred by unification. When the
+//! binder can't yet tell what type an expression has it allocates a
+//! fresh type variable in the `TypeTable`. Each constraint between two
+//! types is then recorded by unifying them, binding type variables to
+//! the types they must have. Once a bind is complete the whole tree is
+//! resolved so that no type variables remain.
+
+use super::tree::{Expression, ExpressionKind, FnDecl, VarDecl};
+use super::types::Typ;
+use super::visit::{self, Folder};
+
+/// Type Mismatch
+///
+/// Returned when two types can't be unified. Contains both types
+/// resolved as far as possible.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TypeMismatch {
+    /// The type which was required
+    pub expected: Typ,
+    /// The type which was found instead
+    pub found: Typ,
+}
+
+impl TypeMismatch {
+    /// Check if Either Type is an Error
+    ///
+    /// Errors have already been reported, so a mismatch involving
+    /// one needs no further explanation.
+    pub fn has_error(&self) -> bool {
+        self.expected == Typ::Error || self.found == Typ::Error
+    }
+}
+
+/// Type Variable Table
+///
+/// Holds the type each type variable is bound to, if it has been
+/// bound yet.
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    vars: Vec<Option<Typ>>,
+}
+
+impl TypeTable {
+    /// Create an Empty Type Table
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a Fresh Type Variable
+    ///
+    /// The returned variable isn't bound to any type until it is
+    /// unified with one.
+    pub fn fresh(&mut self) -> Typ {
+        self.vars.push(None);
+        Typ::Var(self.vars.len() as u32 - 1)
+    }
+
+    /// Resolve a Type
+    ///
+    /// Follows type variable bindings until either a concrete type
+    /// or an unbound variable is found.
+    pub fn resolve(&self, typ: Typ) -> Typ {
+        match typ {
+            Typ::Var(var) => match self.vars[var as usize] {
+                Some(bound) => self.resolve(bound),
+                None => typ,
+            },
+            _ => typ,
+        }
+    }
+
+    /// Unify Two Types
+    ///
+    /// Checks that two types are the same, binding any unbound type
+    /// variables so that they are. On success the unified type is
+    /// returned.
+    ///
+    /// # Errors
+    ///
+    /// If the types are different concrete types then they can't be
+    /// unified and a `TypeMismatch` is returned.
+    pub fn unify(&mut self, expected: Typ, found: Typ) -> Result<Typ, TypeMismatch> {
+        let expected = self.resolve(expected);
+        let found = self.resolve(found);
+        match (expected, found) {
+            _ if expected == found => Ok(expected),
+            (Typ::Var(var), _) => {
+                self.vars[var as usize] = Some(found);
+                Ok(found)
+            }
+            (_, Typ::Var(var)) => {
+                self.vars[var as usize] = Some(expected);
+                Ok(expected)
+            }
+            _ => Err(TypeMismatch { expected, found }),
+        }
+    }
+
+    /// Check if a Type is Fully Resolved
+    pub fn is_resolved(&self, typ: Typ) -> bool {
+        !matches!(self.resolve(typ), Typ::Var(_))
+    }
+
+    /// Finalise a Type
+    ///
+    /// Resolves the type. Any type variable which is still unbound is
+    /// replaced with `Typ::Error`, so the binder must already have
+    /// raised a diagnostic for it.
+    pub fn finalise(&self, typ: Typ) -> Typ {
+        match self.resolve(typ) {
+            Typ::Var(_) => Typ::Error,
+            resolved => resolved,
+        }
+    }
+
+    /// Finalise the Types in a Tree
+    ///
+    /// Replaces every type in the tree with its final type.
+    pub fn finalise_tree(&self, expr: Expression) -> Expression {
+        Finaliser(self).fold_expression(expr)
+    }
+}
+
+/// Folder which Finalises the Types in a Tree
+struct Finaliser<'a>(&'a TypeTable);
+
+impl<'a> Finaliser<'a> {
+    fn finalise_decl(&self, decl: VarDecl) -> VarDecl {
+        VarDecl {
+            ty: self.0.finalise(decl.ty),
+            ..decl
+        }
+    }
+}
+
+impl<'a> Folder for Finaliser<'a> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        let folded = visit::fold_children(self, expr);
+        let kind = match folded.kind {
+            ExpressionKind::Declaration(decl, is_mut, init) => {
+                ExpressionKind::Declaration(self.finalise_decl(decl), is_mut, init)
+            }
+            kind => kind,
+        };
+        Expression::new(kind, self.0.finalise(folded.typ)).with_span(folded.span)
+    }
+
+    fn fold_function(&mut self, func: FnDecl) -> FnDecl {
+        FnDecl {
+            ret_ty: self.0.finalise(func.ret_ty),
+            params: func
+                .params
+                .into_iter()
+                .map(|p| self.finalise_decl(p))
+                .collect(),
+            body: Box::new(self.fold_expression(*func.body)),
+            ..func
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::BuiltinType;
+    use super::*;
+
+    #[test]
+    fn unify_binds_variables() {
+        let number = Typ::Builtin(BuiltinType::Number);
+        let mut table = TypeTable::new();
+        let a = table.fresh();
+        let b = table.fresh();
+        assert_ne!(a, b);
+        assert!(!table.is_resolved(a));
+
+        assert_eq!(Ok(b), table.unify(a, b));
+        assert_eq!(Ok(number), table.unify(number, a));
+        assert!(table.is_resolved(a));
+        assert_eq!(number, table.resolve(b));
+        assert_eq!(number, table.finalise(a));
+    }
+
+    #[test]
+    fn unify_concrete_mismatch() {
+        let number = Typ::Builtin(BuiltinType::Number);
+        let string = Typ::Builtin(BuiltinType::String);
+        let mut table = TypeTable::new();
+        let var = table.fresh();
+        table.unify(var, string).unwrap();
+
+        assert_eq!(Ok(Typ::Unit), table.unify(Typ::Unit, Typ::Unit));
+        assert_eq!(
+            Err(TypeMismatch {
+                expected: number,
+                found: string
+            }),
+            table.unify(number, var)
+        );
+    }
+
+    #[test]
+    fn finalise_unbound_variable() {
+        let mut table = TypeTable::new();
+        let var = table.fresh();
+        assert_eq!(Typ::Error, table.finalise(var));
+        assert_eq!(Typ::Unit, table.finalise(Typ::Unit));
+    }
+}
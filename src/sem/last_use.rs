@@ -0,0 +1,283 @@
+//! Last Use Analysis
+//!
+//! Finds the reads of reference counted variables, such as `String`s,
+//! which are the last use of the variable and marks them as moves.
+//! Lowering retains a value each time it is copied out of a variable,
+//! and releases the variable when its scope ends. A move hands the variable's own
+//! reference over instead, saving both.
+//!
+//! A read can only be a move if nothing after it uses the variable,
+//! and if it is certain to run whenever the end of the variable's
+//! scope is reached. Reads inside a loop, or in one branch of an
+//! `if`, which is nested deeper than the variable's declaration are
+//! never moves.
+
+use super::tree::{Expression, ExpressionKind, FnDecl, VarDecl};
+use super::visit::{self, Folder, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/// Mark the Last Uses in a Tree
+///
+/// Replaces each identifier which is the last use of a reference
+/// counted variable with a `Move`.
+pub fn mark_moves(expr: Expression) -> Expression {
+    let mut uses = LastUses::default();
+    uses.visit_expression(&expr);
+    let moves = uses
+        .variables
+        .into_iter()
+        .filter_map(|variable| variable.last_read)
+        .collect();
+    MarkMoves { moves, reads: 0 }.fold_expression(expr)
+}
+
+/// A Variable Tracked by the Analysis
+struct Variable {
+    /// The region the variable was declared in
+    region: usize,
+    /// The number of the variable's last use, if it was a read which
+    /// can be moved
+    last_read: Option<usize>,
+}
+
+/// Visitor to Find the Last Use of Each Variable
+///
+/// Identifier reads are numbered in the order they are visited,
+/// which is the same order `MarkMoves` folds them in.
+///
+/// The tree is split into regions. Each function body, loop
+/// condition, loop body, and branch of an `if` is a new region. A
+/// read is only movable if it is in the same region as the
+/// variable's declaration.
+#[derive(Default)]
+struct LastUses<'a> {
+    /// The variable each visible name refers to. Names of variables
+    /// which aren't reference counted map to `None`.
+    scopes: Vec<HashMap<&'a str, Option<usize>>>,
+    /// The reference counted variables found so far
+    variables: Vec<Variable>,
+    /// The region being visited
+    region: usize,
+    /// The number of regions entered so far
+    regions: usize,
+    /// The number of reads visited so far
+    reads: usize,
+}
+
+impl<'a> LastUses<'a> {
+    /// Look Up the Reference Counted Variable a Name Refers To
+    fn lookup(&self, id: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(id))
+            .copied()
+            .flatten()
+    }
+
+    /// Declare a Variable in the Current Scope
+    fn declare(&mut self, decl: &'a VarDecl) {
+        let variable = if decl.ty.is_reference_counted() {
+            self.variables.push(Variable {
+                region: self.region,
+                last_read: None,
+            });
+            Some(self.variables.len() - 1)
+        } else {
+            None
+        };
+        if self.scopes.is_empty() {
+            self.scopes.push(HashMap::new());
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(&decl.ident[..], variable);
+        }
+    }
+
+    /// Visit an Expression in a New Region and Scope
+    fn visit_region(&mut self, expr: &'a Expression) {
+        self.regions += 1;
+        let outer = mem::replace(&mut self.region, self.regions);
+        self.scopes.push(HashMap::new());
+        self.visit_expression(expr);
+        self.scopes.pop();
+        self.region = outer;
+    }
+}
+
+impl<'a> Visitor<'a> for LastUses<'a> {
+    fn visit_identifier(&mut self, id: &'a str) {
+        let read = self.reads;
+        self.reads += 1;
+        if let Some(index) = self.lookup(id) {
+            let region = self.region;
+            let variable = &mut self.variables[index];
+            variable.last_read = Some(read).filter(|_| variable.region == region);
+        }
+    }
+
+    fn visit_call(&mut self, callee: &'a Expression, args: &'a [Expression]) {
+        // A called name refers to a function rather than a variable,
+        // even if a variable shares its name.
+        if !is_named_callee(callee) {
+            self.visit_expression(callee);
+        }
+        visit::walk_expressions(self, args);
+    }
+
+    fn visit_assignment(&mut self, id: &'a str, value: &'a Expression) {
+        self.visit_expression(value);
+        if let Some(index) = self.lookup(id) {
+            self.variables[index].last_read = None;
+        }
+    }
+
+    fn visit_if_else(
+        &mut self,
+        cond: &'a Expression,
+        if_true: &'a Expression,
+        if_false: &'a Expression,
+    ) {
+        self.visit_expression(cond);
+        self.visit_region(if_true);
+        self.visit_region(if_false);
+    }
+
+    fn visit_function(&mut self, func: &'a FnDecl) {
+        // Functions can't see the variables of the scope they are
+        // declared in, only their own parameters.
+        let outer_scopes = mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        self.regions += 1;
+        let outer_region = mem::replace(&mut self.region, self.regions);
+        for param in func.params.iter() {
+            self.declare(param);
+        }
+        self.visit_expression(&func.body);
+        self.region = outer_region;
+        self.scopes = outer_scopes;
+    }
+
+    fn visit_loop(&mut self, cond: &'a Expression, body: &'a Expression) {
+        self.visit_region(cond);
+        self.visit_region(body);
+    }
+
+    fn visit_declaration(&mut self, decl: &'a VarDecl, is_mut: bool, init: Option<&'a Expression>) {
+        visit::walk_declaration(self, decl, is_mut, init);
+        self.declare(decl);
+    }
+}
+
+/// Folder to Replace the Last Uses with Moves
+struct MarkMoves {
+    /// The numbers of the reads which are moves
+    moves: HashSet<usize>,
+    /// The number of reads folded so far
+    reads: usize,
+}
+
+impl Folder for MarkMoves {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match expr.kind {
+            ExpressionKind::Identifier(id) => {
+                let read = self.reads;
+                self.reads += 1;
+                let kind = if self.moves.contains(&read) {
+                    ExpressionKind::Move(id)
+                } else {
+                    ExpressionKind::Identifier(id)
+                };
+                Expression::new(kind, expr.typ).with_span(expr.span)
+            }
+            ExpressionKind::Call(callee, args) if is_named_callee(&callee) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.fold_expression(arg))
+                    .collect();
+                Expression::new(ExpressionKind::Call(callee, args), expr.typ).with_span(expr.span)
+            }
+            _ => visit::fold_children(self, expr),
+        }
+    }
+}
+
+/// Check if a Callee is a Function Name
+///
+/// Calls to named functions aren't reads of any variable, so they
+/// are skipped by both `LastUses` and `MarkMoves`.
+fn is_named_callee(callee: &Expression) -> bool {
+    matches!(callee.kind, ExpressionKind::Identifier(_))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Binder, Scope};
+    use super::*;
+    use crate::syntax::{text::SourceText, SyntaxTree};
+
+    /// Collect the Names Read, Marking Moves
+    #[derive(Default)]
+    struct Reads(Vec<String>);
+
+    impl<'a> Visitor<'a> for Reads {
+        fn visit_identifier(&mut self, id: &'a str) {
+            self.0.push(id.into());
+        }
+
+        fn visit_move(&mut self, id: &'a str) {
+            self.0.push(format!("move {}", id));
+        }
+    }
+
+    fn reads(text: &str) -> Vec<String> {
+        let source = SourceText::new(text);
+        let tree = SyntaxTree::parse(&source);
+        let bound = Binder::new(Scope::new()).bind_tree(tree);
+        let mut reads = Reads::default();
+        reads.visit_expression(&bound);
+        reads.0
+    }
+
+    #[test]
+    fn last_read_is_moved() {
+        assert_eq!(vec!["s", "move s"], reads("let s = 'a'\nprint s\nprint s"));
+        // Only reference counted values are moved
+        assert_eq!(vec!["n", "n"], reads("let n = 1\nprint n\nprint n"));
+        // Parameters are variables of the function
+        assert_eq!(vec!["move s"], reads("fn f(s: String): String\n  s\nend"));
+    }
+
+    #[test]
+    fn nested_reads_are_not_moved() {
+        assert_eq!(vec!["s"], reads("let s = 'a'\nwhile false\n  print s\nend"));
+        assert_eq!(vec!["s", "s"], reads("let s = 'a'\nprint s if true else s"));
+        // Unless the variable was declared in the same region
+        assert_eq!(
+            vec!["move t"],
+            reads("while false\n  let t = 'b'\n  print t\nend")
+        );
+    }
+
+    #[test]
+    fn assigned_variables_are_not_moved() {
+        assert_eq!(vec!["s", "s"], reads("var s = 'a'\nprint s\ns = s + 'b'"));
+        assert_eq!(vec!["s", "move s"], reads("var s = 'a'\ns = s\nprint s"));
+    }
+
+    #[test]
+    fn called_names_are_not_reads() {
+        assert_eq!(
+            vec!["move s", "s"],
+            reads("fn s(): Number\n  1\nend\nlet s = 'a'\nprint s\nprint s()")
+        );
+    }
+
+    #[test]
+    fn shadowed_variables_are_separate() {
+        assert_eq!(
+            vec!["move s", "s", "move s"],
+            reads("let s = 'a'\nprint s\nlet s = 1\nprint s\nlet s = 'b'\nprint s")
+        );
+    }
+}
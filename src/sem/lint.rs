@@ -0,0 +1,346 @@
+//! Lints
+//!
+//! Lints are passes over the bound tree which warn about code that is
+//! valid but probably not what was meant. Each lint has a name, the
+//! code of the diagnostics it raises, and a default level. The level
+//! of a lint can be changed for the whole program with `-W`, or for a
+//! single function or declaration with the `allow`, `warn`, and
+//! `deny` attributes. The `#allow` pragma also allows a lint for the
+//! expression after it.
+//!
+//! The lints run once the whole tree has been bound. New lints can be
+//! added to a `Binder` with `with_lint`.
+
+mod constant_condition;
+mod shadowing;
+mod unreachable;
+mod unused;
+
+use super::tree::Expression;
+use crate::diag::{Code, Diagnostic};
+use crate::syntax::text::Span;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Lint Level
+///
+/// How the diagnostics raised by a lint are reported.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LintLevel {
+    /// Don't report the lint
+    Allow,
+    /// Report the lint as a warning
+    Warn,
+    /// Report the lint as an error
+    Deny,
+}
+
+/// Lint
+///
+/// A check over the bound tree. Lints report their findings to the
+/// `LintContext` they are given. The level the findings are reported
+/// at is decided by whoever runs the lint.
+pub trait Lint {
+    /// The Code of the Diagnostics the Lint Raises
+    fn code(&self) -> Code;
+
+    /// The Lint's Name
+    ///
+    /// Used to refer to the lint in attributes, pragmas, and on the
+    /// command line. Defaults to the lint name of the lint's code.
+    fn name(&self) -> &'static str {
+        self.code().lint_name().unwrap_or("")
+    }
+
+    /// The Level the Lint is Reported at Unless Configured
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    /// Check a Bound Tree
+    fn check(&self, expr: &Expression, cx: &mut LintContext<'_>);
+}
+
+/// Lint Context
+///
+/// The information about a bind available to the lints, and where
+/// they collect their diagnostics.
+pub struct LintContext<'a> {
+    /// The references to each definition, by definition
+    references: &'a HashMap<Span, Vec<Span>>,
+    /// The code of the lint being run
+    code: Code,
+    /// The diagnostics raised so far
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> LintContext<'a> {
+    /// Create a Context for a Bind
+    ///
+    /// The `references` are the reference index built by the
+    /// binder, see `Binder::references`.
+    pub fn new(references: &'a HashMap<Span, Vec<Span>>, code: Code) -> Self {
+        LintContext {
+            references,
+            code,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Is a Definition Ever Referred to?
+    ///
+    /// Returns true if any identifier other than the definition
+    /// itself was bound to the definition at `span`.
+    pub fn is_used(&self, span: Span) -> bool {
+        self.references
+            .get(&span)
+            .is_some_and(|refs| refs.iter().any(|&r| r != span))
+    }
+
+    /// Report a Finding
+    ///
+    /// The diagnostic is given the code of the lint being run.
+    pub fn report(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).with_code(self.code));
+    }
+
+    /// Take the Reported Diagnostics
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+/// Lint Levels
+///
+/// The configured level of each lint. Lints which haven't been
+/// configured are reported at their default level.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LintLevels {
+    /// The level to use for lints without their own level
+    all: Option<LintLevel>,
+    /// The level of each configured lint
+    levels: HashMap<Code, LintLevel>,
+}
+
+impl LintLevels {
+    /// Create an Empty Configuration
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the Level of a Lint
+    pub fn with_level(mut self, code: Code, level: LintLevel) -> Self {
+        self.levels.insert(code, level);
+        self
+    }
+
+    /// Set the Level of Every Lint
+    ///
+    /// Lints which have been given their own level keep it.
+    pub fn with_all(mut self, level: LintLevel) -> Self {
+        self.all = Some(level);
+        self
+    }
+
+    /// Add an Option from the Command Line
+    ///
+    /// Options are a lint name or code to warn about it, `no-<name>`
+    /// to allow it, `error=<name>` to deny it, or `all` to warn about
+    /// every lint.
+    pub fn with_option(self, option: &str) -> Result<Self, String> {
+        if option == "all" {
+            return Ok(self.with_all(LintLevel::Warn));
+        }
+        let (name, level) = if let Some(name) = option.strip_prefix("no-") {
+            (name, LintLevel::Allow)
+        } else if let Some(name) = option.strip_prefix("error=") {
+            (name, LintLevel::Deny)
+        } else {
+            (option, LintLevel::Warn)
+        };
+        let code = lookup(name).ok_or_else(|| format!("unknown lint '{}'", name))?;
+        Ok(self.with_level(code, level))
+    }
+
+    /// Get the Level of a Lint
+    pub fn level(&self, lint: &dyn Lint) -> LintLevel {
+        self.levels
+            .get(&lint.code())
+            .copied()
+            .or(self.all)
+            .unwrap_or_else(|| lint.default_level())
+    }
+}
+
+/// Lint Registry
+///
+/// The set of lints run over a bind.
+pub struct LintRegistry {
+    lints: Vec<Box<dyn Lint>>,
+}
+
+impl LintRegistry {
+    /// Create an Empty Registry
+    pub fn new() -> Self {
+        LintRegistry { lints: Vec::new() }
+    }
+
+    /// Create a Registry of the Builtin Lints
+    pub fn builtin() -> Self {
+        let mut registry = LintRegistry::new();
+        registry.register(Box::new(unreachable::UnreachableCode));
+        registry.register(Box::new(unused::UnusedVariable));
+        registry.register(Box::new(shadowing::Shadowing));
+        registry.register(Box::new(constant_condition::ConstantCondition));
+        registry
+    }
+
+    /// Add a Lint to the Registry
+    pub fn register(&mut self, lint: Box<dyn Lint>) {
+        self.lints.push(lint);
+    }
+
+    /// Iterate Over the Registered Lints
+    pub fn lints(&self) -> impl Iterator<Item = &dyn Lint> {
+        self.lints.iter().map(|lint| &**lint)
+    }
+
+    /// Run the Lints Over a Bound Tree
+    ///
+    /// Returns each diagnostic raised along with the level of the
+    /// lint which raised it. Lints are run even if they are allowed,
+    /// as attributes may raise their level for part of the tree.
+    pub fn check(
+        &self,
+        expr: &Expression,
+        references: &HashMap<Span, Vec<Span>>,
+        levels: &LintLevels,
+    ) -> Vec<(LintLevel, Diagnostic)> {
+        let mut found = Vec::new();
+        for lint in self.lints() {
+            let mut cx = LintContext::new(references, lint.code());
+            lint.check(expr, &mut cx);
+            let level = levels.level(lint);
+            found.extend(cx.take_diagnostics().into_iter().map(|d| (level, d)));
+        }
+        found
+    }
+}
+
+impl Default for LintRegistry {
+    fn default() -> Self {
+        LintRegistry::builtin()
+    }
+}
+
+impl FromStr for LintLevel {
+    type Err = String;
+
+    /// Parse a Lint Level from the Attribute that Sets it
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LintLevel::Allow),
+            "warn" => Ok(LintLevel::Warn),
+            "deny" => Ok(LintLevel::Deny),
+            _ => Err(format!("unknown lint level '{}'", s)),
+        }
+    }
+}
+
+/// Look up a Lint by its Name or Code
+///
+/// Codes of diagnostics which aren't raised by lints aren't found.
+pub fn lookup(name: &str) -> Option<Code> {
+    Code::from_lint_name(name)
+        .or_else(|| Code::from_id(name))
+        .filter(|code| code.lint_name().is_some())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Binder, Scope};
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use crate::syntax::SyntaxTree;
+
+    /// Bind a Source and Collect the Codes of its Lints
+    ///
+    /// Returns the codes of the warnings and of the errors.
+    fn lint(src: &str, levels: LintLevels) -> (Vec<Code>, Vec<Code>) {
+        let source = SourceText::new(src);
+        let mut binder = Binder::new(Scope::new()).with_lint_levels(levels);
+        binder.bind_tree(SyntaxTree::parse(&source));
+        let codes =
+            |diagnostics: Vec<Diagnostic>| diagnostics.into_iter().filter_map(|d| d.code).collect();
+        (
+            codes(binder.take_warnings()),
+            codes(binder.take_diagnostics()),
+        )
+    }
+
+    #[test]
+    fn builtin_lints() {
+        let warnings = |src| lint(src, LintLevels::new().with_all(LintLevel::Warn)).0;
+        assert_eq!(
+            vec![Code::UnreachableCode],
+            warnings("while true end\nprint 1")
+        );
+        assert_eq!(
+            vec![Code::UnusedVariable],
+            warnings("fn f(a: Number, _b: Number): Number 1 end\nprint f(1, 2)")
+        );
+        assert_eq!(
+            vec![Code::ShadowedVariable],
+            warnings("let a = 1\nfn f(): Number\n  let a = 2\n  a\nend\nprint a + f()")
+        );
+        assert_eq!(
+            vec![Code::ConstantCondition, Code::ConstantCondition],
+            warnings("print 1 if !true else 2\nuntil true\nend")
+        );
+        assert!(warnings("var i = 0\nwhile i < 10\n  i = i + 1\nend\nwhile true end").is_empty());
+    }
+
+    #[test]
+    fn configure_levels() {
+        let src = "let a = 1\nlet a = 2\nprint a";
+        assert_eq!(
+            (vec![Code::UnusedVariable], vec![]),
+            lint(src, LintLevels::new())
+        );
+
+        let levels = LintLevels::new()
+            .with_option("shadowing")
+            .and_then(|levels| levels.with_option("error=unused_variable"))
+            .unwrap();
+        assert_eq!(
+            (vec![Code::ShadowedVariable], vec![Code::UnusedVariable]),
+            lint(src, levels)
+        );
+
+        let levels = LintLevels::new()
+            .with_option("all")
+            .and_then(|levels| levels.with_option("no-U0028"))
+            .unwrap();
+        assert_eq!((vec![Code::ShadowedVariable], vec![]), lint(src, levels));
+        assert!(LintLevels::new().with_option("undefined_name").is_err());
+        assert!(LintLevels::new().with_option("U0007").is_err());
+    }
+
+    #[test]
+    fn used_variables_are_not_reported() {
+        let unused = |src| lint(src, LintLevels::new()).0;
+        assert!(unused("var a = 0\nprint a\nvar a = 1\nprint a").is_empty());
+        assert!(unused("let s = 'a'\nprint s + s").is_empty());
+        assert!(unused("fn foo(): Number 1 end\nlet foo = 2\nprint foo + foo()").is_empty());
+    }
+
+    #[test]
+    fn attributes_set_levels() {
+        let src = "#[deny(unused_variable)]\nfn f(a: Number): Number\n  #[allow(unused_variable)] let b = 1\n  let c = 2\n  #[warn(shadowing)] let c = 3\n  a + c\nend\nprint f(1)";
+        assert_eq!(
+            (vec![Code::ShadowedVariable], vec![Code::UnusedVariable]),
+            lint(src, LintLevels::new())
+        );
+    }
+}
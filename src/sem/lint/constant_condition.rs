@@ -0,0 +1,58 @@
+//! Constant Condition Lint
+//!
+//! Warns about `if` expressions whose condition is a constant, so
+//! only one branch can ever be taken, and loops whose condition is
+//! always false, so their body never runs. Loops which are always
+//! true are how infinite loops are written, so aren't reported.
+
+use super::super::reachability;
+use super::super::tree::Expression;
+use super::super::visit::Visitor;
+use super::{Lint, LintContext};
+use crate::diag::Code;
+
+/// Constant Condition Lint
+pub struct ConstantCondition;
+
+impl Lint for ConstantCondition {
+    fn code(&self) -> Code {
+        Code::ConstantCondition
+    }
+
+    fn check(&self, expr: &Expression, cx: &mut LintContext<'_>) {
+        Checker { cx }.visit_expression(expr)
+    }
+}
+
+/// Visitor which Finds Constant Conditions
+struct Checker<'a, 'b> {
+    cx: &'a mut LintContext<'b>,
+}
+
+impl<'e> Visitor<'e> for Checker<'_, '_> {
+    fn visit_if_else(
+        &mut self,
+        cond: &'e Expression,
+        if_true: &'e Expression,
+        if_false: &'e Expression,
+    ) {
+        if let Some(value) = reachability::const_bool(cond) {
+            self.cx
+                .report(format!("Condition is always {}", value), cond.span);
+        }
+        self.visit_expression(cond);
+        self.visit_expression(if_true);
+        self.visit_expression(if_false);
+    }
+
+    fn visit_loop(&mut self, cond: &'e Expression, body: &'e Expression) {
+        if reachability::const_bool(cond) == Some(false) {
+            self.cx.report(
+                "Loop condition is always false, so the body never runs",
+                cond.span,
+            );
+        }
+        self.visit_expression(cond);
+        self.visit_expression(body);
+    }
+}
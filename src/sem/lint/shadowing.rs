@@ -0,0 +1,88 @@
+//! Shadowing Lint
+//!
+//! Warns about declarations which hide a variable or parameter of
+//! the same name from an enclosing scope, or earlier in the same one.
+//! Shadowing is often deliberate, so this lint is allowed by default.
+
+use std::collections::HashSet;
+
+use super::super::tree::{Expression, FnDecl, VarDecl};
+use super::super::visit::{self, Visitor};
+use super::{Lint, LintContext, LintLevel};
+use crate::diag::Code;
+
+/// Shadowing Lint
+pub struct Shadowing;
+
+impl Lint for Shadowing {
+    fn code(&self) -> Code {
+        Code::ShadowedVariable
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Allow
+    }
+
+    fn check(&self, expr: &Expression, cx: &mut LintContext<'_>) {
+        let mut checker = Checker {
+            cx,
+            scopes: vec![HashSet::new()],
+        };
+        checker.visit_expression(expr)
+    }
+}
+
+/// Visitor which Finds Shadowed Variables
+///
+/// Tracks the names declared in each scope. Function bodies, loop
+/// bodies, and the branches of an `if` each have their own scope.
+struct Checker<'a, 'b, 'e> {
+    cx: &'a mut LintContext<'b>,
+    scopes: Vec<HashSet<&'e str>>,
+}
+
+impl<'e> Checker<'_, '_, 'e> {
+    /// Visit an Expression in a New Scope
+    fn visit_scoped(&mut self, expr: &'e Expression) {
+        self.scopes.push(HashSet::new());
+        self.visit_expression(expr);
+        self.scopes.pop();
+    }
+}
+
+impl<'e> Visitor<'e> for Checker<'_, '_, 'e> {
+    fn visit_if_else(
+        &mut self,
+        cond: &'e Expression,
+        if_true: &'e Expression,
+        if_false: &'e Expression,
+    ) {
+        self.visit_expression(cond);
+        self.visit_scoped(if_true);
+        self.visit_scoped(if_false);
+    }
+
+    fn visit_function(&mut self, func: &'e FnDecl) {
+        self.scopes.push(HashSet::new());
+        visit::walk_function(self, func);
+        self.scopes.pop();
+    }
+
+    fn visit_loop(&mut self, cond: &'e Expression, body: &'e Expression) {
+        self.visit_expression(cond);
+        self.visit_scoped(body);
+    }
+
+    fn visit_var_decl(&mut self, decl: &'e VarDecl) {
+        let name = &decl.ident[..];
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            self.cx.report(
+                format!("Declaration of '{}' shadows an earlier declaration", name),
+                decl.span,
+            );
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name);
+        }
+    }
+}
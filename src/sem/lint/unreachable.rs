@@ -0,0 +1,48 @@
+//! Unreachable Code Lint
+//!
+//! Warns about the expressions in a sequence after one which never
+//! completes. Function declarations are still reachable through calls
+//! so they aren't included.
+
+use super::super::reachability;
+use super::super::tree::{Expression, ExpressionKind};
+use super::super::visit::{self, Visitor};
+use super::{Lint, LintContext};
+use crate::diag::Code;
+use crate::syntax::text::Span;
+
+/// Unreachable Code Lint
+pub struct UnreachableCode;
+
+impl Lint for UnreachableCode {
+    fn code(&self) -> Code {
+        Code::UnreachableCode
+    }
+
+    fn check(&self, expr: &Expression, cx: &mut LintContext<'_>) {
+        Checker { cx }.visit_expression(expr)
+    }
+}
+
+/// Visitor which Finds Unreachable Code
+struct Checker<'a, 'b> {
+    cx: &'a mut LintContext<'b>,
+}
+
+impl<'e> Visitor<'e> for Checker<'_, '_> {
+    fn visit_sequence(&mut self, exprs: &'e [Expression]) {
+        let mut unreachable: Option<Span> = None;
+        let mut diverged = false;
+        for expr in exprs {
+            if diverged && !matches!(expr.kind, ExpressionKind::Function(_)) {
+                let span = expr.span;
+                unreachable = Some(unreachable.map_or(span, |s| Span::enclosing(s, span)));
+            }
+            diverged = diverged || reachability::diverges(expr);
+        }
+        if let Some(span) = unreachable {
+            self.cx.report("Unreachable expression", span);
+        }
+        visit::walk_expressions(self, exprs)
+    }
+}
@@ -0,0 +1,37 @@
+//! Unused Variable Lint
+//!
+//! Warns about variables and function parameters which are never
+//! referred to after they are declared. Names starting with an
+//! underscore are unused on purpose, so aren't reported.
+
+use super::super::tree::{Expression, VarDecl};
+use super::super::visit::Visitor;
+use super::{Lint, LintContext};
+use crate::diag::Code;
+
+/// Unused Variable Lint
+pub struct UnusedVariable;
+
+impl Lint for UnusedVariable {
+    fn code(&self) -> Code {
+        Code::UnusedVariable
+    }
+
+    fn check(&self, expr: &Expression, cx: &mut LintContext<'_>) {
+        Checker { cx }.visit_expression(expr)
+    }
+}
+
+/// Visitor which Finds Unused Variables
+struct Checker<'a, 'b> {
+    cx: &'a mut LintContext<'b>,
+}
+
+impl<'e> Visitor<'e> for Checker<'_, '_> {
+    fn visit_var_decl(&mut self, decl: &'e VarDecl) {
+        if !decl.ident.starts_with('_') && !self.cx.is_used(decl.span) {
+            self.cx
+                .report(format!("Unused variable '{}'", decl.ident), decl.span);
+        }
+    }
+}
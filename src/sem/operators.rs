@@ -1,65 +1,182 @@
 //! # Semantic Operators
 //!
-//! This module provides semantic undestanding of the builtin
-//! operators. The main entry point is the
+//! This module provides semantic undestanding of infix operators. The
+//! main entry point is the `OperatorTable`, which maps each operator
+//! and pair of operand types to the operation they select. The
+//! builtin operators are registered in `OperatorTable::builtin`, and
+//! further operators can be registered on top of them.
 
 use super::types::{BuiltinType, Typ};
 use crate::syntax::*;
+use std::collections::HashMap;
+use std::fmt;
 
 /// The Semantic Operator
 ///
 /// Semantically bound operator. This is an operator with knowlege of
 /// the types it is to be bound to.
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct SemOp {
+    /// The type of the left hand operand
     pub lhs_typ: Typ,
+    /// The type of the right hand operand
     pub rhs_typ: Typ,
+    /// The operator being applied
     pub op: InfixOp,
+    /// The type the operator evaluates to
     pub result_typ: Typ,
 }
 
-fn num_op(op: InfixOp) -> Option<SemOp> {
-    Some(SemOp {
-        lhs_typ: Typ::Builtin(BuiltinType::Number),
-        rhs_typ: Typ::Builtin(BuiltinType::Number),
-        op,
-        result_typ: Typ::Builtin(BuiltinType::Number),
-    })
+impl SemOp {
+    /// Create a Semantic Operator
+    pub fn new(op: InfixOp, lhs_typ: Typ, rhs_typ: Typ, result_typ: Typ) -> Self {
+        SemOp {
+            lhs_typ,
+            rhs_typ,
+            op,
+            result_typ,
+        }
+    }
 }
 
-fn comp_op(op: InfixOp) -> Option<SemOp> {
-    Some(SemOp {
-        lhs_typ: Typ::Builtin(BuiltinType::Number),
-        rhs_typ: Typ::Builtin(BuiltinType::Number),
-        op,
-        result_typ: Typ::Builtin(BuiltinType::Bool),
-    })
+impl fmt::Display for SemOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} -> {}",
+            self.lhs_typ.name(),
+            self.op.symbol(),
+            self.rhs_typ.name(),
+            self.result_typ.name()
+        )
+    }
 }
 
-/// Find Operator
+/// Operator Table
 ///
-/// Searches for the result type for a given operator.
-pub fn find_builtin_op(op: InfixOp, lhs_typ: Typ, rhs_typ: Typ) -> Option<SemOp> {
-    match (op, lhs_typ, rhs_typ) {
-        (InfixOp::Add, Typ::Builtin(BuiltinType::String), Typ::Builtin(BuiltinType::String)) => {
-            Some(SemOp {
-                lhs_typ,
-                rhs_typ,
-                op,
-                result_typ: Typ::Builtin(BuiltinType::String),
-            })
+/// Registry of the operators available to a bind, keyed by the
+/// operator and the types of its operands. The table starts out
+/// empty. Use `builtin` to create a table containing the operators
+/// the language provides.
+#[derive(Debug, Default, Clone)]
+pub struct OperatorTable {
+    ops: HashMap<(InfixOp, Typ, Typ), SemOp>,
+}
+
+impl OperatorTable {
+    /// Create an Empty Operator Table
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a Table of the Builtin Operators
+    ///
+    /// Arithmetic and ordering are defined on `Number`, `+`
+    /// concatenates `String`s, and `Bool`s can be compared for
+    /// equality.
+    pub fn builtin() -> Self {
+        let number = Typ::Builtin(BuiltinType::Number);
+        let bool_ = Typ::Builtin(BuiltinType::Bool);
+        let string = Typ::Builtin(BuiltinType::String);
+
+        let mut table = OperatorTable::new();
+        for &op in &[InfixOp::Add, InfixOp::Sub, InfixOp::Mul, InfixOp::Div] {
+            table.register(SemOp::new(op, number, number, number));
+        }
+        for &op in &[
+            InfixOp::Eq,
+            InfixOp::NotEq,
+            InfixOp::Lt,
+            InfixOp::LtEq,
+            InfixOp::Gt,
+            InfixOp::GtEq,
+        ] {
+            table.register(SemOp::new(op, number, number, bool_));
         }
-        (InfixOp::Add, Typ::Builtin(BuiltinType::Number), Typ::Builtin(BuiltinType::Number)) => {
-            num_op(op)
+        for &op in &[InfixOp::Eq, InfixOp::NotEq] {
+            table.register(SemOp::new(op, bool_, bool_, bool_));
         }
-        (InfixOp::Sub, _, _) | (InfixOp::Mul, _, _) | (InfixOp::Div, _, _) => num_op(op),
+        table.register(SemOp::new(InfixOp::Add, string, string, string));
+        table
+    }
+
+    /// Register an Operator
+    ///
+    /// Adds the operator to the table. If an operator was already
+    /// registered for the same operand types it is replaced and the
+    /// old one returned.
+    pub fn register(&mut self, sem_op: SemOp) -> Option<SemOp> {
+        self.ops
+            .insert((sem_op.op, sem_op.lhs_typ, sem_op.rhs_typ), sem_op)
+    }
+
+    /// Find Operator
+    ///
+    /// Searches for the operator which accepts the given operand
+    /// types.
+    pub fn find(&self, op: InfixOp, lhs_typ: Typ, rhs_typ: Typ) -> Option<SemOp> {
+        self.ops.get(&(op, lhs_typ, rhs_typ)).copied()
+    }
+
+    /// Get the Candidates for an Operator
+    ///
+    /// Returns every registered overload of the given operator,
+    /// ordered by their signatures.
+    pub fn candidates(&self, op: InfixOp) -> Vec<SemOp> {
+        let mut candidates: Vec<_> = self.ops.values().filter(|o| o.op == op).copied().collect();
+        candidates.sort_by_key(|o| o.to_string());
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtin_operators() {
+        let table = OperatorTable::builtin();
+        let number = Typ::Builtin(BuiltinType::Number);
+        let bool_ = Typ::Builtin(BuiltinType::Bool);
+        let string = Typ::Builtin(BuiltinType::String);
+
+        assert_eq!(
+            Some(bool_),
+            table
+                .find(InfixOp::Lt, number, number)
+                .map(|o| o.result_typ)
+        );
+        assert_eq!(
+            Some(string),
+            table
+                .find(InfixOp::Add, string, string)
+                .map(|o| o.result_typ)
+        );
+        assert_eq!(None, table.find(InfixOp::Sub, string, string));
+        assert_eq!(None, table.find(InfixOp::Lt, bool_, bool_));
+        assert_eq!(None, table.find(InfixOp::Add, number, string));
+        assert_eq!(
+            vec!["Number + Number -> Number", "String + String -> String"],
+            table
+                .candidates(InfixOp::Add)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
 
-        (InfixOp::Eq, _, _)
-        | (InfixOp::NotEq, _, _)
-        | (InfixOp::Lt, _, _)
-        | (InfixOp::LtEq, _, _)
-        | (InfixOp::Gt, _, _)
-        | (InfixOp::GtEq, _, _) => comp_op(op),
+    #[test]
+    fn register_operators() {
+        let string = Typ::Builtin(BuiltinType::String);
+        let number = Typ::Builtin(BuiltinType::Number);
+        let mut table = OperatorTable::new();
+        assert!(table.candidates(InfixOp::Mul).is_empty());
 
-        _ => None,
+        let repeat = SemOp::new(InfixOp::Mul, string, number, string);
+        assert_eq!(None, table.register(repeat));
+        assert_eq!(Some(repeat), table.find(InfixOp::Mul, string, number));
+        assert_eq!(None, table.find(InfixOp::Mul, number, string));
+        assert_eq!(Some(repeat), table.register(repeat));
+        assert_eq!(vec![repeat], table.candidates(InfixOp::Mul));
     }
 }
@@ -0,0 +1,95 @@
+//! Warning Pragmas
+//!
+//! Warnings can be allowed for a single expression by writing an
+//! `#allow` comment on the line before it:
+//!
+//! ```text
+//! #allow(unreachable_code)
+//! fn spin(): Number
+//!     while true end
+//!     0
+//! end
+//! ```
+//!
+//! The allowance covers the whole of the expression, including the
+//! body of a function or loop. Several warnings can be listed,
+//! separated by commas, and each may be given by its lint name or by
+//! its code.
+
+use super::lint;
+use crate::diag::Code;
+use crate::syntax::text::{SourceText, Span};
+
+/// Find the Warnings Allowed for an Expression
+///
+/// Returns the codes named by the `#allow` pragmas in the block of
+/// comment lines directly above the line `span` starts on. Pragmas
+/// only apply to the first expression on a line.
+pub fn allowed_codes(source: &SourceText, span: Span) -> Vec<Code> {
+    let (line, col) = source.position(span);
+    let starts_line = source
+        .line_text(line)
+        .is_some_and(|text| text.chars().take(col).all(char::is_whitespace));
+    if !starts_line {
+        return Vec::new();
+    }
+
+    let mut codes = Vec::new();
+    let comments = (1..line)
+        .rev()
+        .map_while(|line| source.line_text(line))
+        .map(str::trim)
+        .take_while(|text| text.starts_with('#'));
+    for comment in comments {
+        if let Some(names) = comment
+            .strip_prefix("#allow(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            codes.extend(
+                names
+                    .split(',')
+                    .filter_map(|name| lint::lookup(name.trim())),
+            );
+        }
+    }
+    codes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::text::Pos;
+
+    fn allowed_at(source: &str, offset: usize) -> Vec<Code> {
+        let source = SourceText::new(source);
+        allowed_codes(&source, Span::new_at(Pos::from(offset)))
+    }
+
+    #[test]
+    fn pragmas_above_an_expression() {
+        assert_eq!(
+            vec![Code::UnreachableCode],
+            allowed_at("#allow(unreachable_code)\nprint 1", 25)
+        );
+        assert_eq!(
+            vec![Code::UnreachableCode],
+            allowed_at(
+                "  # allow everything below\n  #allow( U0021 )\n  print 1",
+                47
+            )
+        );
+        assert_eq!(
+            vec![Code::UnreachableCode],
+            allowed_at("#allow(unreachable_code)\n# a comment\nprint 1", 37)
+        );
+    }
+
+    #[test]
+    fn pragmas_which_dont_apply() {
+        assert!(allowed_at("print 1", 0).is_empty());
+        assert!(allowed_at("#allow(unreachable_code)\n\nprint 1", 26).is_empty());
+        assert!(allowed_at("#allow(unreachable_code)\nprint 1 print 2", 33).is_empty());
+        assert!(allowed_at("#allow(unknown_lint)\nprint 1", 21).is_empty());
+        assert!(allowed_at("print 1 #allow(unreachable_code)\nprint 2", 33).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Reachability Analysis
+//!
+//! Works out which parts of the bound tree can never complete. An
+//! expression which never completes makes everything after it in the
+//! same sequence unreachable. An expression doesn't complete if it
+//! loops forever, or if it is a `break` or `continue`.
+
+use super::tree::{Expression, ExpressionKind, FnDecl};
+use super::visit::Visitor;
+use crate::syntax::{Constant, LoopControl, PrefixOp};
+
+/// Check if an Expression Diverges
+///
+/// Returns `true` if evaluating the expression can never complete,
+/// so no code following it can ever run.
+pub fn diverges(expr: &Expression) -> bool {
+    use self::ExpressionKind::*;
+    match &expr.kind {
+        Error | Identifier(_) | Move(_) | Literal(_) | Function(_) => false,
+        Prefix(_, inner) | Print(inner) | Convert(inner) | Assignment(_, inner) => diverges(inner),
+        Declaration(_, _, init) => init.as_deref().is_some_and(diverges),
+        Infix(lhs, _, rhs) | Index(lhs, rhs) => diverges(lhs) || diverges(rhs),
+        Call(callee, args) => diverges(callee) || args.iter().any(diverges),
+        IfThenElse(cond, if_true, if_false) => {
+            diverges(cond) || (diverges(if_true) && diverges(if_false))
+        }
+        // A loop whose condition is always true only exits if its
+        // body breaks out of it.
+        Loop(cond, body) => diverges(cond) || (const_bool(cond) == Some(true) && !breaks_out(body)),
+        LoopControl(..) => true,
+        Sequence(exprs) => exprs.iter().any(diverges),
+    }
+}
+
+/// Check if a Loop Body Breaks Out of its Loop
+///
+/// Looks for a `break` of the loop the body belongs to, including
+/// labelled ones from within nested loops.
+fn breaks_out(body: &Expression) -> bool {
+    let mut finder = BreakFinder {
+        depth: 0,
+        found: false,
+    };
+    finder.visit_expression(body);
+    finder.found
+}
+
+/// Visitor to Find the `break`s of a Loop
+struct BreakFinder {
+    /// The number of loops nested inside the one being searched
+    depth: usize,
+    /// Has a `break` of the loop been found?
+    found: bool,
+}
+
+impl<'a> Visitor<'a> for BreakFinder {
+    fn visit_function(&mut self, _func: &'a FnDecl) {
+        // Functions can't break out of loops they are declared in.
+    }
+
+    fn visit_loop(&mut self, cond: &'a Expression, body: &'a Expression) {
+        self.visit_expression(cond);
+        self.depth += 1;
+        self.visit_expression(body);
+        self.depth -= 1;
+    }
+
+    fn visit_loop_control(&mut self, control: LoopControl, depth: usize) {
+        self.found |= control == LoopControl::Break && depth == self.depth;
+    }
+}
+
+/// Get the Value of a Constant Condition
+///
+/// Returns the value of a `Bool` expression if it is a literal, or a
+/// negated literal. Conditions written with `until` are negated
+/// when they are bound.
+pub fn const_bool(expr: &Expression) -> Option<bool> {
+    match &expr.kind {
+        ExpressionKind::Literal(Constant::Bool(b)) => Some(*b),
+        ExpressionKind::Prefix(PrefixOp::Not, inner) => const_bool(inner).map(|b| !b),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Binder, Scope};
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use crate::syntax::SyntaxTree;
+
+    fn bind(source: &str) -> Expression {
+        let source = SourceText::new(source);
+        let tree = SyntaxTree::parse(&source);
+        Binder::new(Scope::new()).bind_tree(tree)
+    }
+
+    #[test]
+    fn infinite_loops_diverge() {
+        assert!(diverges(&bind("while true\nend")));
+        assert!(diverges(&bind("until false\nend")));
+        assert!(diverges(&bind("print 1\nwhile !false\nend")));
+        assert!(diverges(&bind(
+            "let x = (while true end) if 1 == 1 else (until false end)"
+        )));
+    }
+
+    #[test]
+    fn finite_code_completes() {
+        assert!(!diverges(&bind("print 1")));
+        assert!(!diverges(&bind("while false\nend")));
+        assert!(!diverges(&bind(
+            "var i = 0\nwhile i < 10\n  i = i + 1\nend"
+        )));
+        assert!(!diverges(&bind(
+            "let x = (while true end) if 1 == 1 else (while false end)"
+        )));
+        assert!(!diverges(&bind(
+            "fn spin(): Number\n  while true end\n  0\nend"
+        )));
+    }
+
+    #[test]
+    fn breaks_end_infinite_loops() {
+        assert!(!diverges(&bind("while true\n  break\nend")));
+        assert!(!diverges(&bind(
+            "outer: while true\n  while true\n    break outer\n  end\nend"
+        )));
+        assert!(diverges(&bind(
+            "while true\n  while true\n    break\n  end\nend"
+        )));
+        assert!(diverges(&bind("while true\n  continue\nend")));
+    }
+}
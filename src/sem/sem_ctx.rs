@@ -31,9 +31,9 @@ impl<'a> SemCtx<'a> {
         SemCtx {
             locals: vec![HashMap::new()],
             named_types: [
-                (source.intern("String"), Typ::Builtin(BuiltinType::String)),
-                (source.intern("Bool"), Typ::Builtin(BuiltinType::Bool)),
-                (source.intern("Number"), Typ::Builtin(BuiltinType::Number)),
+                (Ident::STRING, Typ::Builtin(BuiltinType::String)),
+                (Ident::BOOL, Typ::Builtin(BuiltinType::Bool)),
+                (Ident::NUMBER, Typ::Builtin(BuiltinType::Number)),
             ]
             .iter()
             .cloned()
@@ -46,22 +46,18 @@ impl<'a> SemCtx<'a> {
     /// Find Type in Context
     ///
     /// Returns the `sem::Typ` declaration for the type if one is
-    /// available.
+    /// available. Missing types, and types which aren't supported
+    /// yet, are never available.
     pub fn sem_ty(&self, ast_ty: &TypeRef) -> Option<Typ> {
-        Some(match *ast_ty {
-            TypeRef::Unit(..) => Typ::Unit,
-            TypeRef::Simple(ref name) => {
-                let id = match name.kind {
-                    TokenKind::Word(id) => id,
-                    _ => panic!("Expected word token"),
-                };
-                return self.named_types.get(&id).cloned();
-            }
+        match *ast_ty {
+            TypeRef::Unit(..) => Some(Typ::Unit),
+            TypeRef::Simple(ref name) => match name.kind {
+                TokenKind::Word(id) => self.named_types.get(&id).cloned(),
+                _ => None,
+            },
             // TODO: array and tuple types
-            TypeRef::Array(..) => unimplemented!("array types are not yet supported"),
-            TypeRef::Tuple(..) => unimplemented!("tuple types are not yet supported"),
-            TypeRef::Missing => panic!("Can't lower missing type"),
-        })
+            TypeRef::Array(..) | TypeRef::Tuple(..) | TypeRef::Missing => None,
+        }
     }
 
     /// Add Local
@@ -92,7 +88,7 @@ impl<'a> SemCtx<'a> {
 
     /// Borrow the Source
     pub fn source(&self) -> &SourceText {
-        &self.source
+        self.source
     }
 
     /// Emit a diagnostic into the context
@@ -0,0 +1,262 @@
+//! Binder Snapshot Tests
+//!
+//! Binds small programs and compares the rendered diagnostics and
+//! typed tree of each against a snapshot in `src/sem/snapshots/`.
+//! When the output of a bind changes on purpose the snapshots can be
+//! updated by running the tests with `ULLAGE_UPDATE_SNAPSHOTS=1` set,
+//! and then reviewing the changes to them.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use super::{Binder, Expression, ExpressionKind, Scope, Typ};
+use crate::syntax::text::SourceText;
+use crate::syntax::SyntaxTree;
+
+macro_rules! check_snapshot {
+    ($name:ident, $src:expr) => {
+        #[test]
+        fn $name() {
+            assert_snapshot(stringify!($name), $src);
+        }
+    };
+}
+
+/// Check a Bind Against its Snapshot
+///
+/// Renders the bind of `src` and compares it with the stored
+/// snapshot called `name`. Panics if they differ, or if there is no
+/// snapshot, unless snapshots are being updated.
+fn assert_snapshot(name: &str, src: &str) {
+    let rendered = render_bind(src);
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "sem", "snapshots"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.snap", name));
+
+    if env::var_os("ULLAGE_UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &rendered).unwrap();
+        return;
+    }
+
+    match fs::read_to_string(&path) {
+        Ok(expected) => assert!(
+            expected == rendered,
+            "snapshot '{}' doesn't match.\n\nexpected:\n{}\nfound:\n{}\n\
+             If the change is intended re-run with ULLAGE_UPDATE_SNAPSHOTS=1",
+            name,
+            expected,
+            rendered
+        ),
+        Err(e) => panic!(
+            "could not read snapshot '{}' ({}). Run with ULLAGE_UPDATE_SNAPSHOTS=1 to create it.\n\n{}",
+            name, e, rendered
+        ),
+    }
+}
+
+/// Render a Bind
+///
+/// Parses and binds the source, then writes out the source, the
+/// diagnostics raised, and the typed tree. Sources which fail to
+/// parse aren't bound, so only the parse diagnostics are shown.
+fn render_bind(src: &str) -> String {
+    let source = SourceText::with_name(src, "snapshot.ulg");
+    let tree = SyntaxTree::parse(&source);
+    let mut rendered = String::from("---- source ----\n");
+    rendered.push_str(src);
+    rendered.push_str("\n---- diagnostics ----\n");
+
+    if tree.has_diagnostics() {
+        for diagnostic in tree.diagnostics() {
+            rendered.push_str(&diagnostic.render(&source, "error"));
+        }
+        return rendered;
+    }
+
+    let mut binder = Binder::new(Scope::new());
+    let bound = binder.bind_tree(tree);
+    for diagnostic in binder.take_diagnostics() {
+        rendered.push_str(&diagnostic.render(&source, "error"));
+    }
+    for warning in binder.take_warnings() {
+        rendered.push_str(&warning.render(&source, "warning"));
+    }
+    rendered.push_str("---- tree ----\n");
+    render_expression(&mut rendered, &source, &bound, 0);
+    rendered
+}
+
+/// Get the Name of a Type
+///
+/// Function types are named by the function they refer to, rather
+/// than by the id of its interned name.
+fn type_name(source: &SourceText, typ: Typ) -> String {
+    match typ {
+        Typ::Function(id) => format!("fn {}", source.interned_value(id)),
+        _ => typ.name().into_owned(),
+    }
+}
+
+/// Render a Typed Expression
+///
+/// Writes one line for the expression, with its type, followed by
+/// each of its children indented one level further.
+fn render_expression(out: &mut String, source: &SourceText, expr: &Expression, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let typ = type_name(source, expr.typ);
+    let mut line = |label: String| {
+        let _ = writeln!(out, "{}{}: {}", indent, label, typ);
+    };
+    let children: Vec<&Expression> = match &expr.kind {
+        ExpressionKind::Error => {
+            line("Error".into());
+            Vec::new()
+        }
+        ExpressionKind::Identifier(id) => {
+            line(format!("Identifier `{}`", id));
+            Vec::new()
+        }
+        ExpressionKind::Move(id) => {
+            line(format!("Move `{}`", id));
+            Vec::new()
+        }
+        ExpressionKind::Literal(value) => {
+            line(format!("Literal {:?}", value));
+            Vec::new()
+        }
+        ExpressionKind::Prefix(op, inner) => {
+            line(format!("Prefix {:?}", op));
+            vec![inner]
+        }
+        ExpressionKind::Infix(lhs, op, rhs) => {
+            line(format!("Infix {:?}", op));
+            vec![lhs, rhs]
+        }
+        ExpressionKind::Call(callee, args) => {
+            line("Call".into());
+            std::iter::once(&**callee).chain(args.iter()).collect()
+        }
+        ExpressionKind::Assignment(id, value) => {
+            line(format!("Assignment `{}`", id));
+            vec![value]
+        }
+        ExpressionKind::Index(indexee, index) => {
+            line("Index".into());
+            vec![indexee, index]
+        }
+        ExpressionKind::IfThenElse(cond, then, els) => {
+            line("IfThenElse".into());
+            vec![cond, then, els]
+        }
+        ExpressionKind::Function(func) => {
+            let params: Vec<_> = func
+                .params
+                .iter()
+                .map(|p| format!("{}: {}", p.ident, p.ty.name()))
+                .collect();
+            line(format!(
+                "Function `{}`({}) -> {}",
+                func.ident,
+                params.join(", "),
+                func.ret_ty.name()
+            ));
+            vec![&func.body]
+        }
+        ExpressionKind::Loop(cond, body) => {
+            line("Loop".into());
+            vec![cond, body]
+        }
+        ExpressionKind::LoopControl(control, depth) => {
+            line(format!("{:?} {}", control, depth));
+            Vec::new()
+        }
+        ExpressionKind::Sequence(exprs) => {
+            line("Sequence".into());
+            exprs.iter().collect()
+        }
+        ExpressionKind::Print(inner) => {
+            line("Print".into());
+            vec![inner]
+        }
+        ExpressionKind::Convert(inner) => {
+            line("Convert".into());
+            vec![inner]
+        }
+        ExpressionKind::Declaration(decl, is_mut, init) => {
+            let style = if *is_mut { "var" } else { "let" };
+            line(format!(
+                "Declaration {} `{}`: {}",
+                style,
+                decl.ident,
+                decl.ty.name()
+            ));
+            init.iter().map(|init| &**init).collect()
+        }
+    };
+    for child in children {
+        render_expression(out, source, child, depth + 1);
+    }
+}
+
+check_snapshot!(
+    bind_infix_operators,
+    "print 1 + 2 * 3 == 7\nprint 'a' + 'b'"
+);
+
+check_snapshot!(bind_invalid_operands, "print 'a' - 'b'");
+
+check_snapshot!(
+    bind_calls,
+    "fn add(a: Number, b: Number): Number\n  a + b\nend\nprint add(1, 2)"
+);
+
+check_snapshot!(
+    bind_calls_with_bad_arguments,
+    "fn add(a: Number, b: Number): Number\n  a + b\nend\nadd(1)\nadd('one', 2)\nadd(1, 2, 3)"
+);
+
+check_snapshot!(
+    bind_if_then_else,
+    "let n = 4\nprint 'big' if n > 3 else 'small'\nprint 1 unless n == 4 else 2"
+);
+
+check_snapshot!(
+    bind_if_then_else_conditions,
+    "print 1 if 'yes' else 2\nprint 1 if 0 else 2"
+);
+
+check_snapshot!(
+    bind_functions,
+    "fn fact(n: Number): Number\n  1 if n <= 1 else n * fact(n - 1)\nend\nfn greet(name: String): String\n  'hi ' + name\nend"
+);
+
+check_snapshot!(
+    bind_functions_missing_return,
+    "fn count(n: Number): Number\n  while n > 0\n    n = n - 1\n  end\nend"
+);
+
+check_snapshot!(
+    bind_loops,
+    "var i = 0\nwhile i < 3\n  i = i + 1\nend\nuntil i == 0\n  i = i - 1\nend"
+);
+
+check_snapshot!(bind_unreachable_after_loop, "while true\nend\nprint 1");
+
+check_snapshot!(
+    bind_declarations,
+    "let a = 1\nvar b: String = 'b'\nvar c: Bool\nc = a > 0\nprint c"
+);
+
+check_snapshot!(
+    bind_declaration_errors,
+    "let a: Number\nlet b = 1\nb = 2\nvar c: Number = 'c'\nvar d: Number\nprint d"
+);
+
+check_snapshot!(
+    bind_call_of_function_expression,
+    "print fn f(): Number\n  1\nend()"
+);
@@ -0,0 +1,80 @@
+//! Name Suggestions
+//!
+//! When a name can't be found the most likely cause is a typo. This
+//! module finds the names which are close enough to the one written
+//! that they were probably what was meant, so that diagnostics can
+//! suggest them.
+
+use std::mem;
+
+/// The Largest Edit Distance to Suggest
+const MAX_DISTANCE: usize = 2;
+
+/// Find the Closest Name
+///
+/// Returns the candidate with the smallest edit distance to `name`,
+/// if any is close enough to be worth suggesting. Names which would
+/// need every character changing are never suggested. Ties are
+/// broken alphabetically.
+pub fn closest<I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let limit = MAX_DISTANCE.min(name.chars().count().saturating_sub(1));
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit Distance Between Two Strings
+///
+/// Counts the single character insertions, deletions, and
+/// substitutions needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        mem::swap(&mut prev, &mut current);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(0, edit_distance("count", "count"));
+        assert_eq!(1, edit_distance("cont", "count"));
+        assert_eq!(1, edit_distance("count", "mount"));
+        assert_eq!(2, edit_distance("cuont", "count"));
+        assert_eq!(5, edit_distance("", "count"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+
+    #[test]
+    fn closest_names() {
+        let candidates = names(&["Number", "String", "Bool", "total", "totals"]);
+        assert_eq!(Some("Number".into()), closest("Numbr", candidates.clone()));
+        assert_eq!(Some("total".into()), closest("totl", candidates.clone()));
+        assert_eq!(Some("total".into()), closest("totel", candidates.clone()));
+        assert_eq!(None, closest("Float", candidates.clone()));
+        assert_eq!(None, closest("x", names(&["y"])));
+        assert_eq!(Some("ab".into()), closest("ax", names(&["ab", "ac"])));
+    }
+}
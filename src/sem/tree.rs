@@ -4,7 +4,8 @@
 //! decorated/semantic expression tree.
 
 use super::types::*;
-use crate::syntax::{Constant, InfixOp, PrefixOp};
+use crate::syntax::text::{Span, DUMMY_SPAN};
+use crate::syntax::{Constant, InfixOp, LoopControl, PrefixOp};
 
 /// A Function Decclaration
 ///
@@ -21,26 +22,45 @@ pub struct FnDecl {
     /// Parameters to the function
     pub params: Vec<VarDecl>,
 
+    /// The attributes which affect how the function is lowered
+    pub attrs: FnAttrs,
+
     /// The body of the function
     ///
     /// The function's return is the value of the expression
     pub body: Box<Expression>,
 }
 
+/// Function Attributes
+///
+/// The attributes applied to a function which change how it is
+/// lowered, rather than how it is bound.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct FnAttrs {
+    /// Hint that calls to the function should be inlined
+    pub inline: bool,
+    /// Give the function the C calling convention, so it can be
+    /// called from outside the program
+    pub export: bool,
+}
+
 /// Variable Declaration
 ///
 /// Represents the binding of a given type to an identifier to create
 /// a variable. Used both for local variable declarations as well as
 /// function parameters.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct VarDecl {
     /// The logical name of the declataion
     ///
     /// FIXME: proper idents rather than strings in the bound tree.
     pub ident: String,
 
-    /// The type of the identifier, if one was specified or inferred.
-    pub ty: Option<Typ>,
+    /// The type of the identifier, either specified or inferred.
+    pub ty: Typ,
+
+    /// The span of the declared identifier
+    pub span: Span,
 }
 
 /// A Semantically Decorated Expression
@@ -48,13 +68,19 @@ pub struct VarDecl {
 /// This struct represents the expression tree after semantic
 /// analysis. This is no longer guaranteed to be a a lieral
 /// representation of the code as it was written.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Expression {
     /// The contents of this expression.
     pub kind: ExpressionKind,
 
-    /// The type of this node, if known
-    pub typ: Option<Typ>,
+    /// The type of this node
+    pub typ: Typ,
+
+    /// The span of the syntax the expression was bound from
+    ///
+    /// Expressions which the binder synthesises, such as implicit
+    /// conversions, may have a dummy span.
+    pub span: Span,
 }
 
 /// The Expression Kind Enum
@@ -82,6 +108,14 @@ pub enum ExpressionKind {
     /// functions when those expressions reference bound values.
     Identifier(String),
 
+    /// Last Use of a Variable
+    ///
+    /// A read of a `String` variable which is the last time the
+    /// variable is used. The value is moved out of the variable
+    /// rather than copied, so it doesn't need to be retained. Moves
+    /// are marked by the binder once the tree is bound.
+    Move(String),
+
     /// Literal Value
     ///
     /// A constant value. This is just plucked straight from the
@@ -122,6 +156,12 @@ pub enum ExpressionKind {
     /// Loop with Condition
     Loop(Box<Expression>, Box<Expression>),
 
+    /// Break or Continue
+    ///
+    /// Applies to an enclosing loop. The number is how many loops to
+    /// skip outwards, so `0` is the innermost loop.
+    LoopControl(LoopControl, usize),
+
     /// A Sequence of Expressions
     Sequence(Vec<Expression>),
 
@@ -132,25 +172,58 @@ pub enum ExpressionKind {
     /// simple output.
     Print(Box<Expression>),
 
+    /// Type Conversion
+    ///
+    /// Converts the inner expression to the type of this
+    /// expression. Conversions are inserted by the binder wherever a
+    /// value is implicitly converted, so the tree passed to lowering
+    /// is fully explicit.
+    Convert(Box<Expression>),
+
     /// Variable Declaration
-    Declaration(VarDecl, bool, Box<Expression>),
+    ///
+    /// Declarations without an initialiser leave the variable
+    /// unassigned until a later assignment.
+    Declaration(VarDecl, bool, Option<Box<Expression>>),
 }
 
 impl Expression {
     /// Create a New Expression from parts
     ///
     /// Constructs a new semantic expression tree node from
-    /// constituent parts. The type information for a given node can
-    /// be set to none if no type inference has yet been run for this
-    /// expression.
-    pub fn new(kind: ExpressionKind, typ: Option<Typ>) -> Self {
-        Expression { kind, typ }
+    /// constituent parts. While binding the type can be a type
+    /// variable if it has yet to be inferred.
+    pub fn new(kind: ExpressionKind, typ: Typ) -> Self {
+        Expression {
+            kind,
+            typ,
+            span: DUMMY_SPAN,
+        }
+    }
+
+    /// Set the Span of the Expression
+    pub fn with_span(self, span: Span) -> Self {
+        Expression { span, ..self }
     }
 
     /// Create an Error Expresion
     ///
     /// Convenience function for returning error expressions.
     pub fn error() -> Self {
-        Expression::new(ExpressionKind::Error, Some(Typ::Error))
+        Expression::new(ExpressionKind::Error, Typ::Error)
+    }
+}
+
+/// Expressions are compared by their kind and type. Where they came
+/// from in the source doesn't change what they mean.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.typ == other.typ
+    }
+}
+
+impl PartialEq for VarDecl {
+    fn eq(&self, other: &Self) -> bool {
+        self.ident == other.ident && self.ty == other.ty
     }
 }
@@ -18,9 +18,14 @@ pub enum Typ {
     /// based off this type is meaningless.
     Error,
 
-    /// Type yet to be inferred.
+    /// Type not given in the source. The bound tree never contains
+    /// this, instead types yet to be inferred are type variables.
     Unknown,
 
+    /// A type variable awaiting inference. These only exist while
+    /// binding and are resolved before the bind completes.
+    Var(u32),
+
     /// The unit type
     Unit,
 
@@ -46,16 +51,28 @@ impl Typ {
     pub fn name(&self) -> Cow<'_, str> {
         Cow::Borrowed(match *self {
             Typ::Error => "!ERROR!",
-            Typ::Unknown => "_",
+            Typ::Unknown | Typ::Var(_) => "_",
             Typ::Unit => "()",
             Typ::Builtin(ref b) => match *b {
                 BuiltinType::Number => "Number",
                 BuiltinType::Bool => "Bool",
                 BuiltinType::String => "String",
+                BuiltinType::StringBuilder => "StringBuilder",
             },
             Typ::Function(id) => return Cow::Owned(format!("Function({:?})", id)),
         })
     }
+
+    /// Is the Type Reference Counted?
+    ///
+    /// Values of these types live on the heap. Lowering retains and
+    /// releases them, freeing each value once it is no longer used.
+    pub fn is_reference_counted(&self) -> bool {
+        matches!(
+            *self,
+            Typ::Builtin(BuiltinType::String) | Typ::Builtin(BuiltinType::StringBuilder)
+        )
+    }
 }
 
 /// The fixed builtin types
@@ -67,4 +84,7 @@ pub enum BuiltinType {
     Bool,
     /// String type. This is a pointer-lenght pair
     String,
+    /// A growable buffer for building up a `String`. Unlike strings,
+    /// builders have reference semantics.
+    StringBuilder,
 }
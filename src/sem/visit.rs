@@ -0,0 +1,352 @@
+//! Semantic Tree Visitor and Folder
+//!
+//! This module provides two ways of walking a bound semantic
+//! tree. The `Visitor` trait walks a borrowed tree and is intended for
+//! analyses which inspect the tree without modifying it. The `Folder`
+//! trait consumes a tree and rebuilds it, allowing passes to replace
+//! any expression with a transformed one.
+//!
+//! As with the syntax visitor each method has a default
+//! implementation which walks the children of the node. Passes only
+//! need to override the methods for the nodes they care about and call
+//! the matching `walk_*` function, or `fold_children`, to continue the
+//! walk.
+
+use super::tree::{Expression, ExpressionKind, FnDecl, VarDecl};
+use crate::syntax::{Constant, InfixOp, LoopControl, PrefixOp};
+
+/// Semantic Tree Visitor
+///
+/// Walks a borrowed semantic tree. The methods for each kind of
+/// expression are passed the fields of the `ExpressionKind` variant
+/// being visited. Visitors which need the type of a node should
+/// override `visit_expression`.
+pub trait Visitor<'a> {
+    /// Visit an Expression
+    ///
+    /// Dispatches to the method for the expression's kind.
+    fn visit_expression(&mut self, expr: &'a Expression) {
+        walk_expression(self, expr)
+    }
+
+    /// Visit an Error Expression
+    fn visit_error(&mut self) {}
+
+    /// Visit an Identifier Reference
+    fn visit_identifier(&mut self, _id: &'a str) {}
+
+    /// Visit the Last Use of a Variable
+    ///
+    /// Moves are reads, so by default they are visited as
+    /// identifiers.
+    fn visit_move(&mut self, id: &'a str) {
+        self.visit_identifier(id)
+    }
+
+    /// Visit a Literal Value
+    fn visit_literal(&mut self, _value: &'a Constant) {}
+
+    /// Visit a Prefix Operator
+    fn visit_prefix(&mut self, _op: PrefixOp, inner: &'a Expression) {
+        self.visit_expression(inner)
+    }
+
+    /// Visit an Infix Operator
+    fn visit_infix(&mut self, lhs: &'a Expression, _op: InfixOp, rhs: &'a Expression) {
+        self.visit_expression(lhs);
+        self.visit_expression(rhs);
+    }
+
+    /// Visit a Call
+    fn visit_call(&mut self, callee: &'a Expression, args: &'a [Expression]) {
+        self.visit_expression(callee);
+        walk_expressions(self, args);
+    }
+
+    /// Visit an Assignment
+    fn visit_assignment(&mut self, _id: &'a str, value: &'a Expression) {
+        self.visit_expression(value)
+    }
+
+    /// Visit an Index Expression
+    fn visit_index(&mut self, indexee: &'a Expression, index: &'a Expression) {
+        self.visit_expression(indexee);
+        self.visit_expression(index);
+    }
+
+    /// Visit an If Expression
+    fn visit_if_else(
+        &mut self,
+        cond: &'a Expression,
+        if_true: &'a Expression,
+        if_false: &'a Expression,
+    ) {
+        self.visit_expression(cond);
+        self.visit_expression(if_true);
+        self.visit_expression(if_false);
+    }
+
+    /// Visit a Function Declaration
+    fn visit_function(&mut self, func: &'a FnDecl) {
+        walk_function(self, func)
+    }
+
+    /// Visit a Loop
+    fn visit_loop(&mut self, cond: &'a Expression, body: &'a Expression) {
+        self.visit_expression(cond);
+        self.visit_expression(body);
+    }
+
+    /// Visit a Break or Continue
+    fn visit_loop_control(&mut self, _control: LoopControl, _depth: usize) {}
+
+    /// Visit a Sequence
+    fn visit_sequence(&mut self, exprs: &'a [Expression]) {
+        walk_expressions(self, exprs)
+    }
+
+    /// Visit a Print Expression
+    fn visit_print(&mut self, inner: &'a Expression) {
+        self.visit_expression(inner)
+    }
+
+    /// Visit a Type Conversion
+    fn visit_convert(&mut self, inner: &'a Expression) {
+        self.visit_expression(inner)
+    }
+
+    /// Visit a Variable Declaration
+    fn visit_declaration(&mut self, decl: &'a VarDecl, is_mut: bool, init: Option<&'a Expression>) {
+        walk_declaration(self, decl, is_mut, init)
+    }
+
+    /// Visit a Variable Declaration or Function Parameter
+    fn visit_var_decl(&mut self, _decl: &'a VarDecl) {}
+}
+
+/// Walk an Expression
+///
+/// Calls the visitor method for the expression's kind.
+pub fn walk_expression<'a, V>(visitor: &mut V, expr: &'a Expression)
+where
+    V: Visitor<'a> + ?Sized,
+{
+    match &expr.kind {
+        ExpressionKind::Error => visitor.visit_error(),
+        ExpressionKind::Identifier(id) => visitor.visit_identifier(id),
+        ExpressionKind::Move(id) => visitor.visit_move(id),
+        ExpressionKind::Literal(value) => visitor.visit_literal(value),
+        ExpressionKind::Prefix(op, inner) => visitor.visit_prefix(*op, inner),
+        ExpressionKind::Infix(lhs, op, rhs) => visitor.visit_infix(lhs, *op, rhs),
+        ExpressionKind::Call(callee, args) => visitor.visit_call(callee, args),
+        ExpressionKind::Assignment(id, value) => visitor.visit_assignment(id, value),
+        ExpressionKind::Index(indexee, index) => visitor.visit_index(indexee, index),
+        ExpressionKind::IfThenElse(cond, if_true, if_false) => {
+            visitor.visit_if_else(cond, if_true, if_false)
+        }
+        ExpressionKind::Function(func) => visitor.visit_function(func),
+        ExpressionKind::Loop(cond, body) => visitor.visit_loop(cond, body),
+        ExpressionKind::LoopControl(control, depth) => visitor.visit_loop_control(*control, *depth),
+        ExpressionKind::Sequence(exprs) => visitor.visit_sequence(exprs),
+        ExpressionKind::Print(inner) => visitor.visit_print(inner),
+        ExpressionKind::Convert(inner) => visitor.visit_convert(inner),
+        ExpressionKind::Declaration(decl, is_mut, init) => {
+            visitor.visit_declaration(decl, *is_mut, init.as_deref())
+        }
+    }
+}
+
+/// Walk a Slice of Expressions
+pub fn walk_expressions<'a, V>(visitor: &mut V, exprs: &'a [Expression])
+where
+    V: Visitor<'a> + ?Sized,
+{
+    for expr in exprs.iter() {
+        visitor.visit_expression(expr);
+    }
+}
+
+/// Walk the Parameters and Body of a Function
+pub fn walk_function<'a, V>(visitor: &mut V, func: &'a FnDecl)
+where
+    V: Visitor<'a> + ?Sized,
+{
+    for param in func.params.iter() {
+        visitor.visit_var_decl(param);
+    }
+    visitor.visit_expression(&func.body);
+}
+
+/// Walk the Declared Variable and Initialiser of a Declaration
+pub fn walk_declaration<'a, V>(
+    visitor: &mut V,
+    decl: &'a VarDecl,
+    _is_mut: bool,
+    init: Option<&'a Expression>,
+) where
+    V: Visitor<'a> + ?Sized,
+{
+    visitor.visit_var_decl(decl);
+    if let Some(init) = init {
+        visitor.visit_expression(init);
+    }
+}
+
+/// Semantic Tree Folder
+///
+/// Consumes a semantic tree and rebuilds it. The default
+/// implementation rebuilds each node unchanged. Passes override
+/// `fold_expression` to transform the nodes they are interested in.
+pub trait Folder {
+    /// Fold an Expression
+    ///
+    /// Folds the children of the expression and returns the rebuilt
+    /// node.
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_children(self, expr)
+    }
+
+    /// Fold a Function Declaration
+    fn fold_function(&mut self, func: FnDecl) -> FnDecl {
+        FnDecl {
+            body: Box::new(self.fold_expression(*func.body)),
+            ..func
+        }
+    }
+}
+
+/// Fold the Children of an Expression
+///
+/// Rebuilds the expression with each of its child expressions
+/// replaced by the result of folding it. The kind of the expression,
+/// its type, and its span are preserved.
+pub fn fold_children<F>(folder: &mut F, expr: Expression) -> Expression
+where
+    F: Folder + ?Sized,
+{
+    use self::ExpressionKind::*;
+
+    let mut fold = |e: Box<Expression>| Box::new(folder.fold_expression(*e));
+    let kind = match expr.kind {
+        kind @ Error
+        | kind @ Identifier(_)
+        | kind @ Move(_)
+        | kind @ Literal(_)
+        | kind @ LoopControl(..) => kind,
+        Prefix(op, inner) => Prefix(op, fold(inner)),
+        Infix(lhs, op, rhs) => Infix(fold(lhs), op, fold(rhs)),
+        Call(callee, args) => {
+            let callee = fold(callee);
+            Call(callee, fold_expressions(folder, args))
+        }
+        Assignment(id, value) => Assignment(id, fold(value)),
+        Index(indexee, index) => Index(fold(indexee), fold(index)),
+        IfThenElse(cond, if_true, if_false) => {
+            IfThenElse(fold(cond), fold(if_true), fold(if_false))
+        }
+        Function(func) => Function(folder.fold_function(func)),
+        Loop(cond, body) => Loop(fold(cond), fold(body)),
+        Sequence(exprs) => Sequence(fold_expressions(folder, exprs)),
+        Print(inner) => Print(fold(inner)),
+        Convert(inner) => Convert(fold(inner)),
+        Declaration(decl, is_mut, init) => Declaration(decl, is_mut, init.map(fold)),
+    };
+    Expression::new(kind, expr.typ).with_span(expr.span)
+}
+
+/// Fold Each Expression in a List
+pub fn fold_expressions<F>(folder: &mut F, exprs: Vec<Expression>) -> Vec<Expression>
+where
+    F: Folder + ?Sized,
+{
+    exprs
+        .into_iter()
+        .map(|e| folder.fold_expression(e))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Binder, Scope};
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use crate::syntax::SyntaxTree;
+
+    fn bind(source: &str) -> Expression {
+        let source = SourceText::new(source);
+        let tree = SyntaxTree::parse(&source);
+        let mut binder = Binder::new(Scope::new());
+        let expr = binder.bind_tree(tree);
+        assert!(binder.take_diagnostics().is_empty());
+        expr
+    }
+
+    #[derive(Default)]
+    struct Collector {
+        idents: Vec<String>,
+        literals: usize,
+    }
+
+    impl<'a> Visitor<'a> for Collector {
+        fn visit_identifier(&mut self, id: &'a str) {
+            self.idents.push(id.into());
+        }
+
+        fn visit_literal(&mut self, _value: &'a Constant) {
+            self.literals += 1;
+        }
+
+        fn visit_var_decl(&mut self, decl: &'a VarDecl) {
+            self.idents.push(format!("decl {}", decl.ident));
+        }
+    }
+
+    #[test]
+    fn visit_walks_every_expression() {
+        let expr = bind(
+            "
+            fn inc(n: Number): Number n + 1 end
+            var i = 0
+            while i < 10
+                i = inc(i)
+            end
+            print 'done' if i == 10 else 'oops'",
+        );
+        let mut collector = Collector::default();
+        collector.visit_expression(&expr);
+        assert_eq!(
+            vec!["decl n", "n", "decl i", "i", "inc", "i", "i"],
+            collector.idents
+        );
+        assert_eq!(6, collector.literals);
+    }
+
+    /// Folder which replaces every number literal with its double
+    struct Doubler;
+
+    impl Folder for Doubler {
+        fn fold_expression(&mut self, expr: Expression) -> Expression {
+            match expr.kind {
+                ExpressionKind::Literal(Constant::Number(n)) => {
+                    Expression::new(ExpressionKind::Literal(Constant::Number(n * 2)), expr.typ)
+                }
+                _ => fold_children(self, expr),
+            }
+        }
+    }
+
+    #[test]
+    fn fold_rebuilds_tree() {
+        let folded = Doubler.fold_expression(bind("print 1 + 2"));
+        assert_eq!(bind("print 2 + 4"), folded);
+    }
+
+    #[test]
+    fn default_fold_is_identity() {
+        struct Identity;
+        impl Folder for Identity {}
+
+        let source = "fn foo(a: Bool): String 'hello' if a else 'world' end print foo(true)";
+        assert_eq!(bind(source), Identity.fold_expression(bind(source)));
+    }
+}
@@ -4,12 +4,20 @@
 //! implementation which recognises a simple lanugage using
 //! Pratt-style operator precedence parsing.
 
+pub mod builder;
+mod edition;
+#[cfg(feature = "arbitrary")]
+pub mod generate;
+pub mod macros;
 mod node;
 pub mod parse;
 pub mod text;
 pub mod tree;
+pub mod visit;
 
+pub use self::edition::Edition;
 pub use self::node::*;
+pub use self::tree::attributes::*;
 pub use self::tree::expression::*;
 pub use self::tree::operators::*;
 pub use self::tree::types::*;
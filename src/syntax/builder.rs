@@ -0,0 +1,371 @@
+//! Syntax Tree Builder
+//!
+//! Constructs syntax trees programmatically rather than by parsing
+//! source text. The builder fabricates the tokens each expression
+//! needs, and interns identifiers in the source the tree is built
+//! for. Fabricated tokens all have a dummy span, so trees from the
+//! builder compare equal to parsed trees of the same shape.
+//!
+//! # Examples
+//!
+//! ```
+//! # use ullage::syntax::builder::TreeBuilder;
+//! # use ullage::syntax::text::SourceText;
+//! # use ullage::syntax::InfixOp;
+//! let source = SourceText::new("");
+//! let b = TreeBuilder::new(&source);
+//!
+//! // fn double(n: Number): Number
+//! //   n * 2
+//! // end
+//! // print double(21)
+//! let double = b.func(
+//!     "double",
+//!     &[("n", "Number")],
+//!     "Number",
+//!     vec![b.infix(b.ident("n"), InfixOp::Mul, b.lit_num(2))],
+//! );
+//! let call = b.print(b.call(b.ident("double"), vec![b.lit_num(21)]));
+//! let root = b.sequence(vec![double, call]);
+//!
+//! let tree = b.finish(root);
+//! assert!(!tree.has_diagnostics());
+//! ```
+
+use std::cell::RefCell;
+
+use super::text::{Ident, SourceText};
+use super::tree::{ExprId, ExpressionArena, Literal, SyntaxTree, Token, TokenKind};
+use super::{
+    BlockBody, DelimItem, Expression, InfixOp, PrefixOp, TypeAnno, TypeRef, TypedId, VarStyle,
+};
+
+/// Syntax Tree Builder
+///
+/// Holds the arena the tree's expressions are added to. Expressions
+/// are added with a shared borrow so that calls to the builder can be
+/// nested in the same shape as the tree they build.
+pub struct TreeBuilder<'a> {
+    /// The source identifiers are interned in
+    source: &'a SourceText,
+    /// The expressions built so far
+    arena: RefCell<ExpressionArena>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    /// Create a Builder for the given Source
+    pub fn new(source: &'a SourceText) -> Self {
+        TreeBuilder {
+            source,
+            arena: Default::default(),
+        }
+    }
+
+    /// Finish the Tree
+    ///
+    /// Consumes the builder and returns a syntax tree with the given
+    /// expression as its root. The tree has no diagnostics.
+    pub fn finish(self, root: ExprId) -> SyntaxTree<'a> {
+        SyntaxTree::new(
+            self.source,
+            self.arena.into_inner(),
+            root,
+            Vec::new(),
+            Token::new(TokenKind::End),
+        )
+    }
+
+    /// Take the Built Expressions
+    ///
+    /// Consumes the builder and returns the arena holding the
+    /// expressions added to it.
+    pub fn into_arena(self) -> ExpressionArena {
+        self.arena.into_inner()
+    }
+
+    /// Add an Expression
+    ///
+    /// Adds an expression which has already been constructed to the
+    /// tree. Used for expressions the builder has no shorthand for.
+    pub fn add(&self, expr: Expression) -> ExprId {
+        self.arena.borrow_mut().alloc(expr)
+    }
+
+    /// Identifier Expression
+    pub fn ident(&self, name: &str) -> ExprId {
+        let id = self.source.intern(name);
+        self.add(Expression::identifier(word(id), id))
+    }
+
+    /// Numeric Literal
+    pub fn lit_num(&self, n: i64) -> ExprId {
+        let token = Token::new(TokenKind::Literal(Literal::Number(n)));
+        self.add(Expression::constant_num(token, n))
+    }
+
+    /// String Literal
+    pub fn lit_str(&self, s: &str) -> ExprId {
+        let token = Token::new(TokenKind::Literal(Literal::RawString(s.into())));
+        self.add(Expression::constant_string(token, s))
+    }
+
+    /// Boolean Literal
+    pub fn lit_bool(&self, b: bool) -> ExprId {
+        let kw = if b { Ident::True } else { Ident::False };
+        self.add(Expression::constant_bool(word(kw), b))
+    }
+
+    /// Prefix Operator Expression
+    pub fn prefix(&self, op: PrefixOp, inner: ExprId) -> ExprId {
+        let kind = match op {
+            PrefixOp::Identity => TokenKind::Plus,
+            PrefixOp::Negate => TokenKind::Minus,
+            PrefixOp::Not => TokenKind::Bang,
+        };
+        self.add(Expression::prefix(Token::new(kind), op, inner))
+    }
+
+    /// Infix Operator Expression
+    pub fn infix(&self, lhs: ExprId, op: InfixOp, rhs: ExprId) -> ExprId {
+        let kind = match op {
+            InfixOp::Assign => TokenKind::Equals,
+            InfixOp::Add => TokenKind::Plus,
+            InfixOp::Sub => TokenKind::Minus,
+            InfixOp::Mul => TokenKind::Star,
+            InfixOp::Div => TokenKind::Slash,
+            InfixOp::Eq => TokenKind::DoubleEquals,
+            InfixOp::NotEq => TokenKind::BangEquals,
+            InfixOp::Lt => TokenKind::LessThan,
+            InfixOp::LtEq => TokenKind::LessThanEqual,
+            InfixOp::Gt => TokenKind::MoreThan,
+            InfixOp::GtEq => TokenKind::MoreThanEqual,
+        };
+        self.add(Expression::infix(lhs, Token::new(kind), op, rhs))
+    }
+
+    /// Function Call Expression
+    pub fn call(&self, callee: ExprId, args: Vec<ExprId>) -> ExprId {
+        self.add(Expression::call(
+            callee,
+            Token::new(TokenKind::OpenBracket),
+            delimited(args),
+            Token::new(TokenKind::CloseBracket),
+        ))
+    }
+
+    /// Index Expression
+    pub fn index(&self, indexee: ExprId, index: ExprId) -> ExprId {
+        self.add(Expression::index(
+            indexee,
+            Token::new(TokenKind::OpenSqBracket),
+            index,
+            Token::new(TokenKind::CloseSqBracket),
+        ))
+    }
+
+    /// Conditional Expression
+    ///
+    /// Builds `then if cond else els`.
+    pub fn if_else(&self, cond: ExprId, then: ExprId, els: ExprId) -> ExprId {
+        self.add(Expression::if_then_else(
+            word(Ident::If),
+            cond,
+            then,
+            word(Ident::Else),
+            els,
+        ))
+    }
+
+    /// While Loop
+    pub fn while_loop(&self, cond: ExprId, body: Vec<ExprId>) -> ExprId {
+        self.add(Expression::loop_while(
+            word(Ident::While),
+            cond,
+            self.block(body),
+        ))
+    }
+
+    /// Until Loop
+    pub fn until_loop(&self, cond: ExprId, body: Vec<ExprId>) -> ExprId {
+        self.add(Expression::loop_while(
+            word(Ident::Until),
+            cond,
+            self.block(body),
+        ))
+    }
+
+    /// Function Definition
+    ///
+    /// Each parameter is given as a pair of its name and the name of
+    /// its type.
+    pub fn func(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        ret: &str,
+        body: Vec<ExprId>,
+    ) -> ExprId {
+        let params = params
+            .iter()
+            .map(|(param, ty)| self.typed_id(param, Some(ty)))
+            .collect();
+        let name = self.source.intern(name);
+        self.add(Expression::function(
+            word(Ident::Fn),
+            (word(name), name),
+            Token::new(TokenKind::OpenBracket),
+            delimited(params),
+            Token::new(TokenKind::CloseBracket),
+            self.type_anno(ret),
+            self.block(body),
+        ))
+    }
+
+    /// Variable Declaration
+    ///
+    /// Declares `name` with `let` or `var` depending on `style`. If
+    /// `ty` is `None` the type is left to be inferred, and if `value`
+    /// is `None` the variable is declared without an initialiser.
+    pub fn declare(
+        &self,
+        style: VarStyle,
+        name: &str,
+        ty: Option<&str>,
+        value: Option<ExprId>,
+    ) -> ExprId {
+        let kw = match style {
+            VarStyle::Immutable => Ident::Let,
+            VarStyle::Mutable => Ident::Var,
+        };
+        let id = self.typed_id(name, ty);
+        self.add(match value {
+            Some(value) => {
+                Expression::declaration(word(kw), id, style, Token::new(TokenKind::Equals), value)
+            }
+            None => Expression::uninitialised_declaration(word(kw), id, style),
+        })
+    }
+
+    /// Print Expression
+    pub fn print(&self, inner: ExprId) -> ExprId {
+        self.add(Expression::print(word(Ident::Print), inner))
+    }
+
+    /// Grouping Expression
+    ///
+    /// Wraps the inner expression in brackets.
+    pub fn group(&self, inner: ExprId) -> ExprId {
+        self.add(Expression::grouping(
+            Token::new(TokenKind::OpenBracket),
+            inner,
+            Token::new(TokenKind::CloseBracket),
+        ))
+    }
+
+    /// Sequence Expression
+    pub fn sequence(&self, exprs: Vec<ExprId>) -> ExprId {
+        self.add(Expression::sequence(exprs))
+    }
+
+    /// Block Body
+    ///
+    /// Wraps the expressions in a sequence closed by `end`.
+    pub fn block(&self, contents: Vec<ExprId>) -> BlockBody {
+        BlockBody {
+            contents: self.sequence(contents),
+            close: word(Ident::End),
+        }
+    }
+
+    /// Type Reference
+    ///
+    /// References the type with the given name. The name `()`
+    /// refers to the unit type.
+    pub fn type_ref(&self, name: &str) -> TypeRef {
+        if name == "()" {
+            TypeRef::unit(
+                Token::new(TokenKind::OpenBracket),
+                Token::new(TokenKind::CloseBracket),
+            )
+        } else {
+            TypeRef::simple(word(self.source.intern(name)))
+        }
+    }
+
+    /// Type Annotation
+    ///
+    /// Annotates with the type with the given name, as `type_ref`.
+    pub fn type_anno(&self, name: &str) -> TypeAnno {
+        TypeAnno::new(Token::new(TokenKind::Colon), self.type_ref(name))
+    }
+
+    /// Identifier with an Optional Type
+    pub fn typed_id(&self, name: &str, ty: Option<&str>) -> TypedId {
+        TypedId::from_parts(
+            word(self.source.intern(name)),
+            ty.map(|ty| self.type_anno(ty)),
+        )
+    }
+}
+
+/// Fabricate a Word Token
+fn word(id: Ident) -> Token {
+    Token::new(TokenKind::Word(id))
+}
+
+/// Separate Items with Commas
+fn delimited<T>(items: Vec<T>) -> Vec<DelimItem<T>> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| match i {
+            0 => DelimItem::First(item),
+            _ => DelimItem::Follow(Token::new(TokenKind::Comma), item),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builder_trees_match_parsed_trees() {
+        let source = SourceText::new("var total: Number = 1 + (2)\nprint 'yes' if true else total");
+        let b = TreeBuilder::new(&source);
+        let decl = b.declare(
+            VarStyle::Mutable,
+            "total",
+            Some("Number"),
+            Some(b.infix(b.lit_num(1), InfixOp::Add, b.group(b.lit_num(2)))),
+        );
+        let print = b.print(b.if_else(b.lit_bool(true), b.lit_str("yes"), b.ident("total")));
+        let root = b.sequence(vec![decl, print]);
+        let built = b.finish(root);
+        let parsed = SyntaxTree::parse(&source);
+
+        assert!(!parsed.has_diagnostics());
+        assert_eq!(parsed.tokens(), built.tokens());
+        assert_eq!(parsed.arena().len(), built.arena().len());
+    }
+
+    #[test]
+    fn function_name_is_taken_from_the_given_ident() {
+        let source = SourceText::new("");
+        let b = TreeBuilder::new(&source);
+        let name = source.intern("f");
+        let fun = Expression::function(
+            word(Ident::Fn),
+            (Token::new(TokenKind::Equals), name),
+            Token::new(TokenKind::OpenBracket),
+            Vec::new(),
+            Token::new(TokenKind::CloseBracket),
+            b.type_anno("Number"),
+            b.block(vec![b.lit_num(1)]),
+        );
+        match fun {
+            Expression::Function(f) => assert_eq!(name, f.identifier),
+            other => panic!("expected function, found {:?}", other),
+        }
+    }
+}
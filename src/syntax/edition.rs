@@ -0,0 +1,118 @@
+//! Language Editions
+//!
+//! An edition is a snapshot of the language's grammar and semantics.
+//! Changes which would stop existing programs compiling, such as new
+//! keywords, are only made in a new edition. Programs written for an
+//! older edition keep compiling by asking for it, either with
+//! `--edition` or with the `edition` key in a project's `Ullage.toml`.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Language Edition
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Default)]
+pub enum Edition {
+    /// The original language, without attributes, macros, or loop
+    /// control. `#[` is the start of a comment and `macro`, `break`,
+    /// and `continue` are ordinary identifiers.
+    Edition2018,
+    /// Adds `#[...]` attributes, the `macro` keyword, and labelled
+    /// loops with `break` and `continue`
+    #[default]
+    Edition2026,
+}
+
+impl Edition {
+    /// The Newest Edition
+    pub const LATEST: Edition = Edition::Edition2026;
+
+    /// The Year the Edition is Named After
+    pub fn year(self) -> u32 {
+        match self {
+            Edition::Edition2018 => 2018,
+            Edition::Edition2026 => 2026,
+        }
+    }
+
+    /// Does the Edition Support Attributes?
+    pub fn has_attributes(self) -> bool {
+        self >= Edition::Edition2026
+    }
+
+    /// Does the Edition Support Macros?
+    pub fn has_macros(self) -> bool {
+        self >= Edition::Edition2026
+    }
+
+    /// Does the Edition Support `break`, `continue`, and Loop Labels?
+    pub fn has_loop_control(self) -> bool {
+        self >= Edition::Edition2026
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Edition::Edition2018 => "2018",
+            Edition::Edition2026 => "2026",
+        })
+    }
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    /// Parse an Edition from its Year
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "2018" => Ok(Edition::Edition2018),
+            "2026" => Ok(Edition::Edition2026),
+            _ => Err(format!("unknown edition '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::text::SourceText;
+    use crate::syntax::{Expression, SyntaxTree};
+
+    #[test]
+    fn parse_editions() {
+        assert_eq!(Ok(Edition::Edition2018), "2018".parse());
+        assert_eq!(Ok(Edition::LATEST), Edition::LATEST.to_string().parse());
+        assert!("2015".parse::<Edition>().is_err());
+        assert_eq!(Edition::LATEST, Edition::default());
+    }
+
+    #[test]
+    fn editions_gate_grammar() {
+        let source = SourceText::new("#[inline]\nlet macro = 1\nprint macro");
+        let old = SyntaxTree::parse_with_edition(&source, Edition::Edition2018);
+        assert!(!old.has_diagnostics(), "{:?}", old.diagnostics());
+        match old.root() {
+            Expression::Sequence(items) => {
+                assert_eq!(2, items.len());
+                assert!(old[items[0]].attributes().is_empty());
+            }
+            root => panic!("expected a sequence, found {:?}", root),
+        }
+
+        let new = SyntaxTree::parse_with_edition(&source, Edition::Edition2026);
+        assert!(new.has_diagnostics());
+
+        let source = SourceText::new("print break");
+        let printed = |tree: &SyntaxTree| match tree.root() {
+            Expression::Sequence(items) => match &tree[items[0]] {
+                Expression::Print(print) => tree[print.inner].clone(),
+                expr => panic!("expected a print, found {:?}", expr),
+            },
+            root => panic!("expected a sequence, found {:?}", root),
+        };
+        let old = SyntaxTree::parse_with_edition(&source, Edition::Edition2018);
+        assert!(matches!(printed(&old), Expression::Identifier(_)));
+        let new = SyntaxTree::parse_with_edition(&source, Edition::Edition2026);
+        assert!(matches!(printed(&new), Expression::LoopControl(_)));
+    }
+}
@@ -0,0 +1,256 @@
+//! Random Syntax Trees
+//!
+//! Generates syntax trees from the raw bytes of an `arbitrary`
+//! `Unstructured`, for use in property tests and fuzzing. The trees
+//! are built with a `TreeBuilder`.
+//!
+//! Only trees which the parser could have produced are generated.
+//! Operands which aren't atoms are grouped, and each statement in a
+//! sequence starts with a word so it can't be read as part of the
+//! statement before it. This means that writing a generated tree's
+//! tokens out and parsing them gives back the same tree. The trees
+//! don't have to make sense though, names are often undefined and
+//! types often mismatched.
+//!
+//! This module is only available with the `arbitrary` feature.
+
+use arbitrary::{Result, Unstructured};
+
+use super::builder::TreeBuilder;
+use super::text::SourceText;
+use super::tree::{ExprId, SyntaxTree};
+use super::{InfixOp, PrefixOp, VarStyle};
+
+/// The Deepest Statements and Expressions are Nested
+const MAX_DEPTH: u32 = 4;
+
+/// Names to Use for Variables, Functions, and Parameters
+const NAMES: &[&str] = &["a", "b", "count", "total", "中"];
+
+/// Names of the Types to Reference
+///
+/// Includes a type which is never defined.
+const TYPES: &[&str] = &["Number", "String", "Bool", "()", "Widget"];
+
+/// Characters to use in String Literals
+const STRING_CHARS: &[char] = &['a', 'b', 'z', ' ', '1', 'ü', '😀'];
+
+/// The Infix Operators
+///
+/// Assignment isn't included as it's only generated as a statement.
+const INFIX_OPS: &[InfixOp] = &[
+    InfixOp::Add,
+    InfixOp::Sub,
+    InfixOp::Mul,
+    InfixOp::Div,
+    InfixOp::Eq,
+    InfixOp::NotEq,
+    InfixOp::Lt,
+    InfixOp::LtEq,
+    InfixOp::Gt,
+    InfixOp::GtEq,
+];
+
+/// The Prefix Operators
+const PREFIX_OPS: &[PrefixOp] = &[PrefixOp::Identity, PrefixOp::Negate, PrefixOp::Not];
+
+/// Generate a Syntax Tree
+///
+/// Builds a random tree, interning its identifiers in `source`. The
+/// tree's root is a sequence of statements. Once the data in `u` runs
+/// out the rest of the tree is made as small as possible.
+pub fn arbitrary_tree<'a>(
+    u: &mut Unstructured<'_>,
+    source: &'a SourceText,
+) -> Result<SyntaxTree<'a>> {
+    let b = TreeBuilder::new(source);
+    let statements = statements(&b, u, MAX_DEPTH)?;
+    let root = b.sequence(statements);
+    Ok(b.finish(root))
+}
+
+/// Generate a Sequence of Statements
+fn statements(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<Vec<ExprId>> {
+    let count = u.int_in_range(0..=3)?;
+    (0..count).map(|_| statement(b, u, depth)).collect()
+}
+
+/// Generate a Statement
+///
+/// Statements all start with a keyword or an identifier.
+fn statement(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<ExprId> {
+    if depth == 0 {
+        return Ok(b.print(operand(b, u, 0)?));
+    }
+    let depth = depth - 1;
+    Ok(match u.int_in_range(0..=6)? {
+        0 => b.print(expression(b, u, depth)?),
+        1 => {
+            let style = if u.arbitrary()? {
+                VarStyle::Mutable
+            } else {
+                VarStyle::Immutable
+            };
+            let ty = if u.arbitrary()? {
+                Some(*u.choose(TYPES)?)
+            } else {
+                None
+            };
+            let value = if u.arbitrary()? {
+                Some(expression(b, u, depth)?)
+            } else {
+                None
+            };
+            b.declare(style, u.choose(NAMES)?, ty, value)
+        }
+        2 => {
+            let target = b.ident(u.choose(NAMES)?);
+            b.infix(target, InfixOp::Assign, expression(b, u, depth)?)
+        }
+        3 => b.while_loop(expression(b, u, depth)?, statements(b, u, depth)?),
+        4 => b.until_loop(expression(b, u, depth)?, statements(b, u, depth)?),
+        5 => {
+            let params = (0..u.int_in_range(0..=2)?)
+                .map(|_| Ok((*u.choose(NAMES)?, *u.choose(TYPES)?)))
+                .collect::<Result<Vec<_>>>()?;
+            let name = u.choose(NAMES)?;
+            let ret = u.choose(TYPES)?;
+            b.func(name, &params, ret, statements(b, u, depth)?)
+        }
+        _ => call(b, u, depth)?,
+    })
+}
+
+/// Generate an Expression
+///
+/// The expression may have infix operators or a condition at its top
+/// level, so should only be used where the grammar accepts any
+/// expression.
+fn expression(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<ExprId> {
+    if depth == 0 {
+        return operand(b, u, 0);
+    }
+    let depth = depth - 1;
+    Ok(match u.int_in_range(0..=3)? {
+        0 => {
+            let lhs = operand(b, u, depth)?;
+            let op = *u.choose(INFIX_OPS)?;
+            b.infix(lhs, op, operand(b, u, depth)?)
+        }
+        1 => b.prefix(*u.choose(PREFIX_OPS)?, simple_operand(b, u, depth)?),
+        2 => {
+            let then = operand(b, u, depth)?;
+            let cond = expression(b, u, depth)?;
+            b.if_else(cond, then, expression(b, u, depth)?)
+        }
+        _ => operand(b, u, depth)?,
+    })
+}
+
+/// Generate an Operand
+///
+/// Operands bind tighter than any infix operator, so can be used on
+/// either side of one.
+fn operand(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<ExprId> {
+    if depth == 0 || u.arbitrary()? {
+        return simple_operand(b, u, depth);
+    }
+    Ok(if u.arbitrary()? {
+        call(b, u, depth - 1)?
+    } else {
+        let indexee = b.ident(u.choose(NAMES)?);
+        b.index(indexee, expression(b, u, depth - 1)?)
+    })
+}
+
+/// Generate a Simple Operand
+///
+/// Simple operands can also follow a prefix operator. Calls and
+/// indexes can't, as the operator would apply only to the callee.
+fn simple_operand(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<ExprId> {
+    Ok(match u.int_in_range(0..=4)? {
+        0 => b.lit_num(u.int_in_range(0..=i64::MAX)?),
+        1 => {
+            let len = u.int_in_range(0..=4)?;
+            let s = (0..len)
+                .map(|_| u.choose(STRING_CHARS).copied())
+                .collect::<Result<String>>()?;
+            b.lit_str(&s)
+        }
+        2 => b.lit_bool(u.arbitrary()?),
+        3 if depth > 0 => b.group(expression(b, u, depth - 1)?),
+        _ => b.ident(u.choose(NAMES)?),
+    })
+}
+
+/// Generate a Function Call
+fn call(b: &TreeBuilder<'_>, u: &mut Unstructured<'_>, depth: u32) -> Result<ExprId> {
+    let callee = b.ident(u.choose(NAMES)?);
+    let args = (0..u.int_in_range(0..=2)?)
+        .map(|_| expression(b, u, depth))
+        .collect::<Result<_>>()?;
+    Ok(b.call(callee, args))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sem::{Binder, Scope};
+
+    /// Run a Check Against Many Generated Trees
+    ///
+    /// The data for each tree comes from a fixed sequence of
+    /// pseudo-random bytes, so failures can be reproduced.
+    fn check_generated<F>(check: F)
+    where
+        F: Fn(SyntaxTree<'_>),
+    {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..500 {
+            let data: Vec<u8> = (0..512)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let source = SourceText::new("");
+            let tree = arbitrary_tree(&mut Unstructured::new(&data), &source).unwrap();
+            check(tree);
+        }
+    }
+
+    #[test]
+    fn generated_trees_reparse_to_equal_trees() {
+        check_generated(|tree| {
+            let text = tree.to_token_string();
+            let reparsed_source = SourceText::new(text.as_str());
+            let reparsed = SyntaxTree::parse(&reparsed_source);
+
+            // Identifiers are interned in different sources, so
+            // the trees are compared by their tokens' text and dumps.
+            assert!(!reparsed.has_diagnostics(), "{}", text);
+            assert_eq!(text, reparsed.to_token_string());
+            let mut dumped = Vec::new();
+            tree.write_to(&mut dumped).unwrap();
+            let mut redumped = Vec::new();
+            reparsed.write_to(&mut redumped).unwrap();
+            assert_eq!(
+                String::from_utf8(dumped).unwrap(),
+                String::from_utf8(redumped).unwrap(),
+                "{}",
+                text
+            );
+        });
+    }
+
+    #[test]
+    fn generated_trees_bind() {
+        check_generated(|tree| {
+            let mut binder = Binder::new(Scope::new());
+            binder.bind_tree(tree);
+            binder.take_diagnostics();
+        });
+    }
+}
@@ -0,0 +1,426 @@
+//! Macro Expansion
+//!
+//! Macros are templates for expressions which are expanded wherever
+//! they are called. Expansion happens after parsing and before
+//! binding, and produces a tree without any macro definitions or
+//! calls in it:
+//!
+//! ```text
+//! macro square(x)
+//!     x * x
+//! end
+//! print square(4)
+//! ```
+//!
+//! Each use of a parameter in the body of a macro is replaced by the
+//! expression passed for it. Arguments which are plain identifiers
+//! can also be used to name things, such as functions, parameters,
+//! variables, and types, so macros can generate items:
+//!
+//! ```text
+//! macro wrapper(name, T)
+//!     fn name(value: T): T value end
+//! end
+//! wrapper(identity, Number)
+//! ```
+//!
+//! Expansion isn't hygienic. Names in the body of a macro refer to
+//! whatever they resolve to where the macro is called. Macros can
+//! only be defined at the top level of a source, and can be called
+//! before they are defined.
+
+use std::collections::hash_map::{Entry, HashMap};
+
+use super::text::{Ident, SourceText, Span};
+use super::tree::{ExprId, ExpressionArena, SyntaxTree, TokenKind};
+use super::{DelimItem, Expression, MacroExpression, SyntaxNode, TypeRef, TypedId};
+use crate::diag::{Code, Diagnostic};
+
+/// The Deepest Macros can Expand Inside Each Other
+pub const RECURSION_LIMIT: usize = 64;
+
+/// Expand the Macros in a Tree
+///
+/// Returns the expanded tree, along with the diagnostics raised while
+/// expanding it. The diagnostics already in the tree are kept. Trees
+/// without any macro definitions are returned as they are.
+pub fn expand(tree: SyntaxTree<'_>) -> (SyntaxTree<'_>, Vec<Diagnostic>) {
+    let has_macros = tree
+        .arena()
+        .iter()
+        .any(|expr| matches!(expr, Expression::Macro(_)));
+    if !has_macros {
+        return (tree, Vec::new());
+    }
+
+    let source = tree.source();
+    let (arena, root, tree_diagnostics, end) = tree.into_parts();
+    let mut expander = Expander {
+        source,
+        arena,
+        macros: HashMap::new(),
+        depth: 0,
+        diagnostics: Vec::new(),
+    };
+    expander.collect_macros(root);
+    let root = expander.expand(root, &HashMap::new());
+    let root = expander.arena.alloc(expander.arena[root].clone());
+    (
+        SyntaxTree::new(source, expander.arena, root, tree_diagnostics, end),
+        expander.diagnostics,
+    )
+}
+
+/// Expands the macros in a single tree
+struct Expander<'a> {
+    /// The source the tree was parsed from
+    source: &'a SourceText,
+    /// The tree's expressions. Expanded expressions are added to the
+    /// end of the arena.
+    arena: ExpressionArena,
+    /// The macros defined in the tree, by name
+    macros: HashMap<Ident, ExprId>,
+    /// The number of macro calls currently being expanded
+    depth: usize,
+    /// The diagnostics raised so far
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The arguments substituted for each macro parameter
+type Substitution = HashMap<Ident, ExprId>;
+
+impl Expander<'_> {
+    /// Find the Macros Defined in the Tree
+    ///
+    /// Macros at the top level are recorded, and any defined
+    /// elsewhere are reported.
+    fn collect_macros(&mut self, root: ExprId) {
+        let top_level = match &self.arena[root] {
+            Expression::Sequence(items) => items.clone(),
+            _ => vec![root],
+        };
+        let mut top_level_spans = Vec::new();
+        for id in top_level {
+            if let Expression::Macro(m) = &self.arena[id] {
+                top_level_spans.push(id.span());
+                if let Entry::Vacant(entry) = self.macros.entry(m.identifier) {
+                    entry.insert(id);
+                } else {
+                    let message = format!(
+                        "Macro '{}' is already defined",
+                        self.source.interned_value(m.identifier)
+                    );
+                    self.error(message, m.identifier_tok.span(), Code::InvalidMacro);
+                }
+            }
+        }
+        let nested: Vec<_> = self
+            .arena
+            .iter()
+            .filter(|expr| matches!(expr, Expression::Macro(_)))
+            .map(SyntaxNode::span)
+            .filter(|span| !top_level_spans.contains(span))
+            .collect();
+        for span in nested {
+            self.error(
+                "Macros can only be defined at the top level",
+                span,
+                Code::InvalidMacro,
+            );
+        }
+    }
+
+    /// Expand an Expression
+    ///
+    /// Returns the id of the expanded expression. Expressions which
+    /// don't contain any macro calls or parameters are shared rather
+    /// than copied.
+    fn expand(&mut self, id: ExprId, subst: &Substitution) -> ExprId {
+        let mut expr = self.arena[id].clone();
+        let mut changed = false;
+        match &mut expr {
+            Expression::Identifier(i) => {
+                if let Some(&arg) = subst.get(&i.ident) {
+                    return arg;
+                }
+            }
+            Expression::Literal(_) => (),
+            Expression::Prefix(p) => {
+                p.inner = self.expand_child(p.inner, subst, &mut changed);
+            }
+            Expression::Infix(i) => {
+                i.left = self.expand_child(i.left, subst, &mut changed);
+                i.right = self.expand_child(i.right, subst, &mut changed);
+            }
+            Expression::Call(c) => {
+                // The macro being called can be passed in as an
+                // argument too.
+                let callee = match &self.arena[c.callee] {
+                    Expression::Identifier(callee) if subst.contains_key(&callee.ident) => {
+                        self.renamed(callee.ident, subst)
+                    }
+                    Expression::Identifier(callee) => Some(callee.ident),
+                    _ => None,
+                };
+                if let Some(&def) = callee.and_then(|callee| self.macros.get(&callee)) {
+                    let args: Vec<_> = c
+                        .arguments
+                        .iter()
+                        .map(|arg| self.expand(*arg.as_inner(), subst))
+                        .collect();
+                    return self.expand_call(def, id, args);
+                }
+                c.callee = self.expand_child(c.callee, subst, &mut changed);
+                for arg in c.arguments.iter_mut() {
+                    let arg = match arg {
+                        DelimItem::First(arg) | DelimItem::Follow(_, arg) => arg,
+                    };
+                    *arg = self.expand_child(*arg, subst, &mut changed);
+                }
+            }
+            Expression::Index(i) => {
+                i.indexee = self.expand_child(i.indexee, subst, &mut changed);
+                i.index = self.expand_child(i.index, subst, &mut changed);
+            }
+            Expression::IfThenElse(i) => {
+                i.cond = self.expand_child(i.cond, subst, &mut changed);
+                i.if_true = self.expand_child(i.if_true, subst, &mut changed);
+                i.if_false = self.expand_child(i.if_false, subst, &mut changed);
+            }
+            Expression::Function(f) => {
+                if let Some(name) = self.renamed(f.identifier, subst) {
+                    f.identifier = name;
+                    f.identifier_tok.kind = TokenKind::Word(name);
+                    changed = true;
+                }
+                for param in f.params.iter_mut() {
+                    let param = match param {
+                        DelimItem::First(param) | DelimItem::Follow(_, param) => param,
+                    };
+                    changed |= self.rename_typed_id(param, subst);
+                }
+                changed |= self.rename_type(&mut f.return_type.type_ref, subst);
+                f.body.contents = self.expand_child(f.body.contents, subst, &mut changed);
+            }
+            // Definitions are dropped from sequences, so this is only
+            // reached for macros defined where they aren't allowed.
+            Expression::Macro(_) => {
+                return self.arena.alloc(Expression::sequence(Vec::new()));
+            }
+            Expression::Loop(l) => {
+                l.condition = self.expand_child(l.condition, subst, &mut changed);
+                l.body.contents = self.expand_child(l.body.contents, subst, &mut changed);
+            }
+            Expression::LoopControl(_) => (),
+            Expression::Sequence(items) => {
+                let before = items.len();
+                items.retain(|&item| !matches!(self.arena[item], Expression::Macro(_)));
+                changed = items.len() != before;
+                for item in items.iter_mut() {
+                    *item = self.expand_child(*item, subst, &mut changed);
+                }
+            }
+            Expression::Print(p) => {
+                p.inner = self.expand_child(p.inner, subst, &mut changed);
+            }
+            Expression::Declaration(d) => {
+                changed |= self.rename_typed_id(&mut d.id, subst);
+                if let Some(init) = &mut d.initialiser {
+                    init.value = self.expand_child(init.value, subst, &mut changed);
+                }
+            }
+            Expression::Grouping(g) => {
+                g.inner = self.expand_child(g.inner, subst, &mut changed);
+            }
+        }
+        if changed {
+            self.arena.alloc(expr)
+        } else {
+            id
+        }
+    }
+
+    /// Expand a Child Expression
+    ///
+    /// Records whether the child was changed by the expansion.
+    fn expand_child(&mut self, id: ExprId, subst: &Substitution, changed: &mut bool) -> ExprId {
+        let expanded = self.expand(id, subst);
+        *changed |= expanded != id;
+        expanded
+    }
+
+    /// Expand a Call to a Macro
+    ///
+    /// The body of the macro is expanded with each parameter
+    /// replaced by the matching argument. Bodies with a single
+    /// expression expand to just that expression.
+    fn expand_call(&mut self, def: ExprId, call: ExprId, args: Vec<ExprId>) -> ExprId {
+        let mac = self.macro_def(def);
+        let name = self.source.interned_value(mac.identifier);
+        let params: Vec<_> = mac.param_names().collect();
+        let body = mac.body.contents;
+        if params.len() != args.len() {
+            let message = format!(
+                "Wrong number of arguments to macro '{}': expected {}, found {}",
+                name,
+                params.len(),
+                args.len()
+            );
+            self.error(message, call.span(), Code::WrongArgumentCount);
+            return self.arena.alloc(Expression::sequence(Vec::new()));
+        }
+        if self.depth >= RECURSION_LIMIT {
+            let message = format!("Expanding macro '{}' exceeded the recursion limit", name);
+            self.error(message, call.span(), Code::InvalidMacro);
+            return self.arena.alloc(Expression::sequence(Vec::new()));
+        }
+
+        let subst = params.into_iter().zip(args).collect();
+        self.depth += 1;
+        let expanded = self.expand(body, &subst);
+        self.depth -= 1;
+        match &self.arena[expanded] {
+            Expression::Sequence(items) if items.len() == 1 => items[0],
+            _ => expanded,
+        }
+    }
+
+    /// Get the Macro Definition with the Given Id
+    fn macro_def(&self, id: ExprId) -> &MacroExpression {
+        match &self.arena[id] {
+            Expression::Macro(m) => m,
+            _ => unreachable!("macros are only recorded for macro definitions"),
+        }
+    }
+
+    /// Find the New Name for an Identifier
+    ///
+    /// Names are replaced if they are a parameter whose argument is
+    /// a plain identifier.
+    fn renamed(&self, name: Ident, subst: &Substitution) -> Option<Ident> {
+        match &self.arena[*subst.get(&name)?] {
+            Expression::Identifier(arg) => Some(arg.ident),
+            _ => None,
+        }
+    }
+
+    /// Rename a Typed Identifier and its Type
+    ///
+    /// Returns true if anything was renamed.
+    fn rename_typed_id(&self, id: &mut TypedId, subst: &Substitution) -> bool {
+        let mut changed = false;
+        if let Some(name) = self.renamed(id.id, subst) {
+            id.id = name;
+            id.id_tok.kind = TokenKind::Word(name);
+            changed = true;
+        }
+        if let Some(anno) = &mut id.typ {
+            changed |= self.rename_type(&mut anno.type_ref, subst);
+        }
+        changed
+    }
+
+    /// Rename the Type Names in a Type Reference
+    ///
+    /// Returns true if anything was renamed.
+    fn rename_type(&self, ty: &mut TypeRef, subst: &Substitution) -> bool {
+        match ty {
+            TypeRef::Simple(tok) => match tok.kind {
+                TokenKind::Word(id) => match self.renamed(id, subst) {
+                    Some(name) => {
+                        tok.kind = TokenKind::Word(name);
+                        true
+                    }
+                    None => false,
+                },
+                _ => false,
+            },
+            TypeRef::Unit(..) | TypeRef::Missing => false,
+            TypeRef::Tuple(_, types, _) => types.iter_mut().fold(false, |changed, ty| {
+                let ty = match ty {
+                    DelimItem::First(ty) | DelimItem::Follow(_, ty) => ty,
+                };
+                self.rename_type(ty, subst) || changed
+            }),
+            TypeRef::Array(_, inner, _) => self.rename_type(inner, subst),
+        }
+    }
+
+    /// Raise a Diagnostic
+    fn error<S: Into<String>>(&mut self, message: S, span: Span, code: Code) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).with_code(code));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_source(src: &str) -> (String, Vec<Diagnostic>) {
+        let source = SourceText::new(src);
+        let tree = SyntaxTree::parse(&source);
+        assert!(!tree.has_diagnostics(), "{:?}", tree.diagnostics());
+        let (tree, diagnostics) = expand(tree);
+        (tree.to_token_string(), diagnostics)
+    }
+
+    #[test]
+    fn trees_without_macros_are_unchanged() {
+        let (expanded, diagnostics) = expand_source("print square(4)");
+        assert_eq!("print square ( 4 )", expanded);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn expand_expression_macros() {
+        let (expanded, diagnostics) =
+            expand_source("print twice(square(1 + 2))\nmacro square(x) x * x end\nmacro twice(x) square(x) + square(x) end");
+        assert_eq!(
+            "print 1 + 2 * 1 + 2 * 1 + 2 * 1 + 2 + 1 + 2 * 1 + 2 * 1 + 2 * 1 + 2",
+            expanded
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn expand_item_macros() {
+        let (expanded, diagnostics) = expand_source(
+            "macro wrapper(name, T, op)\n  fn name(a: T, b: T): T op(a, b) end\n  print name\nend\nwrapper(add, Number, plus)",
+        );
+        assert_eq!(
+            "fn add ( a : Number , b : Number ) : Number plus ( a , b ) end print add",
+            expanded
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn invalid_macros_are_reported() {
+        let (_, diagnostics) = expand_source(
+            "macro m(a) a end\nmacro m(b) b end\nfn f(): Number\n  macro inner() 1 end\n  m(1, 2)\nend\nmacro forever() forever() end\nforever()",
+        );
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.code, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(Code::InvalidMacro), "Macro 'm' is already defined"),
+                (
+                    Some(Code::InvalidMacro),
+                    "Macros can only be defined at the top level"
+                ),
+                (
+                    Some(Code::WrongArgumentCount),
+                    "Wrong number of arguments to macro 'm': expected 1, found 2"
+                ),
+                (
+                    Some(Code::InvalidMacro),
+                    "Expanding macro 'forever' exceeded the recursion limit"
+                ),
+            ],
+            messages
+        );
+    }
+}
@@ -9,7 +9,7 @@ use super::text::{SourceText, Span};
 pub trait SyntaxNode {
     /// A short description of the node. Used for tree pretty
     /// printing.
-    fn description(&self, source: &SourceText) -> std::borrow::Cow<str>;
+    fn description(&self, source: &SourceText) -> std::borrow::Cow<'_, str>;
 
     /// Get the span this node covers in the tree
     ///
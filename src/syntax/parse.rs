@@ -19,14 +19,20 @@ mod tokeniser;
 #[cfg(test)]
 mod checkparse_tests;
 
-use super::text::{Ident, SourceText, DUMMY_SPAN};
-use super::tree::{Literal, SyntaxTree, Token, TokenKind};
+use super::text::{Ident, Pos, SourceText, Span, DUMMY_SPAN};
+use super::tree::{
+    ExprId, ExpressionArena, Literal, SyntaxTree, Token, TokenKind, TriviaTokenKind,
+};
 use super::{
-    BlockBody, DelimItem, Expression, InfixOp, PrefixOp, TypeAnno, TypeRef, TypedId, VarStyle,
+    Attribute, AttributeArg, AttributeArgs, BlockBody, DelimItem, DocComment, Edition, Expression,
+    InfixOp, LoopControl, LoopLabel, PrefixOp, TypeAnno, TypeRef, TypedId, VarStyle,
 };
-use crate::diag::Diagnostic;
+use crate::diag::{self, Applicability, Code, Diagnostic, Fix};
 use std::iter::Iterator;
-use tokeniser::{TokenStream, Tokeniser};
+use std::mem;
+use tokeniser::TokenStream;
+
+pub use self::tokeniser::Tokeniser;
 
 /// Parser state structure
 ///
@@ -34,22 +40,33 @@ use tokeniser::{TokenStream, Tokeniser};
 /// while parsing takes place. It's used to buffer up the current
 /// token that the parser is looking at. Internally the parser also
 /// buffers up the diagnostics which will be emitted at the end of a
-/// parse.
+/// parse, and the arena the parsed expressions are added to.
 pub(crate) struct Parser<'a> {
     source: &'a SourceText,
     lexer: Tokeniser<'a>,
+    arena: ExpressionArena,
     diagnostics: Vec<Diagnostic>,
     current: Option<Token>,
+    last_end: Pos,
+    edition: Edition,
 }
 
 impl<'a> Parser<'a> {
     /// Create a new Parser from a given source text.
     pub fn new(source: &'a SourceText) -> Self {
+        Parser::with_edition(source, Edition::default())
+    }
+
+    /// Create a Parser for a Given Edition of the Language
+    pub fn with_edition(source: &'a SourceText, edition: Edition) -> Self {
         Parser {
             source,
-            lexer: Tokeniser::new(source),
+            lexer: Tokeniser::with_edition(source, edition),
+            arena: ExpressionArena::new(),
             diagnostics: Vec::new(),
             current: None,
+            last_end: source.start(),
+            edition,
         }
     }
 
@@ -57,12 +74,13 @@ impl<'a> Parser<'a> {
     ///
     /// This transfers the ownership of the buffered diagnostics from
     /// the parser and lexer to a new `Vec`. After calling this the
-    /// parser and lexer diagnostics will be empty.
+    /// parser and lexer diagnostics will be empty. Diagnostics are
+    /// returned in source order.
     fn collect_diagnostics(&mut self) -> Vec<Diagnostic> {
         let mut diagnostics = Vec::new();
         diagnostics.append(&mut self.diagnostics);
         diagnostics.append(self.lexer.diagnostics_mut());
-        diagnostics
+        diag::sort_diagnostics(diagnostics)
     }
 
     /// Peek at the current token
@@ -111,10 +129,12 @@ impl<'a> Parser<'a> {
     /// syntasized `TokenKind::End` tokens.
     #[must_use]
     fn advance(&mut self) -> Token {
-        match self.current.take() {
+        let token = match self.current.take() {
             Some(maybe_token) => maybe_token,
             None => self.lexer.next_token(),
-        }
+        };
+        self.last_end = token.span().end();
+        token
     }
 
     /// Check for expected current token kind
@@ -128,17 +148,40 @@ impl<'a> Parser<'a> {
     /// `current_is` should be used rather than calling expect.
     #[must_use]
     fn expect(&mut self, expected: &TokenKind) -> Token {
-        match self.current() {
-            token if token.kind == *expected => self.advance(),
-            other => {
-                let diagnostic = Diagnostic::new(
-                    format!("expecting: {}, found: {}", expected, other.kind),
-                    other.span(),
-                );
-                self.diagnostics.push(diagnostic);
-                Token::new(expected.clone())
-            }
+        if self.current_is(expected) {
+            return self.advance();
+        }
+        let found = self.current();
+        let mut diagnostic = Diagnostic::new(
+            format!("expecting: {}, found: {}", expected, found.kind),
+            found.span(),
+        )
+        .with_code(Code::UnexpectedToken);
+        if let Some(fix) = self.missing_token_fix(expected) {
+            diagnostic = diagnostic.with_fix(fix);
         }
+        self.diagnostics.push(diagnostic);
+        Token::new(expected.clone())
+    }
+
+    /// Suggest Inserting a Missing Token
+    ///
+    /// Closing brackets and `end` are inserted straight after the
+    /// last token which was parsed. No other token can be inserted
+    /// with confidence as they all need something to follow them.
+    fn missing_token_fix(&self, expected: &TokenKind) -> Option<Fix> {
+        let text = match *expected {
+            TokenKind::Word(Ident::End) => "\nend",
+            TokenKind::CloseBracket => ")",
+            TokenKind::CloseSqBracket => "]",
+            _ => return None,
+        };
+        Some(Fix::new(
+            Span::new_at(self.last_end),
+            text,
+            format!("insert `{}`", text.trim_start()),
+            Applicability::MachineApplicable,
+        ))
     }
 
     /// Parse a syntax tree from the given source text
@@ -152,9 +195,9 @@ impl<'a> Parser<'a> {
         while !self.current_is(&TokenKind::End) {
             expressions.push(self.top_level_expression());
         }
+        let root = self.arena.alloc(Expression::sequence(expressions));
         let end = self.expect(&TokenKind::End);
-        let errors = self.collect_diagnostics();
-        SyntaxTree::new(self.source, Expression::sequence(expressions), errors, end)
+        self.finish_tree(root, end)
     }
 
     /// Parse a single expression into a tree
@@ -163,10 +206,19 @@ impl<'a> Parser<'a> {
     /// production in the grammar if attempting to parse a single
     /// item. e.g. for scripting or testing purposes.
     pub fn parse_single(&mut self) -> SyntaxTree<'a> {
-        let expression = self.top_level_expression();
+        let root = self.top_level_expression();
         let end = self.expect(&TokenKind::End);
+        self.finish_tree(root, end)
+    }
+
+    /// Build the Syntax Tree
+    ///
+    /// Moves the parsed expressions and the buffered diagnostics out
+    /// of the parser and into a new tree with the given root.
+    fn finish_tree(&mut self, root: ExprId, end: Token) -> SyntaxTree<'a> {
         let errors = self.collect_diagnostics();
-        SyntaxTree::new(self.source, expression, errors, end)
+        let arena = mem::take(&mut self.arena);
+        SyntaxTree::new(self.source, arena, root, errors, end)
     }
 
     /// Attempt to parse a single expression
@@ -181,10 +233,12 @@ impl<'a> Parser<'a> {
     ///  * With `Token::MIN_LPB` - To parse a root leve expression.
     ///  * With the binding power taken from a token to parse the
     ///    right hand side of an infix expression.
-    fn expression_with_rbp(&mut self, rbp: u32) -> Expression {
-        let mut left = self.parse_nud();
+    fn expression_with_rbp(&mut self, rbp: u32) -> ExprId {
+        let nud = self.parse_nud();
+        let mut left = self.arena.alloc(nud);
         while self.current_binds_tighter_than(rbp) {
-            left = self.parse_led(left);
+            let led = self.parse_led(left);
+            left = self.arena.alloc(led);
         }
         left
     }
@@ -193,7 +247,7 @@ impl<'a> Parser<'a> {
     ///
     /// Parses a single expression with the binding power set to
     /// `Token::MIN_LBP`.
-    fn top_level_expression(&mut self) -> Expression {
+    fn top_level_expression(&mut self) -> ExprId {
         self.expression_with_rbp(Token::MIN_LBP)
     }
 
@@ -209,7 +263,8 @@ impl<'a> Parser<'a> {
                 let err = Diagnostic::new(
                     format!("expected identifier, found: {:}", kind),
                     current.span(),
-                );
+                )
+                .with_code(Code::ExpectedIdentifier);
                 self.diagnostics.push(err);
                 // by starting this with an invalid character we make
                 // sure we don't clash with a real identifier.
@@ -251,7 +306,8 @@ impl<'a> Parser<'a> {
                 TypeRef::tuple(open, types, close)
             }
             t => {
-                let err = Diagnostic::new(format!("expected type, found: {:?}", t), current.span());
+                let err = Diagnostic::new(format!("expected type, found: {:?}", t), current.span())
+                    .with_code(Code::ExpectedType);
                 self.diagnostics.push(err);
                 TypeRef::missing()
             }
@@ -280,23 +336,146 @@ impl<'a> Parser<'a> {
     /// Attempt to parse a local declaration
     ///
     /// Parses the body of a local variable delcaration (`let` or
-    /// `var`).
+    /// `var`). The initialiser is optional.
     fn declaration(&mut self, var_tok: Token) -> Expression {
         let (id_tok, _) = self.identifier();
         let typ = self.optional_type_anno();
-        let assign_tok = self.expect(&TokenKind::Equals);
-        let rhs = self.top_level_expression();
         let style = if let TokenKind::Word(Ident::Var) = var_tok.kind {
             VarStyle::Mutable
         } else {
             VarStyle::Immutable
         };
-        Expression::declaration(
-            var_tok,
-            TypedId::from_parts(id_tok, typ),
-            style,
-            assign_tok,
-            rhs,
+        let id = TypedId::from_parts(id_tok, typ);
+        if !self.current_is(&TokenKind::Equals) {
+            return Expression::uninitialised_declaration(var_tok, id, style);
+        }
+        let assign_tok = self.expect(&TokenKind::Equals);
+        let rhs = self.top_level_expression();
+        Expression::declaration(var_tok, id, style, assign_tok, rhs)
+    }
+
+    /// Parse an Attribute
+    ///
+    /// Parses the rest of an attribute after its opening `#[`. The
+    /// attribute may have a bracketed list of arguments.
+    fn attribute(&mut self, open: Token) -> Attribute {
+        let (name_tok, name) = self.identifier();
+        let args = self.attribute_args();
+        let close = self.expect(&TokenKind::CloseSqBracket);
+        Attribute {
+            open,
+            name,
+            name_tok,
+            args,
+            close,
+        }
+    }
+
+    /// Parse a List of Attribute Arguments
+    ///
+    /// Returns `None` if the next token doesn't open a bracketed
+    /// list.
+    fn attribute_args(&mut self) -> Option<AttributeArgs> {
+        if !self.current_is(&TokenKind::OpenBracket) {
+            return None;
+        }
+        let open = self.advance();
+        let args = self.delimited(
+            |p| p.attribute_arg(),
+            TokenKind::Comma,
+            TokenKind::CloseBracket,
+        );
+        let close = self.expect(&TokenKind::CloseBracket);
+        Some(AttributeArgs { open, args, close })
+    }
+
+    /// Parse a Single Attribute Argument
+    ///
+    /// Arguments are a name, optionally followed by either `=` and a
+    /// literal value, or a nested list of arguments.
+    fn attribute_arg(&mut self) -> AttributeArg {
+        let (name_tok, name) = self.identifier();
+        let value = if self.current_is(&TokenKind::Equals) {
+            let equals = self.advance();
+            let current = self.current();
+            let value = match current.kind {
+                TokenKind::Literal(_) | TokenKind::Word(_) => self.advance(),
+                ref kind => {
+                    let err = Diagnostic::new(
+                        format!("expected attribute value, found: {}", kind),
+                        current.span(),
+                    )
+                    .with_code(Code::UnexpectedToken);
+                    self.diagnostics.push(err);
+                    Token::new(TokenKind::Literal(Literal::RawString(String::new())))
+                }
+            };
+            Some((equals, value))
+        } else {
+            None
+        };
+        let args = match value {
+            Some(_) => None,
+            None => self.attribute_args(),
+        };
+        AttributeArg {
+            name,
+            name_tok,
+            value,
+            args,
+        }
+    }
+
+    /// Parse an Attributed Expression
+    ///
+    /// Parses the attributes starting with the given `#[` and then
+    /// the function or declaration they apply to. Attributes before
+    /// anything else are reported and kept as skipped trivia on a
+    /// stub expression.
+    fn attributed(&mut self, open: Token) -> Expression {
+        let mut attributes = vec![self.attribute(open)];
+        while self.current_is(&TokenKind::AttributeOpen) {
+            let open = self.advance();
+            attributes.push(self.attribute(open));
+        }
+
+        let doc = DocComment::from_leading(attributes[0].open.leading());
+        if self.current_is_any(&[
+            TokenKind::Word(Ident::Fn),
+            TokenKind::Word(Ident::Let),
+            TokenKind::Word(Ident::Var),
+        ]) {
+            let mut expr = self.parse_nud();
+            let (item_doc, item_attributes) = match &mut expr {
+                Expression::Function(f) => (&mut f.doc, &mut f.attributes),
+                Expression::Declaration(d) => (&mut d.doc, &mut d.attributes),
+                _ => unreachable!("functions and declarations always parse as themselves"),
+            };
+            *item_doc = item_doc.take().or(doc);
+            *item_attributes = attributes;
+            return expr;
+        }
+
+        let span = Span::enclosing(
+            attributes[0].span(),
+            attributes[attributes.len() - 1].span(),
+        );
+        self.diagnostics.push(
+            Diagnostic::new(
+                "attributes can only be applied to functions and declarations",
+                span,
+            )
+            .with_code(Code::InvalidAttribute),
+        );
+        let trivia = attributes
+            .into_iter()
+            .flat_map(Attribute::into_tokens)
+            .flat_map(Token::into_trivia)
+            .collect();
+        let stub_id = self.source.intern("0invalid_ident0");
+        Expression::identifier(
+            Token::new(TokenKind::Word(stub_id)).with_leading_trivia(trivia),
+            stub_id,
         )
     }
 
@@ -311,8 +490,47 @@ impl<'a> Parser<'a> {
             expressions.push(self.top_level_expression());
         }
         BlockBody {
-            contents: Box::new(Expression::sequence(expressions)),
-            close: Box::new(self.expect(&TokenKind::Word(Ident::End))),
+            contents: self.arena.alloc(Expression::sequence(expressions)),
+            close: self.expect(&TokenKind::Word(Ident::End)),
+        }
+    }
+
+    /// Labelled Loop
+    ///
+    /// Parses the rest of a loop which starts with a `name:` label.
+    fn labelled_loop(&mut self, name_tok: Token, name: Ident) -> Expression {
+        let colon = self.advance();
+        let label = LoopLabel {
+            name_tok,
+            name,
+            colon,
+        };
+        let kw_token = if self.current_is(&TokenKind::Word(Ident::Until)) {
+            self.advance()
+        } else {
+            self.expect(&TokenKind::Word(Ident::While))
+        };
+        let condition = self.top_level_expression();
+        let block = self.block();
+        Expression::labelled_loop(label, kw_token, condition, block)
+    }
+
+    /// Loop Control Label
+    ///
+    /// Parses the optional label after a `break` or `continue`. The
+    /// label must be on the same line as the keyword, so that an
+    /// expression on the next line isn't mistaken for one.
+    fn loop_control_label(&mut self) -> Option<(Token, Ident)> {
+        let current = self.current();
+        let on_next_line = current
+            .leading()
+            .iter()
+            .any(|t| t.kind() == TriviaTokenKind::Newline);
+        match current.kind {
+            TokenKind::Word(label @ Ident::Unknown(_)) if !on_next_line => {
+                Some((self.advance(), label))
+            }
+            _ => None,
         }
     }
 
@@ -327,7 +545,7 @@ impl<'a> Parser<'a> {
     /// Ternay Body
     ///
     /// The condition and fallback part of a ternary expression.
-    fn ternary_body(&mut self) -> (Expression, Token, Expression) {
+    fn ternary_body(&mut self) -> (ExprId, Token, ExprId) {
         let condition = self.top_level_expression();
         let else_tok = self.expect(&TokenKind::Word(Ident::Else));
         let fallback = self.top_level_expression();
@@ -339,7 +557,7 @@ impl<'a> Parser<'a> {
     /// This is the parse of the symbol when it has an expression to
     /// the left hand side of it. This is responsible for parsing
     /// infix operators and function calls.
-    fn parse_led(&mut self, lhs: Expression) -> Expression {
+    fn parse_led(&mut self, lhs: ExprId) -> Expression {
         let token = self.advance();
 
         match token.kind {
@@ -367,19 +585,13 @@ impl<'a> Parser<'a> {
             // Function call
             TokenKind::OpenBracket => {
                 let open = token;
-                let mut params = Vec::new();
-                while !self.current_is(&TokenKind::CloseBracket) {
-                    let param = self.top_level_expression();
-                    params.push(param);
-                    if !self.current_is(&TokenKind::CloseBracket) {
-                        // FIXME: Delimited lists. Should fit in with
-                        // tuple parsing. Currently this delimiter
-                        // token is getting lost.
-                        let _delim = self.expect(&TokenKind::Comma);
-                    }
-                }
+                let args = self.delimited(
+                    |p| p.top_level_expression(),
+                    TokenKind::Comma,
+                    TokenKind::CloseBracket,
+                );
                 let close = self.expect(&TokenKind::CloseBracket);
-                Expression::call(lhs, open, params, close)
+                Expression::call(lhs, open, args, close)
             }
 
             // Ternay statement:
@@ -416,12 +628,20 @@ impl<'a> Parser<'a> {
         P: Fn(&mut Parser) -> T,
     {
         let mut res = Vec::new();
-        if !self.current_is(&close) {
+        let stop = [close, TokenKind::End];
+        if !self.current_is_any(&stop) {
             res.push(DelimItem::First(p(self)));
         }
-        while !self.current_is(&close) {
+        while !self.current_is_any(&stop) {
+            let start = self.current().span();
             let delim = self.expect(&delimiter);
             res.push(DelimItem::Follow(delim, p(self)));
+            // Items which fail to parse don't always consume a
+            // token. Stop rather than expecting the same delimiter
+            // forever.
+            if self.current().span() == start {
+                break;
+            }
         }
         res
     }
@@ -438,7 +658,7 @@ impl<'a> Parser<'a> {
         match token.kind {
             TokenKind::Word(Ident::Fn) => {
                 let fn_kw = token;
-                let (_fixme, identifier) = self.identifier();
+                let identifier = self.identifier();
                 let params_open = self.expect(&TokenKind::OpenBracket);
                 let params =
                     self.delimited(|p| p.typed_id(), TokenKind::Comma, TokenKind::CloseBracket);
@@ -455,12 +675,46 @@ impl<'a> Parser<'a> {
                     body,
                 )
             }
+            TokenKind::Word(Ident::Macro) if self.edition.has_macros() => {
+                let macro_kw = token;
+                let identifier = self.identifier();
+                let params_open = self.expect(&TokenKind::OpenBracket);
+                let params = self.delimited(
+                    |p| p.identifier().0,
+                    TokenKind::Comma,
+                    TokenKind::CloseBracket,
+                );
+                let params_close = self.expect(&TokenKind::CloseBracket);
+                let body = self.block();
+                Expression::macro_def(
+                    macro_kw,
+                    identifier,
+                    params_open,
+                    params,
+                    params_close,
+                    body,
+                )
+            }
             TokenKind::Word(Ident::While) | TokenKind::Word(Ident::Until) => {
                 let condition = self.top_level_expression();
                 let block = self.block();
                 Expression::loop_while(token, condition, block)
             }
+            TokenKind::Word(Ident::Break) if self.edition.has_loop_control() => {
+                let label = self.loop_control_label();
+                Expression::loop_control(token, LoopControl::Break, label)
+            }
+            TokenKind::Word(Ident::Continue) if self.edition.has_loop_control() => {
+                let label = self.loop_control_label();
+                Expression::loop_control(token, LoopControl::Continue, label)
+            }
+            TokenKind::Word(name @ Ident::Unknown(_))
+                if self.edition.has_loop_control() && self.current_is(&TokenKind::Colon) =>
+            {
+                self.labelled_loop(token, name)
+            }
             TokenKind::Word(Ident::Let) | TokenKind::Word(Ident::Var) => self.declaration(token),
+            TokenKind::AttributeOpen => self.attributed(token),
             TokenKind::Word(Ident::Print) => {
                 let to_print = self.top_level_expression();
                 Expression::print(token, to_print)
@@ -490,6 +744,7 @@ impl<'a> Parser<'a> {
                 if span != DUMMY_SPAN {
                     let err = if token.kind == TokenKind::End {
                         Diagnostic::new("Expected expression but found end of file", span)
+                            .with_code(Code::ExpectedExpression)
                     } else {
                         Diagnostic::new(
                             format!(
@@ -498,13 +753,18 @@ impl<'a> Parser<'a> {
                             ),
                             span,
                         )
+                        .with_code(Code::ExpectedExpression)
                     };
                     self.diagnostics.push(err);
                 }
 
                 // TODO: Unify this with ID stubbing in identifier.
+                // The skipped token is kept as trivia on the stub so
+                // the tree still covers the full source text.
                 let stub_id = self.source.intern("0invalid_ident0");
-                Expression::identifier(Token::new(TokenKind::Word(stub_id)), stub_id)
+                let stub =
+                    Token::new(TokenKind::Word(stub_id)).with_leading_trivia(token.into_trivia());
+                Expression::identifier(stub, stub_id)
             }
         }
     }
@@ -514,7 +774,7 @@ impl<'a> Parser<'a> {
     /// Given a parsed left hand expression and infix operator parse
     /// the right hand side of that expression. Returns the compound
     /// infix expression.
-    fn infix(&mut self, lhs: Expression, token: Token, op: InfixOp) -> Expression {
+    fn infix(&mut self, lhs: ExprId, token: Token, op: InfixOp) -> Expression {
         let rhs = self.expression_with_rbp(token.lbp());
         Expression::infix(lhs, token, op, rhs)
     }
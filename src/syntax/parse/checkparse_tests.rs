@@ -3,469 +3,542 @@
 //! Tests for the parser which check that a given input matches an
 //! exprexpected parse tree.
 
+use super::super::builder::TreeBuilder;
 use super::super::parse::Parser;
-use super::super::text::{Ident, SourceText};
+use super::super::text::SourceText;
 use super::super::tree::{Literal, Token, TokenKind};
 use super::super::*;
 
 macro_rules! check_parse {
-    ($src:expr, |$source:ident| $expected:expr) => {
+    ($src:expr, |$source:ident, $builder:ident| $expected:expr) => {
         let src: &str = $src;
         let $source = SourceText::new(src);
+        let $builder = TreeBuilder::new(&$source);
+        let expected = $expected;
         let mut parser = Parser::new(&$source);
         let tree = parser.parse_single();
         assert_eq!(false, tree.has_diagnostics());
-        assert_eq!(&$expected, tree.root());
+        assert_same(
+            &$builder.into_arena(),
+            expected,
+            tree.arena(),
+            tree.root_id(),
+        );
     };
-    ($src:expr, $expected:expr) => {
-        check_parse!($src, |t| $expected);
+    ($src:expr, |$builder:ident| $expected:expr) => {
+        check_parse!($src, |_source, $builder| $expected);
     };
 }
 
-/// Creates an Identifier Expression
+/// Check Two Trees Match
 ///
-/// This funciton handles interning the indentifier and creating a
-/// mock token for the idnetifier expression to use.
-///
-/// FIXME: Replace with a proper builder API for trees
-fn mk_ident(source: &SourceText, id: &str) -> Expression {
-    let id = source.intern(id);
-    Expression::identifier(Token::new(TokenKind::Word(id)), id)
-}
-
-/// Create a Simple TypeRef
-///
-/// Takes the given string, interns it and creates a type reference to
-/// that simple type.
-///
-/// FIXME: Replace with a proper builder API for trees
-fn mk_simple_ty(source: &SourceText, simple_name: &str) -> TypeRef {
-    TypeRef::simple(Token::new(TokenKind::Word(source.intern(simple_name))))
-}
-
-/// Stub a Type Annotation
-///
-/// Creates a an annotation with a stubbed `:` token and a fabricated
-/// simple type.
-fn mk_simple_ty_anno(source: &SourceText, simple_name: &str) -> TypeAnno {
-    TypeAnno::new(
-        Token::new(TokenKind::Colon),
-        mk_simple_ty(source, simple_name),
-    )
-}
-
-/// Turns a vector of expressions into a dummy block body by pasting a
-/// stubbed `Ident::End` on the end.
-fn blockify(contents: Vec<Expression>) -> BlockBody {
-    BlockBody {
-        contents: Box::new(Expression::Sequence(contents)),
-        close: Box::new(Token::new(TokenKind::Word(Ident::End))),
+/// Expressions from different arenas can't be compared directly, as
+/// their children are only ids. This compares the fields of each
+/// node and then walks into the children of both trees together.
+fn assert_same(
+    expected: &ExpressionArena,
+    expected_id: ExprId,
+    actual: &ExpressionArena,
+    actual_id: ExprId,
+) {
+    let same = |e: ExprId, a: ExprId| assert_same(expected, e, actual, a);
+    let same_items = |e: &[DelimItem<ExprId>], a: &[DelimItem<ExprId>]| {
+        assert_eq!(e.len(), a.len());
+        for (e, a) in e.iter().zip(a.iter()) {
+            if let (DelimItem::Follow(e_tok, _), DelimItem::Follow(a_tok, _)) = (e, a) {
+                assert_eq!(e_tok, a_tok);
+            }
+            same(*e.as_inner(), *a.as_inner());
+        }
+    };
+    match (&expected[expected_id], &actual[actual_id]) {
+        (Expression::Identifier(e), Expression::Identifier(a)) => assert_eq!(e, a),
+        (Expression::Literal(e), Expression::Literal(a)) => assert_eq!(e, a),
+        (Expression::Prefix(e), Expression::Prefix(a)) => {
+            assert_eq!((&e.op_token, e.op), (&a.op_token, a.op));
+            same(e.inner, a.inner);
+        }
+        (Expression::Infix(e), Expression::Infix(a)) => {
+            assert_eq!((&e.op_token, e.op), (&a.op_token, a.op));
+            same(e.left, a.left);
+            same(e.right, a.right);
+        }
+        (Expression::Call(e), Expression::Call(a)) => {
+            assert_eq!(
+                (&e.open_paren, &e.close_paren),
+                (&a.open_paren, &a.close_paren)
+            );
+            same(e.callee, a.callee);
+            same_items(&e.arguments, &a.arguments);
+        }
+        (Expression::Index(e), Expression::Index(a)) => {
+            assert_eq!(
+                (&e.open_bracket, &e.close_bracket),
+                (&a.open_bracket, &a.close_bracket)
+            );
+            same(e.indexee, a.indexee);
+            same(e.index, a.index);
+        }
+        (Expression::IfThenElse(e), Expression::IfThenElse(a)) => {
+            assert_eq!((&e.if_tok, &e.else_tok), (&a.if_tok, &a.else_tok));
+            same(e.cond, a.cond);
+            same(e.if_true, a.if_true);
+            same(e.if_false, a.if_false);
+        }
+        (Expression::Function(e), Expression::Function(a)) => {
+            assert_eq!(
+                (&e.fn_kw, &e.identifier_tok, &e.params_open, &e.params_close),
+                (&a.fn_kw, &a.identifier_tok, &a.params_open, &a.params_close)
+            );
+            assert_eq!((&e.params, &e.return_type), (&a.params, &a.return_type));
+            assert_eq!(e.body.close, a.body.close);
+            same(e.body.contents, a.body.contents);
+        }
+        (Expression::Macro(e), Expression::Macro(a)) => {
+            assert_eq!(
+                (
+                    &e.macro_kw,
+                    &e.identifier_tok,
+                    &e.params_open,
+                    &e.params_close
+                ),
+                (
+                    &a.macro_kw,
+                    &a.identifier_tok,
+                    &a.params_open,
+                    &a.params_close
+                )
+            );
+            assert_eq!(e.params, a.params);
+            assert_eq!(e.body.close, a.body.close);
+            same(e.body.contents, a.body.contents);
+        }
+        (Expression::Loop(e), Expression::Loop(a)) => {
+            assert_eq!(e.label, a.label);
+            assert_eq!((&e.kw_token, &e.body.close), (&a.kw_token, &a.body.close));
+            same(e.condition, a.condition);
+            same(e.body.contents, a.body.contents);
+        }
+        (Expression::LoopControl(e), Expression::LoopControl(a)) => assert_eq!(e, a),
+        (Expression::Sequence(e), Expression::Sequence(a)) => {
+            assert_eq!(e.len(), a.len());
+            for (&e, &a) in e.iter().zip(a.iter()) {
+                same(e, a);
+            }
+        }
+        (Expression::Print(e), Expression::Print(a)) => {
+            assert_eq!(e.print_tok, a.print_tok);
+            same(e.inner, a.inner);
+        }
+        (Expression::Declaration(e), Expression::Declaration(a)) => {
+            assert_eq!((&e.var_kw, e.style, &e.id), (&a.var_kw, a.style, &a.id));
+            match (&e.initialiser, &a.initialiser) {
+                (Some(e), Some(a)) => {
+                    assert_eq!(e.assignment_tok, a.assignment_tok);
+                    same(e.value, a.value);
+                }
+                (e, a) => assert_eq!(e.is_some(), a.is_some()),
+            }
+        }
+        (Expression::Grouping(e), Expression::Grouping(a)) => {
+            assert_eq!((&e.open_tok, &e.close_tok), (&a.open_tok, &a.close_tok));
+            same(e.inner, a.inner);
+        }
+        (e, a) => panic!("expected {:?}, found {:?}", e, a),
     }
 }
 
 #[test]
 fn parse_simple_string() {
-    check_parse!("hello + 123", |s| Expression::infix(
-        mk_ident(&s, "hello"),
+    check_parse!("hello + 123", |b| b.add(Expression::infix(
+        b.ident("hello"),
         Token::new(TokenKind::Plus),
         InfixOp::Add,
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(123))), 123),
-    ));
+        b.lit_num(123),
+    )));
 }
 
 #[test]
 fn parse_operators() {
-    check_parse!("a = b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+    check_parse!("a = b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::Equals),
         InfixOp::Assign,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a + b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a + b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::Plus),
         InfixOp::Add,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a - b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a - b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::Minus),
         InfixOp::Sub,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a * b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a * b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::Star),
         InfixOp::Mul,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a / b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a / b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::Slash),
         InfixOp::Div,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a == b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a == b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::DoubleEquals),
         InfixOp::Eq,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a != b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a != b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::BangEquals),
         InfixOp::NotEq,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a < b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a < b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::LessThan),
         InfixOp::Lt,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a <= b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a <= b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::LessThanEqual),
         InfixOp::LtEq,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a > b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a > b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::MoreThan),
         InfixOp::Gt,
-        mk_ident(&s, "b"),
-    ));
-    check_parse!("a >= b", |s| Expression::infix(
-        mk_ident(&s, "a"),
+        b.ident("b"),
+    )));
+    check_parse!("a >= b", |b| b.add(Expression::infix(
+        b.ident("a"),
         Token::new(TokenKind::MoreThanEqual),
         InfixOp::GtEq,
-        mk_ident(&s, "b"),
-    ));
+        b.ident("b"),
+    )));
 }
 
 #[test]
 fn parse_with_precedence() {
-    check_parse!(
-        "1 + 2 * 3",
-        Expression::infix(
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-            Token::new(TokenKind::Plus),
-            InfixOp::Add,
-            Expression::infix(
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-                Token::new(TokenKind::Star),
-                InfixOp::Mul,
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(3))), 3),
-            ),
-        )
-    );
+    check_parse!("1 + 2 * 3", |b| b.infix(
+        b.lit_num(1),
+        InfixOp::Add,
+        b.infix(b.lit_num(2), InfixOp::Mul, b.lit_num(3))
+    ));
 }
 
 #[test]
 fn parse_prefix_expressions() {
-    check_parse!(
-        "+1 * -2 + +3",
-        Expression::infix(
-            Expression::infix(
-                Expression::prefix(
-                    Token::new(TokenKind::Plus),
-                    PrefixOp::Identity,
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1)
-                ),
-                Token::new(TokenKind::Star),
-                InfixOp::Mul,
-                Expression::prefix(
-                    Token::new(TokenKind::Minus),
-                    PrefixOp::Negate,
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2)
-                ),
-            ),
-            Token::new(TokenKind::Plus),
-            InfixOp::Add,
-            Expression::prefix(
-                Token::new(TokenKind::Plus),
-                PrefixOp::Identity,
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(3))), 3)
-            )
-        )
-    );
-    check_parse!("!a", |s| Expression::prefix(
-        Token::new(TokenKind::Bang),
-        PrefixOp::Not,
-        mk_ident(&s, "a")
-    ));
-    check_parse!("!a != !b", |s| Expression::infix(
-        Expression::prefix(
-            Token::new(TokenKind::Bang),
-            PrefixOp::Not,
-            mk_ident(&s, "a")
+    check_parse!("+1 * -2 + +3", |b| b.infix(
+        b.infix(
+            b.prefix(PrefixOp::Identity, b.lit_num(1)),
+            InfixOp::Mul,
+            b.prefix(PrefixOp::Negate, b.lit_num(2))
         ),
-        Token::new(TokenKind::BangEquals),
+        InfixOp::Add,
+        b.prefix(PrefixOp::Identity, b.lit_num(3))
+    ));
+    check_parse!("!a", |b| b.prefix(PrefixOp::Not, b.ident("a")));
+    check_parse!("!a != !b", |b| b.infix(
+        b.prefix(PrefixOp::Not, b.ident("a")),
         InfixOp::NotEq,
-        Expression::prefix(
-            Token::new(TokenKind::Bang),
-            PrefixOp::Not,
-            mk_ident(&s, "b")
-        ),
+        b.prefix(PrefixOp::Not, b.ident("b"))
     ));
 }
 
 #[test]
 fn parse_simple_call() {
-    check_parse!("foo()", |s| Expression::call(
-        mk_ident(&s, "foo"),
+    check_parse!("foo()", |b| b.add(Expression::call(
+        b.ident("foo"),
         Token::new(TokenKind::OpenBracket),
         Vec::new(),
         Token::new(TokenKind::CloseBracket)
-    ));
+    )));
 }
 
 #[test]
 fn parse_complex_call() {
-    check_parse!("hello(1, 1 + 23, -world)", |s| Expression::call(
-        mk_ident(&s, "hello"),
+    check_parse!("hello(1, 1 + 23, -world)", |b| b.add(Expression::call(
+        b.ident("hello"),
         Token::new(TokenKind::OpenBracket),
         vec![
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-            Expression::infix(
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-                Token::new(TokenKind::Plus),
-                InfixOp::Add,
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(23))), 23),
+            DelimItem::First(b.lit_num(1)),
+            DelimItem::Follow(
+                Token::new(TokenKind::Comma),
+                b.infix(b.lit_num(1), InfixOp::Add, b.lit_num(23))
             ),
-            Expression::prefix(
-                Token::new(TokenKind::Minus),
-                PrefixOp::Negate,
-                mk_ident(&s, "world"),
+            DelimItem::Follow(
+                Token::new(TokenKind::Comma),
+                b.prefix(PrefixOp::Negate, b.ident("world"))
             ),
         ],
         Token::new(TokenKind::CloseBracket),
-    ));
+    )));
 }
 
 #[test]
 fn parse_groups_with_parens() {
-    check_parse!(
-        "(1 + 2) * 3",
-        Expression::infix(
-            Expression::grouping(
-                Token::new(TokenKind::OpenBracket),
-                Expression::infix(
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-                    Token::new(TokenKind::Plus),
-                    InfixOp::Add,
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-                ),
-                Token::new(TokenKind::CloseBracket),
-            ),
-            Token::new(TokenKind::Star),
-            InfixOp::Mul,
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(3))), 3)
-        )
-    );
+    check_parse!("(1 + 2) * 3", |b| b.infix(
+        b.add(Expression::grouping(
+            Token::new(TokenKind::OpenBracket),
+            b.infix(b.lit_num(1), InfixOp::Add, b.lit_num(2)),
+            Token::new(TokenKind::CloseBracket),
+        )),
+        InfixOp::Mul,
+        b.lit_num(3)
+    ));
 }
 
 #[test]
 fn parse_indexing() {
-    check_parse!("hello[world](1, 2[3])", |s| Expression::call(
-        Expression::index(
-            mk_ident(&s, "hello"),
+    check_parse!("hello[world](1, 2[3])", |b| b.add(Expression::call(
+        b.add(Expression::index(
+            b.ident("hello"),
             Token::new(TokenKind::OpenSqBracket),
-            mk_ident(&s, "world"),
+            b.ident("world"),
             Token::new(TokenKind::CloseSqBracket)
-        ),
+        )),
         Token::new(TokenKind::OpenBracket),
         vec![
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-            Expression::index(
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-                Token::new(TokenKind::OpenSqBracket),
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(3))), 3),
-                Token::new(TokenKind::CloseSqBracket)
+            DelimItem::First(b.lit_num(1)),
+            DelimItem::Follow(
+                Token::new(TokenKind::Comma),
+                b.add(Expression::index(
+                    b.lit_num(2),
+                    Token::new(TokenKind::OpenSqBracket),
+                    b.lit_num(3),
+                    Token::new(TokenKind::CloseSqBracket)
+                ))
             ),
         ],
         Token::new(TokenKind::CloseBracket),
-    ));
+    )));
 }
 
 #[test]
 fn parse_ternary_if() {
-    check_parse!("1 if 2 else 3", |s| Expression::if_then_else(
+    check_parse!("1 if 2 else 3", |s, b| b.add(Expression::if_then_else(
         Token::new(TokenKind::Word(s.intern("if"))),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
+        b.lit_num(2),
+        b.lit_num(1),
         Token::new(TokenKind::Word(s.intern("else"))),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(3))), 3),
-    ));
-    check_parse!("hello(1) if foo[23] else world[1 if foo else 2]", |s| {
-        Expression::if_then_else(
+        b.lit_num(3),
+    )));
+    check_parse!("hello(1) if foo[23] else world[1 if foo else 2]", |s, b| {
+        b.add(Expression::if_then_else(
             Token::new(TokenKind::Word(s.intern("if"))),
-            Expression::index(
-                mk_ident(&s, "foo"),
+            b.add(Expression::index(
+                b.ident("foo"),
                 Token::new(TokenKind::OpenSqBracket),
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(23))), 23),
+                b.lit_num(23),
                 Token::new(TokenKind::CloseSqBracket),
-            ),
-            Expression::call(
-                mk_ident(&s, "hello"),
+            )),
+            b.add(Expression::call(
+                b.ident("hello"),
                 Token::new(TokenKind::OpenBracket),
-                vec![Expression::constant_num(
-                    Token::new(TokenKind::Literal(Literal::Number(1))),
-                    1,
-                )],
+                vec![DelimItem::First(b.lit_num(1))],
                 Token::new(TokenKind::CloseBracket),
-            ),
+            )),
             Token::new(TokenKind::Word(s.intern("else"))),
-            Expression::index(
-                mk_ident(&s, "world"),
+            b.add(Expression::index(
+                b.ident("world"),
                 Token::new(TokenKind::OpenSqBracket),
-                Expression::if_then_else(
+                b.add(Expression::if_then_else(
                     Token::new(TokenKind::Word(s.intern("if"))),
-                    mk_ident(&s, "foo"),
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
+                    b.ident("foo"),
+                    b.lit_num(1),
                     Token::new(TokenKind::Word(s.intern("else"))),
-                    Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-                ),
+                    b.lit_num(2),
+                )),
                 Token::new(TokenKind::CloseSqBracket),
-            ),
-        )
+            )),
+        ))
     });
-    check_parse!(
-        "0 unless 1 else 2",
-        Expression::if_then_else(
-            Token::new(TokenKind::Word(s.intern("unless"))),
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(2))), 2),
-            Token::new(TokenKind::Word(s.intern("else"))),
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(0))), 0),
-        )
-    );
+    check_parse!("0 unless 1 else 2", |s, b| b.add(Expression::if_then_else(
+        Token::new(TokenKind::Word(s.intern("unless"))),
+        b.lit_num(1),
+        b.lit_num(2),
+        Token::new(TokenKind::Word(s.intern("else"))),
+        b.lit_num(0),
+    )));
 }
 
 #[test]
 fn parse_unicode_identifiers() {
-    check_parse!("  übåℝ * ßeåk  ", |s| Expression::infix(
-        mk_ident(&s, "übåℝ"),
-        Token::new(TokenKind::Star),
+    check_parse!("  übåℝ * ßeåk  ", |b| b.infix(
+        b.ident("übåℝ"),
         InfixOp::Mul,
-        mk_ident(&s, "ßeåk"),
+        b.ident("ßeåk")
     ));
 }
 
 #[test]
 fn parse_function_def() {
-    check_parse!("fn test() :Num 100 end", |s| Expression::function(
-        Token::new(TokenKind::Word(s.intern("fn"))),
-        s.intern("test"),
-        Token::new(TokenKind::OpenBracket),
-        Vec::new(),
-        Token::new(TokenKind::CloseBracket),
-        mk_simple_ty_anno(&s, "Num"),
-        blockify(vec![Expression::constant_num(
-            Token::new(TokenKind::Literal(Literal::Number(100))),
-            100
-        )])
+    check_parse!("fn test() :Num 100 end", |s, b| b.add(
+        Expression::function(
+            Token::new(TokenKind::Word(s.intern("fn"))),
+            (
+                Token::new(TokenKind::Word(s.intern("test"))),
+                s.intern("test"),
+            ),
+            Token::new(TokenKind::OpenBracket),
+            Vec::new(),
+            Token::new(TokenKind::CloseBracket),
+            b.type_anno("Num"),
+            b.block(vec![b.lit_num(100)])
+        )
     ));
     check_parse!(
         "fn ünécød3() :Num
                 0 if 74 else 888
              end",
-        |s| Expression::function(
+        |s, b| b.add(Expression::function(
             Token::new(TokenKind::Word(s.intern("fn"))),
-            s.intern("ünécød3"),
+            (
+                Token::new(TokenKind::Word(s.intern("ünécød3"))),
+                s.intern("ünécød3"),
+            ),
             Token::new(TokenKind::OpenBracket),
             Vec::new(),
             Token::new(TokenKind::CloseBracket),
-            mk_simple_ty_anno(&s, "Num"),
-            blockify(vec![Expression::if_then_else(
+            b.type_anno("Num"),
+            b.block(vec![b.add(Expression::if_then_else(
                 Token::new(TokenKind::Word(s.intern("if"))),
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(74))), 74),
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(0))), 0),
+                b.lit_num(74),
+                b.lit_num(0),
                 Token::new(TokenKind::Word(s.intern("else"))),
-                Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(888))), 888),
-            )])
-        )
+                b.lit_num(888),
+            ))])
+        ))
     );
 }
 
 #[test]
 fn parse_while_loop() {
-    check_parse!("while 1 end", |s| Expression::loop_while(
+    check_parse!("while 1 end", |s, b| b.add(Expression::loop_while(
         Token::new(TokenKind::Word(s.intern("while"))),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1))), 1),
-        blockify(Vec::new())
-    ));
-    check_parse!("while 0 44 234 end", |s| Expression::loop_while(
+        b.lit_num(1),
+        b.block(Vec::new())
+    )));
+    check_parse!("while 0 44 234 end", |s, b| b.add(Expression::loop_while(
         Token::new(TokenKind::Word(s.intern("while"))),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(0))), 0),
-        blockify(vec![
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(44))), 44),
-            Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(234))), 234)
-        ]),
-    ));
+        b.lit_num(0),
+        b.block(vec![b.lit_num(44), b.lit_num(234)]),
+    )));
 }
 
 #[test]
-fn parse_function_with_args() {
-    check_parse!("fn neg(i: Num): Num - i end", |s| Expression::function(
-        Token::new(TokenKind::Word(s.intern("fn"))),
-        s.intern("neg"),
-        Token::new(TokenKind::OpenBracket),
-        vec![DelimItem::First(TypedId::new(
-            Token::new(TokenKind::Word(s.intern("i"))),
-            mk_simple_ty_anno(&s, "Num")
-        ))],
-        Token::new(TokenKind::CloseBracket),
-        mk_simple_ty_anno(&s, "Num"),
-        blockify(vec![Expression::prefix(
-            Token::new(TokenKind::Minus),
-            PrefixOp::Negate,
-            mk_ident(&s, "i"),
-        )])
+fn parse_labelled_loops() {
+    check_parse!("outer: until 1 continue outer end", |s, b| {
+        let label = LoopLabel {
+            name_tok: Token::new(TokenKind::Word(s.intern("outer"))),
+            name: s.intern("outer"),
+            colon: Token::new(TokenKind::Colon),
+        };
+        let body = b.add(Expression::loop_control(
+            Token::new(TokenKind::Word(s.intern("continue"))),
+            LoopControl::Continue,
+            Some((
+                Token::new(TokenKind::Word(s.intern("outer"))),
+                s.intern("outer"),
+            )),
+        ));
+        b.add(Expression::labelled_loop(
+            label,
+            Token::new(TokenKind::Word(s.intern("until"))),
+            b.lit_num(1),
+            b.block(vec![body]),
+        ))
+    });
+    // A label must be on the same line as the `break`.
+    check_parse!("while 1\n  break\n  a\nend", |s, b| b.add(
+        Expression::loop_while(
+            Token::new(TokenKind::Word(s.intern("while"))),
+            b.lit_num(1),
+            b.block(vec![
+                b.add(Expression::loop_control(
+                    Token::new(TokenKind::Word(s.intern("break"))),
+                    LoopControl::Break,
+                    None,
+                )),
+                b.ident("a"),
+            ]),
+        )
     ));
+}
 
-    check_parse!("fn test(i: Num, j, k: String): String i + j + k end", |s| {
+#[test]
+fn parse_function_with_args() {
+    check_parse!("fn neg(i: Num): Num - i end", |s, b| b.add(
         Expression::function(
             Token::new(TokenKind::Word(s.intern("fn"))),
-            s.intern("test"),
+            (
+                Token::new(TokenKind::Word(s.intern("neg"))),
+                s.intern("neg"),
+            ),
             Token::new(TokenKind::OpenBracket),
-            vec![
-                DelimItem::First(TypedId::new(
-                    Token::new(TokenKind::Word(s.intern("i"))),
-                    mk_simple_ty_anno(&s, "Num"),
-                )),
-                DelimItem::Follow(
-                    Token::new(TokenKind::Comma),
-                    TypedId::new_without_type(Token::new(TokenKind::Word(s.intern("j")))),
+            vec![DelimItem::First(TypedId::new(
+                Token::new(TokenKind::Word(s.intern("i"))),
+                b.type_anno("Num")
+            ))],
+            Token::new(TokenKind::CloseBracket),
+            b.type_anno("Num"),
+            b.block(vec![b.prefix(PrefixOp::Negate, b.ident("i"))])
+        )
+    ));
+
+    check_parse!(
+        "fn test(i: Num, j, k: String): String i + j + k end",
+        |s, b| {
+            b.add(Expression::function(
+                Token::new(TokenKind::Word(s.intern("fn"))),
+                (
+                    Token::new(TokenKind::Word(s.intern("test"))),
+                    s.intern("test"),
                 ),
-                DelimItem::Follow(
-                    Token::new(TokenKind::Comma),
-                    TypedId::new(
-                        Token::new(TokenKind::Word(s.intern("k"))),
-                        mk_simple_ty_anno(&s, "String"),
+                Token::new(TokenKind::OpenBracket),
+                vec![
+                    DelimItem::First(TypedId::new(
+                        Token::new(TokenKind::Word(s.intern("i"))),
+                        b.type_anno("Num"),
+                    )),
+                    DelimItem::Follow(
+                        Token::new(TokenKind::Comma),
+                        TypedId::new_without_type(Token::new(TokenKind::Word(s.intern("j")))),
                     ),
-                ),
-            ],
-            Token::new(TokenKind::CloseBracket),
-            mk_simple_ty_anno(&s, "String"),
-            blockify(vec![Expression::infix(
-                Expression::infix(
-                    mk_ident(&s, "i"),
-                    Token::new(TokenKind::Plus),
+                    DelimItem::Follow(
+                        Token::new(TokenKind::Comma),
+                        TypedId::new(
+                            Token::new(TokenKind::Word(s.intern("k"))),
+                            b.type_anno("String"),
+                        ),
+                    ),
+                ],
+                Token::new(TokenKind::CloseBracket),
+                b.type_anno("String"),
+                b.block(vec![b.infix(
+                    b.infix(b.ident("i"), InfixOp::Add, b.ident("j")),
                     InfixOp::Add,
-                    mk_ident(&s, "j"),
-                ),
-                Token::new(TokenKind::Plus),
-                InfixOp::Add,
-                mk_ident(&s, "k"),
-            )]),
-        )
-    });
+                    b.ident("k"),
+                )]),
+            ))
+        }
+    );
 }
 
 #[test]
 fn parse_simple_array_type() {
-    check_parse!("let f: [Num] = 100", |s| Expression::declaration(
+    check_parse!("let f: [Num] = 100", |s, b| b.add(Expression::declaration(
         Token::new(TokenKind::Word(s.intern("let"))),
         TypedId::from_parts(
             Token::new(TokenKind::Word(s.intern("f"))),
@@ -473,48 +546,31 @@ fn parse_simple_array_type() {
                 Token::new(TokenKind::Colon),
                 TypeRef::array(
                     Token::new(TokenKind::OpenSqBracket),
-                    mk_simple_ty(&s, "Num"),
+                    b.type_ref("Num"),
                     Token::new(TokenKind::CloseSqBracket)
                 )
             )),
         ),
         VarStyle::Immutable,
         Token::new(TokenKind::Equals),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(100))), 100),
-    ));
+        b.lit_num(100),
+    )));
 }
 
 #[test]
 fn parse_simple_let() {
-    check_parse!("let foo = 100", |s| Expression::declaration(
+    check_parse!("let foo = 100", |s, b| b.add(Expression::declaration(
         Token::new(TokenKind::Word(s.intern("let"))),
         TypedId::from_parts(Token::new(TokenKind::Word(s.intern("foo"))), None),
         VarStyle::Immutable,
         Token::new(TokenKind::Equals),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(100))), 100),
-    ));
+        b.lit_num(100),
+    )));
 }
 
 #[test]
 fn parse_simple_tuple() {
-    check_parse!("let f: (Num) = 100", |s| Expression::declaration(
-        Token::new(TokenKind::Word(s.intern("let"))),
-        TypedId::from_parts(
-            Token::new(TokenKind::Word(s.intern("f"))),
-            Some(TypeAnno::new(
-                Token::new(TokenKind::Colon),
-                TypeRef::tuple(
-                    Token::new(TokenKind::OpenBracket),
-                    vec![DelimItem::First(mk_simple_ty(&s, "Num"))],
-                    Token::new(TokenKind::CloseBracket)
-                )
-            )),
-        ),
-        VarStyle::Immutable,
-        Token::new(TokenKind::Equals),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(100))), 100),
-    ));
-    check_parse!("let f: (Num, [String]) = 100", |s| Expression::declaration(
+    check_parse!("let f: (Num) = 100", |s, b| b.add(Expression::declaration(
         Token::new(TokenKind::Word(s.intern("let"))),
         TypedId::from_parts(
             Token::new(TokenKind::Word(s.intern("f"))),
@@ -522,91 +578,107 @@ fn parse_simple_tuple() {
                 Token::new(TokenKind::Colon),
                 TypeRef::tuple(
                     Token::new(TokenKind::OpenBracket),
-                    vec![
-                        DelimItem::First(mk_simple_ty(&s, "Num")),
-                        DelimItem::Follow(
-                            Token::new(TokenKind::Comma),
-                            TypeRef::array(
-                                Token::new(TokenKind::OpenSqBracket),
-                                mk_simple_ty(&s, "String"),
-                                Token::new(TokenKind::CloseSqBracket)
-                            )
-                        ),
-                    ],
+                    vec![DelimItem::First(b.type_ref("Num"))],
                     Token::new(TokenKind::CloseBracket)
                 )
             )),
         ),
         VarStyle::Immutable,
         Token::new(TokenKind::Equals),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(100))), 100),
+        b.lit_num(100),
+    )));
+    check_parse!("let f: (Num, [String]) = 100", |s, b| b.add(
+        Expression::declaration(
+            Token::new(TokenKind::Word(s.intern("let"))),
+            TypedId::from_parts(
+                Token::new(TokenKind::Word(s.intern("f"))),
+                Some(TypeAnno::new(
+                    Token::new(TokenKind::Colon),
+                    TypeRef::tuple(
+                        Token::new(TokenKind::OpenBracket),
+                        vec![
+                            DelimItem::First(b.type_ref("Num")),
+                            DelimItem::Follow(
+                                Token::new(TokenKind::Comma),
+                                TypeRef::array(
+                                    Token::new(TokenKind::OpenSqBracket),
+                                    b.type_ref("String"),
+                                    Token::new(TokenKind::CloseSqBracket)
+                                )
+                            ),
+                        ],
+                        Token::new(TokenKind::CloseBracket)
+                    )
+                )),
+            ),
+            VarStyle::Immutable,
+            Token::new(TokenKind::Equals),
+            b.lit_num(100),
+        )
     ));
 }
 
 #[test]
 fn parse_variable_decl() {
-    check_parse!("var foo = 93", |s| Expression::declaration(
+    check_parse!("var foo = 93", |s, b| b.add(Expression::declaration(
         Token::new(TokenKind::Word(s.intern("var"))),
         TypedId::from_parts(Token::new(TokenKind::Word(s.intern("foo"))), None),
         VarStyle::Mutable,
         Token::new(TokenKind::Equals),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(93))), 93),
-    ));
-    check_parse!("var foo_bar: Number = -99999", |s| Expression::declaration(
-        Token::new(TokenKind::Word(s.intern("var"))),
-        TypedId::from_parts(
-            Token::new(TokenKind::Word(s.intern("foo_bar"))),
-            Some(mk_simple_ty_anno(&s, "Number"))
-        ),
-        VarStyle::Mutable,
-        Token::new(TokenKind::Equals),
-        Expression::prefix(
-            Token::new(TokenKind::Minus),
-            PrefixOp::Negate,
-            Expression::constant_num(
-                Token::new(TokenKind::Literal(Literal::Number(99999))),
-                99999
-            )
-        ),
+        b.lit_num(93),
+    )));
+    check_parse!("var foo_bar: Number = -99999", |s, b| b.add(
+        Expression::declaration(
+            Token::new(TokenKind::Word(s.intern("var"))),
+            TypedId::from_parts(
+                Token::new(TokenKind::Word(s.intern("foo_bar"))),
+                Some(b.type_anno("Number"))
+            ),
+            VarStyle::Mutable,
+            Token::new(TokenKind::Equals),
+            b.prefix(PrefixOp::Negate, b.lit_num(99999)),
+        )
     ));
+    check_parse!("var later: String", |s, b| {
+        b.add(Expression::uninitialised_declaration(
+            Token::new(TokenKind::Word(s.intern("var"))),
+            TypedId::from_parts(
+                Token::new(TokenKind::Word(s.intern("later"))),
+                Some(b.type_anno("String")),
+            ),
+            VarStyle::Mutable,
+        ))
+    });
 }
 
 #[test]
 fn parse_print_operator() {
-    check_parse!("print 1334", |s| Expression::print(
+    check_parse!("print 1334", |s, b| b.add(Expression::print(
         Token::new(TokenKind::Word(s.intern("print"))),
-        Expression::constant_num(Token::new(TokenKind::Literal(Literal::Number(1334))), 1334)
-    ));
+        b.lit_num(1334)
+    )));
 }
 
 #[test]
 fn parse_bool_literal() {
-    check_parse!("true", |s| Expression::constant_bool(
+    check_parse!("true", |s, b| b.add(Expression::constant_bool(
         Token::new(TokenKind::Word(s.intern("true"))),
         true
-    ));
-    check_parse!("false", |s| Expression::constant_bool(
+    )));
+    check_parse!("false", |s, b| b.add(Expression::constant_bool(
         Token::new(TokenKind::Word(s.intern("false"))),
         false
-    ));
+    )));
 }
 
 #[test]
 fn parse_string_literal() {
-    check_parse!(
-        "'hello'",
-        Expression::constant_string(
-            Token::new(TokenKind::Literal(Literal::RawString("hello".into()))),
-            "hello"
-        )
-    );
-    check_parse!(
-        "'über ∂elta'",
-        Expression::constant_string(
-            Token::new(TokenKind::Literal(Literal::RawString(
-                "über ∂elta".into()
-            ))),
-            "über ∂elta"
-        )
-    );
+    check_parse!("'hello'", |b| b.add(Expression::constant_string(
+        Token::new(TokenKind::Literal(Literal::RawString("hello".into()))),
+        "hello"
+    )));
+    check_parse!("'über ∂elta'", |b| b.add(Expression::constant_string(
+        Token::new(TokenKind::Literal(Literal::RawString("über ∂elta".into()))),
+        "über ∂elta"
+    )));
 }
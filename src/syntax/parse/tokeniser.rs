@@ -17,8 +17,11 @@
 
 use super::super::text::{Pos, SourceText, Span};
 use super::super::tree::{Literal, Token, TokenKind, TriviaToken, TriviaTokenKind};
-use crate::diag::Diagnostic;
+use super::super::Edition;
+use crate::diag::{Code, Diagnostic};
+use std::convert::TryInto;
 use std::iter::Peekable;
+use std::mem;
 
 /// Token Stream Trait
 ///
@@ -57,17 +60,26 @@ enum RawTokenKind {
     Plain(TokenKind),
     /// A trivia token
     Trivia(TriviaTokenKind),
+    /// A plain syntax token which is malformed. The token is still
+    /// emitted, along with a diagnostic with the given message.
+    Malformed(TokenKind, &'static str, Code),
 }
 
 /// The Raw Tokeniser
 ///
-/// This walks a state machine over the underlying `SourceText` and
-/// returns a sequence of tokens.
+/// This walks a state machine over the bytes of the underlying
+/// `SourceText` and returns a sequence of tokens. ASCII characters
+/// are matched directly from their bytes and only other characters
+/// are decoded, so spans always fall on character boundaries.
 struct RawTokeniser<'t> {
     /// The undering source buffer
     source: &'t SourceText,
-    /// The current position in the source text.
-    pos: Pos,
+    /// The text of the source buffer
+    text: &'t str,
+    /// The current offset in the source text.
+    pos: usize,
+    /// The edition of the language being tokenised
+    edition: Edition,
 }
 
 impl From<TokenKind> for RawTokenKind {
@@ -85,55 +97,84 @@ impl From<TriviaTokenKind> for RawTokenKind {
 impl<'t> RawTokeniser<'t> {
     /// Create a Tokeniser
     pub fn new(source: &'t SourceText) -> Self {
-        let pos = source.start();
-        RawTokeniser { source, pos }
+        let text = source.slice(source.start(), source.end());
+        let pos = source.start().offset();
+        RawTokeniser {
+            source,
+            text,
+            pos,
+            edition: Edition::default(),
+        }
+    }
+
+    /// Get the Character at an Offset
+    ///
+    /// ASCII characters are read straight from the byte at `offset`,
+    /// other characters are decoded from the text.
+    fn char_at(&self, offset: usize) -> Option<char> {
+        let byte = *self.text.as_bytes().get(offset)?;
+        if byte.is_ascii() {
+            Some(byte as char)
+        } else {
+            self.text[offset..].chars().next()
+        }
     }
 
     /// Skip Over Characters
     ///
     /// Evaluates the predicate to find the end of a given token
-    /// stream. Used to find the end of comments, whitespace and other
-    /// variable-length tokens that we don't need to store values for.
-    fn skip_over<P>(&mut self, chars: &mut Iterator<Item = (char, Pos)>, mut pred: P)
+    /// stream. Used to find the end of identifiers, whitespace and
+    /// other variable-length tokens.
+    fn skip_over<P>(&mut self, pred: P)
     where
-        P: FnMut(char) -> bool,
+        P: Fn(char) -> bool,
     {
-        let more = chars.take_while(|(c, _)| pred(*c)).last();
-        if let Some((_, end)) = more {
-            self.pos = end;
+        while let Some(c) = self.char_at(self.pos).filter(|c| pred(*c)) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Skip to a Byte
+    ///
+    /// Moves the position to the next occurrence of `needle`, or to
+    /// the end of the source if there isn't one. Returns `true` if
+    /// the byte was found. The needle must be ASCII so that it can't
+    /// match part of a multi-byte character.
+    fn skip_to(&mut self, needle: u8) -> bool {
+        match find_byte(needle, &self.text.as_bytes()[self.pos..]) {
+            Some(offset) => {
+                self.pos += offset;
+                true
+            }
+            None => {
+                self.pos = self.text.len();
+                false
+            }
         }
     }
 
     /// One-Char Lookahead Token Choice
     ///
-    /// Look at the next character in the stream. Based on the value
-    /// of the character return either `single` or `double`.
+    /// Look at the next byte in the source. Based on the value of the
+    /// byte return either `single` or `double`.
     ///
-    /// If the next character in `chars` is `maybe_next` then the
-    /// character is consumed from the iterator and `double` is
-    /// returned. Otherwise the position state is left as-is and
-    /// `single` is returned.
+    /// If the next byte is `maybe_next` then it is consumed and
+    /// `double` is returned. Otherwise the position state is left
+    /// as-is and `single` is returned.
     ///
     /// This is designed for recognising two-char tokens like `==` and
     /// `!=` where the first character could be a valid token when
     /// taken on its own.
-    fn ch_choice<T, U>(
-        &mut self,
-        chars: &mut Iterator<Item = (char, Pos)>,
-        maybe_next: char,
-        single: T,
-        double: U,
-    ) -> RawTokenKind
+    fn ch_choice<T, U>(&mut self, maybe_next: u8, single: T, double: U) -> RawTokenKind
     where
         T: Into<RawTokenKind>,
         U: Into<RawTokenKind>,
     {
-        match chars.next() {
-            Some((c, e)) if c == maybe_next => {
-                self.pos = e;
-                double.into()
-            }
-            _ => single.into(),
+        if self.text.as_bytes().get(self.pos) == Some(&maybe_next) {
+            self.pos += 1;
+            double.into()
+        } else {
+            single.into()
         }
     }
 }
@@ -143,93 +184,110 @@ impl<'t> Iterator for RawTokeniser<'t> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let start = self.pos;
-        let mut chars = self.source.walk_chars(start);
-        let kind = chars.next().map(|(c, next_pos)| {
-            self.pos = next_pos;
-            match c {
-                '=' => self.ch_choice(&mut chars, '=', TokenKind::Equals, TokenKind::DoubleEquals),
-                '!' => self.ch_choice(&mut chars, '=', TokenKind::Bang, TokenKind::BangEquals),
-                '+' => TokenKind::Plus.into(),
-                '-' => TokenKind::Minus.into(),
-                '*' => TokenKind::Star.into(),
-                '/' => TokenKind::Slash.into(),
-                '(' => TokenKind::OpenBracket.into(),
-                ')' => TokenKind::CloseBracket.into(),
-                '[' => TokenKind::OpenSqBracket.into(),
-                ']' => TokenKind::CloseSqBracket.into(),
-                ',' => TokenKind::Comma.into(),
-                ':' => TokenKind::Colon.into(),
-                '<' => self.ch_choice(
-                    &mut chars,
-                    '=',
-                    TokenKind::LessThan,
-                    TokenKind::LessThanEqual,
-                ),
-                '>' => self.ch_choice(
-                    &mut chars,
-                    '=',
-                    TokenKind::MoreThan,
-                    TokenKind::MoreThanEqual,
-                ),
-                '#' => {
-                    self.skip_over(&mut chars, |c| c != '\n');
-                    TriviaTokenKind::Comment.into()
-                }
-                '\n' => TriviaTokenKind::Newline.into(),
-                '\r' => self.ch_choice(
-                    &mut chars,
-                    '\n',
-                    TriviaTokenKind::Newline,
-                    TriviaTokenKind::Newline,
-                ),
-                '0'..='9' => {
-                    self.skip_over(&mut chars, |c| c >= '0' && c <= '9');
-                    let lex_val = self.source.slice(start, self.pos);
-                    TokenKind::Literal(Literal::Number(lex_val.parse::<i64>().unwrap())).into()
-                }
-                '\'' => {
-                    // So this is a bit hairy. Essentially we keep
-                    // track of when we have seen the closing
-                    // delimiter so we can consume that token. If we
-                    // see an unterminated string literal then emit it
-                    // as junk instead.
-                    let mut seen_end = false;
-                    self.skip_over(&mut chars, |c| {
-                        if seen_end {
-                            false
-                        } else {
-                            seen_end = c == '\'';
-                            true
-                        }
-                    });
-                    let lex_val = self.source.slice(next_pos, self.pos);
-                    if seen_end {
-                        TokenKind::Literal(Literal::RawString(lex_val[..lex_val.len() - 1].into()))
-                            .into()
-                    } else {
-                        TriviaTokenKind::Junk.into()
-                    }
-                }
-                c if c.is_alphabetic() || c == '_' => {
-                    self.skip_over(&mut chars, |c| c.is_alphanumeric() || c == '_');
-                    let lex_val = self.source.slice(start, self.pos);
-                    let ident = self.source.intern(lex_val);
-                    TokenKind::Word(ident).into()
+        let c = self.char_at(start)?;
+        self.pos += c.len_utf8();
+        let kind = match c {
+            '=' => self.ch_choice(b'=', TokenKind::Equals, TokenKind::DoubleEquals),
+            '!' => self.ch_choice(b'=', TokenKind::Bang, TokenKind::BangEquals),
+            '+' => TokenKind::Plus.into(),
+            '-' => TokenKind::Minus.into(),
+            '*' => TokenKind::Star.into(),
+            '/' => TokenKind::Slash.into(),
+            '(' => TokenKind::OpenBracket.into(),
+            ')' => TokenKind::CloseBracket.into(),
+            '[' => TokenKind::OpenSqBracket.into(),
+            ']' => TokenKind::CloseSqBracket.into(),
+            ',' => TokenKind::Comma.into(),
+            ':' => TokenKind::Colon.into(),
+            '<' => self.ch_choice(b'=', TokenKind::LessThan, TokenKind::LessThanEqual),
+            '>' => self.ch_choice(b'=', TokenKind::MoreThan, TokenKind::MoreThanEqual),
+            '#' if self.edition.has_attributes()
+                && self.text.as_bytes().get(self.pos) == Some(&b'[') =>
+            {
+                self.pos += 1;
+                TokenKind::AttributeOpen.into()
+            }
+            '#' => {
+                let kind = if self.text.as_bytes().get(self.pos) == Some(&b'#') {
+                    TriviaTokenKind::DocComment
+                } else {
+                    TriviaTokenKind::Comment
+                };
+                self.skip_to(b'\n');
+                kind.into()
+            }
+            '\n' => TriviaTokenKind::Newline.into(),
+            '\r' => self.ch_choice(b'\n', TriviaTokenKind::Newline, TriviaTokenKind::Newline),
+            '0'..='9' => {
+                self.skip_over(|c| c.is_ascii_digit());
+                let lex_val = &self.text[start..self.pos];
+                match lex_val.parse::<i64>() {
+                    Ok(value) => TokenKind::Literal(Literal::Number(value)).into(),
+                    Err(_) => RawTokenKind::Malformed(
+                        TokenKind::Literal(Literal::Number(0)),
+                        "number literal is too large",
+                        Code::NumberTooLarge,
+                    ),
                 }
-                c if c.is_whitespace() => {
-                    self.skip_over(&mut chars, |c| c != '\r' && c != '\n' && c.is_whitespace());
-                    TriviaTokenKind::Whitespace.into()
+            }
+            '\'' => {
+                // An unterminated string literal runs to the end of
+                // the source and is emitted as junk.
+                let body_start = self.pos;
+                if self.skip_to(b'\'') {
+                    let lex_val = &self.text[body_start..self.pos];
+                    self.pos += 1;
+                    TokenKind::Literal(Literal::RawString(lex_val.into())).into()
+                } else {
+                    TriviaTokenKind::Junk.into()
                 }
-                _ => TriviaTokenKind::Junk.into(),
             }
-        });
-        kind.map(|k| RawToken {
-            kind: k,
-            span: Span::new(start, self.pos),
+            c if c.is_alphabetic() || c == '_' => {
+                self.skip_over(|c| c.is_alphanumeric() || c == '_');
+                let ident = self.source.intern(&self.text[start..self.pos]);
+                TokenKind::Word(ident).into()
+            }
+            c if c.is_whitespace() => {
+                self.skip_over(|c| c != '\r' && c != '\n' && c.is_whitespace());
+                TriviaTokenKind::Whitespace.into()
+            }
+            _ => TriviaTokenKind::Junk.into(),
+        };
+        Some(RawToken {
+            kind,
+            span: Span::new(Pos::from(start), Pos::from(self.pos)).with_file(self.source.file_id()),
         })
     }
 }
 
+/// Find a Byte
+///
+/// Returns the index of the first occurrence of `needle` in
+/// `haystack`. In the style of `memchr` the haystack is searched a
+/// word at a time until a word which may contain the needle is
+/// found, so long comments and strings are skipped quickly.
+fn find_byte(needle: u8, haystack: &[u8]) -> Option<usize> {
+    const WORD: usize = mem::size_of::<usize>();
+    const LOW_BITS: usize = usize::MAX / 0xFF;
+    const HIGH_BITS: usize = LOW_BITS << 7;
+
+    let repeated = LOW_BITS * needle as usize;
+    let mut offset = 0;
+    for chunk in haystack.chunks_exact(WORD) {
+        // Bytes equal to the needle are zero after the XOR. This sets
+        // the high bit of any zero byte in the word.
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap()) ^ repeated;
+        if word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0 {
+            break;
+        }
+        offset += WORD;
+    }
+    haystack[offset..]
+        .iter()
+        .position(|&b| b == needle)
+        .map(|index| offset + index)
+}
+
 /// The Token Iterator
 ///
 /// Lexer which groups trivia to transform a stream of raw tokens into
@@ -247,8 +305,15 @@ pub struct Tokeniser<'t> {
 impl<'t> Tokeniser<'t> {
     /// Construct a new Tokeniser for the given source text
     pub fn new(source: &'t SourceText) -> Self {
+        Tokeniser::with_edition(source, Edition::default())
+    }
+
+    /// Construct a Tokeniser for a Given Edition
+    pub fn with_edition(source: &'t SourceText, edition: Edition) -> Self {
+        let mut raw = RawTokeniser::new(source);
+        raw.edition = edition;
         Tokeniser {
-            inner: RawTokeniser::new(source).peekable(),
+            inner: raw.peekable(),
             diagnostics: Vec::new(),
         }
     }
@@ -277,7 +342,10 @@ impl<'t> Tokeniser<'t> {
         diagnostics: &mut Vec<Diagnostic>,
     ) {
         if kind == TriviaTokenKind::Junk {
-            diagnostics.push(Diagnostic::new("unrecognised character", span));
+            diagnostics.push(
+                Diagnostic::new("unrecognised character", span)
+                    .with_code(Code::UnrecognisedCharacter),
+            );
         }
         trivia.push(TriviaToken::with_span(span, kind));
     }
@@ -289,8 +357,8 @@ impl<'t> Tokeniser<'t> {
     /// tokens.
     fn collect_leading(&mut self) -> Option<Token> {
         let mut leading = Vec::new();
-        while let Some(token) = self.inner.next() {
-            match token.kind {
+        for token in self.inner.by_ref() {
+            let plain_kind = match token.kind {
                 RawTokenKind::Trivia(trivia_kind) => {
                     Self::buffer_trivia(
                         trivia_kind,
@@ -298,22 +366,23 @@ impl<'t> Tokeniser<'t> {
                         &mut leading,
                         &mut self.diagnostics,
                     );
+                    continue;
                 }
-                RawTokenKind::Plain(plain_kind) => {
-                    return Some(
-                        Token::with_span(token.span, plain_kind).with_leading_trivia(leading),
-                    );
+                RawTokenKind::Plain(plain_kind) => plain_kind,
+                RawTokenKind::Malformed(plain_kind, message, code) => {
+                    self.diagnostics
+                        .push(Diagnostic::new(message, token.span).with_code(code));
+                    plain_kind
                 }
-            }
+            };
+            return Some(Token::with_span(token.span, plain_kind).with_leading_trivia(leading));
         }
         if leading.is_empty() {
             None
         } else {
-            let last_pos = leading.last().unwrap().span().end();
-            Some(
-                Token::with_span(Span::new_at(last_pos), TokenKind::End)
-                    .with_leading_trivia(leading),
-            )
+            let last_span = leading.last().unwrap().span();
+            let end_span = Span::new_at(last_span.end()).with_file(last_span.file());
+            Some(Token::with_span(end_span, TokenKind::End).with_leading_trivia(leading))
         }
     }
 
@@ -327,7 +396,7 @@ impl<'t> Tokeniser<'t> {
         let mut trailing = Vec::new();
         while let Some(next) = self.inner.peek() {
             match next.kind {
-                RawTokenKind::Plain(_) => break,
+                RawTokenKind::Plain(_) | RawTokenKind::Malformed(..) => break,
                 RawTokenKind::Trivia(trivia_kind) => {
                     if trivia_kind == TriviaTokenKind::Newline {
                         break;
@@ -408,6 +477,7 @@ mod test {
         check_lex!("]", RawTokenKind::Plain(TokenKind::CloseSqBracket));
         check_lex!(",", RawTokenKind::Plain(TokenKind::Comma));
         check_lex!(":", RawTokenKind::Plain(TokenKind::Colon));
+        check_lex!("#[", RawTokenKind::Plain(TokenKind::AttributeOpen));
         check_lex!("<", RawTokenKind::Plain(TokenKind::LessThan));
         check_lex!("<=", RawTokenKind::Plain(TokenKind::LessThanEqual));
         check_lex!(">", RawTokenKind::Plain(TokenKind::MoreThan));
@@ -435,10 +505,15 @@ mod test {
             "# longer comment",
             RawTokenKind::Trivia(TriviaTokenKind::Comment)
         );
+        check_lex!("# ∆¬∞€#", RawTokenKind::Trivia(TriviaTokenKind::Comment));
+
+        // Doc comments start with two or more `#`s.
+        check_lex!("##", RawTokenKind::Trivia(TriviaTokenKind::DocComment));
         check_lex!(
-            "# ∆¬∞€#",
-            RawTokenKind::Trivia(TriviaTokenKind::Comment)
+            "## Some docs #",
+            RawTokenKind::Trivia(TriviaTokenKind::DocComment)
         );
+        check_lex!("###", RawTokenKind::Trivia(TriviaTokenKind::DocComment));
 
         // We recognise _all_ denominations of newline
         check_lex!("\n", RawTokenKind::Trivia(TriviaTokenKind::Newline));
@@ -460,6 +535,18 @@ mod test {
             "9999",
             RawTokenKind::Plain(TokenKind::Literal(Literal::Number(9999)))
         );
+        check_lex!(
+            "9223372036854775807",
+            RawTokenKind::Plain(TokenKind::Literal(Literal::Number(i64::MAX)))
+        );
+        check_lex!(
+            "9223372036854775808",
+            RawTokenKind::Malformed(
+                TokenKind::Literal(Literal::Number(0)),
+                "number literal is too large",
+                Code::NumberTooLarge
+            )
+        );
     }
 
     #[test]
@@ -539,6 +626,42 @@ mod test {
         );
     }
 
+    #[test]
+    fn find_byte_in_haystacks() {
+        let long = "abcdefgh".repeat(5) + "'" + "ijklmnop";
+        assert_eq!(Some(40), find_byte(b'\'', long.as_bytes()));
+        assert_eq!(Some(0), find_byte(b'a', long.as_bytes()));
+        assert_eq!(Some(48), find_byte(b'p', long.as_bytes()));
+        assert_eq!(None, find_byte(b'#', long.as_bytes()));
+        assert_eq!(None, find_byte(b'#', b""));
+        assert_eq!(Some(2), find_byte(0x80, &[0x7F, 0x00, 0x80, 0xFF]));
+    }
+
+    #[test]
+    fn raw_tokeniser_spans_multibyte_characters() {
+        let src = SourceText::new("\u{2003}ünî = '∆' ¬ # €\n");
+        let tokeniser = RawTokeniser::new(&src);
+        let tokens = tokeniser
+            .map(|t| (t.span.start().offset(), t.span.end().offset()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, 3),
+                (3, 8),
+                (8, 9),
+                (9, 10),
+                (10, 11),
+                (11, 16),
+                (16, 17),
+                (17, 19),
+                (19, 20),
+                (20, 25),
+                (25, 26),
+            ],
+            tokens
+        );
+    }
+
     #[test]
     fn raw_tokenier_collect_returns_expected_tokens() {
         let src = SourceText::new("var foo = 'hello world' + 1");
@@ -8,9 +8,13 @@
 //! and `Location` types.
 
 mod intern;
+#[cfg(unix)]
+mod mapping;
 mod position;
+mod source_map;
 mod source_text;
 
 pub use self::intern::{Ident, Interner};
-pub use self::position::{Pos, Span, DUMMY_SPAN};
+pub use self::position::{FileId, Pos, Span, DUMMY_SPAN};
+pub use self::source_map::SourceMap;
 pub use self::source_text::SourceText;
@@ -4,14 +4,28 @@
 //! that a string can be stored in an `Interner` only once. The
 //! contents of the string can't be accessed without the `Interner`,
 //! but interned strings can be compared for equality quickly.
+//!
+//! Keywords are matched before the interner's table is consulted, and
+//! the names of the builtin types are interned when the `Interner` is
+//! created, so neither ever need to be looked up.
 
 use indexmap::IndexSet;
+use rustc_hash::FxHasher;
+use std::hash::BuildHasherDefault;
+
+/// The Builtin Type Names
+///
+/// Interned by every `Interner` in this order, so their `Ident`s are
+/// known ahead of time.
+const BUILTIN_TYPES: [&str; 3] = ["Number", "String", "Bool"];
 
 /// Interner
 ///
-/// Keeps a list of intered strings and a map to look them up.
+/// Keeps a list of intered strings and a map to look them up. The
+/// map uses the Fx hash, which is much faster than the default hash
+/// for short keys such as identifiers.
 pub struct Interner {
-    lookup: IndexSet<String>,
+    lookup: IndexSet<Box<str>, BuildHasherDefault<FxHasher>>,
 }
 
 /// Interned String
@@ -20,6 +34,10 @@ pub struct Interner {
 /// in here to allow them to be easily matched.
 #[derive(Debug, PartialEq, Hash, Eq, Copy, Clone)]
 pub enum Ident {
+    /// the `break` keyword
+    Break,
+    /// the `continue` keyword
+    Continue,
     /// the `else` keyword
     Else,
     /// the `end` keyword
@@ -32,6 +50,8 @@ pub enum Ident {
     If,
     /// the `let` keyword
     Let,
+    /// the `macro` keyword
+    Macro,
     /// the `print` keyword
     Print,
     /// the `true` keyword
@@ -48,6 +68,25 @@ pub enum Ident {
     Unknown(usize),
 }
 
+impl Ident {
+    /// The name of the builtin `Number` type
+    pub const NUMBER: Ident = Ident::Unknown(0);
+    /// The name of the builtin `String` type
+    pub const STRING: Ident = Ident::Unknown(1);
+    /// The name of the builtin `Bool` type
+    pub const BOOL: Ident = Ident::Unknown(2);
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        let mut lookup = IndexSet::default();
+        for name in BUILTIN_TYPES.iter() {
+            lookup.insert(Box::from(*name));
+        }
+        Interner { lookup }
+    }
+}
+
 impl Interner {
     /// Create an Empty Interner
     pub fn new() -> Self {
@@ -61,12 +100,15 @@ impl Interner {
     /// intern value is allocated and returned.
     pub fn intern(&mut self, value: &str) -> Ident {
         match value {
+            "break" => Ident::Break,
+            "continue" => Ident::Continue,
             "else" => Ident::Else,
             "end" => Ident::End,
             "false" => Ident::False,
             "fn" => Ident::Fn,
             "if" => Ident::If,
             "let" => Ident::Let,
+            "macro" => Ident::Macro,
             "print" => Ident::Print,
             "true" => Ident::True,
             "unless" => Ident::Unless,
@@ -81,23 +123,30 @@ impl Interner {
     ///
     /// When we have checked that the value isn't a known ident this
     /// can be used to create a new `Ident::Unknown` entry in the
-    /// table.
+    /// table. The value is only copied if it hasn't been seen before.
     fn intern_unknown(&mut self, value: &str) -> Ident {
-        let (index, _) = self.lookup.insert_full(value.into());
+        let index = match self.lookup.get_full(value) {
+            Some((index, _)) => index,
+            None => self.lookup.insert_full(value.into()).0,
+        };
         Ident::Unknown(index)
     }
 
     /// Borrow the Interned value
     ///
-    /// Used to conver the interned value back to a string.
+    /// Used to conver the interned value back to a string. Interned
+    /// values are never moved or freed while the `Interner` lives.
     pub fn interned_value(&self, ident: Ident) -> &str {
         match ident {
+            Ident::Break => "break",
+            Ident::Continue => "continue",
             Ident::Else => "else",
             Ident::End => "end",
             Ident::False => "false",
             Ident::Fn => "fn",
             Ident::If => "if",
             Ident::Let => "let",
+            Ident::Macro => "macro",
             Ident::Print => "print",
             Ident::True => "true",
             Ident::Unless => "unless",
@@ -109,14 +158,6 @@ impl Interner {
     }
 }
 
-impl Default for Interner {
-    fn default() -> Self {
-        Interner {
-            lookup: IndexSet::default(),
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
 
@@ -133,5 +174,18 @@ mod test {
         assert_eq!(foo1, foo2);
         assert_ne!(foo1, bar);
         assert_ne!(foo2, bar);
+        assert_eq!("foo", interner.interned_value(foo1));
+    }
+
+    #[test]
+    fn intern_builtin_types_up_front() {
+        let mut interner = Interner::new();
+
+        assert_eq!(Ident::NUMBER, interner.intern("Number"));
+        assert_eq!(Ident::STRING, interner.intern("String"));
+        assert_eq!(Ident::BOOL, interner.intern("Bool"));
+        assert_eq!(Ident::End, interner.intern("end"));
+        assert_eq!("Bool", interner.interned_value(Ident::BOOL));
+        assert_eq!("while", interner.interned_value(Ident::While));
     }
 }
@@ -0,0 +1,85 @@
+//! Memory Mapped Files
+//!
+//! Large source files can be mapped into memory rather than being
+//! read into a buffer. The mapping is read only, and is validated as
+//! UTF-8 once when it is created so the text can be borrowed from it
+//! as a `str` for as long as the mapping lives.
+//!
+//! The mapping shares its pages with the file, so changes made to the
+//! file by other processes can show through. Creating a mapping is
+//! `unsafe` for this reason.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::{ptr, slice, str};
+
+/// Memory Mapped File
+///
+/// Owns a read only mapping of the whole of a file. The mapping is
+/// removed when this is dropped.
+pub struct Mapping {
+    /// The start of the mapped memory
+    ptr: *mut libc::c_void,
+    /// The length of the mapping in bytes
+    len: usize,
+}
+
+// SAFETY: The mapping is never written to by this process, and is
+// owned uniquely by this structure. The creator of the mapping
+// promised the file won't change while it lives, so it can be shared
+// and sent between threads like any other immutable buffer.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Map a File into Memory
+    ///
+    /// Maps the first `len` bytes of `file`, which should be the
+    /// file's length. Returns an error if the file can't be mapped,
+    /// or if its contents aren't valid UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified, by this or any
+    /// other process, until the mapping is dropped. Truncating it
+    /// causes reads of the mapping to fault, and writes to it could
+    /// leave the text as invalid UTF-8.
+    pub unsafe fn map(file: &File, len: usize) -> io::Result<Self> {
+        let ptr = libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mapping = Mapping { ptr, len };
+        str::from_utf8(mapping.bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(mapping)
+    }
+
+    /// Get the Mapped Bytes
+    fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    /// Get the Mapped Text
+    pub fn as_str(&self) -> &str {
+        // SAFETY: The contents were checked to be UTF-8 in `map`, and
+        // the caller of `map` promised they won't change.
+        unsafe { str::from_utf8_unchecked(self.bytes()) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -7,27 +7,56 @@
 ///
 /// Used to represent a position within a the source of a compilation
 /// session.
-#[derive(Debug, PartialEq, Eq, Copy, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
 pub struct Pos(usize);
 
+/// Source File Identifier
+///
+/// Identifies a single `SourceText` within a `SourceMap`. Source texts
+/// which haven't been added to a map have the default, detached, file
+/// ID.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord, Default)]
+pub struct FileId(u32);
+
 /// Source Buffer Span
 ///
 /// A span represents a range of positions within the source. Each
 /// span is deliniated by the start and end `Pos`s. Spans can be
 /// used to identify the extent of lexemes in the AST, and ranges of
-/// interest when emitting error information.
-#[derive(Debug, PartialEq, Copy, Clone)]
+/// interest when emitting error information. Each span also records
+/// the file it belongs to.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub struct Span {
+    file: FileId,
     start: Pos,
     end: Pos,
 }
 
 /// Dummy Span used when no real span is avaiable.
 pub const DUMMY_SPAN: Span = Span {
+    file: FileId::DETACHED,
     start: Pos(0),
     end: Pos(0),
 };
 
+impl FileId {
+    /// The ID of source texts which don't belong to a `SourceMap`
+    pub const DETACHED: FileId = FileId(0);
+
+    /// Create a File ID from its Index
+    ///
+    /// File IDs are allocated by the `SourceMap`. The first file added
+    /// to a map has index 1.
+    pub(crate) fn from_index(index: usize) -> Self {
+        FileId(index as u32)
+    }
+
+    /// Get the Index of this File ID
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 impl Pos {
     /// Get the Byte Offset
     ///
@@ -59,26 +88,39 @@ impl Span {
     /// before the second one. The cursors themselves can be thought
     /// to point 'between' the characters in the buffer.
     pub fn new(start: Pos, end: Pos) -> Self {
-        Span { start, end }
+        Span {
+            file: FileId::DETACHED,
+            start,
+            end,
+        }
     }
 
     /// Create a zero-width span at a given position
     ///
     /// The retunred span has the same start and end position.
     pub fn new_at(pos: Pos) -> Self {
-        Span {
-            start: pos,
-            end: pos,
-        }
+        Span::new(pos, pos)
+    }
+
+    /// Set the File this Span Belongs to
+    ///
+    /// Returns a copy of this span tagged with the given file.
+    pub fn with_file(self, file: FileId) -> Self {
+        Span { file, ..self }
     }
 
     /// Create a `Span` enclosing two existing spans. This makes a new
     /// span with extents that encompas the highest and lowest `Pos`
-    /// in either span.
+    /// in either span. The file is taken from the first span.
     pub fn enclosing(first: Span, second: Span) -> Self {
         let start = std::cmp::min(first.start(), second.start());
         let end = std::cmp::max(first.end(), second.end());
-        Span::new(start, end)
+        Span::new(start, end).with_file(first.file())
+    }
+
+    /// Get the file this span belongs to
+    pub fn file(&self) -> FileId {
+        self.file
     }
 
     /// Get the starting position of this span
@@ -90,6 +132,14 @@ impl Span {
     pub fn end(&self) -> Pos {
         self.end
     }
+
+    /// Does this Span Contain a Position?
+    ///
+    /// Both ends of the span are included so that a cursor placed
+    /// directly after a token is still considered to be within it.
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.start <= pos && pos <= self.end
+    }
 }
 
 #[cfg(test)]
@@ -116,6 +166,15 @@ pub mod test {
         assert_eq!(Pos::from(3), span.end);
     }
 
+    #[test]
+    fn span_contains_its_ends() {
+        let span = Span::new(2.into(), 4.into());
+        assert!(!span.contains(1.into()));
+        assert!(span.contains(2.into()));
+        assert!(span.contains(4.into()));
+        assert!(!span.contains(5.into()));
+    }
+
     #[test]
     fn span_has_start_end() {
         let span_loc = Span::new(12.into(), 43.into());
@@ -124,6 +183,21 @@ pub mod test {
         assert_eq!(43, span_loc.end().offset());
     }
 
+    #[test]
+    fn span_with_file() {
+        let span = Span::new(1.into(), 2.into());
+        assert_eq!(FileId::DETACHED, span.file());
+
+        let file = FileId::from_index(3);
+        let span = span.with_file(file);
+        assert_eq!(file, span.file());
+        assert_eq!(1, span.start().offset());
+        assert_ne!(Span::new(1.into(), 2.into()), span);
+
+        let other = Span::new(5.into(), 7.into()).with_file(file);
+        assert_eq!(file, Span::enclosing(span, other).file());
+    }
+
     #[test]
     fn span_at_pos() {
         let pos = Pos::from(123);
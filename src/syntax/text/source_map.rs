@@ -0,0 +1,130 @@
+//! Source Map
+//!
+//! A source map owns the `SourceText`s for a compilation session and
+//! hands out a stable `FileId` for each one. Spans produced from a
+//! source in the map carry its file ID, so they can be resolved back
+//! to a `(file, line, col)` location even when a session contains
+//! many files.
+
+use super::{FileId, SourceText, Span};
+use std::rc::Rc;
+
+/// Source Map
+///
+/// Holds each source text added to the session. Sources are
+/// reference counted so that syntax trees can borrow from a source
+/// while more files are added to the map.
+#[derive(Default)]
+pub struct SourceMap {
+    /// The files in the map. The file with ID `n` is at index `n - 1`.
+    files: Vec<Rc<SourceText>>,
+}
+
+impl SourceMap {
+    /// Create an Empty Source Map
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a Source Text to the Map
+    ///
+    /// Takes ownership of the source and assigns it a new `FileId`.
+    /// The source is returned so it can be tokenised and parsed.
+    pub fn add(&mut self, mut source: SourceText) -> Rc<SourceText> {
+        source.file_id = FileId::from_index(self.files.len() + 1);
+        let source = Rc::new(source);
+        self.files.push(source.clone());
+        source
+    }
+
+    /// Get a Source Text by ID
+    ///
+    /// Returns `None` if the file isn't part of this map.
+    pub fn get(&self, file: FileId) -> Option<&SourceText> {
+        let index = file.index().checked_sub(1)?;
+        self.files.get(index).map(|f| &**f)
+    }
+
+    /// Find a Source Text by Name
+    pub fn find(&self, name: &str) -> Option<&SourceText> {
+        self.files.iter().map(|f| &**f).find(|f| f.name() == name)
+    }
+
+    /// Get the Number of Files in the Map
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Is the Map Empty?
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Iterate over the Files in the Map
+    pub fn files(&self) -> impl Iterator<Item = &SourceText> {
+        self.files.iter().map(|f| &**f)
+    }
+
+    /// Resolve a Span
+    ///
+    /// Looks up the file the span belongs to and returns it along
+    /// with the `(line, col)` position of the start of the span. If
+    /// the span doesn't belong to a file in this map then `None` is
+    /// returned.
+    pub fn resolve(&self, span: Span) -> Option<(&SourceText, usize, usize)> {
+        let source = self.get(span.file())?;
+        let (line, col) = source.position(span);
+        Some((source, line, col))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Pos;
+    use super::*;
+    use crate::syntax::SyntaxNode;
+    use crate::syntax::SyntaxTree;
+
+    #[test]
+    fn add_assigns_distinct_ids() {
+        let mut map = SourceMap::new();
+        assert!(map.is_empty());
+
+        let first = map.add(SourceText::with_name("print 1", "first.ulg"));
+        let second = map.add(SourceText::with_name("print 2", "second.ulg"));
+
+        assert_eq!(2, map.len());
+        assert_ne!(first.file_id(), second.file_id());
+        assert_ne!(FileId::DETACHED, first.file_id());
+        assert_eq!("first.ulg", map.get(first.file_id()).unwrap().name());
+        assert_eq!("second.ulg", map.get(second.file_id()).unwrap().name());
+        assert_eq!(second.file_id(), map.find("second.ulg").unwrap().file_id());
+        assert!(map.get(FileId::DETACHED).is_none());
+    }
+
+    #[test]
+    fn resolve_spans_from_parsed_files() {
+        let mut map = SourceMap::new();
+        let lib = map.add(SourceText::with_name(
+            "fn foo(): Number\n  1\nend",
+            "lib.ulg",
+        ));
+        let main = map.add(SourceText::with_name("\n\n  print foo()", "main.ulg"));
+
+        let lib_tree = SyntaxTree::parse(&lib);
+        let main_tree = SyntaxTree::parse(&main);
+
+        let (file, line, col) = map.resolve(lib_tree.root().span()).unwrap();
+        assert_eq!(("lib.ulg", 1, 0), (file.name(), line, col));
+
+        let (file, line, col) = map.resolve(main_tree.root().span()).unwrap();
+        assert_eq!(("main.ulg", 3, 2), (file.name(), line, col));
+    }
+
+    #[test]
+    fn resolve_detached_span() {
+        let mut map = SourceMap::new();
+        map.add(SourceText::new("print 1"));
+        assert!(map.resolve(Span::new_at(Pos::from(0))).is_none());
+    }
+}
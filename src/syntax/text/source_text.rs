@@ -6,24 +6,60 @@
 //! using `Location`s, and `Location`s can be turned into `(line,
 //! col)` position pairs for displaying in diagnostics.
 
-use super::{Ident, Interner, Pos, Span};
+#[cfg(unix)]
+use super::mapping::Mapping;
+use super::{FileId, Ident, Interner, Pos, Span};
 use std::cell::RefCell;
 use std::fs::File;
 use std::io::{self, prelude::*};
+use std::ops::Deref;
 use std::path::Path;
 
+/// The Smallest File to Memory Map
+///
+/// Mapping a file has a fixed cost, so smaller files are read into a
+/// buffer even by `map_path`.
+#[cfg(unix)]
+const MAP_THRESHOLD: u64 = 64 * 1024;
+
+/// Source Buffer
+///
+/// The text of a source is either owned, or borrowed from a memory
+/// mapping of the file it was loaded from.
+enum Buffer {
+    /// Text held in a buffer owned by the source
+    Owned(String),
+    /// Text borrowed from a mapped file
+    #[cfg(unix)]
+    Mapped(Mapping),
+}
+
+impl Deref for Buffer {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Buffer::Owned(text) => text,
+            #[cfg(unix)]
+            Buffer::Mapped(mapping) => mapping.as_str(),
+        }
+    }
+}
+
 /// Source Text Struct
 ///
 /// A source text is a pair of a string containing the contents of the
 /// file or other input and a start position.
 pub struct SourceText {
     /// The contents of the source text
-    source: String,
+    source: Buffer,
     /// The offsets of the beginning of each line. Can be used to
     /// convert a character offset into the (line, column)
     line_offsets: Vec<usize>,
     /// The name of this source code. Used when reporting diagnostics.
     name: String,
+    /// The ID of this source in its `SourceMap`, if it belongs to one.
+    pub(super) file_id: FileId,
     /// String interner to create identifiers
     ///
     /// FIXME: Should this live here?
@@ -38,13 +74,17 @@ impl SourceText {
 
     /// Create a `SourceText` with a known name
     pub fn with_name<T: Into<String>, U: Into<String>>(source: T, name: U) -> Self {
-        let source = source.into();
-        let name = name.into();
-        let line_offsets = get_line_offsets(&source[..]);
+        SourceText::from_buffer(Buffer::Owned(source.into()), name.into())
+    }
+
+    /// Create a `SourceText` from a Buffer
+    fn from_buffer(source: Buffer, name: String) -> Self {
+        let line_offsets = get_line_offsets(&source);
         SourceText {
             source,
             line_offsets,
             name,
+            file_id: FileId::DETACHED,
             interner: Default::default(),
         }
     }
@@ -64,12 +104,44 @@ impl SourceText {
     /// Reads the contents of a given file path into a buffer and
     /// creates a new source text from that.
     pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let name = path.as_ref().display().to_string();
         let mut s = String::new();
         File::open(path.as_ref())?.read_to_string(&mut s)?;
-        Ok(SourceText::with_name(
-            s,
-            path.as_ref().display().to_string(),
-        ))
+        Ok(SourceText::with_name(s, name))
+    }
+
+    /// Create a source text by mapping a file
+    ///
+    /// As `from_path`, but large files are memory mapped instead of
+    /// being read, and the source borrows its text from the mapping.
+    /// If the file can't be mapped, such as when it is a pipe, it is
+    /// read as normal.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified until the source is
+    /// dropped. Editors commonly rewrite files in place, so this
+    /// shouldn't be used for sources which are kept while they are
+    /// being edited, such as by the language server.
+    pub unsafe fn map_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let name = path.as_ref().display().to_string();
+        let mut file = File::open(path.as_ref())?;
+        #[cfg(unix)]
+        {
+            let len = file.metadata()?.len();
+            if len >= MAP_THRESHOLD {
+                match Mapping::map(&file, len as usize) {
+                    Ok(mapping) => {
+                        return Ok(SourceText::from_buffer(Buffer::Mapped(mapping), name))
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => return Err(e),
+                    Err(_) => (),
+                }
+            }
+        }
+        let mut s = String::new();
+        file.read_to_string(&mut s)?;
+        Ok(SourceText::with_name(s, name))
     }
 
     /// Get the Starting Position
@@ -82,6 +154,13 @@ impl SourceText {
         Pos::from(0)
     }
 
+    /// Get the Ending Position
+    ///
+    /// Returns the position after the last character in the source.
+    pub fn end(&self) -> Pos {
+        Pos::from(self.source.len())
+    }
+
     /// Get Line Count
     ///
     /// Returns the number of lines in the source text.
@@ -94,14 +173,29 @@ impl SourceText {
         &self.name
     }
 
+    /// Get the File ID of the source text
+    ///
+    /// Spans produced when tokenising this source are tagged with
+    /// this ID.
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
     /// Intern a String Value
     pub fn intern(&self, value: &str) -> Ident {
         self.interner.borrow_mut().intern(value)
     }
 
     /// Lookup the value of an identifier
-    pub fn interned_value(&self, ident: Ident) -> String {
-        self.interner.borrow().interned_value(ident).into()
+    ///
+    /// The value is borrowed from the interner rather than copied.
+    pub fn interned_value(&self, ident: Ident) -> &str {
+        let value: *const str = self.interner.borrow().interned_value(ident);
+        // SAFETY: Each interned value is boxed, and the interner never
+        // removes or modifies them. Interning more values may move the
+        // boxes but not the strings they own, so the value lives as
+        // long as the interner, which lives as long as `self`.
+        unsafe { &*value }
     }
 
     /// Get Line Position
@@ -119,6 +213,52 @@ impl SourceText {
         }
     }
 
+    /// Get the Position of a Span
+    ///
+    /// Returns the `(line, col)` position of the start of the given
+    /// span. Lines are numbered from 1 and columns are the number of
+    /// characters from the start of the line, starting at 0. Unlike
+    /// `line_pos` the column counts characters rather than bytes so
+    /// it lines up with what is shown in an editor.
+    pub fn position(&self, span: Span) -> (usize, usize) {
+        let offset = span.start().offset();
+        let (line, _) = self.line_pos(offset);
+        let line_start = self.line_offsets[line - 1];
+        (line, self.source[line_start..offset].chars().count())
+    }
+
+    /// Get the Text of a Line
+    ///
+    /// Returns the contents of the given line, numbered from 1,
+    /// without the trailing line ending. If the line is outside the
+    /// source then `None` is returned.
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        if line == 0 {
+            return None;
+        }
+        let start = *self.line_offsets.get(line - 1)?;
+        let end = self
+            .line_offsets
+            .get(line)
+            .cloned()
+            .unwrap_or(self.source.len());
+        let text = &self.source[start..end];
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    /// Get the Start of a Line
+    ///
+    /// Returns the position of the first character on the given
+    /// line, numbered from 1. If the line is outside the source then
+    /// `None` is returned.
+    pub fn line_start(&self, line: usize) -> Option<Pos> {
+        let index = line.checked_sub(1)?;
+        self.line_offsets
+            .get(index)
+            .map(|&offset| Pos::from(offset))
+    }
+
     /// Get the positions at the extents of the given span
     pub fn line_extents(&self, span: Span) -> (Pos, Pos) {
         let start_offset = span.start().offset();
@@ -211,4 +351,75 @@ end
         assert_eq!((5, 30), source.line_pos(93));
         assert_eq!((8, 0), source.line_pos(137));
     }
+
+    #[test]
+    fn source_span_to_position() {
+        let source = SourceText::new("let a = 1\nprint 'über' + b\n");
+        let span = |s: usize, e: usize| Span::new(Pos::from(s), Pos::from(e));
+
+        assert_eq!((1, 0), source.position(span(0, 3)));
+        assert_eq!((1, 4), source.position(span(4, 5)));
+        assert_eq!((2, 0), source.position(span(10, 15)));
+        // `b` is preceded by a multi-byte character
+        assert_eq!((2, 15), source.position(span(26, 27)));
+        assert_eq!((3, 0), source.position(span(28, 28)));
+    }
+
+    #[test]
+    fn source_line_text() {
+        let source = SourceText::new("first\r\nsecond\n\nlast");
+        assert_eq!(None, source.line_text(0));
+        assert_eq!(Some("first"), source.line_text(1));
+        assert_eq!(Some("second"), source.line_text(2));
+        assert_eq!(Some(""), source.line_text(3));
+        assert_eq!(Some("last"), source.line_text(4));
+        assert_eq!(None, source.line_text(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn source_from_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let small_path = dir.path().join("small.ulg");
+        std::fs::write(&small_path, "print 'ünî'\n").unwrap();
+        let large_path = dir.path().join("large.ulg");
+        let large = "print 1\n".repeat(MAP_THRESHOLD as usize);
+        std::fs::write(&large_path, &large).unwrap();
+
+        // SAFETY: The files are only changed once the sources loaded
+        // from them have been dropped.
+        let small = unsafe { SourceText::map_path(&small_path) }.unwrap();
+        assert!(matches!(small.source, Buffer::Owned(_)));
+        assert_eq!(Some("print 'ünî'"), small.line_text(1));
+
+        let mapped = unsafe { SourceText::map_path(&large_path) }.unwrap();
+        assert!(matches!(mapped.source, Buffer::Mapped(_)));
+        assert_eq!(large.as_str(), mapped.slice(mapped.start(), mapped.end()));
+        assert_eq!(MAP_THRESHOLD as usize + 1, mapped.line_count());
+        drop(mapped);
+
+        // Reading never maps the file
+        let read = SourceText::from_path(&large_path).unwrap();
+        assert!(matches!(read.source, Buffer::Owned(_)));
+        assert_eq!(large.as_str(), read.slice(read.start(), read.end()));
+        drop(read);
+
+        let mut invalid = vec![b'#'; MAP_THRESHOLD as usize];
+        invalid.push(0xFF);
+        std::fs::write(&large_path, invalid).unwrap();
+        let err = unsafe { SourceText::map_path(&large_path) }.err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        let err = SourceText::from_path(&large_path).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn source_line_start() {
+        let source = SourceText::new("first\r\nsecond\n\nlast");
+        assert_eq!(None, source.line_start(0));
+        assert_eq!(Some(Pos::from(0)), source.line_start(1));
+        assert_eq!(Some(Pos::from(7)), source.line_start(2));
+        assert_eq!(Some(Pos::from(15)), source.line_start(4));
+        assert_eq!(None, source.line_start(5));
+    }
 }
@@ -4,22 +4,28 @@
 //! tree. This tree defines the full strcuture of a parsed source file
 //! before any semantic transformation is done.
 
+mod arena;
+pub mod attributes;
 pub mod expression;
 pub mod operators;
 mod token;
+mod tokens;
 mod trivia;
 pub mod types;
 
+use std::fmt;
 use std::io::{self, prelude::*};
+use std::ops::Index;
 
 use crate::diag::Diagnostic;
-use crate::parse::Parser;
-use crate::text::SourceText;
+use crate::syntax::parse::Parser;
+use crate::syntax::text::SourceText;
 
+pub use self::arena::{ExprId, ExpressionArena};
 pub use self::token::{Literal, Token, TokenKind};
-pub use self::trivia::{TriviaToken, TriviaTokenKind};
+pub use self::trivia::{DocComment, TriviaToken, TriviaTokenKind};
 
-use super::SyntaxNode;
+use super::{Edition, SyntaxNode};
 use expression::Expression;
 
 /// Syntax tree
@@ -27,8 +33,10 @@ use expression::Expression;
 /// The syntax tree represents the parsed source of a given file. It
 /// contains multiple expressions followed by an end of file token.
 pub struct SyntaxTree<'a> {
+    /// The expressions in the tree
+    arena: ExpressionArena,
     /// The root of the main expression tree
-    root: Expression,
+    root: ExprId,
     /// Diagnostics related to the given tree
     diagnostics: Vec<Diagnostic>,
     /// End token
@@ -45,20 +53,23 @@ impl<'a> SyntaxTree<'a> {
     ///
     /// # Parameters
     ///
+    ///  * `arena`: The arena holding the tree's expressions.
     ///  * `root`: The body of the file. This could be an empty
-    ///  sequence if the file is empty
+    ///    sequence if the file is empty
     ///  * `diagnostics`: Diagnostics raised in the parsing of the
-    ///  source.
+    ///    source.
     ///  * `end`: The closing EOF token. This may have some leading
-    ///  trivia attached and is therefore required for a full-fidelity
-    ///  tree.
+    ///    trivia attached and is therefore required for a full-fidelity
+    ///    tree.
     pub fn new(
         source: &'a SourceText,
-        root: Expression,
+        arena: ExpressionArena,
+        root: ExprId,
         diagnostics: Vec<Diagnostic>,
         end: Token,
     ) -> Self {
         SyntaxTree {
+            arena,
             root,
             diagnostics,
             end,
@@ -66,11 +77,24 @@ impl<'a> SyntaxTree<'a> {
         }
     }
 
+    /// Take the Tree Apart
+    ///
+    /// Returns the arena, root, diagnostics, and end token the tree
+    /// was created from, so a new tree can be built from them.
+    pub fn into_parts(self) -> (ExpressionArena, ExprId, Vec<Diagnostic>, Token) {
+        (self.arena, self.root, self.diagnostics, self.end)
+    }
+
     /// Parse a tree from source text
     pub fn parse(source: &'a SourceText) -> Self {
         Parser::new(source).parse()
     }
 
+    /// Parse a Tree for a Given Edition of the Language
+    pub fn parse_with_edition(source: &'a SourceText, edition: Edition) -> Self {
+        Parser::with_edition(source, edition).parse()
+    }
+
     /// Parse a source tree containing a single expression
     pub fn parse_single(source: &'a SourceText) -> Self {
         Parser::new(source).parse_single()
@@ -78,7 +102,26 @@ impl<'a> SyntaxTree<'a> {
 
     /// Get the root of the tree
     pub fn root(&self) -> &Expression {
-        &self.root
+        &self.arena[self.root]
+    }
+
+    /// Get the Id of the Root Expression
+    pub fn root_id(&self) -> ExprId {
+        self.root
+    }
+
+    /// Look up an Expression in the Tree
+    ///
+    /// Child expressions are referred to by id. This returns the
+    /// expression a given id refers to. Trees can also be indexed by
+    /// id directly.
+    pub fn expression(&self, id: ExprId) -> &Expression {
+        &self.arena[id]
+    }
+
+    /// Get the Tree's Expression Arena
+    pub fn arena(&self) -> &ExpressionArena {
+        &self.arena
     }
 
     /// Get the end token
@@ -96,14 +139,6 @@ impl<'a> SyntaxTree<'a> {
         !self.diagnostics.is_empty()
     }
 
-    /// Returns the root of the expression tree and the EOF token
-    ///
-    /// FIXME: should root and token just be public and remove this,
-    /// `root()`, and `end()`?
-    pub fn into_parts(self) -> (Expression, Token) {
-        (self.root, self.end)
-    }
-
     /// Access the Borrowed Source
     ///
     /// Allows access to the source this syntax tree was parsed from.
@@ -111,6 +146,82 @@ impl<'a> SyntaxTree<'a> {
         self.source
     }
 
+    /// Get the Tokens in the Tree
+    ///
+    /// Returns every token in the tree, including the end token, in
+    /// the order they appear in the source text.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        tokens::expression_tokens(&self.arena, self.root(), &mut tokens);
+        tokens.push(&self.end);
+        tokens
+    }
+
+    /// Write the Source Text
+    ///
+    /// Reproduces the text this tree was parsed from by writing out
+    /// each token along with its leading and trailing trivia.
+    pub fn write_source_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let source = self.source;
+        let write_trivia = |writer: &mut W, trivia: &[TriviaToken]| {
+            trivia.iter().try_for_each(|t| {
+                let span = t.span();
+                writer.write_all(source.slice(span.start(), span.end()).as_bytes())
+            })
+        };
+        for token in self.tokens() {
+            write_trivia(writer, token.leading())?;
+            let span = token.span();
+            writer.write_all(source.slice(span.start(), span.end()).as_bytes())?;
+            write_trivia(writer, token.trailing())?;
+        }
+        Ok(())
+    }
+
+    /// Convert the Tree back to Source
+    ///
+    /// Returns the text written by `write_source_to`. For any parsed
+    /// tree this is the same as the original source text.
+    pub fn to_source_string(&self) -> String {
+        let mut buff = Vec::new();
+        self.write_source_to(&mut buff)
+            .expect("writing to a buffer should not fail");
+        String::from_utf8(buff).expect("source text should be valid UTF-8")
+    }
+
+    /// Write the Tokens as Source
+    ///
+    /// Writes the text of each token in the tree, separated by
+    /// spaces. The tree's trivia, and the text it was parsed from,
+    /// are ignored. This makes it possible to write out trees which
+    /// weren't parsed, such as those from a `TreeBuilder`.
+    pub fn write_tokens_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut sep = "";
+        for token in self.tokens() {
+            if token.kind != TokenKind::End {
+                write!(writer, "{}{}", sep, token.kind.text(self.source))?;
+                sep = " ";
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert the Tree's Tokens to Source
+    ///
+    /// Returns the text written by `write_tokens_to`.
+    pub fn to_token_string(&self) -> String {
+        let mut buff = Vec::new();
+        self.write_tokens_to(&mut buff)
+            .expect("writing to a buffer should not fail");
+        String::from_utf8(buff).expect("token text should be valid UTF-8")
+    }
+
     /// Dump the Expression Tree
     ///
     /// Walks the subnodes of this tree and prints a text representation
@@ -121,7 +232,25 @@ impl<'a> SyntaxTree<'a> {
     {
         let mut writer = io::BufWriter::new(writer);
         let mut prefix = String::new();
-        pretty_tree(&mut writer, self.source, self.root(), &mut prefix, "•")
+        pretty_tree(&mut writer, self, self.root(), &mut prefix, "•")
+    }
+}
+
+impl Index<ExprId> for SyntaxTree<'_> {
+    type Output = Expression;
+
+    fn index(&self, id: ExprId) -> &Expression {
+        self.expression(id)
+    }
+}
+
+impl fmt::Debug for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntaxTree")
+            .field("root", &self.root)
+            .field("arena", &self.arena)
+            .field("end", &self.end)
+            .finish()
     }
 }
 
@@ -130,7 +259,7 @@ impl<'a> SyntaxTree<'a> {
 /// of them as an ASCII tree.
 fn pretty_tree<W>(
     writer: &mut io::BufWriter<W>,
-    source: &SourceText,
+    tree: &SyntaxTree<'_>,
     expr: &Expression,
     prefix: &mut String,
     lead: &str,
@@ -138,23 +267,31 @@ fn pretty_tree<W>(
 where
     W: io::Write,
 {
-    writeln!(writer, "{}{} {}", prefix, lead, expr.description(source))?;
-    let children: Vec<&Expression> = match expr {
+    writeln!(
+        writer,
+        "{}{} {}",
+        prefix,
+        lead,
+        expr.description(tree.source)
+    )?;
+    let children: Vec<ExprId> = match expr {
         Expression::Identifier(_) => Vec::new(),
         Expression::Literal(_) => Vec::new(),
-        Expression::Prefix(p) => vec![&p.inner],
-        Expression::Infix(i) => vec![&i.left, &i.right],
-        Expression::Call(c) => std::iter::once(&*c.callee)
-            .chain(c.arguments.iter())
+        Expression::Prefix(p) => vec![p.inner],
+        Expression::Infix(i) => vec![i.left, i.right],
+        Expression::Call(c) => std::iter::once(c.callee)
+            .chain(c.arguments.iter().map(|a| *a.as_inner()))
             .collect(),
-        Expression::Index(i) => vec![&i.index, &i.indexee],
-        Expression::IfThenElse(i) => vec![&i.cond, &i.if_true, &i.if_false],
-        Expression::Function(f) => vec![&f.body.contents],
-        Expression::Loop(l) => vec![&l.condition, &l.body.contents],
-        Expression::Sequence(s) => s.iter().collect(),
-        Expression::Print(p) => vec![&p.inner],
-        Expression::Declaration(d) => vec![&d.initialiser],
-        Expression::Grouping(g) => vec![&g.inner],
+        Expression::Index(i) => vec![i.index, i.indexee],
+        Expression::IfThenElse(i) => vec![i.cond, i.if_true, i.if_false],
+        Expression::Function(f) => vec![f.body.contents],
+        Expression::Macro(m) => vec![m.body.contents],
+        Expression::Loop(l) => vec![l.condition, l.body.contents],
+        Expression::LoopControl(_) => Vec::new(),
+        Expression::Sequence(s) => s.clone(),
+        Expression::Print(p) => vec![p.inner],
+        Expression::Declaration(d) => d.initialiser.iter().map(|i| i.value).collect(),
+        Expression::Grouping(g) => vec![g.inner],
     };
 
     let orig_prefix_len = prefix.len();
@@ -164,10 +301,10 @@ where
         _ => (),
     }
     if let Some((last, rest)) = children.split_last() {
-        for child in rest {
-            pretty_tree(writer, source, child, prefix, "├─")?;
+        for &child in rest {
+            pretty_tree(writer, tree, &tree[child], prefix, "├─")?;
         }
-        pretty_tree(writer, source, last, prefix, "└─")?;
+        pretty_tree(writer, tree, &tree[*last], prefix, "└─")?;
     }
     if orig_prefix_len < prefix.len() {
         prefix.truncate(orig_prefix_len);
@@ -184,27 +321,88 @@ mod test {
     #[test]
     fn tree_without_diagnositcs_reports_false() {
         let source = SourceText::new("");
-        let tree = SyntaxTree::new(
-            &source,
-            Expression::empty(),
-            Vec::new(),
-            Token::new(TokenKind::End),
-        );
+        let mut arena = ExpressionArena::new();
+        let root = arena.alloc(Expression::empty());
+        let tree = SyntaxTree::new(&source, arena, root, Vec::new(), Token::new(TokenKind::End));
 
-        assert_ne!(true, tree.has_diagnostics());
+        assert!(!tree.has_diagnostics());
     }
 
     #[test]
     fn tree_with_diagnostics_reports_true() {
         let source = SourceText::new("");
+        let mut arena = ExpressionArena::new();
+        let root = arena.alloc(Expression::empty());
         let tree = SyntaxTree::new(
             &source,
-            Expression::empty(),
+            arena,
+            root,
             vec![Diagnostic::new("error: test", DUMMY_SPAN)],
             Token::new(TokenKind::End),
         );
 
-        assert_eq!(true, tree.has_diagnostics());
+        assert!(tree.has_diagnostics());
+    }
+
+    fn assert_round_trips(text: &str) {
+        let source = SourceText::new(text);
+        let tree = SyntaxTree::parse(&source);
+        assert_eq!(text, tree.to_source_string());
+    }
+
+    #[test]
+    fn tree_round_trips_source() {
+        assert_round_trips("");
+        assert_round_trips("  # just a comment\n\n");
+        assert_round_trips("print foo(1, 2 ,3) # call\n");
+        assert_round_trips("fn add(a: Number , b: [Number]): (Number, Bool)\n  a + b[0]\nend");
+        assert_round_trips("let x = 1 unless y else 2\nvar z: String = 'a' if x else 'b'");
+        assert_round_trips("while !(x <= 10)\n\tx = x * -2 # body\r\nend\n");
+        assert_round_trips("macro twice( x ) x + x end # m\nprint twice(1)");
+        assert_round_trips("## docs\n#[inline] #[allow( a , b = 'c' )]\nfn f(): Number 1 end");
+        assert_round_trips("#[cfg(any( unix, not(windows) ), all())] let a = 1");
+    }
+
+    #[test]
+    fn tree_round_trips_source_with_errors() {
+        assert_round_trips("print 1 + ) 2 # oops");
+        assert_round_trips("foo(1, ");
+        assert_round_trips("let = ] \n# trailing");
+        assert_round_trips("fn (: end ~~ $");
+        assert_round_trips("fn 0 = foo(bar baz");
+        assert_round_trips("print 99999999999999999999");
+        assert_round_trips("#[inline print 1");
+        assert_round_trips("#[cold] print 1");
+    }
+
+    #[test]
+    fn spec_sources_round_trip() {
+        for entry in std::fs::read_dir("spec").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("ulg".as_ref()) {
+                let source = SourceText::from_path(&path).unwrap();
+                let tree = SyntaxTree::parse(&source);
+                let text = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(text, tree.to_source_string(), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn tree_tokens_to_string() {
+        let source = SourceText::new("fn add(a: Number,b: Number): Number\n  a+b # sum\nend");
+        let tree = SyntaxTree::parse(&source);
+        assert_eq!(
+            "fn add ( a : Number , b : Number ) : Number a + b end",
+            tree.to_token_string()
+        );
+
+        let source = SourceText::new("print -'hi' if x <= 10 else y[0]");
+        let tree = SyntaxTree::parse(&source);
+        assert_eq!(
+            "print - 'hi' if x <= 10 else y [ 0 ]",
+            tree.to_token_string()
+        );
     }
 
     #[test]
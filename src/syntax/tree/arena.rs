@@ -0,0 +1,114 @@
+//! Expression Arena
+//!
+//! The expressions of a syntax tree are stored together in an arena
+//! owned by the tree rather than each being boxed on its own. Nodes
+//! refer to their child expressions by `ExprId`, and the tree
+//! provides accessors to look them up.
+
+use std::ops::Index;
+
+use super::super::text::Span;
+use super::super::SyntaxNode;
+use super::expression::Expression;
+
+/// Expression Id
+///
+/// Refers to an expression stored in an `ExpressionArena`. The span
+/// of the expression is held alongside its index so that spans can
+/// be computed without access to the arena.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ExprId {
+    index: u32,
+    span: Span,
+}
+
+impl ExprId {
+    /// Get the Span of the Expression
+    pub fn span(self) -> Span {
+        self.span
+    }
+}
+
+/// Expression Arena
+///
+/// Owns the expressions of a syntax tree. Expressions are added to
+/// the arena once their children have been, so the root of a tree is
+/// always the last expression added to it. An `ExprId` is only valid
+/// in the arena which created it.
+#[derive(Debug, Default)]
+pub struct ExpressionArena {
+    expressions: Vec<Expression>,
+}
+
+impl ExpressionArena {
+    /// Create an Empty Arena
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add an Expression to the Arena
+    ///
+    /// Returns the id which refers to the expression from now on.
+    pub fn alloc(&mut self, expression: Expression) -> ExprId {
+        let id = ExprId {
+            index: self.expressions.len() as u32,
+            span: expression.span(),
+        };
+        self.expressions.push(expression);
+        id
+    }
+
+    /// Get the Number of Expressions in the Arena
+    pub fn len(&self) -> usize {
+        self.expressions.len()
+    }
+
+    /// Check if the Arena has no Expressions
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    /// Iterate over the Expressions in the Arena
+    ///
+    /// Expressions are returned in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Expression> {
+        self.expressions.iter()
+    }
+}
+
+impl Index<ExprId> for ExpressionArena {
+    type Output = Expression;
+
+    fn index(&self, id: ExprId) -> &Expression {
+        &self.expressions[id.index as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Token, TokenKind};
+    use super::*;
+    use crate::syntax::text::{Ident, Pos};
+
+    #[test]
+    fn arena_alloc_and_lookup() {
+        let mut arena = ExpressionArena::new();
+        assert!(arena.is_empty());
+
+        let span = Span::new(Pos::from(4), Pos::from(7));
+        let ident = arena.alloc(Expression::identifier(
+            Token::with_span(span, TokenKind::Word(Ident::Print)),
+            Ident::Print,
+        ));
+        let seq = arena.alloc(Expression::sequence(vec![ident]));
+
+        assert_eq!(2, arena.len());
+        assert_eq!(span, ident.span());
+        assert_eq!(span, seq.span());
+        assert_eq!(&Expression::sequence(vec![ident]), &arena[seq]);
+        match &arena[ident] {
+            Expression::Identifier(id) => assert_eq!(Ident::Print, id.ident),
+            other => panic!("expected identifier, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Syntax Attributes
+//!
+//! This module contains the structures used in the syntax tree to
+//! represent attributes. Attributes annotate the function or variable
+//! declaration which follows them, e.g. `#[inline]` or
+//! `#[allow(unreachable_code)]`. The parser doesn't know what any
+//! attribute means, that is left to the later phases of compilation.
+
+use super::super::text::{Ident, SourceText, Span};
+use super::expression::DelimItem;
+use super::Token;
+
+/// Attribute
+///
+/// A single `#[name]` or `#[name(args...)]` attribute.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Attribute {
+    /// The opening `#[`
+    pub open: Token,
+    /// The attribute's name
+    pub name: Ident,
+    /// The token for the attribute's name
+    pub name_tok: Token,
+    /// The attribute's arguments, if it has any
+    pub args: Option<AttributeArgs>,
+    /// The closing `]`
+    pub close: Token,
+}
+
+/// Attribute Arguments
+///
+/// The bracketed list of arguments given to an attribute.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeArgs {
+    /// The opening `(`
+    pub open: Token,
+    /// The arguments
+    pub args: Vec<DelimItem<AttributeArg>>,
+    /// The closing `)`
+    pub close: Token,
+}
+
+/// Attribute Argument
+///
+/// A name, optionally with a value or a nested list of arguments,
+/// e.g. `unreachable_code`, `os = 'linux'`, or `not(unix)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeArg {
+    /// The argument's name
+    pub name: Ident,
+    /// The token for the argument's name
+    pub name_tok: Token,
+    /// The `=` token and the value, if the argument has one
+    pub value: Option<(Token, Token)>,
+    /// The arguments nested in this one, if it has a list
+    pub args: Option<AttributeArgs>,
+}
+
+impl Attribute {
+    /// Get the Attribute's Name
+    pub fn name<'a>(&self, source: &'a SourceText) -> &'a str {
+        source.interned_value(self.name)
+    }
+
+    /// Get the Attribute's Arguments
+    ///
+    /// Returns an iterator over the arguments, without their
+    /// delimiters. Attributes without an argument list have no
+    /// arguments.
+    pub fn args(&self) -> impl Iterator<Item = &AttributeArg> {
+        self.args.iter().flat_map(AttributeArgs::iter)
+    }
+
+    /// Get the Span of the Attribute
+    pub fn span(&self) -> Span {
+        Span::enclosing(self.open.span(), self.close.span())
+    }
+
+    /// Get the Tokens of the Attribute
+    ///
+    /// Returns the attribute's tokens in source order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![&self.open, &self.name_tok];
+        if let Some(args) = &self.args {
+            args.push_tokens(&mut tokens);
+        }
+        tokens.push(&self.close);
+        tokens
+    }
+
+    /// Convert the Attribute into its Tokens
+    ///
+    /// Returns the attribute's tokens in source order.
+    pub fn into_tokens(self) -> Vec<Token> {
+        let mut tokens = vec![self.open, self.name_tok];
+        if let Some(args) = self.args {
+            args.push_into_tokens(&mut tokens);
+        }
+        tokens.push(self.close);
+        tokens
+    }
+}
+
+impl AttributeArgs {
+    /// Iterate over the Arguments
+    ///
+    /// Returns the arguments without their delimiters.
+    pub fn iter(&self) -> impl Iterator<Item = &AttributeArg> {
+        self.args.iter().map(DelimItem::as_inner)
+    }
+
+    /// Add the List's Tokens, in Source Order
+    fn push_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        tokens.push(&self.open);
+        for arg in self.args.iter() {
+            if let DelimItem::Follow(delim, _) = arg {
+                tokens.push(delim);
+            }
+            let arg = arg.as_inner();
+            tokens.push(&arg.name_tok);
+            if let Some((equals, value)) = &arg.value {
+                tokens.push(equals);
+                tokens.push(value);
+            }
+            if let Some(args) = &arg.args {
+                args.push_tokens(tokens);
+            }
+        }
+        tokens.push(&self.close);
+    }
+
+    /// Convert the List into Tokens, in Source Order
+    fn push_into_tokens(self, tokens: &mut Vec<Token>) {
+        tokens.push(self.open);
+        for arg in self.args {
+            let arg = match arg {
+                DelimItem::First(arg) => arg,
+                DelimItem::Follow(delim, arg) => {
+                    tokens.push(delim);
+                    arg
+                }
+            };
+            tokens.push(arg.name_tok);
+            if let Some((equals, value)) = arg.value {
+                tokens.push(equals);
+                tokens.push(value);
+            }
+            if let Some(args) = arg.args {
+                args.push_into_tokens(tokens);
+            }
+        }
+        tokens.push(self.close);
+    }
+}
+
+impl AttributeArg {
+    /// Get the Argument's Name
+    pub fn name<'a>(&self, source: &'a SourceText) -> &'a str {
+        source.interned_value(self.name)
+    }
+
+    /// Get the Argument's Value Token
+    pub fn value(&self) -> Option<&Token> {
+        self.value.as_ref().map(|(_, value)| value)
+    }
+
+    /// Get the Arguments Nested in this One
+    ///
+    /// Returns an iterator over the nested arguments, without their
+    /// delimiters. Arguments without a list have none.
+    pub fn args(&self) -> impl Iterator<Item = &AttributeArg> {
+        self.args.iter().flat_map(AttributeArgs::iter)
+    }
+}
@@ -5,12 +5,15 @@
 
 use super::super::text::{Ident, SourceText, Span, DUMMY_SPAN};
 use super::super::SyntaxNode;
+use super::arena::ExprId;
+use super::attributes::Attribute;
 use super::operators::{InfixOp, PrefixOp};
 use super::token::{Token, TokenKind};
+use super::trivia::DocComment;
 use super::types::TypeAnno;
 
 /// An identifier, with an optional type attached
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TypedId {
     /// The Type of this Identifier
     ///
@@ -59,7 +62,7 @@ impl TypedId {
 /// Delimited Item
 ///
 /// A single element in a list of token-delimited values.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum DelimItem<T> {
     /// The first item in a list. Doesn't have a corresponding
     /// dlimiter token.
@@ -91,20 +94,20 @@ pub enum Constant {
 }
 
 /// A single identifier token
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct IdentifierExpression {
     /// The underlying token
-    pub token: Box<Token>,
+    pub token: Token,
     /// The identifier for the token. This should be the same as the
     /// contents of the `Token::Word`.
     pub ident: Ident,
 }
 
 /// A Literal constant vlaue
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct LiteralExpression {
     /// The underlying token
-    pub token: Box<Token>,
+    pub token: Token,
     /// The 'cooked' Literal, ready to be used.
     pub value: Constant,
 }
@@ -113,30 +116,30 @@ pub struct LiteralExpression {
 ///
 /// Holds the contents of the prefix expression. This is the operator
 /// token and the inner expression.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct PrefixExpression {
     /// The token for the operator
-    pub op_token: Box<Token>,
+    pub op_token: Token,
     /// The operator itself
     pub op: PrefixOp,
     /// The inner Expression
-    pub inner: Box<Expression>,
+    pub inner: ExprId,
 }
 
 /// Infix Operator Expression
 ///
 /// Represents two expressions joined by an inner operator. This does
 /// not distignuish between assignment and other operators.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct InfixOperatorExpression {
     /// The left hand side expression
-    pub left: Box<Expression>,
+    pub left: ExprId,
     /// The token for the operator
-    pub op_token: Box<Token>,
+    pub op_token: Token,
     /// The operator itself
     pub op: InfixOp,
     /// The right hand side expression
-    pub right: Box<Expression>,
+    pub right: ExprId,
 }
 
 /// Call Expression
@@ -144,16 +147,18 @@ pub struct InfixOperatorExpression {
 /// Represnets the application of the call operator `()` to an
 /// expression. The arguments to the function are groupeed together in
 /// a `,` delimited list.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct CallExpression {
     /// The item this funcion call should target
-    pub callee: Box<Expression>,
+    pub callee: ExprId,
     /// The opening `(` of this call
-    pub open_paren: Box<Token>,
-    /// The list of arguments to the call. This could be empty.
-    pub arguments: Vec<Expression>,
+    pub open_paren: Token,
+    /// The list of arguments to the call. This could be empty. Each
+    /// argument after the first carries the `,` which separates it
+    /// from the previous one.
+    pub arguments: Vec<DelimItem<ExprId>>,
     /// THe closing `)` of this call
-    pub close_paren: Box<Token>,
+    pub close_paren: Token,
 }
 
 /// Array Indexing
@@ -161,92 +166,173 @@ pub struct CallExpression {
 /// Index expressions represent accessing one or more values from an
 /// aggregate. The plan is to allow slicing by passing a rage to the
 /// index operator.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct IndexExpression {
     /// The expression being indexed into
-    pub indexee: Box<Expression>,
+    pub indexee: ExprId,
     /// The opening `[` of the index expression
-    pub open_bracket: Box<Token>,
+    pub open_bracket: Token,
     /// The index being accessed
-    pub index: Box<Expression>,
+    pub index: ExprId,
     /// The closing `]` of the expression
-    pub close_bracket: Box<Token>,
+    pub close_bracket: Token,
 }
 
 /// If Else Expression
 ///
 /// The base conditional expression.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct IfElseExpression {
     /// The `if` token`
-    pub if_tok: Box<Token>,
+    pub if_tok: Token,
     /// The condition for the if block
-    pub cond: Box<Expression>,
+    pub cond: ExprId,
     /// The expression to evaluate if the condition is true
-    pub if_true: Box<Expression>,
+    pub if_true: ExprId,
     /// The `else` token
-    pub else_tok: Box<Token>,
+    pub else_tok: Token,
     /// The expression to evaluate if the condition is false
-    pub if_false: Box<Expression>,
+    pub if_false: ExprId,
 }
 
 /// Function Declaration Expression
 ///
 /// Represents the definition of a function and the implementation of
 /// it.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FunctionExpression {
-    /// The function's identifier
-    pub fn_kw: Box<Token>,
+    /// The doc comment before the function, if any
+    pub doc: Option<DocComment>,
+    /// The attributes applied to the function
+    pub attributes: Vec<Attribute>,
+    /// The `fn` keyword token
+    pub fn_kw: Token,
     /// The function's identifier
     pub identifier: Ident,
+    /// The token for the function's identifier
+    pub identifier_tok: Token,
     /// The open `(` before the parameter list
-    pub params_open: Box<Token>,
+    pub params_open: Token,
     /// Function parameters
     pub params: Vec<DelimItem<TypedId>>,
     /// The closing `)` after the parameter list
-    pub params_close: Box<Token>,
+    pub params_close: Token,
     /// Function return type
     pub return_type: TypeAnno,
     /// Body of the function
     pub body: BlockBody,
 }
 
+/// Macro Definition Expression
+///
+/// Defines a macro which is expanded wherever it is called, before
+/// the tree is bound. The body is a template for the expressions each
+/// call expands to, with the parameters standing in for the call's
+/// arguments.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MacroExpression {
+    /// The `macro` keyword token
+    pub macro_kw: Token,
+    /// The macro's identifier
+    pub identifier: Ident,
+    /// The token for the macro's identifier
+    pub identifier_tok: Token,
+    /// The open `(` before the parameter list
+    pub params_open: Token,
+    /// The `Word` tokens naming each parameter
+    pub params: Vec<DelimItem<Token>>,
+    /// The closing `)` after the parameter list
+    pub params_close: Token,
+    /// The template the macro expands to
+    pub body: BlockBody,
+}
+
+impl MacroExpression {
+    /// Get the Names of the Macro's Parameters
+    pub fn param_names(&self) -> impl Iterator<Item = Ident> + '_ {
+        self.params
+            .iter()
+            .filter_map(|param| match param.as_inner().kind {
+                TokenKind::Word(id) => Some(id),
+                _ => None,
+            })
+    }
+}
+
 /// Block Body
 ///
 /// represents the sequence of expressions within a given block, along
 /// with the closing delimiter of the block
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct BlockBody {
     /// The inner expressions
-    pub contents: Box<Expression>,
+    pub contents: ExprId,
     /// The closing delimiter
-    pub close: Box<Token>,
+    pub close: Token,
 }
 
 /// Loop Expression
 ///
 /// Represents a loop operator. Loops always evaluate to `()` but can
 /// run the body of the loop more than once.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct LoopExpression {
+    /// The label naming the loop, if any
+    pub label: Option<LoopLabel>,
     /// The word used to introduce the loop
-    pub kw_token: Box<Token>,
+    pub kw_token: Token,
     /// The loop header expression
-    pub condition: Box<Expression>,
+    pub condition: ExprId,
     /// The loop body
     pub body: BlockBody,
 }
 
+/// Loop Label
+///
+/// The name given to a loop with `name:` before its keyword. Labels
+/// allow `break` and `continue` to refer to an outer loop.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoopLabel {
+    /// The token containing the label's name
+    pub name_tok: Token,
+    /// The label's name
+    pub name: Ident,
+    /// The `:` following the name
+    pub colon: Token,
+}
+
+/// Loop Control Kind
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LoopControl {
+    /// Leave the loop
+    Break,
+    /// Skip to the next check of the loop's condition
+    Continue,
+}
+
+/// Loop Control Expression
+///
+/// A `break` or `continue`, optionally followed by the label of the
+/// loop it applies to. Without a label the innermost loop is used.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoopControlExpression {
+    /// The `break` or `continue` token
+    pub kw_token: Token,
+    /// Which kind of control this is
+    pub kind: LoopControl,
+    /// The label token and name, if any
+    pub label: Option<(Token, Ident)>,
+}
+
 /// Print Expression
 ///
 /// The appliation of the prefix `print` operator.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct PrintExpression {
     /// The `print` token
-    pub print_tok: Box<Token>,
+    pub print_tok: Token,
     /// The expression to be printed
-    pub inner: Box<Expression>,
+    pub inner: ExprId,
 }
 
 /// Variable mutability style
@@ -261,39 +347,74 @@ pub enum VarStyle {
 /// Declaration Expression
 ///
 /// Variable declaration. Holds the identifier the declaration
-/// introduces and the initial value of the expression.
-#[derive(Debug, PartialEq)]
+/// introduces and the initial value of the expression, if it has
+/// one.
+#[derive(Debug, PartialEq, Clone)]
 pub struct DeclarationExpression {
+    /// The doc comment before the declaration, if any
+    pub doc: Option<DocComment>,
+    /// The attributes applied to the declaration
+    pub attributes: Vec<Attribute>,
     /// The keyword token which introduces this declaration
-    pub var_kw: Box<Token>,
+    pub var_kw: Token,
     /// is the variable mutable
     pub style: VarStyle,
     /// The identifier to introduce
     pub id: TypedId,
+    /// Initialiser for the variable. Variables declared without an
+    /// initialiser must be assigned before they are read.
+    pub initialiser: Option<Initialiser>,
+}
+
+/// Declaration Initialiser
+///
+/// The initial value given to a variable when it is declared, and
+/// the `=` token which introduces it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Initialiser {
     /// The assignment token
-    pub assignment_tok: Box<Token>,
-    /// Initialiser for the variable
-    pub initialiser: Box<Expression>,
+    pub assignment_tok: Token,
+    /// The initial value
+    pub value: ExprId,
+}
+
+impl DeclarationExpression {
+    /// Get the Span of the Declaration
+    ///
+    /// Covers the whole declaration, from its first attribute or
+    /// keyword to the end of the initialiser or type annotation.
+    pub fn span(&self) -> Span {
+        let end = match (&self.initialiser, &self.id.typ) {
+            (Some(init), _) => init.value.span(),
+            (None, Some(anno)) if anno.type_ref.span() != DUMMY_SPAN => anno.type_ref.span(),
+            _ => self.id.id_tok.span(),
+        };
+        let start = self
+            .attributes
+            .first()
+            .map_or(self.var_kw.span(), Attribute::span);
+        Span::enclosing(start, end)
+    }
 }
 
 /// Parathesis Grouping Expression
 ///
 /// Represents an inner expression, wrapped in a pair of `()`.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct GroupingExpression {
     /// The opening `(`
-    pub open_tok: Box<Token>,
+    pub open_tok: Token,
     /// The inner expression
-    pub inner: Box<Expression>,
+    pub inner: ExprId,
     /// The closing `)`
-    pub close_tok: Box<Token>,
+    pub close_tok: Token,
 }
 
 /// Represents an AST expression.
 ///
 /// Each variant represnets a unique kind of expression. The data for
 /// that expresison is carried in a data `struct`.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     /// A reference to a variable or function parameter
     Identifier(IdentifierExpression),
@@ -310,18 +431,24 @@ pub enum Expression {
     Index(IndexExpression),
     /// An if expression
     IfThenElse(IfElseExpression),
-    /// Function declaration
-    Function(FunctionExpression),
+    /// Function declaration. Functions and declarations are much
+    /// larger than other expressions so are boxed to keep the arena's
+    /// slots small.
+    Function(Box<FunctionExpression>),
+    /// Macro definition
+    Macro(Box<MacroExpression>),
     /// Conditional Loop
     Loop(LoopExpression),
+    /// Break or continue
+    LoopControl(LoopControlExpression),
     /// Sequence expression. Represents a series of expressions and
     /// evaluates to the last one. If there are no expressions this
     /// evaluates to the unit value `()`.
-    Sequence(Vec<Expression>),
+    Sequence(Vec<ExprId>),
     /// Print Expression
     Print(PrintExpression),
     /// Variable delcaration expression
-    Declaration(DeclarationExpression),
+    Declaration(Box<DeclarationExpression>),
     /// Expression grouped with paranthesis
     Grouping(GroupingExpression),
 }
@@ -333,10 +460,7 @@ impl Expression {
     /// or declaration, part of a function definition or function
     /// call.
     pub fn identifier(token: Token, ident: Ident) -> Self {
-        Expression::Identifier(IdentifierExpression {
-            token: Box::new(token),
-            ident,
-        })
+        Expression::Identifier(IdentifierExpression { token, ident })
     }
 
     /// New Numeric Constant
@@ -346,7 +470,7 @@ impl Expression {
     /// constants.
     pub fn constant_num(token: Token, n: i64) -> Self {
         Expression::Literal(LiteralExpression {
-            token: Box::new(token),
+            token,
             value: Constant::Number(n),
         })
     }
@@ -361,7 +485,7 @@ impl Expression {
         T: Into<String>,
     {
         Expression::Literal(LiteralExpression {
-            token: Box::new(token),
+            token,
             value: Constant::String(s.into()),
         })
     }
@@ -372,7 +496,7 @@ impl Expression {
     /// 'false'.
     pub fn constant_bool(token: Token, b: bool) -> Self {
         Expression::Literal(LiteralExpression {
-            token: Box::new(token),
+            token,
             value: Constant::Bool(b),
         })
     }
@@ -381,11 +505,11 @@ impl Expression {
     ///
     /// Represents the application of a prefix unary operator to
     /// another expression.
-    pub fn prefix(op_token: Token, op: PrefixOp, expr: Expression) -> Self {
+    pub fn prefix(op_token: Token, op: PrefixOp, expr: ExprId) -> Self {
         Expression::Prefix(PrefixExpression {
-            op_token: Box::new(op_token),
+            op_token,
             op,
-            inner: Box::new(expr),
+            inner: expr,
         })
     }
 
@@ -393,12 +517,12 @@ impl Expression {
     ///
     /// Represents the application of an infix binary operator to two
     /// expression operands.
-    pub fn infix(lhs: Expression, op_token: Token, op: InfixOp, rhs: Expression) -> Self {
+    pub fn infix(lhs: ExprId, op_token: Token, op: InfixOp, rhs: ExprId) -> Self {
         Expression::Infix(InfixOperatorExpression {
-            left: Box::new(lhs),
-            op_token: Box::new(op_token),
+            left: lhs,
+            op_token,
             op,
-            right: Box::new(rhs),
+            right: rhs,
         })
     }
 
@@ -406,16 +530,16 @@ impl Expression {
     ///
     /// Represents calling a given function with a numer of arguments.
     pub fn call(
-        callee: Expression,
+        callee: ExprId,
         open_paren: Token,
-        args: Vec<Expression>,
+        args: Vec<DelimItem<ExprId>>,
         close_paren: Token,
     ) -> Self {
         Expression::Call(CallExpression {
-            callee: Box::new(callee),
-            open_paren: Box::new(open_paren),
+            callee,
+            open_paren,
             arguments: args,
-            close_paren: Box::new(close_paren),
+            close_paren,
         })
     }
 
@@ -423,12 +547,12 @@ impl Expression {
     ///
     /// Represents indexing one expression by another. This could be
     /// an array lookup, or slice operation.
-    pub fn index(lhs: Expression, open: Token, index: Expression, close: Token) -> Self {
+    pub fn index(lhs: ExprId, open: Token, index: ExprId, close: Token) -> Self {
         Expression::Index(IndexExpression {
-            indexee: Box::new(lhs),
-            open_bracket: Box::new(open),
-            index: Box::new(index),
-            close_bracket: Box::new(close),
+            indexee: lhs,
+            open_bracket: open,
+            index,
+            close_bracket: close,
         })
     }
 
@@ -438,80 +562,161 @@ impl Expression {
     /// ternary expression.
     pub fn if_then_else(
         if_tok: Token,
-        cond: Expression,
-        then: Expression,
+        cond: ExprId,
+        then: ExprId,
         else_tok: Token,
-        els: Expression,
+        els: ExprId,
     ) -> Self {
         Expression::IfThenElse(IfElseExpression {
-            if_tok: Box::new(if_tok),
-            cond: Box::new(cond),
-            if_true: Box::new(then),
-            else_tok: Box::new(else_tok),
-            if_false: Box::new(els),
+            if_tok,
+            cond,
+            if_true: then,
+            else_tok,
+            if_false: els,
         })
     }
 
     /// New Function Definition
     ///
     /// Create a function delcaration builder. This can be used to
-    /// create a function expression.
+    /// create a function expression. The `identifier` is the token
+    /// the function's name was written with, paired with the name
+    /// itself. Any doc comment in the leading trivia of the `fn_kw`
+    /// is attached to the function.
     pub fn function(
         fn_kw: Token,
-        identifier: Ident,
+        (identifier_tok, identifier): (Token, Ident),
         params_open: Token,
         params: Vec<DelimItem<TypedId>>,
         params_close: Token,
         return_type: TypeAnno,
         body: BlockBody,
     ) -> Expression {
-        Expression::Function(FunctionExpression {
-            fn_kw: Box::new(fn_kw),
+        Expression::Function(Box::new(FunctionExpression {
+            doc: DocComment::from_leading(fn_kw.leading()),
+            attributes: Vec::new(),
+            fn_kw,
             identifier,
-            params_open: Box::new(params_open),
+            identifier_tok,
+            params_open,
             params,
-            params_close: Box::new(params_close),
+            params_close,
             return_type,
             body,
-        })
+        }))
+    }
+
+    /// New Macro Definition
+    ///
+    /// The `identifier` is the token the macro's name was written
+    /// with, paired with the name itself. Each parameter must be a
+    /// `Word` token.
+    pub fn macro_def(
+        macro_kw: Token,
+        (identifier_tok, identifier): (Token, Ident),
+        params_open: Token,
+        params: Vec<DelimItem<Token>>,
+        params_close: Token,
+        body: BlockBody,
+    ) -> Expression {
+        Expression::Macro(Box::new(MacroExpression {
+            macro_kw,
+            identifier,
+            identifier_tok,
+            params_open,
+            params,
+            params_close,
+            body,
+        }))
     }
 
     /// New Loop Expression
     ///
     /// Represents the repeated evaluation of an expression until a
     /// condition changes.
-    pub fn loop_while(kw_token: Token, condition: Expression, body: BlockBody) -> Self {
+    pub fn loop_while(kw_token: Token, condition: ExprId, body: BlockBody) -> Self {
         Expression::Loop(LoopExpression {
-            kw_token: Box::new(kw_token),
-            condition: Box::new(condition),
+            label: None,
+            kw_token,
+            condition,
             body,
         })
     }
 
+    /// New Labelled Loop Expression
+    ///
+    /// A loop with a name which `break` and `continue` can refer to.
+    pub fn labelled_loop(
+        label: LoopLabel,
+        kw_token: Token,
+        condition: ExprId,
+        body: BlockBody,
+    ) -> Self {
+        Expression::Loop(LoopExpression {
+            label: Some(label),
+            kw_token,
+            condition,
+            body,
+        })
+    }
+
+    /// New Loop Control Expression
+    ///
+    /// Represents a `break` or `continue` of the innermost loop, or
+    /// of the loop with the given label.
+    pub fn loop_control(kw_token: Token, kind: LoopControl, label: Option<(Token, Ident)>) -> Self {
+        Expression::LoopControl(LoopControlExpression {
+            kw_token,
+            kind,
+            label,
+        })
+    }
+
     /// New Variable Declaration
     ///
-    /// Represents the declaration of a local variable.
+    /// Represents the declaration of a local variable. Any doc
+    /// comment in the leading trivia of the `var_kw` is attached to
+    /// the declaration.
     pub fn declaration(
         var_kw: Token,
         var: TypedId,
         style: VarStyle,
         assign_tok: Token,
-        expr: Expression,
+        expr: ExprId,
     ) -> Self {
-        Expression::Declaration(DeclarationExpression {
+        Expression::Declaration(Box::new(DeclarationExpression {
+            doc: DocComment::from_leading(var_kw.leading()),
+            attributes: Vec::new(),
             style,
-            var_kw: Box::new(var_kw),
+            var_kw,
             id: var,
-            assignment_tok: Box::new(assign_tok),
-            initialiser: Box::new(expr),
-        })
+            initialiser: Some(Initialiser {
+                assignment_tok: assign_tok,
+                value: expr,
+            }),
+        }))
+    }
+
+    /// New Uninitialised Variable Declaration
+    ///
+    /// Represents the declaration of a local variable which is given
+    /// its first value later on.
+    pub fn uninitialised_declaration(var_kw: Token, var: TypedId, style: VarStyle) -> Self {
+        Expression::Declaration(Box::new(DeclarationExpression {
+            doc: DocComment::from_leading(var_kw.leading()),
+            attributes: Vec::new(),
+            style,
+            var_kw,
+            id: var,
+            initialiser: None,
+        }))
     }
 
     /// New Sequence Expression
     ///
     /// Represents a sequence of expressions evaluated one after the
     /// other.
-    pub fn sequence(exprs: Vec<Expression>) -> Self {
+    pub fn sequence(exprs: Vec<ExprId>) -> Self {
         Expression::Sequence(exprs)
     }
 
@@ -522,32 +727,43 @@ impl Expression {
         Expression::sequence(Vec::new())
     }
 
+    /// Get the Expression's Attributes
+    ///
+    /// Only functions and declarations can have attributes.
+    pub fn attributes(&self) -> &[Attribute] {
+        match self {
+            Expression::Function(f) => &f.attributes,
+            Expression::Declaration(d) => &d.attributes,
+            _ => &[],
+        }
+    }
+
     /// Print Expression
     ///
     /// Evaluates an inner expression, prints it to standard output,
     /// and then returns the inner expression's value.
-    pub fn print(print: Token, expr: Expression) -> Self {
+    pub fn print(print: Token, expr: ExprId) -> Self {
         Expression::Print(PrintExpression {
-            print_tok: Box::new(print),
-            inner: Box::new(expr),
+            print_tok: print,
+            inner: expr,
         })
     }
 
     /// Grouping Expression
     ///
     /// Represents an expression wrapped in `(` and `)`.
-    pub fn grouping(open: Token, inner: Expression, close: Token) -> Self {
+    pub fn grouping(open: Token, inner: ExprId, close: Token) -> Self {
         Expression::Grouping(GroupingExpression {
-            open_tok: Box::new(open),
-            inner: Box::new(inner),
-            close_tok: Box::new(close),
+            open_tok: open,
+            inner,
+            close_tok: close,
         })
     }
 }
 
 impl SyntaxNode for Expression {
     /// Expression description
-    fn description(&self, source: &SourceText) -> std::borrow::Cow<str> {
+    fn description(&self, source: &SourceText) -> std::borrow::Cow<'_, str> {
         match *self {
             Expression::Identifier(ref id) => {
                 format!("Identifier `{}`", source.interned_value(id.ident)).into()
@@ -561,7 +777,19 @@ impl SyntaxNode for Expression {
             Expression::Function(ref f) => {
                 format!("Function `{}`", source.interned_value(f.identifier)).into()
             }
-            Expression::Loop(_) => "Loop".into(),
+            Expression::Macro(ref m) => {
+                format!("Macro `{}`", source.interned_value(m.identifier)).into()
+            }
+            Expression::Loop(ref l) => match l.label {
+                Some(ref label) => format!("Loop `{}`", source.interned_value(label.name)).into(),
+                None => "Loop".into(),
+            },
+            Expression::LoopControl(ref c) => match c.label {
+                Some((_, label)) => {
+                    format!("{:?} `{}`", c.kind, source.interned_value(label)).into()
+                }
+                None => format!("{:?}", c.kind).into(),
+            },
             Expression::Sequence(_) => "Sequence".into(),
             Expression::Print(_) => "Print".into(),
             Expression::Declaration(ref d) => {
@@ -581,16 +809,28 @@ impl SyntaxNode for Expression {
             Expression::Call(ref c) => Span::enclosing(c.callee.span(), c.close_paren.span()),
             Expression::Index(ref i) => Span::enclosing(i.indexee.span(), i.close_bracket.span()),
             Expression::IfThenElse(ref i) => Span::enclosing(i.if_true.span(), i.if_false.span()),
-            Expression::Function(ref f) => Span::enclosing(f.fn_kw.span(), f.body.close.span()),
-            Expression::Loop(ref l) => Span::enclosing(l.kw_token.span(), l.body.close.span()),
+            Expression::Function(ref f) => {
+                let start = f.attributes.first().map_or(f.fn_kw.span(), Attribute::span);
+                Span::enclosing(start, f.body.close.span())
+            }
+            Expression::Macro(ref m) => Span::enclosing(m.macro_kw.span(), m.body.close.span()),
+            Expression::Loop(ref l) => {
+                let start = l
+                    .label
+                    .as_ref()
+                    .map_or(l.kw_token.span(), |l| l.name_tok.span());
+                Span::enclosing(start, l.body.close.span())
+            }
+            Expression::LoopControl(ref c) => match c.label {
+                Some((ref tok, _)) => Span::enclosing(c.kw_token.span(), tok.span()),
+                None => c.kw_token.span(),
+            },
             Expression::Sequence(ref s) => match (s.first(), s.last()) {
                 (Some(first), Some(last)) => Span::enclosing(first.span(), last.span()),
                 _ => DUMMY_SPAN,
             },
             Expression::Print(ref p) => Span::enclosing(p.print_tok.span(), p.inner.span()),
-            Expression::Declaration(ref d) => {
-                Span::enclosing(d.var_kw.span(), d.initialiser.span())
-            }
+            Expression::Declaration(ref d) => d.span(),
             Expression::Grouping(ref g) => Span::enclosing(g.open_tok.span(), g.close_tok.span()),
         }
     }
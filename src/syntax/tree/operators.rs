@@ -30,7 +30,7 @@ pub enum PrefixOp {
 }
 
 /// Represents an AST infix operator
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum InfixOp {
     /// Assignment Operator (`=`)
     Assign,
@@ -57,3 +57,24 @@ pub enum InfixOp {
     /// Greater than or equals operator (`>=`)
     GtEq,
 }
+
+impl InfixOp {
+    /// Get the Operator's Symbol
+    ///
+    /// Returns the operator as it is written in source.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            InfixOp::Assign => "=",
+            InfixOp::Add => "+",
+            InfixOp::Sub => "-",
+            InfixOp::Mul => "*",
+            InfixOp::Div => "/",
+            InfixOp::Eq => "==",
+            InfixOp::NotEq => "!=",
+            InfixOp::Lt => "<",
+            InfixOp::LtEq => "<=",
+            InfixOp::Gt => ">",
+            InfixOp::GtEq => ">=",
+        }
+    }
+}
@@ -3,15 +3,16 @@
 //! A lexeme in the token stream. Tokens are produced by the
 //! `Tokeniser` when parsing a source text.
 
-use super::super::text::{Ident, Span, DUMMY_SPAN};
-use super::TriviaToken;
+use super::super::text::{Ident, SourceText, Span, DUMMY_SPAN};
+use super::{TriviaToken, TriviaTokenKind};
+use std::borrow::Cow;
 use std::fmt;
 
 /// A Syntax Token
 ///
 /// Syntax tokens are produced by the lexer and contain metadata about
 /// their position in the source text.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     /// The `TokenKind` for this token. Public to allow matching over
     /// different token kinds.
@@ -92,6 +93,9 @@ pub enum TokenKind {
     /// The `:` character
     Colon,
 
+    /// The `#[` which opens an attribute
+    AttributeOpen,
+
     /// The `<` character
     LessThan,
 
@@ -109,6 +113,40 @@ pub enum TokenKind {
     End,
 }
 
+impl TokenKind {
+    /// Get the Text of a Token of this Kind
+    ///
+    /// Returns the text a token of this kind would have in the
+    /// source. Words are looked up in the given source's interner.
+    /// The end of file token has no text.
+    pub fn text<'a>(&'a self, source: &'a SourceText) -> Cow<'a, str> {
+        Cow::Borrowed(match self {
+            TokenKind::Word(id) => source.interned_value(*id),
+            TokenKind::Literal(lit) => return Cow::Owned(lit.to_string()),
+            TokenKind::Equals => "=",
+            TokenKind::DoubleEquals => "==",
+            TokenKind::Bang => "!",
+            TokenKind::BangEquals => "!=",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::OpenBracket => "(",
+            TokenKind::CloseBracket => ")",
+            TokenKind::OpenSqBracket => "[",
+            TokenKind::CloseSqBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::AttributeOpen => "#[",
+            TokenKind::LessThan => "<",
+            TokenKind::LessThanEqual => "<=",
+            TokenKind::MoreThan => ">",
+            TokenKind::MoreThanEqual => ">=",
+            TokenKind::End => "",
+        })
+    }
+}
+
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -143,6 +181,7 @@ impl fmt::Display for TokenKind {
                 TokenKind::CloseSqBracket => "']'",
                 TokenKind::Comma => "','",
                 TokenKind::Colon => "':'",
+                TokenKind::AttributeOpen => "'#['",
                 TokenKind::LessThan => "'<'",
                 TokenKind::LessThanEqual => "'<='",
                 TokenKind::MoreThan => "'>'",
@@ -194,6 +233,20 @@ impl Token {
         Token { trailing, ..self }
     }
 
+    /// Convert the Token into Trivia
+    ///
+    /// Used when the parser skips over a token. The token's own text
+    /// becomes a `Skipped` trivia token, surrounded by its leading
+    /// and trailing trivia, so that no source text is lost.
+    pub fn into_trivia(self) -> Vec<TriviaToken> {
+        let mut trivia = self.leading;
+        if self.span != DUMMY_SPAN {
+            trivia.push(TriviaToken::with_span(self.span, TriviaTokenKind::Skipped));
+        }
+        trivia.extend(self.trailing);
+        trivia
+    }
+
     /// Left binding power. This controls the precedence of
     /// the symbol when being parsed as an infix operator.
     ///
@@ -253,7 +306,6 @@ impl PartialEq for Token {
 mod test {
 
     use super::super::super::text::Pos;
-    use super::super::TriviaTokenKind;
     use super::*;
 
     #[test]
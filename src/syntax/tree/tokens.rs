@@ -0,0 +1,180 @@
+//! Token Walking
+//!
+//! Collects the tokens held in a syntax tree in the order they appear
+//! in the source text. Along with each token's trivia this allows the
+//! original text to be reproduced from the tree.
+
+use super::super::text::Ident;
+use super::arena::{ExprId, ExpressionArena};
+use super::expression::{DelimItem, Expression, TypedId};
+use super::types::{TypeAnno, TypeRef};
+use super::{Token, TokenKind};
+
+/// Collect the Tokens of an Expression
+///
+/// Pushes each token in the expression to `tokens`, in source order.
+pub(super) fn expression_tokens<'t>(
+    arena: &'t ExpressionArena,
+    expr: &'t Expression,
+    tokens: &mut Vec<&'t Token>,
+) {
+    let child = |id: ExprId, tokens: &mut Vec<&'t Token>| {
+        expression_tokens(arena, &arena[id], tokens);
+    };
+    match expr {
+        Expression::Identifier(i) => tokens.push(&i.token),
+        Expression::Literal(l) => tokens.push(&l.token),
+        Expression::Prefix(p) => {
+            tokens.push(&p.op_token);
+            child(p.inner, tokens);
+        }
+        Expression::Infix(i) => {
+            child(i.left, tokens);
+            tokens.push(&i.op_token);
+            child(i.right, tokens);
+        }
+        Expression::Call(c) => {
+            child(c.callee, tokens);
+            tokens.push(&c.open_paren);
+            for arg in c.arguments.iter() {
+                delim_tokens(arg, tokens, |id, tokens| child(*id, tokens));
+            }
+            tokens.push(&c.close_paren);
+        }
+        Expression::Index(i) => {
+            child(i.indexee, tokens);
+            tokens.push(&i.open_bracket);
+            child(i.index, tokens);
+            tokens.push(&i.close_bracket);
+        }
+        Expression::IfThenElse(i) => {
+            // The expression before the `if` is the value if true,
+            // but for `unless` it's the value if false.
+            let (lhs, fallback) = match i.if_tok.kind {
+                TokenKind::Word(Ident::Unless) => (i.if_false, i.if_true),
+                _ => (i.if_true, i.if_false),
+            };
+            child(lhs, tokens);
+            tokens.push(&i.if_tok);
+            child(i.cond, tokens);
+            tokens.push(&i.else_tok);
+            child(fallback, tokens);
+        }
+        Expression::Function(f) => {
+            for attr in f.attributes.iter() {
+                tokens.extend(attr.tokens());
+            }
+            tokens.push(&f.fn_kw);
+            tokens.push(&f.identifier_tok);
+            tokens.push(&f.params_open);
+            for param in f.params.iter() {
+                delim_tokens(param, tokens, typed_id_tokens);
+            }
+            tokens.push(&f.params_close);
+            type_anno_tokens(&f.return_type, tokens);
+            child(f.body.contents, tokens);
+            tokens.push(&f.body.close);
+        }
+        Expression::Macro(m) => {
+            tokens.push(&m.macro_kw);
+            tokens.push(&m.identifier_tok);
+            tokens.push(&m.params_open);
+            for param in m.params.iter() {
+                delim_tokens(param, tokens, |tok, tokens| tokens.push(tok));
+            }
+            tokens.push(&m.params_close);
+            child(m.body.contents, tokens);
+            tokens.push(&m.body.close);
+        }
+        Expression::Loop(l) => {
+            if let Some(label) = &l.label {
+                tokens.push(&label.name_tok);
+                tokens.push(&label.colon);
+            }
+            tokens.push(&l.kw_token);
+            child(l.condition, tokens);
+            child(l.body.contents, tokens);
+            tokens.push(&l.body.close);
+        }
+        Expression::LoopControl(c) => {
+            tokens.push(&c.kw_token);
+            if let Some((label, _)) = &c.label {
+                tokens.push(label);
+            }
+        }
+        Expression::Sequence(s) => {
+            for &expr in s.iter() {
+                child(expr, tokens);
+            }
+        }
+        Expression::Print(p) => {
+            tokens.push(&p.print_tok);
+            child(p.inner, tokens);
+        }
+        Expression::Declaration(d) => {
+            for attr in d.attributes.iter() {
+                tokens.extend(attr.tokens());
+            }
+            tokens.push(&d.var_kw);
+            typed_id_tokens(&d.id, tokens);
+            if let Some(init) = &d.initialiser {
+                tokens.push(&init.assignment_tok);
+                child(init.value, tokens);
+            }
+        }
+        Expression::Grouping(g) => {
+            tokens.push(&g.open_tok);
+            child(g.inner, tokens);
+            tokens.push(&g.close_tok);
+        }
+    }
+}
+
+/// Collect the Tokens of a Delimited Item
+fn delim_tokens<'t, T, F>(item: &'t DelimItem<T>, tokens: &mut Vec<&'t Token>, inner: F)
+where
+    F: Fn(&'t T, &mut Vec<&'t Token>),
+{
+    if let DelimItem::Follow(delim, _) = item {
+        tokens.push(delim);
+    }
+    inner(item.as_inner(), tokens);
+}
+
+/// Collect the Tokens of a Typed Identifier
+fn typed_id_tokens<'t>(id: &'t TypedId, tokens: &mut Vec<&'t Token>) {
+    tokens.push(&id.id_tok);
+    if let Some(anno) = &id.typ {
+        type_anno_tokens(anno, tokens);
+    }
+}
+
+/// Collect the Tokens of a Type Annotation
+fn type_anno_tokens<'t>(anno: &'t TypeAnno, tokens: &mut Vec<&'t Token>) {
+    tokens.push(&anno.anno_tok);
+    type_ref_tokens(&anno.type_ref, tokens);
+}
+
+/// Collect the Tokens of a Type Reference
+fn type_ref_tokens<'t>(type_ref: &'t TypeRef, tokens: &mut Vec<&'t Token>) {
+    match type_ref {
+        TypeRef::Simple(tok) => tokens.push(tok),
+        TypeRef::Unit(open, close) => {
+            tokens.push(open);
+            tokens.push(close);
+        }
+        TypeRef::Tuple(open, inner, close) => {
+            tokens.push(open);
+            for item in inner.iter() {
+                delim_tokens(item, tokens, type_ref_tokens);
+            }
+            tokens.push(close);
+        }
+        TypeRef::Array(open, inner, close) => {
+            tokens.push(open);
+            type_ref_tokens(inner, tokens);
+            tokens.push(close);
+        }
+        TypeRef::Missing => (),
+    }
+}
@@ -7,7 +7,7 @@
 //! re-written and then serialised to allow code tranformations
 //! without loss of things like comments and indentation.
 
-use super::super::text::Span;
+use super::super::text::{SourceText, Span};
 
 /// Trivia Token
 ///
@@ -31,8 +31,12 @@ pub enum TriviaTokenKind {
     Junk,
     /// A single line comment
     Comment,
+    /// A single line documentation comment, starting `##`
+    DocComment,
     /// A newline character
     Newline,
+    /// A token skipped by the parser when recovering from an error
+    Skipped,
 }
 
 impl TriviaToken {
@@ -45,4 +49,109 @@ impl TriviaToken {
     pub fn span(&self) -> Span {
         self.span
     }
+
+    /// Get the `TriviaTokenKind` of this trivia
+    pub fn kind(&self) -> TriviaTokenKind {
+        self.kind
+    }
+}
+
+/// Doc Comment
+///
+/// The block of `##` comment lines directly before a function or
+/// variable declaration. Lines of the block may only be separated by
+/// newlines and whitespace, a blank line or a plain comment ends it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocComment {
+    lines: Vec<TriviaToken>,
+}
+
+impl DocComment {
+    /// Find the Doc Comment in a Token's Leading Trivia
+    ///
+    /// Returns the block of doc comment lines which ends on the line
+    /// before the token, if there is one.
+    pub fn from_leading(trivia: &[TriviaToken]) -> Option<Self> {
+        let mut lines = Vec::new();
+        let mut line_has_doc = false;
+        for token in trivia {
+            match token.kind {
+                TriviaTokenKind::DocComment => {
+                    lines.push(*token);
+                    line_has_doc = true;
+                }
+                TriviaTokenKind::Newline => {
+                    if !line_has_doc {
+                        lines.clear();
+                    }
+                    line_has_doc = false;
+                }
+                TriviaTokenKind::Whitespace => (),
+                _ => {
+                    lines.clear();
+                    line_has_doc = false;
+                }
+            }
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(DocComment { lines })
+        }
+    }
+
+    /// Get the Comment Lines
+    pub fn lines(&self) -> &[TriviaToken] {
+        &self.lines
+    }
+
+    /// Get the `Span` of the Whole Comment
+    pub fn span(&self) -> Span {
+        let first = self.lines[0].span;
+        let last = self.lines[self.lines.len() - 1].span;
+        Span::enclosing(first, last)
+    }
+
+    /// Get the Text of the Comment
+    ///
+    /// Returns the comment's lines with the leading `##`, and a single
+    /// space after it, removed.
+    pub fn text(&self, source: &SourceText) -> String {
+        self.lines
+            .iter()
+            .map(|line| {
+                let text = source.slice(line.span.start(), line.span.end());
+                let text = text.trim_start_matches('#');
+                text.strip_prefix(' ').unwrap_or(text).trim_end()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::parse::Tokeniser;
+    use super::*;
+
+    fn doc_before_last(src: &str) -> Option<String> {
+        let source = SourceText::new(src);
+        let token = Tokeniser::new(&source).last().unwrap();
+        DocComment::from_leading(token.leading()).map(|doc| doc.text(&source))
+    }
+
+    #[test]
+    fn doc_comment_blocks() {
+        assert_eq!(None, doc_before_last("1\n2"));
+        assert_eq!(
+            Some("Docs.\n\n  Indented.".to_string()),
+            doc_before_last("1\n  ## Docs.\n  ##\n  ##   Indented.\n  2")
+        );
+        assert_eq!(
+            Some("Second.".to_string()),
+            doc_before_last("## First.\n\n## Second.\n2")
+        );
+        assert_eq!(None, doc_before_last("## First.\n# plain\n2"));
+        assert_eq!(None, doc_before_last("## First.\n\n2"));
+    }
 }
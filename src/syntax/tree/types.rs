@@ -13,7 +13,7 @@ use std::borrow::Cow;
 ///
 /// Represents a reference to a type. This could be a simple reference
 /// to a named type or a complex type such as an array or Tuple.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum TypeRef {
     /// Simple Named Type
     Simple(Box<Token>),
@@ -32,7 +32,7 @@ pub enum TypeRef {
 ///
 /// Reference to a type annotated to a variable or function. This is a
 /// type reference and the accompanying `:` token.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TypeAnno {
     /// The `:` Token
     pub anno_tok: Box<Token>,
@@ -86,7 +86,7 @@ impl TypeRef {
 }
 
 impl SyntaxNode for TypeRef {
-    fn description(&self, source: &SourceText) -> Cow<str> {
+    fn description(&self, source: &SourceText) -> Cow<'_, str> {
         match self {
             TypeRef::Array(..) => "Type <array>".into(),
             TypeRef::Missing => "Type <missing>".into(),
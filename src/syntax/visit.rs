@@ -0,0 +1,416 @@
+//! Syntax Tree Visitor
+//!
+//! This module provides a `Visitor` trait for walking the syntax
+//! tree. Each method on the visitor has a default implementation
+//! which walks the children of the node using the corresponding
+//! `walk_*` function. Visitors only need to override the methods for
+//! the nodes they are interested in.
+//!
+//! To continue walking into the children of a node from an overridden
+//! method call the matching `walk_*` function. Child expressions are
+//! looked up in the arena the visitor returns from `arena`, which
+//! must be the arena of the tree being walked.
+//!
+//! # Examples
+//!
+//! ```
+//! use ullage::syntax::visit::{self, Visitor};
+//! use ullage::syntax::{text::SourceText, CallExpression, ExpressionArena, SyntaxTree};
+//!
+//! struct CallCounter<'ast>(&'ast ExpressionArena, usize);
+//!
+//! impl<'ast> Visitor<'ast> for CallCounter<'ast> {
+//!     fn arena(&self) -> &'ast ExpressionArena {
+//!         self.0
+//!     }
+//!
+//!     fn visit_call(&mut self, call: &'ast CallExpression) {
+//!         self.1 += 1;
+//!         visit::walk_call(self, call);
+//!     }
+//! }
+//!
+//! let source = SourceText::new("print foo(bar(1), 2)");
+//! let tree = SyntaxTree::parse(&source);
+//! let mut counter = CallCounter(tree.arena(), 0);
+//! counter.visit_expression(tree.root());
+//! assert_eq!(2, counter.1);
+//! ```
+
+use super::tree::expression::*;
+use super::tree::types::{TypeAnno, TypeRef};
+use super::tree::{ExprId, ExpressionArena};
+
+/// Syntax Tree Visitor
+///
+/// Walks a borrowed syntax tree. The `'ast` lifetime allows visitors
+/// to hold on to references to the nodes they visit.
+pub trait Visitor<'ast> {
+    /// Get the Arena of the Tree Being Walked
+    ///
+    /// Used to look up the child expressions of each node.
+    fn arena(&self) -> &'ast ExpressionArena;
+
+    /// Visit an Expression
+    ///
+    /// Dispatches to the method for the expression's variant.
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        walk_expression(self, expr)
+    }
+
+    /// Visit an Identifier Expression
+    fn visit_identifier(&mut self, _ident: &'ast IdentifierExpression) {}
+
+    /// Visit a Literal Expression
+    fn visit_literal(&mut self, _lit: &'ast LiteralExpression) {}
+
+    /// Visit a Prefix Operator Expression
+    fn visit_prefix(&mut self, prefix: &'ast PrefixExpression) {
+        walk_prefix(self, prefix)
+    }
+
+    /// Visit an Infix Operator Expression
+    fn visit_infix(&mut self, infix: &'ast InfixOperatorExpression) {
+        walk_infix(self, infix)
+    }
+
+    /// Visit a Call Expression
+    fn visit_call(&mut self, call: &'ast CallExpression) {
+        walk_call(self, call)
+    }
+
+    /// Visit an Index Expression
+    fn visit_index(&mut self, index: &'ast IndexExpression) {
+        walk_index(self, index)
+    }
+
+    /// Visit an If Expression
+    fn visit_if_else(&mut self, if_else: &'ast IfElseExpression) {
+        walk_if_else(self, if_else)
+    }
+
+    /// Visit a Function Expression
+    fn visit_function(&mut self, func: &'ast FunctionExpression) {
+        walk_function(self, func)
+    }
+
+    /// Visit a Macro Definition
+    fn visit_macro(&mut self, mac: &'ast MacroExpression) {
+        walk_macro(self, mac)
+    }
+
+    /// Visit a Loop Expression
+    fn visit_loop(&mut self, lop: &'ast LoopExpression) {
+        walk_loop(self, lop)
+    }
+
+    /// Visit a Break or Continue
+    fn visit_loop_control(&mut self, _control: &'ast LoopControlExpression) {}
+
+    /// Visit a Sequence Expression
+    fn visit_sequence(&mut self, exprs: &'ast [ExprId]) {
+        walk_sequence(self, exprs)
+    }
+
+    /// Visit a Print Expression
+    fn visit_print(&mut self, print: &'ast PrintExpression) {
+        walk_print(self, print)
+    }
+
+    /// Visit a Variable Declaration
+    fn visit_declaration(&mut self, decl: &'ast DeclarationExpression) {
+        walk_declaration(self, decl)
+    }
+
+    /// Visit a Grouping Expression
+    fn visit_grouping(&mut self, grouping: &'ast GroupingExpression) {
+        walk_grouping(self, grouping)
+    }
+
+    /// Visit a Typed Identifier
+    ///
+    /// Called for function parameters and variable declarations.
+    fn visit_typed_id(&mut self, id: &'ast TypedId) {
+        walk_typed_id(self, id)
+    }
+
+    /// Visit a Type Reference
+    fn visit_type_ref(&mut self, type_ref: &'ast TypeRef) {
+        walk_type_ref(self, type_ref)
+    }
+}
+
+/// Walk an Expression
+///
+/// Calls the visitor method for the given expression's variant.
+pub fn walk_expression<'ast, V>(visitor: &mut V, expr: &'ast Expression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    match expr {
+        Expression::Identifier(ident) => visitor.visit_identifier(ident),
+        Expression::Literal(lit) => visitor.visit_literal(lit),
+        Expression::Prefix(prefix) => visitor.visit_prefix(prefix),
+        Expression::Infix(infix) => visitor.visit_infix(infix),
+        Expression::Call(call) => visitor.visit_call(call),
+        Expression::Index(index) => visitor.visit_index(index),
+        Expression::IfThenElse(if_else) => visitor.visit_if_else(if_else),
+        Expression::Function(func) => visitor.visit_function(func),
+        Expression::Macro(mac) => visitor.visit_macro(mac),
+        Expression::Loop(lop) => visitor.visit_loop(lop),
+        Expression::LoopControl(control) => visitor.visit_loop_control(control),
+        Expression::Sequence(exprs) => visitor.visit_sequence(exprs),
+        Expression::Print(print) => visitor.visit_print(print),
+        Expression::Declaration(decl) => visitor.visit_declaration(decl),
+        Expression::Grouping(grouping) => visitor.visit_grouping(grouping),
+    }
+}
+
+/// Walk a Child Expression
+///
+/// Looks the expression up in the visitor's arena and visits it.
+pub fn walk_child<'ast, V>(visitor: &mut V, id: ExprId)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    let expr = &visitor.arena()[id];
+    visitor.visit_expression(expr);
+}
+
+/// Walk the Operand of a Prefix Expression
+pub fn walk_prefix<'ast, V>(visitor: &mut V, prefix: &'ast PrefixExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, prefix.inner);
+}
+
+/// Walk the Operands of an Infix Expression
+pub fn walk_infix<'ast, V>(visitor: &mut V, infix: &'ast InfixOperatorExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, infix.left);
+    walk_child(visitor, infix.right);
+}
+
+/// Walk the Callee and Arguments of a Call
+pub fn walk_call<'ast, V>(visitor: &mut V, call: &'ast CallExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, call.callee);
+    for arg in call.arguments.iter() {
+        walk_child(visitor, *arg.as_inner());
+    }
+}
+
+/// Walk the Indexee and Index of an Index Expression
+pub fn walk_index<'ast, V>(visitor: &mut V, index: &'ast IndexExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, index.indexee);
+    walk_child(visitor, index.index);
+}
+
+/// Walk the Condition and Branches of an If Expression
+pub fn walk_if_else<'ast, V>(visitor: &mut V, if_else: &'ast IfElseExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, if_else.cond);
+    walk_child(visitor, if_else.if_true);
+    walk_child(visitor, if_else.if_false);
+}
+
+/// Walk the Parameters, Return Type, and Body of a Function
+pub fn walk_function<'ast, V>(visitor: &mut V, func: &'ast FunctionExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    for param in func.params.iter() {
+        visitor.visit_typed_id(param.as_inner());
+    }
+    walk_type_anno(visitor, &func.return_type);
+    walk_child(visitor, func.body.contents);
+}
+
+/// Walk the Body of a Macro
+pub fn walk_macro<'ast, V>(visitor: &mut V, mac: &'ast MacroExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, mac.body.contents);
+}
+
+/// Walk the Condition and Body of a Loop
+pub fn walk_loop<'ast, V>(visitor: &mut V, lop: &'ast LoopExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, lop.condition);
+    walk_child(visitor, lop.body.contents);
+}
+
+/// Walk Each Expression in a Sequence
+pub fn walk_sequence<'ast, V>(visitor: &mut V, exprs: &'ast [ExprId])
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    for &expr in exprs.iter() {
+        walk_child(visitor, expr);
+    }
+}
+
+/// Walk the Inner Expression of a Print
+pub fn walk_print<'ast, V>(visitor: &mut V, print: &'ast PrintExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, print.inner);
+}
+
+/// Walk the Identifier and Initialiser of a Declaration
+pub fn walk_declaration<'ast, V>(visitor: &mut V, decl: &'ast DeclarationExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    visitor.visit_typed_id(&decl.id);
+    if let Some(init) = &decl.initialiser {
+        walk_child(visitor, init.value);
+    }
+}
+
+/// Walk the Inner Expression of a Grouping
+pub fn walk_grouping<'ast, V>(visitor: &mut V, grouping: &'ast GroupingExpression)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    walk_child(visitor, grouping.inner);
+}
+
+/// Walk the Type Annotation of a Typed Identifier
+pub fn walk_typed_id<'ast, V>(visitor: &mut V, id: &'ast TypedId)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    if let Some(anno) = &id.typ {
+        walk_type_anno(visitor, anno);
+    }
+}
+
+/// Walk the Type Reference of a Type Annotation
+pub fn walk_type_anno<'ast, V>(visitor: &mut V, anno: &'ast TypeAnno)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    visitor.visit_type_ref(&anno.type_ref);
+}
+
+/// Walk the Inner Types of a Type Reference
+pub fn walk_type_ref<'ast, V>(visitor: &mut V, type_ref: &'ast TypeRef)
+where
+    V: Visitor<'ast> + ?Sized,
+{
+    match type_ref {
+        TypeRef::Tuple(_, inner, _) => {
+            for item in inner.iter() {
+                visitor.visit_type_ref(item.as_inner());
+            }
+        }
+        TypeRef::Array(_, inner, _) => visitor.visit_type_ref(inner),
+        TypeRef::Simple(_) | TypeRef::Unit(..) | TypeRef::Missing => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::text::{Ident, SourceText};
+    use super::super::SyntaxTree;
+    use super::*;
+
+    /// Visitor which records the identifiers it sees, in order
+    struct IdentCollector<'ast> {
+        arena: &'ast ExpressionArena,
+        idents: Vec<Ident>,
+        type_refs: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for IdentCollector<'ast> {
+        fn arena(&self) -> &'ast ExpressionArena {
+            self.arena
+        }
+
+        fn visit_identifier(&mut self, ident: &'ast IdentifierExpression) {
+            self.idents.push(ident.ident);
+        }
+
+        fn visit_typed_id(&mut self, id: &'ast TypedId) {
+            self.idents.push(id.id);
+            walk_typed_id(self, id);
+        }
+
+        fn visit_type_ref(&mut self, type_ref: &'ast TypeRef) {
+            self.type_refs += 1;
+            walk_type_ref(self, type_ref);
+        }
+    }
+
+    fn collect(source: &SourceText) -> (Vec<&str>, usize) {
+        let tree = SyntaxTree::parse(source);
+        assert!(!tree.has_diagnostics());
+        let mut collector = IdentCollector {
+            arena: tree.arena(),
+            idents: Vec::new(),
+            type_refs: 0,
+        };
+        collector.visit_expression(tree.root());
+        let names = collector
+            .idents
+            .into_iter()
+            .map(|id| source.interned_value(id))
+            .collect();
+        (names, collector.type_refs)
+    }
+
+    #[test]
+    fn visit_walks_every_expression() {
+        let source = SourceText::new(
+            "
+            fn add(a: Number, b: [Number]): Number
+                a + b[0]
+            end
+            var total = add(x, (y))
+            while !done
+                print -total if total > 0 else zero
+            end",
+        );
+        let (names, type_refs) = collect(&source);
+        assert_eq!(
+            vec!["a", "b", "a", "b", "total", "add", "x", "y", "done", "total", "total", "zero"],
+            names
+        );
+        assert_eq!(4, type_refs);
+    }
+
+    #[test]
+    fn overridden_methods_can_stop_the_walk() {
+        struct NoFunctions<'ast>(&'ast ExpressionArena, usize);
+        impl<'ast> Visitor<'ast> for NoFunctions<'ast> {
+            fn arena(&self) -> &'ast ExpressionArena {
+                self.0
+            }
+            fn visit_function(&mut self, _func: &'ast FunctionExpression) {}
+            fn visit_identifier(&mut self, _ident: &'ast IdentifierExpression) {
+                self.1 += 1;
+            }
+        }
+
+        let source = SourceText::new("fn foo(): Number bar end foo()");
+        let tree = SyntaxTree::parse(&source);
+        let mut visitor = NoFunctions(tree.arena(), 0);
+        visitor.visit_expression(tree.root());
+        assert_eq!(1, visitor.1);
+    }
+}
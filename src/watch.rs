@@ -0,0 +1,95 @@
+//! File Watching
+//!
+//! Support for the compiler's watch mode, which recompiles whenever
+//! one of its inputs changes. Files are watched by polling their
+//! metadata, so no platform specific notification APIs are needed.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How Often the Watched Files are Checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Snapshot of a Set of Files
+///
+/// Records the modification time and length of each file. Files
+/// which can't be read, such as ones which have been deleted, are
+/// recorded as missing.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot(Vec<(PathBuf, Option<(SystemTime, u64)>)>);
+
+impl Snapshot {
+    /// Take a Snapshot of the Given Files
+    pub fn take(mut paths: Vec<PathBuf>) -> Self {
+        paths.sort();
+        Snapshot(
+            paths
+                .into_iter()
+                .map(|path| {
+                    let stamp = fs::metadata(&path)
+                        .and_then(|m| Ok((m.modified()?, m.len())))
+                        .ok();
+                    (path, stamp)
+                })
+                .collect(),
+        )
+    }
+
+    /// Get the Number of Files in the Snapshot
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is the Snapshot Empty?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Wait for a Change
+///
+/// Blocks until the snapshot of the files returned by `paths`
+/// differs from `last`, and returns the new snapshot. The paths are
+/// asked for again each time the files are checked, so files which
+/// are added or removed are noticed too.
+pub fn wait_for_change<F>(last: &Snapshot, mut paths: F) -> Snapshot
+where
+    F: FnMut() -> Vec<PathBuf>,
+{
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let current = Snapshot::take(paths());
+        if current != *last {
+            return current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snapshot_notices_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.ulg");
+        let second = dir.path().join("second.ulg");
+        fs::write(&first, "print 1").unwrap();
+        let paths = || vec![first.clone(), second.clone()];
+
+        let before = Snapshot::take(paths());
+        assert_eq!(2, before.len());
+        assert_eq!(before, Snapshot::take(paths()));
+
+        fs::write(&second, "print 2").unwrap();
+        let created = Snapshot::take(paths());
+        assert_ne!(before, created);
+
+        fs::write(&first, "print 100").unwrap();
+        let changed = wait_for_change(&created, paths);
+        assert_ne!(created, changed);
+        assert_eq!(changed, Snapshot::take(paths()));
+    }
+}
@@ -0,0 +1,295 @@
+//! End to End Spec Tests
+//!
+//! Compiles each of the programs under `spec/` with the `ullage`
+//! binary, runs the result, and checks what happens against the
+//! assertions embedded in the program's comments. The assertions are
+//! described in `spec/README.md`.
+//!
+//! Compiling the specs needs a working linker, so they're ignored by
+//! default. `./build.sh test` runs them along with the other tests
+//! with `cargo test -- --include-ignored`. The linker passed to the
+//! compiler can be set with the `ULLAGE_SPEC_LINKER` environment
+//! variable.
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How Long the Compiler is Given to Compile Each Spec
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Spec Expectations
+///
+/// The assertions parsed from the comments of a single spec.
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    /// The lines the program should write to standard output
+    output: Vec<String>,
+    /// Errors compilation should fail with, in order
+    errors: Vec<String>,
+    /// Errors the program should abort with, in order
+    aborts: Vec<String>,
+    /// Just compile the spec, don't run it
+    skip_run: bool,
+    /// Extra arguments to pass to the compiler
+    args: Vec<String>,
+}
+
+impl Expectations {
+    /// Parse the Expectations from a Spec's Source
+    fn parse(source: &str) -> Self {
+        let mut expectations = Expectations::default();
+        for line in source.lines() {
+            if let Some(output) = assertion(line, "=>") {
+                expectations.output.push(output.into());
+            }
+            if let Some(error) = assertion(line, "!>") {
+                expectations.errors.push(error.into());
+            }
+            if let Some(error) = assertion(line, "!!abort") {
+                expectations.aborts.push(error.into());
+            }
+            if assertion(line, "!!skip").is_some() {
+                expectations.skip_run = true;
+            }
+            if let Some(args) = assertion(line, "!!args") {
+                expectations
+                    .args
+                    .extend(args.split_whitespace().map(String::from));
+            }
+        }
+        expectations
+    }
+}
+
+/// Find an Assertion in a Line
+///
+/// Assertions are comments starting with the given sigil, optionally
+/// separated from the `#` and the assertion's parameter by a single
+/// space. Returns the parameter if the line has the assertion.
+fn assertion<'a>(line: &'a str, sigil: &str) -> Option<&'a str> {
+    line.match_indices('#').find_map(|(idx, _)| {
+        skip_space(&line[idx + 1..])
+            .strip_prefix(sigil)
+            .map(|param| skip_space(param).trim_end())
+    })
+}
+
+/// Skip a Single Leading Whitespace Character
+fn skip_space(s: &str) -> &str {
+    match s.chars().next() {
+        Some(c) if c.is_whitespace() => &s[c.len_utf8()..],
+        _ => s,
+    }
+}
+
+/// Compile and Run a Spec
+///
+/// Compiles the spec into `out_dir`. If the spec expects errors then
+/// compilation should fail with them. Otherwise the compiled program
+/// is run, and should exit with the expected output. It should exit
+/// successfully, unless it is expected to abort.
+fn run_spec(path: &Path, out_dir: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("could not read: {}", e))?;
+    let expectations = Expectations::parse(&source);
+    let exe = out_dir.join(path.file_stem().unwrap());
+
+    let mut compile = Command::new(env!("CARGO_BIN_EXE_ullage"));
+    if let Some(linker) = env::var_os("ULLAGE_SPEC_LINKER") {
+        compile.arg("--linker").arg(linker);
+    }
+    compile
+        .args(&expectations.args)
+        .arg("-o")
+        .arg(&exe)
+        .arg(path);
+    let compiled = output_within(&mut compile, COMPILE_TIMEOUT)
+        .map_err(|e| format!("compiler failed: {}", e))?;
+    let stderr = String::from_utf8_lossy(&compiled.stderr);
+
+    if compiled.status.code().is_none() {
+        return Err(format!("compiler was killed: {}", compiled.status));
+    }
+    if !expectations.errors.is_empty() {
+        if compiled.status.success() {
+            return Err("expected failure but compilation succeeded".into());
+        }
+        return check_errors(&stderr, &expectations.errors);
+    }
+    if !compiled.status.success() {
+        return Err(format!("compilation failed:\n{}", stderr));
+    }
+    if expectations.skip_run {
+        return Ok(());
+    }
+
+    let run = Command::new(&exe)
+        .output()
+        .map_err(|e| format!("could not run program: {}", e))?;
+    if !expectations.aborts.is_empty() {
+        if run.status.success() {
+            return Err("expected the program to abort but it succeeded".into());
+        }
+        check_errors(&String::from_utf8_lossy(&run.stderr), &expectations.aborts)?;
+    } else if !run.status.success() {
+        return Err(format!("program exited with {}", run.status));
+    }
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let output: Vec<_> = stdout.trim().lines().collect();
+    if output != expectations.output {
+        return Err(format!(
+            "expected output {:?}, found {:?}",
+            expectations.output, output
+        ));
+    }
+    Ok(())
+}
+
+/// Run a Command with a Timeout
+///
+/// Runs the command to completion, collecting its output. If it
+/// doesn't exit within the timeout it is killed and an error
+/// returned.
+fn output_within(command: &mut Command, timeout: Duration) -> Result<Output, String> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    // The pipes are drained as the command runs, so it can't block
+    // on a full pipe while it is waited for.
+    let stdout = drain(child.stdout.take().unwrap());
+    let stderr = drain(child.stderr.take().unwrap());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {}s", timeout.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    })
+}
+
+/// Read a Pipe to its End on Another Thread
+fn drain<R: Read + Send + 'static>(mut pipe: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// Check Compilation Errors
+///
+/// Each expected error should be found in the compiler's output, in
+/// the order they are expected. Lines echoing an assertion from the
+/// source are ignored so they can't satisfy it themselves.
+fn check_errors(stderr: &str, expected: &[String]) -> Result<(), String> {
+    let mut expected = expected.iter().peekable();
+    for line in stderr.lines() {
+        if assertion(line, "!>").is_some() {
+            continue;
+        }
+        if expected.peek().is_some_and(|e| line.contains(e.as_str())) {
+            expected.next();
+        }
+    }
+    match expected.next() {
+        Some(missing) => Err(format!(
+            "expected error '{}' in output:\n{}",
+            missing, stderr
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Find the Specs in a Directory
+///
+/// Recursively walks the directory collecting the `.ulg` files in it.
+fn find_specs(dir: &Path, specs: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_specs(&path, specs);
+        } else if path.extension().is_some_and(|ext| ext == "ulg") {
+            specs.push(path);
+        }
+    }
+}
+
+#[test]
+fn commands_time_out() {
+    let mut sleep = Command::new("sleep");
+    sleep.arg("10");
+    let started = Instant::now();
+    assert_eq!(
+        Err("timed out after 1s".into()),
+        output_within(&mut sleep, Duration::from_secs(1)).map(|_| ())
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let mut echo = Command::new("echo");
+    echo.arg("hello");
+    let output = output_within(&mut echo, Duration::from_secs(5)).unwrap();
+    assert!(output.status.success());
+    assert_eq!(b"hello\n", &output.stdout[..]);
+}
+
+#[test]
+#[ignore]
+fn specs() {
+    let mut specs = Vec::new();
+    find_specs(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("spec"),
+        &mut specs,
+    );
+    specs.sort();
+    let out_dir = tempfile::tempdir().unwrap();
+
+    let failures: Vec<_> = specs
+        .iter()
+        .filter_map(|spec| {
+            run_spec(spec, out_dir.path())
+                .err()
+                .map(|err| format!("{}: {}", spec.display(), err))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} specs failed:\n\n{}",
+        failures.len(),
+        specs.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn parse_expectations() {
+    let source = "print 1 #=> 1\nprint 'a#b' # => a#b \n# !> error: oops\n#  => not one\n#!!skip\n# !!args -O2 --gc=none\n# !!abort error: ouch";
+    assert_eq!(
+        Expectations {
+            output: vec!["1".into(), "a#b".into()],
+            errors: vec!["error: oops".into()],
+            aborts: vec!["error: ouch".into()],
+            skip_run: true,
+            args: vec!["-O2".into(), "--gc=none".into()],
+        },
+        Expectations::parse(source)
+    );
+}